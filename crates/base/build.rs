@@ -16,7 +16,7 @@ mod supabase_startup_snapshot {
     use sb_core::http::sb_core_http;
     use sb_core::http_start::sb_core_http_start;
     use sb_core::net::sb_core_net;
-    use sb_core::permissions::sb_core_permissions;
+    use sb_core::permissions::{sb_core_permissions, PermissionsOptions};
     use sb_core::runtime::sb_core_runtime;
     use sb_core::sb_core_main_js;
     use sb_core::transpiler::maybe_transpile_source;
@@ -188,7 +188,7 @@ mod supabase_startup_snapshot {
         let user_agent = String::from("supabase");
         let fs = Arc::new(deno_fs::RealFs);
         let extensions: Vec<Extension> = vec![
-            sb_core_permissions::init_ops_and_esm(false, None),
+            sb_core_permissions::init_ops_and_esm(PermissionsOptions::default()),
             deno_webidl::deno_webidl::init_ops_and_esm(),
             deno_console::deno_console::init_ops_and_esm(),
             deno_url::deno_url::init_ops_and_esm(),
@@ -214,9 +214,12 @@ mod supabase_startup_snapshot {
             deno_http::deno_http::init_ops_and_esm::<DefaultHttpPropertyExtractor>(),
             deno_io::deno_io::init_ops_and_esm(Some(Default::default())),
             deno_fs::deno_fs::init_ops_and_esm::<Permissions>(fs.clone()),
+            deno_ffi::deno_ffi::init_ops_and_esm::<Permissions>(),
             sb_ai::init_ops_and_esm(),
             sb_env::init_ops_and_esm(),
             sb_os::sb_os::init_ops_and_esm(),
+            sb_kv::sb_kv::init_ops_and_esm(),
+            sb_cache::sb_cache::init_ops_and_esm(),
             sb_user_workers::init_ops_and_esm(),
             sb_user_event_worker::init_ops_and_esm(),
             sb_events_js_interceptors::init_ops_and_esm(),
@@ -0,0 +1,183 @@
+//! Resolution of the effective client address of a request.
+//!
+//! Forwarding headers (`Forwarded`, `X-Forwarded-For`) can be set by anyone, so
+//! they are only honored when the peer of the connection is a configured
+//! trusted proxy. The chain is then walked from the right, skipping trusted
+//! hops, and the first untrusted hop is taken as the client.
+
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{Context, Error};
+use http_v02::HeaderMap;
+use ipnet::IpNet;
+use once_cell::sync::OnceCell;
+
+/// Networks whose peers are allowed to set forwarding headers. Set once at
+/// startup; no proxy is trusted if it's never set.
+pub static TRUSTED_PROXIES: OnceCell<TrustedProxies> = OnceCell::new();
+
+#[derive(Debug, Default, Clone)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn new(nets: Vec<IpNet>) -> Self {
+        Self(nets)
+    }
+
+    /// Parses a list of CIDRs. Bare addresses are taken as single hosts.
+    pub fn parse<'a>(values: impl IntoIterator<Item = &'a str>) -> Result<Self, Error> {
+        values
+            .into_iter()
+            .map(|it| {
+                it.parse::<IpNet>()
+                    .or_else(|_| it.parse::<IpAddr>().map(IpNet::from))
+                    .with_context(|| format!("invalid CIDR: {}", it))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|it| it.contains(&ip))
+    }
+}
+
+/// Returns the address of the client that originated a request received from
+/// `peer`.
+pub(crate) fn resolve_client_ip(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    trusted: &TrustedProxies,
+) -> IpAddr {
+    let mut client = peer;
+
+    if !trusted.contains(peer) {
+        return client;
+    }
+
+    for hop in forwarded_chain(headers).into_iter().rev() {
+        let Some(hop) = hop else {
+            // An obfuscated or malformed hop ends the part of the chain that
+            // can be reasoned about.
+            break;
+        };
+
+        client = hop;
+
+        if !trusted.contains(hop) {
+            break;
+        }
+    }
+
+    client
+}
+
+/// Returns the hops recorded in `Forwarded` or, if it's absent,
+/// `X-Forwarded-For`, in the order they were appended.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded = headers
+        .get_all(http_v02::header::FORWARDED)
+        .iter()
+        .filter_map(|it| it.to_str().ok())
+        .flat_map(|it| it.split(','))
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                .and_then(|(_, value)| parse_node(value.trim().trim_matches('"')))
+        })
+        .collect::<Vec<_>>();
+
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all(http_v02::header::X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|it| it.to_str().ok())
+        .flat_map(|it| it.split(','))
+        .map(|it| parse_node(it.trim()))
+        .collect()
+}
+
+/// Parses `ip`, `ip:port`, `[ipv6]` or `[ipv6]:port`.
+fn parse_node(value: &str) -> Option<IpAddr> {
+    value
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|it| it.ip()))
+        .or_else(|| {
+            value
+                .strip_prefix('[')
+                .and_then(|it| it.strip_suffix(']'))
+                .and_then(|it| it.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+
+        for (name, value) in pairs {
+            map.append(
+                http_v02::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+
+        map
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let trusted = TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()]);
+        let peer = "10.0.0.1".parse().unwrap();
+        let untrusted_peer = "203.0.113.9".parse().unwrap();
+
+        // Headers of untrusted peers are ignored.
+        assert_eq!(
+            resolve_client_ip(
+                untrusted_peer,
+                &headers(&[("x-forwarded-for", "1.1.1.1")]),
+                &trusted
+            ),
+            untrusted_peer
+        );
+
+        // Spoofed leftmost entries are skipped.
+        assert_eq!(
+            resolve_client_ip(
+                peer,
+                &headers(&[("x-forwarded-for", "1.1.1.1, 198.51.100.7, 10.0.0.2")]),
+                &trusted
+            ),
+            "198.51.100.7".parse::<IpAddr>().unwrap()
+        );
+
+        assert_eq!(
+            resolve_client_ip(
+                peer,
+                &headers(&[("forwarded", r#"for="[2001:db8::1]:4711";proto=https"#)]),
+                &trusted
+            ),
+            "2001:db8::1".parse::<IpAddr>().unwrap()
+        );
+
+        assert_eq!(
+            resolve_client_ip(
+                peer,
+                &headers(&[("forwarded", "for=_hidden, for=10.0.0.3")]),
+                &trusted
+            ),
+            "10.0.0.3".parse::<IpAddr>().unwrap()
+        );
+
+        assert_eq!(resolve_client_ip(peer, &HeaderMap::new(), &trusted), peer);
+    }
+}
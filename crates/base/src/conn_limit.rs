@@ -0,0 +1,113 @@
+//! Limits the number of concurrent connections a single client address can
+//! hold, so that a few slow clients can't exhaust the sockets of the server.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::client_ip::TrustedProxies;
+
+#[derive(Debug, Clone)]
+pub(crate) struct ConnLimiter {
+    max_per_ip: usize,
+    trusted_proxies: TrustedProxies,
+    conns: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnLimiter {
+    /// Connections from `trusted_proxies` are left unlimited, as all the
+    /// clients behind a proxy share its address.
+    pub fn new(max_per_ip: usize, trusted_proxies: TrustedProxies) -> Self {
+        Self {
+            max_per_ip,
+            trusted_proxies,
+            conns: Arc::default(),
+        }
+    }
+
+    /// Counts a connection from `ip`, or returns `None` if it already holds the
+    /// maximum number of connections. The connection is released when the
+    /// returned guard is dropped.
+    pub fn try_acquire(&self, ip: IpAddr) -> Option<ConnGuard> {
+        let ip = ip.to_canonical();
+
+        if self.trusted_proxies.contains(ip) {
+            return Some(ConnGuard { ip, conns: None });
+        }
+
+        let mut conns = self.conns.lock().unwrap();
+        let count = conns.entry(ip).or_default();
+
+        if *count >= self.max_per_ip {
+            return None;
+        }
+
+        *count += 1;
+
+        Some(ConnGuard {
+            ip,
+            conns: Some(self.conns.clone()),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ConnGuard {
+    ip: IpAddr,
+    /// `None` for a connection that isn't counted.
+    conns: Option<Arc<Mutex<HashMap<IpAddr, usize>>>>,
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        let Some(conns) = self.conns.as_ref() else {
+            return;
+        };
+
+        let mut conns = conns.lock().unwrap();
+
+        if let Some(count) = conns.get_mut(&self.ip) {
+            *count -= 1;
+
+            if *count == 0 {
+                conns.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_conn_limiter() {
+        let limiter = ConnLimiter::new(2, TrustedProxies::default());
+        let a = "192.0.2.1".parse().unwrap();
+        let b = "192.0.2.2".parse().unwrap();
+
+        let first = limiter.try_acquire(a).unwrap();
+        let _second = limiter.try_acquire(a).unwrap();
+
+        assert!(limiter.try_acquire(a).is_none());
+        assert!(limiter.try_acquire(b).is_some());
+
+        drop(first);
+        assert!(limiter.try_acquire(a).is_some());
+    }
+
+    #[test]
+    fn test_conn_limiter_leaves_trusted_proxies_unlimited() {
+        let limiter = ConnLimiter::new(1, TrustedProxies::parse(["10.0.0.0/8"]).unwrap());
+        let proxy = "10.0.0.1".parse().unwrap();
+        let mapped_proxy = "::ffff:10.0.0.1".parse().unwrap();
+        let client = "192.0.2.1".parse().unwrap();
+
+        let _first = limiter.try_acquire(proxy).unwrap();
+        let _second = limiter.try_acquire(proxy).unwrap();
+        let _third = limiter.try_acquire(mapped_proxy).unwrap();
+
+        let _client = limiter.try_acquire(client).unwrap();
+        assert!(limiter.try_acquire(client).is_none());
+    }
+}
@@ -6,26 +6,29 @@ use crate::utils::units::{bytes_to_display, mib_to_bytes};
 use anyhow::{anyhow, bail, Context, Error};
 use base_mem_check::{MemCheckState, WorkerHeapStatistics};
 use cooked_waker::{IntoWaker, WakeRef};
-use cpu_timer::get_thread_time;
+use cpu_timer::{get_thread_time, ThreadCPUClock};
 use ctor::ctor;
+use deno_broadcast_channel::InMemoryBroadcastChannel;
 use deno_core::error::AnyError;
 use deno_core::url::Url;
 use deno_core::v8::{GCCallbackFlags, GCType, HeapStatistics, Isolate};
 use deno_core::{
-    located_script_name, serde_json, JsRuntime, ModuleCodeString, ModuleId, PollEventLoopOptions,
-    RuntimeOptions,
+    located_script_name, serde_json, CompiledWasmModuleStore, FeatureChecker, JsRuntime,
+    ModuleCodeString, ModuleId, PollEventLoopOptions, RuntimeOptions, SharedArrayBufferStore,
 };
+use deno_fetch::{reqwest, CreateHttpClientOptions};
 use deno_http::DefaultHttpPropertyExtractor;
-use deno_tls::deno_native_certs::load_native_certs;
-use deno_tls::rustls::RootCertStore;
 use deno_tls::RootCertStoreProvider;
 use futures_util::future::poll_fn;
 use futures_util::task::AtomicWaker;
 use log::{error, trace};
 use once_cell::sync::{Lazy, OnceCell};
-use sb_core::conn_sync::DenoRuntimeDropToken;
+use sb_core::broadcast_channel::{bus_key, bus_of, may_share_main_bus, BusLease, MAIN_BUS};
+use sb_core::conn_sync::{DenoRuntimeDropToken, RequestMarks};
+use sb_core::cpu_profile::{CpuProfiler, CPU_PROFILE_CONFIG};
 use sb_core::http::sb_core_http;
 use sb_core::http_start::sb_core_http_start;
+use sb_core::util::http_util::create_http_client_with_resolver;
 use sb_core::util::sync::AtomicFlag;
 use sb_fs::static_fs::StaticFs;
 use serde::Serialize;
@@ -35,13 +38,16 @@ use std::ffi::c_void;
 use std::fmt;
 use std::future::Future;
 use std::marker::PhantomData;
+use std::path::Path;
+use std::rc::Rc;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, RwLock};
 use std::task::Poll;
 use std::thread::ThreadId;
 use std::time::Duration;
-use tokio::sync::mpsc;
-use tokio::time::interval;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{interval, sleep};
 use tokio_util::sync::CancellationToken;
 use tracing::debug;
 
@@ -50,26 +56,46 @@ use event_worker::events::{EventMetadata, WorkerEventWithMetadata};
 use event_worker::js_interceptors::sb_events_js_interceptors;
 use event_worker::sb_user_event_worker;
 use sb_ai::sb_ai;
+use sb_cache::CacheOptions;
+use sb_core::background_tasks::BackgroundTasks;
 use sb_core::cache::CacheSetting;
-use sb_core::cert::ValueRootCertStoreProvider;
+use sb_core::cert::SharedRootCertStoreProvider;
+use sb_core::egress::{EgressPolicy, EgressResolver};
+use sb_core::execution_context::{ExecutionClock, ExecutionContext};
 use sb_core::external_memory::CustomAllocator;
 use sb_core::net::sb_core_net;
-use sb_core::permissions::{sb_core_permissions, Permissions};
+use sb_core::permissions::{sb_core_permissions, Permissions, PermissionsOptions, FFI_ALLOWED};
+use sb_core::proxy::parse_proxy;
 use sb_core::runtime::sb_core_runtime;
 use sb_core::{sb_core_main_js, MemCheckWaker};
 use sb_env::sb_env as sb_env_op;
+use sb_env::secrets::load_secrets;
 use sb_fs::file_system::DenoCompileFileSystem;
 use sb_graph::emitter::EmitterFactory;
+use sb_graph::graph_limits::ModuleGraphLimits;
 use sb_graph::import_map::load_import_map;
 use sb_graph::{generate_binary_eszip, include_glob_patterns_in_eszip, EszipPayloadKind};
+use sb_kv::KvOptions;
 use sb_module_loader::standalone::create_module_loader_for_standalone_from_eszip_kind;
 use sb_module_loader::RuntimeProviders;
 use sb_node::deno_node;
 use sb_workers::context::{UserWorkerMsgs, WorkerContextInitOpts, WorkerRuntimeOpts};
 use sb_workers::sb_user_workers;
+use sb_workers::shared_buffers::{drop_shared_buffers, register_shared_buffers, UserWorkerKey};
 
 const DEFAULT_ALLOC_CHECK_INT_MSEC: u64 = 1000;
 
+/// Files probed, in order, for the entrypoint of a service that doesn't name
+/// one.
+const ENTRYPOINT_CANDIDATES: &[&str] = &[
+    "index.ts",
+    "index.tsx",
+    "index.js",
+    "index.jsx",
+    "index.mjs",
+    "main.ts",
+];
+
 static SUPABASE_UA: Lazy<String> = Lazy::new(|| {
     let deno_version = MAYBE_DENO_VERSION.get().map(|it| &**it).unwrap_or("1.0.0");
     let supabase_version = option_env!("GIT_V_TAG").unwrap_or("0.1.0");
@@ -87,6 +113,16 @@ static ALLOC_CHECK_DUR: Lazy<Duration> = Lazy::new(|| {
         .unwrap_or_else(|| Duration::from_millis(DEFAULT_ALLOC_CHECK_INT_MSEC))
 });
 
+/// Compiled WASM modules, shared by all workers so that a module one of them
+/// compiled and sent, e.g. over a `BroadcastChannel`, is received by another
+/// without being compiled again.
+static COMPILED_WASM_MODULE_STORE: Lazy<CompiledWasmModuleStore> =
+    Lazy::new(CompiledWasmModuleStore::default);
+
+/// Backing stores of `SharedArrayBuffer`s being sent between workers.
+static SHARED_ARRAY_BUFFER_STORE: Lazy<SharedArrayBufferStore> =
+    Lazy::new(SharedArrayBufferStore::default);
+
 // Following static variables are initialized in the cli crate.
 
 pub static SHOULD_DISABLE_DEPRECATED_API_WARNING: OnceCell<bool> = OnceCell::new();
@@ -94,6 +130,31 @@ pub static SHOULD_USE_VERBOSE_DEPRECATED_API_WARNING: OnceCell<bool> = OnceCell:
 pub static SHOULD_INCLUDE_MALLOCED_MEMORY_ON_MEMCHECK: OnceCell<bool> = OnceCell::new();
 pub static MAYBE_DENO_VERSION: OnceCell<String> = OnceCell::new();
 
+pub const V8_FLAGS_ENV: &str = "V8_FLAGS";
+
+/// Flags V8 was started with, see `--v8-flags`.
+pub static V8_FLAGS: OnceCell<Vec<String>> = OnceCell::new();
+
+/// V8 flags a service may set with `v8_flags`. They only tune the compiler
+/// tiers and the GC; flags that reach further, like `--allow-natives-syntax`,
+/// are left to the operator through `--v8-flags`.
+pub const SERVICE_V8_FLAGS: &[&str] = &[
+    "--jitless",
+    "--max-lazy",
+    "--no-lazy-feedback-allocation",
+    "--no-maglev",
+    "--no-opt",
+    "--no-sparkplug",
+    "--optimize-for-size",
+    "--single-threaded-gc",
+];
+
+/// Whether a service may set `flag`, see [`SERVICE_V8_FLAGS`].
+pub fn is_service_v8_flag(flag: &str) -> bool {
+    // V8 reads `_` as `-` in flag names.
+    SERVICE_V8_FLAGS.contains(&flag.replace('_', "-").as_str())
+}
+
 #[ctor]
 fn init_v8_platform() {
     set_v8_flags();
@@ -121,6 +182,14 @@ fn get_error_class_name(e: &AnyError) -> &'static str {
     sb_core::errors_rt::get_error_class_name(e).unwrap_or("Error")
 }
 
+/// Connection pooling of `fetch()` in a user worker.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FetchPoolOptions {
+    keep_alive_ms: Option<u64>,
+    max_idle_per_host: Option<usize>,
+    connect_timeout_ms: Option<u64>,
+}
+
 #[derive(Default)]
 struct MemCheck {
     exceeded_token: CancellationToken,
@@ -202,7 +271,12 @@ pub struct DenoRuntime<RuntimeContext = ()> {
     maybe_inspector: Option<Inspector>,
 
     mem_check: Arc<MemCheck>,
+    background_tasks: BackgroundTasks,
     waker: Arc<AtomicWaker>,
+    _bus_lease: Option<BusLease>,
+    /// Told about a turn of the event loop that has held its thread for too
+    /// long. Set by the supervisor of a user worker.
+    pub(crate) turn_overrun_tx: Option<mpsc::UnboundedSender<()>>,
 
     _phantom_runtime_context: PhantomData<RuntimeContext>,
 }
@@ -211,6 +285,10 @@ impl<RuntimeContext> Drop for DenoRuntime<RuntimeContext> {
     fn drop(&mut self) {
         self.drop_token.cancel();
 
+        if let Some(key) = self.conf.as_user_worker().and_then(|it| it.key) {
+            drop_shared_buffers(&key);
+        }
+
         if self.conf.is_user_worker() {
             self.js_runtime.v8_isolate().remove_gc_prologue_callback(
                 mem_check_gc_prologue_callback_fn,
@@ -248,45 +326,124 @@ where
 
         // TODO(Nyannyacha): Make sure `service_path` is an absolute path first.
 
+        // NOTE: A service can be deployed as a single `.eszip` bundle in place
+        // of its directory, in which case it's resolved against the directory
+        // it replaces, `<root>/<name>` for `<root>/<name>.eszip`. Resolving
+        // it against the directory holding the bundle would hand the read
+        // permissions and the KV, cache and BroadcastChannel namespaces of
+        // the whole root to every bundle in it.
+        let (service_path, maybe_eszip) = match maybe_eszip {
+            None if service_path.extension().is_some_and(|it| it == "eszip")
+                && service_path.is_file() =>
+            {
+                let eszip = EszipPayloadKind::VecKind(tokio::fs::read(&service_path).await?);
+
+                (service_path.with_extension(""), Some(eszip))
+            }
+
+            maybe_eszip => (service_path, maybe_eszip),
+        };
+
         let drop_token = CancellationToken::default();
 
         let base_dir_path = std::env::current_dir().map(|p| p.join(&service_path))?;
         let base_url = Url::from_directory_path(&base_dir_path).unwrap();
+        let service_namespace = base_dir_path.to_string_lossy().into_owned();
 
         let is_user_worker = conf.is_user_worker();
 
-        let potential_exts = vec!["ts", "tsx", "js", "jsx"];
-        let mut main_module_url = base_url.join("index.ts")?;
-
-        for potential_ext in potential_exts {
-            main_module_url = base_url.join(format!("index.{}", potential_ext).as_str())?;
-            if main_module_url.to_file_path().unwrap().exists() {
-                break;
-            }
-        }
-
         let is_some_entry_point = maybe_entrypoint.is_some();
-        if is_some_entry_point {
-            main_module_url = Url::parse(&maybe_entrypoint.unwrap())?;
-        }
+        let main_module_url = resolve_main_module(
+            &base_url,
+            maybe_entrypoint.as_deref(),
+            maybe_eszip.is_some(),
+        )?;
 
-        let mut net_access_disabled = false;
-        let mut allow_net = None;
+        let mut permissions = PermissionsOptions::default();
         let mut allow_remote_modules = true;
+        let mut module_graph_limits = ModuleGraphLimits::default();
+        let mut maybe_http_proxy = None;
+        let mut fetch_pool = FetchPoolOptions::default();
+        let mut maybe_crypto_seed = None;
+        let mut kv_max_size_bytes = None;
+        let mut share_broadcast_channel = false;
+        let mut secrets = sb_env::EnvVars::new();
         if is_user_worker {
             let user_conf = conf.as_user_worker().unwrap();
 
-            net_access_disabled = user_conf.net_access_disabled;
+            maybe_http_proxy = user_conf
+                .http_proxy
+                .as_deref()
+                .map(parse_proxy)
+                .transpose()?;
+            fetch_pool = FetchPoolOptions {
+                keep_alive_ms: user_conf.fetch_keep_alive_ms,
+                max_idle_per_host: user_conf.fetch_max_idle_per_host,
+                connect_timeout_ms: user_conf.fetch_connect_timeout_ms,
+            };
+            maybe_crypto_seed = user_conf.crypto_seed;
+            kv_max_size_bytes =
+                (user_conf.kv_max_size_mb > 0).then(|| mib_to_bytes(user_conf.kv_max_size_mb));
+            share_broadcast_channel = user_conf.share_broadcast_channel;
+
+            if share_broadcast_channel && !may_share_main_bus(&base_dir_path) {
+                bail!(
+                    "share_broadcast_channel requires the service to be listed in --share-broadcast-channel"
+                );
+            }
+
+            // NOTE: Looked up by the resolved path of the service, so that a
+            // bundle gets the secrets of the directory it stands in for.
+            secrets = load_secrets(&service_path)?;
+
+            // NOTE: A native library bypasses every other permission, so the
+            // operator has to allow them before a service can ask for it.
+            if user_conf.allow_ffi && !FFI_ALLOWED.load(Ordering::Relaxed) {
+                bail!("allow_ffi requires the runtime to be started with --allow-ffi");
+            }
+
             allow_remote_modules = user_conf.allow_remote_modules;
+            module_graph_limits = ModuleGraphLimits {
+                max_size_bytes: (user_conf.max_module_graph_size_mb > 0)
+                    .then(|| mib_to_bytes(user_conf.max_module_graph_size_mb)),
+                max_remote_modules: (user_conf.max_remote_modules > 0)
+                    .then_some(user_conf.max_remote_modules as usize),
+            };
 
-            allow_net = match &user_conf.allow_net {
-                Some(allow_net) => Some(
-                    allow_net
+            permissions = PermissionsOptions {
+                net_access_disabled: user_conf.net_access_disabled,
+                allow_net: match &user_conf.allow_net {
+                    Some(allow_net) => Some(
+                        allow_net
+                            .iter()
+                            .map(|s| FromStr::from_str(s.as_str()))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    ),
+                    None => None,
+                },
+                // NOTE: A service can always read its own files. Relative
+                // paths are resolved against it.
+                allow_read: user_conf.allow_read.as_ref().map(|allow_read| {
+                    allow_read
                         .iter()
-                        .map(|s| FromStr::from_str(s.as_str()))
-                        .collect::<Result<Vec<_>, _>>()?,
-                ),
-                None => None,
+                        .map(|it| base_dir_path.join(it))
+                        .chain(std::iter::once(base_dir_path.clone()))
+                        .collect()
+                }),
+                allow_write: user_conf.allow_write.as_ref().map(|allow_write| {
+                    allow_write
+                        .iter()
+                        .map(|it| base_dir_path.join(it))
+                        .collect()
+                }),
+                base_dir: Some(base_dir_path.clone()),
+                allow_env: user_conf.allow_env.clone(),
+                allow_hrtime: user_conf.allow_hrtime,
+                allow_ffi: user_conf.allow_ffi,
+                egress_policy: EgressPolicy::parse(
+                    &user_conf.egress_allow,
+                    user_conf.egress_deny.as_deref(),
+                )?,
             };
         }
 
@@ -306,6 +463,9 @@ where
             };
 
             emitter_factory.set_file_fetcher_allow_remote(allow_remote_modules);
+            emitter_factory.set_module_graph_limits(module_graph_limits);
+            emitter_factory.set_package_json_dir(&base_dir_path)?;
+            emitter_factory.set_lockfile_dir(&base_dir_path)?;
             emitter_factory.set_file_fetcher_cache_strategy(cache_strategy);
             emitter_factory.set_decorator_type(maybe_decorator);
 
@@ -346,45 +506,35 @@ where
             EszipPayloadKind::Eszip(eszip)
         };
 
-        // Create and populate a root cert store based on environment variable.
-        // Reference: https://github.com/denoland/deno/blob/v1.37.0/cli/args/mod.rs#L467
-        let mut root_cert_store = RootCertStore::empty();
-        let ca_stores: Vec<String> = (|| {
-            let env_ca_store = std::env::var("DENO_TLS_CA_STORE").ok()?;
-            Some(
-                env_ca_store
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect(),
-            )
-        })()
-        .unwrap_or_else(|| vec!["mozilla".to_string()]);
-
-        for store in ca_stores.iter() {
-            match store.as_str() {
-                "mozilla" => {
-                    root_cert_store = deno_tls::create_default_root_cert_store();
-                }
-                "system" => {
-                    let roots = load_native_certs().expect("could not load platform certs");
-                    for root in roots {
-                        root_cert_store
-                            .add((&*root.0).into())
-                            .expect("Failed to add platform cert to root cert store");
-                    }
-                }
-                _ => {
-                    bail!(
-                        "Unknown certificate store \"{0}\" specified (allowed: \"system,mozilla\")",
-                        store
-                    );
-                }
-            }
-        }
-
+        // NOTE: The store is built on the first boot and shared afterwards.
+        // Loading it here fails the boot if the configured CAs can't be read,
+        // rather than the first TLS connection.
         let root_cert_store_provider: Arc<dyn RootCertStoreProvider> =
-            Arc::new(ValueRootCertStoreProvider::new(root_cert_store.clone()));
+            Arc::new(SharedRootCertStoreProvider);
+
+        root_cert_store_provider.get_or_try_init()?;
+
+        // NOTE: `fetch()` creates its client from the options of the extension
+        // unless one is already in the state, and those options cover neither
+        // pooling nor the resolver. The client of every worker resolves hosts
+        // through the DNS overrides of the runtime, and checks the addresses
+        // it connects to against the egress policy, which only user workers
+        // have.
+        let fetch_client = create_http_client_with_resolver(
+            &SUPABASE_UA,
+            CreateHttpClientOptions {
+                root_cert_store: Some(root_cert_store_provider.get_or_try_init()?.clone()),
+                proxy: maybe_http_proxy.clone(),
+                pool_max_idle_per_host: match fetch_pool.keep_alive_ms {
+                    Some(0) => Some(0),
+                    _ => fetch_pool.max_idle_per_host,
+                },
+                pool_idle_timeout: fetch_pool.keep_alive_ms.filter(|it| *it > 0).map(Some),
+                ..Default::default()
+            },
+            Arc::new(EgressResolver(Arc::new(permissions.egress_policy.clone()))),
+            fetch_pool.connect_timeout_ms.map(Duration::from_millis),
+        )?;
 
         let mut stdio = Some(Default::default());
 
@@ -414,6 +564,7 @@ where
             static_files,
             npm_snapshot,
             vfs_path,
+            source_maps,
         } = rt_provider;
 
         let op_fs = {
@@ -432,8 +583,25 @@ where
 
         let mod_code = module_code;
 
+        // NOTE: The workers of a service share a bus, so that a message reaches
+        // all of them. The events worker keeps one of its own. Services listed
+        // in `--share-broadcast-channel` may join the bus of the main worker
+        // instead (see `process_worker` for workers run in isolation).
+        let (broadcast_channel, bus_lease) = if conf.is_main_worker() {
+            let (bus, lease) = bus_of(MAIN_BUS);
+            (bus, Some(lease))
+        } else if is_user_worker {
+            let (bus, lease) = bus_of(&bus_key(
+                Path::new(&service_namespace),
+                share_broadcast_channel,
+            ));
+            (bus, Some(lease))
+        } else {
+            (InMemoryBroadcastChannel::default(), None)
+        };
+
         let extensions = vec![
-            sb_core_permissions::init_ops(net_access_disabled, allow_net),
+            sb_core_permissions::init_ops(permissions),
             deno_webidl::deno_webidl::init_ops(),
             deno_console::deno_console::init_ops(),
             deno_url::deno_url::init_ops(),
@@ -446,6 +614,7 @@ where
             deno_fetch::deno_fetch::init_ops::<Permissions>(deno_fetch::Options {
                 user_agent: SUPABASE_UA.clone(),
                 root_cert_store_provider: Some(root_cert_store_provider.clone()),
+                proxy: maybe_http_proxy,
                 ..Default::default()
             }),
             deno_websocket::deno_websocket::init_ops::<Permissions>(
@@ -453,19 +622,19 @@ where
                 Some(root_cert_store_provider.clone()),
                 None,
             ),
-            // TODO: support providing a custom seed for crypto
-            deno_crypto::deno_crypto::init_ops(None),
-            deno_broadcast_channel::deno_broadcast_channel::init_ops(
-                deno_broadcast_channel::InMemoryBroadcastChannel::default(),
-            ),
+            deno_crypto::deno_crypto::init_ops(maybe_crypto_seed),
+            deno_broadcast_channel::deno_broadcast_channel::init_ops(broadcast_channel),
             deno_net::deno_net::init_ops::<Permissions>(Some(root_cert_store_provider), None),
             deno_tls::deno_tls::init_ops(),
             deno_http::deno_http::init_ops::<DefaultHttpPropertyExtractor>(),
             deno_io::deno_io::init_ops(stdio),
             deno_fs::deno_fs::init_ops::<Permissions>(op_fs.clone()),
+            deno_ffi::deno_ffi::init_ops::<Permissions>(),
             sb_env_op::init_ops(),
             sb_ai::init_ops(),
             sb_os::sb_os::init_ops(),
+            sb_kv::sb_kv::init_ops(),
+            sb_cache::sb_cache::init_ops(),
             sb_user_workers::init_ops(),
             sb_user_event_worker::init_ops(),
             sb_events_js_interceptors::init_ops(),
@@ -483,8 +652,9 @@ where
         let mut mem_check = MemCheck::default();
 
         if conf.is_user_worker() {
-            let memory_limit =
-                mib_to_bytes(conf.as_user_worker().unwrap().memory_limit_mb) as usize;
+            let user_conf = conf.as_user_worker().unwrap();
+            let memory_limit = mib_to_bytes(user_conf.memory_limit_mb) as usize;
+            let initial_heap_size = mib_to_bytes(user_conf.initial_heap_size_mb()) as usize;
 
             let allocator = CustomAllocator::new(memory_limit);
 
@@ -493,22 +663,36 @@ where
             mem_check.limit = Some(memory_limit);
             create_params = Some(
                 deno_core::v8::CreateParams::default()
-                    .heap_limits(mib_to_bytes(0) as usize, memory_limit)
+                    .heap_limits(initial_heap_size, memory_limit)
                     .array_buffer_allocator(allocator.into_v8_allocator()),
             )
         };
 
         let mem_check = Arc::new(mem_check);
+        let background_tasks = BackgroundTasks::default();
+
+        // NOTE: These APIs are gated as unstable by their extensions, which
+        // exit the process when a disabled one is called. FFI is denied by the
+        // permissions of the worker instead.
+        let mut feature_checker = FeatureChecker::default();
+        feature_checker.enable_feature(deno_broadcast_channel::UNSTABLE_FEATURE_NAME);
+        feature_checker.enable_feature(deno_ffi::UNSTABLE_FEATURE_NAME);
+
         let runtime_options = RuntimeOptions {
             extensions,
             is_main: true,
-            inspector: maybe_inspector.is_some(),
+            // NOTE: CPU profiles are taken through a local session of the
+            // inspector.
+            inspector: maybe_inspector.is_some()
+                || (is_user_worker && CPU_PROFILE_CONFIG.get().is_some()),
             create_params,
             get_error_class_fn: Some(&get_error_class_name),
-            shared_array_buffer_store: None,
-            compiled_wasm_module_store: None,
+            shared_array_buffer_store: Some(SHARED_ARRAY_BUFFER_STORE.clone()),
+            compiled_wasm_module_store: Some(COMPILED_WASM_MODULE_STORE.clone()),
             startup_snapshot: snapshot::snapshot(),
             module_loader: Some(module_loader),
+            source_map_getter: Some(Rc::new(source_maps)),
+            feature_checker: Some(Arc::new(feature_checker)),
             ..Default::default()
         };
 
@@ -579,6 +763,17 @@ where
             .execute_script(located_script_name!(), ModuleCodeString::from(script))
             .expect("Failed to execute bootstrap script");
 
+        let maybe_cpu_profiler =
+            (is_user_worker && CPU_PROFILE_CONFIG.get().is_some()).then(|| {
+                let name = conf
+                    .as_user_worker()
+                    .and_then(|it| it.key)
+                    .map(|it| it.to_string())
+                    .unwrap_or_default();
+
+                CpuProfiler::new(js_runtime.inspector(), name)
+            });
+
         {
             // run inside a closure, so op_state_rc is released
             let op_state_rc = js_runtime.op_state();
@@ -596,15 +791,57 @@ where
                 op_state.put::<HashMap<usize, CancellationToken>>(HashMap::new());
             }
 
+            op_state.put::<reqwest::Client>(fetch_client);
+
             if conf.is_user_worker() {
                 let conf = conf.as_user_worker().unwrap();
 
+                op_state.put::<BackgroundTasks>(background_tasks.clone());
+                op_state.put::<HashMap<usize, RequestMarks>>(HashMap::new());
+
+                if let Some(allowlist) = conf.env_allowlist.as_ref() {
+                    env_vars.retain(|key, _| allowlist.contains(key));
+                }
+
+                env_vars.extend(secrets);
+
                 // set execution id for user workers
                 env_vars.insert(
                     "SB_EXECUTION_ID".to_string(),
                     conf.key.map_or("".to_string(), |k| k.to_string()),
                 );
 
+                if let Some(key) = conf.key {
+                    register_shared_buffers(key);
+                    op_state.put(UserWorkerKey(key));
+                }
+
+                if let Some(cpu_profiler) = maybe_cpu_profiler {
+                    op_state.put(cpu_profiler);
+                }
+
+                op_state.put(ExecutionContext {
+                    execution_id: conf.key.map(|it| it.to_string()),
+                    service_name: conf.service_path.as_deref().and_then(|it| {
+                        Path::new(it)
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                    }),
+                    cpu_time_limit_ms: (conf.cpu_time_hard_limit_ms > 0)
+                        .then_some(conf.cpu_time_hard_limit_ms),
+                    memory_limit_bytes: (conf.memory_limit_mb > 0)
+                        .then(|| mib_to_bytes(conf.memory_limit_mb) as usize),
+                    clock: Arc::new({
+                        let clock = ExecutionClock::default();
+
+                        clock.set_deadline(
+                            (conf.worker_timeout_ms > 0)
+                                .then(|| Duration::from_millis(conf.worker_timeout_ms)),
+                        );
+                        clock
+                    }),
+                });
+
                 if let Some(events_msg_tx) = conf.events_msg_tx.clone() {
                     op_state.put::<mpsc::UnboundedSender<WorkerEventWithMetadata>>(events_msg_tx);
                     op_state.put::<EventMetadata>(EventMetadata {
@@ -615,6 +852,13 @@ where
             }
 
             op_state.put::<sb_env::EnvVars>(env_vars);
+            op_state.put(CacheOptions {
+                namespace: service_namespace.clone(),
+            });
+            op_state.put(KvOptions {
+                namespace: service_namespace,
+                max_size_bytes: kv_max_size_bytes,
+            });
             op_state.put(DenoRuntimeDropToken(drop_token.clone()))
         }
 
@@ -667,7 +911,10 @@ where
             maybe_inspector,
 
             mem_check,
+            background_tasks,
             waker: Arc::default(),
+            _bus_lease: bus_lease,
+            turn_overrun_tx: None,
 
             _phantom_runtime_context: PhantomData,
         })
@@ -814,8 +1061,40 @@ where
         let termination_request_token = self.termination_request_token.clone();
 
         let mem_check_state = is_user_worker.then(|| self.mem_check.clone());
+        let execution_clock = self.execution_clock();
+        let time_slice = is_user_worker
+            .then_some(*base_rt::USER_WORKER_TIME_SLICE)
+            .flatten();
+
+        let time_slice_ns = time_slice.map(|it| it.as_nanos() as i64);
+        let max_turn_slices = *base_rt::USER_WORKER_MAX_TURN_SLICES;
+        let turn_tx = time_slice
+            .filter(|_| max_turn_slices > 0)
+            .zip(self.turn_overrun_tx.clone())
+            .map(|(it, overrun_tx)| {
+                let (tx, rx) = watch::channel(None);
+                let limit = it * max_turn_slices;
+
+                drop(
+                    base_rt::SUPERVISOR_RT.spawn(watch_turns(rx, limit, move || {
+                        let _ = overrun_tx.send(());
+                    })),
+                );
+
+                tx
+            });
+
+        let mut should_yield = false;
 
         poll_fn(move |cx| {
+            // A turn that overran the time slice puts the worker at the back of
+            // the queue of its pool thread, so that other workers sharing the
+            // thread get to run before it does again.
+            if std::mem::take(&mut should_yield) {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+
             // INVARIANT: Only can steal current task by other threads when LIFO
             // task scheduler heuristic disabled. Turning off the heuristic is
             // unstable now, so it's not considered.
@@ -831,6 +1110,7 @@ where
             let mut this = self.get_v8_tls_guard();
 
             let js_runtime = &mut this.js_runtime;
+            let cpu_time_before_poll_ns = *accumulated_cpu_time_ns;
             let cpu_metrics_guard = get_cpu_metrics_guard(
                 thread_id,
                 maybe_cpu_usage_metrics_tx,
@@ -861,19 +1141,49 @@ where
                     Cow::Borrowed(waker)
                 };
 
-                js_runtime.poll_event_loop(
+                if let Some(tx) = turn_tx.as_ref() {
+                    tx.send_replace(TurnStart::now());
+                }
+
+                let poll_result = js_runtime.poll_event_loop(
                     &mut std::task::Context::from_waker(waker.as_ref()),
                     PollEventLoopOptions {
                         wait_for_inspector,
                         ..Default::default()
                     },
-                )
+                );
+
+                if let Some(tx) = turn_tx.as_ref() {
+                    tx.send_replace(None);
+                }
+
+                poll_result
             } else {
                 Poll::Pending
             };
 
             drop(cpu_metrics_guard);
 
+            if let Some(clock) = execution_clock.as_ref() {
+                clock
+                    .cpu_time_used_ns
+                    .store(*accumulated_cpu_time_ns, Ordering::Relaxed);
+            }
+
+            if let Some(time_slice_ns) = time_slice_ns {
+                let turn_ns = *accumulated_cpu_time_ns - cpu_time_before_poll_ns;
+
+                if turn_ns > time_slice_ns && poll_result.is_pending() {
+                    trace!(
+                        "name: {:?}, ran {}ms in a single turn, yielding",
+                        name.as_ref(),
+                        turn_ns / 1_000_000
+                    );
+
+                    should_yield = true;
+                }
+            }
+
             if is_user_worker {
                 let mem_state = mem_check_state.as_ref().unwrap();
                 let total_malloced_bytes = mem_state.check(js_runtime.v8_isolate().as_mut());
@@ -913,6 +1223,18 @@ where
         self.mem_check.state.clone()
     }
 
+    pub fn background_tasks(&self) -> BackgroundTasks {
+        self.background_tasks.clone()
+    }
+
+    pub fn execution_clock(&self) -> Option<Arc<ExecutionClock>> {
+        self.js_runtime
+            .op_state()
+            .borrow()
+            .try_borrow::<ExecutionContext>()
+            .map(|it| it.clock.clone())
+    }
+
     pub fn add_memory_limit_callback<C>(&self, cb: C)
     where
         // XXX(Nyannyacha): Should we relax bounds a bit more?
@@ -970,6 +1292,53 @@ where
     }
 }
 
+/// Resolves the entrypoint of a service. An explicit one is a path relative
+/// to the service, which it can't leave; only a bundle may name any of the
+/// modules it holds by URL.
+fn resolve_main_module(
+    base_url: &Url,
+    maybe_entrypoint: Option<&str>,
+    is_bundle: bool,
+) -> Result<Url, Error> {
+    if let Some(entrypoint) = maybe_entrypoint {
+        let url = base_url
+            .join(entrypoint)
+            .with_context(|| format!("invalid entrypoint: {}", entrypoint))?;
+
+        if is_bundle {
+            return Ok(url);
+        }
+
+        // NOTE: `join` has already resolved `..`, but a symlink in the service
+        // can still point outside of it.
+        let is_inside = match (url.to_file_path(), base_url.to_file_path()) {
+            (Ok(path), Ok(base_dir)) => match (path.canonicalize(), base_dir.canonicalize()) {
+                (Ok(path), Ok(base_dir)) => path.starts_with(base_dir),
+                _ => path.starts_with(base_dir),
+            },
+
+            _ => false,
+        };
+
+        if !is_inside {
+            bail!("entrypoint {} is outside of the service", entrypoint);
+        }
+
+        return Ok(url);
+    }
+
+    for candidate in ENTRYPOINT_CANDIDATES {
+        let url = base_url.join(candidate)?;
+
+        if url.to_file_path().is_ok_and(|it| it.exists()) {
+            return Ok(url);
+        }
+    }
+
+    // NOTE: Services given as module code have no file to find.
+    Ok(base_url.join(ENTRYPOINT_CANDIDATES[0])?)
+}
+
 fn get_current_cpu_time_ns() -> Result<i64, Error> {
     get_thread_time().context("can't get current thread time")
 }
@@ -1022,21 +1391,56 @@ fn get_cpu_metrics_guard<'l>(
     })
 }
 
+/// Collects the V8 flags of `V8_FLAGS` and of the `--v8-flags` options on the
+/// command line.
+fn v8_flags_from_env_and_args() -> Vec<String> {
+    let split = |it: &str| {
+        it.split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    let mut v8_flags = std::env::var(V8_FLAGS_ENV)
+        .map(|it| split(&it))
+        .unwrap_or_default();
+
+    // NOTE: This runs before `main`, so the arguments are scanned by hand
+    // rather than parsed by the CLI.
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+
+        if let Some(value) = arg.strip_prefix("--v8-flags=") {
+            v8_flags.extend(split(value));
+        } else if arg == "--v8-flags" {
+            v8_flags.extend(args.next().as_deref().map(split).unwrap_or_default());
+        }
+    }
+
+    v8_flags
+}
+
 fn set_v8_flags() {
-    let v8_flags = std::env::var("V8_FLAGS").unwrap_or("".to_string());
-    let mut vec = vec![""];
+    let v8_flags = v8_flags_from_env_and_args();
 
     if v8_flags.is_empty() {
         return;
     }
 
-    vec.append(&mut v8_flags.split(' ').collect());
+    let mut vec = vec![String::new()];
+
+    vec.extend(v8_flags.iter().cloned());
 
-    let ignored = deno_core::v8_set_flags(vec.iter().map(|v| v.to_string()).collect());
+    let ignored = deno_core::v8_set_flags(vec);
 
     if *ignored.as_slice() != [""] {
         error!("v8 flags unrecognized {:?}", ignored);
     }
+
+    let _ = V8_FLAGS.set(v8_flags);
 }
 
 extern "C" fn mem_check_gc_prologue_callback_fn(
@@ -1050,6 +1454,73 @@ extern "C" fn mem_check_gc_prologue_callback_fn(
     }
 }
 
+/// Where a turn of the event loop started on the CPU clock of its thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TurnStart {
+    clock: ThreadCPUClock,
+    cpu_time_ns: i64,
+}
+
+impl TurnStart {
+    fn now() -> Option<Self> {
+        let clock = ThreadCPUClock::current().ok()?;
+
+        Some(Self {
+            clock,
+            cpu_time_ns: clock.now().ok()?,
+        })
+    }
+}
+
+/// Calls `on_overrun` once a turn of the event loop, whose start is sent over
+/// `turn_rx`, has used `limit` of CPU time. Returns once the sender is gone.
+async fn watch_turns<F>(
+    mut turn_rx: watch::Receiver<Option<TurnStart>>,
+    limit: Duration,
+    on_overrun: F,
+) where
+    F: FnOnce(),
+{
+    let limit_ns = limit.as_nanos() as i64;
+
+    loop {
+        let started_at = *turn_rx.borrow_and_update();
+        let Some(started_at) = started_at else {
+            if turn_rx.changed().await.is_err() {
+                return;
+            }
+
+            continue;
+        };
+
+        let Ok(cpu_time_ns) = started_at.clock.now() else {
+            return;
+        };
+
+        let used_ns = cpu_time_ns - started_at.cpu_time_ns;
+
+        if used_ns >= limit_ns {
+            if *turn_rx.borrow() == Some(started_at) {
+                on_overrun();
+                return;
+            }
+
+            continue;
+        }
+
+        // NOTE: A turn can't use CPU time faster than the wall clock goes, so
+        // it can't overrun before then.
+        tokio::select! {
+            _ = sleep(Duration::from_nanos((limit_ns - used_ns) as u64)) => {}
+            changed = turn_rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::deno_runtime::DenoRuntime;
@@ -1079,7 +1550,7 @@ mod test {
     use tokio::time::timeout;
     use url::Url;
 
-    use super::GetRuntimeContext;
+    use super::{resolve_main_module, watch_turns, GetRuntimeContext, TurnStart};
 
     impl<RuntimeContext> DenoRuntime<RuntimeContext> {
         fn to_value_mut<T>(&mut self, global_value: &v8::Global<v8::Value>) -> Result<T, AnyError>
@@ -1402,6 +1873,101 @@ mod test {
         std::mem::drop(main_mod_ev);
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_service_bundle() {
+        let bundle_path = PathBuf::from("./test_cases/eszip-silly-test.eszip");
+        let binary_eszip = generate_binary_eszip(
+            PathBuf::from("./test_cases/eszip-silly-test/index.ts"),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        fs::write(&bundle_path, binary_eszip.into_bytes()).unwrap();
+
+        let _guard = scopeguard::guard(bundle_path, |it| {
+            let _ = fs::remove_file(it);
+        });
+
+        // NOTE: The bundle stands in for `./test_cases/eszip-silly-test`, so
+        // its modules resolve against that directory rather than against
+        // `./test_cases`.
+        let mut rt = RuntimeBuilder::new()
+            .set_path("./test_cases/eszip-silly-test.eszip")
+            .set_worker_runtime_conf(WorkerRuntimeOpts::UserWorker(Default::default()))
+            .build()
+            .await;
+
+        let main_mod_ev = rt.js_runtime.mod_evaluate(rt.main_module_id);
+        let _ = rt
+            .js_runtime
+            .run_event_loop(PollEventLoopOptions::default())
+            .await;
+
+        let read_is_even_global = rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from("globalThis.isTenEven;".to_string()),
+            )
+            .unwrap();
+
+        let read_is_even = rt.to_value_mut::<serde_json::Value>(&read_is_even_global);
+
+        assert_eq!(read_is_even.unwrap().to_string(), "true");
+        std::mem::drop(main_mod_ev);
+    }
+
+    #[test]
+    fn test_resolve_main_module() {
+        let dir = std::env::temp_dir().join(format!("entrypoint-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let base_url = Url::from_directory_path(&dir).unwrap();
+
+        assert_eq!(
+            resolve_main_module(&base_url, None, false).unwrap(),
+            base_url.join("index.ts").unwrap()
+        );
+
+        File::create(dir.join("main.ts")).unwrap();
+        assert_eq!(
+            resolve_main_module(&base_url, None, false).unwrap(),
+            base_url.join("main.ts").unwrap()
+        );
+
+        File::create(dir.join("index.mjs")).unwrap();
+        assert_eq!(
+            resolve_main_module(&base_url, None, false).unwrap(),
+            base_url.join("index.mjs").unwrap()
+        );
+
+        assert_eq!(
+            resolve_main_module(&base_url, Some("src/server.ts"), false).unwrap(),
+            base_url.join("src/server.ts").unwrap()
+        );
+        assert_eq!(
+            resolve_main_module(&base_url, Some("file:///src/index.ts"), true).unwrap(),
+            Url::parse("file:///src/index.ts").unwrap()
+        );
+
+        assert!(resolve_main_module(&base_url, Some("../other/index.ts"), false).is_err());
+        assert!(resolve_main_module(&base_url, Some("/etc/passwd"), false).is_err());
+        assert!(resolve_main_module(&base_url, Some("file:///src/index.ts"), false).is_err());
+        assert!(resolve_main_module(&base_url, Some("https://example.com/x.ts"), false).is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", dir.join("etc")).unwrap();
+            assert!(resolve_main_module(&base_url, Some("etc/passwd"), false).is_err());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     // Main Runtime should have access to `EdgeRuntime`
     #[tokio::test]
     #[serial]
@@ -1757,6 +2323,43 @@ mod test {
         assert!(user_serde_deno_env.unwrap().is_null());
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_user_worker_env_allowlist() {
+        let mut user_rt = RuntimeBuilder::new()
+            .set_env_vars(HashMap::from([
+                ("KEEP".to_string(), "kept".to_string()),
+                ("DROP".to_string(), "dropped".to_string()),
+            ]))
+            .set_worker_runtime_conf(WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+                env_allowlist: Some(vec!["KEEP".to_string()]),
+                ..Default::default()
+            }))
+            .build()
+            .await;
+
+        assert_eq!(
+            user_rt.env_vars.get("KEEP").map(String::as_str),
+            Some("kept")
+        );
+        assert!(!user_rt.env_vars.contains_key("DROP"));
+
+        let env = user_rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from(
+                    r#"[Deno.env.get("KEEP"), Deno.env.get("DROP") ?? null]"#.to_string(),
+                ),
+            )
+            .unwrap();
+
+        assert_eq!(
+            user_rt.to_value_mut::<serde_json::Value>(&env).unwrap(),
+            serde_json::json!(["kept", null])
+        );
+    }
+
     fn create_basic_user_runtime_builder<T, U>(
         path: &str,
         memory_limit_mb: T,
@@ -1975,4 +2578,47 @@ mod test {
 
         user_rt.run(duplex_stream_rx, None, None).await.0.unwrap();
     }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_watch_turns_calls_back_on_overrun() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let limit = Duration::from_millis(100);
+        let (tx, rx) = tokio::sync::watch::channel(None);
+        let (overrun_tx, mut overrun_rx) = mpsc::unbounded_channel();
+        let watcher = tokio::spawn(watch_turns(rx, limit, move || {
+            overrun_tx.send(()).unwrap();
+        }));
+
+        // A turn that is waiting rather than running uses no CPU time, and is
+        // left alone however long it takes.
+        tx.send_replace(TurnStart::now());
+        tokio::time::sleep(limit * 2).await;
+        tx.send_replace(None);
+        assert!(overrun_rx.try_recv().is_err());
+
+        let is_done = Arc::new(AtomicBool::new(false));
+        let (start_tx, start_rx) = std::sync::mpsc::channel();
+        let busy_loop = std::thread::spawn({
+            let is_done = is_done.clone();
+            move || {
+                start_tx.send(TurnStart::now()).unwrap();
+
+                while !is_done.load(Ordering::Relaxed) {
+                    std::hint::spin_loop();
+                }
+            }
+        });
+
+        tx.send_replace(start_rx.recv().unwrap());
+        timeout(limit * 10, overrun_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        is_done.store(true, Ordering::Relaxed);
+        busy_loop.join().unwrap();
+        timeout(limit, watcher).await.unwrap().unwrap();
+    }
 }
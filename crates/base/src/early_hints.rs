@@ -0,0 +1,251 @@
+use std::{
+    io::IoSlice,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
+
+use http_v02::HeaderValue;
+use sb_core::conn_sync::EarlyHintsSender;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Preload links a worker wants sent to the client in a `103 Early Hints`.
+pub(super) static EARLY_HINTS_HEADER: &str = "x-early-hints";
+
+/// Queues `103 Early Hints` responses for the connection of a [`Stream`].
+///
+/// NOTE: hyper 0.14 can't write informational responses on the server side,
+/// so they are written by the stream below it instead, right before whatever
+/// hyper writes next. This only holds for HTTP/1.1, where hyper writes the
+/// responses of a connection one at a time.
+#[derive(Debug, Clone, Default)]
+pub(super) struct EarlyHints(Arc<Mutex<Queue>>);
+
+#[derive(Debug, Default)]
+struct Queue {
+    buf: Vec<u8>,
+    /// The request hints can be sent for, until its response is returned.
+    open: Option<u64>,
+    last: u64,
+}
+
+impl EarlyHints {
+    /// Lets hints be sent for the request about to be handled, until the
+    /// returned [`OpenRequest`] is dropped.
+    pub(super) fn open(&self) -> OpenRequest {
+        let mut queue = self.0.lock().unwrap();
+
+        queue.last += 1;
+        queue.open = Some(queue.last);
+
+        OpenRequest {
+            hints: self.clone(),
+            id: queue.last,
+        }
+    }
+
+    /// Queues a `103 Early Hints` with `links`, unless there are none or the
+    /// response of the request has already been returned.
+    fn send<'a>(&self, request: u64, links: impl IntoIterator<Item = &'a HeaderValue>) {
+        let mut queue = self.0.lock().unwrap();
+        let mut links = links.into_iter().peekable();
+
+        if queue.open != Some(request) || links.peek().is_none() {
+            return;
+        }
+
+        queue.buf.extend_from_slice(b"HTTP/1.1 103 Early Hints\r\n");
+
+        for link in links {
+            queue.buf.extend_from_slice(b"link: ");
+            queue.buf.extend_from_slice(link.as_bytes());
+            queue.buf.extend_from_slice(b"\r\n");
+        }
+
+        queue.buf.extend_from_slice(b"\r\n");
+    }
+}
+
+/// A request of the connection that hints can be sent for. Dropped once its
+/// response is returned, as hints can't follow the response.
+#[derive(Debug)]
+pub(super) struct OpenRequest {
+    hints: EarlyHints,
+    id: u64,
+}
+
+impl OpenRequest {
+    pub(super) fn send<'a>(&self, links: impl IntoIterator<Item = &'a HeaderValue>) {
+        self.hints.send(self.id, links);
+    }
+
+    /// Returns the sender the worker uses for `EdgeRuntime.sendEarlyHints()`.
+    pub(super) fn sender(&self) -> EarlyHintsSender {
+        let (hints, id) = (self.hints.clone(), self.id);
+
+        EarlyHintsSender::new(move |links| hints.send(id, links))
+    }
+}
+
+impl Drop for OpenRequest {
+    fn drop(&mut self) {
+        let mut queue = self.hints.0.lock().unwrap();
+
+        if queue.open == Some(self.id) {
+            queue.open = None;
+        }
+    }
+}
+
+pub(super) struct Stream<S> {
+    inner: S,
+    hints: EarlyHints,
+    pending: Vec<u8>,
+}
+
+impl<S> Stream<S> {
+    pub(super) fn new(inner: S) -> (Self, EarlyHints) {
+        let hints = EarlyHints::default();
+
+        (
+            Self {
+                inner,
+                hints: hints.clone(),
+                pending: vec![],
+            },
+            hints,
+        )
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Stream<S> {
+    /// Writes out the queued responses before anything else.
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.pending.append(&mut self.hints.0.lock().unwrap().buf);
+
+        while !self.pending.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending))?;
+
+            if n == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+            }
+
+            self.pending.drain(..n);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Stream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Stream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        ready!(self.poll_write_pending(cx))?;
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        ready!(self.poll_write_pending(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        ready!(self.poll_write_pending(cx))?;
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    async fn written_by(f: impl FnOnce(&EarlyHints)) -> String {
+        let (mut client, server) = duplex(1024);
+        let (mut stream, hints) = Stream::new(server);
+
+        f(&hints);
+
+        stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+        drop(stream);
+
+        let mut buf = String::new();
+
+        client.read_to_string(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_early_hints_precede_the_next_write() {
+        let buf = written_by(|hints| {
+            let request = hints.open();
+
+            request.send([&HeaderValue::from_static("</a.css>; rel=preload; as=style")]);
+            request
+                .sender()
+                .send(&[HeaderValue::from_static("</b.js>; rel=preload; as=script")]);
+        })
+        .await;
+
+        assert_eq!(
+            buf,
+            concat!(
+                "HTTP/1.1 103 Early Hints\r\n",
+                "link: </a.css>; rel=preload; as=style\r\n",
+                "\r\n",
+                "HTTP/1.1 103 Early Hints\r\n",
+                "link: </b.js>; rel=preload; as=script\r\n",
+                "\r\n",
+                "HTTP/1.1 200 OK\r\n\r\n"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_early_hints_are_not_sent_without_links() {
+        let buf = written_by(|hints| hints.open().send([])).await;
+
+        assert_eq!(buf, "HTTP/1.1 200 OK\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_early_hints_are_not_sent_after_the_response() {
+        let buf = written_by(|hints| {
+            let sender = hints.open().sender();
+            let next = hints.open();
+
+            sender.send(&[HeaderValue::from_static("</a.css>; rel=preload; as=style")]);
+            drop(next);
+            sender.send(&[HeaderValue::from_static("</a.css>; rel=preload; as=style")]);
+        })
+        .await;
+
+        assert_eq!(buf, "HTTP/1.1 200 OK\r\n\r\n");
+    }
+}
@@ -0,0 +1,401 @@
+//! AWS Lambda custom runtime mode.
+//!
+//! When enabled, invocations are polled from the Lambda runtime API and
+//! dispatched to the main worker. HTTP events (API Gateway and function URLs)
+//! are turned into regular requests and answered in the matching proxy
+//! response format. Any other event is posted as JSON to `/`, and the response
+//! body of the main worker is returned as the invocation result.
+//!
+//! See: https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Error};
+use base64::Engine;
+use deno_core::serde_json::{self, json, Value};
+use hyper_v014::{Body, Client, Request, Response, Uri};
+use log::{debug, error};
+use sb_core::SharedMetricSource;
+use sb_workers::context::WorkerRequestMsg;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+/// Holds the `host:port` of the runtime API inside a Lambda execution
+/// environment.
+static RUNTIME_API_ENV: &str = "AWS_LAMBDA_RUNTIME_API";
+static API_VERSION: &str = "2018-06-01";
+
+static REQUEST_ID_HEADER: &str = "lambda-runtime-aws-request-id";
+static TRACE_ID_HEADER: &str = "lambda-runtime-trace-id";
+static AMZN_TRACE_ID_HEADER: &str = "x-amzn-trace-id";
+
+static POLL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventKind {
+    /// API Gateway HTTP APIs (payload 2.0) and function URLs.
+    HttpV2,
+    /// API Gateway REST APIs (payload 1.0).
+    HttpV1,
+    Raw,
+}
+
+pub(crate) fn runtime_api() -> Option<String> {
+    std::env::var(RUNTIME_API_ENV)
+        .ok()
+        .filter(|it| !it.is_empty())
+}
+
+/// Polls invocations from the runtime API at `api` until `token` is cancelled.
+pub(crate) async fn run(
+    api: String,
+    main_worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    metric_src: SharedMetricSource,
+    token: CancellationToken,
+) {
+    let client = Client::new();
+    let base_uri = format!("http://{}/{}/runtime/invocation", api, API_VERSION);
+    let next_uri = match format!("{}/next", base_uri).parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(err) => {
+            error!("invalid {}: {}", RUNTIME_API_ENV, err);
+            return;
+        }
+    };
+
+    debug!("polling lambda invocations from {}", api);
+
+    loop {
+        let next = tokio::select! {
+            _ = token.cancelled() => break,
+            res = client.get(next_uri.clone()) => res,
+        };
+
+        let next = match next {
+            Ok(next) if next.status().is_success() => next,
+            Ok(next) => {
+                error!("lambda runtime api returned {}", next.status());
+                sleep(POLL_RETRY_DELAY).await;
+                continue;
+            }
+
+            Err(err) => {
+                error!("failed to poll the lambda runtime api: {}", err);
+                sleep(POLL_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        let Some(request_id) = header_str(&next, REQUEST_ID_HEADER).map(str::to_owned) else {
+            error!("lambda invocation is missing a request id");
+            continue;
+        };
+
+        let trace_id = header_str(&next, TRACE_ID_HEADER).map(str::to_owned);
+
+        metric_src.incl_received_requests();
+
+        let result = async {
+            let event = hyper_v014::body::to_bytes(next.into_body()).await?;
+            invoke(&main_worker_req_tx, &event, trace_id.as_deref()).await
+        }
+        .await;
+
+        let (uri, payload) = match result {
+            Ok(payload) => (format!("{}/{}/response", base_uri, request_id), payload),
+            Err(err) => {
+                error!("lambda invocation {} failed: {:#}", request_id, err);
+                (
+                    format!("{}/{}/error", base_uri, request_id),
+                    serde_json::to_vec(&json!({
+                        "errorMessage": format!("{:#}", err),
+                        "errorType": "Runtime.InvocationError",
+                    }))
+                    .unwrap(),
+                )
+            }
+        };
+
+        let post = Request::post(uri)
+            .header(http_v02::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(payload))
+            .unwrap();
+
+        if let Err(err) = client.request(post).await {
+            error!("failed to report lambda invocation {}: {}", request_id, err);
+        }
+
+        metric_src.incl_handled_requests();
+    }
+}
+
+async fn invoke(
+    main_worker_req_tx: &mpsc::UnboundedSender<WorkerRequestMsg>,
+    event: &[u8],
+    trace_id: Option<&str>,
+) -> Result<Vec<u8>, Error> {
+    let event = serde_json::from_slice::<Value>(event).context("invalid event")?;
+    let (mut req, kind) = event_to_request(&event)?;
+
+    if let Some(value) = trace_id.and_then(|it| it.parse().ok()) {
+        req.headers_mut().insert(AMZN_TRACE_ID_HEADER, value);
+    }
+
+    let (res_tx, res_rx) = oneshot::channel();
+
+    main_worker_req_tx
+        .send(WorkerRequestMsg {
+            req,
+            res_tx,
+            conn_token: None,
+        })
+        .map_err(|_| anyhow!("main worker is not available"))?;
+
+    let res = res_rx.await??;
+
+    response_to_payload(res, kind).await
+}
+
+fn event_to_request(event: &Value) -> Result<(Request<Body>, EventKind), Error> {
+    let kind = if event.pointer("/requestContext/http/method").is_some() {
+        EventKind::HttpV2
+    } else if event.get("httpMethod").is_some() {
+        EventKind::HttpV1
+    } else {
+        EventKind::Raw
+    };
+
+    let builder = match kind {
+        EventKind::HttpV2 => {
+            let method = str_at(event, "/requestContext/http/method").unwrap_or("GET");
+            let path = str_at(event, "/rawPath").unwrap_or("/");
+            let uri = match str_at(event, "/rawQueryString") {
+                Some(query) if !query.is_empty() => format!("{}?{}", path, query),
+                _ => path.to_owned(),
+            };
+
+            let mut builder = Request::builder().method(method).uri(uri);
+
+            for (name, value) in string_map(event.get("headers")) {
+                builder = builder.header(name, value);
+            }
+
+            if let Some(cookies) = event.get("cookies").and_then(Value::as_array) {
+                let cookies = cookies
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join("; ");
+
+                builder = builder.header(http_v02::header::COOKIE, cookies);
+            }
+
+            builder
+        }
+
+        EventKind::HttpV1 => {
+            let method = str_at(event, "/httpMethod").unwrap_or("GET");
+            let path = str_at(event, "/path").unwrap_or("/");
+            let query = multi_string_map(
+                event.get("multiValueQueryStringParameters"),
+                event.get("queryStringParameters"),
+            )
+            .into_iter()
+            .flat_map(|(key, values)| {
+                values.into_iter().map(move |value| {
+                    format!(
+                        "{}={}",
+                        urlencoding::encode(key),
+                        urlencoding::encode(value)
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+
+            let uri = if query.is_empty() {
+                path.to_owned()
+            } else {
+                format!("{}?{}", path, query)
+            };
+
+            let mut builder = Request::builder().method(method).uri(uri);
+
+            for (name, values) in
+                multi_string_map(event.get("multiValueHeaders"), event.get("headers"))
+            {
+                for value in values {
+                    builder = builder.header(name, value);
+                }
+            }
+
+            builder
+        }
+
+        EventKind::Raw => Request::post("/")
+            .header(http_v02::header::CONTENT_TYPE, "application/json")
+            .header(http_v02::header::HOST, "localhost"),
+    };
+
+    let body = match kind {
+        EventKind::Raw => serde_json::to_vec(event)?,
+        _ => match str_at(event, "/body") {
+            Some(body) if event.get("isBase64Encoded") == Some(&Value::Bool(true)) => {
+                base64::engine::general_purpose::STANDARD
+                    .decode(body)
+                    .context("invalid base64 body")?
+            }
+
+            Some(body) => body.as_bytes().to_vec(),
+            None => vec![],
+        },
+    };
+
+    let mut req = builder.body(Body::from(body))?;
+
+    if !req.headers().contains_key(http_v02::header::HOST) {
+        req.headers_mut()
+            .insert(http_v02::header::HOST, "localhost".parse().unwrap());
+    }
+
+    Ok((req, kind))
+}
+
+async fn response_to_payload(res: Response<Body>, kind: EventKind) -> Result<Vec<u8>, Error> {
+    let (parts, body) = res.into_parts();
+    let body = hyper_v014::body::to_bytes(body).await?;
+
+    if kind == EventKind::Raw {
+        if !parts.status.is_success() {
+            bail!(
+                "main worker responded with {}: {}",
+                parts.status,
+                String::from_utf8_lossy(&body)
+            );
+        }
+
+        return Ok(body.to_vec());
+    }
+
+    let mut headers = HashMap::<String, Vec<String>>::new();
+
+    for (name, value) in parts.headers.iter() {
+        headers
+            .entry(name.to_string())
+            .or_default()
+            .push(String::from_utf8_lossy(value.as_bytes()).into_owned());
+    }
+
+    let body = base64::engine::general_purpose::STANDARD.encode(body);
+    let payload = if kind == EventKind::HttpV2 {
+        let cookies = headers.remove("set-cookie").unwrap_or_default();
+        let headers = headers
+            .into_iter()
+            .map(|(name, values)| (name, values.join(", ")))
+            .collect::<HashMap<_, _>>();
+
+        json!({
+            "statusCode": parts.status.as_u16(),
+            "headers": headers,
+            "cookies": cookies,
+            "body": body,
+            "isBase64Encoded": true,
+        })
+    } else {
+        json!({
+            "statusCode": parts.status.as_u16(),
+            "multiValueHeaders": headers,
+            "body": body,
+            "isBase64Encoded": true,
+        })
+    };
+
+    Ok(serde_json::to_vec(&payload)?)
+}
+
+fn header_str<'a>(res: &'a Response<Body>, name: &str) -> Option<&'a str> {
+    res.headers().get(name).and_then(|it| it.to_str().ok())
+}
+
+fn str_at<'a>(value: &'a Value, pointer: &str) -> Option<&'a str> {
+    value.pointer(pointer).and_then(Value::as_str)
+}
+
+fn string_map(value: Option<&Value>) -> impl Iterator<Item = (&str, &str)> {
+    value
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| Some((key.as_str(), value.as_str()?)))
+}
+
+/// Reads a `multiValue*` map of an event, falling back to its single-valued
+/// counterpart.
+fn multi_string_map<'a>(
+    multi: Option<&'a Value>,
+    single: Option<&'a Value>,
+) -> Vec<(&'a str, Vec<&'a str>)> {
+    if let Some(multi) = multi.and_then(Value::as_object) {
+        return multi
+            .iter()
+            .map(|(key, values)| {
+                let values = values
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .collect();
+
+                (key.as_str(), values)
+            })
+            .collect();
+    }
+
+    string_map(single)
+        .map(|(key, value)| (key, vec![value]))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_to_request() {
+        let (req, kind) = event_to_request(&json!({
+            "rawPath": "/hello-world",
+            "rawQueryString": "a=1",
+            "cookies": ["a=b", "c=d"],
+            "headers": { "host": "example.com", "content-type": "text/plain" },
+            "requestContext": { "http": { "method": "POST" } },
+            "body": "aGVsbG8=",
+            "isBase64Encoded": true,
+        }))
+        .unwrap();
+
+        assert_eq!(kind, EventKind::HttpV2);
+        assert_eq!(req.method(), "POST");
+        assert_eq!(req.uri(), "/hello-world?a=1");
+        assert_eq!(req.headers()["host"], "example.com");
+        assert_eq!(req.headers()["cookie"], "a=b; c=d");
+
+        let (req, kind) = event_to_request(&json!({
+            "httpMethod": "GET",
+            "path": "/hello-world",
+            "multiValueQueryStringParameters": { "a": ["1", "2"] },
+        }))
+        .unwrap();
+
+        assert_eq!(kind, EventKind::HttpV1);
+        assert_eq!(req.uri(), "/hello-world?a=1&a=2");
+        assert_eq!(req.headers()["host"], "localhost");
+
+        let (req, kind) = event_to_request(&json!({ "detail": {} })).unwrap();
+
+        assert_eq!(kind, EventKind::Raw);
+        assert_eq!(req.method(), "POST");
+        assert_eq!(req.uri(), "/");
+    }
+}
@@ -1,5 +1,6 @@
 extern crate core;
 
+pub mod client_ip;
 pub mod commands;
 pub mod deno_runtime;
 pub mod macros;
@@ -8,11 +9,21 @@ pub mod server;
 pub mod snapshot;
 pub mod utils;
 
+mod conn_limit;
+mod early_hints;
 mod inspector_server;
+mod lambda;
+mod proxy_protocol;
+mod speculative_boot;
 mod timeout;
 
 pub use inspector_server::InspectorOption;
+pub use sb_core::cache::{deno_dir::DenoDir, module_cache};
+pub use sb_core::{broadcast_channel, cert, cpu_profile, dns, permissions, proxy};
+pub use sb_env::secrets;
 pub use sb_graph::DecoratorType;
+pub use sb_kv as kv;
+pub use sb_workers::retirement;
 
 #[cfg(test)]
 mod tracing;
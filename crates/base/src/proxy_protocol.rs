@@ -0,0 +1,208 @@
+//! Minimal PROXY protocol (v1 and v2) support.
+//!
+//! When the runtime is placed behind an L4 load balancer, the peer address of
+//! an accepted connection is the address of the load balancer. The PROXY
+//! protocol header sent in front of the actual stream carries the real client
+//! address.
+//!
+//! Spec: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Error};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+static HEADER_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+const V1_PREFIX: &[u8] = b"PROXY";
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Reads and consumes the PROXY protocol header from the start of `stream`.
+///
+/// Returns the source address announced by the proxy, or `None` if the header
+/// does not carry one (e.g. `UNKNOWN`, `LOCAL` or a non-IP address family).
+/// Connections without a valid header are rejected with an error, as the spec
+/// requires receivers not to guess.
+pub(crate) async fn read_header(stream: &mut TcpStream) -> Result<Option<SocketAddr>, Error> {
+    tokio::time::timeout(HEADER_READ_TIMEOUT, read_header_inner(stream))
+        .await
+        .context("timed out while reading the proxy protocol header")?
+}
+
+async fn read_header_inner(stream: &mut TcpStream) -> Result<Option<SocketAddr>, Error> {
+    let mut prefix = [0u8; 5];
+
+    stream.read_exact(&mut prefix).await?;
+
+    if prefix == V1_PREFIX {
+        // NOTE: The v1 header has no length field, so it is read byte by byte
+        // to avoid consuming any of the stream that follows it.
+        let mut line = prefix.to_vec();
+
+        loop {
+            if line.len() >= V1_MAX_LEN {
+                bail!("proxy protocol v1 header is too long");
+            }
+
+            line.push(stream.read_u8().await?);
+
+            if line.ends_with(b"\r\n") {
+                return parse_v1(&line);
+            }
+        }
+    }
+
+    if prefix == V2_SIGNATURE[..5] {
+        let mut header = [0u8; 16];
+
+        header[..5].copy_from_slice(&prefix);
+        stream.read_exact(&mut header[5..]).await?;
+
+        let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+        let mut payload = vec![0u8; len];
+
+        stream.read_exact(&mut payload).await?;
+
+        return parse_v2(&header, &payload);
+    }
+
+    bail!("missing proxy protocol header")
+}
+
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>, Error> {
+    let line = std::str::from_utf8(line)?
+        .strip_suffix("\r\n")
+        .ok_or_else(|| anyhow!("proxy protocol v1 header must end with CRLF"))?;
+
+    let mut parts = line.split(' ');
+
+    if parts.next() != Some("PROXY") {
+        bail!("invalid proxy protocol v1 header");
+    }
+
+    match parts.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => bail!("unsupported proxy protocol v1 protocol"),
+    }
+
+    let (Some(src_ip), Some(_dst_ip), Some(src_port), Some(_dst_port), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        bail!("malformed proxy protocol v1 header");
+    };
+
+    Ok(Some(SocketAddr::new(
+        IpAddr::from_str(src_ip).context("invalid source address")?,
+        u16::from_str(src_port).context("invalid source port")?,
+    )))
+}
+
+fn parse_v2(header: &[u8; 16], payload: &[u8]) -> Result<Option<SocketAddr>, Error> {
+    if &header[..12] != V2_SIGNATURE {
+        bail!("invalid proxy protocol v2 signature");
+    }
+
+    let version = header[12] >> 4;
+    let command = header[12] & 0x0F;
+
+    if version != 2 {
+        bail!("unsupported proxy protocol version: {}", version);
+    }
+
+    match command {
+        // LOCAL: health checks from the proxy itself, no address is conveyed.
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => bail!("unsupported proxy protocol v2 command: {}", command),
+    }
+
+    let port_at = |idx: usize| u16::from_be_bytes([payload[idx], payload[idx + 1]]);
+
+    match header[13] >> 4 {
+        // AF_INET
+        0x1 => {
+            if payload.len() < 12 {
+                bail!("truncated proxy protocol v2 ipv4 addresses");
+            }
+
+            let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port_at(8))))
+        }
+
+        // AF_INET6
+        0x2 => {
+            if payload.len() < 36 {
+                bail!("truncated proxy protocol v2 ipv6 addresses");
+            }
+
+            let mut octets = [0u8; 16];
+
+            octets.copy_from_slice(&payload[..16]);
+
+            Ok(Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(octets)),
+                port_at(32),
+            )))
+        }
+
+        // AF_UNSPEC, AF_UNIX
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_v1() {
+        assert_eq!(
+            parse_v1(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n").unwrap(),
+            Some("192.168.0.1:56324".parse().unwrap())
+        );
+
+        assert_eq!(
+            parse_v1(b"PROXY TCP6 ::1 ::1 56324 443\r\n").unwrap(),
+            Some("[::1]:56324".parse().unwrap())
+        );
+
+        assert_eq!(parse_v1(b"PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_v1(b"PROXY TCP4 192.168.0.1\r\n").is_err());
+        assert!(parse_v1(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443").is_err());
+    }
+
+    #[test]
+    fn test_parse_v2() {
+        let mut header = [0u8; 16];
+
+        header[..12].copy_from_slice(V2_SIGNATURE);
+        header[12] = 0x21;
+        header[13] = 0x11;
+        header[14..].copy_from_slice(&12u16.to_be_bytes());
+
+        let payload = [10, 0, 0, 1, 10, 0, 0, 2, 0xDC, 0x04, 0x01, 0xBB];
+
+        assert_eq!(
+            parse_v2(&header, &payload).unwrap(),
+            Some("10.0.0.1:56324".parse().unwrap())
+        );
+
+        // LOCAL command
+        header[12] = 0x20;
+        assert_eq!(parse_v2(&header, &payload).unwrap(), None);
+
+        // truncated addresses
+        header[12] = 0x21;
+        assert!(parse_v2(&header, &payload[..4]).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! cgroup v2 enforcement of the limits of process workers.
+//!
+//! Every worker process is moved into a cgroup of its own under the configured
+//! root, so the kernel enforces its memory limit (with an allowance for the
+//! runtime itself) and caps it at one CPU, the most a single isolate thread can
+//! use anyway.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+use log::debug;
+use once_cell::sync::OnceCell;
+use uuid::Uuid;
+
+/// Directory under which the cgroups of workers are created. Set once at
+/// startup; workers are not placed into cgroups if it's never set.
+pub static CGROUP_ROOT: OnceCell<PathBuf> = OnceCell::new();
+
+/// Memory the process needs on top of the heap of the isolate.
+static PROCESS_OVERHEAD_MB: u64 = 64;
+static CPU_PERIOD_US: u64 = 100_000;
+
+/// Prepares `root` to hold the cgroups of workers and makes it the root used
+/// by [`WorkerCgroup::create`].
+pub fn init_root(root: PathBuf) -> Result<(), Error> {
+    if !Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        bail!("cgroup v2 is not mounted at /sys/fs/cgroup");
+    }
+
+    std::fs::create_dir_all(&root)
+        .with_context(|| format!("failed to create cgroup {}", root.display()))?;
+
+    // NOTE: The root must not hold any process itself, or the kernel refuses
+    // to delegate controllers to its children.
+    std::fs::write(root.join("cgroup.subtree_control"), "+cpu +memory").with_context(|| {
+        format!(
+            "failed to enable the cpu and memory controllers in {}",
+            root.display()
+        )
+    })?;
+
+    let _ = CGROUP_ROOT.set(root);
+
+    Ok(())
+}
+
+/// The cgroup of a single worker process. It's removed on drop, which only
+/// succeeds once the process has exited.
+#[derive(Debug)]
+pub(crate) struct WorkerCgroup {
+    path: PathBuf,
+}
+
+impl WorkerCgroup {
+    /// Creates the cgroup of worker `key` and moves the process `pid` into it.
+    /// Returns `None` if no cgroup root is configured.
+    pub fn create(key: Uuid, pid: u32, memory_limit_mb: u64) -> Result<Option<Self>, Error> {
+        let Some(root) = CGROUP_ROOT.get() else {
+            return Ok(None);
+        };
+
+        let path = root.join(format!("worker-{}", key));
+
+        std::fs::create_dir(&path)
+            .with_context(|| format!("failed to create cgroup {}", path.display()))?;
+
+        let cgroup = Self { path };
+        let memory_max = (memory_limit_mb + PROCESS_OVERHEAD_MB) * 1024 * 1024;
+
+        cgroup.write("memory.max", memory_max)?;
+        cgroup.write("cpu.max", format!("{} {}", CPU_PERIOD_US, CPU_PERIOD_US))?;
+
+        // NOTE: The swap controller is not available on every host.
+        if cgroup.path.join("memory.swap.max").exists() {
+            cgroup.write("memory.swap.max", 0)?;
+        }
+
+        cgroup.write("cgroup.procs", pid)?;
+
+        Ok(Some(cgroup))
+    }
+
+    fn write(&self, file: &str, value: impl ToString) -> Result<(), Error> {
+        let path = self.path.join(file);
+
+        std::fs::write(&path, value.to_string())
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+impl Drop for WorkerCgroup {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir(&self.path) {
+            debug!("failed to remove cgroup {}: {}", self.path.display(), err);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerPolicy {
+    /// Failures (boot failures and uncaught exceptions) within `window` that
+    /// open the circuit of a service.
+    pub failure_threshold: usize,
+    pub window: Duration,
+    /// How long requests to a service are short-circuited once its circuit is
+    /// open.
+    pub cool_down: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            window: Duration::from_secs(60),
+            cool_down: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ServiceCircuit {
+    failures: VecDeque<Instant>,
+    open_until: Option<Instant>,
+    /// The cool-down has passed, and the next boot decides whether the circuit
+    /// closes or opens again.
+    half_open: bool,
+}
+
+/// Per-service circuit breaker, shared between the worker pool and the tasks
+/// booting workers.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    policy: CircuitBreakerPolicy,
+    services: Arc<Mutex<HashMap<String, ServiceCircuit>>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(policy: CircuitBreakerPolicy) -> Self {
+        Self {
+            policy,
+            services: Arc::default(),
+        }
+    }
+
+    /// Returns how long requests to `service_path` remain short-circuited, if
+    /// its circuit is open.
+    pub fn check(&self, service_path: &str) -> Option<Duration> {
+        self.check_at(service_path, Instant::now())
+    }
+
+    pub fn record_success(&self, service_path: &str) {
+        let mut services = self.services.lock().unwrap();
+
+        if let Some(circuit) = services.get_mut(service_path) {
+            if circuit.half_open {
+                services.remove(service_path);
+            }
+        }
+    }
+
+    pub fn record_failure(&self, service_path: &str) {
+        self.record_failure_at(service_path, Instant::now())
+    }
+
+    fn check_at(&self, service_path: &str, now: Instant) -> Option<Duration> {
+        let mut services = self.services.lock().unwrap();
+        let circuit = services.get_mut(service_path)?;
+        let open_until = circuit.open_until?;
+
+        if now < open_until {
+            return Some(open_until - now);
+        }
+
+        circuit.open_until = None;
+        circuit.half_open = true;
+
+        None
+    }
+
+    fn record_failure_at(&self, service_path: &str, now: Instant) {
+        let mut services = self.services.lock().unwrap();
+        let circuit = services.entry(service_path.to_string()).or_default();
+
+        while circuit
+            .failures
+            .front()
+            .is_some_and(|it| now.duration_since(*it) > self.policy.window)
+        {
+            circuit.failures.pop_front();
+        }
+
+        circuit.failures.push_back(now);
+
+        if circuit.half_open || circuit.failures.len() >= self.policy.failure_threshold {
+            warn!(
+                "opening circuit of {} for {:?} after {} failure(s)",
+                service_path,
+                self.policy.cool_down,
+                circuit.failures.len()
+            );
+
+            circuit.failures.clear();
+            circuit.open_until = Some(now + self.policy.cool_down);
+            circuit.half_open = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(CircuitBreakerPolicy {
+            failure_threshold: 2,
+            window: Duration::from_secs(10),
+            cool_down: Duration::from_secs(5),
+        });
+
+        let now = Instant::now();
+
+        breaker.record_failure_at("a", now);
+        assert_eq!(breaker.check_at("a", now), None);
+
+        // Failures outside of the window are forgotten.
+        breaker.record_failure_at("a", now + Duration::from_secs(11));
+        assert_eq!(breaker.check_at("a", now + Duration::from_secs(11)), None);
+
+        breaker.record_failure_at("a", now + Duration::from_secs(12));
+        assert_eq!(
+            breaker.check_at("a", now + Duration::from_secs(13)),
+            Some(Duration::from_secs(4))
+        );
+
+        assert_eq!(breaker.check_at("b", now), None);
+
+        // A single failure after the cool-down opens the circuit again.
+        assert_eq!(breaker.check_at("a", now + Duration::from_secs(17)), None);
+        breaker.record_failure_at("a", now + Duration::from_secs(17));
+        assert!(breaker
+            .check_at("a", now + Duration::from_secs(18))
+            .is_some());
+
+        // ...and a success closes it.
+        assert_eq!(breaker.check_at("a", now + Duration::from_secs(22)), None);
+        breaker.record_success("a");
+        breaker.record_failure_at("a", now + Duration::from_secs(23));
+        assert_eq!(breaker.check_at("a", now + Duration::from_secs(23)), None);
+    }
+}
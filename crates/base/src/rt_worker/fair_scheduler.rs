@@ -0,0 +1,270 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use log::{debug, error};
+use sb_workers::context::{UserWorkerMsgs, WorkerSlot};
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+
+type Waiter = oneshot::Sender<WorkerSlot>;
+
+/// Hands out the pool-wide worker slots to the services waiting for one.
+///
+/// Requests of the same service are served in order, while the services take
+/// turns, the one holding the fewest slots first: once the slots run out, a
+/// service with a long backlog gets one slot at a time like any other, rather
+/// than every slot until its backlog is cleared.
+///
+/// The slots a hot service took while the others were quiet would still starve
+/// them, as its warm workers keep their slots for as long as they live. So
+/// while a service waits for its turn, the service holding the most slots is
+/// asked to give one back if it holds more than one above the waiting one.
+#[derive(Clone)]
+pub struct FairScheduler {
+    waiters_tx: mpsc::UnboundedSender<(String, Waiter)>,
+}
+
+impl FairScheduler {
+    pub fn new(slots: usize, worker_pool_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>) -> Self {
+        let (waiters_tx, waiters_rx) = mpsc::unbounded_channel();
+
+        drop(tokio::spawn(dispatch(
+            Arc::new(Semaphore::new(slots)),
+            waiters_rx,
+            worker_pool_msgs_tx,
+        )));
+
+        Self { waiters_tx }
+    }
+
+    /// Waits for a slot to be granted to `service_path`. The slot is released
+    /// when the returned slot is dropped.
+    pub async fn acquire(&self, service_path: &str) -> Option<WorkerSlot> {
+        let (tx, rx) = oneshot::channel();
+
+        self.waiters_tx.send((service_path.to_string(), tx)).ok()?;
+        rx.await.ok()
+    }
+}
+
+/// Waiters by service, along with the order the services get their next slot
+/// in.
+#[derive(Default)]
+struct Turns {
+    queues: HashMap<String, VecDeque<Waiter>>,
+    order: VecDeque<String>,
+    /// Number of slots each service holds.
+    held: HashMap<String, Arc<AtomicUsize>>,
+}
+
+impl Turns {
+    fn push(&mut self, (service_path, waiter): (String, Waiter)) {
+        let queue = self.queues.entry(service_path.clone()).or_default();
+
+        if queue.is_empty() {
+            self.order.push_back(service_path);
+        }
+
+        queue.push_back(waiter);
+    }
+
+    fn held_by(&self, service_path: &str) -> usize {
+        self.held
+            .get(service_path)
+            .map_or(0, |it| it.load(Ordering::Acquire))
+    }
+
+    /// Grants `permit` to the first waiter of the service whose turn it is,
+    /// which is the waiting service holding the fewest slots, or the one that
+    /// has waited the longest for its turn among those.
+    fn grant(&mut self, permit: OwnedSemaphorePermit) {
+        loop {
+            let Some(idx) =
+                (0..self.order.len()).min_by_key(|&idx| (self.held_by(&self.order[idx]), idx))
+            else {
+                return;
+            };
+
+            let service_path = self.order.remove(idx).unwrap();
+            let Some(queue) = self.queues.get_mut(&service_path) else {
+                continue;
+            };
+
+            // NOTE: Requests that timed out while waiting have dropped their
+            // receiver, in which case the slot goes to the next one.
+            while queue.front().is_some_and(|it| it.is_closed()) {
+                queue.pop_front();
+            }
+
+            let waiter = queue.pop_front();
+
+            if queue.is_empty() {
+                self.queues.remove(&service_path);
+            } else {
+                self.order.push_back(service_path.clone());
+            }
+
+            if let Some(waiter) = waiter {
+                let held = self.held.entry(service_path).or_default().clone();
+
+                // NOTE: If the waiter went away just now, the slot is dropped
+                // and returns to the pool.
+                let _ = waiter.send(WorkerSlot::new(permit, held));
+                return;
+            }
+        }
+    }
+
+    /// Returns the service holding the most slots, if it holds more than one
+    /// above the waiting service the next slot goes to.
+    fn greediest(&mut self) -> Option<String> {
+        let queues = &self.queues;
+
+        self.held.retain(|service_path, it| {
+            it.load(Ordering::Acquire) > 0 || queues.contains_key(service_path)
+        });
+
+        let next_held = self.order.iter().map(|it| self.held_by(it)).min()?;
+        let (service_path, held) = self
+            .held
+            .iter()
+            .map(|(service_path, it)| (service_path, it.load(Ordering::Acquire)))
+            .max_by_key(|(_, held)| *held)?;
+
+        (held > next_held + 1).then(|| service_path.clone())
+    }
+}
+
+async fn dispatch(
+    slots: Arc<Semaphore>,
+    mut waiters_rx: mpsc::UnboundedReceiver<(String, Waiter)>,
+    worker_pool_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+) {
+    let mut turns = Turns::default();
+
+    // NOTE: One slot is reclaimed at a time. The next one is only asked for
+    // once a slot has been granted since.
+    let mut reclaiming = false;
+
+    loop {
+        if turns.queues.is_empty() {
+            let Some(waiter) = waiters_rx.recv().await else {
+                return;
+            };
+
+            turns.push(waiter);
+            continue;
+        }
+
+        if !reclaiming && slots.available_permits() == 0 {
+            if let Some(service_path) = turns.greediest() {
+                debug!("reclaiming a worker slot from {}", service_path);
+                reclaiming = worker_pool_msgs_tx
+                    .send(UserWorkerMsgs::Reclaim(service_path))
+                    .is_ok();
+            }
+        }
+
+        tokio::select! {
+            biased;
+
+            maybe_waiter = waiters_rx.recv() => match maybe_waiter {
+                Some(waiter) => turns.push(waiter),
+                None => return,
+            },
+
+            permit = slots.clone().acquire_owned() => match permit {
+                Ok(permit) => {
+                    reclaiming = false;
+                    turns.grant(permit);
+                }
+
+                Err(err) => {
+                    error!("worker slots are no longer available: {}", err);
+                    return;
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fair_scheduler() {
+        let scheduler = FairScheduler::new(1, mpsc::unbounded_channel().0);
+        let first = scheduler.acquire("hot").await.unwrap();
+
+        let (order_tx, mut order_rx) = mpsc::unbounded_channel();
+        let mut waiters = vec![];
+
+        for service_path in ["hot", "hot", "hot", "cold"] {
+            let scheduler = scheduler.clone();
+            let order_tx = order_tx.clone();
+
+            waiters.push(tokio::spawn(async move {
+                let _permit = scheduler.acquire(service_path).await.unwrap();
+                let _ = order_tx.send(service_path);
+            }));
+
+            tokio::task::yield_now().await;
+        }
+
+        drop(first);
+
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+
+        drop(order_tx);
+
+        let mut order = vec![];
+        while let Some(service_path) = order_rx.recv().await {
+            order.push(service_path);
+        }
+
+        assert_eq!(order, ["hot", "cold", "hot", "hot"]);
+    }
+
+    #[tokio::test]
+    async fn test_fair_scheduler_reclaims_slots() {
+        let (pool_tx, mut pool_rx) = mpsc::unbounded_channel();
+        let scheduler = FairScheduler::new(2, pool_tx);
+        let first = scheduler.acquire("hot").await.unwrap();
+        let _second = scheduler.acquire("hot").await.unwrap();
+
+        let acquire = |service_path: &'static str| {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire(service_path).await })
+        };
+
+        let hot = acquire("hot");
+
+        tokio::task::yield_now().await;
+
+        let cold = acquire("cold");
+
+        // NOTE: The hot service holds every slot, so it's asked to give one
+        // back for the cold one rather than starving it.
+        let Some(UserWorkerMsgs::Reclaim(service_path)) = pool_rx.recv().await else {
+            panic!("no worker slot was reclaimed");
+        };
+
+        assert_eq!(service_path, "hot");
+
+        // NOTE: The slot goes to the cold service, even though the hot one has
+        // been waiting for longer.
+        drop(first);
+
+        let cold = cold.await.unwrap().unwrap();
+
+        tokio::task::yield_now().await;
+        assert!(!hot.is_finished());
+        assert!(pool_rx.try_recv().is_err());
+
+        drop(cold);
+        assert!(hot.await.unwrap().is_some());
+    }
+}
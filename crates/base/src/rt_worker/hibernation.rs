@@ -0,0 +1,285 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Error};
+use bytes::Bytes;
+use http_v02::{HeaderMap, Method, StatusCode, Uri, Version};
+use hyper_v014::upgrade::Upgraded;
+use hyper_v014::{Body, Request};
+use log::debug;
+use sb_core::conn_sync::HibernationState;
+use sb_workers::context::{UserWorkerMsgs, WorkerHibernation};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+
+const RELAY_BUF_SIZE: usize = 8 * 1024;
+
+/// Lets the upgraded connections of a worker outlive it once it hibernates.
+/// The pool puts it in the requests it sends to a worker that can hibernate.
+#[derive(Debug, Clone)]
+pub struct Hibernation {
+    pub worker: WorkerHibernation,
+    pub service_path: String,
+    pub pool_msg_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+}
+
+/// An upgraded connection a hibernated worker left behind, along with what
+/// the client sent since. It travels in the request that picks it back up,
+/// which the worker handling it answers as if the connection were new.
+pub struct ParkedConnection {
+    // NOTE: Extensions of a request must be `Sync`, which `Upgraded` isn't.
+    io: Mutex<Upgraded>,
+    read_buf: Bytes,
+    /// What the hibernated worker set with `EdgeRuntime.setHibernationState()`.
+    pub state: Option<String>,
+}
+
+impl ParkedConnection {
+    /// Returns the connection, and what the client sent over it since.
+    pub fn into_parts(self) -> (Upgraded, Bytes) {
+        (self.io.into_inner().unwrap(), self.read_buf)
+    }
+}
+
+/// An upgraded connection that can be parked once its worker hibernates.
+pub(super) struct Resumable {
+    pub hibernation: Hibernation,
+    pub state: HibernationState,
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+}
+
+impl Resumable {
+    /// Keeps the head of `req`, which is sent again to pick the connection back
+    /// up.
+    pub fn new(hibernation: Hibernation, state: HibernationState, req: &Request<Body>) -> Self {
+        Self {
+            hibernation,
+            state,
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+        }
+    }
+
+    /// Waits for the client to send something over `downstream`, then has a
+    /// worker of the service pick the connection back up.
+    pub async fn park(self, mut downstream: Upgraded) {
+        let mut buf = vec![0; RELAY_BUF_SIZE];
+        let n = match downstream.read(&mut buf).await {
+            Ok(0) | Err(_) => {
+                debug!("client went away while the worker was hibernating");
+                return;
+            }
+
+            Ok(n) => n,
+        };
+
+        buf.truncate(n);
+
+        let Self {
+            hibernation,
+            state,
+            method,
+            uri,
+            version,
+            headers,
+        } = self;
+
+        let mut req = Request::new(Body::empty());
+
+        *req.method_mut() = method;
+        *req.uri_mut() = uri;
+        *req.version_mut() = version;
+        *req.headers_mut() = headers;
+
+        req.extensions_mut().insert(ParkedConnection {
+            io: Mutex::new(downstream),
+            read_buf: Bytes::from(buf),
+            state: state.take(),
+        });
+
+        if let Err(err) = resume(&hibernation, req).await {
+            debug!(
+                "failed to wake up a worker of {}: {}",
+                hibernation.service_path, err
+            );
+        }
+    }
+}
+
+async fn resume(hibernation: &Hibernation, req: Request<Body>) -> Result<(), Error> {
+    let (res_tx, res_rx) = oneshot::channel();
+
+    hibernation
+        .pool_msg_tx
+        .send(UserWorkerMsgs::Resume(
+            hibernation.service_path.clone(),
+            req,
+            res_tx,
+        ))
+        .map_err(|_| anyhow!("pool is no longer running"))?;
+
+    // NOTE: The worker answers with the response it would have upgraded the
+    // connection with. The client has seen one already, so it's swallowed.
+    let (res, req_end_tx) = res_rx.await??;
+    let _ = req_end_tx.send(());
+
+    if res.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(anyhow!("worker refused the connection: {}", res.status()));
+    }
+
+    Ok(())
+}
+
+/// Counts itself in an atomic counter until it is dropped.
+struct Counted(Arc<AtomicUsize>);
+
+impl Counted {
+    fn new(count: &Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::AcqRel);
+        Self(count.clone())
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Relays bytes between `upstream` and `downstream` like
+/// [`tokio::io::copy_bidirectional`], but stops once `hibernation` is
+/// cancelled, unless either side is already done. Returns whether it stopped
+/// for that reason, in which case `downstream` is left open.
+///
+/// The worker is only hibernated while at least one such relay is running.
+pub(super) async fn relay_until_hibernated<U, D>(
+    upstream: &mut U,
+    downstream: &mut D,
+    hibernation: &WorkerHibernation,
+) -> io::Result<bool>
+where
+    U: AsyncRead + AsyncWrite + Unpin,
+    D: AsyncRead + AsyncWrite + Unpin,
+{
+    let _resumable = Counted::new(&hibernation.resumable);
+    let mut up_buf = vec![0; RELAY_BUF_SIZE];
+    let mut down_buf = vec![0; RELAY_BUF_SIZE];
+    let mut is_up_done = false;
+    let mut is_down_done = false;
+
+    // NOTE: Only reads are raced, as they're cancel safe. Whatever was read is
+    // written out before the relay can stop.
+    while !is_up_done || !is_down_done {
+        tokio::select! {
+            biased;
+
+            _ = hibernation.token.cancelled(), if !is_up_done && !is_down_done => {
+                return Ok(true);
+            }
+
+            n = upstream.read(&mut up_buf), if !is_up_done => match n? {
+                0 => {
+                    downstream.shutdown().await?;
+                    is_up_done = true;
+                }
+
+                n => {
+                    downstream.write_all(&up_buf[..n]).await?;
+                    downstream.flush().await?;
+                }
+            },
+
+            n = downstream.read(&mut down_buf), if !is_down_done => match n? {
+                0 => {
+                    upstream.shutdown().await?;
+                    is_down_done = true;
+                }
+
+                n => {
+                    upstream.write_all(&down_buf[..n]).await?;
+                    upstream.flush().await?;
+                }
+            },
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::duplex;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_relay_stops_once_hibernated() {
+        let (mut worker, mut upstream) = duplex(1024);
+        let (mut client, mut downstream) = duplex(1024);
+        let hibernation = WorkerHibernation::default();
+
+        let relay = tokio::spawn({
+            let hibernation = hibernation.clone();
+            async move {
+                let hibernated =
+                    relay_until_hibernated(&mut upstream, &mut downstream, &hibernation)
+                        .await
+                        .unwrap();
+
+                (hibernated, downstream)
+            }
+        });
+
+        let mut buf = [0; 4];
+
+        client.write_all(b"ping").await.unwrap();
+        worker.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        worker.write_all(b"pong").await.unwrap();
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+        assert!(hibernation.has_resumable());
+
+        hibernation.token.cancel();
+
+        let (hibernated, mut downstream) = relay.await.unwrap();
+
+        assert!(hibernated);
+        assert!(!hibernation.has_resumable());
+
+        // The connection to the client is still open once the worker is gone.
+        drop(worker);
+        client.write_all(b"wake").await.unwrap();
+        downstream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"wake");
+    }
+
+    #[tokio::test]
+    async fn test_relay_ends_once_both_sides_are_done() {
+        let (mut worker, mut upstream) = duplex(1024);
+        let (mut client, mut downstream) = duplex(1024);
+        let hibernation = WorkerHibernation::default();
+
+        worker.write_all(b"bye").await.unwrap();
+        worker.shutdown().await.unwrap();
+        client.shutdown().await.unwrap();
+
+        assert!(
+            !relay_until_hibernated(&mut upstream, &mut downstream, &hibernation)
+                .await
+                .unwrap()
+        );
+        assert!(!hibernation.has_resumable());
+
+        let mut buf = vec![];
+
+        client.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"bye");
+    }
+}
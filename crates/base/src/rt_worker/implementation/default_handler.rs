@@ -69,8 +69,12 @@ impl WorkerHandler for Worker {
                         }
                     }
 
+                    let background_tasks = created_rt.background_tasks();
+
                     Ok(WorkerEvents::EventLoopCompleted(EventLoopCompletedEvent {
                         cpu_time_used: cpu_usage_ms as usize,
+                        background_tasks: background_tasks.registered(),
+                        pending_background_tasks: background_tasks.pending(),
                     }))
                 }
             }
@@ -0,0 +1,229 @@
+//! Sampling of the memory pressure of the host, so that the pool can shrink by
+//! evicting idle workers before the OOM killer picks a victim.
+//!
+//! The memory usage and stall information (PSI) are read from the cgroup v2
+//! the runtime runs in if it has a memory limit, or else from the host. Both
+//! are only available on Linux.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use event_worker::events::MemoryPressure;
+use log::{error, warn};
+use sb_workers::context::UserWorkerMsgs;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone)]
+pub struct MemoryPressurePolicy {
+    /// Fraction of the available memory in use above which idle workers are
+    /// evicted.
+    pub max_used_ratio: f64,
+    /// Share of the last 10 seconds, in percent, some tasks stalled on memory
+    /// above which idle workers are evicted.
+    pub max_stall_pct: Option<f64>,
+    pub interval: Duration,
+}
+
+impl Default for MemoryPressurePolicy {
+    fn default() -> Self {
+        Self {
+            max_used_ratio: 0.9,
+            max_stall_pct: None,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl MemoryPressurePolicy {
+    pub fn is_exceeded(&self, pressure: &MemoryPressure) -> bool {
+        pressure.used_bytes as f64 >= pressure.total_bytes as f64 * self.max_used_ratio
+            || self
+                .max_stall_pct
+                .zip(pressure.stall_pct)
+                .is_some_and(|(max, it)| it >= max)
+    }
+
+    /// Returns how many bytes have to be freed to get below the threshold.
+    pub fn excess_bytes(&self, pressure: &MemoryPressure) -> u64 {
+        let threshold = (pressure.total_bytes as f64 * self.max_used_ratio) as u64;
+        pressure.used_bytes.saturating_sub(threshold)
+    }
+}
+
+/// Samples the memory pressure every `policy.interval` and reports it to the
+/// pool whenever it exceeds the policy.
+pub fn spawn_monitor(
+    policy: MemoryPressurePolicy,
+    worker_pool_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+) {
+    let Some(source) = PressureSource::detect() else {
+        warn!("memory pressure can't be sampled on this host");
+        return;
+    };
+
+    drop(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(policy.interval);
+
+        loop {
+            interval.tick().await;
+
+            let pressure = match source.sample() {
+                Ok(it) => it,
+                Err(err) => {
+                    error!("failed to sample memory pressure: {}", err);
+                    continue;
+                }
+            };
+
+            if policy.is_exceeded(&pressure)
+                && worker_pool_msgs_tx
+                    .send(UserWorkerMsgs::MemoryPressure(pressure))
+                    .is_err()
+            {
+                return;
+            }
+        }
+    }));
+}
+
+enum PressureSource {
+    Cgroup(PathBuf),
+    Host,
+}
+
+impl PressureSource {
+    fn detect() -> Option<Self> {
+        if let Some(cgroup) = std::fs::read_to_string("/proc/self/cgroup")
+            .ok()
+            .and_then(|it| parse_cgroup_path(&it).map(str::to_string))
+        {
+            let path = Path::new("/sys/fs/cgroup").join(cgroup.trim_start_matches('/'));
+            let limited =
+                std::fs::read_to_string(path.join("memory.max")).is_ok_and(|it| it.trim() != "max");
+
+            if limited {
+                return Some(Self::Cgroup(path));
+            }
+        }
+
+        Path::new("/proc/meminfo").exists().then_some(Self::Host)
+    }
+
+    fn sample(&self) -> std::io::Result<MemoryPressure> {
+        let (used_bytes, total_bytes, psi) = match self {
+            Self::Cgroup(path) => {
+                let read_bytes = |name: &str| -> std::io::Result<u64> {
+                    std::fs::read_to_string(path.join(name))?
+                        .trim()
+                        .parse()
+                        .map_err(|_| std::io::ErrorKind::InvalidData.into())
+                };
+
+                (
+                    read_bytes("memory.current")?,
+                    read_bytes("memory.max")?,
+                    std::fs::read_to_string(path.join("memory.pressure")),
+                )
+            }
+
+            Self::Host => {
+                let (available, total) = parse_meminfo(&std::fs::read_to_string("/proc/meminfo")?)
+                    .ok_or(std::io::ErrorKind::InvalidData)?;
+
+                (
+                    total.saturating_sub(available),
+                    total,
+                    std::fs::read_to_string("/proc/pressure/memory"),
+                )
+            }
+        };
+
+        Ok(MemoryPressure {
+            used_bytes,
+            total_bytes,
+            stall_pct: psi.ok().and_then(|it| parse_psi_some_avg10(&it)),
+        })
+    }
+}
+
+/// Returns the path of the cgroup v2 in `/proc/self/cgroup`.
+fn parse_cgroup_path(content: &str) -> Option<&str> {
+    content.lines().find_map(|it| it.strip_prefix("0::"))
+}
+
+/// Returns the available and total memory in `/proc/meminfo`, in bytes.
+fn parse_meminfo(content: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| {
+        content.lines().find_map(|line| {
+            line.strip_prefix(name)?
+                .trim_start_matches(':')
+                .trim()
+                .strip_suffix("kB")?
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .map(|it| it * 1024)
+        })
+    };
+
+    Some((field("MemAvailable")?, field("MemTotal")?))
+}
+
+fn parse_psi_some_avg10(content: &str) -> Option<f64> {
+    content
+        .lines()
+        .find_map(|it| it.strip_prefix("some "))?
+        .split_whitespace()
+        .find_map(|it| it.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_pressure() {
+        assert_eq!(
+            parse_meminfo(
+                "MemTotal:        2048 kB\nMemFree:    512 kB\nMemAvailable:   1024 kB\n"
+            ),
+            Some((1024 * 1024, 2048 * 1024))
+        );
+        assert_eq!(
+            parse_psi_some_avg10(concat!(
+                "some avg10=12.50 avg60=3.00 avg300=1.00 total=1234\n",
+                "full avg10=2.00 avg60=0.50 avg300=0.10 total=123\n"
+            )),
+            Some(12.5)
+        );
+        assert_eq!(
+            parse_cgroup_path("0::/system.slice/edge-runtime.service\n"),
+            Some("/system.slice/edge-runtime.service")
+        );
+
+        let policy = MemoryPressurePolicy {
+            max_stall_pct: Some(10.0),
+            ..Default::default()
+        };
+        let pressure = MemoryPressure {
+            used_bytes: 80,
+            total_bytes: 100,
+            stall_pct: Some(5.0),
+        };
+
+        assert!(!policy.is_exceeded(&pressure));
+        assert!(policy.is_exceeded(&MemoryPressure {
+            stall_pct: Some(12.5),
+            ..pressure
+        }));
+        assert_eq!(
+            policy.excess_bytes(&MemoryPressure {
+                used_bytes: 95,
+                ..pressure
+            }),
+            5
+        );
+    }
+}
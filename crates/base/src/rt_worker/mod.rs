@@ -1,5 +1,16 @@
+#[cfg(target_os = "linux")]
+pub mod cgroup;
+pub mod circuit_breaker;
+pub mod fair_scheduler;
+pub mod hibernation;
 pub mod implementation;
+pub mod memory_pressure;
+#[cfg(unix)]
+pub mod process_worker;
+pub mod request_capture;
+pub mod service_watcher;
 pub mod supervisor;
+pub mod tenant_quota;
 pub mod utils;
 pub mod worker;
 pub mod worker_ctx;
@@ -0,0 +1,1124 @@
+//! Process isolation of user workers.
+//!
+//! With process isolation, every user worker boots in a child process of its
+//! own (the hidden `user-worker` subcommand), so untrusted code is separated
+//! from the server and from other tenants by the OS rather than by V8 alone.
+//! The child runs the worker under its usual supervisor and serves it over
+//! HTTP/2 on a unix socket. The pool multiplexes every request to the worker
+//! over a single connection to that socket, so concurrent requests don't
+//! block each other.
+//!
+//! The spec of the worker is written to the stdin of the child, which keeps
+//! the pipe open for as long as the worker lives. The child exits once the
+//! pipe is closed, so workers never outlive the server.
+//!
+//! The `BroadcastChannel` bus of the worker is bridged over the same
+//! connection with the bus of its service in the server (see [`bridge_bus`]),
+//! so a message still reaches the other workers of the service, and the main
+//! worker if the service shares its channels with it.
+//!
+//! On Linux, the processes can also be placed into cgroups enforcing their
+//! limits (see [`super::cgroup`]).
+
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Error};
+use deno_broadcast_channel::{BroadcastChannel, InMemoryBroadcastChannel};
+use deno_config::JsxImportSourceConfig;
+use deno_core::serde_json;
+use deno_core::url::Url;
+use event_worker::events::UncaughtExceptionEvent;
+use futures_util::StreamExt;
+use http_utils::utils::{emit_status_code, get_upgrade_type};
+use http_v02::{HeaderValue, StatusCode, Version};
+use hyper_v014::client::conn::{http1, http2};
+use hyper_v014::server::conn::Http;
+use hyper_v014::service::service_fn;
+use hyper_v014::upgrade::OnUpgrade;
+use hyper_v014::{Body, Request, Response};
+use log::{debug, error};
+use sb_core::broadcast_channel::{bus_key, bus_of, BusLease};
+use sb_core::cert::{TlsCaConfig, TLS_CA_CONFIG};
+use sb_core::conn_sync::CronRequest;
+use sb_core::dns::{DnsConfig, DNS_CONFIG};
+use sb_graph::DecoratorType;
+use sb_kv::{KvBackendConfig, KV_BACKEND};
+use sb_workers::context::{
+    HeapProfile, Timing, TimingStatus, UserWorkerMsgs, UserWorkerRuntimeOpts,
+    WorkerContextInitOpts, WorkerExit, WorkerExitStatus, WorkerRequestMsg, WorkerRuntimeOpts,
+};
+use sb_workers::retirement::RetirementBudgets;
+use serde::{Deserialize, Serialize};
+use tokio::io::{copy_bidirectional, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::deno_runtime::{V8_FLAGS, V8_FLAGS_ENV};
+
+use super::worker_ctx::{connect_h2, create_worker, upgrade_to_h2_request, TerminationToken};
+use super::worker_pool::SupervisorPolicy;
+
+/// Printed by the child on its stdout once the worker has booted and the socket
+/// accepts connections.
+static READY_LINE: &str = "edge-runtime-worker-ready";
+
+/// Carries the [`CronRequest`] of a request from the parent to the child.
+static CRON_REQUEST_HEADER: &str = "x-edge-runtime-cron-request";
+
+/// Marks the requests of the parent bridging the `BroadcastChannel` bus of the
+/// child with its own. Like [`CRON_REQUEST_HEADER`], only trusted on this hop.
+static BUS_BRIDGE_HEADER: &str = "x-edge-runtime-bus-bridge";
+
+/// Limits of the worker, i.e. the parts of [`UserWorkerRuntimeOpts`] that can
+/// cross the process boundary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessWorkerLimits {
+    memory_limit_mb: u64,
+    low_memory_multiplier: u64,
+    heap_profile: Option<HeapProfile>,
+    worker_timeout_ms: u64,
+    wall_clock_grace_ms: u64,
+    boot_timeout_ms: u64,
+    max_module_graph_size_mb: u64,
+    max_remote_modules: u64,
+    cpu_time_soft_limit_ms: u64,
+    cpu_time_hard_limit_ms: u64,
+    hibernate_after_ms: u64,
+    idle_timeout_ms: u64,
+    max_requests: u64,
+    net_access_disabled: bool,
+    allow_net: Option<Vec<String>>,
+    allow_read: Option<Vec<String>>,
+    allow_write: Option<Vec<String>>,
+    allow_env: Option<Vec<String>>,
+    env_allowlist: Option<Vec<String>>,
+    allow_hrtime: bool,
+    allow_ffi: bool,
+    egress_allow: Vec<String>,
+    egress_deny: Option<Vec<String>>,
+    http_proxy: Option<String>,
+    fetch_keep_alive_ms: Option<u64>,
+    fetch_max_idle_per_host: Option<usize>,
+    fetch_connect_timeout_ms: Option<u64>,
+    crypto_seed: Option<u64>,
+    kv_max_size_mb: u64,
+    share_broadcast_channel: bool,
+    custom_module_root: Option<String>,
+    allow_remote_modules: bool,
+    retirement_budgets: RetirementBudgets,
+}
+
+impl From<&UserWorkerRuntimeOpts> for ProcessWorkerLimits {
+    fn from(opts: &UserWorkerRuntimeOpts) -> Self {
+        Self {
+            memory_limit_mb: opts.memory_limit_mb,
+            low_memory_multiplier: opts.low_memory_multiplier,
+            heap_profile: opts.heap_profile,
+            worker_timeout_ms: opts.worker_timeout_ms,
+            wall_clock_grace_ms: opts.wall_clock_grace_ms,
+            boot_timeout_ms: opts.boot_timeout_ms,
+            max_module_graph_size_mb: opts.max_module_graph_size_mb,
+            max_remote_modules: opts.max_remote_modules,
+            cpu_time_soft_limit_ms: opts.cpu_time_soft_limit_ms,
+            cpu_time_hard_limit_ms: opts.cpu_time_hard_limit_ms,
+            hibernate_after_ms: opts.hibernate_after_ms,
+            idle_timeout_ms: opts.idle_timeout_ms,
+            max_requests: opts.max_requests,
+            net_access_disabled: opts.net_access_disabled,
+            allow_net: opts.allow_net.clone(),
+            allow_read: opts.allow_read.clone(),
+            allow_write: opts.allow_write.clone(),
+            allow_env: opts.allow_env.clone(),
+            env_allowlist: opts.env_allowlist.clone(),
+            allow_hrtime: opts.allow_hrtime,
+            allow_ffi: opts.allow_ffi,
+            egress_allow: opts.egress_allow.clone(),
+            egress_deny: opts.egress_deny.clone(),
+            http_proxy: opts.http_proxy.clone(),
+            fetch_keep_alive_ms: opts.fetch_keep_alive_ms,
+            fetch_max_idle_per_host: opts.fetch_max_idle_per_host,
+            fetch_connect_timeout_ms: opts.fetch_connect_timeout_ms,
+            crypto_seed: opts.crypto_seed,
+            kv_max_size_mb: opts.kv_max_size_mb,
+            share_broadcast_channel: opts.share_broadcast_channel,
+            custom_module_root: opts.custom_module_root.clone(),
+            allow_remote_modules: opts.allow_remote_modules,
+            retirement_budgets: opts.retirement_budgets,
+        }
+    }
+}
+
+impl From<ProcessWorkerLimits> for UserWorkerRuntimeOpts {
+    fn from(limits: ProcessWorkerLimits) -> Self {
+        Self {
+            memory_limit_mb: limits.memory_limit_mb,
+            low_memory_multiplier: limits.low_memory_multiplier,
+            heap_profile: limits.heap_profile,
+            worker_timeout_ms: limits.worker_timeout_ms,
+            wall_clock_grace_ms: limits.wall_clock_grace_ms,
+            boot_timeout_ms: limits.boot_timeout_ms,
+            max_module_graph_size_mb: limits.max_module_graph_size_mb,
+            max_remote_modules: limits.max_remote_modules,
+            cpu_time_soft_limit_ms: limits.cpu_time_soft_limit_ms,
+            cpu_time_hard_limit_ms: limits.cpu_time_hard_limit_ms,
+            hibernate_after_ms: limits.hibernate_after_ms,
+            idle_timeout_ms: limits.idle_timeout_ms,
+            max_requests: limits.max_requests,
+            net_access_disabled: limits.net_access_disabled,
+            allow_net: limits.allow_net,
+            allow_read: limits.allow_read,
+            allow_write: limits.allow_write,
+            allow_env: limits.allow_env,
+            env_allowlist: limits.env_allowlist,
+            allow_hrtime: limits.allow_hrtime,
+            allow_ffi: limits.allow_ffi,
+            egress_allow: limits.egress_allow,
+            egress_deny: limits.egress_deny,
+            http_proxy: limits.http_proxy,
+            fetch_keep_alive_ms: limits.fetch_keep_alive_ms,
+            fetch_max_idle_per_host: limits.fetch_max_idle_per_host,
+            fetch_connect_timeout_ms: limits.fetch_connect_timeout_ms,
+            crypto_seed: limits.crypto_seed,
+            kv_max_size_mb: limits.kv_max_size_mb,
+            share_broadcast_channel: limits.share_broadcast_channel,
+            custom_module_root: limits.custom_module_root,
+            allow_remote_modules: limits.allow_remote_modules,
+            retirement_budgets: limits.retirement_budgets,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessWorkerSpec {
+    key: Uuid,
+    service_path: PathBuf,
+    no_module_cache: bool,
+    import_map_path: Option<String>,
+    env_vars: HashMap<String, String>,
+    maybe_entrypoint: Option<String>,
+    maybe_decorator: Option<DecoratorType>,
+    static_patterns: Vec<String>,
+    jsx_specifier: Option<String>,
+    jsx_module: Option<String>,
+    limits: ProcessWorkerLimits,
+    /// Settings of the operator, which the child can't read from its own
+    /// arguments.
+    dns_config: Option<DnsConfig>,
+    tls_ca_config: Option<TlsCaConfig>,
+    kv_backend: Option<KvBackendConfig>,
+}
+
+/// Boots the user worker described by `opts` in a child process. The worker
+/// reports its shutdown to the pool through the `pool_msg_tx` of its options,
+/// just like an in-process worker.
+pub(crate) async fn spawn_process_worker(
+    opts: WorkerContextInitOpts,
+    termination_token: Option<TerminationToken>,
+) -> Result<(mpsc::UnboundedSender<WorkerRequestMsg>, WorkerExit), Error> {
+    if opts.maybe_eszip.is_some() || opts.maybe_module_code.is_some() {
+        bail!("eszip and inline module code are not supported with process isolation");
+    }
+
+    let Some(conf) = opts.conf.as_user_worker() else {
+        bail!("not a user worker");
+    };
+
+    let key = conf.key.unwrap_or_else(Uuid::new_v4);
+    let pool_msg_tx = conf.pool_msg_tx.clone();
+    let cancel = conf.cancel.clone().unwrap_or_default();
+    let socket_path = std::env::temp_dir().join(format!("edge-runtime-worker-{}.sock", key));
+    let bus_key = service_bus_key(&opts.service_path, conf.share_broadcast_channel)?;
+    let (jsx_specifier, jsx_module) = opts
+        .maybe_jsx_import_source_config
+        .as_ref()
+        .map(|it| (it.default_specifier.clone(), Some(it.module.clone())))
+        .unwrap_or_default();
+
+    let spec = ProcessWorkerSpec {
+        key,
+        service_path: opts.service_path.clone(),
+        no_module_cache: opts.no_module_cache,
+        import_map_path: opts.import_map_path.clone(),
+        env_vars: opts.env_vars.clone(),
+        maybe_entrypoint: opts.maybe_entrypoint.clone(),
+        maybe_decorator: opts.maybe_decorator,
+        static_patterns: opts.static_patterns.clone(),
+        jsx_specifier,
+        jsx_module,
+        limits: conf.into(),
+        dns_config: DNS_CONFIG.get().cloned(),
+        tls_ca_config: TLS_CA_CONFIG.get().cloned(),
+        kv_backend: KV_BACKEND.get().cloned(),
+    };
+
+    let mut command = Command::new(std::env::current_exe()?);
+
+    // NOTE: The child applies these to the threads running the worker (see
+    // `base_rt::placement`).
+    if let Some(cpu_set) = conf.cpu_set.as_ref() {
+        if base_rt::placement::parse_cpu_set(cpu_set).is_none() {
+            bail!("invalid cpu set: {}", cpu_set);
+        }
+
+        command.env(base_rt::placement::CPU_SET_ENV, cpu_set);
+    }
+    if let Some(nice) = conf.nice {
+        command.env(base_rt::placement::NICE_ENV, nice.to_string());
+    }
+
+    // NOTE: The child sets its V8 flags before parsing its arguments, so they
+    // go through the environment.
+    let v8_flags = V8_FLAGS
+        .get()
+        .into_iter()
+        .flatten()
+        .chain(conf.v8_flags.iter())
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    if !v8_flags.is_empty() {
+        command.env(V8_FLAGS_ENV, v8_flags.join(" "));
+    }
+
+    let mut child = command
+        .arg("user-worker")
+        .arg("--socket")
+        .arg(&socket_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("failed to spawn the worker process")?;
+
+    // NOTE: The child waits for its spec before booting anything, so it's
+    // placed into its cgroup before any user code runs.
+    #[cfg(target_os = "linux")]
+    let cgroup = match child.id() {
+        Some(pid) => super::cgroup::WorkerCgroup::create(key, pid, conf.memory_limit_mb)?,
+        None => None,
+    };
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    stdin.write_all(&serde_json::to_vec(&spec)?).await?;
+    stdin.write_all(b"\n").await?;
+
+    // NOTE: Anything the worker prints while booting goes through the same
+    // pipe as the ready line. It's relayed to the stdout of this process, the
+    // same way as what the worker prints afterwards.
+    let mut line = String::new();
+    loop {
+        line.clear();
+
+        if stdout.read_line(&mut line).await? == 0 {
+            let status = child.wait().await?;
+            bail!("worker process exited while booting: {}", status);
+        }
+
+        if line.trim_end() == READY_LINE {
+            break;
+        }
+
+        tokio::io::stdout().write_all(line.as_bytes()).await?;
+    }
+
+    drop(tokio::spawn(async move {
+        let _ = tokio::io::copy(&mut stdout, &mut tokio::io::stdout()).await;
+    }));
+
+    let exit = WorkerExit::default();
+    let (msg_tx, mut msg_rx) = mpsc::unbounded_channel::<WorkerRequestMsg>();
+    let conn = Arc::new(WorkerConnection {
+        socket_path: socket_path.clone(),
+        sender: Mutex::default(),
+    });
+
+    let bus_bridge = CancellationToken::new();
+
+    drop(tokio::spawn(bridge_bus(
+        conn.clone(),
+        bus_key,
+        bus_bridge.clone(),
+    )));
+
+    drop(tokio::spawn({
+        let exit = exit.clone();
+
+        async move {
+            let termination_requested = async {
+                match termination_token.as_ref() {
+                    Some(token) => token.inbound.cancelled().await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::pin!(termination_requested);
+
+            let mut is_killing = false;
+            let status = loop {
+                tokio::select! {
+                    status = child.wait() => break status,
+                    msg = msg_rx.recv(), if !is_killing => match msg {
+                        Some(msg) => drop(tokio::spawn(forward_request(conn.clone(), msg))),
+                        None => {
+                            is_killing = true;
+                            let _ = child.start_kill();
+                        }
+                    },
+
+                    _ = &mut termination_requested, if !is_killing => {
+                        is_killing = true;
+                        let _ = child.start_kill();
+                    }
+                }
+            };
+
+            drop(stdin);
+            bus_bridge.cancel();
+
+            #[cfg(target_os = "linux")]
+            drop(cgroup);
+
+            let _ = std::fs::remove_file(&socket_path);
+
+            match status {
+                Ok(status) if status.success() || is_killing => {
+                    debug!("worker process exited: isolate: {:?}", key);
+                }
+
+                Ok(status) => {
+                    exit.set(WorkerExitStatus::WithUncaughtException(
+                        UncaughtExceptionEvent {
+                            exception: format!("worker process exited with {}", status),
+                            cpu_time_used: 0,
+                        },
+                    ))
+                    .await;
+                }
+
+                Err(err) => error!("failed to wait for the worker process: {}", err),
+            }
+
+            cancel.cancel();
+
+            if let Some(tx) = pool_msg_tx {
+                if tx.send(UserWorkerMsgs::Shutdown(key)).is_err() {
+                    error!("failed to send the shutdown signal to user worker pool");
+                }
+            }
+
+            if let Some(token) = termination_token {
+                token.outbound.cancel();
+            }
+        }
+    }));
+
+    Ok((msg_tx, exit))
+}
+
+/// The HTTP/2 connection to a worker process, established on first use and
+/// again whenever it breaks.
+struct WorkerConnection {
+    socket_path: PathBuf,
+    sender: Mutex<Option<http2::SendRequest<Body>>>,
+}
+
+impl WorkerConnection {
+    async fn sender(&self) -> Result<http2::SendRequest<Body>, Error> {
+        let mut sender = self.sender.lock().await;
+
+        if let Some(sender) = sender.as_ref().filter(|it| !it.is_closed()) {
+            return Ok(sender.clone());
+        }
+
+        let stream = UnixStream::connect(&self.socket_path).await?;
+        let request_sender = connect_h2(stream).await?;
+
+        *sender = Some(request_sender.clone());
+
+        Ok(request_sender)
+    }
+}
+
+async fn forward_request(conn: Arc<WorkerConnection>, msg: WorkerRequestMsg) {
+    let WorkerRequestMsg {
+        mut req,
+        res_tx,
+        conn_token,
+    } = msg;
+
+    // NOTE: The marker of a cron request crosses the process boundary as a
+    // header, which is only trusted on this hop.
+    req.headers_mut().remove(CRON_REQUEST_HEADER);
+    req.headers_mut().remove(BUS_BRIDGE_HEADER);
+
+    if get_upgrade_type(req.headers()).is_some() {
+        forward_upgrade_request(&conn.socket_path, req, res_tx).await;
+        return;
+    }
+
+    upgrade_to_h2_request(&mut req);
+
+    if let Some(cron) = req.extensions_mut().remove::<CronRequest>() {
+        if let Ok(value) = serde_json::to_string(&cron)
+            .map_err(Error::from)
+            .and_then(|it| Ok(HeaderValue::try_from(it)?))
+        {
+            req.headers_mut().insert(CRON_REQUEST_HEADER, value);
+        }
+    }
+
+    let mut request_sender = match conn.sender().await {
+        Ok(sender) => sender,
+        Err(err) => {
+            error!("failed to connect to the worker process: {}", err);
+            let _ = res_tx.send(Ok(emit_status_code(StatusCode::BAD_GATEWAY, None, true)));
+            return;
+        }
+    };
+
+    let conn_cancelled = async {
+        match conn_token {
+            Some(token) => token.cancelled_owned().await,
+            None => std::future::pending().await,
+        }
+    };
+
+    // NOTE: Dropping the pending response resets the HTTP/2 stream, which
+    // aborts the request in the worker process.
+    tokio::select! {
+        res = request_sender.send_request(req) => {
+            let _ = res_tx.send(res);
+        }
+
+        _ = conn_cancelled => {}
+    }
+}
+
+/// Sends an upgrade request (e.g. the handshake of a WebSocket) to the worker
+/// process over an HTTP/1.1 connection of its own, since the HTTP/2 one can't
+/// carry it. Once the worker accepts the upgrade, the upgraded connection of the
+/// client is spliced with the one to the worker.
+async fn forward_upgrade_request(
+    socket_path: &Path,
+    mut req: Request<Body>,
+    res_tx: oneshot::Sender<Result<Response<Body>, hyper_v014::Error>>,
+) {
+    let downstream = req.extensions_mut().remove::<OnUpgrade>();
+    let connected = async {
+        let stream = UnixStream::connect(socket_path).await?;
+        let (request_sender, connection) = http1::Builder::new().handshake(stream).await?;
+
+        drop(tokio::spawn(async move {
+            // NOTE: The connection hands itself over to the upgrade once the
+            // worker accepts it.
+            if let Err(err) = connection.await {
+                debug!("upgrade connection to the worker process closed: {}", err);
+            }
+        }));
+
+        Ok::<_, Error>(request_sender)
+    };
+
+    let mut request_sender = match connected.await {
+        Ok(sender) => sender,
+        Err(err) => {
+            error!("failed to connect to the worker process: {}", err);
+            let _ = res_tx.send(Ok(emit_status_code(StatusCode::BAD_GATEWAY, None, true)));
+            return;
+        }
+    };
+
+    let mut res = match request_sender.send_request(req).await {
+        Ok(res) => res,
+        Err(err) => {
+            let _ = res_tx.send(Err(err));
+            return;
+        }
+    };
+
+    if let Some(downstream) = downstream.filter(|_| res.status() == StatusCode::SWITCHING_PROTOCOLS)
+    {
+        let upstream = hyper_v014::upgrade::on(&mut res);
+
+        drop(tokio::spawn(async move {
+            let (Ok(mut downstream), Ok(mut upstream)) = tokio::join!(downstream, upstream) else {
+                debug!("failed to upgrade a connection to the worker process");
+                return;
+            };
+
+            let _ = copy_bidirectional(&mut downstream, &mut upstream).await;
+        }));
+    }
+
+    let _ = res_tx.send(Ok(res));
+}
+
+/// Returns the key of the bus the worker of the service at `service_path`
+/// joins, the same way the runtime does.
+fn service_bus_key(service_path: &Path, share_with_main: bool) -> Result<String, Error> {
+    let service_path =
+        if service_path.extension().is_some_and(|it| it == "eszip") && service_path.is_file() {
+            service_path.with_extension("")
+        } else {
+            service_path.to_path_buf()
+        };
+
+    Ok(bus_key(
+        &std::env::current_dir()?.join(service_path),
+        share_with_main,
+    ))
+}
+
+/// A message of a `BroadcastChannel` crossing the process boundary, sent as a
+/// line of JSON.
+#[derive(Debug, Serialize, Deserialize)]
+struct BusMessage {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl BusMessage {
+    fn to_line(&self) -> Result<Vec<u8>, Error> {
+        let mut line = serde_json::to_vec(self)?;
+
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+fn bus_bridge_request(method: &str, body: Body) -> Result<Request<Body>, Error> {
+    Ok(Request::builder()
+        .method(method)
+        .uri("http://localhost/")
+        .version(Version::HTTP_2)
+        .header(BUS_BRIDGE_HEADER, "1")
+        .body(body)?)
+}
+
+/// Bridges the bus of `key` in this process with the bus of the worker process
+/// behind `conn`: the messages the child publishes are streamed from it and
+/// published here, and the messages published here are posted to it one by
+/// one, in order. Runs until `cancel` is cancelled or the child goes away.
+///
+/// Neither side echoes a message back, since a bus doesn't deliver a message
+/// to the subscription it was sent through.
+async fn bridge_bus(conn: Arc<WorkerConnection>, key: String, cancel: CancellationToken) {
+    let (bus, _lease) = bus_of(&key);
+    let resource = match bus.subscribe() {
+        Ok(resource) => resource,
+        Err(err) => {
+            error!("failed to subscribe to the bus of the worker: {}", err);
+            return;
+        }
+    };
+
+    let inbound = async {
+        let mut body = conn
+            .sender()
+            .await?
+            .send_request(bus_bridge_request("GET", Body::empty())?)
+            .await?
+            .into_body();
+
+        let mut buf = Vec::new();
+
+        while let Some(chunk) = body.next().await {
+            buf.extend_from_slice(&chunk?);
+
+            while let Some(end) = buf.iter().position(|it| *it == b'\n') {
+                let line = buf.drain(..=end).collect::<Vec<_>>();
+                let msg = serde_json::from_slice::<BusMessage>(&line)?;
+
+                bus.send(&resource, msg.name, msg.data).await?;
+            }
+        }
+
+        Ok::<_, Error>(())
+    };
+
+    let outbound = async {
+        while let Some((name, data)) = bus.recv(&resource).await? {
+            let line = BusMessage { name, data }.to_line()?;
+            let res = conn
+                .sender()
+                .await?
+                .send_request(bus_bridge_request("POST", Body::from(line))?)
+                .await?;
+
+            if !res.status().is_success() {
+                debug!("worker process refused a bus message: {}", res.status());
+            }
+        }
+
+        Ok::<_, Error>(())
+    };
+
+    tokio::select! {
+        Err(err) = inbound => debug!("bus bridge closed: {}", err),
+        Err(err) = outbound => debug!("bus bridge closed: {}", err),
+        _ = cancel.cancelled() => {}
+    }
+
+    let _ = bus.unsubscribe(&resource);
+}
+
+/// The end of [`bridge_bus`] in the child process.
+struct BusBridge {
+    bus: InMemoryBroadcastChannel,
+    resource: <InMemoryBroadcastChannel as BroadcastChannel>::Resource,
+    _lease: BusLease,
+}
+
+impl BusBridge {
+    fn new(key: &str) -> Result<Self, Error> {
+        let (bus, lease) = bus_of(key);
+
+        Ok(Self {
+            resource: bus.subscribe()?,
+            bus,
+            _lease: lease,
+        })
+    }
+
+    /// Streams the messages of the worker to the parent on `GET`, and
+    /// publishes the message the parent posts on `POST`.
+    async fn serve(self: Arc<Self>, req: Request<Body>) -> Result<Response<Body>, Error> {
+        if req.method() == "GET" {
+            let messages = futures_util::stream::unfold(self, |this| async move {
+                let (name, data) = this.bus.recv(&this.resource).await.ok()??;
+                let line = BusMessage { name, data }
+                    .to_line()
+                    .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err));
+
+                Some((line, this))
+            });
+
+            return Ok(Response::new(Body::wrap_stream(messages)));
+        }
+
+        let body = hyper_v014::body::to_bytes(req.into_body()).await?;
+        let msg = serde_json::from_slice::<BusMessage>(&body)?;
+
+        self.bus.send(&self.resource, msg.name, msg.data).await?;
+
+        Ok(emit_status_code(StatusCode::NO_CONTENT, None, false))
+    }
+}
+
+/// Entrypoint of the child process: boots the worker from the spec on stdin
+/// and serves it on `socket_path` until it shuts down or stdin is closed.
+pub async fn run_process_worker(socket_path: PathBuf) -> Result<(), Error> {
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    let mut line = String::new();
+
+    stdin.read_line(&mut line).await?;
+
+    let spec = serde_json::from_str::<ProcessWorkerSpec>(&line).context("invalid worker spec")?;
+    let key = spec.key;
+
+    if let Some(config) = spec.dns_config {
+        let _ = DNS_CONFIG.set(config);
+    }
+    if let Some(config) = spec.tls_ca_config {
+        let _ = TLS_CA_CONFIG.set(config);
+    }
+    if let Some(config) = spec.kv_backend {
+        let _ = KV_BACKEND.set(config);
+    }
+    let cancel = CancellationToken::new();
+    let (pool_msg_tx, mut pool_msg_rx) = mpsc::unbounded_channel::<UserWorkerMsgs>();
+    let (_req_start_tx, req_start_rx) = mpsc::unbounded_channel();
+    let (req_end_tx, req_end_rx) = mpsc::unbounded_channel::<()>();
+    let status = TimingStatus {
+        demand: Arc::default(),
+        is_retired: Arc::default(),
+    };
+
+    let max_requests = Some(spec.limits.max_requests as usize).filter(|it| *it > 0);
+    let bus_bridge = Arc::new(BusBridge::new(&service_bus_key(
+        &spec.service_path,
+        spec.limits.share_broadcast_channel,
+    )?)?);
+    let mut conf = UserWorkerRuntimeOpts::from(spec.limits);
+
+    conf.key = Some(key);
+    conf.service_path = Some(spec.service_path.to_string_lossy().into_owned());
+    conf.pool_msg_tx = Some(pool_msg_tx);
+    conf.cancel = Some(cancel);
+
+    let ctx = create_worker(
+        (
+            WorkerContextInitOpts {
+                service_path: spec.service_path,
+                no_module_cache: spec.no_module_cache,
+                import_map_path: spec.import_map_path,
+                env_vars: spec.env_vars,
+                events_rx: None,
+                timing: Some(Timing {
+                    status: status.clone(),
+                    req: (req_start_rx, req_end_rx),
+                }),
+                conf: WorkerRuntimeOpts::UserWorker(conf),
+                maybe_eszip: None,
+                maybe_module_code: None,
+                maybe_entrypoint: spec.maybe_entrypoint,
+                maybe_decorator: spec.maybe_decorator,
+                static_patterns: spec.static_patterns,
+                maybe_jsx_import_source_config: spec.jsx_module.map(|module| {
+                    JsxImportSourceConfig {
+                        default_specifier: spec.jsx_specifier,
+                        default_types_specifier: None,
+                        module,
+                        base_url: Url::from_file_path(std::env::current_dir().unwrap()).unwrap(),
+                    }
+                }),
+            },
+            SupervisorPolicy::PerWorker,
+        ),
+        None,
+        None,
+    )
+    .await?;
+
+    let _ = std::fs::remove_file(&socket_path);
+    let listener = UnixListener::bind(&socket_path)?;
+
+    std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600))?;
+
+    let parent_gone = CancellationToken::new();
+
+    drop(tokio::spawn({
+        let parent_gone = parent_gone.clone();
+        async move {
+            let _ = stdin.read_to_end(&mut vec![]).await;
+            parent_gone.cancel();
+        }
+    }));
+
+    println!("{}", READY_LINE);
+
+    loop {
+        tokio::select! {
+            msg = pool_msg_rx.recv() => match msg {
+                Some(UserWorkerMsgs::Shutdown(_)) | None => break,
+                Some(_) => {}
+            },
+
+            _ = parent_gone.cancelled() => break,
+
+            conn = listener.accept() => {
+                let (stream, _) = match conn {
+                    Ok(conn) => conn,
+                    Err(err) => {
+                        error!("failed to accept a connection: {}", err);
+                        continue;
+                    }
+                };
+
+                let msg_tx = ctx.msg_tx.clone();
+                let status = status.clone();
+                let req_end_tx = req_end_tx.clone();
+                let bus_bridge = bus_bridge.clone();
+                let service = service_fn(move |req: Request<Body>| {
+                    let msg_tx = msg_tx.clone();
+                    let req_end_tx = req_end_tx.clone();
+                    let bus_bridge = bus_bridge.clone();
+                    let is_bus_bridge = req.headers().contains_key(BUS_BRIDGE_HEADER);
+
+                    if !is_bus_bridge {
+                        status.demand(max_requests);
+                    }
+
+                    async move {
+                        if is_bus_bridge {
+                            bus_bridge.serve(req).await
+                        } else {
+                            relay_request(msg_tx, req_end_tx, req).await
+                        }
+                    }
+                });
+
+                drop(tokio::spawn(async move {
+                    // NOTE: The parent sends upgrade requests over HTTP/1.1
+                    // connections of their own, see `forward_upgrade_request`.
+                    if let Err(err) = Http::new().serve_connection(stream, service).with_upgrades().await {
+                        debug!("worker connection closed with an error: {}", err);
+                    }
+                }));
+            }
+        }
+    }
+
+    let _ = std::fs::remove_file(&socket_path);
+
+    if let Some(err) = ctx.exit.error().await {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Hands `req` to the worker, signalling the end of the request to the
+/// supervisor once the response body has been sent or dropped.
+async fn relay_request(
+    msg_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    req_end_tx: mpsc::UnboundedSender<()>,
+    mut req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+    if let Some(cron) = req
+        .headers_mut()
+        .remove(CRON_REQUEST_HEADER)
+        .and_then(|it| serde_json::from_slice::<CronRequest>(it.as_bytes()).ok())
+    {
+        req.extensions_mut().insert(cron);
+    }
+
+    let guard = scopeguard::guard(req_end_tx, |it| {
+        let _ = it.send(());
+    });
+
+    // NOTE: Hyper drops this future if the stream of the request is reset,
+    // which then aborts the request in the worker.
+    let cancel = CancellationToken::new();
+    let cancel_guard = cancel.clone().drop_guard();
+    let (res_tx, res_rx) = oneshot::channel();
+
+    msg_tx
+        .send(WorkerRequestMsg {
+            req,
+            res_tx,
+            conn_token: Some(cancel),
+        })
+        .map_err(|_| anyhow!("worker is not available"))?;
+
+    let (parts, body) = res_rx.await??.into_parts();
+
+    Ok(Response::from_parts(
+        parts,
+        Body::wrap_stream(body.map(move |it| {
+            let _guard = (&guard, &cancel_guard);
+            it
+        })),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use http_v02::header;
+
+    use super::*;
+    use crate::rt_worker::worker_ctx::downgrade_h2_request;
+
+    #[test]
+    fn test_limits_round_trip() {
+        let opts = UserWorkerRuntimeOpts {
+            memory_limit_mb: 64,
+            worker_timeout_ms: 1000,
+            max_requests: 10,
+            allow_net: Some(vec!["example.com".to_string()]),
+            ..Default::default()
+        };
+
+        let limits = serde_json::to_string(&ProcessWorkerLimits::from(&opts)).unwrap();
+        let opts = UserWorkerRuntimeOpts::from(
+            serde_json::from_str::<ProcessWorkerLimits>(&limits).unwrap(),
+        );
+
+        assert_eq!(opts.memory_limit_mb, 64);
+        assert_eq!(opts.worker_timeout_ms, 1000);
+        assert_eq!(opts.max_requests, 10);
+        assert_eq!(opts.allow_net, Some(vec!["example.com".to_string()]));
+        assert!(opts.pool_msg_tx.is_none());
+    }
+
+    #[test]
+    fn test_operator_settings_round_trip() {
+        let dns_config = DnsConfig::parse(["db.internal=10.0.0.5"], None).unwrap();
+        let kv_backend = KvBackendConfig::remote("https://kv.internal", Some("t".into())).unwrap();
+        let tls_ca_config = TlsCaConfig {
+            stores: Some(vec!["mozilla".to_string()]),
+            ca_files: vec![PathBuf::from("/etc/ssl/internal.pem")],
+        };
+
+        let (dns_config, tls_ca_config, kv_backend) =
+            serde_json::from_str::<(DnsConfig, TlsCaConfig, KvBackendConfig)>(
+                &serde_json::to_string(&(dns_config, tls_ca_config, kv_backend)).unwrap(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            dns_config.lookup_override("db.internal"),
+            Some("10.0.0.5".parse().unwrap())
+        );
+        assert_eq!(tls_ca_config.stores, Some(vec!["mozilla".to_string()]));
+        assert!(matches!(
+            kv_backend,
+            KvBackendConfig::Remote { url, access_token: Some(_) } if url.as_str() == "https://kv.internal/"
+        ));
+    }
+
+    #[test]
+    fn test_worker_termination_token() {
+        let pool = TerminationToken::new();
+        let worker = pool.detached_child_token();
+
+        worker.outbound.cancel();
+        assert!(!pool.outbound.is_cancelled());
+
+        pool.cancel();
+        assert!(worker.inbound.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_bus_bridge() {
+        let key = "/srv/functions/bridged";
+        let bridge = Arc::new(BusBridge::new(key).unwrap());
+        let (bus, _lease) = bus_of(key);
+        let worker = bus.subscribe().unwrap();
+
+        let line = BusMessage {
+            name: "from-parent".to_string(),
+            data: vec![1],
+        }
+        .to_line()
+        .unwrap();
+
+        let res = bridge
+            .clone()
+            .serve(bus_bridge_request("POST", Body::from(line)).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            bus.recv(&worker).await.unwrap(),
+            Some(("from-parent".to_string(), vec![1]))
+        );
+
+        let mut body = bridge
+            .serve(bus_bridge_request("GET", Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .into_body();
+
+        bus.send(&worker, "from-worker".to_string(), vec![2])
+            .await
+            .unwrap();
+
+        let chunk = body.next().await.unwrap().unwrap();
+        let msg = serde_json::from_slice::<BusMessage>(&chunk).unwrap();
+
+        assert_eq!((msg.name.as_str(), msg.data), ("from-worker", vec![2]));
+    }
+
+    #[tokio::test]
+    async fn test_forward_upgrade_request() {
+        let socket_path = std::env::temp_dir().join(format!("{}.sock", Uuid::new_v4()));
+        let worker = UnixListener::bind(&socket_path).unwrap();
+
+        // NOTE: Stands in for the worker process, echoing what it reads from
+        // the upgraded connection.
+        drop(tokio::spawn(async move {
+            let (stream, _) = worker.accept().await.unwrap();
+            let service = service_fn(|mut req: Request<Body>| async move {
+                let upgrade = hyper_v014::upgrade::on(&mut req);
+
+                drop(tokio::spawn(async move {
+                    let mut io = upgrade.await.unwrap();
+                    let mut buf = [0; 4];
+
+                    io.read_exact(&mut buf).await.unwrap();
+                    io.write_all(&buf).await.unwrap();
+                }));
+
+                Response::builder()
+                    .status(StatusCode::SWITCHING_PROTOCOLS)
+                    .header(header::CONNECTION, "upgrade")
+                    .header(header::UPGRADE, "echo")
+                    .body(Body::empty())
+            });
+
+            Http::new()
+                .serve_connection(stream, service)
+                .with_upgrades()
+                .await
+                .unwrap();
+        }));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        drop(tokio::spawn({
+            let socket_path = socket_path.clone();
+
+            async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let service = service_fn(move |req: Request<Body>| {
+                    let socket_path = socket_path.clone();
+
+                    async move {
+                        let (res_tx, res_rx) = oneshot::channel();
+
+                        forward_upgrade_request(&socket_path, req, res_tx).await;
+                        res_rx.await.unwrap()
+                    }
+                });
+
+                Http::new()
+                    .serve_connection(stream, service)
+                    .with_upgrades()
+                    .await
+                    .unwrap();
+            }
+        }));
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: upgrade\r\nUpgrade: echo\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut buf = vec![0; 1024];
+        let len = client.read(&mut buf).await.unwrap();
+
+        assert!(buf[..len].starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+
+        client.write_all(b"meow").await.unwrap();
+
+        let mut buf = [0; 4];
+
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"meow");
+
+        let _ = std::fs::remove_file(&socket_path);
+    }
+
+    #[test]
+    fn test_upgrade_to_h2_request() {
+        let mut req = Request::get("/hello?a=1")
+            .header(header::HOST, "example.com")
+            .body(Body::empty())
+            .unwrap();
+
+        upgrade_to_h2_request(&mut req);
+        assert_eq!(req.uri(), "http://example.com/hello?a=1");
+        assert_eq!(req.version(), Version::HTTP_2);
+
+        downgrade_h2_request(&mut req);
+        assert_eq!(req.uri(), "/hello?a=1");
+        assert_eq!(req.headers()[header::HOST], "example.com");
+    }
+}
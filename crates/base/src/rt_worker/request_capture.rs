@@ -0,0 +1,344 @@
+//! Sampled capture of full request/response exchanges for deep debugging.
+//!
+//! A captured exchange is sent to the event worker as a
+//! [`WorkerEvents::RequestCapture`] event once the response body has been
+//! consumed (or dropped).
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use event_worker::events::{
+    CapturedMessage, EventMetadata, RequestCaptureEvent, WorkerEventWithMetadata, WorkerEvents,
+};
+use futures_util::TryStreamExt;
+use http_v02::{HeaderMap, Request, Response};
+use hyper_v014::body::HttpBody;
+use hyper_v014::Body;
+use rand::Rng;
+use sb_core::util::token::token_eq;
+use tokio::sync::mpsc;
+
+/// Requests carrying this header with the configured debug token are always
+/// captured, regardless of the sample rate.
+pub static CAPTURE_DEBUG_HEADER: &str = "x-edge-runtime-capture";
+
+static REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone)]
+pub struct RequestCapturePolicy {
+    /// Fraction of requests to capture, between `0.0` and `1.0`.
+    pub sample_rate: f64,
+    pub debug_token: Option<String>,
+    pub max_body_bytes: usize,
+    /// Lowercase names of headers whose values are never recorded.
+    pub redact_headers: HashSet<String>,
+}
+
+impl Default for RequestCapturePolicy {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            debug_token: None,
+            max_body_bytes: 4096,
+            redact_headers: [
+                "authorization",
+                "proxy-authorization",
+                "cookie",
+                "set-cookie",
+                "apikey",
+                "x-api-key",
+            ]
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        }
+    }
+}
+
+impl RequestCapturePolicy {
+    fn should_capture(&self, headers: &HeaderMap) -> bool {
+        let forced = self
+            .debug_token
+            .as_deref()
+            .zip(headers.get(CAPTURE_DEBUG_HEADER))
+            .is_some_and(|(token, value)| token_eq(token.as_bytes(), value.as_bytes()));
+
+        forced || (self.sample_rate > 0.0 && rand::thread_rng().gen_bool(self.sample_rate.min(1.0)))
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if self.redact_headers.contains(name.as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+
+                (name.to_string(), value)
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct BodyBuf {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+impl BodyBuf {
+    fn push(&mut self, chunk: &[u8], limit: usize) {
+        let remaining = limit.saturating_sub(self.bytes.len());
+
+        if chunk.len() > remaining {
+            self.truncated = true;
+        }
+
+        self.bytes
+            .extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+    }
+
+    fn into_message(self, headers: Vec<(String, String)>) -> CapturedMessage {
+        CapturedMessage {
+            headers,
+            body: String::from_utf8_lossy(&self.bytes).into_owned(),
+            body_truncated: self.truncated,
+        }
+    }
+}
+
+fn tee_body(body: Body, limit: usize) -> (Body, Arc<Mutex<BodyBuf>>) {
+    let buf = Arc::<Mutex<BodyBuf>>::default();
+
+    if body.is_end_stream() {
+        return (body, buf);
+    }
+
+    let body = Body::wrap_stream(body.inspect_ok({
+        let buf = buf.clone();
+        move |chunk| buf.lock().unwrap().push(chunk, limit)
+    }));
+
+    (body, buf)
+}
+
+pub(crate) struct RequestCapture {
+    policy: RequestCapturePolicy,
+    method: String,
+    uri: String,
+    started_at: Instant,
+    req_headers: Vec<(String, String)>,
+    req_body: Arc<Mutex<BodyBuf>>,
+    events_tx: mpsc::UnboundedSender<WorkerEventWithMetadata>,
+    metadata: EventMetadata,
+}
+
+impl RequestCapture {
+    /// Decides whether `req` is captured, and if so, starts recording its body.
+    ///
+    /// The debug header is always removed so that it never reaches the worker.
+    pub(crate) fn begin(
+        policy: &RequestCapturePolicy,
+        mut req: Request<Body>,
+        events_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+        metadata: EventMetadata,
+    ) -> (Request<Body>, Option<Self>) {
+        let capture = policy.should_capture(req.headers());
+
+        req.headers_mut().remove(CAPTURE_DEBUG_HEADER);
+
+        let Some(events_tx) = events_tx.filter(|_| capture) else {
+            return (req, None);
+        };
+
+        let (parts, body) = req.into_parts();
+        let (body, req_body) = tee_body(body, policy.max_body_bytes);
+
+        let this = Self {
+            policy: policy.clone(),
+            method: parts.method.to_string(),
+            uri: parts.uri.to_string(),
+            started_at: Instant::now(),
+            req_headers: policy.headers(&parts.headers),
+            req_body,
+            events_tx,
+            metadata,
+        };
+
+        (Request::from_parts(parts, body), Some(this))
+    }
+
+    /// Starts recording the response. The event is emitted when the returned
+    /// response body is fully consumed or dropped.
+    pub(crate) fn finish(self, res: Response<Body>) -> Response<Body> {
+        let (parts, body) = res.into_parts();
+        let limit = self.policy.max_body_bytes;
+        let mut emitter = CaptureEmitter {
+            status: parts.status.as_u16(),
+            res_headers: self.policy.headers(&parts.headers),
+            res_body: BodyBuf::default(),
+            capture: Some(self),
+        };
+
+        let body = if body.is_end_stream() {
+            drop(emitter);
+            body
+        } else {
+            Body::wrap_stream(body.inspect_ok(move |chunk| emitter.res_body.push(chunk, limit)))
+        };
+
+        Response::from_parts(parts, body)
+    }
+}
+
+struct CaptureEmitter {
+    status: u16,
+    res_headers: Vec<(String, String)>,
+    res_body: BodyBuf,
+    capture: Option<RequestCapture>,
+}
+
+impl Drop for CaptureEmitter {
+    fn drop(&mut self) {
+        let Some(capture) = self.capture.take() else {
+            return;
+        };
+
+        let req_body = std::mem::take(&mut *capture.req_body.lock().unwrap());
+        let event = RequestCaptureEvent {
+            method: capture.method,
+            uri: capture.uri,
+            status: self.status,
+            duration_ms: capture.started_at.elapsed().as_millis() as u64,
+            request: req_body.into_message(capture.req_headers),
+            response: std::mem::take(&mut self.res_body)
+                .into_message(std::mem::take(&mut self.res_headers)),
+        };
+
+        let _ = capture.events_tx.send(WorkerEventWithMetadata {
+            event: WorkerEvents::RequestCapture(event),
+            metadata: capture.metadata,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use http_v02::HeaderValue;
+
+    use super::*;
+
+    fn policy() -> RequestCapturePolicy {
+        RequestCapturePolicy {
+            debug_token: Some("s3cr3t".to_string()),
+            max_body_bytes: 8,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_capture_debug_token() {
+        let mut headers = HeaderMap::new();
+
+        assert!(!policy().should_capture(&headers));
+
+        for (token, captured) in [("s3cr3t", true), ("s3cr3T", false), ("s3cr3", false)] {
+            headers.insert(CAPTURE_DEBUG_HEADER, HeaderValue::from_static(token));
+            assert_eq!(policy().should_capture(&headers), captured, "{}", token);
+        }
+
+        headers.insert(CAPTURE_DEBUG_HEADER, HeaderValue::from_static("s3cr3t"));
+        assert!(!RequestCapturePolicy::default().should_capture(&headers));
+
+        let sampled = RequestCapturePolicy {
+            sample_rate: 1.0,
+            ..Default::default()
+        };
+
+        assert!(sampled.should_capture(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_capture_body_truncation() {
+        let mut buf = BodyBuf::default();
+
+        buf.push(b"hello ", 8);
+        assert!(!buf.truncated);
+
+        buf.push(b"world", 8);
+        buf.push(b"!", 8);
+
+        let message = buf.into_message(vec![]);
+
+        assert_eq!(message.body, "hello wo");
+        assert!(message.body_truncated);
+    }
+
+    #[test]
+    fn test_capture_header_redaction() {
+        let mut headers = HeaderMap::new();
+
+        headers.insert("authorization", HeaderValue::from_static("Bearer s3cr3t"));
+        headers.insert("cookie", HeaderValue::from_static("session=s3cr3t"));
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+
+        let mut captured = policy().headers(&headers);
+
+        captured.sort();
+        assert_eq!(
+            captured,
+            [
+                ("authorization", REDACTED),
+                ("content-type", "text/plain"),
+                ("cookie", REDACTED),
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_capture_event() {
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+        let req = Request::post("/hello")
+            .header(CAPTURE_DEBUG_HEADER, "s3cr3t")
+            .header("authorization", "Bearer s3cr3t")
+            .body(Body::from("hello world"))
+            .unwrap();
+
+        let (req, capture) =
+            RequestCapture::begin(&policy(), req, Some(events_tx), EventMetadata::default());
+
+        assert!(!req.headers().contains_key(CAPTURE_DEBUG_HEADER));
+
+        let body = hyper_v014::body::to_bytes(req.into_body()).await.unwrap();
+
+        assert_eq!(body, "hello world");
+
+        let res = capture.unwrap().finish(Response::new(Body::from("meow")));
+        let body = hyper_v014::body::to_bytes(res.into_body()).await.unwrap();
+
+        assert_eq!(body, "meow");
+
+        let Some(WorkerEventWithMetadata {
+            event: WorkerEvents::RequestCapture(event),
+            ..
+        }) = events_rx.recv().await
+        else {
+            panic!("no request capture event");
+        };
+
+        assert_eq!(event.method, "POST");
+        assert_eq!(event.status, 200);
+        assert_eq!(event.request.body, "hello wo");
+        assert!(event.request.body_truncated);
+        assert_eq!(
+            event.request.headers,
+            vec![("authorization".to_string(), REDACTED.to_string())]
+        );
+        assert_eq!(event.response.body, "meow");
+        assert!(!event.response.body_truncated);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+use log::{debug, error};
+use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use sb_workers::context::UserWorkerMsgs;
+use sb_workers::introspection::SERVICE_CONFIG_FILE;
+use tokio::sync::mpsc;
+
+/// Watches the directories of services that have workers, and notifies the
+/// worker pool when any of their files change.
+pub struct ServiceWatcher {
+    watcher: RecommendedWatcher,
+    services: HashMap<String, PathBuf>,
+    /// Told the path of the services whose config may have changed, see
+    /// [`UserWorkerMsgs::WatchConfigs`].
+    config_txs: Vec<mpsc::UnboundedSender<String>>,
+}
+
+impl ServiceWatcher {
+    pub fn new(worker_pool_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>) -> Result<Self, Error> {
+        let watcher = RecommendedWatcher::new(
+            move |res: notify::Result<Event>| match res {
+                Ok(event) => {
+                    if !(event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove())
+                    {
+                        return;
+                    }
+
+                    for path in event.paths {
+                        let _ = worker_pool_msgs_tx.send(UserWorkerMsgs::ServiceChanged(path));
+                    }
+                }
+
+                Err(err) => error!("service watcher error: {}", err),
+            },
+            Config::default(),
+        )?;
+
+        Ok(Self {
+            watcher,
+            services: HashMap::new(),
+            config_txs: vec![],
+        })
+    }
+
+    pub fn watch(&mut self, service_path: &str) {
+        if self.services.contains_key(service_path) {
+            return;
+        }
+
+        let Ok(dir) = std::fs::canonicalize(service_path) else {
+            return;
+        };
+
+        if let Err(err) = self.watcher.watch(&dir, RecursiveMode::Recursive) {
+            error!("failed to watch {}: {}", dir.display(), err);
+            return;
+        }
+
+        debug!("watching {} for changes", dir.display());
+        self.services.insert(service_path.to_string(), dir);
+    }
+
+    /// Stops watching a service that no longer has workers. Its config is
+    /// reported as changed, since it's no longer watched for changes.
+    pub fn unwatch(&mut self, service_path: &str) {
+        let Some(dir) = self.services.remove(service_path) else {
+            return;
+        };
+
+        // NOTE: The watch is already gone if the directory was removed.
+        if let Err(err) = self.watcher.unwatch(&dir) {
+            debug!("failed to unwatch {}: {}", dir.display(), err);
+        }
+
+        debug!("no longer watching {} for changes", dir.display());
+        self.config_changed(service_path);
+    }
+
+    pub fn watch_configs(&mut self, tx: mpsc::UnboundedSender<String>) {
+        self.config_txs.push(tx);
+    }
+
+    /// Tells the config watchers that the config of a service may have
+    /// changed.
+    pub fn config_changed(&mut self, service_path: &str) {
+        self.config_txs
+            .retain(|tx| tx.send(service_path.to_string()).is_ok());
+    }
+
+    /// Returns the service paths whose directory contains `changed`.
+    pub fn affected_services<'a>(&'a self, changed: &'a Path) -> impl Iterator<Item = &'a str> {
+        self.services
+            .iter()
+            .filter(move |(_, dir)| changed.starts_with(dir))
+            .map(|(service_path, _)| service_path.as_str())
+    }
+
+    /// Returns the service path whose config is `changed`, if it's one.
+    pub fn configured_service(&self, changed: &Path) -> Option<&str> {
+        if changed.file_name()? != SERVICE_CONFIG_FILE {
+            return None;
+        }
+
+        self.services
+            .iter()
+            .find(|(_, dir)| changed.parent() == Some(dir.as_path()))
+            .map(|(service_path, _)| service_path.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+
+    async fn next_change(rx: &mut mpsc::UnboundedReceiver<UserWorkerMsgs>) -> Option<PathBuf> {
+        loop {
+            match timeout(Duration::from_secs(5), rx.recv()).await {
+                Ok(Some(UserWorkerMsgs::ServiceChanged(path))) => return Some(path),
+                Ok(Some(_)) => continue,
+                Ok(None) | Err(_) => return None,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_service_watcher_reports_changed_services() {
+        let dir = tempfile::tempdir().unwrap();
+        let service_path = dir.path().join("hello");
+        let other_path = dir.path().join("hello-world");
+
+        std::fs::create_dir(&service_path).unwrap();
+        std::fs::create_dir(&other_path).unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = ServiceWatcher::new(tx).unwrap();
+        let service_path = service_path.to_str().unwrap();
+
+        watcher.watch(service_path);
+        watcher.watch(other_path.to_str().unwrap());
+        std::fs::write(Path::new(service_path).join(SERVICE_CONFIG_FILE), "").unwrap();
+
+        let changed = next_change(&mut rx).await.unwrap();
+
+        assert_eq!(
+            watcher.affected_services(&changed).collect::<Vec<_>>(),
+            vec![service_path]
+        );
+        assert_eq!(watcher.configured_service(&changed), Some(service_path));
+
+        let changed = changed.with_file_name("index.ts");
+
+        assert_eq!(
+            watcher.affected_services(&changed).collect::<Vec<_>>(),
+            vec![service_path]
+        );
+        assert_eq!(watcher.configured_service(&changed), None);
+    }
+
+    #[tokio::test]
+    async fn test_service_watcher_forgets_unwatched_services() {
+        let dir = tempfile::tempdir().unwrap();
+        let service_path = dir.path().to_str().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (config_tx, mut config_rx) = mpsc::unbounded_channel();
+        let mut watcher = ServiceWatcher::new(tx).unwrap();
+
+        watcher.watch_configs(config_tx);
+        watcher.watch(service_path);
+        watcher.unwatch(service_path);
+
+        assert_eq!(config_rx.try_recv().unwrap(), service_path);
+
+        std::fs::write(dir.path().join("index.ts"), "").unwrap();
+
+        let canonical = std::fs::canonicalize(dir.path()).unwrap();
+
+        assert_eq!(
+            watcher
+                .affected_services(&canonical.join("index.ts"))
+                .count(),
+            0
+        );
+        assert!(timeout(Duration::from_millis(500), rx.recv())
+            .await
+            .is_err());
+    }
+}
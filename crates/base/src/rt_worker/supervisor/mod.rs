@@ -1,14 +1,26 @@
 pub mod strategy_per_request;
 pub mod strategy_per_worker;
 
-use std::sync::Arc;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use base_mem_check::MemCheckState;
 use cpu_timer::{CPUAlarmVal, CPUTimer};
 use deno_core::v8::IsolateHandle;
 use enum_as_inner::EnumAsInner;
+use event_worker::events::ShutdownReason;
 use futures_util::task::AtomicWaker;
-use log::error;
+use log::{debug, error, info};
+use once_cell::sync::OnceCell;
+use sb_core::background_tasks::BackgroundTasks;
+use sb_core::execution_context::ExecutionClock;
+use sb_core::util::sync::AtomicFlag;
 use sb_workers::context::{Timing, UserWorkerMsgs, UserWorkerRuntimeOpts};
+use sb_workers::retirement::{check_retirement, WorkerUsage};
 use tokio::sync::{
     mpsc::{self, UnboundedReceiver},
     oneshot,
@@ -18,6 +30,26 @@ use uuid::Uuid;
 
 use super::{worker_ctx::TerminationToken, worker_pool::SupervisorPolicy};
 
+/// Where heap snapshots of workers terminated for memory are written to.
+pub static HEAP_SNAPSHOT_CONFIG: OnceCell<HeapSnapshotConfig> = OnceCell::new();
+
+#[derive(Debug, Clone)]
+pub struct HeapSnapshotConfig {
+    pub dir: PathBuf,
+    /// Number of snapshots kept in the directory; the oldest ones are removed
+    /// to make room for a new one.
+    pub max_count: usize,
+    /// Size of all the snapshots kept in the directory. A snapshot that
+    /// doesn't fit even once the older ones are removed is dropped.
+    pub max_size_bytes: u64,
+}
+
+/// Snapshots are written one at a time, so that they don't exceed the limits
+/// of the directory together.
+static HEAP_SNAPSHOT_LOCK: Mutex<()> = Mutex::new(());
+
+static HEAP_SNAPSHOT_EXTENSION: &str = "heapsnapshot";
+
 #[repr(C)]
 pub struct IsolateInterruptData {
     pub should_terminate: bool,
@@ -55,6 +87,180 @@ pub extern "C" fn handle_interrupt(
     }
 }
 
+/// Asks the isolate to write a heap snapshot before it is terminated, if a
+/// directory for them is configured. Interrupts run in the order they are
+/// requested, so this must be called before the termination is requested.
+pub fn request_heap_snapshot(thread_safe_handle: &IsolateHandle, key: Uuid) {
+    let Some(HeapSnapshotConfig { dir, .. }) = HEAP_SNAPSHOT_CONFIG.get() else {
+        return;
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_millis())
+        .unwrap_or_default();
+
+    let path = dir.join(format!("{}-{}.{}", timestamp, key, HEAP_SNAPSHOT_EXTENSION));
+    let data_ptr_mut = Box::into_raw(Box::new(path));
+
+    if !thread_safe_handle.request_interrupt(
+        handle_heap_snapshot_interrupt,
+        data_ptr_mut as *mut std::ffi::c_void,
+    ) {
+        drop(unsafe { Box::from_raw(data_ptr_mut) });
+    }
+}
+
+extern "C" fn handle_heap_snapshot_interrupt(
+    isolate: &mut deno_core::v8::Isolate,
+    data: *mut std::ffi::c_void,
+) {
+    let path: Box<PathBuf>;
+
+    unsafe {
+        path = Box::from_raw(data as *mut PathBuf);
+    }
+
+    let Some(config) = HEAP_SNAPSHOT_CONFIG.get() else {
+        return;
+    };
+
+    let _guard = HEAP_SNAPSHOT_LOCK
+        .lock()
+        .unwrap_or_else(|it| it.into_inner());
+    let result = prune_heap_snapshots(config)
+        .and_then(|max_size_bytes| write_heap_snapshot(isolate, &path, max_size_bytes));
+
+    match result {
+        Ok(()) => info!("wrote a heap snapshot of the worker: {}", path.display()),
+        Err(err) => error!(
+            "failed to write a heap snapshot of the worker: {}: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+/// Removes the oldest snapshots of the directory, so that a new one fits in
+/// its limits, and returns the size left for it.
+fn prune_heap_snapshots(config: &HeapSnapshotConfig) -> std::io::Result<u64> {
+    std::fs::create_dir_all(&config.dir)?;
+
+    let mut snapshots = vec![];
+
+    for entry in std::fs::read_dir(&config.dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path
+            .extension()
+            .is_some_and(|it| it == HEAP_SNAPSHOT_EXTENSION)
+        {
+            snapshots.push((path, entry.metadata()?.len()));
+        }
+    }
+
+    // NOTE: The names start with the time the snapshot was taken at.
+    snapshots.sort();
+
+    let mut size = snapshots.iter().map(|(_, len)| len).sum::<u64>();
+    let mut snapshots = snapshots.into_iter();
+
+    while snapshots.len() >= config.max_count.max(1) || size >= config.max_size_bytes {
+        let Some((path, len)) = snapshots.next() else {
+            break;
+        };
+
+        std::fs::remove_file(&path)?;
+        size -= len;
+    }
+
+    Ok(config.max_size_bytes.saturating_sub(size))
+}
+
+fn write_heap_snapshot(
+    isolate: &mut deno_core::v8::Isolate,
+    path: &Path,
+    max_size_bytes: u64,
+) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut written = 0u64;
+    let mut result = Ok(());
+
+    isolate.take_heap_snapshot(|chunk| {
+        written += chunk.len() as u64;
+
+        if written > max_size_bytes {
+            result = Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "the snapshot exceeds the size limit of the directory",
+            ));
+            return false;
+        }
+
+        match file.write_all(chunk) {
+            Ok(()) => true,
+            Err(err) => {
+                result = Err(err);
+                false
+            }
+        }
+    });
+
+    if let Err(err) = result.and_then(|_| file.flush()) {
+        drop(file);
+        let _ = std::fs::remove_file(path);
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+pub struct NearHeapLimitData {
+    /// Heap size the isolate must get below to keep running.
+    pub heap_limit: usize,
+    /// Lowered once the collection has run.
+    pub collecting: Arc<AtomicFlag>,
+    pub on_exceeded: Box<dyn FnOnce() + Send>,
+}
+
+/// Second phase of the near heap limit callback of an isolate: performs a full
+/// GC, and only gives up on the isolate if its heap is still above the limit
+/// afterwards.
+pub extern "C" fn handle_near_heap_limit_interrupt(
+    isolate: &mut deno_core::v8::Isolate,
+    data: *mut std::ffi::c_void,
+) {
+    let boxed_data: Box<NearHeapLimitData>;
+
+    unsafe {
+        boxed_data = Box::from_raw(data as *mut NearHeapLimitData);
+    }
+
+    let mut heap_stats = deno_core::v8::HeapStatistics::default();
+
+    isolate.low_memory_notification();
+    isolate.get_heap_statistics(&mut heap_stats);
+
+    let NearHeapLimitData {
+        heap_limit,
+        collecting,
+        on_exceeded,
+    } = *boxed_data;
+
+    if heap_stats.used_heap_size() >= heap_limit {
+        on_exceeded();
+    } else {
+        debug!(
+            "heap shrank below the limit after a full gc: used: {}, limit: {}",
+            heap_stats.used_heap_size(),
+            heap_limit
+        );
+    }
+
+    collecting.lower();
+}
+
 #[repr(C)]
 pub struct IsolateMemoryStats {
     pub used_heap_size: usize,
@@ -127,10 +333,16 @@ pub struct Arguments {
     pub supervisor_policy: SupervisorPolicy,
     pub timing: Option<Timing>,
     pub memory_limit_rx: mpsc::UnboundedReceiver<()>,
+    /// Told when a single turn of the event loop of the worker has used too
+    /// much CPU time, see [`base_rt::USER_WORKER_MAX_TURN_SLICES`].
+    pub turn_overrun_rx: mpsc::UnboundedReceiver<()>,
     pub pool_msg_tx: Option<mpsc::UnboundedSender<UserWorkerMsgs>>,
     pub isolate_memory_usage_tx: oneshot::Sender<IsolateMemoryStats>,
     pub thread_safe_handle: IsolateHandle,
     pub waker: Arc<AtomicWaker>,
+    pub mem_check_state: Arc<RwLock<MemCheckState>>,
+    pub background_tasks: BackgroundTasks,
+    pub execution_clock: Option<Arc<ExecutionClock>>,
     pub tokens: Tokens,
 }
 
@@ -151,3 +363,97 @@ async fn wait_cpu_alarm(maybe_alarm: Option<&mut UnboundedReceiver<()>>) -> Opti
         None => None,
     }
 }
+
+/// Whether the pool has handed the worker the last request it may serve.
+fn is_max_requests_reached(max_requests: u64, demand: &AtomicUsize) -> bool {
+    max_requests > 0 && demand.load(Ordering::Acquire) as u64 >= max_requests
+}
+
+/// Publishes the resource usage of the worker to the pool.
+fn report_usage(
+    runtime_opts: &UserWorkerRuntimeOpts,
+    mem_check_state: &RwLock<MemCheckState>,
+    cpu_time_ms: i64,
+) {
+    let Some(stats) = runtime_opts.stats.as_ref() else {
+        return;
+    };
+
+    stats
+        .cpu_time_ms
+        .store(cpu_time_ms.max(0) as u64, Ordering::Release);
+    stats.used_heap_size.store(
+        mem_check_state.read().unwrap().current.used_heap_size,
+        Ordering::Release,
+    );
+}
+
+/// Consults the retirement policies of the worker.
+fn retirement_reason(
+    runtime_opts: &UserWorkerRuntimeOpts,
+    mem_check_state: &RwLock<MemCheckState>,
+    uptime: Duration,
+    cpu_time_ms: i64,
+    requests_served: usize,
+) -> Option<ShutdownReason> {
+    if runtime_opts.retirement_budgets.is_empty() && runtime_opts.retirement_policies.is_empty() {
+        return None;
+    }
+
+    check_retirement(
+        &runtime_opts.retirement_budgets,
+        &runtime_opts.retirement_policies,
+        &WorkerUsage {
+            uptime,
+            cpu_time_ms: cpu_time_ms.max(0) as u64,
+            requests_served,
+            used_heap_size: mem_check_state.read().unwrap().current.used_heap_size,
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prune_heap_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = HeapSnapshotConfig {
+            dir: dir.path().to_path_buf(),
+            max_count: 3,
+            max_size_bytes: 100,
+        };
+
+        for (name, len) in [("1-a", 30), ("2-b", 30), ("3-c", 30)] {
+            std::fs::write(
+                dir.path()
+                    .join(format!("{}.{}", name, HEAP_SNAPSHOT_EXTENSION)),
+                vec![0; len],
+            )
+            .unwrap();
+        }
+
+        std::fs::write(dir.path().join("notes.txt"), vec![0; 1000]).unwrap();
+
+        // NOTE: Only the oldest snapshot has to go to keep two and leave room
+        // for a third one; other files are left alone.
+        assert_eq!(prune_heap_snapshots(&config).unwrap(), 40);
+
+        let mut names = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|it| it.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+
+        names.sort();
+        assert_eq!(names, ["2-b.heapsnapshot", "3-c.heapsnapshot", "notes.txt"]);
+
+        let config = HeapSnapshotConfig {
+            max_size_bytes: 30,
+            ..config
+        };
+
+        assert_eq!(prune_heap_snapshots(&config).unwrap(), 30);
+        assert!(!dir.path().join("3-c.heapsnapshot").exists());
+    }
+}
@@ -4,12 +4,13 @@ use std::{future::pending, sync::atomic::Ordering, time::Duration};
 use std::thread::ThreadId;
 
 use event_worker::events::ShutdownReason;
-use log::error;
+use log::{debug, error};
 use sb_workers::context::{Timing, TimingStatus, UserWorkerMsgs};
 use tokio::time::Instant;
 
 use crate::rt_worker::supervisor::{
-    handle_interrupt, wait_cpu_alarm, CPUUsage, CPUUsageMetrics, IsolateInterruptData, Tokens,
+    handle_interrupt, is_max_requests_reached, report_usage, request_heap_snapshot,
+    retirement_reason, wait_cpu_alarm, CPUUsage, CPUUsageMetrics, IsolateInterruptData, Tokens,
 };
 
 use super::Arguments;
@@ -23,9 +24,13 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
         cpu_timer_param,
         cpu_usage_metrics_rx,
         mut memory_limit_rx,
+        mut turn_overrun_rx,
         pool_msg_tx,
         isolate_memory_usage_tx,
         thread_safe_handle,
+        mem_check_state,
+        background_tasks,
+        execution_clock,
         tokens: Tokens {
             termination,
             supervise,
@@ -42,7 +47,7 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
     let (cpu_timer, mut cpu_alarms_rx) = cpu_timer.unzip();
     let (_, hard_limit_ms) = cpu_timer_param.limits();
 
-    let _guard = scopeguard::guard(is_retired, |v| {
+    let guard = scopeguard::guard(is_retired, |v| {
         v.raise();
     });
 
@@ -55,9 +60,15 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
     let mut cpu_usage_accumulated_ms = 0i64;
 
     let mut complete_reason = None::<ShutdownReason>;
+    // Reasons decided once a request has completed wait for background tasks
+    // rather than terminating the worker while they're pending.
+    let mut is_request_completed = false;
+    let mut deferred_reason = None::<ShutdownReason>;
     let mut req_ack_count = 0usize;
     let mut req_start_ack = false;
 
+    let started_at = Instant::now();
+
     let wall_clock_limit_ms = runtime_opts.worker_timeout_ms;
     let is_wall_clock_limit_disabled = wall_clock_limit_ms == 0;
 
@@ -68,8 +79,24 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
     });
 
     let wall_clock_duration_alert = tokio::time::sleep(wall_clock_duration);
+    // NOTE: `EdgeRuntime.context` has to follow the wall clock as it's moved,
+    // as the deadline the runtime started it with is only good for the first
+    // request.
+    let set_deadline = |timeout: Duration| {
+        if let Some(clock) = execution_clock.as_ref() {
+            clock.set_deadline((!is_wall_clock_limit_disabled).then_some(timeout));
+        }
+    };
+    let wall_clock_grace = Duration::from_millis(runtime_opts.wall_clock_grace_ms);
+    let mut is_in_wall_clock_grace = false;
+
+    let idle_timeout_ms = runtime_opts.idle_timeout_ms;
+    let is_idle_eviction_disabled = oneshot || idle_timeout_ms == 0;
+    let idle_duration = Duration::from_millis(idle_timeout_ms);
+    let idle_sleep = tokio::time::sleep(idle_duration);
 
     tokio::pin!(wall_clock_duration_alert);
+    tokio::pin!(idle_sleep);
 
     loop {
         tokio::select! {
@@ -114,6 +141,8 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
                         cpu_usage_ms += diff / 1_000_000;
                         cpu_usage_accumulated_ms = accumulated / 1_000_000;
 
+                        report_usage(&runtime_opts, &mem_check_state, cpu_usage_accumulated_ms);
+
                         if !cpu_timer_param.is_disabled() {
                             if cpu_usage_ms >= hard_limit_ms as i64 {
                                 error!("CPU time limit reached: isolate: {:?}", key);
@@ -148,6 +177,10 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
                     }
                 }
 
+                if let Some(clock) = execution_clock.as_ref() {
+                    clock.reset_cpu_time();
+                }
+
                 cpu_usage_ms = 0;
                 req_start_ack = true;
                 complete_reason = None;
@@ -160,7 +193,24 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
                 assert!(req_start_ack, "supervisor observed the request end signal but did not see request start signal");
 
                 req_ack_count += 1;
-                complete_reason = Some(ShutdownReason::EarlyDrop);
+                is_request_completed = true;
+                complete_reason = Some(if oneshot {
+                    ShutdownReason::EarlyDrop
+                } else if is_max_requests_reached(runtime_opts.max_requests, &demand) {
+                    ShutdownReason::MaxRequests
+                } else if let Some(reason) = retirement_reason(
+                    &runtime_opts,
+                    &mem_check_state,
+                    started_at.elapsed(),
+                    cpu_usage_accumulated_ms,
+                    req_ack_count,
+                ) {
+                    guard.raise();
+                    debug!("retiring worker: isolate: {:?} (reason = {:?})", key, reason);
+                    reason
+                } else {
+                    ShutdownReason::EarlyDrop
+                });
             }
 
             _ = &mut wall_clock_duration_alert, if !is_wall_clock_limit_disabled => {
@@ -168,7 +218,19 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
                     wall_clock_duration_alert
                         .as_mut()
                         .reset(Instant::now() + wall_clock_duration);
+                    set_deadline(wall_clock_duration);
 
+                    continue;
+                } else if req_start_ack && !is_in_wall_clock_grace && !wall_clock_grace.is_zero() {
+                    // NOTE: The worker is retired so that it's not handed new
+                    // requests while the current one finishes.
+                    guard.raise();
+                    is_in_wall_clock_grace = true;
+                    wall_clock_duration_alert
+                        .as_mut()
+                        .reset(Instant::now() + wall_clock_grace);
+
+                    error!("wall clock duration reached, waiting for the in-flight request: isolate: {:?}", key);
                     continue;
                 } else {
                     error!("wall clock duraiton reached: isolate: {:?}", key);
@@ -176,18 +238,52 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
                 }
             }
 
+            _ = &mut idle_sleep, if !is_idle_eviction_disabled && !req_start_ack => {
+                if req_ack_count != demand.load(Ordering::Acquire) || background_tasks.pending() > 0 {
+                    idle_sleep.as_mut().reset(Instant::now() + idle_duration);
+                    continue;
+                }
+
+                guard.raise();
+                debug!("evicting idle worker: isolate: {:?}", key);
+                complete_reason = Some(ShutdownReason::Idle);
+            }
+
             Some(_) = memory_limit_rx.recv() => {
                 error!("memory limit reached for the worker: isolate: {:?}", key);
+                request_heap_snapshot(&thread_safe_handle, key);
                 complete_reason = Some(ShutdownReason::Memory);
             }
+
+            Some(_) = turn_overrun_rx.recv() => {
+                error!("a single turn ran for too long: isolate: {:?}", key);
+                // NOTE: The turn never yields to the background tasks it would
+                // otherwise be waiting for.
+                is_request_completed = false;
+                complete_reason = Some(ShutdownReason::TurnTime);
+            }
+
+            _ = background_tasks.wait_settled(), if deferred_reason.is_some() => {
+                debug!("background tasks settled: isolate: {:?}", key);
+                complete_reason = deferred_reason.take();
+            }
         }
 
+        let is_graceful = std::mem::take(&mut is_request_completed);
+
         match complete_reason.take() {
-            Some(ShutdownReason::EarlyDrop) if !oneshot => {
+            Some(ShutdownReason::EarlyDrop)
+                if !oneshot && !is_max_requests_reached(runtime_opts.max_requests, &demand) =>
+            {
                 req_start_ack = false;
                 wall_clock_duration_alert
                     .as_mut()
                     .reset(Instant::now() + wall_clock_duration);
+                set_deadline(wall_clock_duration);
+
+                if !is_idle_eviction_disabled {
+                    idle_sleep.as_mut().reset(Instant::now() + idle_duration);
+                }
 
                 if let Some(tx) = pool_msg_tx.clone() {
                     if tx.send(UserWorkerMsgs::Idle(key)).is_err() {
@@ -198,6 +294,12 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
                 continue;
             }
 
+            Some(reason) if is_graceful && background_tasks.pending() > 0 => {
+                debug!("waiting for background tasks: isolate: {:?}", key);
+                deferred_reason = Some(reason);
+                continue;
+            }
+
             Some(reason) => {
                 let data_ptr_mut = Box::into_raw(Box::new(IsolateInterruptData {
                     should_terminate: true,
@@ -4,12 +4,15 @@ use std::{future::pending, sync::atomic::Ordering, time::Duration};
 use std::thread::ThreadId;
 
 use event_worker::events::ShutdownReason;
-use log::error;
+use log::{debug, error};
 use sb_workers::context::{Timing, TimingStatus, UserWorkerMsgs};
 
-use crate::rt_worker::supervisor::{wait_cpu_alarm, CPUUsage, Tokens};
+use crate::rt_worker::supervisor::{is_max_requests_reached, wait_cpu_alarm, CPUUsage, Tokens};
 
-use super::{handle_interrupt, Arguments, CPUUsageMetrics, IsolateInterruptData};
+use super::{
+    handle_interrupt, report_usage, request_heap_snapshot, retirement_reason, Arguments,
+    CPUUsageMetrics, IsolateInterruptData,
+};
 
 pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
     let Arguments {
@@ -17,12 +20,15 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
         runtime_opts,
         timing,
         mut memory_limit_rx,
+        mut turn_overrun_rx,
         cpu_timer,
         cpu_timer_param,
         cpu_usage_metrics_rx,
         pool_msg_tx,
         isolate_memory_usage_tx,
         thread_safe_handle,
+        mem_check_state,
+        background_tasks,
         tokens: Tokens {
             termination,
             supervise,
@@ -52,6 +58,12 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
     let mut cpu_time_soft_limit_reached = false;
     let mut wall_clock_alerts = 0;
     let mut req_ack_count = 0usize;
+    let mut policy_retire_reason = Option::<ShutdownReason>::None;
+    // Set once the worker would have been terminated after its last request,
+    // but background tasks were still pending.
+    let mut deferred_reason = Option::<ShutdownReason>::None;
+
+    let started_at = tokio::time::Instant::now();
 
     let wall_clock_limit_ms = runtime_opts.worker_timeout_ms;
     let is_wall_clock_limit_disabled = wall_clock_limit_ms == 0;
@@ -70,6 +82,23 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
             .unwrap_or(Duration::from_millis(1)),
     );
 
+    let wall_clock_grace = Duration::from_millis(runtime_opts.wall_clock_grace_ms);
+    let wall_clock_grace_sleep = tokio::time::sleep(Duration::ZERO);
+    let mut is_in_wall_clock_grace = false;
+
+    let hibernation = runtime_opts
+        .hibernation
+        .clone()
+        .filter(|_| runtime_opts.hibernate_after_ms > 0);
+    let is_hibernation_disabled = hibernation.is_none();
+    let hibernate_duration = Duration::from_millis(runtime_opts.hibernate_after_ms);
+    let hibernate_sleep = tokio::time::sleep(hibernate_duration);
+
+    let idle_timeout_ms = runtime_opts.idle_timeout_ms;
+    let is_idle_eviction_disabled = idle_timeout_ms == 0;
+    let idle_duration = Duration::from_millis(idle_timeout_ms);
+    let idle_sleep = tokio::time::sleep(idle_duration);
+
     let early_retire_fn = || {
         // we should raise a retire signal because subsequent incoming requests are unlikely to get
         // enough wall clock time or cpu time
@@ -93,6 +122,9 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
     };
 
     tokio::pin!(wall_clock_duration_alert);
+    tokio::pin!(wall_clock_grace_sleep);
+    tokio::pin!(hibernate_sleep);
+    tokio::pin!(idle_sleep);
 
     loop {
         tokio::select! {
@@ -137,6 +169,14 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
                         is_worker_entered = false;
                         cpu_usage_ms = accumulated / 1_000_000;
 
+                        report_usage(&runtime_opts, &mem_check_state, cpu_usage_ms);
+
+                        if !is_hibernation_disabled {
+                            hibernate_sleep
+                                .as_mut()
+                                .reset(tokio::time::Instant::now() + hibernate_duration);
+                        }
+
                         if !cpu_timer_param.is_disabled() {
                             if cpu_usage_ms >= hard_limit_ms as i64 {
                                 terminate_fn();
@@ -148,6 +188,11 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
                                 cpu_time_soft_limit_reached = true;
 
                                 if req_ack_count == demand.load(Ordering::Acquire) {
+                                    if background_tasks.pending() > 0 {
+                                        deferred_reason = Some(ShutdownReason::EarlyDrop);
+                                        continue;
+                                    }
+
                                     terminate_fn();
                                     error!("early termination due to the last request being completed: isolate: {:?}", key);
                                     return (ShutdownReason::EarlyDrop, cpu_usage_ms);
@@ -166,6 +211,11 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
                         cpu_time_soft_limit_reached = true;
 
                         if req_ack_count == demand.load(Ordering::Acquire) {
+                            if background_tasks.pending() > 0 {
+                                deferred_reason = Some(ShutdownReason::EarlyDrop);
+                                continue;
+                            }
+
                             terminate_fn();
                             error!("early termination due to the last request being completed: isolate: {:?}", key);
                             return (ShutdownReason::EarlyDrop, cpu_usage_ms);
@@ -181,6 +231,18 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
             Some(_) = req_end_rx.recv() => {
                 req_ack_count += 1;
 
+                if is_in_wall_clock_grace && req_ack_count == demand.load(Ordering::Acquire) {
+                    terminate_fn();
+                    error!("wall clock duration reached after the last request being completed: isolate: {:?}", key);
+                    return (ShutdownReason::WallClockTime, cpu_usage_ms);
+                }
+
+                if !is_idle_eviction_disabled {
+                    idle_sleep
+                        .as_mut()
+                        .reset(tokio::time::Instant::now() + idle_duration);
+                }
+
                 if !cpu_time_soft_limit_reached {
                     if let Some(tx) = pool_msg_tx.clone() {
                         if tx.send(UserWorkerMsgs::Idle(key)).is_err() {
@@ -189,16 +251,55 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
                     }
                 }
 
-                if !cpu_time_soft_limit_reached || req_ack_count != demand.load(Ordering::Acquire) {
+                if policy_retire_reason.is_none() {
+                    policy_retire_reason = retirement_reason(
+                        &runtime_opts,
+                        &mem_check_state,
+                        started_at.elapsed(),
+                        cpu_usage_ms,
+                        req_ack_count,
+                    );
+
+                    if let Some(reason) = policy_retire_reason {
+                        early_retire_fn();
+                        debug!("retiring worker: isolate: {:?} (reason = {:?})", key, reason);
+                    }
+                }
+
+                let retire_reason = if cpu_time_soft_limit_reached {
+                    Some(ShutdownReason::EarlyDrop)
+                } else if is_max_requests_reached(runtime_opts.max_requests, &demand) {
+                    Some(ShutdownReason::MaxRequests)
+                } else {
+                    policy_retire_reason
+                };
+
+                let Some(reason) = retire_reason else {
+                    continue;
+                };
+
+                if req_ack_count != demand.load(Ordering::Acquire) {
+                    continue;
+                }
+
+                if background_tasks.pending() > 0 {
+                    debug!("waiting for background tasks: isolate: {:?}", key);
+                    deferred_reason = Some(reason);
                     continue;
                 }
 
                 terminate_fn();
                 error!("early termination due to the last request being completed: isolate: {:?}", key);
-                return (ShutdownReason::EarlyDrop, cpu_usage_ms);
+                return (reason, cpu_usage_ms);
+            }
+
+            _ = background_tasks.wait_settled(), if deferred_reason.is_some() => {
+                terminate_fn();
+                error!("early termination due to the background tasks being settled: isolate: {:?}", key);
+                return (deferred_reason.unwrap(), cpu_usage_ms);
             }
 
-            _ = wall_clock_duration_alert.tick(), if !is_wall_clock_limit_disabled => {
+            _ = wall_clock_duration_alert.tick(), if !is_wall_clock_limit_disabled && !is_in_wall_clock_grace => {
                 if wall_clock_alerts == 0 {
                     // first tick completes immediately
                     wall_clock_alerts += 1;
@@ -209,6 +310,16 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
                 } else {
                     let is_in_flight_req_exists = req_ack_count != demand.load(Ordering::Acquire);
 
+                    if is_in_flight_req_exists && !wall_clock_grace.is_zero() {
+                        is_in_wall_clock_grace = true;
+                        wall_clock_grace_sleep
+                            .as_mut()
+                            .reset(tokio::time::Instant::now() + wall_clock_grace);
+
+                        error!("wall clock duration reached, waiting for in-flight requests: isolate: {:?}", key);
+                        continue;
+                    }
+
                     terminate_fn();
 
                     error!("wall clock duration reached: isolate: {:?} (in_flight_req_exists = {})", key, is_in_flight_req_exists);
@@ -217,11 +328,63 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
                 }
             }
 
+            _ = &mut wall_clock_grace_sleep, if is_in_wall_clock_grace => {
+                terminate_fn();
+                error!("wall clock grace period elapsed: isolate: {:?}", key);
+                return (ShutdownReason::WallClockTime, cpu_usage_ms);
+            }
+
+            _ = &mut hibernate_sleep, if !is_hibernation_disabled => {
+                // NOTE: A worker with no connection to park is left to idle
+                // eviction, as nothing would wake it back up.
+                if is_worker_entered
+                    || req_ack_count != demand.load(Ordering::Acquire)
+                    || background_tasks.pending() > 0
+                    || !hibernation.as_ref().unwrap().has_resumable()
+                {
+                    hibernate_sleep
+                        .as_mut()
+                        .reset(tokio::time::Instant::now() + hibernate_duration);
+
+                    continue;
+                }
+
+                // NOTE: The connections are parked before the worker is
+                // terminated, so that none of them is taken for closed.
+                early_retire_fn();
+                hibernation.as_ref().unwrap().token.cancel();
+                terminate_fn();
+                debug!("hibernating idle worker: isolate: {:?}", key);
+                return (ShutdownReason::Hibernated, cpu_usage_ms);
+            }
+
+            _ = &mut idle_sleep, if !is_idle_eviction_disabled => {
+                if req_ack_count != demand.load(Ordering::Acquire) || background_tasks.pending() > 0 {
+                    idle_sleep
+                        .as_mut()
+                        .reset(tokio::time::Instant::now() + idle_duration);
+
+                    continue;
+                }
+
+                early_retire_fn();
+                terminate_fn();
+                debug!("evicting idle worker: isolate: {:?}", key);
+                return (ShutdownReason::Idle, cpu_usage_ms);
+            }
+
             Some(_) = memory_limit_rx.recv() => {
+                request_heap_snapshot(&thread_safe_handle, key);
                 terminate_fn();
                 error!("memory limit reached for the worker: isolate: {:?}", key);
                 return (ShutdownReason::Memory, cpu_usage_ms);
             }
+
+            Some(_) = turn_overrun_rx.recv() => {
+                terminate_fn();
+                error!("a single turn ran for too long: isolate: {:?}", key);
+                return (ShutdownReason::TurnTime, cpu_usage_ms);
+            }
         }
     }
 }
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+/// Aggregate limits of the workers of a tenant, enforced on top of the limits
+/// of each worker.
+#[derive(Debug, Clone)]
+pub struct TenantQuotaPolicy {
+    /// Live workers of a tenant. Further workers aren't booted, while the warm
+    /// ones keep serving.
+    pub max_workers: Option<usize>,
+    /// Sum of the heap sizes of the live workers of a tenant.
+    pub max_memory_mb: Option<u64>,
+    /// CPU time the workers of a tenant may use within `window`.
+    pub max_cpu_time_ms: Option<u64>,
+    pub window: Duration,
+}
+
+impl Default for TenantQuotaPolicy {
+    fn default() -> Self {
+        Self {
+            max_workers: None,
+            max_memory_mb: None,
+            max_cpu_time_ms: None,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+impl TenantQuotaPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.max_workers.is_none() && self.max_memory_mb.is_none() && self.max_cpu_time_ms.is_none()
+    }
+}
+
+/// Resources the live workers of a tenant use at the moment.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TenantUsage {
+    pub workers: usize,
+    pub used_heap_size: usize,
+    /// Total CPU time the live workers have used since they booted.
+    pub cpu_time_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    pub resource: &'static str,
+    pub retry_after: Duration,
+}
+
+#[derive(Debug)]
+struct TenantWindow {
+    started_at: Instant,
+    /// CPU time of the workers of the tenant that have exited.
+    exited_cpu_time_ms: u64,
+    /// Total CPU time of the tenant when the window started.
+    cpu_time_at_start_ms: u64,
+}
+
+/// Tracks the CPU time of tenants across the workers they boot and retire.
+pub struct TenantQuotas {
+    policy: TenantQuotaPolicy,
+    tenants: HashMap<String, TenantWindow>,
+}
+
+impl TenantQuotas {
+    pub fn new(policy: TenantQuotaPolicy) -> Self {
+        Self {
+            policy,
+            tenants: HashMap::new(),
+        }
+    }
+
+    /// Returns the quota `tenant` has exhausted, if any. The worker quota is
+    /// only consulted when the request would boot a worker.
+    pub fn check(
+        &mut self,
+        tenant: &str,
+        usage: &TenantUsage,
+        boot: bool,
+    ) -> Option<QuotaExceeded> {
+        self.check_at(tenant, usage, boot, Instant::now())
+    }
+
+    /// Accounts the CPU time of a worker of `tenant` that has exited, so that
+    /// it keeps counting towards the window it was used in.
+    pub fn record_exit(&mut self, tenant: &str, cpu_time_ms: u64) {
+        if let Some(window) = self.tenants.get_mut(tenant) {
+            window.exited_cpu_time_ms += cpu_time_ms;
+        }
+    }
+
+    fn check_at(
+        &mut self,
+        tenant: &str,
+        usage: &TenantUsage,
+        boot: bool,
+        now: Instant,
+    ) -> Option<QuotaExceeded> {
+        let exceeded = |resource| {
+            warn!("tenant {} exceeded its {} quota", tenant, resource);
+
+            Some(QuotaExceeded {
+                resource,
+                retry_after: Duration::from_secs(1),
+            })
+        };
+
+        if boot
+            && self
+                .policy
+                .max_workers
+                .is_some_and(|it| usage.workers >= it)
+        {
+            return exceeded("worker");
+        }
+
+        if self
+            .policy
+            .max_memory_mb
+            .is_some_and(|it| usage.used_heap_size as u64 >= it * 1024 * 1024)
+        {
+            return exceeded("memory");
+        }
+
+        let max_cpu_time_ms = self.policy.max_cpu_time_ms?;
+        let window = self
+            .tenants
+            .entry(tenant.to_string())
+            .or_insert_with(|| TenantWindow {
+                started_at: now,
+                exited_cpu_time_ms: 0,
+                cpu_time_at_start_ms: usage.cpu_time_ms,
+            });
+
+        let total_cpu_time_ms = window.exited_cpu_time_ms + usage.cpu_time_ms;
+
+        if now.duration_since(window.started_at) >= self.policy.window {
+            window.started_at = now;
+            window.cpu_time_at_start_ms = total_cpu_time_ms;
+        }
+
+        let used_cpu_time_ms = total_cpu_time_ms.saturating_sub(window.cpu_time_at_start_ms);
+
+        if used_cpu_time_ms >= max_cpu_time_ms {
+            let retry_after = (window.started_at + self.policy.window).duration_since(now);
+
+            warn!(
+                "tenant {} used {}ms of CPU time within {:?}",
+                tenant, used_cpu_time_ms, self.policy.window
+            );
+
+            return Some(QuotaExceeded {
+                resource: "CPU time",
+                retry_after,
+            });
+        }
+
+        None
+    }
+}
+
+/// Returns the tenant a service belongs to: the one given by the main worker,
+/// or else the directory containing the service.
+pub fn tenant_of(service_path: &str, tenant: Option<&str>) -> String {
+    tenant.map(str::to_string).unwrap_or_else(|| {
+        Path::new(service_path)
+            .parent()
+            .map(|it| it.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tenant_quotas() {
+        let mut quotas = TenantQuotas::new(TenantQuotaPolicy {
+            max_workers: Some(2),
+            max_cpu_time_ms: Some(100),
+            ..Default::default()
+        });
+
+        let now = Instant::now();
+        let usage = TenantUsage {
+            workers: 2,
+            used_heap_size: 0,
+            cpu_time_ms: 500,
+        };
+
+        // Workers only count towards the quota when another one would boot.
+        assert_eq!(quotas.check_at("a", &usage, false, now), None);
+        assert_eq!(
+            quotas
+                .check_at("a", &usage, true, now)
+                .map(|it| it.resource),
+            Some("worker")
+        );
+
+        // CPU time used by a worker that has exited still counts...
+        quotas.record_exit("a", 160);
+        let usage = TenantUsage {
+            workers: 1,
+            cpu_time_ms: 450,
+            ..usage
+        };
+
+        assert_eq!(
+            quotas.check_at("a", &usage, true, now + Duration::from_secs(10)),
+            Some(QuotaExceeded {
+                resource: "CPU time",
+                retry_after: Duration::from_secs(50),
+            })
+        );
+
+        // ...until the window has passed.
+        assert_eq!(
+            quotas.check_at("a", &usage, true, now + Duration::from_secs(60)),
+            None
+        );
+        assert_eq!(quotas.check_at("b", &usage, true, now), None);
+    }
+}
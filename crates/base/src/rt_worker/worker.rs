@@ -12,14 +12,17 @@ use event_worker::events::{
 };
 use futures_util::FutureExt;
 use log::{debug, error};
+use sb_core::conn_sync::RequestMarks;
 use sb_core::{MetricSource, RuntimeMetricSource, WorkerMetricSource};
 use sb_workers::context::{UserWorkerMsgs, WorkerContextInitOpts, WorkerExit, WorkerExitStatus};
 use std::any::Any;
 use std::future::{pending, Future};
 use std::pin::Pin;
+use std::time::Duration;
 use tokio::io;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot::{self, Receiver, Sender};
+use tokio::task::{JoinError, JoinHandle};
 use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
@@ -42,7 +45,7 @@ pub struct Worker {
 }
 
 pub type HandleCreationType<'r> = Pin<Box<dyn Future<Output = Result<WorkerEvents, Error>> + 'r>>;
-pub type DuplexStreamEntry = (io::DuplexStream, Option<CancellationToken>);
+pub type DuplexStreamEntry = (io::DuplexStream, Option<CancellationToken>, RequestMarks);
 
 pub trait WorkerHandler: Send {
     fn handle_error(&self, error: Error) -> Result<WorkerEvents, Error>;
@@ -97,7 +100,7 @@ impl Worker {
         exit: WorkerExit,
         termination_token: Option<TerminationToken>,
         inspector: Option<Inspector>,
-    ) {
+    ) -> JoinHandle<Result<(), JoinError>> {
         let worker_name = self.worker_name.clone();
         let worker_key = self.worker_key;
         let event_metadata = self.event_metadata.clone();
@@ -111,6 +114,12 @@ impl Worker {
         let timing = opts.timing.take();
         let worker_kind = opts.conf.to_worker_kind();
         let maybe_main_worker_opts = opts.conf.as_main_worker().cloned();
+        let maybe_boot_timeout = opts
+            .conf
+            .as_user_worker()
+            .map(|it| it.boot_timeout_ms)
+            .filter(|it| *it > 0)
+            .map(Duration::from_millis);
 
         let cancel = self.cancel.clone();
         let rt = if worker_kind.is_user_worker() {
@@ -119,14 +128,31 @@ impl Worker {
             &base_rt::PRIMARY_WORKER_RT
         };
 
-        let _worker_handle = rt.spawn_pinned(move || {
+        rt.spawn_pinned(move || {
+            if worker_kind.is_user_worker() {
+                base_rt::placement::place_user_worker_thread();
+            }
+
             tokio::task::spawn_local(async move {
                 let (maybe_cpu_usage_metrics_tx, maybe_cpu_usage_metrics_rx) = worker_kind
                     .is_user_worker()
                     .then(unbounded_channel::<CPUUsageMetrics>)
                     .unzip();
 
-                let result = match DenoRuntime::new(opts, inspector).await {
+                // NOTE: Loading the module graph (e.g. fetching remote
+                // imports) is bounded by the boot timeout only; the wall clock
+                // limit starts once the worker has booted.
+                let boot = DenoRuntime::new(opts, inspector);
+                let boot_result = match maybe_boot_timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, boot)
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(anyhow!("worker did not boot within {}ms", timeout.as_millis()))
+                        }),
+                    None => boot.await,
+                };
+
+                let result = match boot_result {
                     Ok(mut new_runtime) => {
                         let metric_src = {
                             let js_runtime = &mut new_runtime.js_runtime;
@@ -172,6 +198,7 @@ impl Worker {
                                 cancel,
                                 timing,
                                 termination_token.clone(),
+                                exit.clone(),
                             ) else {
                                 return;
                             };
@@ -338,6 +365,6 @@ impl Worker {
                     Err(err) => error!("unexpected worker error {}", err),
                 };
             })
-        });
+        })
     }
 }
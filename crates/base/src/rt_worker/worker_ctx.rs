@@ -11,37 +11,46 @@ use cpu_timer::CPUTimer;
 use deno_config::JsxImportSourceConfig;
 use deno_core::{InspectorSessionProxy, LocalInspectorSession};
 use event_worker::events::{
-    BootEvent, ShutdownEvent, WorkerEventWithMetadata, WorkerEvents, WorkerMemoryUsed,
+    BootEvent, EventMetadata, RestartEvent, ShutdownEvent, UncaughtExceptionEvent,
+    WorkerEventWithMetadata, WorkerEvents, WorkerMemoryUsed,
 };
+use futures_util::future::Either;
 use futures_util::pin_mut;
 use http_utils::io::Upgraded2;
 use http_utils::utils::{emit_status_code, get_upgrade_type};
-use http_v02::StatusCode;
-use hyper_v014::client::conn::http1;
+use http_v02::{header, HeaderValue, StatusCode, Uri, Version};
+use hyper_v014::body::HttpBody;
+use hyper_v014::client::conn::{http1, http2};
 use hyper_v014::upgrade::OnUpgrade;
 use hyper_v014::{Body, Request, Response};
 use log::{debug, error};
+use sb_core::conn_sync::{
+    CronRequest, EarlyHintsSender, MuxRequests, RequestMarks, ResponseTrailers, MUX_REQUEST_HEADER,
+};
+use sb_core::cpu_profile::{is_cpu_profile_token, CPU_PROFILE_HEADER};
+use sb_core::util::sync::AtomicFlag;
 use sb_core::{MetricSource, SharedMetricSource};
 use sb_graph::{DecoratorType, EszipPayloadKind};
 use sb_workers::context::{
     EventWorkerRuntimeOpts, MainWorkerRuntimeOpts, Timing, UserWorkerMsgs, WorkerContextInitOpts,
-    WorkerExit, WorkerKind, WorkerRequestMsg, WorkerRuntimeOpts,
+    WorkerExit, WorkerExitStatus, WorkerKind, WorkerRequestMsg, WorkerRuntimeOpts,
 };
-use sb_workers::errors::WorkerError;
-use std::future::pending;
+use std::collections::HashSet;
+use std::future::{pending, Future};
 use std::io::ErrorKind;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{self, copy_bidirectional};
+use tokio::io::{self, copy_bidirectional, AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::{mpsc, oneshot, Mutex};
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
 use tokio_rustls::server::TlsStream;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+use super::hibernation::{relay_until_hibernated, Hibernation, ParkedConnection, Resumable};
 use super::supervisor::{self, CPUTimerParam, CPUUsageMetrics};
 use super::worker::DuplexStreamEntry;
 use super::worker_pool::{SupervisorPolicy, WorkerPoolPolicy};
@@ -79,6 +88,15 @@ impl TerminationToken {
         }
     }
 
+    /// Like [`Self::child_token`], but the child reports its own termination
+    /// rather than that of the parent.
+    pub fn detached_child_token(&self) -> Self {
+        Self {
+            inbound: self.inbound.child_token(),
+            outbound: CancellationToken::default(),
+        }
+    }
+
     pub fn cancel(&self) {
         self.inbound.cancel();
     }
@@ -93,65 +111,226 @@ impl TerminationToken {
     }
 }
 
+/// A request sent to the worker over a stream of its own speaks HTTP/1.1, while
+/// an HTTP/2 request carries its authority in the URI rather than in a `Host`
+/// header. Rewrites `req` into the equivalent HTTP/1.1 request so that it
+/// reaches the worker intact.
+///
+/// NOTE: Only upgrades are sent that way, so trailers never need to cross such
+/// a stream. See [`forward_with_trailers`] for the others.
+pub(super) fn downgrade_h2_request(req: &mut Request<Body>) {
+    if req.version() != Version::HTTP_2 {
+        return;
+    }
+
+    if !req.headers().contains_key(header::HOST) {
+        if let Some(host) = req
+            .uri()
+            .authority()
+            .and_then(|it| HeaderValue::from_str(it.as_str()).ok())
+        {
+            req.headers_mut().insert(header::HOST, host);
+        }
+    }
+
+    if let Some(uri) = req
+        .uri()
+        .path_and_query()
+        .and_then(|it| Uri::try_from(it.as_str()).ok())
+    {
+        *req.uri_mut() = uri;
+    }
+
+    *req.version_mut() = Version::HTTP_11;
+}
+
+/// The executor the HTTP/2 connections to workers run their streams on.
+#[derive(Clone, Copy)]
+struct TokioExecutor;
+
+impl<F> hyper_v014::rt::Executor<F> for TokioExecutor
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, fut: F) {
+        drop(tokio::spawn(fut));
+    }
+}
+
+// NOTE: A stream may fill the window of the connection on its own. The window
+// of the connection is kept well above that of a stream, so a client reading a
+// response slowly doesn't stall the responses to the others.
+const H2_STREAM_WINDOW_SIZE: u32 = 256 * 1024;
+const H2_CONN_WINDOW_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Sets up an HTTP/2 connection to a worker over `io` and drives it in the
+/// background.
+pub(super) async fn connect_h2<T>(io: T) -> Result<http2::SendRequest<Body>, Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (request_sender, connection) = http2::Builder::new(TokioExecutor)
+        .initial_stream_window_size(H2_STREAM_WINDOW_SIZE)
+        .initial_connection_window_size(H2_CONN_WINDOW_SIZE)
+        .handshake(io)
+        .await?;
+
+    tokio::task::spawn(async move {
+        if let Err(err) = connection.await {
+            error!("error in worker connection: {}", err);
+        }
+    });
+
+    Ok(request_sender)
+}
+
+/// HTTP/2 carries the authority of a request in its URI. Rewrites `req` into
+/// the equivalent HTTP/2 request, which the worker turns back into the request
+/// it would have seen over HTTP/1.1.
+pub(super) fn upgrade_to_h2_request(req: &mut Request<Body>) {
+    downgrade_h2_request(req);
+
+    let authority = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|it| it.to_str().ok())
+        .unwrap_or("localhost");
+
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|it| it.as_str())
+        .unwrap_or("/");
+
+    if let Ok(uri) = Uri::builder()
+        .scheme("http")
+        .authority(authority)
+        .path_and_query(path_and_query)
+        .build()
+    {
+        *req.uri_mut() = uri;
+    }
+
+    *req.version_mut() = Version::HTTP_2;
+}
+
+// NOTE: The stream is shared by every request to the worker, so it buffers
+// more than a stream carrying a single request does.
+const MUX_STREAM_BUF_SIZE: usize = 64 * 1024;
+
+/// The HTTP/2 connection requests to an in-process worker are multiplexed over,
+/// set up on first use and again whenever it breaks.
+struct WorkerConnection {
+    duplex_stream_tx: mpsc::UnboundedSender<DuplexStreamEntry>,
+    requests: MuxRequests,
+    sender: Mutex<Option<http2::SendRequest<Body>>>,
+}
+
+impl WorkerConnection {
+    fn new(duplex_stream_tx: mpsc::UnboundedSender<DuplexStreamEntry>) -> Self {
+        Self {
+            duplex_stream_tx,
+            requests: MuxRequests::default(),
+            sender: Mutex::default(),
+        }
+    }
+
+    async fn sender(&self) -> Result<http2::SendRequest<Body>, Error> {
+        let mut sender = self.sender.lock().await;
+
+        if let Some(sender) = sender.as_ref().filter(|it| !it.is_closed()) {
+            return Ok(sender.clone());
+        }
+
+        let (ours, theirs) = io::duplex(MUX_STREAM_BUF_SIZE);
+        let marks = RequestMarks {
+            mux: Some(self.requests.clone()),
+            ..Default::default()
+        };
+
+        self.duplex_stream_tx
+            .send((theirs, None, marks))
+            .map_err(|_| anyhow!("worker is no longer accepting connections"))?;
+
+        let request_sender = connect_h2(ours).await?;
+
+        *sender = Some(request_sender.clone());
+
+        Ok(request_sender)
+    }
+}
+
 async fn handle_request(
     worker_kind: WorkerKind,
-    duplex_stream_tx: mpsc::UnboundedSender<DuplexStreamEntry>,
+    conn: Arc<WorkerConnection>,
     msg: WorkerRequestMsg,
     maybe_request_idle_timeout: Option<u64>,
 ) -> Result<(), Error> {
-    let (ours, theirs) = io::duplex(1024);
     let WorkerRequestMsg {
         mut req,
         res_tx,
         conn_token,
     } = msg;
 
-    let _ = duplex_stream_tx.send((theirs, conn_token.clone()));
-    let req_upgrade_type = get_upgrade_type(req.headers());
-    let req_upgrade = req_upgrade_type
-        .clone()
-        .and_then(|it| Some(it).zip(req.extensions_mut().remove::<OnUpgrade>()));
-
-    // send the HTTP request to the worker over duplex stream
-    let (mut request_sender, connection) =
-        http1::Builder::new().writev(true).handshake(ours).await?;
+    downgrade_h2_request(&mut req);
+
+    // NOTE: The token of a profiled request is only for the runtime, so it's
+    // taken out before the user code can see it.
+    let cpu_profile = matches!(worker_kind, WorkerKind::UserWorker)
+        && req
+            .headers_mut()
+            .remove(CPU_PROFILE_HEADER)
+            .is_some_and(|it| is_cpu_profile_token(it.as_bytes()));
+
+    req.headers_mut().remove(MUX_REQUEST_HEADER);
+
+    let marks = RequestMarks {
+        cron: req.extensions_mut().remove::<CronRequest>(),
+        cpu_profile,
+        resumed_from: req
+            .extensions()
+            .get::<ParkedConnection>()
+            .map(|it| it.state.clone().unwrap_or_default()),
+        early_hints: req.extensions_mut().remove::<EarlyHintsSender>(),
+        ..Default::default()
+    };
 
+    let req_cancel = conn_token.clone();
+    let req_upgrade_type = get_upgrade_type(req.headers());
+    let trailers =
+        (req_upgrade_type.is_none() && accepts_trailers(&req)).then(|| marks.trailers.clone());
     let (upgrade_tx, upgrade_rx) = oneshot::channel();
+    let mut mux_request = None;
 
-    // spawn a task to poll the connection and drive the HTTP state
-    tokio::task::spawn({
-        async move {
-            match connection.without_shutdown().await {
-                Err(e) => {
-                    error!(
-                        "error in {} worker connection: {}",
-                        worker_kind,
-                        e.message()
-                    );
-                }
+    // NOTE: Requests are multiplexed over the HTTP/2 connection to the worker,
+    // except for upgrades, which take over the stream they're sent over.
+    let res_fut = if req_upgrade_type.is_none() {
+        let request = conn.requests.register(conn_token, marks);
 
-                Ok(parts) => {
-                    if let Some((requested, req_upgrade)) = req_upgrade {
-                        if let Ok((Some(accepted), status)) = upgrade_rx.await {
-                            if status == StatusCode::SWITCHING_PROTOCOLS && accepted == requested {
-                                tokio::spawn(relay_upgraded_request_and_response(
-                                    req_upgrade,
-                                    parts,
-                                    maybe_request_idle_timeout,
-                                ));
+        req.headers_mut()
+            .insert(MUX_REQUEST_HEADER, HeaderValue::from(request.key()));
 
-                                return;
-                            }
-                        };
-                    }
+        upgrade_to_h2_request(&mut req);
+        mux_request = Some(request);
 
-                    if let Some(token) = conn_token {
-                        token.cancelled_owned().await;
-                    }
-                }
-            }
-        }
-    });
+        let mut request_sender = conn.sender().await?;
+
+        Either::Left(request_sender.send_request(req))
+    } else {
+        Either::Right(
+            send_request_alone(
+                worker_kind,
+                &conn.duplex_stream_tx,
+                req,
+                conn_token,
+                marks,
+                upgrade_rx,
+                maybe_request_idle_timeout,
+            )
+            .await?,
+        )
+    };
 
     tokio::task::yield_now().await;
 
@@ -165,12 +344,22 @@ async fn handle_request(
     };
 
     let res = tokio::select! {
-        resp = request_sender.send_request(req) => resp,
+        resp = res_fut => resp,
         _ = maybe_cancel_fut => {
             Ok(emit_status_code(http_v02::StatusCode::GATEWAY_TIMEOUT, None, false))
         }
+
+        _ = wait_for_cancellation(req_cancel) => {
+            // NOTE: Dropping the pending response closes the worker stream,
+            // which aborts the request in the worker.
+            debug!("client went away before the {} worker responded", worker_kind);
+            return Ok(());
+        }
     };
 
+    // NOTE: The worker has taken the request by the time it responds.
+    drop(mux_request);
+
     let Ok(res) = res else {
         drop(res_tx.send(res));
         return Ok(());
@@ -189,6 +378,18 @@ async fn handle_request(
         }
     }
 
+    if let Some(trailers) = trailers {
+        let (parts, body) = res.into_parts();
+        let body = forward_with_trailers(
+            body,
+            trailers,
+            maybe_request_idle_timeout.map(Duration::from_millis),
+        );
+
+        drop(res_tx.send(Ok(Response::from_parts(parts, body))));
+        return Ok(());
+    }
+
     if let Some(timeout_ms) = maybe_request_idle_timeout {
         let headers = res.headers();
         let is_streamed_response = !headers.contains_key(http_v02::header::CONTENT_LENGTH);
@@ -210,10 +411,165 @@ async fn handle_request(
     Ok(())
 }
 
+/// Whether the client of `req` takes trailers in its response, as gRPC clients
+/// do.
+fn accepts_trailers(req: &Request<Body>) -> bool {
+    req.headers()
+        .get_all(header::TE)
+        .iter()
+        .filter_map(|it| it.to_str().ok())
+        .flat_map(|it| it.split(','))
+        .any(|it| it.trim().eq_ignore_ascii_case("trailers"))
+}
+
+/// Forwards `body` along with its trailers, followed by those the worker set
+/// beside the response. Like [`CancelOnWriteTimeout`], the body ends early if
+/// the worker doesn't write to it for `idle_timeout`.
+fn forward_with_trailers(
+    mut body: Body,
+    trailers: ResponseTrailers,
+    idle_timeout: Option<Duration>,
+) -> Body {
+    let (mut tx, forwarded) = Body::channel();
+
+    tokio::spawn(async move {
+        loop {
+            let chunk = match idle_timeout {
+                Some(dur) => match tokio::time::timeout(dur, body.data()).await {
+                    Ok(chunk) => chunk,
+                    Err(_) => return,
+                },
+
+                None => body.data().await,
+            };
+
+            match chunk {
+                Some(Ok(chunk)) => {
+                    if tx.send_data(chunk).await.is_err() {
+                        return;
+                    }
+                }
+
+                Some(Err(err)) => {
+                    debug!("failed to forward the response body: {}", err);
+                    tx.abort();
+                    return;
+                }
+
+                None => break,
+            }
+        }
+
+        let mut map = match body.trailers().await {
+            Ok(map) => map.unwrap_or_default(),
+            Err(err) => {
+                debug!("failed to forward the response trailers: {}", err);
+                tx.abort();
+                return;
+            }
+        };
+
+        if let Some(set) = trailers.take() {
+            map.extend(set);
+        }
+
+        if !map.is_empty() {
+            let _ = tx.send_trailers(map).await;
+        }
+    });
+
+    forwarded
+}
+
+/// Sends `req` to the worker over a stream of its own. An upgrade needs this,
+/// as the upgraded connection takes the stream over once the worker accepts it.
+async fn send_request_alone(
+    worker_kind: WorkerKind,
+    duplex_stream_tx: &mpsc::UnboundedSender<DuplexStreamEntry>,
+    mut req: Request<Body>,
+    conn_token: Option<CancellationToken>,
+    marks: RequestMarks,
+    upgrade_rx: oneshot::Receiver<(Option<String>, StatusCode)>,
+    maybe_request_idle_timeout: Option<u64>,
+) -> Result<impl Future<Output = Result<Response<Body>, hyper_v014::Error>>, Error> {
+    let (ours, theirs) = io::duplex(1024);
+    let resumable = req
+        .extensions_mut()
+        .remove::<Hibernation>()
+        .map(|it| Resumable::new(it, marks.hibernation_state.clone(), &req));
+
+    let _ = duplex_stream_tx.send((theirs, conn_token.clone(), marks));
+    let req_upgrade = get_upgrade_type(req.headers()).and_then(|it| {
+        let downstream = match req.extensions_mut().remove::<ParkedConnection>() {
+            Some(parked) => Downstream::Parked(parked),
+            None => Downstream::Upgrade(req.extensions_mut().remove::<OnUpgrade>()?),
+        };
+
+        Some((it, downstream))
+    });
+
+    // send the HTTP request to the worker over duplex stream
+    let (mut request_sender, connection) =
+        http1::Builder::new().writev(true).handshake(ours).await?;
+
+    // spawn a task to poll the connection and drive the HTTP state
+    tokio::task::spawn({
+        async move {
+            match connection.without_shutdown().await {
+                Err(e) => {
+                    error!(
+                        "error in {} worker connection: {}",
+                        worker_kind,
+                        e.message()
+                    );
+                }
+
+                Ok(parts) => {
+                    if let Some((requested, req_upgrade)) = req_upgrade {
+                        if let Ok((Some(accepted), status)) = upgrade_rx.await {
+                            if status == StatusCode::SWITCHING_PROTOCOLS && accepted == requested {
+                                tokio::spawn(relay_upgraded_request_and_response(
+                                    req_upgrade,
+                                    parts,
+                                    maybe_request_idle_timeout,
+                                    resumable,
+                                ));
+
+                                return;
+                            }
+                        };
+                    }
+
+                    if let Some(token) = conn_token {
+                        token.cancelled_owned().await;
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(request_sender.send_request(req))
+}
+
+async fn wait_for_cancellation(token: Option<CancellationToken>) {
+    match token {
+        Some(token) => token.cancelled_owned().await,
+        None => pending().await,
+    }
+}
+
+/// The client side of an upgraded connection.
+enum Downstream {
+    Upgrade(OnUpgrade),
+    /// Left behind by a hibernated worker.
+    Parked(ParkedConnection),
+}
+
 async fn relay_upgraded_request_and_response(
-    downstream: OnUpgrade,
+    downstream: Downstream,
     parts: http1::Parts<io::DuplexStream>,
     maybe_idle_timeout: Option<u64>,
+    resumable: Option<Resumable>,
 ) {
     let upstream = Upgraded2::new(parts.io, parts.read_buf);
     let mut upstream = if let Some(timeout_ms) = maybe_idle_timeout {
@@ -222,10 +578,37 @@ async fn relay_upgraded_request_and_response(
         ReadTimeoutStream::with_bypass(upstream)
     };
 
-    let mut downstream = downstream.await.expect("failed to upgrade request");
+    let mut downstream = match downstream {
+        Downstream::Upgrade(it) => it.await.expect("failed to upgrade request"),
+        Downstream::Parked(parked) => {
+            let (io, read_buf) = parked.into_parts();
+
+            if let Err(err) = upstream.write_all(&read_buf).await {
+                debug!("failed to pick up a parked connection: {}", err);
+                return;
+            }
+
+            io
+        }
+    };
+
+    let result = match resumable.as_ref() {
+        Some(it) => {
+            relay_until_hibernated(&mut upstream, &mut downstream, &it.hibernation.worker).await
+        }
+
+        None => copy_bidirectional(&mut upstream, &mut downstream)
+            .await
+            .map(|_| false),
+    };
+
+    match result {
+        Ok(false) => {}
+        Ok(true) => {
+            // NOTE: Only a relay with a `Resumable` stops for hibernation.
+            resumable.unwrap().park(downstream).await;
+        }
 
-    match copy_bidirectional(&mut upstream, &mut downstream).await {
-        Ok(_) => {}
         Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::BrokenPipe) => {}
         Err(err) if matches!(err.kind(), ErrorKind::UnexpectedEof) => {
             let Ok(_) = downstream.downcast::<timeout::Stream<TlsStream<TcpStream>>>() else {
@@ -258,8 +641,10 @@ pub fn create_supervisor(
     cancel: Option<CancellationToken>,
     timing: Option<Timing>,
     termination_token: Option<TerminationToken>,
+    exit: WorkerExit,
 ) -> Result<(Option<CPUTimer>, CancellationToken), Error> {
     let (memory_limit_tx, memory_limit_rx) = mpsc::unbounded_channel();
+    let (turn_overrun_tx, turn_overrun_rx) = mpsc::unbounded_channel();
     let (waker, thread_safe_handle) = {
         let js_runtime = &mut worker_runtime.js_runtime;
         (
@@ -271,8 +656,12 @@ pub fn create_supervisor(
     // we assert supervisor is only run for user workers
     let conf = worker_runtime.conf.as_user_worker().unwrap().clone();
     let mem_check_state = worker_runtime.mem_check_state();
+    let background_tasks = worker_runtime.background_tasks();
+    let execution_clock = worker_runtime.execution_clock();
     let termination_request_token = worker_runtime.termination_request_token.clone();
 
+    worker_runtime.turn_overrun_tx = Some(turn_overrun_tx);
+
     let giveup_process_requests_token = cancel.clone();
     let supervise_cancel_token = CancellationToken::new();
     let tokens = supervisor::Tokens {
@@ -310,15 +699,49 @@ pub fn create_supervisor(
         }
     });
 
+    // NOTE: Once the heap shrinks back below the initial limit, V8 restores
+    // it in place of the allowance given below.
+    worker_runtime
+        .js_runtime
+        .v8_isolate()
+        .automatically_restore_initial_heap_limit(0.9);
+
     worker_runtime.js_runtime.add_near_heap_limit_callback({
         let send_fn = send_memory_limit_fn;
-        move |current, _| {
-            send_fn("v8");
+        let multiplier = conf.near_heap_limit_multiplier() as usize;
+        let thread_safe_handle = thread_safe_handle.clone();
+        let collecting = Arc::new(AtomicFlag::default());
+
+        move |current, initial| {
+            // NOTE: The callback runs in the middle of a GC, so the full GC
+            // deciding whether the isolate has to go can only run from an
+            // interrupt afterwards. If the heap grows near the limit again
+            // before that, there is nothing left to collect.
+            if !collecting.raise() {
+                send_fn("v8");
+            } else {
+                let data_ptr_mut = Box::into_raw(Box::new(supervisor::NearHeapLimitData {
+                    heap_limit: initial,
+                    collecting: collecting.clone(),
+                    on_exceeded: Box::new({
+                        let send_fn = send_fn.clone();
+                        move || send_fn("v8")
+                    }),
+                }));
+
+                if !thread_safe_handle.request_interrupt(
+                    supervisor::handle_near_heap_limit_interrupt,
+                    data_ptr_mut as *mut std::ffi::c_void,
+                ) {
+                    drop(unsafe { Box::from_raw(data_ptr_mut) });
+                    send_fn("v8");
+                }
+            }
 
             // give an allowance on current limit (until the isolate is
-            // terminated) we do this so that oom won't end up killing the
-            // edge-runtime process
-            current * (conf.low_memory_multiplier as usize)
+            // terminated or the gc frees enough) we do this so that oom won't
+            // end up killing the edge-runtime process
+            current * multiplier
         }
     });
 
@@ -348,10 +771,14 @@ pub fn create_supervisor(
                 supervisor_policy,
                 timing,
                 memory_limit_rx,
+                turn_overrun_rx,
                 pool_msg_tx,
                 isolate_memory_usage_tx,
                 thread_safe_handle,
                 waker: waker.clone(),
+                mem_check_state: mem_check_state.clone(),
+                background_tasks,
+                execution_clock,
                 tokens,
             };
 
@@ -365,6 +792,10 @@ pub fn create_supervisor(
                 }
             };
 
+            // NOTE: The reason must be in place before the requests awaiting
+            // the worker are cancelled below, so that they can tell why.
+            exit.set(WorkerExitStatus::Shutdown(reason)).await;
+
             // NOTE: Sending a signal to the pooler that it is the user worker going
             // disposed down and will not accept awaiting subsequent requests, so
             // they must be re-polled again.
@@ -567,6 +998,8 @@ pub struct WorkerCtx {
     pub metric: MetricSource,
     pub msg_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
     pub exit: WorkerExit,
+    /// Cancelled once the worker has terminated, for whatever reason.
+    pub terminated: CancellationToken,
 }
 
 pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
@@ -597,7 +1030,7 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
     let downcast_reference = worker.as_any().downcast_ref::<Worker>();
 
     if let Some(worker_struct_ref) = downcast_reference {
-        worker_struct_ref.start(
+        let worker_handle = worker_struct_ref.start(
             worker_init_opts,
             (duplex_stream_tx.clone(), duplex_stream_rx),
             worker_boot_result_tx,
@@ -606,23 +1039,41 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
             inspector,
         );
 
+        let terminated = CancellationToken::new();
+
+        drop(tokio::spawn({
+            let exit = exit.clone();
+            let terminated = terminated.clone();
+
+            async move {
+                if let Err(err) = worker_handle.await.and_then(std::convert::identity) {
+                    error!("{} worker task failed: {}", worker_kind, err);
+                    exit.set(WorkerExitStatus::WithUncaughtException(
+                        UncaughtExceptionEvent {
+                            exception: err.to_string(),
+                            cpu_time_used: 0,
+                        },
+                    ))
+                    .await;
+                }
+
+                terminated.cancel();
+            }
+        }));
+
         // create an async task waiting for requests for worker
         let (worker_req_tx, mut worker_req_rx) = mpsc::unbounded_channel::<WorkerRequestMsg>();
 
         let worker_req_handle: tokio::task::JoinHandle<Result<(), Error>> = tokio::task::spawn({
-            let stream_tx = duplex_stream_tx;
+            let conn = Arc::new(WorkerConnection::new(duplex_stream_tx));
             async move {
                 while let Some(msg) = worker_req_rx.recv().await {
                     tokio::task::spawn({
-                        let stream_tx_inner = stream_tx.clone();
+                        let conn = conn.clone();
                         async move {
-                            if let Err(err) = handle_request(
-                                worker_kind,
-                                stream_tx_inner,
-                                msg,
-                                maybe_request_idle_timeout,
-                            )
-                            .await
+                            if let Err(err) =
+                                handle_request(worker_kind, conn, msg, maybe_request_idle_timeout)
+                                    .await
                             {
                                 error!("worker failed to handle request: {:?}", err);
                             }
@@ -666,6 +1117,7 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
                     metric,
                     msg_tx: worker_req_tx,
                     exit,
+                    terminated,
                 })
             }
         }
@@ -694,10 +1146,7 @@ pub async fn send_user_worker_request(
     // wait for the response back from the worker
     let res = tokio::select! {
         () = cancel.cancelled() => {
-            bail!(exit
-                .error()
-                .await
-                .unwrap_or(anyhow!(WorkerError::RequestCancelledBySupervisor)))
+            bail!(exit.cancelled_error().await)
         }
 
         res = res_rx => res,
@@ -719,6 +1168,40 @@ pub async fn send_user_worker_request(
     }
 }
 
+/// How the main worker is restarted after it terminates unexpectedly, e.g.
+/// because of an uncaught exception or a panic of its thread.
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Restarts attempted in a row before giving up. The count starts over
+    /// once a restarted worker stays up for a while.
+    pub max_restarts: u32,
+    /// Delay before the first restart attempt, doubled on each further one.
+    pub initial_backoff_ms: u64,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff_ms: 100,
+        }
+    }
+}
+
+impl RestartPolicy {
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    /// A worker that stayed up for this long is considered healthy again.
+    const RESET_AFTER: Duration = Duration::from_secs(60);
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(16);
+
+        Duration::from_millis(self.initial_backoff_ms.saturating_mul(1 << exp))
+            .min(Self::MAX_BACKOFF)
+    }
+}
+
 // Todo: Fix
 #[allow(clippy::too_many_arguments)]
 pub async fn create_main_worker(
@@ -731,42 +1214,148 @@ pub async fn create_main_worker(
     termination_token: Option<TerminationToken>,
     inspector: Option<Inspector>,
     jsx: Option<JsxImportSourceConfig>,
+    restart_policy: RestartPolicy,
+    events_msg_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
 ) -> Result<mpsc::UnboundedSender<WorkerRequestMsg>, Error> {
-    let mut service_path = main_worker_path.clone();
-    let mut maybe_eszip = None;
-    if let Some(ext) = main_worker_path.extension() {
-        if ext == "eszip" {
-            service_path = main_worker_path.parent().unwrap().to_path_buf();
-            maybe_eszip = Some(EszipPayloadKind::VecKind(std::fs::read(main_worker_path)?));
+    let event_metadata = EventMetadata {
+        service_path: Some(main_worker_path.to_string_lossy().into_owned()),
+        execution_id: None,
+    };
+
+    let boot = {
+        let termination_token = termination_token.clone();
+
+        move || {
+            let main_worker_path = main_worker_path.clone();
+            let import_map_path = import_map_path.clone();
+            let runtime_opts = runtime_opts.clone();
+            let maybe_entrypoint = maybe_entrypoint.clone();
+            let termination_token = termination_token.clone();
+            let inspector = inspector.clone();
+            let jsx = jsx.clone();
+
+            async move {
+                let mut service_path = main_worker_path.clone();
+                let mut maybe_eszip = None;
+                if let Some(ext) = main_worker_path.extension() {
+                    if ext == "eszip" {
+                        service_path = main_worker_path.parent().unwrap().to_path_buf();
+                        maybe_eszip =
+                            Some(EszipPayloadKind::VecKind(std::fs::read(main_worker_path)?));
+                    }
+                }
+
+                create_worker(
+                    (
+                        WorkerContextInitOpts {
+                            service_path,
+                            import_map_path,
+                            no_module_cache,
+                            events_rx: None,
+                            timing: None,
+                            maybe_eszip,
+                            maybe_entrypoint,
+                            maybe_decorator,
+                            maybe_module_code: None,
+                            conf: WorkerRuntimeOpts::MainWorker(runtime_opts),
+                            env_vars: std::env::vars().collect(),
+                            static_patterns: vec![],
+                            maybe_jsx_import_source_config: jsx,
+                        },
+                        termination_token,
+                    ),
+                    inspector,
+                    None,
+                )
+                .await
+                .map_err(|err| anyhow!("main worker boot error: {}", err))
+            }
         }
-    }
+    };
 
-    let ctx = create_worker(
-        (
-            WorkerContextInitOpts {
-                service_path,
-                import_map_path,
-                no_module_cache,
-                events_rx: None,
-                timing: None,
-                maybe_eszip,
-                maybe_entrypoint,
-                maybe_decorator,
-                maybe_module_code: None,
-                conf: WorkerRuntimeOpts::MainWorker(runtime_opts),
-                env_vars: std::env::vars().collect(),
-                static_patterns: vec![],
-                maybe_jsx_import_source_config: jsx,
-            },
-            termination_token,
-        ),
-        inspector,
-        None,
-    )
-    .await
-    .map_err(|err| anyhow!("main worker boot error: {}", err))?;
+    let mut ctx = boot().await?;
+    let (req_tx, mut req_rx) = mpsc::unbounded_channel::<WorkerRequestMsg>();
+
+    // NOTE: Requests go through a channel of our own, so that they reach the
+    // restarted worker. They are queued while it boots.
+    drop(tokio::spawn(async move {
+        let mut booted_at = Instant::now();
+        let mut attempt = 0;
+
+        'outer: loop {
+            tokio::select! {
+                msg = req_rx.recv() => match msg {
+                    Some(msg) => {
+                        if ctx.msg_tx.send(msg).is_err() {
+                            error!("main worker is not available");
+                        }
+                    }
+
+                    None => break,
+                },
 
-    Ok(ctx.msg_tx)
+                _ = ctx.terminated.cancelled() => {
+                    if termination_token.as_ref().is_some_and(|it| it.inbound.is_cancelled()) {
+                        break;
+                    }
+
+                    let mut reason = ctx
+                        .exit
+                        .error()
+                        .await
+                        .map(|it| it.to_string())
+                        .unwrap_or_else(|| "main worker terminated".to_string());
+
+                    if booted_at.elapsed() >= RestartPolicy::RESET_AFTER {
+                        attempt = 0;
+                    }
+
+                    loop {
+                        if attempt >= restart_policy.max_restarts {
+                            error!(
+                                "main worker failed {} times in a row, giving up: {}",
+                                attempt, reason
+                            );
+                            break 'outer;
+                        }
+
+                        attempt += 1;
+
+                        let backoff = restart_policy.backoff(attempt);
+
+                        error!(
+                            "restarting main worker in {:?} (attempt {}): {}",
+                            backoff, attempt, reason
+                        );
+
+                        send_event_if_event_worker_available(
+                            events_msg_tx.clone(),
+                            WorkerEvents::Restart(RestartEvent {
+                                attempt,
+                                backoff_ms: backoff.as_millis() as u64,
+                                reason: reason.clone(),
+                            }),
+                            event_metadata.clone(),
+                        );
+
+                        sleep(backoff).await;
+
+                        match boot().await {
+                            Ok(new_ctx) => {
+                                ctx = new_ctx;
+                                booted_at = Instant::now();
+                                continue 'outer;
+                            }
+
+                            Err(err) => reason = err.to_string(),
+                        }
+                    }
+                }
+            }
+        }
+    }));
+
+    Ok(req_tx)
 }
 
 pub async fn create_events_worker(
@@ -898,6 +1487,44 @@ pub async fn create_user_worker_pool(
                                 worker_pool.idle(&key);
                             }
 
+                            Some(UserWorkerMsgs::ServiceChanged(path)) => {
+                                worker_pool.recycle_changed_services(&path);
+                            }
+
+                            Some(UserWorkerMsgs::WatchConfigs(tx)) => {
+                                worker_pool.watch_configs(tx);
+                            }
+
+                            Some(UserWorkerMsgs::Drain(service_path, tx)) => {
+                                let retired = worker_pool.retire_services(&HashSet::from([service_path]));
+                                let _ = tx.send(retired);
+                            }
+
+                            Some(UserWorkerMsgs::Snapshot(tx)) => {
+                                let _ = tx.send(worker_pool.snapshot());
+                            }
+
+                            Some(UserWorkerMsgs::Terminate(target, tx)) => {
+                                let _ = tx.send(worker_pool.terminate(&target));
+                            }
+
+                            Some(UserWorkerMsgs::MemoryPressure(pressure)) => {
+                                worker_pool.relieve_memory_pressure(pressure);
+                            }
+
+                            Some(UserWorkerMsgs::Reclaim(service_path)) => {
+                                worker_pool.reclaim_slot(&service_path);
+                            }
+
+                            Some(UserWorkerMsgs::Resume(service_path, req, res_tx)) => {
+                                worker_pool.resume(
+                                    &service_path,
+                                    req,
+                                    res_tx,
+                                    termination_token.as_ref().map(|it| it.child_token()),
+                                );
+                            }
+
                             Some(UserWorkerMsgs::Shutdown(key)) => {
                                 worker_pool.shutdown(&key);
 
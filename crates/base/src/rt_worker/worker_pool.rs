@@ -1,36 +1,56 @@
+use crate::deno_runtime::is_service_v8_flag;
 use crate::inspector_server::Inspector;
+use crate::rt_worker::circuit_breaker::{CircuitBreaker, CircuitBreakerPolicy};
+use crate::rt_worker::fair_scheduler::FairScheduler;
+use crate::rt_worker::hibernation::Hibernation;
+use crate::rt_worker::memory_pressure::{self, MemoryPressurePolicy};
+use crate::rt_worker::request_capture::{RequestCapture, RequestCapturePolicy};
+use crate::rt_worker::service_watcher::ServiceWatcher;
+use crate::rt_worker::tenant_quota::{tenant_of, TenantQuotaPolicy, TenantQuotas, TenantUsage};
 use crate::rt_worker::worker_ctx::{create_worker, send_user_worker_request};
 use crate::server::ServerFlags;
 use anyhow::{anyhow, bail, Context, Error};
 use enum_as_inner::EnumAsInner;
-use event_worker::events::WorkerEventWithMetadata;
+use event_worker::events::{
+    EventMetadata, MemoryPressure, MemoryPressureEvictionEvent, PoolActivity, PoolEvent,
+    WorkerEventWithMetadata, WorkerEvents,
+};
 use http_v02::Request;
 use hyper_v014::Body;
-use log::error;
+use log::{error, info, warn};
 use sb_core::util::sync::AtomicFlag;
 use sb_core::SharedMetricSource;
 use sb_workers::context::{
-    CreateUserWorkerResult, SendRequestResult, Timing, TimingStatus, UserWorkerMsgs,
-    UserWorkerProfile, WorkerContextInitOpts, WorkerRuntimeOpts,
+    CreateUserWorkerResult, SendRequestResult, TerminateTarget, Timing, TimingStatus,
+    UserWorkerLimits, UserWorkerMsgs, UserWorkerProfile, UserWorkerRuntimeOpts, UserWorkerSnapshot,
+    UserWorkerState, UserWorkerStats, WorkerContextInitOpts, WorkerHibernation, WorkerRuntimeOpts,
 };
 use sb_workers::errors::WorkerError;
+use sb_workers::retirement::{RetirementBudgets, RetirementPolicy};
 use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot::Sender;
-use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError};
+use tokio::sync::{mpsc, oneshot, Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use super::worker_ctx::TerminationToken;
 
+/// Decides how the booted workers of a service are reused.
 #[derive(Debug, Clone, Copy, EnumAsInner)]
 pub enum SupervisorPolicy {
+    /// A warm worker serves any number of requests concurrently until it's
+    /// retired, and requests are spread over the warm workers round-robin.
     PerWorker,
+    /// A warm worker serves one request at a time; a new worker is booted only
+    /// when every warm one is busy. With `oneshot`, every request gets a cold
+    /// worker of its own.
     PerRequest { oneshot: bool },
 }
 
@@ -68,6 +88,27 @@ pub struct WorkerPoolPolicy {
     supervisor_policy: SupervisorPolicy,
     max_parallelism: usize,
     request_wait_timeout_ms: u64,
+    /// Maximum number of requests per service waiting for a worker to become
+    /// available. Requests beyond it are shed immediately.
+    max_queue_depth: Option<usize>,
+    /// Maximum number of user workers across all services. Once reached, the
+    /// services waiting for a worker take turns.
+    max_user_workers: Option<usize>,
+    request_capture: Option<RequestCapturePolicy>,
+    circuit_breaker: Option<CircuitBreakerPolicy>,
+    tenant_quota: Option<TenantQuotaPolicy>,
+    memory_pressure: Option<MemoryPressurePolicy>,
+    watch_services: bool,
+    /// Boot every user worker in a child process of its own.
+    process_isolation: bool,
+    /// Applied to every user worker whose own budgets are not set.
+    retirement_budgets: RetirementBudgets,
+    /// Consulted for every user worker in addition to its own policies.
+    retirement_policies: Vec<Arc<dyn RetirementPolicy>>,
+    limit_ceilings: UserWorkerLimitCeilings,
+    /// Services whose workers the inspector is attached to. Empty means all of
+    /// them.
+    inspect_services: Vec<String>,
 }
 
 impl Default for WorkerPoolPolicy {
@@ -81,6 +122,18 @@ impl Default for WorkerPoolPolicy {
             supervisor_policy: SupervisorPolicy::default(),
             max_parallelism: available_parallelism,
             request_wait_timeout_ms: 10000,
+            max_queue_depth: None,
+            max_user_workers: None,
+            request_capture: None,
+            circuit_breaker: None,
+            tenant_quota: None,
+            memory_pressure: None,
+            watch_services: false,
+            process_isolation: false,
+            retirement_budgets: RetirementBudgets::default(),
+            retirement_policies: vec![],
+            limit_ceilings: UserWorkerLimitCeilings::default(),
+            inspect_services: vec![],
         }
     }
 }
@@ -99,8 +152,108 @@ impl WorkerPoolPolicy {
             request_wait_timeout_ms: server_flags
                 .request_wait_timeout_ms
                 .unwrap_or(default.request_wait_timeout_ms),
+            max_queue_depth: server_flags.request_queue_depth,
+            max_user_workers: default.max_user_workers,
+            request_capture: default.request_capture,
+            circuit_breaker: default.circuit_breaker,
+            tenant_quota: default.tenant_quota,
+            memory_pressure: default.memory_pressure,
+            watch_services: server_flags.watch,
+            process_isolation: server_flags.process_isolation,
+            retirement_budgets: default.retirement_budgets,
+            retirement_policies: default.retirement_policies,
+            limit_ceilings: UserWorkerLimitCeilings {
+                memory_limit_mb: server_flags.max_worker_memory_limit_mb,
+                worker_timeout_ms: server_flags.max_worker_timeout_ms,
+                cpu_time_ms: server_flags.max_worker_cpu_time_ms,
+            },
+            inspect_services: default.inspect_services,
         }
     }
+
+    pub fn with_request_capture(mut self, policy: RequestCapturePolicy) -> Self {
+        self.request_capture = Some(policy);
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, policy: CircuitBreakerPolicy) -> Self {
+        self.circuit_breaker = Some(policy);
+        self
+    }
+
+    pub fn with_max_user_workers(mut self, max_user_workers: usize) -> Self {
+        self.max_user_workers = Some(max_user_workers).filter(|it| *it > 0);
+        self
+    }
+
+    pub fn with_tenant_quota(mut self, policy: TenantQuotaPolicy) -> Self {
+        self.tenant_quota = Some(policy);
+        self
+    }
+
+    pub fn with_memory_pressure(mut self, policy: MemoryPressurePolicy) -> Self {
+        self.memory_pressure = Some(policy);
+        self
+    }
+
+    pub fn with_retirement_budgets(mut self, budgets: RetirementBudgets) -> Self {
+        self.retirement_budgets = budgets;
+        self
+    }
+
+    pub fn with_retirement_policy(mut self, policy: Arc<dyn RetirementPolicy>) -> Self {
+        self.retirement_policies.push(policy);
+        self
+    }
+
+    pub fn with_inspect_services(mut self, services: Vec<String>) -> Self {
+        self.inspect_services = services;
+        self
+    }
+
+    /// Returns whether the inspector is attached to the workers of
+    /// `service_path`. A service is chosen by its path, or by its trailing
+    /// components such as its directory name.
+    fn should_inspect(&self, service_path: &str) -> bool {
+        self.inspect_services.is_empty()
+            || self
+                .inspect_services
+                .iter()
+                .any(|it| Path::new(service_path).ends_with(it.trim_end_matches('/')))
+    }
+}
+
+/// Server-wide ceilings of the limits of user workers. The options a worker is
+/// created with can only tighten them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserWorkerLimitCeilings {
+    pub memory_limit_mb: Option<u64>,
+    pub worker_timeout_ms: Option<u64>,
+    pub cpu_time_ms: Option<u64>,
+}
+
+impl UserWorkerLimitCeilings {
+    /// Clamps the limits of `opts`, returning whether any of them was lowered.
+    pub fn apply(&self, opts: &mut UserWorkerRuntimeOpts) -> bool {
+        // NOTE: A limit of zero disables it, so it's always above the ceiling.
+        fn clamp(value: &mut u64, ceiling: Option<u64>) -> bool {
+            match ceiling {
+                Some(ceiling) if *value == 0 || *value > ceiling => {
+                    *value = ceiling;
+                    true
+                }
+
+                _ => false,
+            }
+        }
+
+        let mut clamped = clamp(&mut opts.memory_limit_mb, self.memory_limit_mb);
+
+        clamped |= clamp(&mut opts.worker_timeout_ms, self.worker_timeout_ms);
+        clamped |= clamp(&mut opts.cpu_time_soft_limit_ms, self.cpu_time_ms);
+        clamped |= clamp(&mut opts.cpu_time_hard_limit_ms, self.cpu_time_ms);
+        clamped
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -132,6 +285,9 @@ pub struct ActiveWorkerRegistry {
     next: Option<usize>,
     notify_pair: (flume::Sender<Option<Uuid>>, flume::Receiver<Option<Uuid>>),
     sem: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+    booting: Arc<AtomicUsize>,
+    max_concurrent_requests: Option<usize>,
 }
 
 impl ActiveWorkerRegistry {
@@ -141,6 +297,9 @@ impl ActiveWorkerRegistry {
             next: Option::default(),
             notify_pair: flume::unbounded(),
             sem: Arc::new(Semaphore::const_new(max_parallelism)),
+            queued: Arc::default(),
+            booting: Arc::default(),
+            max_concurrent_requests: None,
         }
     }
 
@@ -197,6 +356,10 @@ impl ActiveWorkerRegistry {
     }
 }
 
+/// Keeps the booted workers of each service warm and dispatches requests to
+/// them, booting a new one only when none can take the request (see
+/// [`SupervisorPolicy`]) and the service hasn't reached `max_parallelism`.
+//
 // every new worker gets a new UUID (can reuse execution_id)
 // user_workers - maintain a hashmap of (uuid - workerProfile (include service path))
 // active_workers - hashmap of (service_path - uuid)
@@ -216,6 +379,22 @@ pub struct WorkerPool {
 
     // TODO: refactor this out of worker pool
     pub worker_event_sender: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+
+    service_watcher: Option<ServiceWatcher>,
+    circuit_breaker: Option<CircuitBreaker>,
+    tenant_quotas: Option<TenantQuotas>,
+    scheduler: Option<FairScheduler>,
+    /// How the workers of each service that can hibernate are woken up, see
+    /// [`Self::resume`].
+    wake_opts: HashMap<String, WakeOpts>,
+}
+
+/// Options the workers of a service that can hibernate are woken up with, kept
+/// while the service has workers or connections to pick back up.
+struct WakeOpts {
+    opts: WorkerContextInitOpts,
+    /// See [`WorkerHibernation::service`].
+    service: Arc<()>,
 }
 
 impl WorkerPool {
@@ -227,10 +406,33 @@ impl WorkerPool {
         inspector: Option<Inspector>,
         request_idle_timeout: Option<u64>,
     ) -> Self {
+        let service_watcher = if policy.watch_services {
+            ServiceWatcher::new(worker_pool_msgs_tx.clone())
+                .inspect_err(|err| error!("failed to create service watcher: {}", err))
+                .ok()
+        } else {
+            None
+        };
+
+        let circuit_breaker = policy.circuit_breaker.clone().map(CircuitBreaker::new);
+        let tenant_quotas = policy.tenant_quota.clone().map(TenantQuotas::new);
+        let scheduler = policy
+            .max_user_workers
+            .map(|it| FairScheduler::new(it, worker_pool_msgs_tx.clone()));
+
+        if let Some(pressure_policy) = policy.memory_pressure.clone() {
+            memory_pressure::spawn_monitor(pressure_policy, worker_pool_msgs_tx.clone());
+        }
+
         Self {
             policy,
             metric_src,
             worker_event_sender,
+            service_watcher,
+            circuit_breaker,
+            tenant_quotas,
+            scheduler,
+            wake_opts: HashMap::new(),
             user_workers: HashMap::new(),
             active_workers: HashMap::new(),
             maybe_inspector: inspector,
@@ -252,7 +454,11 @@ impl WorkerPool {
             .to_string();
 
         let is_oneshot_policy = self.policy.supervisor_policy.is_oneshot();
-        let inspector = self.maybe_inspector.clone();
+        let inspector = self
+            .maybe_inspector
+            .clone()
+            .filter(|_| self.policy.should_inspect(&service_path));
+
         let request_idle_timeout = self.maybe_request_idle_timeout;
 
         let force_create = worker_options
@@ -260,8 +466,90 @@ impl WorkerPool {
             .as_user_worker()
             .map_or(false, |it| !is_oneshot_policy && it.force_create);
 
+        let (max_workers, max_concurrent_requests) = worker_options
+            .conf
+            .as_user_worker()
+            .map(|it| (it.max_workers, it.max_concurrent_requests))
+            .unwrap_or_default();
+
+        // NOTE: The worker cap of a service is fixed by the first worker
+        // created for it, as its semaphore can't be resized; the request cap
+        // follows the latest options.
+        let registry = self
+            .active_workers
+            .entry(service_path.clone())
+            .or_insert_with(|| {
+                ActiveWorkerRegistry::new(
+                    max_workers
+                        .map(|it| it.clamp(1, self.policy.max_parallelism))
+                        .unwrap_or(self.policy.max_parallelism),
+                )
+            });
+
+        registry.max_concurrent_requests = max_concurrent_requests.filter(|it| *it > 0);
+
+        let tenant = tenant_of(
+            &service_path,
+            worker_options
+                .conf
+                .as_user_worker()
+                .and_then(|it| it.tenant.as_deref()),
+        );
+
+        if let Some(conf) = worker_options.conf.as_user_worker_mut() {
+            conf.tenant = Some(tenant.clone());
+        }
+
+        if worker_options
+            .conf
+            .as_user_worker()
+            .is_some_and(|it| it.hibernate_after_ms > 0)
+        {
+            match wake_init_opts(&worker_options).filter(|_| !self.policy.process_isolation) {
+                Some(opts) => {
+                    let wake_opts = self
+                        .wake_opts
+                        .entry(service_path.clone())
+                        .and_modify(|it| it.opts = opts.clone())
+                        .or_insert_with(|| WakeOpts {
+                            opts,
+                            service: Arc::default(),
+                        });
+
+                    if let Some(conf) = worker_options.conf.as_user_worker_mut() {
+                        conf.hibernation = Some(WorkerHibernation {
+                            service: wake_opts.service.clone(),
+                            ..Default::default()
+                        });
+                    }
+                }
+
+                None => {
+                    warn!(
+                        "workers of {} can't hibernate: they must run in-process, booted from a service path",
+                        service_path
+                    );
+
+                    if let Some(conf) = worker_options.conf.as_user_worker_mut() {
+                        conf.hibernate_after_ms = 0;
+                    }
+                }
+            }
+        }
+
+        if self.tenant_quota_exceeded(&tenant, false, &tx) {
+            return;
+        }
+
         if let Some(ref active_worker_uuid) = self.maybe_active_worker(&service_path, force_create)
         {
+            self.send_pool_event(
+                &service_path,
+                *active_worker_uuid,
+                PoolActivity::WarmHit,
+                Duration::ZERO,
+            );
+
             if tx
                 .send(Ok(CreateUserWorkerResult {
                     key: *active_worker_uuid,
@@ -273,6 +561,26 @@ impl WorkerPool {
             return;
         }
 
+        if let Some(retry_after) = self
+            .circuit_breaker
+            .as_ref()
+            .and_then(|it| it.check(&service_path))
+        {
+            if tx
+                .send(Err(anyhow!(WorkerError::CircuitOpen {
+                    retry_after_secs: retry_after.as_secs() + 1
+                })))
+                .is_err()
+            {
+                error!("main worker receiver dropped")
+            }
+            return;
+        }
+
+        if self.tenant_quota_exceeded(&tenant, true, &tx) {
+            return;
+        }
+
         enum FlowAfterFence {
             Stop,
             Resend(Sender<Result<CreateUserWorkerResult, Error>>),
@@ -289,9 +597,13 @@ impl WorkerPool {
                 .or_insert_with(|| ActiveWorkerRegistry::new(self.policy.max_parallelism));
 
             let sem = registry.sem.clone();
+            let queued = registry.queued.clone();
             let (_, notify_rx) = registry.notify_pair.clone();
             let wait_timeout =
                 tokio::time::sleep(Duration::from_millis(self.policy.request_wait_timeout_ms));
+            let max_queue_depth = self.policy.max_queue_depth;
+            let metric_src = self.metric_src.clone();
+            let service_path = service_path.clone();
 
             async move {
                 use FlowAfterFence::*;
@@ -308,6 +620,28 @@ impl WorkerPool {
                     _ => {}
                 }
 
+                if max_queue_depth.is_some_and(|it| queued.load(Ordering::Acquire) >= it) {
+                    metric_src.incl_shed_requests();
+
+                    if tx
+                        .send(Err(anyhow!(WorkerError::RequestQueueFull)))
+                        .is_err()
+                    {
+                        error!("main worker receiver dropped");
+                    }
+                    return Stop;
+                }
+
+                queued.fetch_add(1, Ordering::AcqRel);
+                metric_src.incl_pending_requests();
+                metric_src.incl_queued_requests(&service_path);
+
+                let metric_src = scopeguard::guard(metric_src, |it| {
+                    queued.fetch_sub(1, Ordering::AcqRel);
+                    it.decl_pending_requests();
+                    it.decl_queued_requests(&service_path);
+                });
+
                 tokio::pin!(wait_timeout);
                 loop {
                     tokio::select! {
@@ -330,6 +664,8 @@ impl WorkerPool {
                         },
 
                         () = &mut wait_timeout => {
+                            metric_src.incl_shed_requests();
+
                             if tx.send(Err(anyhow!("worker did not respond in time"))).is_err() {
                                 error!("main worker receiver dropped");
                             }
@@ -343,8 +679,18 @@ impl WorkerPool {
         let worker_pool_msgs_tx = self.worker_pool_msgs_tx.clone();
         let events_msg_tx = self.worker_event_sender.clone();
         let supervisor_policy = self.policy.supervisor_policy;
+        let process_isolation = self.policy.process_isolation;
+        let retirement_budgets = self.policy.retirement_budgets;
+        let retirement_policies = self.policy.retirement_policies.clone();
+        let limit_ceilings = self.policy.limit_ceilings;
+        let circuit_breaker = self.circuit_breaker.clone();
+        let scheduler = self.scheduler.clone();
+        let metric_src = self.metric_src.clone();
+        let wait_timeout = Duration::from_millis(self.policy.request_wait_timeout_ms);
+        let booting = self.active_workers[&service_path].booting.clone();
 
         drop(tokio::spawn(async move {
+            let queued_at = Instant::now();
             let (permit, tx) = match wait_fence_fut.await {
                 FlowAfterFence::Stop => return,
                 FlowAfterFence::Resend(tx) => {
@@ -392,53 +738,109 @@ impl WorkerPool {
                 FlowAfterFence::Create(permit, tx) => (permit, tx),
             };
 
-            let Ok(mut user_worker_rt_opts) = worker_options.conf.into_user_worker() else {
-                return;
-            };
+            let slot = match scheduler.as_ref() {
+                Some(scheduler) => {
+                    let remaining = wait_timeout.saturating_sub(queued_at.elapsed());
+                    let slot = tokio::select! {
+                        slot = scheduler.acquire(&service_path) => slot,
+                        () = tokio::time::sleep(remaining) => None,
+                    };
+
+                    if slot.is_none() {
+                        metric_src.incl_shed_requests();
 
-            let uuid = uuid::Uuid::new_v4();
-            let cancel = CancellationToken::new();
-            let (req_start_timing_tx, req_start_timing_rx) =
-                mpsc::unbounded_channel::<Arc<Notify>>();
+                        if tx
+                            .send(Err(anyhow!("worker did not respond in time")))
+                            .is_err()
+                        {
+                            error!("main worker receiver dropped");
+                        }
+                        return;
+                    }
 
-            let status = TimingStatus {
-                demand: Arc::new(AtomicUsize::new(0)),
-                is_retired: Arc::new(AtomicFlag::default()),
+                    slot
+                }
+
+                None => None,
             };
 
-            let (req_end_timing_tx, req_end_timing_rx) = mpsc::unbounded_channel::<()>();
+            let queue_wait = queued_at.elapsed();
 
-            user_worker_rt_opts.service_path = Some(service_path.clone());
-            user_worker_rt_opts.key = Some(uuid);
+            if let Some(conf) = worker_options.conf.as_user_worker_mut() {
+                if conf.retirement_budgets.is_empty() {
+                    conf.retirement_budgets = retirement_budgets;
+                }
 
-            user_worker_rt_opts.pool_msg_tx = Some(worker_pool_msgs_tx.clone());
-            user_worker_rt_opts.events_msg_tx = events_msg_tx;
-            user_worker_rt_opts.cancel = Some(cancel.clone());
+                conf.retirement_policies.extend(retirement_policies);
 
-            worker_options.timing = Some(Timing {
-                status: status.clone(),
-                req: (req_start_timing_rx, req_end_timing_rx),
+                if limit_ceilings.apply(conf) {
+                    warn!(
+                        "limits of {} were lowered to the server ceilings",
+                        service_path
+                    );
+                }
+            }
+
+            booting.fetch_add(1, Ordering::AcqRel);
+
+            let booting = scopeguard::guard(booting, |it| {
+                it.fetch_sub(1, Ordering::AcqRel);
             });
 
-            worker_options.conf = WorkerRuntimeOpts::UserWorker(user_worker_rt_opts);
+            // NOTE: A termination token can't be reused once the first boot
+            // attempt has cancelled it.
+            let maybe_retry_opts = termination_token
+                .is_none()
+                .then(|| retry_init_opts(&worker_options))
+                .flatten();
+
+            let boot = |worker_options| {
+                boot_user_worker(
+                    worker_options,
+                    service_path.clone(),
+                    worker_pool_msgs_tx.clone(),
+                    events_msg_tx.clone(),
+                    supervisor_policy,
+                    process_isolation,
+                    termination_token.clone(),
+                    inspector.clone(),
+                    request_idle_timeout,
+                )
+            };
 
-            match create_worker(
-                (worker_options, supervisor_policy, termination_token.clone()),
-                inspector,
-                request_idle_timeout,
-            )
-            .await
-            {
-                Ok(ctx) => {
-                    let profile = UserWorkerProfile {
-                        worker_request_msg_tx: ctx.msg_tx,
-                        timing_tx_pair: (req_start_timing_tx, req_end_timing_tx),
+            let result = match (boot(worker_options).await, maybe_retry_opts) {
+                (Err(err), Some(retry_opts)) => {
+                    warn!(
+                        "failed to boot worker for {}, retrying with {}: {}",
                         service_path,
-                        permit: permit.map(Arc::new),
-                        status: status.clone(),
-                        exit: ctx.exit,
-                        cancel,
-                    };
+                        retry_opts.service_path.display(),
+                        err
+                    );
+
+                    boot(retry_opts).await
+                }
+
+                (result, _) => result,
+            };
+
+            drop(booting);
+
+            if let Some(breaker) = circuit_breaker.as_ref() {
+                if result.is_ok() {
+                    breaker.record_success(&service_path);
+                } else {
+                    breaker.record_failure(&service_path);
+                }
+            }
+
+            match result {
+                Ok((uuid, mut profile)) => {
+                    let status = profile.status.clone();
+                    let max_requests = profile.max_requests;
+
+                    profile.permit = permit.map(Arc::new);
+                    profile.slot = slot.map(Arc::new);
+                    profile.queue_wait = queue_wait;
 
                     if worker_pool_msgs_tx
                         .send(UserWorkerMsgs::Created(uuid, profile))
@@ -450,7 +852,7 @@ impl WorkerPool {
                         error!("main worker receiver dropped")
                     };
 
-                    status.demand.fetch_add(1, Ordering::Release);
+                    status.demand(max_requests);
                 }
                 Err(e) => {
                     if tx.send(Err(e)).is_err() {
@@ -463,6 +865,45 @@ impl WorkerPool {
         }));
     }
 
+    /// Rejects the request with [`WorkerError::TenantQuotaExceeded`] if
+    /// `tenant` has exhausted one of its quotas.
+    fn tenant_quota_exceeded(
+        &mut self,
+        tenant: &str,
+        boot: bool,
+        tx: &Sender<Result<CreateUserWorkerResult, Error>>,
+    ) -> bool {
+        let Some(quotas) = self.tenant_quotas.as_mut() else {
+            return false;
+        };
+
+        // NOTE: Workers that are still booting aren't accounted yet.
+        let usage = self
+            .user_workers
+            .values()
+            .filter(|it| it.tenant == tenant)
+            .fold(TenantUsage::default(), |acc, it| TenantUsage {
+                workers: acc.workers + 1,
+                used_heap_size: acc.used_heap_size + it.stats.used_heap_size(),
+                cpu_time_ms: acc.cpu_time_ms + it.stats.cpu_time_ms(),
+            });
+
+        let Some(exceeded) = quotas.check(tenant, &usage, boot) else {
+            return false;
+        };
+
+        let err = WorkerError::TenantQuotaExceeded {
+            resource: exceeded.resource,
+            retry_after_secs: exceeded.retry_after.as_secs() + 1,
+        };
+
+        if tx.send(Err(anyhow!(err))).is_err() {
+            error!("main worker receiver dropped")
+        }
+
+        true
+    }
+
     pub fn add_user_worker(&mut self, key: Uuid, profile: UserWorkerProfile) {
         let registry = self
             .active_workers
@@ -473,33 +914,243 @@ impl WorkerPool {
             .workers
             .insert(WorkerId(key, self.policy.supervisor_policy.is_per_worker()));
 
+        if let Some(watcher) = self.service_watcher.as_mut() {
+            watcher.watch(&profile.service_path);
+        }
+
+        let service_path = profile.service_path.clone();
+        let queue_wait = profile.queue_wait;
+
         self.user_workers.insert(key, profile);
         self.metric_src.incl_active_user_workers();
+        self.send_pool_event(&service_path, key, PoolActivity::ColdBoot, queue_wait);
+    }
+
+    /// Retires the warm workers of services whose directory contains `changed`,
+    /// so that the next request to those services boots a fresh worker.
+    pub fn recycle_changed_services(&mut self, changed: &Path) {
+        let Some(watcher) = self.service_watcher.as_mut() else {
+            return;
+        };
+
+        if let Some(service_path) = watcher.configured_service(changed).map(str::to_string) {
+            watcher.config_changed(&service_path);
+        }
+
+        let service_paths = watcher
+            .affected_services(changed)
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+
+        if service_paths.is_empty() {
+            return;
+        }
+
+        if self.retire_services(&service_paths) > 0 {
+            info!("{} has changed, recycling workers", changed.display());
+        }
+    }
+
+    /// See [`UserWorkerMsgs::WatchConfigs`].
+    pub fn watch_configs(&mut self, tx: mpsc::UnboundedSender<String>) {
+        if let Some(watcher) = self.service_watcher.as_mut() {
+            watcher.watch_configs(tx);
+        }
+    }
+
+    /// Retires the warm workers of `service_paths` and returns how many were
+    /// retired. Requests they are handling still complete.
+    pub fn retire_services(&mut self, service_paths: &HashSet<String>) -> usize {
+        let keys = self
+            .user_workers
+            .iter()
+            .filter(|(key, profile)| {
+                service_paths.contains(&profile.service_path)
+                    && self
+                        .active_workers
+                        .get(&profile.service_path)
+                        .is_some_and(|it| it.workers.contains(*key))
+            })
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for key in keys.iter() {
+            self.retire(key);
+        }
+
+        keys.len()
+    }
+
+    /// Retires the targeted workers, then terminates each of them once its
+    /// in-flight requests have completed.
+    pub fn terminate(&mut self, target: &TerminateTarget) -> usize {
+        let keys = self
+            .user_workers
+            .iter()
+            .filter(|(key, profile)| match target {
+                TerminateTarget::Worker(it) => it == *key,
+                TerminateTarget::Service(it) => *it == profile.service_path,
+            })
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for key in keys.iter() {
+            self.retire(key);
+
+            let profile = self.user_workers.get(key).unwrap();
+            let stats = profile.stats.clone();
+            let termination = profile.termination.clone();
+
+            drop(tokio::spawn(async move {
+                stats.wait_drained().await;
+                termination.cancel();
+            }));
+        }
+
+        keys.len()
+    }
+
+    /// Evicts idle workers, the ones with the largest heap first, until the
+    /// memory they used covers the excess of `pressure`. Evicts at least one
+    /// worker if any is idle.
+    pub fn relieve_memory_pressure(&mut self, pressure: MemoryPressure) -> usize {
+        let Some(policy) = self.policy.memory_pressure.as_ref() else {
+            return 0;
+        };
+
+        let mut excess = policy.excess_bytes(&pressure);
+        let mut idle = self
+            .user_workers
+            .iter()
+            .filter(|(key, profile)| {
+                profile.stats.in_flight() == 0
+                    && self
+                        .active_workers
+                        .get(&profile.service_path)
+                        .is_some_and(|it| it.workers.contains(*key))
+            })
+            .map(|(key, profile)| (*key, profile.stats.used_heap_size()))
+            .collect::<Vec<_>>();
+
+        idle.sort_by_key(|(_, used_heap_size)| std::cmp::Reverse(*used_heap_size));
+
+        let mut evicted = 0;
+
+        for (key, used_heap_size) in idle {
+            if evicted > 0 && excess == 0 {
+                break;
+            }
+
+            self.retire(&key);
+
+            let profile = &self.user_workers[&key];
+
+            warn!(
+                "evicting idle worker of {} ({} bytes of heap) due to memory pressure",
+                profile.service_path, used_heap_size
+            );
+
+            profile.termination.cancel();
+
+            if let Some(tx) = self.worker_event_sender.as_ref() {
+                let _ = tx.send(WorkerEventWithMetadata {
+                    event: WorkerEvents::MemoryPressureEviction(MemoryPressureEvictionEvent {
+                        pressure,
+                        used_heap_size,
+                        uptime_ms: profile.stats.uptime().as_millis() as u64,
+                    }),
+                    metadata: EventMetadata {
+                        service_path: Some(profile.service_path.clone()),
+                        execution_id: Some(key),
+                    },
+                });
+            }
+
+            excess = excess.saturating_sub(used_heap_size as u64);
+            evicted += 1;
+        }
+
+        evicted
+    }
+
+    /// Frees a pool-wide slot of `service_path` for the services waiting for
+    /// one, by retiring the worker holding a slot with the fewest in-flight
+    /// requests. The worker is terminated once those have completed.
+    pub fn reclaim_slot(&mut self, service_path: &str) -> bool {
+        let Some(key) = self
+            .user_workers
+            .iter()
+            .filter(|(_, profile)| profile.service_path == service_path && profile.slot.is_some())
+            .min_by_key(|(_, profile)| profile.stats.in_flight())
+            .map(|(key, _)| *key)
+        else {
+            return false;
+        };
+
+        info!(
+            "reclaiming the worker slot of {} for the services waiting for one",
+            service_path
+        );
+
+        self.terminate(&TerminateTarget::Worker(key)) > 0
     }
 
     pub fn send_request(
         &self,
         key: &Uuid,
-        req: Request<Body>,
+        mut req: Request<Body>,
         res_tx: Sender<Result<SendRequestResult, Error>>,
         conn_token: Option<CancellationToken>,
     ) {
+        if let Some(limit) = self
+            .user_workers
+            .get(key)
+            .and_then(|it| self.concurrency_limit_reached(&it.service_path))
+        {
+            if res_tx
+                .send(Err(anyhow!(WorkerError::ConcurrencyLimitReached { limit })))
+                .is_err()
+            {
+                error!("main worker receiver dropped")
+            }
+            return;
+        }
+
         let _: Result<(), Error> = match self.user_workers.get(key) {
             Some(worker) => {
+                if let Some(hibernation) = worker.hibernation.clone() {
+                    req.extensions_mut().insert(Hibernation {
+                        worker: hibernation,
+                        service_path: worker.service_path.clone(),
+                        pool_msg_tx: self.worker_pool_msgs_tx.clone(),
+                    });
+                }
+
                 let policy = self.policy.supervisor_policy;
                 let profile = worker.clone();
                 let exit = worker.exit.clone();
                 let cancel = worker.cancel.clone();
                 let (req_start_tx, req_end_tx) = profile.timing_tx_pair.clone();
+                let req_end_tx = track_in_flight(&profile.stats, req_end_tx);
+                let (req, maybe_capture) = match self.policy.request_capture.as_ref() {
+                    Some(capture_policy) => RequestCapture::begin(
+                        capture_policy,
+                        req,
+                        self.worker_event_sender.clone(),
+                        EventMetadata {
+                            service_path: Some(profile.service_path.clone()),
+                            execution_id: Some(*key),
+                        },
+                    ),
+
+                    None => (req, None),
+                };
 
                 // Create a closure to handle the request and send the response
                 let request_handler = async move {
                     if !policy.is_per_worker() {
                         if cancel.is_cancelled() {
-                            bail!(exit
-                                .error()
-                                .await
-                                .unwrap_or(anyhow!(WorkerError::RequestCancelledBySupervisor)))
+                            bail!(exit.cancelled_error().await)
                         }
 
                         let fence = Arc::new(Notify::const_new());
@@ -524,10 +1175,7 @@ impl WorkerPool {
                         tokio::select! {
                             _ = fence.notified() => {}
                             _ = cancel.cancelled() => {
-                                bail!(exit
-                                    .error()
-                                    .await
-                                    .unwrap_or(anyhow!(WorkerError::RequestCancelledBySupervisor)))
+                                bail!(exit.cancelled_error().await)
                             }
                         }
                     }
@@ -542,7 +1190,13 @@ impl WorkerPool {
                     .await;
 
                     match result {
-                        Ok(req) => Ok((req, req_end_tx)),
+                        Ok(res) => Ok((
+                            match maybe_capture {
+                                Some(capture) => capture.finish(res),
+                                None => res,
+                            },
+                            req_end_tx,
+                        )),
                         Err(err) => {
                             let _ = req_end_tx.send(());
                             error!("failed to send request to user worker: {}", err.to_string());
@@ -574,6 +1228,61 @@ impl WorkerPool {
         };
     }
 
+    /// Sends `req`, which picks back up a connection a hibernated worker of
+    /// `service_path` parked, to a warm worker of the service, or to one booted
+    /// with the options the hibernated one was.
+    pub fn resume(
+        &mut self,
+        service_path: &str,
+        req: Request<Body>,
+        res_tx: Sender<Result<SendRequestResult, Error>>,
+        termination_token: Option<TerminationToken>,
+    ) {
+        let Some(opts) = self
+            .wake_opts
+            .get(service_path)
+            .and_then(|it| wake_init_opts(&it.opts))
+        else {
+            if res_tx
+                .send(Err(anyhow!(
+                    "workers of {} can't be woken up",
+                    service_path
+                )))
+                .is_err()
+            {
+                error!("parked connection receiver dropped")
+            }
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        let worker_pool_msgs_tx = self.worker_pool_msgs_tx.clone();
+
+        self.create_user_worker(opts, tx, termination_token);
+
+        drop(tokio::spawn(async move {
+            let key = match rx.await {
+                Ok(Ok(CreateUserWorkerResult { key })) => key,
+                Ok(Err(err)) => {
+                    let _ = res_tx.send(Err(err));
+                    return;
+                }
+
+                Err(_) => {
+                    let _ = res_tx.send(Err(anyhow!("worker creation was abandoned")));
+                    return;
+                }
+            };
+
+            if worker_pool_msgs_tx
+                .send(UserWorkerMsgs::SendRequest(key, req, res_tx, None))
+                .is_err()
+            {
+                error!("user worker msgs receiver dropped")
+            }
+        }));
+    }
+
     pub fn idle(&mut self, key: &Uuid) {
         if let Some(registry) = self
             .user_workers
@@ -587,18 +1296,153 @@ impl WorkerPool {
     pub fn shutdown(&mut self, key: &Uuid) {
         self.retire(key);
 
-        let Some((notify_tx, _)) = self
-            .user_workers
-            .remove(key)
-            .and_then(|it| self.active_workers.get(&it.service_path))
-            .map(|it| it.notify_pair.clone())
-        else {
+        if let Some((breaker, profile)) =
+            self.circuit_breaker.clone().zip(self.user_workers.get(key))
+        {
+            let exit = profile.exit.clone();
+            let service_path = profile.service_path.clone();
+
+            drop(tokio::spawn(async move {
+                if exit.error().await.is_some() {
+                    breaker.record_failure(&service_path);
+                }
+            }));
+        }
+
+        let Some(profile) = self.user_workers.remove(key) else {
             return;
         };
 
-        let _ = notify_tx.send(None);
+        if let Some(quotas) = self.tenant_quotas.as_mut() {
+            quotas.record_exit(&profile.tenant, profile.stats.cpu_time_ms());
+        }
 
         self.metric_src.decl_active_user_workers();
+        self.send_pool_event(
+            &profile.service_path,
+            *key,
+            PoolActivity::Eviction,
+            Duration::ZERO,
+        );
+
+        if let Some(registry) = self.active_workers.get(&profile.service_path) {
+            let _ = registry.notify_pair.0.send(None);
+        }
+
+        let is_service_gone = !self
+            .user_workers
+            .values()
+            .any(|it| it.service_path == profile.service_path);
+
+        if let Some(watcher) = self.service_watcher.as_mut().filter(|_| is_service_gone) {
+            watcher.unwatch(&profile.service_path);
+        }
+
+        self.prune_wake_opts();
+    }
+
+    /// Forgets how to wake up the services that no longer have a worker, nor a
+    /// connection to pick back up.
+    fn prune_wake_opts(&mut self) {
+        // NOTE: A worker may let go of its share a little after its shutdown,
+        // in which case the service is forgotten on a later one.
+        self.wake_opts
+            .retain(|_, it| Arc::strong_count(&it.service) > 1);
+    }
+
+    fn send_pool_event(
+        &self,
+        service_path: &str,
+        key: Uuid,
+        activity: PoolActivity,
+        queue_wait: Duration,
+    ) {
+        let Some(tx) = self.worker_event_sender.as_ref() else {
+            return;
+        };
+
+        let _ = tx.send(WorkerEventWithMetadata {
+            event: WorkerEvents::Pool(PoolEvent {
+                activity,
+                queue_wait_ms: queue_wait.as_millis() as u64,
+                pool_size: self.user_workers.len(),
+            }),
+            metadata: EventMetadata {
+                service_path: Some(service_path.to_string()),
+                execution_id: Some(key),
+            },
+        });
+    }
+
+    /// Returns the request cap of `service_path` if its workers are already
+    /// handling that many requests.
+    fn concurrency_limit_reached(&self, service_path: &str) -> Option<usize> {
+        let limit = self
+            .active_workers
+            .get(service_path)?
+            .max_concurrent_requests?;
+
+        let in_flight = self
+            .user_workers
+            .values()
+            .filter(|it| it.service_path == service_path)
+            .map(|it| it.stats.in_flight())
+            .sum::<usize>();
+
+        (in_flight >= limit).then_some(limit)
+    }
+
+    /// Returns the state of every worker the pool knows about, including the
+    /// ones that are still booting.
+    pub fn snapshot(&self) -> Vec<UserWorkerSnapshot> {
+        let mut workers = self
+            .user_workers
+            .iter()
+            .map(|(key, profile)| {
+                let is_active = self
+                    .active_workers
+                    .get(&profile.service_path)
+                    .is_some_and(|it| it.workers.contains(key));
+
+                let state = if !is_active || profile.status.is_retired.is_raised() {
+                    UserWorkerState::Retiring
+                } else if profile.stats.in_flight() > 0 {
+                    UserWorkerState::Busy
+                } else {
+                    UserWorkerState::Idle
+                };
+
+                UserWorkerSnapshot {
+                    key: Some(*key),
+                    service_path: profile.service_path.clone(),
+                    state,
+                    uptime_ms: profile.stats.uptime().as_millis() as u64,
+                    requests_served: profile.stats.requests_served(),
+                    in_flight: profile.stats.in_flight(),
+                    cpu_time_ms: profile.stats.cpu_time_ms(),
+                    used_heap_size: profile.stats.used_heap_size(),
+                    limits: Some(profile.limits),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (service_path, registry) in self.active_workers.iter() {
+            for _ in 0..registry.booting.load(Ordering::Acquire) {
+                workers.push(UserWorkerSnapshot {
+                    key: None,
+                    service_path: service_path.clone(),
+                    state: UserWorkerState::Booting,
+                    uptime_ms: 0,
+                    requests_served: 0,
+                    in_flight: 0,
+                    cpu_time_ms: 0,
+                    used_heap_size: 0,
+                    limits: None,
+                });
+            }
+        }
+
+        workers
     }
 
     fn retire(&mut self, key: &Uuid) {
@@ -609,6 +1453,7 @@ impl WorkerPool {
                 .expect("registry must be initialized at this point");
 
             let _ = profile.permit.take();
+            let _ = profile.slot.take();
             let (notify_tx, _) = registry.notify_pair.clone();
 
             for _ in 0..notify_tx.receiver_count() {
@@ -639,11 +1484,9 @@ impl WorkerPool {
             .map(|it| it.status.is_retired.clone())
         {
             Some(is_retired) if !is_retired.is_raised() => {
-                self.user_workers
-                    .get(&worker_uuid)
-                    .map(|it| it.status.demand.as_ref())
-                    .unwrap()
-                    .fetch_add(1, Ordering::Release);
+                let profile = self.user_workers.get(&worker_uuid).unwrap();
+
+                profile.status.demand(profile.max_requests);
 
                 Some(worker_uuid)
             }
@@ -655,3 +1498,264 @@ impl WorkerPool {
         }
     }
 }
+
+/// Boots a user worker for `service_path`. The returned profile holds no
+/// semaphore permit yet.
+#[allow(clippy::too_many_arguments)]
+async fn boot_user_worker(
+    mut worker_options: WorkerContextInitOpts,
+    service_path: String,
+    worker_pool_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+    events_msg_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+    supervisor_policy: SupervisorPolicy,
+    process_isolation: bool,
+    termination_token: Option<TerminationToken>,
+    inspector: Option<Inspector>,
+    request_idle_timeout: Option<u64>,
+) -> Result<(Uuid, UserWorkerProfile), Error> {
+    let Ok(mut user_worker_rt_opts) = worker_options.conf.into_user_worker() else {
+        bail!("not a user worker");
+    };
+
+    let uuid = uuid::Uuid::new_v4();
+    let cancel = CancellationToken::new();
+
+    // NOTE: Every worker gets a token of its own so that it can be terminated
+    // without the rest of the pool, and so that its exit, or a failed boot,
+    // isn't taken for the termination of the pool.
+    let termination_token = termination_token
+        .map(|it| it.detached_child_token())
+        .unwrap_or_default();
+
+    let termination = termination_token.inbound.clone();
+    // NOTE: Each boot attempt gets a token of its own.
+    let hibernation = user_worker_rt_opts
+        .hibernation
+        .take()
+        .filter(|_| supervisor_policy.is_per_worker() && user_worker_rt_opts.hibernate_after_ms > 0)
+        .map(|it| WorkerHibernation {
+            service: it.service,
+            ..Default::default()
+        });
+    let stats = UserWorkerStats::default();
+    let limits = UserWorkerLimits::from(&user_worker_rt_opts);
+    let max_requests = Some(user_worker_rt_opts.max_requests as usize).filter(|it| *it > 0);
+    let tenant = user_worker_rt_opts.tenant.clone().unwrap_or_default();
+    let (req_start_timing_tx, req_start_timing_rx) = mpsc::unbounded_channel::<Arc<Notify>>();
+
+    let status = TimingStatus {
+        demand: Arc::new(AtomicUsize::new(0)),
+        is_retired: Arc::new(AtomicFlag::default()),
+    };
+
+    let (req_end_timing_tx, req_end_timing_rx) = mpsc::unbounded_channel::<()>();
+
+    if let Some(flag) = user_worker_rt_opts
+        .v8_flags
+        .iter()
+        .find(|it| !is_service_v8_flag(it))
+    {
+        bail!("V8 flag {} is not allowed for services", flag);
+    }
+
+    if !process_isolation && !user_worker_rt_opts.v8_flags.is_empty() {
+        warn!(
+            "ignoring the v8 flags of {}: they need process isolation",
+            service_path
+        );
+    }
+
+    user_worker_rt_opts.service_path = Some(service_path.clone());
+    user_worker_rt_opts.key = Some(uuid);
+
+    user_worker_rt_opts.pool_msg_tx = Some(worker_pool_msgs_tx);
+    user_worker_rt_opts.events_msg_tx = events_msg_tx;
+    user_worker_rt_opts.cancel = Some(cancel.clone());
+    user_worker_rt_opts.stats = Some(stats.clone());
+    user_worker_rt_opts.hibernation = hibernation.clone();
+
+    worker_options.timing = Some(Timing {
+        status: status.clone(),
+        req: (req_start_timing_rx, req_end_timing_rx),
+    });
+
+    worker_options.conf = WorkerRuntimeOpts::UserWorker(user_worker_rt_opts);
+
+    // NOTE: A worker in a child process is supervised there. Only the
+    // per-worker policy is allowed with process isolation, so the pool never
+    // waits on the timing channels dropped here.
+    let (worker_request_msg_tx, exit) = if process_isolation {
+        #[cfg(unix)]
+        {
+            super::process_worker::spawn_process_worker(worker_options, Some(termination_token))
+                .await?
+        }
+
+        #[cfg(not(unix))]
+        bail!("process isolation is only supported on unix")
+    } else {
+        let ctx = create_worker(
+            (worker_options, supervisor_policy, Some(termination_token)),
+            inspector,
+            request_idle_timeout,
+        )
+        .await?;
+
+        (ctx.msg_tx, ctx.exit)
+    };
+
+    Ok((
+        uuid,
+        UserWorkerProfile {
+            worker_request_msg_tx,
+            timing_tx_pair: (req_start_timing_tx, req_end_timing_tx),
+            service_path,
+            tenant,
+            permit: None,
+            slot: None,
+            status,
+            exit,
+            cancel,
+            termination,
+            stats,
+            limits,
+            max_requests,
+            queue_wait: Duration::ZERO,
+            hibernation,
+        },
+    ))
+}
+
+/// Counts a request as in flight on the worker until the end of its response
+/// is signalled through the returned sender, which then forwards the signal to
+/// `req_end_tx`.
+fn track_in_flight(
+    stats: &UserWorkerStats,
+    req_end_tx: mpsc::UnboundedSender<()>,
+) -> mpsc::UnboundedSender<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+    let in_flight = stats.in_flight.clone();
+    let drained = stats.drained.clone();
+
+    stats.requests_served.fetch_add(1, Ordering::AcqRel);
+    in_flight.fetch_add(1, Ordering::AcqRel);
+
+    drop(tokio::spawn(async move {
+        // NOTE: The sender is dropped without a signal if the request never
+        // reaches the worker.
+        if rx.recv().await.is_some() {
+            let _ = req_end_tx.send(());
+        }
+
+        if in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            drained.notify_waiters();
+        }
+    }));
+
+    tx
+}
+
+/// Returns the options to boot a worker with again after `opts` failed to
+/// boot: its fallback service if it has one, otherwise the same service.
+fn retry_init_opts(opts: &WorkerContextInitOpts) -> Option<WorkerContextInitOpts> {
+    let maybe_fallback = opts
+        .conf
+        .as_user_worker()
+        .and_then(|it| it.fallback_service_path.clone());
+
+    // NOTE: An eszip or inline module code is consumed by the first attempt.
+    if maybe_fallback.is_none() && (opts.maybe_eszip.is_some() || opts.maybe_module_code.is_some())
+    {
+        return None;
+    }
+
+    let is_fallback = maybe_fallback.is_some();
+
+    Some(WorkerContextInitOpts {
+        service_path: maybe_fallback
+            .map(PathBuf::from)
+            .unwrap_or_else(|| opts.service_path.clone()),
+        no_module_cache: opts.no_module_cache,
+        import_map_path: opts.import_map_path.clone(),
+        env_vars: opts.env_vars.clone(),
+        events_rx: None,
+        timing: None,
+        conf: opts.conf.clone(),
+        maybe_eszip: None,
+        maybe_module_code: None,
+        maybe_entrypoint: if is_fallback {
+            None
+        } else {
+            opts.maybe_entrypoint.clone()
+        },
+        maybe_decorator: opts.maybe_decorator,
+        static_patterns: opts.static_patterns.clone(),
+        maybe_jsx_import_source_config: opts.maybe_jsx_import_source_config.clone(),
+    })
+}
+
+/// Returns the options to boot a worker with to wake up a hibernated worker
+/// booted with `opts`, unless it can't be booted again from them.
+fn wake_init_opts(opts: &WorkerContextInitOpts) -> Option<WorkerContextInitOpts> {
+    // NOTE: An eszip or inline module code is consumed by the first boot.
+    if opts.maybe_eszip.is_some() || opts.maybe_module_code.is_some() {
+        return None;
+    }
+
+    Some(WorkerContextInitOpts {
+        service_path: opts.service_path.clone(),
+        no_module_cache: opts.no_module_cache,
+        import_map_path: opts.import_map_path.clone(),
+        env_vars: opts.env_vars.clone(),
+        events_rx: None,
+        timing: None,
+        conf: opts.conf.clone(),
+        maybe_eszip: None,
+        maybe_module_code: None,
+        maybe_entrypoint: opts.maybe_entrypoint.clone(),
+        maybe_decorator: opts.maybe_decorator,
+        static_patterns: opts.static_patterns.clone(),
+        maybe_jsx_import_source_config: opts.maybe_jsx_import_source_config.clone(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_limit_ceilings() {
+        let ceilings = UserWorkerLimitCeilings {
+            memory_limit_mb: Some(256),
+            worker_timeout_ms: Some(60_000),
+            cpu_time_ms: None,
+        };
+
+        let mut opts = UserWorkerRuntimeOpts {
+            memory_limit_mb: 128,
+            worker_timeout_ms: 0,
+            cpu_time_hard_limit_ms: 200,
+            ..Default::default()
+        };
+
+        assert!(ceilings.apply(&mut opts));
+        assert_eq!(opts.memory_limit_mb, 128);
+        assert_eq!(opts.worker_timeout_ms, 60_000);
+        assert_eq!(opts.cpu_time_hard_limit_ms, 200);
+        assert!(!ceilings.apply(&mut opts));
+    }
+
+    #[test]
+    fn test_should_inspect() {
+        let policy = WorkerPoolPolicy::default();
+
+        assert!(policy.should_inspect("./examples/hello-world"));
+
+        let policy = policy.with_inspect_services(vec!["hello-world/".to_string()]);
+
+        assert!(policy.should_inspect("./examples/hello-world"));
+        assert!(policy.should_inspect("/srv/examples/hello-world"));
+        assert!(!policy.should_inspect("./examples/world"));
+        assert!(!policy.should_inspect("./examples/hello-world-2"));
+    }
+}
@@ -1,14 +1,20 @@
+use crate::client_ip::{resolve_client_ip, TrustedProxies, TRUSTED_PROXIES};
+use crate::conn_limit::{ConnGuard, ConnLimiter};
+use crate::early_hints::{EarlyHints, OpenRequest, EARLY_HINTS_HEADER};
 use crate::inspector_server::Inspector;
 use crate::rt_worker::worker_ctx::{
-    create_events_worker, create_main_worker, create_user_worker_pool, TerminationToken,
+    create_events_worker, create_main_worker, create_user_worker_pool, RestartPolicy,
+    TerminationToken,
 };
 use crate::rt_worker::worker_pool::WorkerPoolPolicy;
+use crate::speculative_boot::{SpeculativeBoot, SPECULATIVE_BOOT_PATH};
 use crate::InspectorOption;
 use anyhow::{anyhow, bail, Context, Error};
 use deno_config::JsxImportSourceConfig;
 use event_worker::events::WorkerEventWithMetadata;
 use futures_util::future::{poll_fn, BoxFuture};
-use futures_util::{FutureExt, Stream};
+use futures_util::FutureExt;
+use hyper_v014::body::HttpBody;
 use hyper_v014::{server::conn::Http, service::Service, Body, Request, Response};
 use log::{debug, error, info, trace, warn};
 use rustls_pemfile::read_one_from_slice;
@@ -16,9 +22,10 @@ use rustls_pemfile::Item;
 use sb_core::SharedMetricSource;
 use sb_graph::DecoratorType;
 use sb_workers::context::{MainWorkerRuntimeOpts, WorkerRequestMsg};
+use sha2::{Digest, Sha256};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
 use std::future::{pending, Future};
 use std::net::IpAddr;
-use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::path::Path;
 use std::pin::Pin;
@@ -27,16 +34,16 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
-use tls_listener::TlsListener;
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::pin;
 use tokio::sync::mpsc::{Sender, UnboundedSender};
 use tokio::sync::{mpsc, oneshot};
 use tokio::time::{sleep, timeout};
 use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
-use tokio_rustls::rustls::ServerConfig;
-use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::server::{Acceptor, WebPkiClientVerifier};
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::{LazyConfigAcceptor, TlsAcceptor};
 use tokio_util::sync::CancellationToken;
 use url::Url;
 
@@ -71,17 +78,31 @@ impl<S> Drop for CancelOnDrop<S> {
     }
 }
 
-impl<S: Stream + Unpin> Stream for CancelOnDrop<S> {
-    type Item = S::Item;
+// NOTE: This is a body rather than a stream, so that the trailers of the
+// response (e.g. the `grpc-status` of a gRPC call) are not lost.
+impl<S: HttpBody + Unpin> HttpBody for CancelOnDrop<S> {
+    type Data = S::Data;
+    type Error = S::Error;
 
-    fn poll_next(
+    fn poll_data(
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
-    ) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.as_mut().inner).poll_next(cx)
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_data(cx)
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Result<Option<http_v02::HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper_v014::body::SizeHint {
         self.inner.size_hint()
     }
 }
@@ -122,9 +143,102 @@ impl TerminationTokens {
     }
 }
 
+/// Information about an accepted connection.
+#[derive(Debug, Clone, Default)]
+struct ConnInfo {
+    /// Address of the peer of the connection.
+    peer_addr: Option<SocketAddr>,
+    /// Client address announced by the PROXY protocol header.
+    client_addr: Option<SocketAddr>,
+    /// Identity of a client certificate verified during the TLS handshake.
+    client_cert: Option<ClientCertInfo>,
+    /// Releases the slot of the connection in the per-client limit once the
+    /// connection is gone.
+    _conn_guard: Option<Arc<ConnGuard>>,
+}
+
+#[derive(Debug, Clone)]
+struct ClientCertInfo {
+    subject: String,
+    sha256: String,
+}
+
+impl ClientCertInfo {
+    fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            sha256: format!("{:x}", Sha256::digest(der)),
+        })
+    }
+}
+
+static CLIENT_CERT_SUBJECT_HEADER: &str = "x-client-cert-subject";
+static CLIENT_CERT_SHA256_HEADER: &str = "x-client-cert-sha256";
+static CLIENT_IP_HEADER: &str = "x-client-ip";
+
+/// Upper bound of `--max-header-count`. hyper rejects HTTP/1 requests with
+/// more headers than this with a 431 on its own, before they can be checked.
+pub const MAX_HEADER_COUNT: usize = 100;
+
+/// Limits applied to every request before it's dispatched to the main worker.
+#[derive(Debug, Clone, Copy, Default)]
+struct RequestLimits {
+    body_read_timeout: Option<Duration>,
+    max_uri_length: Option<usize>,
+    max_header_count: Option<usize>,
+    /// Total size of the header names and values.
+    max_header_size: Option<usize>,
+}
+
+impl RequestLimits {
+    fn from_flags(flags: &ServerFlags) -> Self {
+        Self {
+            body_read_timeout: flags
+                .request_body_read_timeout_ms
+                .map(Duration::from_millis),
+            max_uri_length: flags.max_uri_length,
+            max_header_count: flags.max_header_count,
+            max_header_size: flags.max_header_size,
+        }
+    }
+
+    /// Returns the status a request exceeding the limits is rejected with.
+    fn check(&self, req: &Request<Body>) -> Option<http_v02::StatusCode> {
+        let uri_length = req.uri().path_and_query().map_or(0, |it| it.as_str().len());
+
+        if self.max_uri_length.is_some_and(|it| uri_length > it) {
+            return Some(http_v02::StatusCode::URI_TOO_LONG);
+        }
+
+        let headers = req.headers();
+
+        if self.max_header_count.is_some_and(|it| headers.len() > it) {
+            return Some(http_v02::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        }
+
+        if let Some(max) = self.max_header_size {
+            let size = headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len())
+                .sum::<usize>();
+
+            if size > max {
+                return Some(http_v02::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+            }
+        }
+
+        None
+    }
+}
+
 struct WorkerService {
     metric_src: SharedMetricSource,
     worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    conn_info: ConnInfo,
+    limits: RequestLimits,
+    early_hints: EarlyHints,
     cancel: CancellationToken,
 }
 
@@ -132,12 +246,18 @@ impl WorkerService {
     fn new(
         metric_src: SharedMetricSource,
         worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+        conn_info: ConnInfo,
+        limits: RequestLimits,
+        early_hints: EarlyHints,
     ) -> (Self, CancellationToken) {
         let cancel = CancellationToken::new();
         (
             Self {
                 metric_src,
                 worker_req_tx,
+                conn_info,
+                limits,
+                early_hints,
                 cancel: cancel.clone(),
             },
             cancel,
@@ -146,7 +266,7 @@ impl WorkerService {
 }
 
 impl Service<Request<Body>> for WorkerService {
-    type Response = Response<Body>;
+    type Response = Response<CancelOnDrop<Body>>;
     type Error = anyhow::Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
@@ -154,7 +274,57 @@ impl Service<Request<Body>> for WorkerService {
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        if let Some(status) = self.limits.check(&req) {
+            debug!("rejecting request exceeding limits with {}", status);
+
+            let res = Response::builder()
+                .status(status)
+                .header(http_v02::header::CONNECTION, "close")
+                .body(CancelOnDrop {
+                    inner: Body::empty(),
+                    cancel: None,
+                })
+                .map_err(Error::from);
+
+            return Box::pin(async move { res });
+        }
+
+        // NOTE: Speculative boot hints are sent by the server itself, and
+        // boot workers on behalf of whoever sends them.
+        if req.uri().path() == SPECULATIVE_BOOT_PATH {
+            let res = Response::builder()
+                .status(http_v02::StatusCode::NOT_FOUND)
+                .body(CancelOnDrop {
+                    inner: Body::empty(),
+                    cancel: None,
+                })
+                .map_err(Error::from);
+
+            return Box::pin(async move { res });
+        }
+
+        // NOTE: A PROXY protocol header already names the client, in which
+        // case the proxy that sent it is not a hop of the forwarding chain.
+        if let Some(addr) = self.conn_info.client_addr.or(self.conn_info.peer_addr) {
+            apply_client_ip_headers(&mut req, addr);
+        }
+
+        apply_client_cert_headers(&mut req, self.conn_info.client_cert.as_ref());
+
+        if let Some(dur) = self.limits.body_read_timeout {
+            req = with_body_read_timeout(req, dur);
+        }
+
+        // NOTE: Informational responses are not defined for HTTP/1.0, and
+        // hyper doesn't let them out on HTTP/2.
+        let early_hints = (req.version() == http_v02::Version::HTTP_11).then(|| {
+            let request = self.early_hints.open();
+
+            req.extensions_mut().insert(request.sender());
+            request
+        });
+
         // create a response in a future.
         let cancel = self.cancel.child_token();
         let metric_src = self.metric_src.clone();
@@ -169,6 +339,11 @@ impl Service<Request<Body>> for WorkerService {
                 conn_token: Some(cancel.clone()),
             };
 
+            // NOTE: Hyper drops this future if the client goes away before the
+            // response is ready (e.g. the connection is reset or an HTTP/2
+            // stream is cancelled), which aborts the request in the worker.
+            let cancel_guard = cancel.clone().drop_guard();
+
             worker_req_tx.send(msg)?;
             metric_src.incl_received_requests();
 
@@ -196,15 +371,19 @@ impl Service<Request<Body>> for WorkerService {
                 }
             };
 
+            let cancel = cancel_guard.disarm();
+
             let res = match res {
-                Ok(res) => {
+                Ok(mut res) => {
+                    apply_early_hints(&mut res, early_hints);
+
                     let (parts, body) = res.into_parts();
                     Response::from_parts(
                         parts,
-                        Body::wrap_stream(CancelOnDrop {
+                        CancelOnDrop {
                             inner: body,
                             cancel: Some(cancel),
-                        }),
+                        },
                     )
                 }
 
@@ -218,10 +397,10 @@ impl Service<Request<Body>> for WorkerService {
                     // FIXME: add an error body
                     Response::builder()
                         .status(http_v02::StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::wrap_stream(CancelOnDrop {
+                        .body(CancelOnDrop {
                             inner: Body::empty(),
                             cancel: Some(cancel),
-                        }))
+                        })
                         .unwrap()
                 }
             };
@@ -234,6 +413,142 @@ impl Service<Request<Body>> for WorkerService {
     }
 }
 
+/// Sets the `x-client-ip` header to the effective client address of the
+/// request, and records `addr` in its `X-Forwarded-For` chain.
+///
+/// Forwarding headers of peers that are not trusted proxies are replaced, so
+/// that the worker never sees addresses the runtime did not vouch for.
+fn apply_client_ip_headers(req: &mut Request<Body>, addr: SocketAddr) {
+    let no_proxies = TrustedProxies::default();
+    let trusted = TRUSTED_PROXIES.get().unwrap_or(&no_proxies);
+    let client_ip = resolve_client_ip(addr.ip(), req.headers(), trusted);
+
+    if !trusted.contains(addr.ip()) {
+        let headers = req.headers_mut();
+
+        headers.remove(http_v02::header::FORWARDED);
+        headers.remove(http_v02::header::X_FORWARDED_FOR);
+    }
+
+    append_forwarded_for(req, addr);
+
+    if let Ok(value) = http_v02::HeaderValue::from_str(&client_ip.to_string()) {
+        req.headers_mut().insert(CLIENT_IP_HEADER, value);
+    }
+}
+
+/// Fails the request body if the client doesn't send any of it for `dur`, so
+/// that a slow client can't keep a worker waiting on its body indefinitely.
+///
+/// NOTE: The body is relayed through a channel rather than a stream, so that
+/// its trailers (e.g. those of a gRPC call) are not lost.
+fn with_body_read_timeout(req: Request<Body>, dur: Duration) -> Request<Body> {
+    if req.body().is_end_stream() {
+        return req;
+    }
+
+    let (parts, mut body) = req.into_parts();
+    let (mut body_tx, body_rx) = Body::channel();
+
+    drop(tokio::spawn(async move {
+        loop {
+            match timeout(dur, body.data()).await {
+                Ok(Some(Ok(chunk))) => {
+                    if body_tx.send_data(chunk).await.is_err() {
+                        return;
+                    }
+                }
+
+                Ok(Some(Err(err))) => {
+                    debug!("failed to read request body: {}", err);
+                    body_tx.abort();
+                    return;
+                }
+
+                Ok(None) => break,
+                Err(_) => {
+                    debug!("request body read timed out");
+                    body_tx.abort();
+                    return;
+                }
+            }
+        }
+
+        match timeout(dur, body.trailers()).await {
+            Ok(Ok(Some(trailers))) => {
+                let _ = body_tx.send_trailers(trailers).await;
+            }
+
+            Ok(Ok(None)) => {}
+            Ok(Err(_)) | Err(_) => body_tx.abort(),
+        }
+    }));
+
+    Request::from_parts(parts, body_rx)
+}
+
+/// Appends the client address to the `X-Forwarded-For` header of the request.
+fn append_forwarded_for(req: &mut Request<Body>, addr: SocketAddr) {
+    let headers = req.headers_mut();
+    let ip = addr.ip().to_string();
+    let value = match headers
+        .get(http_v02::header::X_FORWARDED_FOR)
+        .and_then(|it| it.to_str().ok())
+    {
+        Some(prev) => format!("{}, {}", prev, ip),
+        None => ip,
+    };
+
+    if let Ok(value) = http_v02::HeaderValue::from_str(&value) {
+        headers.insert(http_v02::header::X_FORWARDED_FOR, value);
+    }
+}
+
+/// Forwards the identity of a verified client certificate to the worker.
+///
+/// Client supplied headers with the same names are always removed so that they
+/// can't be spoofed.
+fn apply_client_cert_headers(req: &mut Request<Body>, maybe_cert: Option<&ClientCertInfo>) {
+    let headers = req.headers_mut();
+
+    headers.remove(CLIENT_CERT_SUBJECT_HEADER);
+    headers.remove(CLIENT_CERT_SHA256_HEADER);
+
+    let Some(cert) = maybe_cert else {
+        return;
+    };
+
+    if let Ok(value) = http_v02::HeaderValue::from_str(&cert.subject) {
+        headers.insert(CLIENT_CERT_SUBJECT_HEADER, value);
+    }
+
+    if let Ok(value) = http_v02::HeaderValue::from_str(&cert.sha256) {
+        headers.insert(CLIENT_CERT_SHA256_HEADER, value);
+    }
+}
+
+/// Relays the preload links a worker sets in `x-early-hints` to the client in a
+/// `103 Early Hints` ahead of the response, if the connection allows for it.
+/// No more hints can be sent for the request after this.
+///
+/// The links are also kept as `Link` headers of the response, for clients and
+/// proxies that drop informational responses.
+fn apply_early_hints(res: &mut Response<Body>, early_hints: Option<OpenRequest>) {
+    let headers = res.headers_mut();
+    let hints = match headers.entry(EARLY_HINTS_HEADER) {
+        http_v02::header::Entry::Occupied(entry) => entry.remove_entry_mult().1.collect::<Vec<_>>(),
+        http_v02::header::Entry::Vacant(_) => return,
+    };
+
+    if let Some(early_hints) = early_hints.as_ref() {
+        early_hints.send(&hints);
+    }
+
+    for hint in hints {
+        headers.append(http_v02::header::LINK, hint);
+    }
+}
+
 pub struct WorkerEntrypoints {
     pub main: Option<String>,
     pub events: Option<String>,
@@ -244,11 +559,34 @@ pub struct ServerFlags {
     pub no_module_cache: bool,
     pub allow_main_inspector: bool,
     pub tcp_nodelay: bool,
+    pub tcp_keepalive_secs: Option<u64>,
+    pub tcp_write_timeout_ms: Option<u64>,
+    pub tcp_backlog: Option<i32>,
+    pub dual_stack: bool,
+    pub proxy_protocol: bool,
+    pub speculative_boot: bool,
+    pub watch: bool,
     pub graceful_exit_deadline_sec: u64,
     pub graceful_exit_keepalive_deadline_ms: Option<u64>,
     pub request_wait_timeout_ms: Option<u64>,
+    pub request_queue_depth: Option<usize>,
     pub request_idle_timeout_ms: Option<u64>,
     pub request_read_timeout_ms: Option<u64>,
+    pub request_body_read_timeout_ms: Option<u64>,
+    pub max_connections_per_ip: Option<usize>,
+    pub max_header_size: Option<usize>,
+    /// At most [`MAX_HEADER_COUNT`].
+    pub max_header_count: Option<usize>,
+    pub max_uri_length: Option<usize>,
+    pub lambda_runtime: bool,
+    pub process_isolation: bool,
+    pub main_worker_max_restarts: Option<u32>,
+    pub main_worker_restart_backoff_ms: Option<u64>,
+    /// Ceilings of the limits user workers are booted with, whatever the main
+    /// worker asks for.
+    pub max_worker_memory_limit_mb: Option<u64>,
+    pub max_worker_timeout_ms: Option<u64>,
+    pub max_worker_cpu_time_ms: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -256,6 +594,13 @@ pub struct Tls {
     port: u16,
     key: PrivateKeyDer<'static>,
     cert_chain: Vec<CertificateDer<'static>>,
+    client_auth: Option<TlsClientAuth>,
+}
+
+#[derive(Debug, Clone)]
+struct TlsClientAuth {
+    roots: Vec<CertificateDer<'static>>,
+    optional: bool,
 }
 
 impl Clone for Tls {
@@ -264,6 +609,7 @@ impl Clone for Tls {
             port: self.port,
             key: self.key.clone_key(),
             cert_chain: self.cert_chain.clone(),
+            client_auth: self.client_auth.clone(),
         }
     }
 }
@@ -306,22 +652,77 @@ impl Tls {
             port,
             key,
             cert_chain,
+            client_auth: None,
         })
     }
 
-    fn into_acceptor(self) -> anyhow::Result<TlsAcceptor> {
-        Ok(Arc::new(
-            ServerConfig::builder()
-                .with_no_client_auth()
-                .with_single_cert(self.cert_chain, self.key)
-                .with_context(|| "can't make TLS acceptor")?,
-        )
-        .into())
+    /// Enables client certificate verification (mutual TLS) against the CA
+    /// certificates in the PEM bundle `ca`.
+    ///
+    /// If `optional` is true, clients without a certificate are still accepted,
+    /// but a presented certificate must be valid.
+    pub fn with_client_auth(mut self, ca: &[u8], optional: bool) -> anyhow::Result<Self> {
+        let mut roots = vec![];
+        let mut ca_slice = ca;
+
+        while let Some((item, remain_ca_slice)) = read_one_from_slice(ca_slice)
+            .map_err(|err| anyhow!("can't resolve client ca: {:?}", err))?
+        {
+            if let Item::X509Certificate(cert) = item {
+                roots.push(cert);
+            }
+
+            ca_slice = remain_ca_slice;
+        }
+
+        if roots.is_empty() {
+            bail!("invalid client ca data");
+        }
+
+        self.client_auth = Some(TlsClientAuth { roots, optional });
+        Ok(self)
+    }
+
+    fn into_server_config(self) -> anyhow::Result<Arc<ServerConfig>> {
+        let builder = ServerConfig::builder();
+        let builder = if let Some(TlsClientAuth { roots, optional }) = self.client_auth {
+            let mut root_store = RootCertStore::empty();
+
+            for cert in roots {
+                root_store
+                    .add(cert)
+                    .with_context(|| "invalid client ca certificate")?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder(Arc::new(root_store));
+            let verifier = if optional {
+                verifier.allow_unauthenticated()
+            } else {
+                verifier
+            };
+
+            builder.with_client_cert_verifier(
+                verifier
+                    .build()
+                    .with_context(|| "can't make client certificate verifier")?,
+            )
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let mut config = builder
+            .with_single_cert(self.cert_chain, self.key)
+            .with_context(|| "can't make TLS acceptor")?;
+
+        // NOTE: gRPC clients require HTTP/2, which is negotiated over ALPN.
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
     }
 }
 
 pub struct Server {
-    ip: Ipv4Addr,
+    ip: IpAddr,
     port: u16,
     tls: Option<Tls>,
     main_worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
@@ -351,6 +752,13 @@ impl Server {
         jsx_specifier: Option<String>,
         jsx_module: Option<String>,
     ) -> Result<Self, Error> {
+        if flags
+            .max_header_count
+            .is_some_and(|it| it > MAX_HEADER_COUNT)
+        {
+            bail!("max header count cannot be greater than {MAX_HEADER_COUNT}");
+        }
+
         let mut worker_events_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>> = None;
         let maybe_events_entrypoint = entrypoints.events;
         let maybe_main_entrypoint = entrypoints.main;
@@ -388,7 +796,7 @@ impl Server {
         // Create a user worker pool
         let (shared_metric_src, worker_pool_tx) = create_user_worker_pool(
             maybe_user_worker_policy.unwrap_or_default(),
-            worker_events_tx,
+            worker_events_tx.clone(),
             Some(termination_tokens.pool.clone()),
             static_patterns,
             inspector.clone(),
@@ -399,6 +807,7 @@ impl Server {
 
         // create main worker
         let main_worker_path = Path::new(&main_service_path).to_path_buf();
+        let default_restart_policy = RestartPolicy::default();
         let main_worker_req_tx = create_main_worker(
             main_worker_path,
             import_map_path.clone(),
@@ -420,10 +829,19 @@ impl Server {
                 None
             },
             jsx_config,
+            RestartPolicy {
+                max_restarts: flags
+                    .main_worker_max_restarts
+                    .unwrap_or(default_restart_policy.max_restarts),
+                initial_backoff_ms: flags
+                    .main_worker_restart_backoff_ms
+                    .unwrap_or(default_restart_policy.initial_backoff_ms),
+            },
+            worker_events_tx,
         )
         .await?;
 
-        let ip = Ipv4Addr::from_str(ip)?;
+        let ip = parse_ip_addr(ip)?;
 
         Ok(Self {
             ip,
@@ -442,12 +860,15 @@ impl Server {
     }
 
     pub async fn listen(&mut self) -> Result<(), Error> {
-        let addr = SocketAddr::new(IpAddr::V4(self.ip), self.port);
-        let non_secure_listener = TcpListener::bind(&addr).await?;
-        let mut secure_listener = if let Some(tls) = self.tls.take() {
-            let addr = SocketAddr::new(IpAddr::V4(self.ip), tls.port);
+        let dual_stack = self.flags.dual_stack;
+        let backlog = self.flags.tcp_backlog.unwrap_or(DEFAULT_TCP_BACKLOG);
+        let addr = SocketAddr::new(self.ip, self.port);
+        let non_secure_listener = bind_tcp_listener(addr, dual_stack, backlog)?;
+        let secure_listener = if let Some(tls) = self.tls.take() {
+            let addr = SocketAddr::new(self.ip, tls.port);
             Some((
-                TlsListener::new(tls.into_acceptor()?, TcpListener::bind(addr).await?),
+                bind_tcp_listener(addr, dual_stack, backlog)?,
+                tls.into_server_config()?,
                 addr,
             ))
         } else {
@@ -455,6 +876,10 @@ impl Server {
         };
 
         let metric_src = self.metric_src.clone();
+        let speculative_boot = self
+            .flags
+            .speculative_boot
+            .then(|| SpeculativeBoot::new(self.main_worker_req_tx.clone()));
         let termination_tokens = &self.termination_tokens;
         let input_termination_token = termination_tokens.input.as_ref();
         let flags = self.flags;
@@ -468,7 +893,7 @@ impl Server {
             non_secure_listener.local_addr()?
         );
 
-        if let Some((_, addr)) = secure_listener.as_ref() {
+        if let Some((_, _, addr)) = secure_listener.as_ref() {
             debug!("edge-runtime is listening on {:?} (secure)", addr);
         }
 
@@ -483,16 +908,33 @@ impl Server {
         let graceful_exit_token = CancellationToken::new();
 
         let ServerFlags {
-            tcp_nodelay,
+            proxy_protocol,
             request_read_timeout_ms,
+            max_connections_per_ip,
             mut graceful_exit_deadline_sec,
             mut graceful_exit_keepalive_deadline_ms,
             ..
         } = flags;
 
         let request_read_timeout_dur = request_read_timeout_ms.map(Duration::from_millis);
+        let request_limits = RequestLimits::from_flags(&flags);
+        let conn_limiter = max_connections_per_ip
+            .map(|it| ConnLimiter::new(it, TRUSTED_PROXIES.get().cloned().unwrap_or_default()));
         let mut terminate_signal_fut = get_termination_signal();
 
+        if flags.lambda_runtime {
+            let Some(api) = crate::lambda::runtime_api() else {
+                bail!("lambda runtime mode requires AWS_LAMBDA_RUNTIME_API to be set");
+            };
+
+            tokio::spawn(crate::lambda::run(
+                api,
+                self.main_worker_req_tx.clone(),
+                metric_src.clone(),
+                graceful_exit_token.clone(),
+            ));
+        }
+
         loop {
             let main_worker_req_tx = self.main_worker_req_tx.clone();
             let event_tx = event_tx.clone();
@@ -501,18 +943,29 @@ impl Server {
             tokio::select! {
                 msg = non_secure_listener.accept() => {
                     match msg {
-                        Ok((stream, _)) => {
-                            if tcp_nodelay {
-                                let _ = stream.set_nodelay(true);
-                            }
+                        Ok((stream, peer_addr)) => {
+                            tune_tcp_stream(&stream, &flags);
+
+                            let speculative_boot = speculative_boot.clone();
+                            let conn_limiter = conn_limiter.clone();
 
                             accept_stream(
-                                stream,
+                                async move {
+                                    let (stream, conn_info) =
+                                        accept_proxy_header(stream, peer_addr, proxy_protocol, conn_limiter).await?;
+
+                                    if let Some(speculative_boot) = speculative_boot {
+                                        speculative_boot.hint_from_stream(&stream).await;
+                                    }
+
+                                    Some((stream, conn_info))
+                                },
                                 main_worker_req_tx,
                                 event_tx,
                                 metric_src,
                                 graceful_exit_token.clone(),
-                                request_read_timeout_dur
+                                request_read_timeout_dur,
+                                request_limits
                             )
                         }
                         Err(e) => error!("socket error: {}", e)
@@ -520,7 +973,7 @@ impl Server {
                 }
 
                 msg = async {
-                    if let Some((listener, _addr)) = secure_listener.as_mut() {
+                    if let Some((listener, _, _)) = secure_listener.as_ref() {
                         listener.accept()
                     } else {
                         pending::<()>().await;
@@ -528,18 +981,47 @@ impl Server {
                     }.await
                 } => {
                     match msg {
-                        Ok((stream, _)) => {
-                            if tcp_nodelay {
-                                let _ = stream.get_ref().0.set_nodelay(true);
-                            }
+                        Ok((stream, peer_addr)) => {
+                            tune_tcp_stream(&stream, &flags);
+
+                            let config = secure_listener.as_ref().unwrap().1.clone();
+                            let speculative_boot = speculative_boot.clone();
+                            let conn_limiter = conn_limiter.clone();
 
                             accept_stream(
-                                stream,
+                                async move {
+                                    let (stream, conn_info) =
+                                        accept_proxy_header(stream, peer_addr, proxy_protocol, conn_limiter).await?;
+
+                                    let handshake = tls_handshake(stream, config, speculative_boot);
+
+                                    match timeout(TLS_HANDSHAKE_TIMEOUT, handshake).await {
+                                        Ok(Ok(stream)) => {
+                                            let client_cert = stream
+                                                .get_ref()
+                                                .1
+                                                .peer_certificates()
+                                                .and_then(|it| it.first())
+                                                .and_then(|it| ClientCertInfo::from_der(it));
+
+                                            Some((stream, ConnInfo { client_cert, ..conn_info }))
+                                        }
+                                        Ok(Err(e)) => {
+                                            error!("tls handshake failed: {}", e);
+                                            None
+                                        }
+                                        Err(_) => {
+                                            error!("tls handshake timed out");
+                                            None
+                                        }
+                                    }
+                                },
                                 main_worker_req_tx,
                                 event_tx,
                                 metric_src,
                                 graceful_exit_token.clone(),
-                                request_read_timeout_dur
+                                request_read_timeout_dur,
+                                request_limits
                             )
                         }
                         Err(e) => error!("socket error: {}", e)
@@ -702,20 +1184,180 @@ fn get_termination_signal() -> BoxFuture<'static, i32> {
     pending().boxed()
 }
 
-fn accept_stream<I>(
-    io: I,
+static TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+static DEFAULT_TCP_BACKLOG: i32 = 1024;
+
+/// Parses the address to listen on. Both IPv4 and IPv6 addresses are accepted,
+/// and IPv6 addresses may be enclosed in brackets (e.g. `[::]`).
+fn parse_ip_addr(ip: &str) -> Result<IpAddr, Error> {
+    let ip = ip
+        .strip_prefix('[')
+        .and_then(|it| it.strip_suffix(']'))
+        .unwrap_or(ip);
+
+    IpAddr::from_str(ip).with_context(|| format!("invalid ip address: {}", ip))
+}
+
+/// Binds a TCP listener on `addr`.
+///
+/// If `addr` is an IPv6 address, `dual_stack` decides whether the socket also
+/// accepts IPv4 connections (as IPv4-mapped addresses) regardless of the
+/// platform default.
+fn bind_tcp_listener(
+    addr: SocketAddr,
+    dual_stack: bool,
+    backlog: i32,
+) -> Result<TcpListener, Error> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+    if addr.is_ipv6() {
+        socket.set_only_v6(!dual_stack)?;
+    }
+
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket
+        .bind(&addr.into())
+        .with_context(|| format!("can't bind to {}", addr))?;
+    socket.listen(backlog)?;
+
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// Applies the TCP options of `flags` to an accepted connection. Failures are
+/// only logged, since the connection is still usable without them.
+fn tune_tcp_stream(stream: &TcpStream, flags: &ServerFlags) {
+    if flags.tcp_nodelay {
+        let _ = stream.set_nodelay(true);
+    }
+
+    let socket = SockRef::from(stream);
+
+    if let Some(secs) = flags.tcp_keepalive_secs {
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(secs));
+
+        if let Err(err) = socket.set_tcp_keepalive(&keepalive) {
+            warn!("failed to enable tcp keepalive: {}", err);
+        }
+    }
+
+    if let Some(ms) = flags.tcp_write_timeout_ms {
+        // NOTE: `SO_SNDTIMEO` has no effect on non-blocking sockets, so the
+        // write timeout is enforced by the kernel as the maximum time
+        // transmitted data may remain unacknowledged.
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Err(err) = socket.set_tcp_user_timeout(Some(Duration::from_millis(ms))) {
+            warn!("failed to set tcp write timeout: {}", err);
+        }
+
+        #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+        {
+            let _ = ms;
+            warn!("tcp write timeout is not supported on this platform");
+        }
+    }
+}
+
+/// Completes the TLS handshake, hinting the speculative boot with the SNI of
+/// the ClientHello as soon as it's read.
+async fn tls_handshake(
+    stream: TcpStream,
+    config: Arc<ServerConfig>,
+    speculative_boot: Option<SpeculativeBoot>,
+) -> std::io::Result<tokio_rustls::server::TlsStream<TcpStream>> {
+    let Some(speculative_boot) = speculative_boot else {
+        return TlsAcceptor::from(config).accept(stream).await;
+    };
+
+    // NOTE: Reading the ClientHello first lets the hint be sent before the
+    // rest of the handshake round trips take place.
+    let start = LazyConfigAcceptor::new(Acceptor::default(), stream).await?;
+
+    speculative_boot.hint(start.client_hello().server_name(), None);
+    start.into_stream(config).await
+}
+
+/// Reads the PROXY protocol header from the stream if `proxy_protocol` is
+/// enabled. Returns `None` if the connection should be dropped.
+///
+/// Also counts the connection against the per-client limit of
+/// `conn_limiter`, dropping it if the client already holds too many.
+async fn accept_proxy_header(
+    mut stream: TcpStream,
+    peer_addr: SocketAddr,
+    proxy_protocol: bool,
+    conn_limiter: Option<ConnLimiter>,
+) -> Option<(TcpStream, ConnInfo)> {
+    let client_addr = if proxy_protocol {
+        match crate::proxy_protocol::read_header(&mut stream).await {
+            Ok(client_addr) => {
+                trace!("proxy protocol client address: {:?}", client_addr);
+                client_addr
+            }
+
+            Err(e) => {
+                error!("failed to read proxy protocol header: {}", e);
+                return None;
+            }
+        }
+    } else {
+        None
+    };
+
+    let conn_guard = match conn_limiter {
+        Some(limiter) => {
+            let ip = client_addr.unwrap_or(peer_addr).ip();
+            let Some(guard) = limiter.try_acquire(ip) else {
+                debug!("too many connections from {}", ip);
+                return None;
+            };
+
+            Some(Arc::new(guard))
+        }
+
+        None => None,
+    };
+
+    Some((
+        stream,
+        ConnInfo {
+            peer_addr: Some(peer_addr),
+            client_addr,
+            _conn_guard: conn_guard,
+            ..Default::default()
+        },
+    ))
+}
+
+/// Accepts a connection once `io_fut` resolves to the stream and information
+/// about the connection. If it resolves to `None`, the connection is dropped.
+fn accept_stream<F, I>(
+    io_fut: F,
     req_tx: UnboundedSender<WorkerRequestMsg>,
     event_tx: Option<UnboundedSender<ServerEvent>>,
     metric_src: SharedMetricSource,
     graceful_exit_token: CancellationToken,
     maybe_req_read_timeout_dur: Option<Duration>,
+    request_limits: RequestLimits,
 ) where
+    F: Future<Output = Option<(I, ConnInfo)>> + Send + 'static,
     I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
     metric_src.incl_active_io();
     tokio::task::spawn({
         async move {
-            let (service, cancel) = WorkerService::new(metric_src.clone(), req_tx);
+            let _active_io_count_guard = scopeguard::guard(metric_src.clone(), |it| {
+                it.decl_active_io();
+            });
+
+            let Some((io, conn_info)) = io_fut.await else {
+                return;
+            };
+
+            let (io, early_hints) = crate::early_hints::Stream::new(io);
+            let (service, cancel) =
+                WorkerService::new(metric_src, req_tx, conn_info, request_limits, early_hints);
             let (io, maybe_timeout_tx) = if let Some(timeout_dur) = maybe_req_read_timeout_dur {
                 crate::timeout::Stream::with_timeout(io, timeout_dur)
             } else {
@@ -723,12 +1365,18 @@ fn accept_stream<I>(
             };
 
             let _guard = cancel.drop_guard();
-            let _active_io_count_guard = scopeguard::guard(metric_src, |it| {
-                it.decl_active_io();
-            });
 
             let mut shutting_down = false;
-            let conn_fut = Http::new()
+            let mut http = Http::new();
+
+            if let Some(size) = request_limits.max_header_size {
+                // NOTE: hyper requires the read buffer to hold at least 8KiB.
+                // Anything tighter is still enforced per request.
+                http.http1_max_buf_size(size.max(8192))
+                    .http2_max_header_list_size(size.try_into().unwrap_or(u32::MAX));
+            }
+
+            let conn_fut = http
                 .serve_connection(io, crate::timeout::Service::new(service, maybe_timeout_tx))
                 .with_upgrades();
 
@@ -760,3 +1408,47 @@ fn accept_stream<I>(
         }
     });
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_body_read_timeout_keeps_trailers() {
+        let (mut body_tx, body) = Body::channel();
+        let req = with_body_read_timeout(Request::new(body), Duration::from_secs(1));
+
+        drop(tokio::spawn(async move {
+            let mut trailers = http_v02::HeaderMap::new();
+
+            trailers.insert("grpc-status", http_v02::HeaderValue::from_static("0"));
+            body_tx.send_data("hello".into()).await.unwrap();
+            body_tx.send_trailers(trailers).await.unwrap();
+        }));
+
+        let mut body = req.into_body();
+
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+        assert!(body.data().await.is_none());
+        assert_eq!(body.trailers().await.unwrap().unwrap()["grpc-status"], "0");
+    }
+
+    #[tokio::test]
+    async fn test_body_read_timeout_fails_a_stalled_body() {
+        let (mut body_tx, body) = Body::channel();
+        let req = with_body_read_timeout(Request::new(body), Duration::from_millis(100));
+
+        body_tx.send_data("hello".into()).await.unwrap();
+
+        let mut body = req.into_body();
+
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+        assert!(timeout(Duration::from_secs(1), body.data())
+            .await
+            .unwrap()
+            .unwrap()
+            .is_err());
+
+        drop(body_tx);
+    }
+}
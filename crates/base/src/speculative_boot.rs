@@ -0,0 +1,140 @@
+//! Speculative boot of user workers on TCP connect.
+//!
+//! As soon as a connection is accepted, the likely target of its first request
+//! is guessed (from the request line and the `Host` header peeked off the
+//! socket, or from the TLS SNI) and handed to the main worker as a hint, so
+//! that it can start booting the worker while the rest of the request is still
+//! in transit.
+
+use std::time::Duration;
+
+use hyper_v014::{Body, Request};
+use log::trace;
+use sb_workers::context::WorkerRequestMsg;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+
+/// Path of the internal request the main worker receives a hint through.
+pub(crate) static SPECULATIVE_BOOT_PATH: &str = "/_internal/speculative-boot";
+
+/// Carries the path of the request line that was peeked, if any.
+pub(crate) static SPECULATIVE_PATH_HEADER: &str = "x-edge-runtime-speculative-path";
+
+static PEEK_TIMEOUT: Duration = Duration::from_millis(50);
+const PEEK_BUF_SIZE: usize = 2048;
+
+#[derive(Clone)]
+pub(crate) struct SpeculativeBoot {
+    main_worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+}
+
+impl SpeculativeBoot {
+    pub(crate) fn new(main_worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>) -> Self {
+        Self { main_worker_req_tx }
+    }
+
+    /// Peeks the start of the first request on `stream` without consuming it
+    /// and sends a hint built from it.
+    pub(crate) async fn hint_from_stream(&self, stream: &TcpStream) {
+        let mut buf = [0u8; PEEK_BUF_SIZE];
+        let len = match timeout(PEEK_TIMEOUT, stream.peek(&mut buf)).await {
+            Ok(Ok(len)) => len,
+            _ => 0,
+        };
+
+        let (path, host) = parse_request_target(&buf[..len]);
+
+        self.hint(host, path);
+    }
+
+    /// Asks the main worker to boot the worker that would serve a request for
+    /// `host` and `path`. The response of the main worker is discarded.
+    pub(crate) fn hint(&self, host: Option<&str>, path: Option<&str>) {
+        let mut builder = Request::builder()
+            .uri(SPECULATIVE_BOOT_PATH)
+            .header(http_v02::header::HOST, host.unwrap_or("localhost"));
+
+        if let Some(path) = path {
+            builder = builder.header(SPECULATIVE_PATH_HEADER, path);
+        }
+
+        let Ok(req) = builder.body(Body::empty()) else {
+            return;
+        };
+
+        let (res_tx, res_rx) = oneshot::channel();
+        let msg = WorkerRequestMsg {
+            req,
+            res_tx,
+            conn_token: None,
+        };
+
+        if self.main_worker_req_tx.send(msg).is_err() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            if let Ok(Err(err)) = res_rx.await {
+                trace!("speculative boot hint failed: {}", err);
+            }
+        });
+    }
+}
+
+/// Extracts the request target and the `Host` header from a (possibly partial)
+/// HTTP/1.x request head.
+fn parse_request_target(buf: &[u8]) -> (Option<&str>, Option<&str>) {
+    // NOTE: The peeked bytes may end in the middle of a multi-byte character.
+    let head = match std::str::from_utf8(buf) {
+        Ok(head) => head,
+        Err(err) => std::str::from_utf8(&buf[..err.valid_up_to()]).unwrap_or_default(),
+    };
+
+    // Only lines terminated by CRLF are complete.
+    let Some((head, _)) = head.rsplit_once("\r\n") else {
+        return (None, None);
+    };
+
+    let mut lines = head.split("\r\n");
+    let path = lines.next().and_then(|line| {
+        let mut parts = line.split(' ');
+        let (_method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+
+        version.starts_with("HTTP/").then_some(target)
+    });
+
+    let host = lines.take_while(|line| !line.is_empty()).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+
+        name.eq_ignore_ascii_case("host").then(|| value.trim())
+    });
+
+    (path, host)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_target() {
+        assert_eq!(
+            parse_request_target(b"GET /hello-world/foo HTTP/1.1\r\nHost: a.example.com\r\n\r\n"),
+            (Some("/hello-world/foo"), Some("a.example.com"))
+        );
+
+        assert_eq!(
+            parse_request_target(b"POST /hello-world HTTP/1.1\r\nContent-Le"),
+            (Some("/hello-world"), None)
+        );
+
+        assert_eq!(
+            parse_request_target(b"GET / HTTP/1.1\r\nHost: a.exam"),
+            (Some("/"), None)
+        );
+
+        assert_eq!(parse_request_target(b"GET /hello-wor"), (None, None));
+        assert_eq!(parse_request_target(b""), (None, None));
+    }
+}
@@ -0,0 +1,36 @@
+Deno.serve(async () => {
+    // The caches outlive the worker, so start from a clean slate.
+    await caches.delete("v1");
+
+    const cache = await caches.open("v1");
+    const url = "https://example.com/greeting";
+
+    await cache.put(
+        new Request(url, { headers: { "accept-language": "en" } }),
+        new Response("hello", { headers: { "vary": "Accept-Language" } }),
+    );
+
+    const hit = await cache.match(
+        new Request(url, { headers: { "accept-language": "en" } }),
+    );
+    const miss = await cache.match(
+        new Request(url, { headers: { "accept-language": "fr" } }),
+    );
+
+    let rejected = false;
+    try {
+        await cache.put(new Request(url, { method: "POST" }), new Response("nope"));
+    } catch (e) {
+        rejected = e instanceof TypeError;
+    }
+
+    const deleted = await cache.delete(url);
+
+    return Response.json({
+        hit: await hit?.text(),
+        miss: miss === undefined,
+        rejected,
+        deleted,
+        keys: await caches.keys(),
+    });
+});
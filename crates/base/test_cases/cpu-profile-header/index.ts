@@ -0,0 +1,4 @@
+// Reports whether the token of a profiled request reached the worker.
+Deno.serve((req: Request) =>
+	new Response(JSON.stringify({ token: req.headers.get('x-edge-runtime-cpu-profile') }))
+);
@@ -0,0 +1,8 @@
+// NOTE: The worker never calls `Deno.serve()`, its jobs are served anyway.
+Deno.cron("tick", "*/5 * * * *", () => {
+	console.log("tick");
+});
+
+Deno.cron("fail", "0 0 * * *", { signal: new AbortController().signal }, () => {
+	throw new Error("boom");
+});
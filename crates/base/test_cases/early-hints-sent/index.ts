@@ -0,0 +1,8 @@
+Deno.serve(async (req) => {
+	EdgeRuntime.sendEarlyHints(req, ['</style.css>; rel=preload; as=style']);
+	EdgeRuntime.sendEarlyHints(req, []);
+
+	await new Promise((resolve) => setTimeout(resolve, 100));
+
+	return new Response('meow');
+});
@@ -0,0 +1,7 @@
+Deno.serve(() => {
+	return new Response('meow', {
+		headers: {
+			'x-early-hints': '</style.css>; rel=preload; as=style',
+		},
+	});
+});
@@ -0,0 +1,2 @@
+// Streams the request body back as it arrives.
+Deno.serve((req: Request) => new Response(req.body));
@@ -0,0 +1,5 @@
+Deno.serve(() => {
+	const { executionId, deadline } = EdgeRuntime.context;
+
+	return Response.json({ executionId, deadline });
+});
@@ -0,0 +1,11 @@
+Deno.serve(() => {
+	const { executionId, serviceName, deadline, remaining } = EdgeRuntime.context;
+
+	return Response.json({
+		hasExecutionId: typeof executionId === "string" && executionId.length > 0,
+		serviceName,
+		hasDeadline: deadline > Date.now(),
+		hasCpuTime: remaining.cpuTimeMs > 0,
+		hasMemory: remaining.memoryBytes > 0 && remaining.memoryBytes < 150 * 1024 * 1024,
+	});
+});
@@ -0,0 +1,22 @@
+const encoder = new TextEncoder();
+
+Deno.serve((req) => {
+	const body = new ReadableStream({
+		start(controller) {
+			controller.enqueue(encoder.encode('meow'));
+
+			EdgeRuntime.setTrailers(req, {
+				'grpc-status': '0',
+				'grpc-message': 'ok',
+			});
+
+			controller.close();
+		},
+	});
+
+	return new Response(body, {
+		headers: {
+			'content-type': 'application/grpc',
+		},
+	});
+});
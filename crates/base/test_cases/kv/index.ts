@@ -0,0 +1,40 @@
+Deno.serve(async () => {
+    const kv = await Deno.openKv();
+
+    // The store outlives the worker, so start from a clean slate.
+    for await (const entry of kv.list({ prefix: ["users"] })) {
+        await kv.delete(entry.key);
+    }
+
+    const { versionstamp } = await kv.set(["users", "alice"], { age: 30 });
+    await kv.set(["users", "bob"], { age: 40 });
+    await kv.set(["users", "carol"], { age: 50 }, { expireIn: 1 });
+
+    const stale = await kv.atomic()
+        .check({ key: ["users", "alice"], versionstamp: null })
+        .set(["users", "alice"], { age: 0 })
+        .commit();
+
+    const fresh = await kv.atomic()
+        .check({ key: ["users", "alice"], versionstamp })
+        .set(["users", "alice"], { age: 31 })
+        .commit();
+
+    await new Promise((resolve) => setTimeout(resolve, 10));
+
+    const users = [];
+    for await (const entry of kv.list({ prefix: ["users"] }, { reverse: true })) {
+        users.push([entry.key[1], entry.value.age]);
+    }
+
+    const carol = await kv.get(["users", "carol"]);
+
+    kv.close();
+
+    return Response.json({
+        users,
+        stale: stale.ok,
+        fresh: fresh.ok,
+        carol: carol.value,
+    });
+});
@@ -0,0 +1,14 @@
+// Holds each request until the next one arrives, so a pair of requests only
+// completes if the worker serves both of them at once.
+let release: (() => void) | null = null;
+
+Deno.serve(async (req: Request) => {
+	if (release !== null) {
+		release();
+		release = null;
+	} else {
+		await new Promise<void>((resolve) => release = resolve);
+	}
+
+	return new Response(JSON.stringify({ key: req.headers.get('x-edge-runtime-request-key') }));
+});
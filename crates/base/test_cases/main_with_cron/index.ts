@@ -0,0 +1,28 @@
+// Runs the `Deno.cron()` jobs of `./test_cases/cron` the way the scheduler of
+// the main worker does, and forwards any other request as is.
+Deno.serve(async (req: Request) => {
+	const { pathname } = new URL(req.url);
+	const worker = await EdgeRuntime.userWorkers.create({
+		servicePath: './test_cases/cron',
+		memoryLimitMb: 150,
+		workerTimeoutMs: 10 * 60 * 1000,
+		cpuTimeSoftLimitMs: 10 * 60 * 1000,
+		cpuTimeHardLimitMs: 10 * 60 * 1000,
+		noModuleCache: false,
+		importMapPath: null,
+		envVars: [],
+	});
+
+	if (pathname === '/list') {
+		return await worker.fetch(new Request('http://localhost/'), { cron: 'list' });
+	}
+
+	if (pathname.startsWith('/run/')) {
+		const job = pathname.slice('/run/'.length);
+		const jobReq = new Request('http://localhost/', { method: 'POST' });
+
+		return await worker.fetch(jobReq, { cron: { run: job } });
+	}
+
+	return await worker.fetch(req);
+});
@@ -0,0 +1,17 @@
+// Forwards every request to `./test_cases/websocket-hibernation`, whose workers
+// hibernate once they have been idle for a second.
+Deno.serve(async (req: Request) => {
+	const worker = await EdgeRuntime.userWorkers.create({
+		servicePath: './test_cases/websocket-hibernation',
+		memoryLimitMb: 150,
+		workerTimeoutMs: 10 * 60 * 1000,
+		cpuTimeSoftLimitMs: 10 * 60 * 1000,
+		cpuTimeHardLimitMs: 10 * 60 * 1000,
+		hibernateAfterMs: 1000,
+		noModuleCache: false,
+		importMapPath: null,
+		envVars: [],
+	});
+
+	return await worker.fetch(req);
+});
@@ -0,0 +1,31 @@
+// Mirrors every request with `examples/main/mirror.ts`, as configured by
+// `MIRROR_CONFIG_PATH`, and forwards the original one as is.
+import { sampleMirror, sendMirror } from '../../../../examples/main/mirror.ts';
+
+const createWorker = (servicePath: string) =>
+	EdgeRuntime.userWorkers.create({
+		servicePath,
+		memoryLimitMb: 150,
+		workerTimeoutMs: 10 * 60 * 1000,
+		cpuTimeSoftLimitMs: 10 * 60 * 1000,
+		cpuTimeHardLimitMs: 10 * 60 * 1000,
+		noModuleCache: false,
+		importMapPath: null,
+		envVars: [],
+	});
+
+Deno.serve(async (req: Request) => {
+	const serviceName = new URL(req.url).pathname.split('/')[1];
+	const mirrored = sampleMirror(req, serviceName);
+
+	if (mirrored) {
+		sendMirror('./test_cases', serviceName, mirrored, async (servicePath, req, signal) => {
+			const worker = await createWorker(servicePath);
+			return await worker.fetch(req, { signal });
+		});
+	}
+
+	const worker = await createWorker(`./test_cases/${serviceName}`);
+
+	return await worker.fetch(req);
+});
@@ -0,0 +1,14 @@
+import { Buffer } from "buffer";
+import { EventEmitter } from "events";
+import { join } from "path";
+
+const emitter = new EventEmitter();
+
+Deno.serve(() => {
+    let message = "";
+
+    emitter.once("message", (it) => message = it);
+    emitter.emit("message", Buffer.from(join("meow", "meow")).toString("base64"));
+
+    return new Response(message);
+});
@@ -0,0 +1,16 @@
+let aborted = false;
+
+Deno.serve(async (req: Request) => {
+	const { pathname } = new URL(req.url);
+
+	// NOTE: The path is prefixed with the service name when the main worker
+	// forwards the request.
+	if (pathname.endsWith("/aborted")) {
+		return new Response(String(aborted));
+	}
+
+	await new Promise(resolve => req.signal.addEventListener("abort", resolve));
+
+	aborted = true;
+	return new Response(null, { status: 204 });
+});
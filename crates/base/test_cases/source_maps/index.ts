@@ -0,0 +1,13 @@
+interface Frame {
+	file: string;
+	line: number;
+}
+
+function currentFrame(): Frame {
+	const frame = new Error().stack!.split("\n")[1];
+	const [, file, line] = frame.match(/\((.+):(\d+):\d+\)$/)!;
+
+	return { file: file.split("/").pop()!, line: Number(line) };
+}
+
+Deno.serve(() => Response.json(currentFrame()));
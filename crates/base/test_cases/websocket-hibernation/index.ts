@@ -0,0 +1,14 @@
+// Echoes every message, prefixed with the state the hibernated worker left for
+// the connection once it has been picked back up.
+Deno.serve((req: Request) => {
+	const resumed = EdgeRuntime.getHibernationState(req);
+	const { socket, response } = Deno.upgradeWebSocket(req);
+
+	EdgeRuntime.setHibernationState(req, resumed ?? 'session-42');
+
+	socket.onmessage = ev => {
+		socket.send(resumed === null ? ev.data : `${resumed}:${ev.data}`);
+	};
+
+	return response;
+});
@@ -0,0 +1,13 @@
+Deno.serve((req: Request) => {
+	const { socket, response } = Deno.upgradeWebSocket(req, { protocol: "chat" });
+
+	socket.onopen = () => {
+		socket.send(socket.protocol);
+	};
+
+	socket.onmessage = ev => {
+		socket.send(ev.data);
+	};
+
+	return response;
+});
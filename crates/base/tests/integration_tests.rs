@@ -19,7 +19,11 @@ use anyhow::Context;
 use async_tungstenite::WebSocketStream;
 use base::{
     integration_test, integration_test_listen_fut, integration_test_with_server_flag,
-    rt_worker::worker_ctx::{create_user_worker_pool, create_worker, TerminationToken},
+    retirement::RetirementBudgets,
+    rt_worker::{
+        worker_ctx::{create_user_worker_pool, create_worker, TerminationToken},
+        worker_pool::{SupervisorPolicy, WorkerPoolPolicy},
+    },
     server::{ServerEvent, ServerFlags, ServerHealth, Tls},
     DecoratorType,
 };
@@ -27,7 +31,10 @@ use deno_core::serde_json;
 use futures_util::{future::BoxFuture, Future, FutureExt, SinkExt, StreamExt};
 use http::{Method, Request, Response as HttpResponse, StatusCode};
 use http_utils::utils::get_upgrade_type;
-use hyper::{body::to_bytes, Body};
+use hyper::{
+    body::{to_bytes, HttpBody},
+    Body,
+};
 use reqwest::{
     header,
     multipart::{Form, Part},
@@ -243,6 +250,43 @@ async fn test_not_trigger_pku_sigsegv_due_to_jit_compilation_non_cli() {
     main_termination_token.cancel_and_wait().await;
 }
 
+#[tokio::test]
+#[serial]
+async fn test_cpu_profile_token_is_not_seen_by_user_workers() {
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::GET,
+            format!("http://localhost:{}/cpu-profile-header", NON_SECURE_PORT),
+        )
+        .header("x-edge-runtime-cpu-profile", "s3cret")
+        .body(Body::empty())
+        .build()
+        .unwrap();
+
+    let request_builder = Some(RequestBuilder::from_parts(client, req));
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+
+            assert_eq!(res.status().as_u16(), 200);
+            assert_eq!(
+                res.json::<serde_json::Value>().await.unwrap(),
+                serde_json::json!({ "token": null })
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
 #[tokio::test]
 #[serial]
 async fn test_main_worker_options_request() {
@@ -285,6 +329,235 @@ async fn test_main_worker_options_request() {
     );
 }
 
+#[tokio::test]
+#[serial]
+async fn test_speculative_boot_path_is_refused_to_clients() {
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::GET,
+            format!(
+                "http://localhost:{}/_internal/speculative-boot",
+                NON_SECURE_PORT
+            ),
+        )
+        .header("x-edge-runtime-speculative-path", "/std_user_worker")
+        .build()
+        .unwrap();
+
+    let request_builder = Some(RequestBuilder::from_parts(client, req));
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            assert_eq!(resp.unwrap().status().as_u16(), 404);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_body_streams_into_user_worker() {
+    let (body_tx, mut body_rx) = mpsc::unbounded_channel::<Result<&'static str, io::Error>>();
+    let body = Body::wrap_stream(futures_util::stream::poll_fn(move |cx| {
+        body_rx.poll_recv(cx)
+    }));
+
+    body_tx.send(Ok("ping")).unwrap();
+
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::POST,
+            format!("http://localhost:{}/echo-stream", NON_SECURE_PORT),
+        )
+        .body(body)
+        .build()
+        .unwrap();
+
+    let request_builder = Some(RequestBuilder::from_parts(client, req));
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async move {
+            let mut res = resp.unwrap();
+            assert!(res.status().as_u16() == 200);
+
+            // The worker echoes the first chunk before the request body ends.
+            let mut echoed = vec![];
+
+            while echoed.len() < 4 {
+                echoed.extend_from_slice(&res.chunk().await.unwrap().unwrap());
+            }
+
+            assert_eq!(echoed, b"ping");
+
+            body_tx.send(Ok("pong")).unwrap();
+            drop(body_tx);
+
+            assert_eq!(res.bytes().await.unwrap(), "pong");
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_signal_aborts_on_client_disconnect() {
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::GET,
+            format!("http://localhost:{}/", NON_SECURE_PORT),
+        )
+        .timeout(Duration::from_millis(500))
+        .build()
+        .unwrap();
+
+    let request_builder = Some(RequestBuilder::from_parts(client, req));
+
+    integration_test!(
+        "./test_cases/request-signal",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            // The client gives up on the request while the handler waits for
+            // its signal.
+            assert!(resp.unwrap_err().is_timeout());
+
+            sleep(Duration::from_millis(500)).await;
+
+            let res = reqwest::get(format!("http://localhost:{}/aborted", NON_SECURE_PORT))
+                .await
+                .unwrap();
+
+            assert!(res.status().as_u16() == 200);
+            assert_eq!(res.text().await.unwrap(), "true");
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_signal_aborts_on_client_disconnect_in_user_worker() {
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::GET,
+            format!("http://localhost:{}/request-signal", NON_SECURE_PORT),
+        )
+        .timeout(Duration::from_millis(500))
+        .build()
+        .unwrap();
+
+    let request_builder = Some(RequestBuilder::from_parts(client, req));
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            // The client gives up on the request while the user worker waits
+            // for its signal, which has to follow the main worker's connection.
+            assert!(resp.unwrap_err().is_timeout());
+
+            sleep(Duration::from_millis(500)).await;
+
+            let res = reqwest::get(format!(
+                "http://localhost:{}/request-signal/aborted",
+                NON_SECURE_PORT
+            ))
+            .await
+            .unwrap();
+
+            assert!(res.status().as_u16() == 200);
+            assert_eq!(res.text().await.unwrap(), "true");
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_deno_cron() {
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::GET,
+            format!("http://localhost:{}/list", NON_SECURE_PORT),
+        )
+        .build()
+        .unwrap();
+
+    let request_builder = Some(RequestBuilder::from_parts(client.clone(), req));
+
+    integration_test!(
+        "./test_cases/main_with_cron",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async move {
+            let res = resp.unwrap();
+            assert!(res.status().as_u16() == 200);
+            assert_eq!(
+                res.json::<serde_json::Value>().await.unwrap(),
+                serde_json::json!([
+                    { "name": "tick", "schedule": "*/5 * * * *" },
+                    { "name": "fail", "schedule": "0 0 * * *" },
+                ])
+            );
+
+            for (job, status) in [("tick", 204), ("fail", 500), ("missing", 404)] {
+                let res = client
+                    .post(format!("http://localhost:{}/run/{}", NON_SECURE_PORT, job))
+                    .send()
+                    .await
+                    .unwrap();
+
+                assert_eq!(res.status().as_u16(), status, "{}", job);
+            }
+
+            // Clients can't run the jobs by themselves, the request reaches
+            // the fallback server of the worker.
+            let res = client
+                .post(format!("http://localhost:{}/", NON_SECURE_PORT))
+                .header("x-edge-runtime-cron", "tick")
+                .header("x-edge-runtime-cron-request", "{\"run\":\"tick\"}")
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(res.status().as_u16(), 404);
+        }),
+        TerminationToken::new()
+    );
+}
+
 #[tokio::test]
 #[serial]
 async fn test_main_worker_post_request() {
@@ -786,6 +1059,26 @@ async fn test_node_server() {
     );
 }
 
+#[tokio::test]
+#[serial]
+async fn test_node_bare_built_in() {
+    integration_test!(
+        "./test_cases/node-bare-built-in",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            assert_eq!(res.status().as_u16(), 200);
+            assert_eq!(res.text().await.unwrap(), "bWVvdy9tZW93");
+        }),
+        TerminationToken::new()
+    );
+}
+
 #[tokio::test]
 #[serial]
 async fn test_tls_throw_invalid_data() {
@@ -830,6 +1123,244 @@ async fn test_user_worker_json_imports() {
     );
 }
 
+#[tokio::test]
+#[serial]
+async fn test_kv() {
+    integration_test!(
+        "./test_cases/kv",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            assert!(res.status().as_u16() == 200);
+
+            let body_bytes = res.bytes().await.unwrap();
+            assert_eq!(
+                body_bytes,
+                r#"{"users":[["bob",40],["alice",31]],"stale":false,"fresh":true,"carol":null}"#
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_caches() {
+    integration_test!(
+        "./test_cases/caches",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            assert!(res.status().as_u16() == 200);
+
+            let body_bytes = res.bytes().await.unwrap();
+            assert_eq!(
+                body_bytes,
+                r#"{"hit":"hello","miss":true,"rejected":true,"deleted":true,"keys":["v1"]}"#
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_execution_context() {
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "execution-context",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            assert!(res.status().as_u16() == 200);
+
+            let body_bytes = res.bytes().await.unwrap();
+            assert_eq!(
+                body_bytes,
+                concat!(
+                    r#"{"hasExecutionId":true,"serviceName":"execution-context","#,
+                    r#""hasDeadline":true,"hasCpuTime":true,"hasMemory":true}"#
+                )
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_execution_context_deadline_moves_per_request() {
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_per_request_policy(100000)
+        .build()
+        .await;
+
+    let mut contexts = vec![];
+
+    for delay_secs in [1, 0, 0] {
+        let mut res = tb
+            .request(|| {
+                Request::builder()
+                    .uri("/execution-context-deadline")
+                    .method("GET")
+                    .body(Body::empty())
+                    .context("can't make request")
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.status().as_u16(), StatusCode::OK);
+
+        let buf = to_bytes(res.body_mut()).await.unwrap();
+
+        contexts.push(serde_json::from_slice::<serde_json::Value>(&buf).unwrap());
+        sleep(Duration::from_secs(delay_secs)).await;
+    }
+
+    let (first, third) = (&contexts[0], &contexts[2]);
+
+    // The same worker serves every request, but the wall clock restarts once
+    // a request is done, which was a second after the first one here.
+    assert_eq!(first["executionId"], third["executionId"]);
+    assert!(
+        third["deadline"].as_u64().unwrap() >= first["deadline"].as_u64().unwrap() + 900,
+        "{first} {third}"
+    );
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_worker_is_retired_once_its_request_budget_is_spent() {
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_worker_pool_policy(
+            WorkerPoolPolicy::new(
+                SupervisorPolicy::PerRequest { oneshot: false },
+                1,
+                ServerFlags {
+                    request_wait_timeout_ms: Some(100000),
+                    ..Default::default()
+                },
+            )
+            .with_retirement_budgets(RetirementBudgets {
+                requests: Some(2),
+                ..Default::default()
+            }),
+        )
+        .build()
+        .await;
+
+    let mut execution_ids = vec![];
+
+    for _ in 0..3 {
+        let mut res = tb
+            .request(|| {
+                Request::builder()
+                    .uri("/execution-context-deadline")
+                    .method("GET")
+                    .body(Body::empty())
+                    .context("can't make request")
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.status().as_u16(), StatusCode::OK);
+
+        let buf = to_bytes(res.body_mut()).await.unwrap();
+        let context = serde_json::from_slice::<serde_json::Value>(&buf).unwrap();
+
+        execution_ids.push(context["executionId"].clone());
+
+        // Gives the supervisor time to retire the worker.
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    assert_eq!(execution_ids[0], execution_ids[1]);
+    assert_ne!(execution_ids[1], execution_ids[2]);
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_response_trailers_are_forwarded() {
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_per_request_policy(100000)
+        .build()
+        .await;
+
+    for te in [Some("trailers"), None] {
+        let mut res = tb
+            .request(|| {
+                let mut builder = Request::builder().uri("/grpc-trailers").method("POST");
+
+                if let Some(te) = te {
+                    builder = builder.header(header::TE, te);
+                }
+
+                builder.body(Body::empty()).context("can't make request")
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.status().as_u16(), StatusCode::OK);
+        assert_eq!(to_bytes(res.body_mut()).await.unwrap(), "meow");
+
+        let trailers = res.body_mut().trailers().await.unwrap();
+
+        if te.is_some() {
+            let trailers = trailers.unwrap();
+
+            assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+            assert_eq!(trailers.get("grpc-message").unwrap(), "ok");
+        } else {
+            // NOTE: Trailers are only sent to clients that take them.
+            assert!(trailers.is_none());
+        }
+    }
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_source_mapped_stack_trace() {
+    integration_test!(
+        "./test_cases/source_maps",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            assert!(res.status().as_u16() == 200);
+
+            // The frame points at the line of the TypeScript source rather than
+            // the one of the emitted code, which lost the interface above it.
+            let body_bytes = res.bytes().await.unwrap();
+            assert_eq!(body_bytes, r#"{"file":"index.ts","line":7}"#);
+        }),
+        TerminationToken::new()
+    );
+}
+
 #[tokio::test]
 #[serial]
 async fn test_user_imports_npm() {
@@ -915,12 +1446,46 @@ async fn req_failure_case_timeout() {
             let valid_output =
                 buf == "{\"msg\":\"InvalidWorkerCreation: worker did not respond in time\"}";
 
-            found_timeout = status_500 && valid_output;
-        }
+            found_timeout = status_500 && valid_output;
+        }
+    }
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+    assert!(found_timeout);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_requests_are_multiplexed_over_the_worker_connection() {
+    let tb = TestBedBuilder::new("./test_cases/main_multiplexed")
+        .build()
+        .await;
+
+    // NOTE: The key of a multiplexed request is set by the runtime alone, so
+    // the one a client sends never reaches the worker.
+    let req_body_fn = || {
+        Request::builder()
+            .uri("/")
+            .method("GET")
+            .header("x-edge-runtime-request-key", "0")
+            .body(Body::empty())
+            .context("can't make request")
+    };
+
+    let (res1, res2) = timeout(Duration::from_secs(10), async {
+        join!(tb.request(req_body_fn), tb.request(req_body_fn))
+    })
+    .await
+    .expect("concurrent requests blocked each other");
+
+    for res in [res1, res2] {
+        let mut res = res.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(to_bytes(res.body_mut()).await.unwrap(), "{\"key\":null}");
     }
 
     tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
-    assert!(found_timeout);
 }
 
 #[tokio::test]
@@ -1329,6 +1894,70 @@ async fn test_websocket_upgrade(maybe_tls: Option<Tls>, use_node_ws: bool) {
     );
 }
 
+#[tokio::test]
+#[serial]
+async fn test_websocket_upgrade_with_protocol() {
+    let nonce = tungstenite::handshake::client::generate_key();
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::GET,
+            format!(
+                "http://localhost:{}/websocket-upgrade-protocol",
+                NON_SECURE_PORT
+            ),
+        )
+        .header(header::CONNECTION, "upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_KEY, &nonce)
+        .header(header::SEC_WEBSOCKET_VERSION, "13")
+        .header(header::SEC_WEBSOCKET_PROTOCOL, "superchat, chat")
+        .build()
+        .unwrap();
+
+    let request_builder = Some(RequestBuilder::from_parts(client, req));
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+
+            assert!(res.status().as_u16() == 101);
+            assert_eq!(
+                res.headers().get(header::SEC_WEBSOCKET_PROTOCOL).unwrap(),
+                "chat"
+            );
+
+            let upgraded = res.upgrade().await.unwrap();
+            let mut ws = WebSocketStream::from_raw_socket(
+                upgraded.compat(),
+                tungstenite::protocol::Role::Client,
+                None,
+            )
+            .await;
+
+            // The socket of the worker knows the protocol it accepted.
+            assert_eq!(
+                ws.next().await.unwrap().unwrap().into_text().unwrap(),
+                "chat"
+            );
+
+            ws.send(Message::Text("meow".into())).await.unwrap();
+            assert_eq!(
+                ws.next().await.unwrap().unwrap().into_text().unwrap(),
+                "meow"
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
 #[tokio::test]
 #[serial]
 async fn test_graceful_shutdown() {
@@ -1438,6 +2067,67 @@ async fn test_websocket_upgrade_node_secure() {
     test_websocket_upgrade(new_localhost_tls(true), true).await;
 }
 
+#[tokio::test]
+#[serial]
+async fn test_websocket_is_picked_back_up_after_hibernation() {
+    let nonce = tungstenite::handshake::client::generate_key();
+    let client = reqwest_v011::Client::new();
+    let req = client
+        .request(
+            Method::GET,
+            format!("http://localhost:{}/", NON_SECURE_PORT),
+        )
+        .header(header::CONNECTION, "upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_KEY, &nonce)
+        .header(header::SEC_WEBSOCKET_VERSION, "13")
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test!(
+        "./test_cases/main_with_hibernation",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+
+            assert_eq!(res.status().as_u16(), 101);
+
+            let upgraded = res.upgrade().await.unwrap();
+            let mut ws = WebSocketStream::from_raw_socket(
+                upgraded.compat(),
+                tungstenite::protocol::Role::Client,
+                None,
+            )
+            .await;
+
+            ws.send(Message::Text("meow".into())).await.unwrap();
+            assert_eq!(
+                ws.next().await.unwrap().unwrap().into_text().unwrap(),
+                "meow"
+            );
+
+            // The worker hibernates after a second of idling. The next message
+            // wakes a new one up, which gets the state the first one left.
+            sleep(Duration::from_secs(3)).await;
+
+            ws.send(Message::Text("meow!!".into())).await.unwrap();
+            assert_eq!(
+                ws.next().await.unwrap().unwrap().into_text().unwrap(),
+                "session-42:meow!!"
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
 async fn test_decorators(ty: Option<DecoratorType>) {
     let is_disabled = ty.is_none();
     let client = Client::new();
@@ -1589,6 +2279,29 @@ async fn test_slowloris<F, R>(request_read_timeout_ms: u64, maybe_tls: Option<Tl
 where
     F: (FnOnce(Box<dyn AsyncReadWrite>) -> R) + Send + 'static,
     R: Future<Output = bool> + Send,
+{
+    test_raw_connection(
+        "./test_cases/main",
+        ServerFlags {
+            request_read_timeout_ms: Some(request_read_timeout_ms),
+            ..Default::default()
+        },
+        maybe_tls,
+        test_fn,
+    )
+    .await;
+}
+
+/// Runs `test_fn` with a raw connection to the server, for checking what goes
+/// over the wire.
+async fn test_raw_connection<F, R>(
+    main_service_path: &str,
+    flags: ServerFlags,
+    maybe_tls: Option<Tls>,
+    test_fn: F,
+) where
+    F: (FnOnce(Box<dyn AsyncReadWrite>) -> R) + Send + 'static,
+    R: Future<Output = bool> + Send,
 {
     let token = TerminationToken::new();
 
@@ -1598,13 +2311,10 @@ where
     let mut listen_fut = integration_test_listen_fut!(
         NON_SECURE_PORT,
         maybe_tls,
-        "./test_cases/main",
+        main_service_path,
         None,
         None,
-        ServerFlags {
-            request_read_timeout_ms: Some(request_read_timeout_ms),
-            ..Default::default()
-        },
+        flags,
         health_tx,
         Some(token.clone())
     );
@@ -1692,6 +2402,121 @@ async fn test_slowloris_no_prompt_timeout(maybe_tls: Option<Tls>, invert: bool)
     .await;
 }
 
+#[tokio::test]
+#[serial]
+async fn test_early_hints_are_sent_ahead_of_the_response() {
+    test_slowloris(u64::MAX, new_localhost_tls(false), |mut io| async move {
+        static HEADER: &[u8] =
+            b"GET /early-hints HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+        io.write_all(HEADER).await.unwrap();
+        io.flush().await.unwrap();
+
+        let mut buf = vec![];
+
+        io.read_to_end(&mut buf).await.unwrap();
+
+        let res = String::from_utf8(buf).unwrap();
+        let (hints, res) = res.split_once("\r\n\r\n").unwrap();
+
+        hints == "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload; as=style"
+            && res.starts_with("HTTP/1.1 200 OK\r\n")
+            && res.contains("link: </style.css>; rel=preload; as=style\r\n")
+            && !res.contains("x-early-hints")
+            && res.contains("meow")
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_early_hints_are_sent_by_the_worker_before_it_responds() {
+    test_slowloris(u64::MAX, new_localhost_tls(false), |mut io| async move {
+        static HEADER: &[u8] =
+            b"GET /early-hints-sent HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+        io.write_all(HEADER).await.unwrap();
+        io.flush().await.unwrap();
+
+        let mut buf = vec![];
+
+        io.read_to_end(&mut buf).await.unwrap();
+
+        let res = String::from_utf8(buf).unwrap();
+        let (hints, res) = res.split_once("\r\n\r\n").unwrap();
+
+        // NOTE: The hints sent without links don't make it to the client.
+        hints == "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload; as=style"
+            && res.starts_with("HTTP/1.1 200 OK\r\n")
+            && !res.contains("103 Early Hints")
+            && res.contains("meow")
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_mirrored_requests_keep_early_hints_and_trailers() {
+    std::env::set_var(
+        "MIRROR_CONFIG_PATH",
+        "./test_cases/main_with_mirror/mirrors.json",
+    );
+
+    let tb = TestBedBuilder::new("./test_cases/main_with_mirror")
+        .with_per_request_policy(100000)
+        .build()
+        .await;
+
+    let mut res = tb
+        .request(|| {
+            Request::builder()
+                .uri("/grpc-trailers")
+                .method("POST")
+                .header(header::TE, "trailers")
+                .body(Body::empty())
+                .context("can't make request")
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(res.status().as_u16(), StatusCode::OK);
+    assert_eq!(to_bytes(res.body_mut()).await.unwrap(), "meow");
+
+    let trailers = res.body_mut().trailers().await.unwrap().unwrap();
+
+    assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+    assert_eq!(trailers.get("grpc-message").unwrap(), "ok");
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+
+    test_raw_connection(
+        "./test_cases/main_with_mirror",
+        ServerFlags::default(),
+        new_localhost_tls(false),
+        |mut io| async move {
+            static HEADER: &[u8] =
+                b"GET /early-hints-sent HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+            io.write_all(HEADER).await.unwrap();
+            io.flush().await.unwrap();
+
+            let mut buf = vec![];
+
+            io.read_to_end(&mut buf).await.unwrap();
+
+            let res = String::from_utf8(buf).unwrap();
+            let (hints, res) = res.split_once("\r\n\r\n").unwrap();
+
+            hints == "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload; as=style"
+                && res.starts_with("HTTP/1.1 200 OK\r\n")
+                && res.contains("meow")
+        },
+    )
+    .await;
+
+    std::env::remove_var("MIRROR_CONFIG_PATH");
+}
+
 #[tokio::test]
 #[serial]
 async fn test_slowloris_no_prompt_timeout_non_secure() {
@@ -2078,6 +2903,106 @@ async fn test_request_idle_timeout_websocket_node_secure() {
     test_request_idle_timeout_websocket_deno(new_localhost_tls(true), true).await;
 }
 
+async fn test_request_limits_long_uri(maybe_tls: Option<Tls>) {
+    let client = maybe_tls.client();
+    let req = client
+        .request(
+            Method::GET,
+            format!(
+                "{}://localhost:{}/{}",
+                maybe_tls.schema(),
+                maybe_tls.port(),
+                "meow".repeat(32)
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            max_uri_length: Some(64),
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        maybe_tls,
+        (|resp| async {
+            assert_eq!(resp.unwrap().status().as_u16(), StatusCode::URI_TOO_LONG);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_limits_long_uri_non_secure() {
+    test_request_limits_long_uri(new_localhost_tls(false)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_limits_long_uri_secure() {
+    test_request_limits_long_uri(new_localhost_tls(true)).await;
+}
+
+async fn test_request_limits_too_many_headers(maybe_tls: Option<Tls>) {
+    let client = maybe_tls.client();
+    let mut req = client.request(
+        Method::GET,
+        format!(
+            "{}://localhost:{}/std_user_worker",
+            maybe_tls.schema(),
+            maybe_tls.port(),
+        ),
+    );
+
+    for i in 0..8 {
+        req = req.header(format!("x-meow-{i}"), "meow");
+    }
+
+    let request_builder = Some(req);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            max_header_count: Some(4),
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        maybe_tls,
+        (|resp| async {
+            assert_eq!(
+                resp.unwrap().status().as_u16(),
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_limits_too_many_headers_non_secure() {
+    test_request_limits_too_many_headers(new_localhost_tls(false)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_limits_too_many_headers_secure() {
+    test_request_limits_too_many_headers(new_localhost_tls(true)).await;
+}
+
 #[tokio::test]
 #[serial]
 async fn test_should_not_hang_when_forced_redirection_for_specifiers() {
@@ -1,7 +1,8 @@
 use deno_core::v8;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct WorkerHeapStatistics {
     pub total_heap_size: usize,
@@ -33,7 +34,7 @@ impl From<&'_ v8::HeapStatistics> for WorkerHeapStatistics {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
 pub struct MemCheckState {
     pub current: WorkerHeapStatistics,
     pub exceeded: bool,
@@ -1,7 +1,10 @@
 use std::num::NonZeroUsize;
+use std::time::Duration;
 
 use once_cell::sync::Lazy;
 
+pub mod placement;
+
 pub const DEFAULT_PRIMARY_WORKER_POOL_SIZE: usize = 2;
 pub const DEFAULT_USER_WORKER_POOL_SIZE: usize = 1;
 
@@ -56,3 +59,31 @@ pub static USER_WORKER_RT: Lazy<tokio_util::task::LocalPoolHandle> = Lazy::new(|
         )
     })
 });
+
+/// CPU time after which a turn of the event loop of a user worker makes the
+/// worker yield its pool thread once the turn is over, so that the other workers
+/// sharing the thread run before it does again.
+///
+/// NOTE: This only yields between turns. V8 can't suspend a running isolate and
+/// resume it later, so a turn that overruns the slice still runs to completion
+/// before the next worker gets to run.
+pub static USER_WORKER_TIME_SLICE: Lazy<Option<Duration>> = Lazy::new(|| {
+    std::env::var("EDGE_RUNTIME_WORKER_TIME_SLICE_MS")
+        .ok()
+        .and_then(|it| it.parse::<u64>().ok())
+        .filter(|it| *it > 0)
+        .map(Duration::from_millis)
+});
+
+/// Opt-in limit on the number of time slices of CPU time a single turn may use.
+/// The supervisor terminates a worker whose turn goes over it (e.g. stuck in a
+/// busy loop) with `ShutdownReason::TurnTime`, rather than leave it to hold the
+/// pool thread until the CPU time hard limit. The worker is not requeued. Set
+/// with `EDGE_RUNTIME_WORKER_MAX_TURN_SLICES`; zero, the default, lets turns run
+/// for as long as they like.
+pub static USER_WORKER_MAX_TURN_SLICES: Lazy<u32> = Lazy::new(|| {
+    std::env::var("EDGE_RUNTIME_WORKER_MAX_TURN_SLICES")
+        .ok()
+        .and_then(|it| it.parse::<u32>().ok())
+        .unwrap_or(0)
+});
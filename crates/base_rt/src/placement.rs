@@ -0,0 +1,135 @@
+//! CPU affinity and scheduling priority of the threads running user workers,
+//! so that a heavy function load can't take the CPU from the thread accepting
+//! connections.
+
+use std::cell::Cell;
+
+use log::error;
+use once_cell::sync::Lazy;
+
+/// Env var holding the CPUs user worker threads are pinned to, e.g. `2-7,9`.
+pub const CPU_SET_ENV: &str = "EDGE_RUNTIME_WORKER_CPU_SET";
+/// Env var holding the nice value of user worker threads.
+pub const NICE_ENV: &str = "EDGE_RUNTIME_WORKER_NICE";
+
+#[derive(Debug, Clone, Default)]
+pub struct ThreadPlacement {
+    pub cpu_set: Option<Vec<usize>>,
+    /// Only lowering the priority (a positive value) is allowed without
+    /// privileges.
+    pub nice: Option<i32>,
+}
+
+pub static USER_WORKER_THREAD_PLACEMENT: Lazy<ThreadPlacement> = Lazy::new(|| ThreadPlacement {
+    cpu_set: std::env::var(CPU_SET_ENV)
+        .ok()
+        .and_then(|it| parse_cpu_set(&it)),
+    nice: std::env::var(NICE_ENV).ok().and_then(|it| it.parse().ok()),
+});
+
+thread_local! {
+    static PLACED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Applies [`USER_WORKER_THREAD_PLACEMENT`] to the calling thread, once per
+/// thread.
+pub fn place_user_worker_thread() {
+    if PLACED.with(|it| it.replace(true)) {
+        return;
+    }
+
+    if let Err(err) = USER_WORKER_THREAD_PLACEMENT.apply_to_current_thread() {
+        error!("failed to place user worker thread: {}", err);
+    }
+}
+
+impl ThreadPlacement {
+    #[cfg(target_os = "linux")]
+    pub fn apply_to_current_thread(&self) -> std::io::Result<()> {
+        if let Some(cpu_set) = self.cpu_set.as_ref() {
+            // SAFETY: `set` is a plain bit mask, and zero refers to the calling
+            // thread.
+            unsafe {
+                let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+
+                for cpu in cpu_set {
+                    libc::CPU_SET(*cpu, &mut set);
+                }
+
+                if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+
+        if let Some(nice) = self.nice {
+            // NOTE: On Linux, the nice value is a property of the thread rather
+            // than the process.
+            unsafe {
+                let tid = libc::gettid() as libc::id_t;
+
+                if libc::setpriority(libc::PRIO_PROCESS, tid, nice) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply_to_current_thread(&self) -> std::io::Result<()> {
+        if self.cpu_set.is_some() || self.nice.is_some() {
+            return Err(std::io::ErrorKind::Unsupported.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a CPU list like `0-3,8`. Returns `None` if it's malformed or empty.
+pub fn parse_cpu_set(value: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+
+    for part in value.split(',').map(str::trim) {
+        let (start, end) = part.split_once('-').unwrap_or((part, part));
+        let (start, end) = (
+            start.trim().parse::<usize>().ok()?,
+            end.trim().parse().ok()?,
+        );
+
+        if start > end || end >= libc_cpu_setsize() {
+            return None;
+        }
+
+        cpus.extend(start..=end);
+    }
+
+    (!cpus.is_empty()).then_some(cpus)
+}
+
+fn libc_cpu_setsize() -> usize {
+    #[cfg(target_os = "linux")]
+    {
+        libc::CPU_SETSIZE as usize
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        1024
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_set() {
+        assert_eq!(parse_cpu_set("0-2, 5"), Some(vec![0, 1, 2, 5]));
+        assert_eq!(parse_cpu_set("3"), Some(vec![3]));
+        assert_eq!(parse_cpu_set("3-1"), None);
+        assert_eq!(parse_cpu_set(""), None);
+        assert_eq!(parse_cpu_set("a"), None);
+    }
+}
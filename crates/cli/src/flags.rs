@@ -51,15 +51,118 @@ pub(super) fn get_cli() -> Command {
                 .global(true)
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            arg!(--"console-passthrough")
+                .help("Also print the console output of user workers when an event worker receives it")
+                .env("EDGE_RUNTIME_CONSOLE_PASSTHROUGH")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"allow-ffi")
+                .help("Let services load native libraries with Deno.dlopen() when their permissions set allow_ffi; a service asking for it is refused otherwise")
+                .env("EDGE_RUNTIME_ALLOW_FFI")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"share-broadcast-channel" <SERVICE_PATH>)
+                .help("Let the service at this path join the BroadcastChannel bus of the main worker when its function.toml sets share_broadcast_channel; a service asking for it is refused otherwise")
+                .env("EDGE_RUNTIME_SHARE_BROADCAST_CHANNEL")
+                .global(true)
+                .value_delimiter(',')
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"module-cache-dir" <DIR>)
+                .help("Directory of the module cache shared by all workers (default: $DENO_DIR)")
+                .env("EDGE_RUNTIME_MODULE_CACHE_DIR")
+                .global(true)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"heap-snapshot-dir" <DIR>)
+                .help("Directory a heap snapshot is written to when a worker is terminated for memory")
+                .env("EDGE_RUNTIME_HEAP_SNAPSHOT_DIR")
+                .global(true)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"heap-snapshot-max-count" <COUNT>)
+                .help("Number of heap snapshots kept in --heap-snapshot-dir; the oldest ones are removed to make room for new ones")
+                .env("EDGE_RUNTIME_HEAP_SNAPSHOT_MAX_COUNT")
+                .global(true)
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("10"),
+        )
+        .arg(
+            arg!(--"heap-snapshot-max-size-mb" <MB>)
+                .help("Size of all the heap snapshots kept in --heap-snapshot-dir; a snapshot that doesn't fit is dropped")
+                .env("EDGE_RUNTIME_HEAP_SNAPSHOT_MAX_SIZE_MB")
+                .global(true)
+                .value_parser(value_parser!(u64))
+                .default_value("2048"),
+        )
+        .arg(
+            arg!(--"secrets-dir" <DIR>)
+                .help("Directory holding the secrets of each service at the path of the service relative to --services-root, which are injected into its environment")
+                .env("EDGE_RUNTIME_SECRETS_DIR")
+                .global(true)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"services-root" <DIR>)
+                .help("Directory the path of a service is taken relative to when looking up its secrets in --secrets-dir; services outside of it get none. Defaults to the working directory")
+                .env("EDGE_RUNTIME_SERVICES_ROOT")
+                .global(true)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"cpu-profile-dir" <DIR>)
+                .help("Directory the CPU profiles of requests sent with --cpu-profile-token are written to")
+                .env("EDGE_RUNTIME_CPU_PROFILE_DIR")
+                .global(true)
+                .requires("cpu-profile-token")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"cpu-profile-token" <TOKEN>)
+                .help("Requests carrying this token in the x-edge-runtime-cpu-profile header are profiled")
+                .env("EDGE_RUNTIME_CPU_PROFILE_TOKEN")
+                .global(true)
+                .requires("cpu-profile-dir"),
+        )
+        // NOTE: V8 is started before the CLI is parsed, so the value is read
+        // from the raw arguments (see `base::deno_runtime`).
+        .arg(
+            arg!(--"v8-flags" <FLAGS>)
+                .help("Space separated flags V8 is started with, on top of $V8_FLAGS")
+                .global(true)
+                .allow_hyphen_values(true),
+        )
         .subcommand(get_start_command())
         .subcommand(get_bundle_command())
         .subcommand(get_unbundle_command())
+        .subcommand(get_schema_command())
+        .subcommand(get_module_cache_command())
+        .subcommand(get_user_worker_command())
 }
 
 fn get_start_command() -> Command {
     Command::new("start")
         .about("Start the server")
-        .arg(arg!(-i --ip <HOST>).help("Host IP address to listen on").default_value("0.0.0.0"))
+        .arg(
+            arg!(-i --ip <HOST>)
+                .help("Host IP address to listen on (IPv4 or IPv6)")
+                .default_value("0.0.0.0"),
+        )
+        .arg(
+            arg!(--"dual-stack")
+                .help("Accept IPv4 connections as well when listening on an IPv6 address")
+                .env("EDGE_RUNTIME_DUAL_STACK")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             arg!(-p --port <PORT>)
                 .help("Port to listen on")
@@ -88,6 +191,20 @@ fn get_start_command() -> Command {
                 .env("EDGE_RUNTIME_TLS_CERT_PATH")
                 .value_parser(value_parser!(PathBuf)),
         )
+        .arg(
+            arg!(--"tls-client-ca" <Path>)
+                .help("Path to PEM-encoded CA bundle used to verify client certificates (mTLS)")
+                .env("EDGE_RUNTIME_TLS_CLIENT_CA_PATH")
+                .requires("tls")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"tls-client-auth-optional")
+                .help("Accept clients that do not present a certificate when mTLS is enabled")
+                .env("EDGE_RUNTIME_TLS_CLIENT_AUTH_OPTIONAL")
+                .requires("tls-client-ca")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             arg!(--"main-service" <DIR>)
                 .help("Path to main service directory or eszip")
@@ -99,7 +216,11 @@ fn get_start_command() -> Command {
                 .default_value("false")
                 .value_parser(FalseyValueParser::new()),
         )
-        .arg(arg!(--"import-map" <Path>).help("Path to import map file"))
+        .arg(
+            arg!(--"import-map" <Path>)
+                .help("Path to import map file, or the import map itself as JSON or a data: URL")
+                .env("EDGE_RUNTIME_IMPORT_MAP"),
+        )
         .arg(arg!(--"event-worker" <Path>).help("Path to event worker directory"))
         .arg(arg!(--"main-entrypoint" <Path>).help("Path to entrypoint in main service (only for eszips)"))
         .arg(arg!(--"events-entrypoint" <Path>).help("Path to entrypoint in events worker (only for eszips)"))
@@ -157,6 +278,43 @@ fn get_start_command() -> Command {
                 .default_value("10000")
                 .value_parser(value_parser!(u64)),
         )
+        .arg(
+            arg!(--"main-worker-max-restarts" <COUNT>)
+                .help("Maximum number of times in a row the main worker is restarted after terminating unexpectedly")
+                .env("EDGE_RUNTIME_MAIN_WORKER_MAX_RESTARTS")
+                .default_value("5")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"main-worker-restart-backoff" <MILLISECONDS>)
+                .help("Delay before restarting the main worker, doubled on each further attempt")
+                .env("EDGE_RUNTIME_MAIN_WORKER_RESTART_BACKOFF")
+                .default_value("100")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"max-worker-memory-limit" <MiB>)
+                .help("Maximum memory limit of user workers; larger limits requested by the main worker are lowered to it")
+                .env("EDGE_RUNTIME_MAX_WORKER_MEMORY_LIMIT")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"max-worker-timeout" <MILLISECONDS>)
+                .help("Maximum wall clock limit of user workers; larger limits requested by the main worker are lowered to it")
+                .env("EDGE_RUNTIME_MAX_WORKER_TIMEOUT")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"max-worker-cpu-time" <MILLISECONDS>)
+                .help("Maximum CPU time limits of user workers; larger limits requested by the main worker are lowered to it")
+                .env("EDGE_RUNTIME_MAX_WORKER_CPU_TIME")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"request-queue-depth" <COUNT>)
+                .help("Maximum number of requests per service waiting for a worker to become available; further requests are rejected with 503")
+                .value_parser(value_parser!(usize)),
+        )
         .arg(
             arg!(--"request-idle-timeout" <MILLISECONDS>)
                 .help("Maximum time in milliseconds that can be waited from when a worker takes over the request (disabled by default)")
@@ -167,6 +325,35 @@ fn get_start_command() -> Command {
                 .help("Maximum time in milliseconds that can be waited from when the connection is accepted until the request body is fully read (disabled by default)")
                 .value_parser(value_parser!(u64)),
         )
+        .arg(
+            arg!(--"request-body-read-timeout" <MILLISECONDS>)
+                .help("Maximum time in milliseconds the client may go without sending any of the request body (disabled by default)")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"max-connections-per-ip" <COUNT>)
+                .help("Maximum number of concurrent connections from a single client address, other than a trusted proxy (unlimited by default)")
+                .env("EDGE_RUNTIME_MAX_CONNECTIONS_PER_IP")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"max-header-size" <BYTES>)
+                .help("Maximum total size of the request headers; larger requests are rejected with 431")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"max-header-count" <COUNT>)
+                .help(concat!(
+                    "Maximum number of request headers; requests with more are rejected with 431. ",
+                    "The count cannot be greater than 100."
+                ))
+                .value_parser(value_parser!(u32).range(1..=100).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"max-uri-length" <BYTES>)
+                .help("Maximum length of the request target; longer requests are rejected with 414")
+                .value_parser(value_parser!(usize)),
+        )
         .arg(
             arg!(--"inspect" [HOST_AND_PORT])
                 .help("Activate inspector on host:port")
@@ -198,12 +385,161 @@ fn get_start_command() -> Command {
                 .requires("inspector")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            arg!(--"inspect-service" <SERVICE>)
+                .help("Only attach the inspector to the workers of this service, given by its path or directory name (can be repeated)")
+                .requires("inspector")
+                .action(ArgAction::Append),
+        )
         .arg(arg!(--"static" <Path>).help("Glob pattern for static files to be included"))
         .arg(arg!(--"jsx-specifier" <Path> "A valid JSX specifier"))
         .arg(
             arg!(--"jsx-module" <Path> "A valid JSX module")
                 .value_parser(["jsx-runtime", "jsx-dev-runtime", "precompile", "react"]),
         )
+        .arg(
+            arg!(--"proxy-protocol")
+                .help("Expect a PROXY protocol (v1 or v2) header on every accepted connection")
+                .env("EDGE_RUNTIME_PROXY_PROTOCOL")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"watch")
+                .help("Recycle warm workers of a service whenever its files change, and have the main worker reload its function.toml")
+                .env("EDGE_RUNTIME_WATCH")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"speculative-boot")
+                .help("Start booting the likely target worker as soon as a connection is accepted")
+                .env("EDGE_RUNTIME_SPECULATIVE_BOOT")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"lambda-runtime")
+                .help("Also serve invocations polled from the AWS Lambda runtime API (requires AWS_LAMBDA_RUNTIME_API)")
+                .env("EDGE_RUNTIME_LAMBDA_RUNTIME")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"process-isolation")
+                .help("Boot every user worker in a child process of its own (unix only, per_worker policy)")
+                .env("EDGE_RUNTIME_PROCESS_ISOLATION")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"cgroup-root" <DIR>)
+                .help("cgroup v2 directory to place worker processes under, enforcing their memory and CPU limits (Linux only)")
+                .env("EDGE_RUNTIME_CGROUP_ROOT")
+                .requires("process-isolation")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"request-capture-sample-rate" <RATE>)
+                .help("Fraction of requests (0.0 to 1.0) whose full request/response is captured into the event stream")
+                .env("EDGE_RUNTIME_REQUEST_CAPTURE_SAMPLE_RATE")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--"request-capture-token" <TOKEN>)
+                .help("Always capture requests carrying this token in the `x-edge-runtime-capture` header")
+                .env("EDGE_RUNTIME_REQUEST_CAPTURE_TOKEN"),
+        )
+        .arg(
+            arg!(--"request-capture-max-body-bytes" <BYTES>)
+                .help("Maximum number of body bytes recorded per captured request and response")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"request-capture-redact-header" <NAME>)
+                .help("Additional header whose value is redacted from captured requests and responses")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"circuit-breaker-threshold" <COUNT>)
+                .help("Failures of a service within the window after which its requests are rejected with 503 for a cool-down period")
+                .env("EDGE_RUNTIME_CIRCUIT_BREAKER_THRESHOLD")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"circuit-breaker-window" <MILLISECONDS>)
+                .help("Window in which failures of a service are counted towards the circuit breaker threshold")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"circuit-breaker-cool-down" <MILLISECONDS>)
+                .help("How long requests to a service are rejected once its circuit is open")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"max-user-workers" <COUNT>)
+                .help("Maximum number of user workers across all services. Once reached, services waiting for a worker take turns, so that one busy service can't starve the others")
+                .env("EDGE_RUNTIME_MAX_USER_WORKERS")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"memory-pressure-threshold" <PERCENT>)
+                .help("Share of the host (or cgroup) memory in use above which idle workers are evicted, largest heap first")
+                .env("EDGE_RUNTIME_MEMORY_PRESSURE_THRESHOLD")
+                .value_parser(value_parser!(u8).range(1..=100)),
+        )
+        .arg(
+            arg!(--"memory-pressure-stall" <PERCENT>)
+                .help("Evict idle workers once tasks stalled on memory for this share of the last 10 seconds (Linux PSI)")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--"tenant-max-workers" <COUNT>)
+                .help("Maximum number of live workers per tenant. Requests that would boot another one are rejected with 429")
+                .env("EDGE_RUNTIME_TENANT_MAX_WORKERS")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"tenant-max-memory" <MEGABYTES>)
+                .help("Maximum heap size of the live workers of a tenant combined")
+                .env("EDGE_RUNTIME_TENANT_MAX_MEMORY")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"tenant-max-cpu-time" <MILLISECONDS>)
+                .help("Maximum CPU time the workers of a tenant may use within the quota window")
+                .env("EDGE_RUNTIME_TENANT_MAX_CPU_TIME")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"tenant-quota-window" <MILLISECONDS>)
+                .help("Window in which the CPU time of a tenant is counted towards its quota")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"worker-retire-after" <MILLISECONDS>)
+                .help("Retire user workers once they have been up for this long, after the request in flight")
+                .env("EDGE_RUNTIME_WORKER_RETIRE_AFTER")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"worker-retire-after-cpu-time" <MILLISECONDS>)
+                .help("Retire user workers once they have used this much CPU time")
+                .env("EDGE_RUNTIME_WORKER_RETIRE_AFTER_CPU_TIME")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"worker-retire-after-memory" <MEGABYTES>)
+                .help("Retire user workers once their heap has grown to this size")
+                .env("EDGE_RUNTIME_WORKER_RETIRE_AFTER_MEMORY")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"worker-retire-after-requests" <COUNT>)
+                .help("Retire user workers once they have served this many requests")
+                .env("EDGE_RUNTIME_WORKER_RETIRE_AFTER_REQUESTS")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"validate-config")
+                .help("Validate the configuration, report every problem found and exit without serving. Unknown keys in function.toml fail the validation here, but are only warned about by start")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             arg!(--"tcp-nodelay" [BOOL])
                 .help("Disables Nagle's algorithm")
@@ -213,6 +549,75 @@ fn get_start_command() -> Command {
                 .default_value("true")
                 .default_missing_value("true"),
         )
+        .arg(
+            arg!(--"trusted-proxy" <CIDR>)
+                .help("Network of a proxy allowed to set Forwarded/X-Forwarded-For; the client IP is taken from those headers only for such peers")
+                .env("EDGE_RUNTIME_TRUSTED_PROXIES")
+                .value_delimiter(',')
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"tls-ca-store" <STORES>)
+                .help("Certificate stores TLS connections of workers trust (mozilla, system); defaults to DENO_TLS_CA_STORE, or else mozilla")
+                .value_parser(["mozilla", "system"])
+                .value_delimiter(',')
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"tls-ca-file" <PATH>)
+                .help("PEM file of extra CA certificates TLS connections of workers trust")
+                .env("EDGE_RUNTIME_TLS_CA_FILES")
+                .value_delimiter(',')
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"kv-url" <URL>)
+                .help("Remote service keeping the stores of Deno.openKv(), shared by several runtimes; by default each service gets a SQLite database in the Deno dir")
+                .env("EDGE_RUNTIME_KV_URL"),
+        )
+        .arg(
+            arg!(--"kv-access-token" <TOKEN>)
+                .help("Bearer token sent to the remote service of --kv-url")
+                .env("EDGE_RUNTIME_KV_ACCESS_TOKEN")
+                .hide_env_values(true),
+        )
+        .arg(
+            arg!(--"dns-override" <HOST_IP>)
+                .help("Resolve HOST to IP for the connections of workers, given as HOST=IP")
+                .env("EDGE_RUNTIME_DNS_OVERRIDES")
+                .value_delimiter(',')
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"dns-resolver" <ADDR>)
+                .help("Nameserver the connections of workers resolve hostnames with, instead of the resolver of the host")
+                .env("EDGE_RUNTIME_DNS_RESOLVER"),
+        )
+        .arg(
+            arg!(--"tcp-keepalive" <SECONDS>)
+                .help("Idle time before TCP keepalive probes are sent on accepted connections")
+                .env("EDGE_RUNTIME_TCP_KEEPALIVE")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"tcp-write-timeout" <MILLISECONDS>)
+                .help("Maximum time written data may remain unacknowledged before the connection is dropped (Linux only). Reads are bounded by --request-read-timeout")
+                .env("EDGE_RUNTIME_TCP_WRITE_TIMEOUT")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"tcp-backlog" <COUNT>)
+                .help("Maximum number of pending connections in the accept queue")
+                .default_value("1024")
+                .value_parser(value_parser!(i32).range(1..)),
+        )
+        .arg(
+            arg!(--"module-cache-max-size" <MiB>)
+                .help("Evicts the least recently used remote modules once the module cache grows past this")
+                .env("EDGE_RUNTIME_MODULE_CACHE_MAX_SIZE")
+                .value_parser(value_parser!(u64)),
+        )
 }
 
 fn get_bundle_command() -> Command {
@@ -228,7 +633,10 @@ fn get_bundle_command() -> Command {
                 .required(true),
         )
         .arg(arg!(--"static" <Path>).help("Glob pattern for static files to be included"))
-        .arg(arg!(--"import-map" <Path>).help("Path to import map file"))
+        .arg(
+            arg!(--"import-map" <Path>)
+                .help("Path to import map file, or the import map itself as JSON or a data: URL"),
+        )
         .arg(
             arg!(--"decorator" <TYPE>)
                 .help("Type of decorator to use when bundling. If not specified, the decorator feature is disabled.")
@@ -256,3 +664,37 @@ fn get_unbundle_command() -> Command {
                 .required(true),
         )
 }
+
+fn get_schema_command() -> Command {
+    Command::new("schema")
+        .about("Prints the JSON schema of the events delivered to the event worker")
+}
+
+fn get_module_cache_command() -> Command {
+    Command::new("module-cache")
+        .about("Inspects or prunes the module cache shared by all workers")
+        .subcommand_required(true)
+        .subcommand(Command::new("info").about("Prints the location and size of the module cache"))
+        .subcommand(
+            Command::new("prune")
+                .about("Evicts the least recently used remote modules")
+                .arg(
+                    arg!(--"max-size" <MiB>)
+                        .help("Size to prune the module cache down to")
+                        .default_value("0")
+                        .value_parser(value_parser!(u64)),
+                ),
+        )
+}
+
+fn get_user_worker_command() -> Command {
+    Command::new("user-worker")
+        .about("Runs a single user worker in process isolation mode")
+        .hide(true)
+        .arg(
+            arg!(--"socket" <PATH>)
+                .help("Path of the unix socket to serve the worker on")
+                .required(true)
+                .value_parser(value_parser!(PathBuf)),
+        )
+}
@@ -1,28 +1,48 @@
 mod env;
 mod flags;
+mod validate;
 
 #[cfg(not(feature = "tracing"))]
 mod logger;
 
 use anyhow::{anyhow, bail, Error};
+use base::broadcast_channel::SHARED_BUS_SERVICES;
+use base::cert::{TlsCaConfig, TLS_CA_CONFIG};
+use base::client_ip::{TrustedProxies, TRUSTED_PROXIES};
 use base::commands::start_server;
-
+use base::cpu_profile::{CpuProfileConfig, CPU_PROFILE_CONFIG};
+use base::dns::{DnsConfig, DNS_CONFIG};
+use base::kv::{KvBackendConfig, KV_BACKEND};
+use base::permissions::FFI_ALLOWED;
+use base::retirement::RetirementBudgets;
+
+use base::rt_worker::circuit_breaker::CircuitBreakerPolicy;
+use base::rt_worker::memory_pressure::MemoryPressurePolicy;
+use base::rt_worker::request_capture::RequestCapturePolicy;
+use base::rt_worker::supervisor::{HeapSnapshotConfig, HEAP_SNAPSHOT_CONFIG};
+use base::rt_worker::tenant_quota::TenantQuotaPolicy;
 use base::rt_worker::worker_pool::{SupervisorPolicy, WorkerPoolPolicy};
+use base::secrets::{SecretsConfig, SECRETS_CONFIG};
 use base::server::{ServerFlags, Tls, WorkerEntrypoints};
-use base::{DecoratorType, InspectorOption};
+use base::utils::units::{bytes_to_display, mib_to_bytes};
+use base::{module_cache, DecoratorType, DenoDir, InspectorOption};
 use clap::ArgMatches;
 use deno_core::url::Url;
 use env::resolve_deno_runtime_env;
+use event_worker::events::WORKER_EVENT_SCHEMA;
+use event_worker::js_interceptors::CONSOLE_PASSTHROUGH;
 use flags::{get_cli, EszipV2ChecksumKind};
 use log::warn;
 use sb_graph::emitter::EmitterFactory;
-use sb_graph::import_map::load_import_map;
+use sb_graph::import_map::{is_inline_import_map, load_import_map};
 use sb_graph::{extract_from_file, generate_binary_eszip, include_glob_patterns_in_eszip};
 use std::fs::File;
 use std::io::Write;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 fn main() -> Result<(), anyhow::Error> {
     resolve_deno_runtime_env();
@@ -63,10 +83,127 @@ fn main() -> Result<(), anyhow::Error> {
             }
         }
 
+        if matches.get_flag("console-passthrough") {
+            std::env::set_var("EDGE_RUNTIME_CONSOLE_PASSTHROUGH", "true");
+            CONSOLE_PASSTHROUGH.store(true, Ordering::Relaxed);
+        }
+
+        // NOTE: Passed on through the environment as well, so that the workers
+        // run in process isolation honor it too.
+        if matches.get_flag("allow-ffi") {
+            std::env::set_var("EDGE_RUNTIME_ALLOW_FFI", "true");
+            FFI_ALLOWED.store(true, Ordering::Relaxed);
+        }
+
+        // NOTE: Resolved against the working directory the same way the path
+        // of a service is, and passed on through the environment so that the
+        // workers run in process isolation honor it too.
+        if let Some(paths) = matches.get_many::<PathBuf>("share-broadcast-channel") {
+            let cwd = std::env::current_dir()?;
+            let paths = paths.map(|it| cwd.join(it)).collect::<Vec<_>>();
+
+            std::env::set_var(
+                "EDGE_RUNTIME_SHARE_BROADCAST_CHANNEL",
+                paths
+                    .iter()
+                    .map(|it| it.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+            SHARED_BUS_SERVICES.set(paths).ok();
+        }
+
+        // NOTE: The module cache is located through `DENO_DIR`, so that the
+        // workers run in process isolation share it too.
+        if let Some(dir) = matches.get_one::<PathBuf>("module-cache-dir") {
+            std::env::set_var("DENO_DIR", dir);
+        }
+
+        // NOTE: Passed on through the environment as well, so that the workers
+        // run in process isolation write their snapshots there too.
+        if let Some(dir) = matches.get_one::<PathBuf>("heap-snapshot-dir") {
+            let max_count = matches
+                .get_one::<u64>("heap-snapshot-max-count")
+                .copied()
+                .unwrap();
+            let max_size_mb = matches
+                .get_one::<u64>("heap-snapshot-max-size-mb")
+                .copied()
+                .unwrap();
+
+            std::env::set_var("EDGE_RUNTIME_HEAP_SNAPSHOT_DIR", dir);
+            std::env::set_var(
+                "EDGE_RUNTIME_HEAP_SNAPSHOT_MAX_COUNT",
+                max_count.to_string(),
+            );
+            std::env::set_var(
+                "EDGE_RUNTIME_HEAP_SNAPSHOT_MAX_SIZE_MB",
+                max_size_mb.to_string(),
+            );
+            HEAP_SNAPSHOT_CONFIG
+                .set(HeapSnapshotConfig {
+                    dir: dir.clone(),
+                    max_count: max_count as usize,
+                    max_size_bytes: mib_to_bytes(max_size_mb),
+                })
+                .ok();
+        }
+
+        if let Some(dir) = matches.get_one::<PathBuf>("secrets-dir") {
+            let services_root = matches.get_one::<PathBuf>("services-root").cloned();
+
+            std::env::set_var("EDGE_RUNTIME_SECRETS_DIR", dir);
+
+            if let Some(root) = services_root.as_ref() {
+                std::env::set_var("EDGE_RUNTIME_SERVICES_ROOT", root);
+            }
+
+            SECRETS_CONFIG
+                .set(SecretsConfig {
+                    dir: dir.clone(),
+                    services_root,
+                })
+                .ok();
+        }
+
+        if let Some((dir, token)) = matches
+            .get_one::<PathBuf>("cpu-profile-dir")
+            .zip(matches.get_one::<String>("cpu-profile-token"))
+        {
+            std::env::set_var("EDGE_RUNTIME_CPU_PROFILE_DIR", dir);
+            std::env::set_var("EDGE_RUNTIME_CPU_PROFILE_TOKEN", token);
+            CPU_PROFILE_CONFIG
+                .set(CpuProfileConfig {
+                    dir: dir.clone(),
+                    token: token.clone(),
+                })
+                .ok();
+        }
+
         #[allow(clippy::single_match)]
         #[allow(clippy::arc_with_non_send_sync)]
         match matches.subcommand() {
             Some(("start", sub_matches)) => {
+                let report = validate::validate_start_args(sub_matches);
+
+                if sub_matches.get_flag("validate-config") {
+                    if report.is_ok() {
+                        println!("configuration is valid");
+                        return Ok(());
+                    }
+
+                    eprintln!("{}", report);
+                    bail!("found {} configuration problem(s)", report.problems.len());
+                }
+
+                for problem in report.warnings() {
+                    warn!("{}", problem);
+                }
+
+                if report.has_errors() {
+                    bail!("invalid configuration\n{}", report.errors());
+                }
+
                 let ip = sub_matches.get_one::<String>("ip").cloned().unwrap();
                 let port = sub_matches.get_one::<u16>("port").copied().unwrap();
 
@@ -83,7 +220,19 @@ fn main() -> Result<(), anyhow::Error> {
                         bail!("unable to load the key file or cert file");
                     };
 
-                    Some(Tls::new(port, &key_slice, &cert_slice)?)
+                    let tls = Tls::new(port, &key_slice, &cert_slice)?;
+
+                    if let Some(ca_path) = sub_matches.get_one::<PathBuf>("tls-client-ca") {
+                        let Ok(ca_slice) = std::fs::read(ca_path) else {
+                            bail!("unable to load the client ca file");
+                        };
+
+                        let optional = sub_matches.get_flag("tls-client-auth-optional");
+
+                        Some(tls.with_client_auth(&ca_slice, optional)?)
+                    } else {
+                        Some(tls)
+                    }
                 } else {
                     None
                 };
@@ -143,10 +292,37 @@ fn main() -> Result<(), anyhow::Error> {
                     sub_matches.get_one::<usize>("max-parallelism").cloned();
                 let maybe_request_wait_timeout =
                     sub_matches.get_one::<u64>("request-wait-timeout").cloned();
+                let maybe_request_queue_depth =
+                    sub_matches.get_one::<usize>("request-queue-depth").cloned();
+                let maybe_main_worker_max_restarts = sub_matches
+                    .get_one::<u32>("main-worker-max-restarts")
+                    .cloned();
+                let maybe_main_worker_restart_backoff = sub_matches
+                    .get_one::<u64>("main-worker-restart-backoff")
+                    .cloned();
+                let maybe_max_worker_memory_limit = sub_matches
+                    .get_one::<u64>("max-worker-memory-limit")
+                    .cloned();
+                let maybe_max_worker_timeout =
+                    sub_matches.get_one::<u64>("max-worker-timeout").cloned();
+                let maybe_max_worker_cpu_time =
+                    sub_matches.get_one::<u64>("max-worker-cpu-time").cloned();
                 let maybe_request_idle_timeout =
                     sub_matches.get_one::<u64>("request-idle-timeout").cloned();
                 let maybe_request_read_timeout =
                     sub_matches.get_one::<u64>("request-read-timeout").cloned();
+                let maybe_request_body_read_timeout = sub_matches
+                    .get_one::<u64>("request-body-read-timeout")
+                    .cloned();
+                let maybe_max_connections_per_ip = sub_matches
+                    .get_one::<usize>("max-connections-per-ip")
+                    .cloned()
+                    .filter(|it| *it > 0);
+                let maybe_max_header_size =
+                    sub_matches.get_one::<usize>("max-header-size").cloned();
+                let maybe_max_header_count =
+                    sub_matches.get_one::<usize>("max-header-count").cloned();
+                let maybe_max_uri_length = sub_matches.get_one::<usize>("max-uri-length").cloned();
                 let static_patterns =
                     if let Some(val_ref) = sub_matches.get_many::<String>("static") {
                         val_ref.map(|s| s.as_str()).collect::<Vec<&str>>()
@@ -174,17 +350,178 @@ fn main() -> Result<(), anyhow::Error> {
                 };
 
                 let tcp_nodelay = sub_matches.get_one::<bool>("tcp-nodelay").copied().unwrap();
+                let tcp_keepalive_secs = sub_matches.get_one::<u64>("tcp-keepalive").cloned();
+                let tcp_write_timeout_ms = sub_matches.get_one::<u64>("tcp-write-timeout").cloned();
+                let tcp_backlog = sub_matches.get_one::<i32>("tcp-backlog").cloned();
+                let dual_stack = sub_matches.get_flag("dual-stack");
+                let proxy_protocol = sub_matches.get_flag("proxy-protocol");
+                let speculative_boot = sub_matches.get_flag("speculative-boot");
+                let lambda_runtime = sub_matches.get_flag("lambda-runtime");
+                let process_isolation = sub_matches.get_flag("process-isolation");
+                let watch = sub_matches.get_flag("watch");
+
+                if let Some(values) = sub_matches.get_many::<String>("trusted-proxy") {
+                    let proxies = TrustedProxies::parse(values.map(String::as_str))?;
+                    let _ = TRUSTED_PROXIES.set(proxies);
+                }
+
+                let _ = TLS_CA_CONFIG.set(TlsCaConfig {
+                    stores: sub_matches
+                        .get_many::<String>("tls-ca-store")
+                        .map(|it| it.cloned().collect()),
+                    ca_files: sub_matches
+                        .get_many::<PathBuf>("tls-ca-file")
+                        .map(|it| it.cloned().collect())
+                        .unwrap_or_default(),
+                });
+
+                if let Some(url) = sub_matches.get_one::<String>("kv-url") {
+                    let _ = KV_BACKEND.set(KvBackendConfig::remote(
+                        url,
+                        sub_matches.get_one::<String>("kv-access-token").cloned(),
+                    )?);
+                }
+
+                let dns_overrides = sub_matches.get_many::<String>("dns-override");
+                let dns_resolver = sub_matches.get_one::<String>("dns-resolver");
+
+                if dns_overrides.is_some() || dns_resolver.is_some() {
+                    let config = DnsConfig::parse(
+                        dns_overrides.into_iter().flatten().map(String::as_str),
+                        dns_resolver.map(String::as_str),
+                    )?;
+
+                    let _ = DNS_CONFIG.set(config);
+                }
+
                 let flags = ServerFlags {
                     no_module_cache,
                     allow_main_inspector,
                     tcp_nodelay,
+                    tcp_keepalive_secs,
+                    tcp_write_timeout_ms,
+                    tcp_backlog,
+                    dual_stack,
+                    proxy_protocol,
+                    speculative_boot,
+                    watch,
                     graceful_exit_deadline_sec,
                     graceful_exit_keepalive_deadline_ms,
                     request_wait_timeout_ms: maybe_request_wait_timeout,
+                    request_queue_depth: maybe_request_queue_depth,
                     request_idle_timeout_ms: maybe_request_idle_timeout,
                     request_read_timeout_ms: maybe_request_read_timeout,
+                    request_body_read_timeout_ms: maybe_request_body_read_timeout,
+                    max_connections_per_ip: maybe_max_connections_per_ip,
+                    max_header_size: maybe_max_header_size,
+                    max_header_count: maybe_max_header_count,
+                    max_uri_length: maybe_max_uri_length,
+                    lambda_runtime,
+                    process_isolation,
+                    main_worker_max_restarts: maybe_main_worker_max_restarts,
+                    main_worker_restart_backoff_ms: maybe_main_worker_restart_backoff,
+                    max_worker_memory_limit_mb: maybe_max_worker_memory_limit,
+                    max_worker_timeout_ms: maybe_max_worker_timeout,
+                    max_worker_cpu_time_ms: maybe_max_worker_cpu_time,
                 };
 
+                if process_isolation {
+                    if cfg!(not(unix)) {
+                        bail!("process isolation is only supported on unix");
+                    }
+
+                    if maybe_supervisor_policy.is_some_and(|it| !it.is_per_worker()) {
+                        bail!("process isolation requires the per_worker policy");
+                    }
+                }
+
+                if let Some(root) = sub_matches.get_one::<PathBuf>("cgroup-root").cloned() {
+                    #[cfg(target_os = "linux")]
+                    base::rt_worker::cgroup::init_root(root)?;
+
+                    #[cfg(not(target_os = "linux"))]
+                    bail!("cgroups are only supported on linux: {}", root.display());
+                }
+
+                let worker_pool_policy = WorkerPoolPolicy::new(
+                    maybe_supervisor_policy,
+                    if let Some(true) = maybe_supervisor_policy
+                        .as_ref()
+                        .map(SupervisorPolicy::is_oneshot)
+                    {
+                        if let Some(parallelism) = maybe_max_parallelism {
+                            if parallelism == 0 || parallelism > 1 {
+                                warn!(
+                                    "{}",
+                                    concat!(
+                                        "if `oneshot` policy is enabled, the maximum ",
+                                        "parallelism is fixed to `1` as forcibly"
+                                    )
+                                );
+                            }
+                        }
+
+                        Some(1)
+                    } else {
+                        maybe_max_parallelism
+                    },
+                    flags,
+                );
+
+                let worker_pool_policy =
+                    if let Some(capture_policy) = get_request_capture_policy(sub_matches) {
+                        worker_pool_policy.with_request_capture(capture_policy)
+                    } else {
+                        worker_pool_policy
+                    };
+
+                let worker_pool_policy =
+                    if let Some(breaker_policy) = get_circuit_breaker_policy(sub_matches) {
+                        worker_pool_policy.with_circuit_breaker(breaker_policy)
+                    } else {
+                        worker_pool_policy
+                    };
+
+                let worker_pool_policy = if let Some(max_user_workers) =
+                    sub_matches.get_one::<usize>("max-user-workers").copied()
+                {
+                    worker_pool_policy.with_max_user_workers(max_user_workers)
+                } else {
+                    worker_pool_policy
+                };
+
+                let worker_pool_policy =
+                    if let Some(pressure_policy) = get_memory_pressure_policy(sub_matches) {
+                        worker_pool_policy.with_memory_pressure(pressure_policy)
+                    } else {
+                        worker_pool_policy
+                    };
+
+                let worker_pool_policy =
+                    if let Some(quota_policy) = get_tenant_quota_policy(sub_matches) {
+                        worker_pool_policy.with_tenant_quota(quota_policy)
+                    } else {
+                        worker_pool_policy
+                    };
+
+                let worker_pool_policy =
+                    worker_pool_policy.with_retirement_budgets(get_retirement_budgets(sub_matches));
+
+                let worker_pool_policy = worker_pool_policy.with_inspect_services(
+                    sub_matches
+                        .get_many::<String>("inspect-service")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                );
+
+                if let Some(max_size) = sub_matches.get_one::<u64>("module-cache-max-size") {
+                    module_cache::spawn_pruner(
+                        mib_to_bytes(*max_size),
+                        Duration::from_secs(5 * 60),
+                    );
+                }
+
                 start_server(
                     ip.as_str(),
                     port,
@@ -192,30 +529,7 @@ fn main() -> Result<(), anyhow::Error> {
                     main_service_path,
                     event_service_manager_path,
                     get_decorator_option(sub_matches),
-                    Some(WorkerPoolPolicy::new(
-                        maybe_supervisor_policy,
-                        if let Some(true) = maybe_supervisor_policy
-                            .as_ref()
-                            .map(SupervisorPolicy::is_oneshot)
-                        {
-                            if let Some(parallelism) = maybe_max_parallelism {
-                                if parallelism == 0 || parallelism > 1 {
-                                    warn!(
-                                        "{}",
-                                        concat!(
-                                            "if `oneshot` policy is enabled, the maximum ",
-                                            "parallelism is fixed to `1` as forcibly"
-                                        )
-                                    );
-                                }
-                            }
-
-                            Some(1)
-                        } else {
-                            maybe_max_parallelism
-                        },
-                        flags,
-                    )),
+                    Some(worker_pool_policy),
                     import_map_path,
                     flags,
                     None,
@@ -262,14 +576,21 @@ fn main() -> Result<(), anyhow::Error> {
                 let maybe_import_map = load_import_map(import_map_path.clone())
                     .map_err(|e| anyhow!("import map path is invalid ({})", e))?;
                 let mut maybe_import_map_url = None;
-                if maybe_import_map.is_some() {
-                    let abs_import_map_path =
-                        std::env::current_dir().map(|p| p.join(import_map_path.unwrap()))?;
-                    maybe_import_map_url = Some(
+                if let Some(import_map) = maybe_import_map.as_ref() {
+                    let import_map_path = import_map_path.unwrap();
+
+                    // NOTE: An inline import map has no file of its own, so it's
+                    // keyed by the directory its specifiers are resolved against.
+                    maybe_import_map_url = Some(if is_inline_import_map(&import_map_path) {
+                        import_map.base_url().join("import_map.json")?.to_string()
+                    } else {
+                        let abs_import_map_path =
+                            std::env::current_dir().map(|p| p.join(import_map_path))?;
+
                         Url::from_file_path(abs_import_map_path)
                             .map_err(|_| anyhow!("failed get import map url"))?
-                            .to_string(),
-                    );
+                            .to_string()
+                    });
                 }
                 let maybe_checksum_kind = sub_matches
                     .get_one::<EszipV2ChecksumKind>("checksum")
@@ -278,6 +599,8 @@ fn main() -> Result<(), anyhow::Error> {
 
                 emitter_factory.set_decorator_type(maybe_decorator);
                 emitter_factory.set_import_map(maybe_import_map.clone());
+                emitter_factory.set_package_json_dir(entrypoint_dir_path)?;
+                emitter_factory.set_lockfile_dir(entrypoint_dir_path)?;
 
                 let mut eszip = generate_binary_eszip(
                     &entrypoint_script_path,
@@ -317,6 +640,42 @@ fn main() -> Result<(), anyhow::Error> {
                     );
                 }
             }
+            Some(("schema", _)) => {
+                println!("{}", WORKER_EVENT_SCHEMA);
+            }
+            Some(("module-cache", sub_matches)) => {
+                let deno_dir = DenoDir::new(None)?;
+
+                match sub_matches.subcommand() {
+                    Some(("info", _)) => {
+                        let usage = module_cache::usage(&deno_dir)?;
+
+                        println!("location: {}", deno_dir.root_path_for_display());
+                        println!(
+                            "remote modules and emitted code: {} files, {}",
+                            usage.files,
+                            bytes_to_display(usage.size_bytes)
+                        );
+                    }
+                    Some(("prune", sub_matches)) => {
+                        let max_size = sub_matches.get_one::<u64>("max-size").copied().unwrap();
+                        let evicted = module_cache::prune(&deno_dir, mib_to_bytes(max_size))?;
+
+                        println!(
+                            "evicted {} files, {}",
+                            evicted.files,
+                            bytes_to_display(evicted.size_bytes)
+                        );
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            #[cfg(unix)]
+            Some(("user-worker", sub_matches)) => {
+                let socket_path = sub_matches.get_one::<PathBuf>("socket").cloned().unwrap();
+
+                base::rt_worker::process_worker::run_process_worker(socket_path).await?;
+            }
             _ => {
                 // unrecognized command
             }
@@ -339,6 +698,130 @@ fn get_decorator_option(sub_matches: &ArgMatches) -> Option<DecoratorType> {
         })
 }
 
+fn get_request_capture_policy(sub_matches: &ArgMatches) -> Option<RequestCapturePolicy> {
+    let sample_rate = sub_matches
+        .get_one::<f64>("request-capture-sample-rate")
+        .copied()
+        .unwrap_or(0.0);
+    let debug_token = sub_matches
+        .get_one::<String>("request-capture-token")
+        .cloned();
+
+    if sample_rate <= 0.0 && debug_token.is_none() {
+        return None;
+    }
+
+    let mut policy = RequestCapturePolicy {
+        sample_rate,
+        debug_token,
+        ..Default::default()
+    };
+
+    if let Some(max_body_bytes) = sub_matches
+        .get_one::<usize>("request-capture-max-body-bytes")
+        .copied()
+    {
+        policy.max_body_bytes = max_body_bytes;
+    }
+
+    if let Some(headers) = sub_matches.get_many::<String>("request-capture-redact-header") {
+        policy
+            .redact_headers
+            .extend(headers.map(|it| it.to_lowercase()));
+    }
+
+    Some(policy)
+}
+
+fn get_circuit_breaker_policy(sub_matches: &ArgMatches) -> Option<CircuitBreakerPolicy> {
+    let failure_threshold = sub_matches
+        .get_one::<usize>("circuit-breaker-threshold")
+        .copied()
+        .filter(|it| *it > 0)?;
+
+    let mut policy = CircuitBreakerPolicy {
+        failure_threshold,
+        ..Default::default()
+    };
+
+    if let Some(window_ms) = sub_matches
+        .get_one::<u64>("circuit-breaker-window")
+        .copied()
+    {
+        policy.window = Duration::from_millis(window_ms);
+    }
+    if let Some(cool_down_ms) = sub_matches
+        .get_one::<u64>("circuit-breaker-cool-down")
+        .copied()
+    {
+        policy.cool_down = Duration::from_millis(cool_down_ms);
+    }
+
+    Some(policy)
+}
+
+fn get_memory_pressure_policy(sub_matches: &ArgMatches) -> Option<MemoryPressurePolicy> {
+    let threshold = sub_matches
+        .get_one::<u8>("memory-pressure-threshold")
+        .copied();
+    let max_stall_pct = sub_matches.get_one::<f64>("memory-pressure-stall").copied();
+
+    if threshold.is_none() && max_stall_pct.is_none() {
+        return None;
+    }
+
+    let mut policy = MemoryPressurePolicy {
+        max_stall_pct,
+        ..Default::default()
+    };
+
+    if let Some(threshold) = threshold {
+        policy.max_used_ratio = threshold as f64 / 100.0;
+    }
+
+    Some(policy)
+}
+
+fn get_tenant_quota_policy(sub_matches: &ArgMatches) -> Option<TenantQuotaPolicy> {
+    let mut policy = TenantQuotaPolicy {
+        max_workers: sub_matches
+            .get_one::<usize>("tenant-max-workers")
+            .copied()
+            .filter(|it| *it > 0),
+        max_memory_mb: sub_matches
+            .get_one::<u64>("tenant-max-memory")
+            .copied()
+            .filter(|it| *it > 0),
+        max_cpu_time_ms: sub_matches
+            .get_one::<u64>("tenant-max-cpu-time")
+            .copied()
+            .filter(|it| *it > 0),
+        ..Default::default()
+    };
+
+    if let Some(window_ms) = sub_matches.get_one::<u64>("tenant-quota-window").copied() {
+        policy.window = Duration::from_millis(window_ms);
+    }
+
+    (!policy.is_empty()).then_some(policy)
+}
+
+fn get_retirement_budgets(sub_matches: &ArgMatches) -> RetirementBudgets {
+    RetirementBudgets {
+        wall_clock_ms: sub_matches.get_one::<u64>("worker-retire-after").copied(),
+        cpu_time_ms: sub_matches
+            .get_one::<u64>("worker-retire-after-cpu-time")
+            .copied(),
+        heap_size_bytes: sub_matches
+            .get_one::<u64>("worker-retire-after-memory")
+            .map(|it| mib_to_bytes(*it) as usize),
+        requests: sub_matches
+            .get_one::<usize>("worker-retire-after-requests")
+            .copied()
+            .filter(|it| *it > 0),
+    }
+}
+
 fn get_inspector_option(key: &str, addr: &SocketAddr) -> Result<InspectorOption, anyhow::Error> {
     match key {
         "inspect" => Ok(InspectorOption::Inspect(*addr)),
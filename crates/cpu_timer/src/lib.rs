@@ -178,12 +178,16 @@ impl CPUTimer {
 }
 
 pub fn get_thread_time() -> Result<i64, Error> {
+    clock_time_ns(libc::CLOCK_THREAD_CPUTIME_ID)
+}
+
+fn clock_time_ns(clock_id: libc::clockid_t) -> Result<i64, Error> {
     let mut time = libc::timespec {
         tv_sec: 0,
         tv_nsec: 0,
     };
 
-    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } == -1 {
+    if unsafe { libc::clock_gettime(clock_id, &mut time) } == -1 {
         return Err(std::io::Error::last_os_error().into());
     }
 
@@ -191,6 +195,35 @@ pub fn get_thread_time() -> Result<i64, Error> {
     Ok(time.tv_sec * 1_000_000_000 + time.tv_nsec)
 }
 
+/// The CPU time clock of a thread, which other threads can read too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadCPUClock(libc::clockid_t);
+
+impl ThreadCPUClock {
+    /// Returns the clock of the calling thread.
+    #[cfg(target_os = "linux")]
+    pub fn current() -> Result<Self, Error> {
+        let mut clock_id: libc::clockid_t = 0;
+        let errno = unsafe { libc::pthread_getcpuclockid(libc::pthread_self(), &mut clock_id) };
+
+        if errno != 0 {
+            return Err(std::io::Error::from_raw_os_error(errno).into());
+        }
+
+        Ok(Self(clock_id))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn current() -> Result<Self, Error> {
+        anyhow::bail!("thread CPU clock: not enabled (need Linux)")
+    }
+
+    /// Returns the CPU time the thread has used so far, in nanoseconds.
+    pub fn now(&self) -> Result<i64, Error> {
+        clock_time_ns(self.0)
+    }
+}
+
 #[cfg_attr(target_os = "linux", linux::ctor)]
 #[cfg(target_os = "linux")]
 fn register_sigalrm() {
@@ -1,10 +1,25 @@
 import { primordials, core } from "ext:core/mod.js";
 const { SymbolAsyncIterator } = primordials;
 
-const { op_event_accept } = core.ops;
+const { op_event_accept, op_event_accept_msgpack, op_event_schema } = core.ops;
 
 class SupabaseEventListener {
+	constructor(options = {}) {
+		// `msgpack` yields each event as raw msgpack bytes (see `schema()`),
+		// which is cheaper than building JS objects for high-volume sinks.
+		this.encoding = options.encoding ?? 'json';
+	}
+
+	static schema() {
+		return JSON.parse(op_event_schema());
+	}
+
 	async nextEvent() {
+		if (this.encoding === 'msgpack') {
+			const bytes = await op_event_accept_msgpack();
+			return { value: bytes ?? undefined, done: bytes === null };
+		}
+
 		try {
 			const reqEvt = await op_event_accept();
 			const done = reqEvt === 'Done';
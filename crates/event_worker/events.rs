@@ -1,17 +1,28 @@
 use base_mem_check::MemCheckState;
+use schemars::gen::SchemaSettings;
+use schemars::schema::RootSchema;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct BootEvent {
     pub boot_time: usize,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct BootFailureEvent {
     pub msg: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Sent before each attempt to restart a worker that terminated unexpectedly.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RestartEvent {
+    pub attempt: u32,
+    pub backoff_ms: u64,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct WorkerMemoryUsed {
     pub total: usize,
     pub heap: usize,
@@ -19,40 +30,58 @@ pub struct WorkerMemoryUsed {
     pub mem_check_captured: MemCheckState,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShutdownReason {
     WallClockTime,
     CPUTime,
     Memory,
     EarlyDrop,
     TerminationRequested,
+    /// Evicted after serving no request for the idle timeout of the worker.
+    Idle,
+    /// Hibernated with nothing but upgraded connections open, which are
+    /// picked back up by another worker.
+    Hibernated,
+    /// Recycled after serving the maximum number of requests of the worker.
+    MaxRequests,
+    /// Retired by a custom retirement policy.
+    Retired,
+    /// Terminated in the middle of a turn of its event loop that used too
+    /// much CPU time, e.g. stuck in a busy loop.
+    TurnTime,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct ShutdownEvent {
     pub reason: ShutdownReason,
     pub cpu_time_used: usize,
     pub memory_used: WorkerMemoryUsed,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct UncaughtExceptionEvent {
     pub exception: String,
     pub cpu_time_used: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct EventLoopCompletedEvent {
     pub cpu_time_used: usize,
+    /// Tasks registered through `EdgeRuntime.waitUntil`.
+    #[serde(default)]
+    pub background_tasks: usize,
+    /// Registered tasks that hadn't settled when the event loop completed.
+    #[serde(default)]
+    pub pending_background_tasks: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct LogEvent {
     pub msg: String,
     pub level: LogLevel,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub enum LogLevel {
     Debug,
     Info,
@@ -60,7 +89,67 @@ pub enum LogLevel {
     Error,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default)]
+pub struct CapturedMessage {
+    pub headers: Vec<(String, String)>,
+    /// Body bytes up to the capture limit, lossily decoded as UTF-8.
+    pub body: String,
+    pub body_truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RequestCaptureEvent {
+    pub method: String,
+    pub uri: String,
+    pub status: u16,
+    pub duration_ms: u64,
+    pub request: CapturedMessage,
+    pub response: CapturedMessage,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolActivity {
+    /// A worker was booted to serve the request.
+    ColdBoot,
+    /// A warm worker was reused to serve the request.
+    WarmHit,
+    /// A worker was removed from the pool.
+    Eviction,
+}
+
+/// Sent by the worker pool, so that autoscaling and capacity planning can be
+/// driven from the event stream.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct PoolEvent {
+    pub activity: PoolActivity,
+    /// Time the request waited for a worker slot. Always zero for warm hits
+    /// and evictions.
+    pub queue_wait_ms: u64,
+    /// User workers in the pool, across all services, after the activity.
+    pub pool_size: usize,
+}
+
+/// Memory usage of the host (or the cgroup the runtime runs in).
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy)]
+pub struct MemoryPressure {
+    pub used_bytes: u64,
+    pub total_bytes: u64,
+    /// Share of the last 10 seconds some tasks stalled on memory, in percent.
+    /// Only known if the kernel reports pressure stall information.
+    pub stall_pct: Option<f64>,
+}
+
+/// Sent by the worker pool for every idle worker it evicted to relieve memory
+/// pressure.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct MemoryPressureEvictionEvent {
+    pub pressure: MemoryPressure,
+    pub used_heap_size: usize,
+    pub uptime_ms: u64,
+}
+
+/// Externally tagged: a single-entry map from the event type to its payload.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub enum WorkerEvents {
     Boot(BootEvent),
     BootFailure(BootFailureEvent),
@@ -68,6 +157,10 @@ pub enum WorkerEvents {
     Shutdown(ShutdownEvent),
     EventLoopCompleted(EventLoopCompletedEvent),
     Log(LogEvent),
+    RequestCapture(RequestCaptureEvent),
+    Restart(RestartEvent),
+    Pool(PoolEvent),
+    MemoryPressureEviction(MemoryPressureEvictionEvent),
 }
 
 impl WorkerEvents {
@@ -85,18 +178,57 @@ impl WorkerEvents {
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug, Clone)]
 pub struct EventMetadata {
     pub service_path: Option<String>,
     pub execution_id: Option<Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Layout of a worker event. The msgpack encoding uses maps with the same
+/// field names as this schema.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct WorkerEventWithMetadata {
     pub event: WorkerEvents,
     pub metadata: EventMetadata,
 }
 
+/// JSON schema describing the layout of [`WorkerEventWithMetadata`], so that
+/// consumers in other languages can decode the binary encoding. Generated by
+/// [`worker_event_schema`]; run the tests with `UPDATE_SCHEMAS=1` to rewrite
+/// it after changing the events.
+pub const WORKER_EVENT_SCHEMA: &str = include_str!("schema/worker_event.schema.json");
+
+pub fn worker_event_schema() -> RootSchema {
+    let mut schema = SchemaSettings::draft2019_09()
+        .into_generator()
+        .into_root_schema_for::<WorkerEventWithMetadata>();
+
+    schema.schema.metadata().id =
+        Some("https://supabase.com/edge-runtime/worker_event.schema.json".to_string());
+
+    schema
+}
+
+impl WorkerEventWithMetadata {
+    /// Encodes the event as msgpack. Structs are encoded as maps keyed by
+    /// field name (see [`WORKER_EVENT_SCHEMA`]) and UUIDs as strings.
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        let mut buf = Vec::new();
+
+        self.serialize(
+            &mut rmp_serde::Serializer::new(&mut buf)
+                .with_struct_map()
+                .with_human_readable(),
+        )?;
+
+        Ok(buf)
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        Self::deserialize(&mut rmp_serde::Deserializer::new(bytes).with_human_readable())
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub enum RawEvent {
     Event(Box<WorkerEventWithMetadata>),
@@ -110,3 +242,33 @@ pub struct IncomingEvent {
     data: Option<Vec<u8>>,
     done: bool,
 }
+
+#[cfg(test)]
+mod test {
+    use deno_core::serde_json;
+
+    use super::*;
+
+    #[test]
+    fn test_worker_event_schema_is_generated_from_the_events() {
+        let generated = serde_json::to_string_pretty(&worker_event_schema()).unwrap() + "\n";
+
+        if std::env::var_os("UPDATE_SCHEMAS").is_some() {
+            std::fs::write(
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/schema/worker_event.schema.json"
+                ),
+                generated,
+            )
+            .unwrap();
+
+            return;
+        }
+
+        assert!(
+            WORKER_EVENT_SCHEMA == generated,
+            "schema/worker_event.schema.json is out of date, run the tests with `UPDATE_SCHEMAS=1`"
+        );
+    }
+}
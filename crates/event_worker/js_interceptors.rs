@@ -1,43 +1,91 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::events::{EventMetadata, LogEvent, LogLevel, WorkerEvents};
 use crate::WorkerEventWithMetadata;
 use deno_core::error::AnyError;
 use deno_core::op2;
 use deno_core::OpState;
-use log::error;
 use tokio::sync::mpsc;
 
+/// Also writes the console output of user workers to the stdout and stderr of
+/// the host when it is sent to the events worker, for local development.
+pub static CONSOLE_PASSTHROUGH: AtomicBool = AtomicBool::new(false);
+
+impl LogLevel {
+    /// Maps the level `console` passes to its print function: `0` for
+    /// `debug`, `1` for `log` and `info`, `2` for `warn` and `3` for `error`.
+    fn from_console_level(level: u32) -> Self {
+        match level {
+            0 => Self::Debug,
+            1 => Self::Info,
+            2 => Self::Warning,
+            _ => Self::Error,
+        }
+    }
+
+    fn as_log_level(&self) -> log::Level {
+        match self {
+            Self::Debug => log::Level::Debug,
+            Self::Info => log::Level::Info,
+            Self::Warning => log::Level::Warn,
+            Self::Error => log::Level::Error,
+        }
+    }
+}
+
 #[op2(fast)]
 fn op_user_worker_log(
     state: &mut OpState,
     #[string] msg: &str,
-    is_err: bool,
+    #[smi] level: u32,
 ) -> Result<(), AnyError> {
     let maybe_tx = state.try_borrow::<mpsc::UnboundedSender<WorkerEventWithMetadata>>();
-    let mut level = LogLevel::Info;
-    if is_err {
-        level = LogLevel::Error;
-    }
+    let level = LogLevel::from_console_level(level);
 
-    if let Some(tx) = maybe_tx {
-        let event_metadata = state
-            .try_borrow::<EventMetadata>()
-            .unwrap_or(&EventMetadata::default())
-            .clone();
-
-        let metadata = EventMetadata { ..event_metadata };
-
-        tx.send(WorkerEventWithMetadata {
-            event: WorkerEvents::Log(LogEvent {
-                msg: msg.to_string(),
-                level,
-            }),
-            metadata,
-        })?;
-    } else {
-        error!("[{:?}] {}", level, msg.to_string());
+    let Some(tx) = maybe_tx else {
+        log::log!(level.as_log_level(), "[{:?}] {}", level, msg.trim_end());
+        return Ok(());
+    };
+
+    if CONSOLE_PASSTHROUGH.load(Ordering::Relaxed) {
+        // NOTE: Written as is, like the console of Deno does, so a closed pipe
+        // is no reason to fail the call.
+        let _ = match level {
+            LogLevel::Debug | LogLevel::Info => std::io::stdout().write_all(msg.as_bytes()),
+            LogLevel::Warning | LogLevel::Error => std::io::stderr().write_all(msg.as_bytes()),
+        };
     }
 
+    let event_metadata = state
+        .try_borrow::<EventMetadata>()
+        .unwrap_or(&EventMetadata::default())
+        .clone();
+
+    let metadata = EventMetadata { ..event_metadata };
+
+    tx.send(WorkerEventWithMetadata {
+        event: WorkerEvents::Log(LogEvent {
+            msg: msg.to_string(),
+            level,
+        }),
+        metadata,
+    })?;
+
     Ok(())
 }
 
 deno_core::extension!(sb_events_js_interceptors, ops = [op_user_worker_log,],);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_console_levels() {
+        assert!(matches!(LogLevel::from_console_level(0), LogLevel::Debug));
+        assert!(matches!(LogLevel::from_console_level(1), LogLevel::Info));
+        assert!(matches!(LogLevel::from_console_level(2), LogLevel::Warning));
+        assert!(matches!(LogLevel::from_console_level(3), LogLevel::Error));
+    }
+}
@@ -1,7 +1,7 @@
-use crate::events::{RawEvent, WorkerEventWithMetadata};
+use crate::events::{RawEvent, WorkerEventWithMetadata, WORKER_EVENT_SCHEMA};
 use anyhow::{bail, Error};
 use deno_core::op2;
-use deno_core::OpState;
+use deno_core::{OpState, ToJsBuffer};
 use std::cell::RefCell;
 use std::rc::Rc;
 use tokio::sync::mpsc;
@@ -9,9 +9,7 @@ use tokio::sync::mpsc;
 pub mod events;
 pub mod js_interceptors;
 
-#[op2(async)]
-#[serde]
-async fn op_event_accept(state: Rc<RefCell<OpState>>) -> Result<RawEvent, Error> {
+async fn recv_event(state: Rc<RefCell<OpState>>) -> Result<Option<WorkerEventWithMetadata>, Error> {
     let rx = {
         let mut op_state = state.borrow_mut();
         op_state.try_take::<mpsc::UnboundedReceiver<WorkerEventWithMetadata>>()
@@ -26,14 +24,37 @@ async fn op_event_accept(state: Rc<RefCell<OpState>>) -> Result<RawEvent, Error>
     let mut op_state = state.borrow_mut();
     op_state.put::<mpsc::UnboundedReceiver<WorkerEventWithMetadata>>(rx);
 
-    match data {
+    Ok(data)
+}
+
+#[op2(async)]
+#[serde]
+async fn op_event_accept(state: Rc<RefCell<OpState>>) -> Result<RawEvent, Error> {
+    match recv_event(state).await? {
         Some(event) => Ok(RawEvent::Event(Box::new(event))),
         None => Ok(RawEvent::Done),
     }
 }
 
+/// Same as `op_event_accept`, but hands the event over as msgpack bytes.
+/// Returns `None` once the event stream is done.
+#[op2(async)]
+#[serde]
+async fn op_event_accept_msgpack(state: Rc<RefCell<OpState>>) -> Result<Option<ToJsBuffer>, Error> {
+    match recv_event(state).await? {
+        Some(event) => Ok(Some(event.to_msgpack()?.into())),
+        None => Ok(None),
+    }
+}
+
+#[op2]
+#[string]
+fn op_event_schema() -> &'static str {
+    WORKER_EVENT_SCHEMA
+}
+
 deno_core::extension!(
     sb_user_event_worker,
-    ops = [op_event_accept],
+    ops = [op_event_accept, op_event_accept_msgpack, op_event_schema],
     esm = ["event_worker.js"]
 );
@@ -8,6 +8,8 @@ use std::path::Path;
 use std::path::PathBuf;
 
 use deno_core::error::AnyError;
+use deno_core::futures::future::BoxFuture;
+use deno_core::futures::FutureExt;
 use deno_core::located_script_name;
 use deno_core::op2;
 use deno_core::url::Url;
@@ -53,6 +55,11 @@ use crate::global::global_template_middleware;
 
 pub trait NodePermissions {
     fn check_net_url(&mut self, url: &Url, api_name: &str) -> Result<(), AnyError>;
+    /// Checks the addresses the host of `url` resolves to, for the requests
+    /// sent through a client that resolves it on its own.
+    fn check_net_url_lookup(&self, _url: &Url) -> BoxFuture<'static, Result<(), AnyError>> {
+        async { Ok(()) }.boxed()
+    }
     #[inline(always)]
     fn check_read(&mut self, path: &Path) -> Result<(), AnyError> {
         self.check_read_with_api_name(path, None)
@@ -45,10 +45,13 @@ where
     let method = Method::from_bytes(&method)?;
     let url = Url::parse(&url)?;
 
-    {
+    // NOTE: The client of the state checks the addresses it connects to, while
+    // one created by user code resolves hosts on its own.
+    let lookup = {
         let permissions = state.borrow_mut::<P>();
         permissions.check_net_url(&url, "ClientRequest")?;
-    }
+        client_rid.map(|_| permissions.check_net_url_lookup(&url))
+    };
 
     let mut header_map = HeaderMap::new();
     for (key, value) in headers {
@@ -76,11 +79,18 @@ where
     let cancel_handle_ = cancel_handle.clone();
 
     let fut = async move {
-        request
-            .send()
-            .or_cancel(cancel_handle_)
-            .await
-            .map(|res| res.map_err(|err| type_error(err.to_string())))
+        async move {
+            if let Some(lookup) = lookup {
+                lookup.await?;
+            }
+
+            request
+                .send()
+                .await
+                .map_err(|err| type_error(err.to_string()))
+        }
+        .or_cancel(cancel_handle_)
+        .await
     };
 
     let request_rid = state
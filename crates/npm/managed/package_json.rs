@@ -3,7 +3,7 @@
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use deno_config::package_json::PackageJsonDepValue;
+use deno_config::package_json::{PackageJson, PackageJsonDepValue};
 use deno_config::workspace::Workspace;
 use deno_semver::package::PackageReq;
 
@@ -78,6 +78,26 @@ impl PackageJsonInstallDepsProvider {
         }
     }
 
+    /// Provides the npm dependencies of a single `package.json`, outside of
+    /// any workspace.
+    pub fn from_package_json(pkg_json: &PackageJson) -> Self {
+        let mut remote_pkg_reqs = pkg_json
+            .resolve_local_package_json_deps()
+            .into_values()
+            .filter_map(|dep| match dep {
+                Ok(PackageJsonDepValue::Req(pkg_req)) => Some(pkg_req),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        remote_pkg_reqs.sort();
+
+        Self {
+            remote_pkg_reqs,
+            workspace_pkgs: vec![],
+        }
+    }
+
     pub fn remote_pkg_reqs(&self) -> &Vec<PackageReq> {
         &self.remote_pkg_reqs
     }
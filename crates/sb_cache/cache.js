@@ -0,0 +1,206 @@
+import { primordials, core } from "ext:core/mod.js";
+import { Request, RequestPrototype } from "ext:deno_fetch/23_request.js";
+import { nullBodyStatus, Response } from "ext:deno_fetch/23_response.js";
+import { URL } from "ext:deno_url/00_url.js";
+
+const ops = core.ops;
+
+const {
+	ArrayPrototypeFilter,
+	ArrayPrototypeIncludes,
+	ArrayPrototypeMap,
+	ObjectPrototypeIsPrototypeOf,
+	StringPrototypeSplit,
+	StringPrototypeToLowerCase,
+	StringPrototypeTrim,
+	Symbol,
+	TypeError,
+	Uint8Array,
+} = primordials;
+
+const {
+	op_cache_storage_open,
+	op_cache_storage_has,
+	op_cache_storage_delete,
+	op_cache_storage_keys,
+	op_cache_put,
+	op_cache_match,
+	op_cache_delete,
+} = ops;
+
+const illegalConstructorKey = Symbol("illegalConstructorKey");
+
+function toRequest(request) {
+	if (ObjectPrototypeIsPrototypeOf(RequestPrototype, request)) {
+		return request;
+	}
+
+	return new Request(request);
+}
+
+// Entries are keyed by the URL of the request, without its fragment.
+function cacheKey(request) {
+	const url = new URL(request.url);
+
+	if (url.protocol !== "http:" && url.protocol !== "https:") {
+		throw new TypeError("Request url protocol must be 'http:' or 'https:'");
+	}
+
+	url.hash = "";
+
+	return url.href;
+}
+
+function requestHeaders(request) {
+	return [...request.headers];
+}
+
+class Cache {
+	#name;
+
+	constructor(key, name) {
+		if (key !== illegalConstructorKey) {
+			throw new TypeError("Illegal constructor");
+		}
+
+		this.#name = name;
+	}
+
+	async put(request, response) {
+		request = toRequest(request);
+
+		if (request.method !== "GET") {
+			throw new TypeError("Request method must be GET");
+		}
+
+		const url = cacheKey(request);
+
+		if (response.status === 206) {
+			throw new TypeError("Responses with status 206 can't be cached");
+		}
+
+		const vary = ArrayPrototypeFilter(
+			ArrayPrototypeMap(
+				StringPrototypeSplit(response.headers.get("vary") ?? "", ","),
+				(it) => StringPrototypeToLowerCase(StringPrototypeTrim(it)),
+			),
+			(it) => it !== "",
+		);
+
+		if (ArrayPrototypeIncludes(vary, "*")) {
+			throw new TypeError("Responses with 'Vary: *' can't be cached");
+		}
+
+		if (response.bodyUsed) {
+			throw new TypeError("Response body is already used");
+		}
+
+		const body = new Uint8Array(await response.arrayBuffer());
+
+		await op_cache_put(this.#name, {
+			url,
+			vary: ArrayPrototypeMap(vary, (it) => [it, request.headers.get(it)]),
+			status: response.status,
+			statusText: response.statusText,
+			headers: [...response.headers],
+		}, body);
+	}
+
+	async match(request, options = {}) {
+		request = toRequest(request);
+
+		if (request.method !== "GET" && !options.ignoreMethod) {
+			return undefined;
+		}
+
+		const entry = await op_cache_match(
+			this.#name,
+			cacheKey(request),
+			requestHeaders(request),
+		);
+
+		if (entry === null) {
+			return undefined;
+		}
+
+		const { meta, body } = entry;
+
+		return new Response(
+			ArrayPrototypeIncludes(nullBodyStatus, meta.status) ? null : body,
+			{
+				status: meta.status,
+				statusText: meta.statusText,
+				headers: meta.headers,
+			},
+		);
+	}
+
+	async delete(request, options = {}) {
+		request = toRequest(request);
+
+		if (request.method !== "GET" && !options.ignoreMethod) {
+			return false;
+		}
+
+		return await op_cache_delete(this.#name, cacheKey(request));
+	}
+}
+
+class CacheStorage {
+	constructor(key) {
+		if (key !== illegalConstructorKey) {
+			throw new TypeError("Illegal constructor");
+		}
+	}
+
+	async open(name) {
+		name = `${name}`;
+		await op_cache_storage_open(name);
+
+		return new Cache(illegalConstructorKey, name);
+	}
+
+	async has(name) {
+		return await op_cache_storage_has(`${name}`);
+	}
+
+	async delete(name) {
+		return await op_cache_storage_delete(`${name}`);
+	}
+
+	async keys() {
+		return await op_cache_storage_keys();
+	}
+
+	async match(request, options = {}) {
+		if (options.cacheName !== undefined) {
+			const name = `${options.cacheName}`;
+
+			if (!(await op_cache_storage_has(name))) {
+				return undefined;
+			}
+
+			return await new Cache(illegalConstructorKey, name).match(
+				request,
+				options,
+			);
+		}
+
+		for (const name of await op_cache_storage_keys()) {
+			const response = await new Cache(illegalConstructorKey, name).match(
+				request,
+				options,
+			);
+
+			if (response !== undefined) {
+				return response;
+			}
+		}
+
+		return undefined;
+	}
+}
+
+const caches = new CacheStorage(illegalConstructorKey);
+
+export { Cache, caches, CacheStorage };
@@ -0,0 +1,181 @@
+//! The `caches` web API of workers. Each service gets caches of its own, see
+//! [`WebCacheStore`].
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use deno_core::error::{type_error, AnyError};
+use deno_core::{op2, JsBuffer, OpState, ToJsBuffer};
+use once_cell::sync::OnceCell;
+use sb_core::cache::deno_dir::DenoDir;
+use serde::Serialize;
+
+mod store;
+
+pub use store::{CachedResponse, CachedResponseMeta, WebCacheStore};
+
+#[derive(Debug, Clone)]
+pub struct WebCacheConfig {
+    /// Bounds the entries kept in memory, shared by all services.
+    pub max_memory_bytes: usize,
+    /// Bounds the entries of each service on disk.
+    pub max_disk_bytes: u64,
+}
+
+impl Default for WebCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_memory_bytes: 64 * 1024 * 1024,
+            max_disk_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// Limits of the store, set once by the embedder before the first worker
+/// boots.
+pub static WEB_CACHE_CONFIG: OnceCell<WebCacheConfig> = OnceCell::new();
+
+static WEB_CACHE_STORE: OnceCell<WebCacheStore> = OnceCell::new();
+
+/// The caches of a worker, put into its `OpState`.
+#[derive(Debug, Clone)]
+pub struct CacheOptions {
+    /// Identifies the caches of the service, e.g. its path.
+    pub namespace: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheMatchValue {
+    meta: CachedResponseMeta,
+    body: ToJsBuffer,
+}
+
+fn namespace_of(state: &RefCell<OpState>) -> Result<String, AnyError> {
+    state
+        .borrow()
+        .try_borrow::<CacheOptions>()
+        .map(|it| it.namespace.clone())
+        .ok_or_else(|| type_error("caches is not available in this worker"))
+}
+
+/// Runs `f` with the store on the blocking pool, as it touches the disk.
+async fn with_store<T, F>(f: F) -> Result<T, AnyError>
+where
+    T: Send + 'static,
+    F: FnOnce(&'static WebCacheStore) -> Result<T, AnyError> + Send + 'static,
+{
+    let store = WEB_CACHE_STORE.get_or_try_init(|| {
+        let config = WEB_CACHE_CONFIG.get().cloned().unwrap_or_default();
+
+        Ok::<_, AnyError>(WebCacheStore::new(
+            DenoDir::new(None)?.web_cache_folder_path(),
+            config.max_memory_bytes,
+            config.max_disk_bytes,
+        ))
+    })?;
+
+    tokio::task::spawn_blocking(move || f(store)).await?
+}
+
+#[op2(async)]
+async fn op_cache_storage_open(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+) -> Result<(), AnyError> {
+    let namespace = namespace_of(&state)?;
+
+    with_store(move |store| Ok(store.open(&namespace, &name)?)).await
+}
+
+#[op2(async)]
+async fn op_cache_storage_has(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+) -> Result<bool, AnyError> {
+    let namespace = namespace_of(&state)?;
+
+    with_store(move |store| Ok(store.has(&namespace, &name))).await
+}
+
+#[op2(async)]
+async fn op_cache_storage_delete(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+) -> Result<bool, AnyError> {
+    let namespace = namespace_of(&state)?;
+
+    with_store(move |store| Ok(store.delete_cache(&namespace, &name)?)).await
+}
+
+#[op2(async)]
+#[serde]
+async fn op_cache_storage_keys(state: Rc<RefCell<OpState>>) -> Result<Vec<String>, AnyError> {
+    let namespace = namespace_of(&state)?;
+
+    with_store(move |store| Ok(store.keys(&namespace)?)).await
+}
+
+#[op2(async)]
+async fn op_cache_put(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[serde] meta: CachedResponseMeta,
+    #[buffer] body: JsBuffer,
+) -> Result<(), AnyError> {
+    let namespace = namespace_of(&state)?;
+    let response = CachedResponse {
+        meta,
+        body: body.to_vec(),
+    };
+
+    with_store(move |store| store.put(&namespace, &name, response)).await
+}
+
+#[op2(async)]
+#[serde]
+async fn op_cache_match(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[string] url: String,
+    #[serde] request_headers: Vec<(String, String)>,
+) -> Result<Option<CacheMatchValue>, AnyError> {
+    let namespace = namespace_of(&state)?;
+
+    with_store(move |store| {
+        Ok(store
+            .get(&namespace, &name, &url)?
+            .filter(|it| it.meta.matches(&request_headers))
+            .map(|it| CacheMatchValue {
+                meta: it.meta.clone(),
+                body: it.body.clone().into(),
+            }))
+    })
+    .await
+}
+
+#[op2(async)]
+async fn op_cache_delete(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[string] url: String,
+) -> Result<bool, AnyError> {
+    let namespace = namespace_of(&state)?;
+
+    with_store(move |store| Ok(store.delete(&namespace, &name, &url)?)).await
+}
+
+deno_core::extension!(
+    sb_cache,
+    ops = [
+        op_cache_storage_open,
+        op_cache_storage_has,
+        op_cache_storage_delete,
+        op_cache_storage_keys,
+        op_cache_put,
+        op_cache_match,
+        op_cache_delete
+    ],
+    esm_entry_point = "ext:sb_cache/cache.js",
+    esm = ["cache.js"]
+);
@@ -0,0 +1,432 @@
+//! Storage of the caches of workers. Entries are written through to a folder
+//! per service on disk, and the most recently used ones are also kept in
+//! memory, which all services share.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{bail, Error};
+use deno_core::serde_json;
+use indexmap::IndexMap;
+use sb_core::util::checksum;
+use sb_core::util::fs::atomic_write_file_with_retries;
+use serde::{Deserialize, Serialize};
+
+const CACHE_PERM: u32 = 0o644;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedResponseMeta {
+    pub url: String,
+    /// Values of the request headers named by the `Vary` header of the
+    /// response, at the time it was put.
+    pub vary: Vec<(String, Option<String>)>,
+    pub status: u16,
+    pub status_text: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl CachedResponseMeta {
+    /// Returns whether a request with `request_headers` matches the request the
+    /// response was put with.
+    pub fn matches(&self, request_headers: &[(String, String)]) -> bool {
+        self.vary.iter().all(|(name, value)| {
+            let actual = request_headers
+                .iter()
+                .find(|(it, _)| it.eq_ignore_ascii_case(name))
+                .map(|(_, it)| it.as_str());
+
+            actual == value.as_deref()
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    pub meta: CachedResponseMeta,
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    /// Encodes the entry as the length of its metadata, the metadata as JSON
+    /// and the body.
+    fn encode(&self) -> Result<Vec<u8>, Error> {
+        let meta = serde_json::to_vec(&self.meta)?;
+        let mut buf = Vec::with_capacity(4 + meta.len() + self.body.len());
+
+        buf.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&meta);
+        buf.extend_from_slice(&self.body);
+
+        Ok(buf)
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let Some((len, rest)) = buf.split_first_chunk::<4>() else {
+            bail!("truncated cache entry");
+        };
+
+        let len = u32::from_le_bytes(*len) as usize;
+
+        if rest.len() < len {
+            bail!("truncated cache entry");
+        }
+
+        let (meta, body) = rest.split_at(len);
+
+        Ok(Self {
+            meta: serde_json::from_slice(meta)?,
+            body: body.to_vec(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct MemoryCache {
+    entries: IndexMap<PathBuf, (Arc<CachedResponse>, usize)>,
+    size_bytes: usize,
+}
+
+impl MemoryCache {
+    fn remove(&mut self, path: &Path) {
+        if let Some((_, size)) = self.entries.shift_remove(path) {
+            self.size_bytes -= size;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedFile {
+    path: PathBuf,
+    size_bytes: u64,
+    modified: SystemTime,
+}
+
+pub struct WebCacheStore {
+    dir: PathBuf,
+    max_memory_bytes: usize,
+    max_disk_bytes: u64,
+    memory: Mutex<MemoryCache>,
+}
+
+impl WebCacheStore {
+    /// Creates a store in `dir`. `max_memory_bytes` bounds the entries kept in
+    /// memory and `max_disk_bytes` the entries of each service on disk.
+    pub fn new(dir: PathBuf, max_memory_bytes: usize, max_disk_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_memory_bytes,
+            max_disk_bytes,
+            memory: Mutex::default(),
+        }
+    }
+
+    fn service_dir(&self, namespace: &str) -> PathBuf {
+        self.dir.join(checksum::gen(&[namespace.as_bytes()]))
+    }
+
+    // NOTE: Cache names are hex encoded rather than hashed, so that
+    // `caches.keys()` can list them.
+    fn cache_dir(&self, namespace: &str, name: &str) -> PathBuf {
+        self.service_dir(namespace)
+            .join(faster_hex::hex_string(name.as_bytes()))
+    }
+
+    fn entry_path(&self, namespace: &str, name: &str, url: &str) -> PathBuf {
+        self.cache_dir(namespace, name)
+            .join(checksum::gen(&[url.as_bytes()]))
+    }
+
+    pub fn open(&self, namespace: &str, name: &str) -> io::Result<()> {
+        std::fs::create_dir_all(self.cache_dir(namespace, name))
+    }
+
+    pub fn has(&self, namespace: &str, name: &str) -> bool {
+        self.cache_dir(namespace, name).is_dir()
+    }
+
+    /// Returns the names of the caches of a service, sorted.
+    pub fn keys(&self, namespace: &str) -> io::Result<Vec<String>> {
+        let entries = match std::fs::read_dir(self.service_dir(namespace)) {
+            Ok(it) => it,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+
+        let mut names = vec![];
+
+        for entry in entries {
+            let name = entry?.file_name();
+            let mut buf = vec![0; name.len() / 2];
+
+            if faster_hex::hex_decode(name.as_encoded_bytes(), &mut buf).is_ok() {
+                if let Ok(name) = String::from_utf8(buf) {
+                    names.push(name);
+                }
+            }
+        }
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn delete_cache(&self, namespace: &str, name: &str) -> io::Result<bool> {
+        let dir = self.cache_dir(namespace, name);
+
+        self.forget(&dir);
+
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn put(&self, namespace: &str, name: &str, response: CachedResponse) -> Result<(), Error> {
+        let data = response.encode()?;
+
+        if data.len() as u64 > self.max_disk_bytes {
+            bail!(
+                "response too large to be cached (max {} bytes)",
+                self.max_disk_bytes
+            );
+        }
+
+        let path = self.entry_path(namespace, name, &response.meta.url);
+
+        std::fs::create_dir_all(self.cache_dir(namespace, name))?;
+        atomic_write_file_with_retries(&path, &data, CACHE_PERM)?;
+
+        self.remember(path, Arc::new(response), data.len());
+        self.prune(namespace)?;
+
+        Ok(())
+    }
+
+    pub fn get(
+        &self,
+        namespace: &str,
+        name: &str,
+        url: &str,
+    ) -> Result<Option<Arc<CachedResponse>>, Error> {
+        let path = self.entry_path(namespace, name, url);
+
+        {
+            let mut memory = self.memory.lock().unwrap();
+
+            if let Some(index) = memory.entries.get_index_of(&path) {
+                let last = memory.entries.len() - 1;
+
+                memory.entries.move_index(index, last);
+
+                return Ok(Some(memory.entries[last].0.clone()));
+            }
+        }
+
+        let data = match std::fs::read(&path) {
+            Ok(it) => it,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let response = Arc::new(CachedResponse::decode(&data)?);
+
+        self.remember(path, response.clone(), data.len());
+
+        Ok(Some(response))
+    }
+
+    pub fn delete(&self, namespace: &str, name: &str, url: &str) -> io::Result<bool> {
+        let path = self.entry_path(namespace, name, url);
+
+        self.forget(&path);
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn remember(&self, path: PathBuf, response: Arc<CachedResponse>, size_bytes: usize) {
+        let mut memory = self.memory.lock().unwrap();
+
+        memory.remove(&path);
+
+        if size_bytes > self.max_memory_bytes {
+            return;
+        }
+
+        memory.entries.insert(path, (response, size_bytes));
+        memory.size_bytes += size_bytes;
+
+        while memory.size_bytes > self.max_memory_bytes {
+            let Some((_, (_, size))) = memory.entries.shift_remove_index(0) else {
+                break;
+            };
+
+            memory.size_bytes -= size;
+        }
+    }
+
+    /// Drops the entries at or under `path` from memory.
+    fn forget(&self, path: &Path) {
+        let mut memory = self.memory.lock().unwrap();
+        let paths = memory
+            .entries
+            .keys()
+            .filter(|it| it.starts_with(path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for it in paths {
+            memory.remove(&it);
+        }
+    }
+
+    /// Evicts the entries of a service put the longest ago until they take at
+    /// most `max_disk_bytes`.
+    fn prune(&self, namespace: &str) -> io::Result<()> {
+        let mut files = vec![];
+
+        for entry in std::fs::read_dir(self.service_dir(namespace))? {
+            let entry = entry?;
+
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            for entry in std::fs::read_dir(entry.path())? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+
+                if metadata.is_file() {
+                    files.push(CachedFile {
+                        path: entry.path(),
+                        size_bytes: metadata.len(),
+                        modified: metadata.modified()?,
+                    });
+                }
+            }
+        }
+
+        let mut size_bytes = files.iter().map(|it| it.size_bytes).sum::<u64>();
+
+        files.sort_by_key(|it| it.modified);
+
+        for file in files {
+            if size_bytes <= self.max_disk_bytes {
+                break;
+            }
+
+            self.forget(&file.path);
+
+            match std::fs::remove_file(&file.path) {
+                Ok(()) => size_bytes = size_bytes.saturating_sub(file.size_bytes),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn response(url: &str, body: &[u8]) -> CachedResponse {
+        CachedResponse {
+            meta: CachedResponseMeta {
+                url: url.to_string(),
+                vary: vec![("accept".to_string(), Some("text/plain".to_string()))],
+                status: 200,
+                status_text: "OK".to_string(),
+                headers: vec![("content-type".to_string(), "text/plain".to_string())],
+            },
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_web_cache_store() {
+        let root = std::env::temp_dir().join(format!("web-cache-{}", std::process::id()));
+        let store = WebCacheStore::new(root.clone(), 256, 1024);
+
+        store.open("svc", "v1").unwrap();
+        assert!(store.has("svc", "v1"));
+        assert!(!store.has("other", "v1"));
+        assert_eq!(store.keys("svc").unwrap(), vec!["v1".to_string()]);
+
+        let a = response("https://example.com/a", &[1; 300]);
+
+        store.put("svc", "v1", a.clone()).unwrap();
+        assert_eq!(
+            *store
+                .get("svc", "v1", "https://example.com/a")
+                .unwrap()
+                .unwrap(),
+            a
+        );
+
+        assert!(store.memory.lock().unwrap().entries.is_empty());
+        assert!(store
+            .get("svc", "v2", "https://example.com/a")
+            .unwrap()
+            .is_none());
+        assert!(store
+            .get("other", "v1", "https://example.com/a")
+            .unwrap()
+            .is_none());
+
+        let b = response("https://example.com/b", &[2; 10]);
+
+        std::thread::sleep(Duration::from_millis(20));
+        store.put("svc", "v1", b.clone()).unwrap();
+        assert_eq!(store.memory.lock().unwrap().entries.len(), 1);
+
+        assert!(b
+            .meta
+            .matches(&[("Accept".to_string(), "text/plain".to_string())]));
+
+        assert!(!b.meta.matches(&[]));
+
+        // Putting `c` takes the service over its quota on disk, so the entry
+        // put the longest ago is evicted.
+        std::thread::sleep(Duration::from_millis(20));
+        store
+            .put("svc", "v2", response("https://example.com/c", &[3; 600]))
+            .unwrap();
+
+        assert!(store
+            .get("svc", "v1", "https://example.com/a")
+            .unwrap()
+            .is_none());
+        assert!(store
+            .get("svc", "v1", "https://example.com/b")
+            .unwrap()
+            .is_some());
+        assert!(store
+            .get("svc", "v2", "https://example.com/c")
+            .unwrap()
+            .is_some());
+
+        assert!(store.delete("svc", "v1", "https://example.com/b").unwrap());
+        assert!(store
+            .get("svc", "v1", "https://example.com/b")
+            .unwrap()
+            .is_none());
+
+        assert!(store.delete_cache("svc", "v2").unwrap());
+        assert!(!store.delete_cache("svc", "v2").unwrap());
+        assert_eq!(store.keys("svc").unwrap(), vec!["v1".to_string()]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
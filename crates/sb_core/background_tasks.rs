@@ -0,0 +1,97 @@
+//! Promises registered through `EdgeRuntime.waitUntil`.
+//!
+//! The supervisor of a user worker holds off terminating it after its last
+//! request while any of them is pending, so work scheduled after the response
+//! was sent (flushing logs, updating a cache, ...) gets to complete.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use deno_core::{op2, OpState};
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct Inner {
+    registered: AtomicUsize,
+    pending: AtomicUsize,
+    settled: Notify,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundTasks(Arc<Inner>);
+
+impl BackgroundTasks {
+    fn begin(&self) {
+        self.0.registered.fetch_add(1, Ordering::AcqRel);
+        self.0.pending.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn end(&self) {
+        if self.0.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.settled.notify_waiters();
+        }
+    }
+
+    /// Number of tasks registered since the worker started.
+    pub fn registered(&self) -> usize {
+        self.0.registered.load(Ordering::Acquire)
+    }
+
+    pub fn pending(&self) -> usize {
+        self.0.pending.load(Ordering::Acquire)
+    }
+
+    /// Resolves once no task is pending.
+    pub async fn wait_settled(&self) {
+        loop {
+            let notified = self.0.settled.notified();
+
+            if self.pending() == 0 {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+}
+
+#[op2(fast)]
+pub fn op_background_task_begin(state: &mut OpState) {
+    if let Some(tasks) = state.try_borrow::<BackgroundTasks>() {
+        tasks.begin();
+    }
+}
+
+#[op2(fast)]
+pub fn op_background_task_end(state: &mut OpState) {
+    if let Some(tasks) = state.try_borrow::<BackgroundTasks>() {
+        tasks.end();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_settled() {
+        let tasks = BackgroundTasks::default();
+
+        tasks.begin();
+        tasks.begin();
+        tasks.end();
+
+        assert_eq!(tasks.registered(), 2);
+        assert_eq!(tasks.pending(), 1);
+
+        let waiter = tokio::spawn({
+            let tasks = tasks.clone();
+            async move { tasks.wait_settled().await }
+        });
+
+        tasks.end();
+        waiter.await.unwrap();
+
+        assert_eq!(tasks.pending(), 0);
+    }
+}
@@ -0,0 +1,109 @@
+//! Buses of `BroadcastChannel`, through which the workers of a service reach
+//! each other. A bus lives as long as a worker holds a lease on it, so that
+//! workers booted later still join it while their service is up, and is
+//! dropped with the last one.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use deno_broadcast_channel::InMemoryBroadcastChannel;
+use once_cell::sync::{Lazy, OnceCell};
+
+/// Key of the bus of the main worker, which services sharing their channels
+/// with it join instead of their own. Can't collide with the path of a
+/// service.
+pub const MAIN_BUS: &str = "";
+
+/// Directories of the services the operator lets join the bus of the main
+/// worker. A service can't join it on its own say-so, since it would then
+/// see the messages of the main worker and of every other service doing so.
+pub static SHARED_BUS_SERVICES: OnceCell<Vec<PathBuf>> = OnceCell::new();
+
+static BUSES: Lazy<Mutex<HashMap<String, (InMemoryBroadcastChannel, usize)>>> =
+    Lazy::new(Mutex::default);
+
+/// Keeps the bus it was handed out with alive. The bus is removed once the
+/// last lease on it is dropped.
+#[derive(Debug)]
+pub struct BusLease(String);
+
+impl Drop for BusLease {
+    fn drop(&mut self) {
+        let mut buses = BUSES.lock().unwrap();
+
+        if let Some((_, leases)) = buses.get_mut(&self.0) {
+            *leases -= 1;
+
+            if *leases == 0 {
+                buses.remove(&self.0);
+            }
+        }
+    }
+}
+
+/// Returns the bus of `key`, creating it on first use, along with a lease
+/// keeping it alive.
+pub fn bus_of(key: &str) -> (InMemoryBroadcastChannel, BusLease) {
+    let mut buses = BUSES.lock().unwrap();
+    let (bus, leases) = buses.entry(key.to_string()).or_default();
+
+    *leases += 1;
+
+    (bus.clone(), BusLease(key.to_string()))
+}
+
+/// Returns the key of the bus the workers of the service in `service_dir`
+/// join.
+pub fn bus_key(service_dir: &Path, share_with_main: bool) -> String {
+    if share_with_main {
+        MAIN_BUS.to_string()
+    } else {
+        service_dir.to_string_lossy().into_owned()
+    }
+}
+
+/// Whether the operator lets the service in `service_dir` join the bus of the
+/// main worker.
+pub fn may_share_main_bus(service_dir: &Path) -> bool {
+    SHARED_BUS_SERVICES
+        .get()
+        .is_some_and(|it| it.iter().any(|dir| dir == service_dir))
+}
+
+#[cfg(test)]
+mod test {
+    use deno_broadcast_channel::BroadcastChannel;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bus_of() {
+        let (a, _lease_a) = bus_of("/srv/functions/hello");
+        let (b, _lease_b) = bus_of("/srv/functions/hello");
+        let rid_a = a.subscribe().unwrap();
+        let rid_b = b.subscribe().unwrap();
+
+        a.send(&rid_a, "invalidate".to_string(), vec![1, 2])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            b.recv(&rid_b).await.unwrap(),
+            Some(("invalidate".to_string(), vec![1, 2]))
+        );
+    }
+
+    #[test]
+    fn test_bus_is_pruned() {
+        let key = "/srv/functions/pruned";
+        let (_, lease_a) = bus_of(key);
+        let (_, lease_b) = bus_of(key);
+
+        drop(lease_a);
+        assert!(BUSES.lock().unwrap().contains_key(key));
+
+        drop(lease_b);
+        assert!(!BUSES.lock().unwrap().contains_key(key));
+    }
+}
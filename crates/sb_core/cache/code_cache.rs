@@ -0,0 +1,119 @@
+use deno_ast::ModuleSpecifier;
+use deno_core::error::AnyError;
+use deno_webstorage::rusqlite::params;
+use log::debug;
+
+use super::cache_db::CacheDB;
+use super::cache_db::CacheDBConfiguration;
+use super::cache_db::CacheDBHash;
+use super::cache_db::CacheFailure;
+
+const SELECT_CODE_CACHE: &str = "
+SELECT
+  data
+FROM
+  codecache
+WHERE
+  specifier=?1
+  AND source_hash=?2
+LIMIT 1";
+
+pub static CODE_CACHE_DB: CacheDBConfiguration = CacheDBConfiguration {
+    table_initializer: concat!(
+        "CREATE TABLE IF NOT EXISTS codecache (",
+        "specifier TEXT PRIMARY KEY,",
+        "source_hash INTEGER NOT NULL,",
+        "data BLOB NOT NULL",
+        ");"
+    ),
+    on_version_change: "DELETE FROM codecache;",
+    preheat_queries: &[SELECT_CODE_CACHE],
+    // NOTE: V8 recompiles the module without the cache, so losing it only costs
+    // the time the cache would have saved.
+    on_failure: CacheFailure::Blackhole,
+};
+
+/// A cache of the V8 code cache of modules, keyed by their specifier and the
+/// hash of their source. Consuming it lets V8 skip compiling a module that
+/// hasn't changed since a previous boot.
+pub struct CodeCache {
+    conn: CacheDB,
+}
+
+impl CodeCache {
+    pub fn new(conn: CacheDB) -> Self {
+        Self { conn }
+    }
+
+    pub fn get_sync(&self, specifier: &ModuleSpecifier, source_hash: u64) -> Option<Vec<u8>> {
+        match self.get(specifier, source_hash) {
+            Ok(it) => it,
+            Err(err) => {
+                debug!("failed to read the code cache of {}: {}", specifier, err);
+                None
+            }
+        }
+    }
+
+    pub fn set_sync(&self, specifier: &ModuleSpecifier, source_hash: u64, data: &[u8]) {
+        if let Err(err) = self.set(specifier, source_hash, data) {
+            debug!("failed to write the code cache of {}: {}", specifier, err);
+        }
+    }
+
+    fn get(
+        &self,
+        specifier: &ModuleSpecifier,
+        source_hash: u64,
+    ) -> Result<Option<Vec<u8>>, AnyError> {
+        self.conn.query_row(
+            SELECT_CODE_CACHE,
+            params![specifier.as_str(), CacheDBHash::new(source_hash)],
+            |row| {
+                let data: Vec<u8> = row.get(0)?;
+                Ok(data)
+            },
+        )
+    }
+
+    fn set(
+        &self,
+        specifier: &ModuleSpecifier,
+        source_hash: u64,
+        data: &[u8],
+    ) -> Result<(), AnyError> {
+        let sql = "
+      INSERT OR REPLACE INTO
+        codecache (specifier, source_hash, data)
+      VALUES
+        (?1, ?2, ?3)";
+        self.conn.execute(
+            sql,
+            params![specifier.as_str(), CacheDBHash::new(source_hash), data],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_code_cache() {
+        let cache = CodeCache::new(CacheDB::in_memory(&CODE_CACHE_DB, "1.0.0"));
+        let specifier = ModuleSpecifier::parse("file:///src/index.ts").unwrap();
+
+        assert_eq!(cache.get_sync(&specifier, 1), None);
+
+        cache.set_sync(&specifier, 1, &[1, 2, 3]);
+        assert_eq!(cache.get_sync(&specifier, 1), Some(vec![1, 2, 3]));
+
+        // A changed source invalidates the cached data.
+        assert_eq!(cache.get_sync(&specifier, 2), None);
+
+        cache.set_sync(&specifier, 2, &[4, 5]);
+        assert_eq!(cache.get_sync(&specifier, 1), None);
+        assert_eq!(cache.get_sync(&specifier, 2), Some(vec![4, 5]));
+    }
+}
@@ -108,6 +108,22 @@ impl DenoDir {
         self.root.join("check_cache_v1")
     }
 
+    /// Path for the V8 code cache of modules.
+    pub fn code_cache_db_file_path(&self) -> PathBuf {
+        // bump this version name to invalidate the entire cache
+        self.root.join("v8_code_cache_v1")
+    }
+
+    /// Path to the key-value stores of services, see `Deno.openKv()`.
+    pub fn kv_folder_path(&self) -> PathBuf {
+        self.root.join("kv")
+    }
+
+    /// Path to the caches of services, see `caches`.
+    pub fn web_cache_folder_path(&self) -> PathBuf {
+        self.root.join("web_cache")
+    }
+
     /// Path to the registries cache, used for the lps.
     pub fn registries_folder_path(&self) -> PathBuf {
         self.root.join("registries")
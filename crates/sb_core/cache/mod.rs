@@ -1,12 +1,14 @@
 pub mod cache_db;
 pub mod caches;
 pub mod check;
+pub mod code_cache;
 pub mod common;
 pub mod deno_dir;
 pub mod disk_cache;
 pub mod emit;
 pub mod fc_permissions;
 pub mod incremental;
+pub mod module_cache;
 pub mod module_info;
 pub mod node;
 pub mod parsed_source;
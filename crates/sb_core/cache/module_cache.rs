@@ -0,0 +1,192 @@
+//! Inspection and size-based pruning of the module cache the workers share.
+//!
+//! Only remote modules (`deps`) and emitted code (`gen`) are pruned. They're
+//! kept as one file per module, so evicting any of them only costs a refetch
+//! or a re-emit the next time it's imported. npm packages are cached as whole
+//! directories the npm resolver relies on and are left alone.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{error, info};
+
+use crate::cache::deno_dir::DenoDir;
+
+/// Suffix of the file holding the headers of a cached remote module.
+const METADATA_SUFFIX: &str = ".metadata.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleCacheUsage {
+    pub files: usize,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug)]
+struct CachedFile {
+    path: PathBuf,
+    size_bytes: u64,
+    last_used: SystemTime,
+}
+
+fn prunable_folders(deno_dir: &DenoDir) -> [PathBuf; 2] {
+    [
+        deno_dir.deps_folder_path(),
+        deno_dir.gen_cache.location.clone(),
+    ]
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<CachedFile>) -> io::Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(it) => it,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if metadata.is_file() {
+            files.push(CachedFile {
+                path: entry.path(),
+                size_bytes: metadata.len(),
+                // NOTE: The access time isn't updated on every mount, in which
+                // case this degrades to evicting the oldest modules first.
+                last_used: metadata.accessed().or_else(|_| metadata.modified())?,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn usage_of(files: &[CachedFile]) -> ModuleCacheUsage {
+    ModuleCacheUsage {
+        files: files.len(),
+        size_bytes: files.iter().map(|it| it.size_bytes).sum(),
+    }
+}
+
+/// Returns the size of the prunable part of the module cache.
+pub fn usage(deno_dir: &DenoDir) -> io::Result<ModuleCacheUsage> {
+    let mut files = vec![];
+
+    for folder in prunable_folders(deno_dir) {
+        collect_files(&folder, &mut files)?;
+    }
+
+    Ok(usage_of(&files))
+}
+
+/// Evicts the least recently used modules until the prunable part of the
+/// module cache takes at most `max_size_bytes`. Returns what was evicted.
+pub fn prune(deno_dir: &DenoDir, max_size_bytes: u64) -> io::Result<ModuleCacheUsage> {
+    let mut files = vec![];
+
+    for folder in prunable_folders(deno_dir) {
+        collect_files(&folder, &mut files)?;
+    }
+
+    let mut size_bytes = usage_of(&files).size_bytes;
+    let mut evicted = ModuleCacheUsage::default();
+
+    files.sort_by_key(|it| it.last_used);
+
+    for file in files {
+        if size_bytes <= max_size_bytes {
+            break;
+        }
+
+        // The headers of a remote module go with it.
+        let path = file.path.to_string_lossy();
+        let sibling = match path.strip_suffix(METADATA_SUFFIX) {
+            Some(it) => PathBuf::from(it),
+            None => PathBuf::from(format!("{}{}", path, METADATA_SUFFIX)),
+        };
+
+        for path in [&file.path, &sibling] {
+            let Ok(metadata) = std::fs::metadata(path) else {
+                continue;
+            };
+
+            match std::fs::remove_file(path) {
+                Ok(()) => {
+                    size_bytes = size_bytes.saturating_sub(metadata.len());
+                    evicted.files += 1;
+                    evicted.size_bytes += metadata.len();
+                }
+
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    Ok(evicted)
+}
+
+/// Prunes the module cache down to `max_size_bytes` every `interval`.
+pub fn spawn_pruner(max_size_bytes: u64, interval: Duration) {
+    drop(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+
+            let result =
+                tokio::task::spawn_blocking(move || prune(&DenoDir::new(None)?, max_size_bytes))
+                    .await;
+
+            match result {
+                Ok(Ok(evicted)) if evicted.files > 0 => info!(
+                    "evicted {} files ({} bytes) from the module cache",
+                    evicted.files, evicted.size_bytes
+                ),
+
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => error!("failed to prune the module cache: {}", err),
+                Err(err) => error!("failed to prune the module cache: {}", err),
+            }
+        }
+    }));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prune() {
+        let root = std::env::temp_dir().join(format!("module-cache-{}", std::process::id()));
+        let deno_dir = DenoDir::new(Some(root.clone())).unwrap();
+        let deps = deno_dir.deps_folder_path().join("https").join("deno.land");
+
+        std::fs::create_dir_all(&deps).unwrap();
+
+        for (name, size) in [("old", 100), ("new", 50)] {
+            std::fs::write(deps.join(name), vec![0; size]).unwrap();
+            std::fs::write(deps.join(format!("{}{}", name, METADATA_SUFFIX)), "{}").unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        assert_eq!(
+            usage(&deno_dir).unwrap(),
+            ModuleCacheUsage {
+                files: 4,
+                size_bytes: 154,
+            }
+        );
+
+        let evicted = prune(&deno_dir, 100).unwrap();
+
+        assert_eq!(evicted.size_bytes, 102);
+        assert!(!deps.join("old").exists());
+        assert!(!deps.join(format!("old{}", METADATA_SUFFIX)).exists());
+        assert!(deps.join("new").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
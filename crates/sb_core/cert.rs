@@ -3,10 +3,23 @@ use deno_core::error::AnyError;
 use deno_tls::deno_native_certs::load_native_certs;
 use deno_tls::rustls::RootCertStore;
 use deno_tls::{rustls_pemfile, webpki_roots, RootCertStoreProvider};
-use std::io::{BufReader, Cursor, Read};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Cursor, Read};
 use std::path::PathBuf;
 use thiserror::Error;
 
+/// Trust anchors of the TLS connections of workers, set once from the CLI.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TlsCaConfig {
+    /// `mozilla` and/or `system`. `None` defers to `DENO_TLS_CA_STORE`.
+    pub stores: Option<Vec<String>>,
+    /// PEM files of extra CA certificates, on top of `DENO_CERT`.
+    pub ca_files: Vec<PathBuf>,
+}
+
+pub static TLS_CA_CONFIG: OnceCell<TlsCaConfig> = OnceCell::new();
+
 pub struct ValueRootCertStoreProvider {
     pub root_cert_store: RootCertStore,
 }
@@ -23,6 +36,31 @@ impl RootCertStoreProvider for ValueRootCertStoreProvider {
     }
 }
 
+/// Provides the root cert store of [`TLS_CA_CONFIG`], which is built once and
+/// shared by fetch, net and websocket of every worker.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SharedRootCertStoreProvider;
+
+impl RootCertStoreProvider for SharedRootCertStoreProvider {
+    fn get_or_try_init(&self) -> Result<&RootCertStore, AnyError> {
+        static ROOT_CERT_STORE: OnceCell<RootCertStore> = OnceCell::new();
+
+        ROOT_CERT_STORE.get_or_try_init(|| {
+            let config = TLS_CA_CONFIG.get().cloned().unwrap_or_default();
+            let mut root_cert_store = get_root_cert_store(None, config.stores, None)?;
+
+            for ca_file in config.ca_files {
+                let file = std::fs::File::open(&ca_file)
+                    .with_context(|| format!("failed opening CA file: {}", ca_file.display()))?;
+
+                add_pem_certs(&mut root_cert_store, &mut BufReader::new(file))?;
+            }
+
+            Ok(root_cert_store)
+        })
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum CaData {
     /// The string is a file path
@@ -99,19 +137,28 @@ pub fn get_root_cert_store(
             CaData::Bytes(data) => BufReader::new(Box::new(Cursor::new(data)) as _),
         };
 
-        for cert in rustls_pemfile::certs(&mut reader) {
-            if let Err(err) = cert
-                .with_context(|| "failed to load the certificate")
-                .and_then(|it| {
-                    root_cert_store
-                        .add(it.clone())
-                        .with_context(|| "error adding a certificate to the store")
-                })
-            {
-                return Err(RootCertStoreLoadError::FailedAddPemFile(err.to_string()));
-            }
-        }
+        add_pem_certs(&mut root_cert_store, &mut reader)?;
     }
 
     Ok(root_cert_store)
 }
+
+fn add_pem_certs(
+    root_cert_store: &mut RootCertStore,
+    reader: &mut dyn BufRead,
+) -> Result<(), RootCertStoreLoadError> {
+    for cert in rustls_pemfile::certs(reader) {
+        if let Err(err) = cert
+            .with_context(|| "failed to load the certificate")
+            .and_then(|it| {
+                root_cert_store
+                    .add(it.clone())
+                    .with_context(|| "error adding a certificate to the store")
+            })
+        {
+            return Err(RootCertStoreLoadError::FailedAddPemFile(err.to_string()));
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,19 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
 use deno_core::Resource;
+use hyper_v014::header::HeaderValue;
+use hyper_v014::HeaderMap;
+use serde::{Deserialize, Serialize};
 use tokio_util::sync::CancellationToken;
 
-pub struct ConnWatcher(pub Option<CancellationToken>);
+/// The header a request multiplexed over the HTTP/2 connection to a worker
+/// carries its key in. The runtime sets it, and takes it out again before the
+/// worker sees the request.
+pub const MUX_REQUEST_HEADER: &str = "x-edge-runtime-request-key";
+
+pub struct ConnWatcher(pub Option<CancellationToken>, pub RequestMarks);
 
 impl Resource for ConnWatcher {
     fn name(&self) -> std::borrow::Cow<str> {
@@ -15,5 +27,169 @@ impl ConnWatcher {
     }
 }
 
+/// Marks a request the main worker sends to list or run the `Deno.cron()` jobs
+/// of a user worker. It is set by the runtime alone, as an extension of the
+/// request, so a client can't run the jobs of a worker by crafting a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CronRequest {
+    List,
+    Run(String),
+}
+
+/// What the runtime alone says about a request it hands to a worker. It travels
+/// beside the request rather than in it, so a client can't forge it.
+#[derive(Debug, Clone, Default)]
+pub struct RequestMarks {
+    pub cron: Option<CronRequest>,
+    /// The request carried the token of `--cpu-profile-token`.
+    pub cpu_profile: bool,
+    /// The requests multiplexed over the connection, if it's the HTTP/2
+    /// connection of a worker rather than one carrying a single request.
+    pub mux: Option<MuxRequests>,
+    /// Set by the worker with `EdgeRuntime.setTrailers()`, and sent after the
+    /// body of its response.
+    pub trailers: ResponseTrailers,
+    /// Set by the worker with `EdgeRuntime.setHibernationState()`, and handed
+    /// to the worker that picks the upgraded connection back up once the one
+    /// that accepted it has hibernated.
+    pub hibernation_state: HibernationState,
+    /// The state the hibernated worker left for the upgraded connection this
+    /// request picks back up, empty if it left none.
+    pub resumed_from: Option<String>,
+    /// Used by the worker with `EdgeRuntime.sendEarlyHints()`. `None` if the
+    /// client of the request can't take a `103 Early Hints`.
+    pub early_hints: Option<EarlyHintsSender>,
+}
+
+/// Sends `Link`s to the client of a request in a `103 Early Hints`, ahead of
+/// the response. The runtime puts it in the request, as an extension, for the
+/// clients that can take one.
+#[derive(Clone)]
+pub struct EarlyHintsSender(Arc<dyn Fn(&[HeaderValue]) + Send + Sync>);
+
+impl EarlyHintsSender {
+    pub fn new(send: impl Fn(&[HeaderValue]) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(send))
+    }
+
+    pub fn send(&self, links: &[HeaderValue]) {
+        (self.0)(links)
+    }
+}
+
+impl fmt::Debug for EarlyHintsSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EarlyHintsSender").finish_non_exhaustive()
+    }
+}
+
+/// The trailers of the response to a request. The worker can't write trailer
+/// frames itself, so they are passed beside the response instead, and appended
+/// to it by the runtime once its body is done.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseTrailers(Arc<Mutex<Option<HeaderMap>>>);
+
+impl ResponseTrailers {
+    pub fn set(&self, trailers: HeaderMap) {
+        *self.0.lock().unwrap() = Some(trailers);
+    }
+
+    pub fn take(&self) -> Option<HeaderMap> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// The state a worker keeps for an upgraded connection across hibernation,
+/// e.g. the session the connection belongs to.
+#[derive(Debug, Clone, Default)]
+pub struct HibernationState(Arc<Mutex<Option<String>>>);
+
+impl HibernationState {
+    pub fn set(&self, state: String) {
+        *self.0.lock().unwrap() = Some(state);
+    }
+
+    pub fn take(&self) -> Option<String> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// What the runtime says about each request it multiplexes over the HTTP/2
+/// connection to a worker, by the key the request carries in
+/// [`MUX_REQUEST_HEADER`].
+#[derive(Debug, Clone, Default)]
+pub struct MuxRequests(Arc<Mutex<MuxRequestsInner>>);
+
+#[derive(Debug, Default)]
+struct MuxRequestsInner {
+    next_key: u64,
+    entries: HashMap<u64, (Option<CancellationToken>, RequestMarks)>,
+}
+
+impl MuxRequests {
+    pub fn register(&self, token: Option<CancellationToken>, marks: RequestMarks) -> MuxRequest {
+        let mut inner = self.0.lock().unwrap();
+        let key = inner.next_key;
+
+        inner.next_key += 1;
+        inner.entries.insert(key, (token, marks));
+
+        MuxRequest {
+            key,
+            requests: self.clone(),
+        }
+    }
+
+    pub fn take(&self, key: u64) -> Option<(Option<CancellationToken>, RequestMarks)> {
+        self.0.lock().unwrap().entries.remove(&key)
+    }
+}
+
+/// A request registered with [`MuxRequests`]. Its entry is removed once this is
+/// dropped, in case the worker never took it.
+pub struct MuxRequest {
+    key: u64,
+    requests: MuxRequests,
+}
+
+impl MuxRequest {
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+}
+
+impl Drop for MuxRequest {
+    fn drop(&mut self) {
+        let _ = self.requests.take(self.key);
+    }
+}
+
 #[derive(Clone)]
 pub struct DenoRuntimeDropToken(pub CancellationToken);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mux_requests() {
+        let requests = MuxRequests::default();
+        let token = CancellationToken::new();
+
+        let a = requests.register(Some(token.clone()), RequestMarks::default());
+        let b = requests.register(None, RequestMarks::default());
+
+        assert_ne!(a.key(), b.key());
+
+        let (taken, _) = requests.take(a.key()).unwrap();
+
+        assert!(taken.is_some());
+        assert!(requests.take(a.key()).is_none());
+
+        let key = b.key();
+
+        drop(b);
+        assert!(requests.take(key).is_none());
+    }
+}
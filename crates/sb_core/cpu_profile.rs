@@ -0,0 +1,174 @@
+//! CPU profiles of single requests of user workers. A profile is taken through
+//! a local session of the inspector of the worker and written as a
+//! `.cpuprofile` file, which Chrome DevTools can open.
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use deno_core::error::AnyError;
+use deno_core::{op2, JsRuntimeInspector, LocalInspectorSession, OpState, ResourceId};
+use log::info;
+use once_cell::sync::OnceCell;
+
+use crate::conn_sync::ConnWatcher;
+use crate::util::token::token_eq;
+
+/// Header a request carries the token in. The runtime takes it out before the
+/// request reaches a user worker.
+pub const CPU_PROFILE_HEADER: &str = "x-edge-runtime-cpu-profile";
+
+pub static CPU_PROFILE_CONFIG: OnceCell<CpuProfileConfig> = OnceCell::new();
+
+#[derive(Debug, Clone)]
+pub struct CpuProfileConfig {
+    pub dir: PathBuf,
+    /// Requests carrying it in the `x-edge-runtime-cpu-profile` header are
+    /// profiled.
+    pub token: String,
+}
+
+/// Whether `token` is the configured one, see [`token_eq`].
+pub fn is_cpu_profile_token(token: &[u8]) -> bool {
+    let Some(config) = CPU_PROFILE_CONFIG.get() else {
+        return false;
+    };
+
+    token_eq(config.token.as_bytes(), token)
+}
+
+/// Profiler of a user worker, which runs for one request at a time.
+pub struct CpuProfiler {
+    inspector: Rc<RefCell<JsRuntimeInspector>>,
+    name: String,
+    is_active: bool,
+    session: Option<LocalInspectorSession>,
+}
+
+impl CpuProfiler {
+    pub fn new(inspector: Rc<RefCell<JsRuntimeInspector>>, name: String) -> Self {
+        Self {
+            inspector,
+            name,
+            is_active: false,
+            session: None,
+        }
+    }
+}
+
+/// Starts profiling if the runtime marked the request on the connection
+/// watched by `rid` for it and no other request is being profiled, returning
+/// whether it did.
+#[op2(async)]
+pub async fn op_cpu_profile_start(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+) -> Result<bool, AnyError> {
+    let is_marked = state
+        .borrow()
+        .resource_table
+        .get::<ConnWatcher>(rid)
+        .is_ok_and(|it| it.1.cpu_profile);
+
+    if !is_marked {
+        return Ok(false);
+    }
+
+    let mut session = {
+        let mut state = state.borrow_mut();
+        let Some(profiler) = state.try_borrow_mut::<CpuProfiler>() else {
+            return Ok(false);
+        };
+
+        // NOTE: The profiler samples the whole isolate, so the requests
+        // handled concurrently show up in the profile as well.
+        if profiler.is_active {
+            return Ok(false);
+        }
+
+        profiler.is_active = true;
+        profiler.inspector.borrow().create_local_session()
+    };
+
+    let result = async {
+        session.post_message::<()>("Profiler.enable", None).await?;
+        session.post_message::<()>("Profiler.start", None).await
+    }
+    .await;
+
+    let mut state = state.borrow_mut();
+    let profiler = state.borrow_mut::<CpuProfiler>();
+
+    if let Err(err) = result {
+        profiler.is_active = false;
+        return Err(err);
+    }
+
+    profiler.session = Some(session);
+    Ok(true)
+}
+
+/// Stops profiling and writes the profile, returning its path.
+#[op2(async)]
+#[string]
+pub async fn op_cpu_profile_stop(state: Rc<RefCell<OpState>>) -> Result<Option<String>, AnyError> {
+    let (mut session, name) = {
+        let mut state = state.borrow_mut();
+        let Some(profiler) = state.try_borrow_mut::<CpuProfiler>() else {
+            return Ok(None);
+        };
+
+        let Some(session) = profiler.session.take() else {
+            return Ok(None);
+        };
+
+        (session, profiler.name.clone())
+    };
+
+    let result = session.post_message::<()>("Profiler.stop", None).await;
+
+    drop(session);
+    state.borrow_mut().borrow_mut::<CpuProfiler>().is_active = false;
+
+    let Some(config) = CPU_PROFILE_CONFIG.get() else {
+        return Ok(None);
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_millis())
+        .unwrap_or_default();
+
+    let path = config
+        .dir
+        .join(format!("{}-{}.cpuprofile", timestamp, name));
+
+    let profile = deno_core::serde_json::to_vec(&result?["profile"])?;
+
+    tokio::fs::create_dir_all(&config.dir).await?;
+    tokio::fs::write(&path, profile).await?;
+
+    info!("wrote a cpu profile of the worker: {}", path.display());
+    Ok(Some(path.display().to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_cpu_profile_token() {
+        CPU_PROFILE_CONFIG
+            .set(CpuProfileConfig {
+                dir: PathBuf::from("/tmp/profiles"),
+                token: "s3cret".to_string(),
+            })
+            .unwrap();
+
+        assert!(is_cpu_profile_token(b"s3cret"));
+        assert!(!is_cpu_profile_token(b"s3creT"));
+        assert!(!is_cpu_profile_token(b"s3cre"));
+        assert!(!is_cpu_profile_token(b""));
+    }
+}
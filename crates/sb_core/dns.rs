@@ -0,0 +1,202 @@
+//! Name resolution of the connections user code opens, with static overrides
+//! so that functions can reach internal services by name without touching
+//! /etc/hosts of the host.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Error};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::TokioAsyncResolver;
+
+pub static DNS_CONFIG: OnceCell<DnsConfig> = OnceCell::new();
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DnsConfig {
+    overrides: HashMap<String, IpAddr>,
+    /// Nameserver asked instead of the resolver of the host.
+    resolver: Option<SocketAddr>,
+    #[serde(skip)]
+    shared_resolver: Arc<OnceCell<SharedResolver>>,
+}
+
+/// The resolver of a nameserver, shared by every worker so that they share
+/// its connections and its cache. Each worker runs on a runtime of its own,
+/// while the connections of a resolver are bound to the runtime they were
+/// opened on, so the lookups run on a runtime of the resolver's own.
+struct SharedResolver {
+    runtime: tokio::runtime::Handle,
+    resolver: TokioAsyncResolver,
+}
+
+impl fmt::Debug for SharedResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedResolver").finish()
+    }
+}
+
+impl SharedResolver {
+    fn new(nameserver: SocketAddr) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let handle = runtime.handle().clone();
+
+        std::thread::Builder::new()
+            .name("sb-dns-resolver".into())
+            .spawn(move || runtime.block_on(std::future::pending::<()>()))?;
+
+        Ok(Self {
+            runtime: handle,
+            resolver: TokioAsyncResolver::tokio(
+                ResolverConfig::from_parts(
+                    None,
+                    vec![],
+                    NameServerConfigGroup::from_ips_clear(
+                        &[nameserver.ip()],
+                        nameserver.port(),
+                        true,
+                    ),
+                ),
+                ResolverOpts::default(),
+            ),
+        })
+    }
+
+    async fn lookup(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let resolver = self.resolver.clone();
+        let host = host.to_string();
+
+        let lookup = self
+            .runtime
+            .spawn(async move { resolver.lookup_ip(host).await })
+            .await?
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+        Ok(lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+}
+
+impl DnsConfig {
+    pub fn new(overrides: HashMap<String, IpAddr>, resolver: Option<SocketAddr>) -> Self {
+        Self {
+            overrides,
+            resolver,
+            shared_resolver: Arc::default(),
+        }
+    }
+
+    /// Parses a list of `host=ip` overrides and the address of a nameserver,
+    /// whose port defaults to 53.
+    pub fn parse<'a>(
+        overrides: impl IntoIterator<Item = &'a str>,
+        resolver: Option<&str>,
+    ) -> Result<Self, Error> {
+        let overrides = overrides
+            .into_iter()
+            .map(|it| {
+                let (host, ip) = it
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("invalid DNS override: {} (expected HOST=IP)", it))?;
+
+                let ip = ip
+                    .trim()
+                    .parse::<IpAddr>()
+                    .with_context(|| format!("invalid DNS override: {}", it))?;
+
+                Ok((host.trim().to_ascii_lowercase(), ip))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let resolver = resolver
+            .map(|it| {
+                it.parse::<SocketAddr>()
+                    .or_else(|_| it.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+                    .with_context(|| format!("invalid DNS resolver address: {}", it))
+            })
+            .transpose()?;
+
+        Ok(Self::new(overrides, resolver))
+    }
+
+    pub fn lookup_override(&self, host: &str) -> Option<IpAddr> {
+        self.overrides
+            .get(host.trim_end_matches('.').to_ascii_lowercase().as_str())
+            .copied()
+    }
+
+    pub async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        if let Some(ip) = self.lookup_override(host) {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        let Some(nameserver) = self.resolver else {
+            return Ok(tokio::net::lookup_host((host, port)).await?.collect());
+        };
+
+        self.shared_resolver
+            .get_or_try_init(|| SharedResolver::new(nameserver))?
+            .lookup(host, port)
+            .await
+    }
+}
+
+/// Whether the operator has pointed `host` somewhere with `--dns-override`.
+pub fn is_overridden(host: &str) -> bool {
+    DNS_CONFIG
+        .get()
+        .is_some_and(|it| it.lookup_override(host).is_some())
+}
+
+/// Resolves `host` the way connections of user code do.
+pub async fn resolve_addr(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    match DNS_CONFIG.get() {
+        Some(config) => config.resolve(host, port).await,
+        None => DnsConfig::default().resolve(host, port).await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dns_overrides() {
+        let config = DnsConfig::parse(
+            ["db.internal=10.0.0.5", "API.internal = fd00::1"],
+            Some("10.0.0.2"),
+        )
+        .unwrap();
+
+        assert_eq!(config.resolver, Some("10.0.0.2:53".parse().unwrap()));
+        assert_eq!(
+            config.resolve("db.internal", 5432).await.unwrap(),
+            vec!["10.0.0.5:5432".parse().unwrap()]
+        );
+
+        assert_eq!(
+            config.resolve("api.internal.", 443).await.unwrap(),
+            vec!["[fd00::1]:443".parse().unwrap()]
+        );
+
+        assert_eq!(
+            config.resolve("[::1]", 80).await.unwrap(),
+            vec!["[::1]:80".parse().unwrap()]
+        );
+
+        assert!(DnsConfig::parse(["db.internal"], None).is_err());
+        assert!(DnsConfig::parse(["db.internal=localhost"], None).is_err());
+        assert!(DnsConfig::parse([], Some("ns.internal")).is_err());
+    }
+}
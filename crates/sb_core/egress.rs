@@ -0,0 +1,419 @@
+//! Egress policy of user workers, checked by the fetch, net and websocket
+//! permissions so that user code can't reach the private network of the host
+//! (SSRF).
+
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use deno_core::error::{custom_error, AnyError};
+use deno_fetch::reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use ipnet::{IpNet, Ipv4Net};
+
+use crate::dns::{is_overridden, resolve_addr};
+
+/// Ranges user workers can't reach unless a service allows them: the private
+/// networks of RFC 1918 and RFC 4193, loopback, link-local addresses (which
+/// include the metadata endpoints of cloud providers), the shared address
+/// space of carrier-grade NAT, the benchmarking network, multicast, the
+/// reserved range (which includes the broadcast address) and the unspecified
+/// addresses, which reach the host itself. The NAT64 prefixes (the well-known
+/// one and the local-use one of RFC 8215), 6to4 and Teredo are denied too,
+/// since they lead to any IPv4 address through a translator or a relay. IPv4-mapped IPv6 addresses are checked as the IPv4 address they map
+/// to.
+pub const DEFAULT_EGRESS_DENY: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "169.254.0.0/16",
+    "198.18.0.0/15",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "64:ff9b::/96",
+    "64:ff9b:1::/48",
+    "2001::/32",
+    "2002::/16",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EgressRule {
+    Cidr(IpNet),
+    /// A hostname, or `*.example.com` for its subdomains.
+    Host(String),
+}
+
+impl FromStr for EgressRule {
+    type Err = AnyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Ok(net) = s.parse::<IpNet>() {
+            return Ok(Self::Cidr(canonical_net(net)));
+        }
+
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Self::Cidr(IpNet::from(ip.to_canonical())));
+        }
+
+        if s.is_empty() || s.contains(['/', ':']) {
+            return Err(custom_error(
+                "TypeError",
+                format!("invalid egress rule: {:?}", s),
+            ));
+        }
+
+        Ok(Self::Host(s.to_ascii_lowercase()))
+    }
+}
+
+impl EgressRule {
+    fn matches_host(&self, host: &str) -> bool {
+        match self {
+            Self::Host(pattern) => match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|it| it.ends_with('.')),
+                None => pattern == host,
+            },
+
+            Self::Cidr(_) => false,
+        }
+    }
+
+    fn matches_ip(&self, ip: IpAddr) -> bool {
+        match self {
+            Self::Cidr(net) => net.contains(&ip),
+            Self::Host(_) => false,
+        }
+    }
+}
+
+/// Hosts and ranges that can't be reached, with exceptions. An allow rule wins
+/// over a deny rule. The default policy allows everything.
+#[derive(Debug, Clone, Default)]
+pub struct EgressPolicy {
+    allow: Vec<EgressRule>,
+    deny: Vec<EgressRule>,
+}
+
+impl EgressPolicy {
+    pub fn new(allow: Vec<EgressRule>, deny: Vec<EgressRule>) -> Self {
+        Self { allow, deny }
+    }
+
+    /// Parses the rules of a policy. `deny` defaults to
+    /// [`DEFAULT_EGRESS_DENY`].
+    pub fn parse(allow: &[String], deny: Option<&[String]>) -> Result<Self, AnyError> {
+        Ok(Self::new(
+            parse_rules(allow.iter().map(String::as_str))?,
+            match deny {
+                Some(deny) => parse_rules(deny.iter().map(String::as_str))?,
+                None => parse_rules(DEFAULT_EGRESS_DENY.iter().copied())?,
+            },
+        ))
+    }
+
+    /// Checks `host` against the host rules, and against the CIDRs if it's an
+    /// IP literal. The addresses a hostname resolves to are checked when the
+    /// connection is made, through [`EgressPolicy::check_addrs`].
+    pub fn check(&self, host: &str) -> Result<(), AnyError> {
+        if self.deny.is_empty() {
+            return Ok(());
+        }
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return self.check_ip(host, ip);
+        }
+
+        let host = host.to_ascii_lowercase();
+
+        if self.allow.iter().any(|it| it.matches_host(&host)) {
+            return Ok(());
+        }
+
+        if self.deny.iter().any(|it| it.matches_host(&host)) {
+            return Err(egress_denied(&host));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the addresses `host` resolved to, right before connecting to
+    /// them. A hostname allowed by name is allowed wherever it points.
+    pub fn check_addrs(&self, host: &str, addrs: &[SocketAddr]) -> Result<(), AnyError> {
+        // NOTE: A host the operator pointed somewhere is trusted to lead
+        // there, which is usually an internal service in a denied range.
+        if self.deny.is_empty() || is_overridden(host) {
+            return Ok(());
+        }
+
+        let host = host.to_ascii_lowercase();
+
+        if self.allow.iter().any(|it| it.matches_host(&host)) {
+            return Ok(());
+        }
+
+        for addr in addrs {
+            self.check_ip(&host, addr.ip())?;
+        }
+
+        Ok(())
+    }
+
+    /// Like [`EgressPolicy::check`], but also resolves a hostname to check its
+    /// addresses, for the connections that resolve it again on their own
+    /// (`Deno.connectTls()` with a client certificate, `WebSocket` and the
+    /// http module of node with a client of its own).
+    ///
+    /// NOTE: The name can point elsewhere by the time the connection resolves
+    /// it. Connections that go through [`EgressResolver`] or [`resolve_addr`]
+    /// don't need it.
+    pub async fn check_lookup(&self, host: &str) -> Result<(), AnyError> {
+        self.check(host)?;
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        if host.parse::<IpAddr>().is_ok()
+            || !self.deny.iter().any(|it| matches!(it, EgressRule::Cidr(_)))
+        {
+            return Ok(());
+        }
+
+        // NOTE: A failed lookup is left for the connection to report.
+        let Ok(addrs) = resolve_addr(host, 0).await else {
+            return Ok(());
+        };
+
+        self.check_addrs(host, &addrs)
+    }
+
+    fn check_ip(&self, host: &str, ip: IpAddr) -> Result<(), AnyError> {
+        let ip = ip.to_canonical();
+
+        if self.allow.iter().any(|it| it.matches_ip(ip)) {
+            return Ok(());
+        }
+
+        if self.deny.iter().any(|it| it.matches_ip(ip)) {
+            return Err(egress_denied(host));
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolves the hosts `fetch()` connects to through the DNS overrides of the
+/// runtime, and refuses the ones whose addresses the egress policy denies.
+/// The connection is made to the addresses checked here, so a name can't be
+/// pointed elsewhere between the check and the connection.
+#[derive(Debug, Clone)]
+pub struct EgressResolver(pub Arc<EgressPolicy>);
+
+impl Resolve for EgressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.0.clone();
+
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs = resolve_addr(host, 0).await?;
+
+            policy.check_addrs(host, &addrs)?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Turns a range of IPv4-mapped IPv6 addresses into the IPv4 range they map
+/// to, since addresses are matched as the IPv4 address they map to.
+fn canonical_net(net: IpNet) -> IpNet {
+    match net {
+        IpNet::V6(v6) if v6.prefix_len() >= 96 => match v6.network().to_ipv4_mapped() {
+            Some(v4) => IpNet::V4(Ipv4Net::new(v4, v6.prefix_len() - 96).unwrap()),
+            None => net,
+        },
+        it => it,
+    }
+}
+
+fn parse_rules<'a>(rules: impl Iterator<Item = &'a str>) -> Result<Vec<EgressRule>, AnyError> {
+    rules.map(EgressRule::from_str).collect()
+}
+
+fn egress_denied(host: &str) -> AnyError {
+    custom_error(
+        "PermissionDenied",
+        format!("Access to {host} is denied by the egress policy of the user worker"),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_egress_policy() {
+        let policy = EgressPolicy::parse(
+            &["10.1.2.3".to_string(), "*.internal.example.com".to_string()],
+            None,
+        )
+        .unwrap();
+
+        assert!(policy.check("93.184.215.14").is_ok());
+        assert!(policy.check("10.0.0.1").is_err());
+        assert!(policy.check("127.0.0.1").is_err());
+        assert!(policy.check("0.0.0.0").is_err());
+        assert!(policy.check("100.64.0.1").is_err());
+        assert!(policy.check("[::1]").is_err());
+        assert!(policy.check("[::]").is_err());
+        assert!(policy.check("[::ffff:127.0.0.1]").is_err());
+        assert!(policy.check("169.254.169.254").is_err());
+        assert!(policy.check("[::ffff:192.168.1.1]").is_err());
+        assert!(policy.check("[fe80::1]").is_err());
+        assert!(policy.check("10.1.2.3").is_ok());
+        assert!(policy.check("api.internal.example.com").is_ok());
+
+        let policy =
+            EgressPolicy::parse(&[], Some(&["metadata.google.internal".to_string()])).unwrap();
+
+        assert!(policy.check("metadata.google.internal").is_err());
+        assert!(policy.check("10.0.0.1").is_ok());
+        assert!(EgressPolicy::default().check("10.0.0.1").is_ok());
+        assert!("10.0.0.0/33".parse::<EgressRule>().is_err());
+    }
+
+    #[test]
+    fn test_egress_policy_resolved_addrs() {
+        let policy = EgressPolicy::parse(&["db.example.com".to_string()], None).unwrap();
+        let private = ["10.0.0.5:443".parse().unwrap()];
+        let public = ["93.184.215.14:443".parse().unwrap()];
+
+        // NOTE: A hostname isn't resolved by the permission check, but the
+        // addresses it resolved to are checked before connecting.
+        assert!(policy.check("rebind.example.com").is_ok());
+        assert!(policy.check_addrs("rebind.example.com", &private).is_err());
+        assert!(policy.check_addrs("rebind.example.com", &public).is_ok());
+        assert!(policy.check_addrs("db.example.com", &private).is_ok());
+        assert!(policy
+            .check_addrs(
+                "mapped.example.com",
+                &["[::ffff:10.0.0.5]:443".parse().unwrap()]
+            )
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_egress_policy_lookup() {
+        let policy = EgressPolicy::parse(&[], None).unwrap();
+
+        assert!(policy.check_lookup("localhost").await.is_err());
+        assert!(policy.check_lookup("[::1]").await.is_err());
+        assert!(EgressPolicy::default()
+            .check_lookup("localhost")
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn test_default_egress_deny() {
+        let policy = EgressPolicy::parse(&[], None).unwrap();
+
+        for (range, ips) in [
+            ("0.0.0.0/8", &["0.0.0.0", "0.1.2.3"][..]),
+            ("10.0.0.0/8", &["10.0.0.1", "10.255.255.255"][..]),
+            ("100.64.0.0/10", &["100.64.0.1", "100.127.255.255"][..]),
+            ("127.0.0.0/8", &["127.0.0.1", "127.1.2.3"][..]),
+            ("172.16.0.0/12", &["172.16.0.1", "172.31.255.255"][..]),
+            ("192.168.0.0/16", &["192.168.0.1", "192.168.255.255"][..]),
+            ("169.254.0.0/16", &["169.254.169.254"][..]),
+            ("198.18.0.0/15", &["198.18.0.1", "198.19.255.255"][..]),
+            ("224.0.0.0/4", &["224.0.0.251", "239.255.255.250"][..]),
+            ("240.0.0.0/4", &["240.0.0.1", "255.255.255.255"][..]),
+            ("::/128", &["::"][..]),
+            ("::1/128", &["::1"][..]),
+            (
+                "64:ff9b::/96",
+                &["64:ff9b::a00:1", "64:ff9b::5db8:d70e"][..],
+            ),
+            (
+                "64:ff9b:1::/48",
+                &["64:ff9b:1::a00:1", "64:ff9b:1:ffff::1"][..],
+            ),
+            ("2001::/32", &["2001::1", "2001:0:4136:e378::1"][..]),
+            ("2002::/16", &["2002:a00:1::1", "2002:5db8:d70e::1"][..]),
+            ("fc00::/7", &["fc00::1", "fd12:3456::1"][..]),
+            ("fe80::/10", &["fe80::1", "febf::1"][..]),
+        ] {
+            assert!(DEFAULT_EGRESS_DENY.contains(&range), "{}", range);
+
+            for ip in ips {
+                assert!(policy.check(ip).is_err(), "{} in {}", ip, range);
+
+                if ip.parse::<IpAddr>().unwrap().is_ipv4() {
+                    let mapped = format!("[::ffff:{}]", ip);
+
+                    assert!(policy.check(&mapped).is_err(), "{} in {}", mapped, range);
+                }
+            }
+        }
+
+        for ip in [
+            "1.1.1.1",
+            "93.184.215.14",
+            "100.128.0.1",
+            "172.32.0.1",
+            "198.20.0.1",
+            "223.255.255.255",
+            "::ffff:93.184.215.14",
+            "64:ff9c::1",
+            "64:ff9b:2::1",
+            "2001:db8::1",
+            "2001:4860:4860::8888",
+            "2606:4700:4700::1111",
+        ] {
+            assert!(policy.check(ip).is_ok(), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_egress_rules_of_mapped_addresses() {
+        let policy = EgressPolicy::parse(
+            &["::ffff:10.1.0.0/112".to_string()],
+            Some(&["::ffff:10.0.0.0/104".to_string()]),
+        )
+        .unwrap();
+
+        assert!(policy.check("10.2.0.1").is_err());
+        assert!(policy.check("[::ffff:10.2.0.1]").is_err());
+        assert!(policy.check("10.1.0.1").is_ok());
+        assert!(policy.check("[::ffff:10.1.0.1]").is_ok());
+        assert!(policy.check("11.0.0.1").is_ok());
+        assert_eq!(
+            "::ffff:10.0.0.0/104".parse::<EgressRule>().unwrap(),
+            "10.0.0.0/8".parse::<EgressRule>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_egress_policy_dns_overrides() {
+        let _ = crate::dns::DNS_CONFIG
+            .set(crate::dns::DnsConfig::parse(["db.egress.internal=10.0.0.5"], None).unwrap());
+
+        let policy = EgressPolicy::parse(&[], None).unwrap();
+        let private = ["10.0.0.5:5432".parse().unwrap()];
+
+        assert!(policy.check_addrs("db.egress.internal", &private).is_ok());
+        assert!(policy
+            .check_addrs("other.egress.internal", &private)
+            .is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! The execution of a user worker as seen by its code through
+//! `EdgeRuntime.context`: who it is and how much of its budget is left.
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use deno_core::{op2, v8, OpState};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionContext {
+    pub execution_id: Option<String>,
+    pub service_name: Option<String>,
+    pub cpu_time_limit_ms: Option<u64>,
+    pub memory_limit_bytes: Option<usize>,
+    pub clock: Arc<ExecutionClock>,
+}
+
+/// The wall clock and CPU time budget of a worker. The supervisor restarts it
+/// whenever it resets the limits, which the per request policy does for every
+/// request.
+#[derive(Debug, Default)]
+pub struct ExecutionClock {
+    /// CPU time the worker has used, as of the start of the current turn of
+    /// its event loop.
+    pub cpu_time_used_ns: AtomicI64,
+    /// CPU time the worker had used when the budget was last restarted.
+    cpu_time_baseline_ns: AtomicI64,
+    /// Time, in milliseconds since the epoch, the worker is terminated at; `0`
+    /// without a wall clock limit.
+    deadline_ms: AtomicU64,
+}
+
+impl ExecutionClock {
+    /// Moves the deadline to `timeout` from now, or removes it.
+    pub fn set_deadline(&self, timeout: Option<Duration>) {
+        let deadline_ms = timeout.map_or(0, |it| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .saturating_add(it)
+                .as_millis() as u64
+        });
+
+        self.deadline_ms.store(deadline_ms, Ordering::Relaxed);
+    }
+
+    /// Starts counting the CPU time used from now on.
+    pub fn reset_cpu_time(&self) {
+        self.cpu_time_baseline_ns.store(
+            self.cpu_time_used_ns.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn deadline_ms(&self) -> Option<u64> {
+        Some(self.deadline_ms.load(Ordering::Relaxed)).filter(|it| *it > 0)
+    }
+
+    pub fn cpu_time_used_ms(&self) -> u64 {
+        let used_ns = self.cpu_time_used_ns.load(Ordering::Relaxed)
+            - self.cpu_time_baseline_ns.load(Ordering::Relaxed);
+
+        used_ns.max(0) as u64 / 1_000_000
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionInfo {
+    execution_id: Option<String>,
+    service_name: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionBudget {
+    cpu_time_ms: Option<u64>,
+    memory_bytes: Option<usize>,
+}
+
+#[op2]
+#[serde]
+pub fn op_execution_context(state: &mut OpState) -> Option<ExecutionInfo> {
+    state
+        .try_borrow::<ExecutionContext>()
+        .map(|it| ExecutionInfo {
+            execution_id: it.execution_id.clone(),
+            service_name: it.service_name.clone(),
+        })
+}
+
+/// Returns the time, in milliseconds since the epoch, the worker is terminated
+/// at, which moves with every request under the per request policy.
+#[op2]
+#[serde]
+pub fn op_execution_deadline(state: &mut OpState) -> Option<u64> {
+    state
+        .try_borrow::<ExecutionContext>()
+        .and_then(|it| it.clock.deadline_ms())
+}
+
+/// Returns what is left of the CPU time and memory of the worker. A budget
+/// without a limit is `null`.
+#[op2]
+#[serde]
+pub fn op_execution_budget(scope: &mut v8::HandleScope, state: &mut OpState) -> ExecutionBudget {
+    let Some(context) = state.try_borrow::<ExecutionContext>() else {
+        return ExecutionBudget {
+            cpu_time_ms: None,
+            memory_bytes: None,
+        };
+    };
+
+    let cpu_time_used_ms = context.clock.cpu_time_used_ms();
+
+    let memory_bytes = context.memory_limit_bytes.map(|limit| {
+        let mut stats = v8::HeapStatistics::default();
+
+        scope.get_heap_statistics(&mut stats);
+        limit.saturating_sub(
+            stats
+                .used_heap_size()
+                .saturating_add(stats.external_memory()),
+        )
+    });
+
+    ExecutionBudget {
+        cpu_time_ms: context
+            .cpu_time_limit_ms
+            .map(|it| it.saturating_sub(cpu_time_used_ms)),
+        memory_bytes,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execution_clock_restarts() {
+        let clock = ExecutionClock::default();
+
+        assert_eq!(clock.deadline_ms(), None);
+
+        clock.set_deadline(Some(Duration::from_secs(60)));
+
+        let deadline_ms = clock.deadline_ms().unwrap();
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+
+        assert!(deadline_ms > now_ms && deadline_ms <= now_ms + 60_000);
+
+        clock.set_deadline(None);
+        assert_eq!(clock.deadline_ms(), None);
+
+        clock.cpu_time_used_ns.store(30_000_000, Ordering::Relaxed);
+        assert_eq!(clock.cpu_time_used_ms(), 30);
+
+        clock.reset_cpu_time();
+        assert_eq!(clock.cpu_time_used_ms(), 0);
+
+        clock.cpu_time_used_ns.store(45_000_000, Ordering::Relaxed);
+        assert_eq!(clock.cpu_time_used_ms(), 15);
+    }
+}
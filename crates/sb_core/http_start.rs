@@ -1,16 +1,26 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::future::Future;
 use std::rc::Rc;
 
 use deno_core::error::bad_resource;
 use deno_core::error::bad_resource_id;
+use deno_core::error::type_error;
 use deno_core::error::AnyError;
 use deno_core::op2;
+use deno_core::ByteString;
+use deno_core::CancelFuture;
+use deno_core::CancelHandle;
 use deno_core::OpState;
+use deno_core::RcRef;
 use deno_core::ResourceId;
 use deno_http::http_create_conn_resource;
+use deno_http::{HttpRequestReader, HttpStreamReadResource};
+use hyper_v014::header::{HeaderName, HeaderValue};
+use hyper_v014::HeaderMap;
 use tokio_util::sync::CancellationToken;
 
-use crate::conn_sync::ConnWatcher;
+use crate::conn_sync::{ConnWatcher, CronRequest, RequestMarks, MUX_REQUEST_HEADER};
 use crate::http::DuplexStream2;
 use crate::net::TokioDuplexResource;
 
@@ -41,7 +51,12 @@ fn op_http_start(
             "http",
         )?;
 
-        let conn_watcher = state.resource_table.add(ConnWatcher(token));
+        let marks = state
+            .try_borrow_mut::<HashMap<usize, RequestMarks>>()
+            .and_then(|it| it.remove(&id))
+            .unwrap_or_default();
+
+        let conn_watcher = state.resource_table.add(ConnWatcher(token, marks));
 
         return Ok((conn, conn_watcher));
     }
@@ -49,4 +64,193 @@ fn op_http_start(
     Err(bad_resource_id())
 }
 
-deno_core::extension!(sb_core_http_start, ops = [op_http_start]);
+/// Returns a watcher of its own for the request read from `stream_rid`, if the
+/// runtime multiplexed it over the connection watched by `rid`, or `None` for a
+/// request that has the connection to itself. The key of the request is taken
+/// out of its headers, so the worker never sees it.
+#[op2(async)]
+#[serde]
+async fn op_http_request_watcher(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+    #[smi] stream_rid: ResourceId,
+) -> Result<Option<ResourceId>, AnyError> {
+    let (requests, stream) = {
+        let state = state.borrow();
+        let requests = state
+            .resource_table
+            .get::<ConnWatcher>(rid)
+            .ok()
+            .and_then(|it| it.1.mux.clone());
+
+        let Some(requests) = requests else {
+            return Ok(None);
+        };
+
+        (
+            requests,
+            state
+                .resource_table
+                .get::<HttpStreamReadResource>(stream_rid)?,
+        )
+    };
+
+    let key = {
+        let mut rd = RcRef::map(&stream, |r| &r.rd).borrow_mut().await;
+        let HttpRequestReader::Headers(request) = &mut *rd else {
+            return Err(bad_resource("request was already read from"));
+        };
+
+        request.headers_mut().remove(MUX_REQUEST_HEADER)
+    };
+
+    let entry = key
+        .and_then(|it| it.to_str().ok()?.parse::<u64>().ok())
+        .and_then(|it| requests.take(it));
+
+    // NOTE: The entry of a request is gone if the client went away before the
+    // worker took the request.
+    let (token, marks) = entry.unwrap_or_else(|| {
+        let token = CancellationToken::new();
+
+        token.cancel();
+        (Some(token), RequestMarks::default())
+    });
+
+    Ok(Some(
+        state
+            .borrow_mut()
+            .resource_table
+            .add(ConnWatcher(token, marks)),
+    ))
+}
+
+/// Resolves to `true` once the connection watched by `rid` is done with, which
+/// is when the client goes away or the response to it has been sent, or to
+/// `false` right away for a connection nobody watches. It also resolves to
+/// `false` once `cancel_rid` is closed, as a connection that is kept alive
+/// outlives the request that is watching it.
+#[op2(async)]
+fn op_http_conn_closed(
+    state: &mut OpState,
+    #[smi] rid: ResourceId,
+    #[smi] cancel_rid: ResourceId,
+) -> impl Future<Output = bool> {
+    // NOTE: The token is cloned out of the resource here, as the watcher is
+    // taken by the request being forwarded to a user worker.
+    let token = state
+        .resource_table
+        .get::<ConnWatcher>(rid)
+        .ok()
+        .and_then(|it| it.get());
+
+    let cancel = state.resource_table.get::<CancelHandle>(cancel_rid).ok();
+
+    async move {
+        let (Some(token), Some(cancel)) = (token, cancel) else {
+            return false;
+        };
+
+        token.cancelled_owned().or_cancel(cancel).await.is_ok()
+    }
+}
+
+/// Returns the `Deno.cron()` request the main worker sent over the connection
+/// watched by `rid`, if any.
+#[op2]
+#[serde]
+fn op_http_cron_request(state: &mut OpState, #[smi] rid: ResourceId) -> Option<CronRequest> {
+    state
+        .resource_table
+        .get::<ConnWatcher>(rid)
+        .ok()
+        .and_then(|it| it.1.cron.clone())
+}
+
+/// Sets the trailers of the response to the request watched by `rid`, which
+/// replace any set before.
+#[op2]
+fn op_http_set_response_trailers(
+    state: &mut OpState,
+    #[smi] rid: ResourceId,
+    #[serde] trailers: Vec<(ByteString, ByteString)>,
+) -> Result<(), AnyError> {
+    let watcher = state.resource_table.get::<ConnWatcher>(rid)?;
+    let mut map = HeaderMap::with_capacity(trailers.len());
+
+    for (name, value) in trailers {
+        let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(&name),
+            HeaderValue::from_bytes(&value),
+        ) else {
+            return Err(type_error("invalid trailer"));
+        };
+
+        map.append(name, value);
+    }
+
+    watcher.1.trailers.set(map);
+    Ok(())
+}
+
+/// Sends `links` to the client of the request watched by `rid` in a `103 Early
+/// Hints`, if it can take one.
+#[op2]
+fn op_http_send_early_hints(
+    state: &mut OpState,
+    #[smi] rid: ResourceId,
+    #[serde] links: Vec<String>,
+) -> Result<(), AnyError> {
+    let watcher = state.resource_table.get::<ConnWatcher>(rid)?;
+    let Some(early_hints) = watcher.1.early_hints.as_ref() else {
+        return Ok(());
+    };
+
+    let links = links
+        .iter()
+        .map(|it| HeaderValue::from_str(it).map_err(|_| type_error("invalid link")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    early_hints.send(&links);
+    Ok(())
+}
+
+/// Sets the state kept for the upgraded connection of the request watched by
+/// `rid` while its worker hibernates.
+#[op2]
+fn op_http_set_hibernation_state(
+    state: &mut OpState,
+    #[smi] rid: ResourceId,
+    #[string] hibernation_state: String,
+) -> Result<(), AnyError> {
+    let watcher = state.resource_table.get::<ConnWatcher>(rid)?;
+
+    watcher.1.hibernation_state.set(hibernation_state);
+    Ok(())
+}
+
+/// Returns the state a hibernated worker left for the upgraded connection the
+/// request watched by `rid` picks back up, if it does.
+#[op2]
+#[string]
+fn op_http_hibernation_state(state: &mut OpState, #[smi] rid: ResourceId) -> Option<String> {
+    state
+        .resource_table
+        .get::<ConnWatcher>(rid)
+        .ok()
+        .and_then(|it| it.1.resumed_from.clone())
+}
+
+deno_core::extension!(
+    sb_core_http_start,
+    ops = [
+        op_http_start,
+        op_http_request_watcher,
+        op_http_conn_closed,
+        op_http_cron_request,
+        op_http_set_response_trailers,
+        op_http_send_early_hints,
+        op_http_set_hibernation_state,
+        op_http_hibernation_state
+    ]
+);
@@ -44,12 +44,20 @@ import {
 import { promiseRejectMacrotaskCallback } from 'ext:sb_core_main_js/js/promises.js';
 import { denoOverrides, fsVars } from 'ext:sb_core_main_js/js/denoOverrides.js';
 import { registerDeclarativeServer } from 'ext:sb_core_main_js/js/00_serve.js';
+import { EdgeRuntimeContext } from 'ext:sb_core_main_js/js/context.js';
+import {
+	getHibernationState,
+	sendEarlyHints,
+	setHibernationState,
+	setResponseTrailers,
+} from 'ext:sb_core_main_js/js/http.js';
 import * as performance from 'ext:deno_web/15_performance.js';
 import * as messagePort from 'ext:deno_web/13_message_port.js';
 import { SupabaseEventListener } from 'ext:sb_user_event_worker/event_worker.js';
 import * as MainWorker from 'ext:sb_core_main_js/js/main_worker.js';
 import * as DenoWSStream from 'ext:deno_websocket/02_websocketstream.js';
 import * as eventSource from 'ext:deno_fetch/27_eventsource.js';
+import * as cache from 'ext:sb_cache/cache.js';
 import * as WebGPU from 'ext:deno_webgpu/00_init.js';
 import * as WebGPUSurface from 'ext:deno_webgpu/02_surface.js';
 
@@ -66,8 +74,11 @@ const {
 	ObjectKeys,
 	ObjectDefineProperty,
 	ObjectDefineProperties,
+	ObjectFreeze,
 	ObjectSetPrototypeOf,
 	ObjectHasOwn,
+	PromisePrototypeThen,
+	PromiseResolve,
 	SafeSet,
 	StringPrototypeIncludes,
 	StringPrototypeSplit,
@@ -146,6 +157,11 @@ const globalScope = {
 	Headers: nonEnumerable(headers.Headers),
 	fetch: writable(fetch.fetch),
 
+	// cache
+	Cache: nonEnumerable(cache.Cache),
+	CacheStorage: nonEnumerable(cache.CacheStorage),
+	caches: readOnly(cache.caches),
+
 	// base64
 	atob: writable(base64.atob),
 	btoa: writable(base64.btoa),
@@ -249,6 +265,9 @@ const globalScope = {
 	// messagePort
 	structuredClone: writable(messagePort.structuredClone),
 
+	// broadcastChannel
+	BroadcastChannel: nonEnumerable(broadcastChannel.BroadcastChannel),
+
 	// Branding as a WebIDL object
 	[webidl.brand]: nonEnumerable(webidl.brand),
 };
@@ -495,11 +514,9 @@ globalThis.bootstrapSBEdge = (opts, extraCtx) => {
 	// because they are not counted in the external memory statistics of the
 	// individual isolates.
 
-	// NOTE(Nyannyacha): Put below inside `isUserWorker` block if we have the
-	// plan to support a shared array buffer across the isolates. But for now,
-	// we explicitly disabled the shared buffer option between isolate globally
-	// in `deno_runtime.rs`, so this patch also applies regardless of worker
-	// type.
+	// NOTE: The main worker can create shared buffers and share them with
+	// user workers, which can't create any themselves. See
+	// `EdgeRuntime.sharedBuffer()`.
 	const wasmMemoryCtor = globalThis.WebAssembly.Memory;
 	const wasmMemoryPrototypeGrow = wasmMemoryCtor.prototype.grow;
 
@@ -521,19 +538,64 @@ globalThis.bootstrapSBEdge = (opts, extraCtx) => {
 		return new wasmMemoryCtor(maybeOpts);
 	}
 
-	delete globalThis.SharedArrayBuffer;
-	globalThis.WebAssembly.Memory = patchedWasmMemoryCtor;
+	if (isUserWorker) {
+		delete globalThis.SharedArrayBuffer;
+		globalThis.WebAssembly.Memory = patchedWasmMemoryCtor;
+	}
 
 	/// DISABLE SHARED MEMORY INSTALL MEM CHECK TIMING
 
 	if (isUserWorker) {
 		delete globalThis.EdgeRuntime;
 
+		ObjectDefineProperty(globalThis, 'EdgeRuntime', readOnly(ObjectFreeze({
+			context: EdgeRuntimeContext,
+			// A `SharedArrayBuffer` the main worker shared with this worker,
+			// or `undefined`.
+			sharedBuffer(name) {
+				return ops.op_user_worker_shared_buffer(`${name}`);
+			},
+			// Keeps the worker alive (within its limits) after the response was
+			// sent, until `promise` settles.
+			waitUntil(promise) {
+				ops.op_background_task_begin();
+				PromisePrototypeThen(
+					PromiseResolve(promise),
+					() => ops.op_background_task_end(),
+					(err) => {
+						ops.op_background_task_end();
+						globalThis.console.error('background task failed:', err);
+					},
+				);
+
+				return promise;
+			},
+			// Sets the trailers sent after the body of the response to
+			// `request`. See `setResponseTrailers()` in `http.js`.
+			setTrailers(request, trailers) {
+				setResponseTrailers(request, trailers);
+			},
+			// Sends `links` to the client of `request` in a `103 Early Hints`
+			// before the response is returned. See `sendEarlyHints()` in
+			// `http.js`.
+			sendEarlyHints(request, links) {
+				sendEarlyHints(request, links);
+			},
+			// Keeps `state` for the WebSocket accepted for `request` while the
+			// worker hibernates. See `setHibernationState()` in `http.js`.
+			setHibernationState(request, state) {
+				setHibernationState(request, state);
+			},
+			getHibernationState(request) {
+				return getHibernationState(request);
+			},
+		})));
+
 		// override console
 		ObjectDefineProperties(globalThis, {
 			console: nonEnumerable(
 				new console.Console((msg, level) => {
-					return ops.op_user_worker_log(msg, level > 1);
+					return ops.op_user_worker_log(msg, level);
 				}),
 			),
 		});
@@ -0,0 +1,90 @@
+import { AsyncLocalStorage } from "ext:deno_node/async_hooks.ts";
+import { core, primordials } from "ext:core/mod.js";
+
+const ops = core.ops;
+const {
+	DateNow,
+	JSONParse,
+	MathMax,
+	ObjectFreeze,
+	StringPrototypeCharCodeAt,
+} = primordials;
+
+// The main worker forwards the caller identity it derived for a request in
+// this header, encoded as base64 JSON.
+const IDENTITY_HEADER = "x-edge-runtime-identity";
+
+const requestContext = new AsyncLocalStorage();
+const decoder = new TextDecoder();
+
+function getRequestIdentity(request) {
+	const raw = request.headers.get(IDENTITY_HEADER);
+
+	if (raw === null) {
+		return null;
+	}
+
+	try {
+		const bytes = Uint8Array.from(atob(raw), (it) => StringPrototypeCharCodeAt(it, 0));
+		return ObjectFreeze(JSONParse(decoder.decode(bytes)));
+	} catch {
+		return null;
+	}
+}
+
+// Runs `fn` (the request handler) within the context of `request`, so that
+// `EdgeRuntime.context` reflects the request being handled.
+function runWithRequestContext(request, fn) {
+	const identity = getRequestIdentity(request);
+
+	// NOTE: Requests without an identity skip the async context entirely to
+	// avoid installing promise hooks in workers that don't need them.
+	if (identity === null) {
+		return fn();
+	}
+
+	return requestContext.run({ identity }, fn);
+}
+
+let executionInfo = null;
+
+function getExecutionInfo() {
+	executionInfo ??= ObjectFreeze(ops.op_execution_context() ?? {
+		executionId: null,
+		serviceName: null,
+	});
+
+	return executionInfo;
+}
+
+const EdgeRuntimeContext = ObjectFreeze({
+	get identity() {
+		return requestContext.getStore()?.identity ?? null;
+	},
+	get executionId() {
+		return getExecutionInfo().executionId;
+	},
+	get serviceName() {
+		return getExecutionInfo().serviceName;
+	},
+	// Time, in milliseconds since the epoch, the worker is terminated at, or
+	// `null` without a wall clock limit. Under the per request policy, it
+	// moves along with every request.
+	get deadline() {
+		return ops.op_execution_deadline();
+	},
+	// What is left of the CPU time (in milliseconds) and of the memory (in
+	// bytes) of the worker; a budget without a limit is `null`.
+	get remaining() {
+		const { cpuTimeMs, memoryBytes } = ops.op_execution_budget();
+		const deadline = ops.op_execution_deadline();
+
+		return ObjectFreeze({
+			timeMs: deadline === null ? null : MathMax(deadline - DateNow(), 0),
+			cpuTimeMs,
+			memoryBytes,
+		});
+	},
+});
+
+export { EdgeRuntimeContext, runWithRequestContext };
@@ -0,0 +1,92 @@
+import { primordials } from "ext:core/mod.js";
+import { runWithRequestContext } from "ext:sb_core_main_js/js/context.js";
+
+const {
+	ArrayFrom,
+	MapPrototypeDelete,
+	MapPrototypeGet,
+	MapPrototypeHas,
+	MapPrototypeSet,
+	RegExpPrototypeTest,
+	StringPrototypeTrim,
+} = primordials;
+
+const CRON_NAME_REGEX = /^[a-zA-Z0-9_-]{1,64}$/;
+
+const jobs = new Map();
+
+// Registers a job the way `Deno.cron(name, schedule, [options], handler)` does.
+// The returned promise resolves once the job is stopped through
+// `options.signal`.
+function registerCronJob(name, schedule, handlerOrOptions, maybeHandler) {
+	const [options, handler] = typeof handlerOrOptions === "function"
+		? [{}, handlerOrOptions]
+		: [handlerOrOptions ?? {}, maybeHandler];
+
+	if (typeof name !== "string" || !RegExpPrototypeTest(CRON_NAME_REGEX, name)) {
+		throw new TypeError(
+			"Invalid cron name: only alphanumeric characters, '_' and '-' are allowed, up to 64 of them",
+		);
+	}
+
+	if (typeof schedule !== "string") {
+		throw new TypeError("Cron schedule must be a string");
+	}
+
+	if (typeof handler !== "function") {
+		throw new TypeError("Cron handler must be a function");
+	}
+
+	if (MapPrototypeHas(jobs, name)) {
+		throw new TypeError(`Cron with this name already exists: ${name}`);
+	}
+
+	const job = { schedule: StringPrototypeTrim(schedule), handler };
+
+	MapPrototypeSet(jobs, name, job);
+
+	return new Promise((resolve) => {
+		options.signal?.addEventListener("abort", () => {
+			if (MapPrototypeGet(jobs, name) === job) {
+				MapPrototypeDelete(jobs, name);
+			}
+
+			resolve();
+		}, { once: true });
+	});
+}
+
+// Answers the requests the scheduler of the main worker sends to list or run
+// the jobs, or returns `null` for any other request.
+//
+// NOTE: `cronRequest` is set by the runtime on the requests sent through
+// `worker.fetch(req, { cron })` alone (`"list"` or `{ run: name }`), so that
+// clients can't run the jobs.
+async function respondToCronRequest(cronRequest, request) {
+	if (cronRequest === null) {
+		return null;
+	}
+
+	if (cronRequest === "list") {
+		return Response.json(
+			ArrayFrom(jobs, ([name, { schedule }]) => ({ name, schedule })),
+		);
+	}
+
+	const name = cronRequest.run;
+	const job = MapPrototypeGet(jobs, name);
+
+	if (job === undefined) {
+		return new Response(null, { status: 404 });
+	}
+
+	try {
+		await runWithRequestContext(request, () => job.handler());
+		return new Response(null, { status: 204 });
+	} catch (error) {
+		console.error(`cron job ${name} failed:`, error);
+		return new Response(null, { status: 500 });
+	}
+}
+
+export { registerCronJob, respondToCronRequest };
@@ -1,12 +1,15 @@
+import { core } from 'ext:core/mod.js';
 import * as net from 'ext:deno_net/01_net.js';
 import * as tls from 'ext:deno_net/02_tls.js';
 import * as timers from 'ext:deno_web/02_timers.js';
 import * as permissions from 'ext:sb_core_main_js/js/permissions.js';
 import { errors } from 'ext:sb_core_main_js/js/errors.js';
-import { serve, serveHttp, upgradeWebSocket } from 'ext:sb_core_main_js/js/http.js';
+import { cron, serve, serveHttp, upgradeWebSocket } from 'ext:sb_core_main_js/js/http.js';
 import * as fs from 'ext:deno_fs/30_fs.js';
 import { osCalls } from 'ext:sb_os/os.js';
 import * as io from 'ext:deno_io/12_io.js';
+import { openKv } from 'ext:sb_kv/kv.js';
+import * as ffi from 'ext:deno_ffi/00_ffi.js';
 
 const osCallsVars = {
 	gid: osCalls.gid,
@@ -88,6 +91,34 @@ const fsVars = {
 	utimeSync: fs.utimeSync,
 };
 
+// NOTE: `connectTls` of deno_net resolves the hostname on its own. Connecting
+// with `connect`, which resolves it through the DNS overrides of the runtime
+// and checks the addresses against the egress policy, and upgrading the
+// connection keeps TLS on the same path. `startTls` can't present a client
+// certificate, which is left to the original once the addresses of the
+// hostname have been checked.
+async function connectTls(options) {
+	const { hostname = '127.0.0.1', port, caCerts, alpnProtocols } = options;
+
+	if (
+		options.certFile !== undefined || options.cert !== undefined ||
+		options.key !== undefined || options.certChain !== undefined ||
+		options.privateKey !== undefined
+	) {
+		await core.ops.op_net_check_egress(hostname);
+		return await tls.connectTls(options);
+	}
+
+	const conn = await net.connect({ hostname, port });
+
+	try {
+		return await tls.startTls(conn, { hostname, caCerts, alpnProtocols });
+	} catch (err) {
+		conn.close();
+		throw err;
+	}
+}
+
 const ioVars = {
 	stdout: io.stdout,
 	stderr: io.stderr,
@@ -95,12 +126,13 @@ const ioVars = {
 };
 
 const denoOverrides = {
+	cron,
 	serve,
 	serveHttp,
 	upgradeWebSocket,
 	listen: net.listen,
 	connect: net.connect,
-	connectTls: tls.connectTls,
+	connectTls,
 	startTls: tls.startTls,
 	resolveDns: net.resolveDns,
 	permissions: permissions.permissions,
@@ -110,6 +142,12 @@ const denoOverrides = {
 	refTimer: timers.refTimer,
 	unrefTimer: timers.unrefTimer,
 	isatty: (_arg) => false,
+	openKv,
+	dlopen: ffi.dlopen,
+	UnsafeCallback: ffi.UnsafeCallback,
+	UnsafePointer: ffi.UnsafePointer,
+	UnsafePointerView: ffi.UnsafePointerView,
+	UnsafeFnPointer: ffi.UnsafeFnPointer,
 	...ioVars,
 	...fsVars,
 	...osCallsVars,
@@ -27,6 +27,10 @@ const buildDomErrorClass = (name) => class extends DOMException {
 const InvalidWorkerResponse = buildErrorClass("InvalidWorkerResponse");
 const InvalidWorkerCreation = buildErrorClass("InvalidWorkerCreation");
 const WorkerRequestCancelled = buildErrorClass("WorkerRequestCancelled");
+const WorkerCircuitOpen = buildErrorClass("WorkerCircuitOpen");
+const WorkerQueueFull = buildErrorClass("WorkerQueueFull");
+const WorkerConcurrencyLimit = buildErrorClass("WorkerConcurrencyLimit");
+const WorkerTenantQuotaExceeded = buildErrorClass("WorkerTenantQuotaExceeded");
 const NotFound = buildErrorClass("NotFound");
 const PermissionDenied = buildErrorClass("PermissionDenied");
 const ConnectionRefused = buildErrorClass("ConnectionRefused");
@@ -57,6 +61,13 @@ function registerErrors() {
     core.registerErrorClass("InvalidWorkerResponse", InvalidWorkerResponse);
     core.registerErrorClass("InvalidWorkerCreation", InvalidWorkerCreation);
     core.registerErrorClass("WorkerRequestCancelled", WorkerRequestCancelled);
+    core.registerErrorClass("WorkerCircuitOpen", WorkerCircuitOpen);
+    core.registerErrorClass("WorkerQueueFull", WorkerQueueFull);
+    core.registerErrorClass("WorkerConcurrencyLimit", WorkerConcurrencyLimit);
+    core.registerErrorClass(
+      "WorkerTenantQuotaExceeded",
+      WorkerTenantQuotaExceeded,
+    );
     core.registerErrorClass("NotFound", NotFound);
     core.registerErrorClass("PermissionDenied", PermissionDenied);
     core.registerErrorClass("ConnectionRefused", ConnectionRefused);
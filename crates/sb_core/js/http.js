@@ -2,14 +2,25 @@ import "ext:deno_http/01_http.js";
 
 import { core, internals, primordials } from "ext:core/mod.js";
 import { fromInnerResponse, newInnerResponse } from "ext:deno_fetch/23_response.js";
-import { RequestPrototype } from "ext:deno_fetch/23_request.js";
+import { abortRequest, RequestPrototype } from "ext:deno_fetch/23_request.js";
+import { Headers } from "ext:deno_fetch/20_headers.js";
 import { HttpConn } from "ext:sb_core_main_js/js/01_http.js";
 import { upgradeWebSocket } from "ext:deno_http/02_websocket.ts";
+import { registerCronJob, respondToCronRequest } from "ext:sb_core_main_js/js/cron.js";
+import { EdgeRuntimeContext, runWithRequestContext } from "ext:sb_core_main_js/js/context.js";
+import { clearTimeout, setTimeout, unrefTimer } from "ext:deno_web/02_timers.js";
 
 const ops = core.ops;
 
 const { internalRidSymbol } = core;
-const { ObjectPrototypeIsPrototypeOf } = primordials;
+const {
+	ArrayFrom,
+	DateNow,
+	MathMax,
+	ObjectAssign,
+	ObjectPrototypeIsPrototypeOf,
+	PromisePrototypeThen,
+} = primordials;
 
 const HttpConnPrototypeNextRequest = HttpConn.prototype.nextRequest;
 const HttpConnPrototypeClose = HttpConn.prototype.close;
@@ -63,9 +74,17 @@ function serveHttp(conn) {
 			return null;
 		}
 
-		nextRequest.request[kSupabaseTag] = {
+		// NOTE: A request the runtime multiplexed over the connection shares it
+		// with others, so it's watched on its own.
+		const requestWatcherRid = await ops.op_http_request_watcher(
 			watcherRid,
-			streamRid: nextRequest.streamRid
+			nextRequest.streamRid,
+		);
+
+		nextRequest.request[kSupabaseTag] = {
+			watcherRid: requestWatcherRid ?? watcherRid,
+			streamRid: nextRequest.streamRid,
+			multiplexed: requestWatcherRid !== null,
 		};
 
 		return nextRequest;
@@ -82,6 +101,11 @@ function serveHttp(conn) {
 	return httpConn;
 }
 
+// Whether the worker is being served, and the server started for its cron jobs
+// until it calls `Deno.serve()` itself.
+let isServing = false;
+let cronServer = null;
+
 function serve(args1, args2) {
 	const options = {
 		port: 9999,
@@ -89,8 +113,6 @@ function serve(args1, args2) {
 		transport: "tcp",
 	};
 
-	const listener = Deno.listen(options);
-
 	if (typeof args1 === "function") {
 		options["handler"] = args1;
 	} else if (typeof args2 === "function") {
@@ -110,6 +132,30 @@ function serve(args1, args2) {
 		}
 	}
 
+	if (cronServer !== null) {
+		const { options: cronOptions, server } = cronServer;
+
+		cronServer = null;
+
+		// NOTE: The handler takes over the server, whose listener is already
+		// accepting connections.
+		ObjectAssign(cronOptions, options);
+		cronOptions["onListen"]?.({
+			hostname: cronOptions.hostname,
+			port: cronOptions.port
+		});
+
+		return server;
+	}
+
+	return listenAndServe(options);
+}
+
+function listenAndServe(options) {
+	const listener = Deno.listen(options);
+
+	isServing = true;
+
 	const handleHttp = async (conn) => {
 		const currentHttpConn = serveHttp(conn);
 
@@ -156,17 +202,126 @@ function serve(args1, args2) {
 	};
 }
 
+// Workers declaring cron jobs are served even if they never call
+// `Deno.serve()`, as the jobs are run through requests.
+function cron(name, schedule, handlerOrOptions, maybeHandler) {
+	const stopped = registerCronJob(name, schedule, handlerOrOptions, maybeHandler);
+
+	if (!isServing) {
+		const options = {
+			port: 9999,
+			hostname: "0.0.0.0",
+			transport: "tcp",
+			handler: () => new Response(null, { status: 404 }),
+		};
+
+		cronServer = { options, server: listenAndServe(options) };
+	}
+
+	return stopped;
+}
+
+// The watcher of a multiplexed request is its own, so it goes with the request.
 async function respond(requestEvent, httpConn, options) {
+	const tag = getSupabaseTag(requestEvent.request);
+
+	try {
+		return await respondProfiled(requestEvent, httpConn, options);
+	} finally {
+		if (tag?.multiplexed) {
+			core.tryClose(tag.watcherRid);
+		}
+	}
+}
+
+// Requests the runtime found the token of `--cpu-profile-token` on are run
+// under the V8 CPU profiler. The token itself never reaches the worker.
+async function respondProfiled(requestEvent, httpConn, options) {
+	const watcherRid = getSupabaseTag(requestEvent.request)?.watcherRid;
+
+	if (watcherRid === void 0 || !(await ops.op_cpu_profile_start(watcherRid))) {
+		return respondInner(requestEvent, httpConn, options);
+	}
+
+	try {
+		return await respondInner(requestEvent, httpConn, options);
+	} finally {
+		try {
+			await ops.op_cpu_profile_stop();
+		} catch (error) {
+			console.error("failed to write the cpu profile:", error);
+		}
+	}
+}
+
+// Aborts `request.signal` when the client goes away or the worker reaches its
+// deadline before the handler is done, so that the fetches and timers it is
+// tied to are cancelled. Returns a function which stops watching.
+function abortRequestOnClose(request) {
+	let done = false;
+	let timer = null;
+
+	const abort = () => {
+		if (!done) {
+			done = true;
+			abortRequest(request);
+		}
+	};
+
+	const watcherRid = getSupabaseTag(request)?.watcherRid;
+	let cancelRid = null;
+
+	if (watcherRid !== void 0) {
+		// NOTE: Closing the cancel handle settles the op once the request is
+		// done, as the connection may be kept alive for many more requests.
+		cancelRid = core.createCancelHandle();
+
+		const promise = ops.op_http_conn_closed(watcherRid, cancelRid);
+
+		// NOTE: A connection that is kept open must not keep the worker alive.
+		core.unrefOpPromise(promise);
+		PromisePrototypeThen(promise, closed => closed && abort(), () => {});
+	}
+
+	const deadline = EdgeRuntimeContext.deadline;
+
+	if (deadline !== null) {
+		timer = setTimeout(abort, MathMax(deadline - DateNow(), 0));
+		unrefTimer(timer);
+	}
+
+	return () => {
+		done = true;
+
+		if (cancelRid !== null) {
+			core.tryClose(cancelRid);
+		}
+
+		if (timer !== null) {
+			clearTimeout(timer);
+		}
+	};
+}
+
+async function respondInner(requestEvent, httpConn, options) {
+	const stopWatching = abortRequestOnClose(requestEvent.request);
+
 	/** @type {Response} */
 	let response;
 	try {
-		response = await options["handler"](requestEvent.request, {
-			remoteAddr: {
-				port: options.port,
-				hostname: options.hostname,
-				transport: options.transport
-			}
-		});
+		response = await respondToCronRequest(
+			getCronRequest(requestEvent.request),
+			requestEvent.request,
+		);
+		response ??= await runWithRequestContext(requestEvent.request, () =>
+			options["handler"](requestEvent.request, {
+				remoteAddr: {
+					port: options.port,
+					hostname: options.hostname,
+					transport: options.transport
+				}
+			})
+		);
 
 	} catch (error) {
 		if (options["onError"] !== void 0) {
@@ -176,6 +331,8 @@ async function respond(requestEvent, httpConn, options) {
 			console.error(error);
 			response = internalServerError();
 		}
+	} finally {
+		stopWatching();
 	}
 
 	if (response === internals.RAW_UPGRADE_RESPONSE_SENTINEL) {
@@ -209,7 +366,12 @@ async function respond(requestEvent, httpConn, options) {
 			// respondWith() fails when the connection has already been closed,
 			// or there is some other error with responding on this connection
 			// that prompts us to close it and open a new connection.
-			return closeHttpConn(httpConn);
+			//
+			// NOTE: A multiplexed request only fails its own stream, while the
+			// connection still carries the other requests.
+			if (!getSupabaseTag(requestEvent.request)?.multiplexed) {
+				return closeHttpConn(httpConn);
+			}
 		}
 	}
 }
@@ -226,6 +388,64 @@ function getSupabaseTag(request) {
 	return request[kSupabaseTag];
 }
 
+// Returns the `Deno.cron()` request the main worker sent, see `cron.js`.
+function getCronRequest(request) {
+	const watcherRid = getSupabaseTag(request)?.watcherRid;
+
+	return watcherRid === void 0 ? null : ops.op_http_cron_request(watcherRid);
+}
+
+// Sets the trailers sent after the body of the response to `request`, e.g. the
+// `grpc-status` of a gRPC call. They must be set before the body is closed.
+function setResponseTrailers(request, trailers) {
+	const watcherRid = getSupabaseTag(request)?.watcherRid;
+
+	if (watcherRid === void 0) {
+		throw new TypeError("Unable to find the request the trailers are for");
+	}
+
+	ops.op_http_set_response_trailers(
+		watcherRid,
+		ArrayFrom(new Headers(trailers).entries()),
+	);
+}
+
+// Sends `links` (values of `Link` headers, e.g. preload links) to the client of
+// `request` in a `103 Early Hints`, ahead of the response. They are dropped if
+// the client can't take one, or once the response has been returned.
+function sendEarlyHints(request, links) {
+	const watcherRid = getSupabaseTag(request)?.watcherRid;
+
+	if (watcherRid === void 0) {
+		throw new TypeError("Unable to find the request the hints are for");
+	}
+
+	ops.op_http_send_early_hints(watcherRid, ArrayFrom(links, String));
+}
+
+// Sets the state kept for the WebSocket accepted for `request` while the worker
+// hibernates. The worker that picks the connection back up gets it from
+// `getHibernationState()`. It must be set while responding to the upgrade.
+function setHibernationState(request, state) {
+	const watcherRid = getSupabaseTag(request)?.watcherRid;
+
+	if (watcherRid === void 0) {
+		throw new TypeError("Unable to find the request the state is for");
+	}
+
+	ops.op_http_set_hibernation_state(watcherRid, String(state));
+}
+
+// Returns the state a hibernated worker left for the connection `request` picks
+// back up, an empty string if it left none, or null if `request` is a new one.
+function getHibernationState(request) {
+	const watcherRid = getSupabaseTag(request)?.watcherRid;
+
+	return watcherRid === void 0
+		? null
+		: ops.op_http_hibernation_state(watcherRid);
+}
+
 function applySupabaseTag(src, dest) {
 	if (
 		!ObjectPrototypeIsPrototypeOf(RequestPrototype, src)
@@ -241,9 +461,14 @@ internals.getSupabaseTag = getSupabaseTag;
 internals.RAW_UPGRADE_RESPONSE_SENTINEL = RAW_UPGRADE_RESPONSE_SENTINEL;
 
 export {
+	cron,
 	serve,
 	serveHttp,
 	getSupabaseTag,
 	applySupabaseTag,
+	setResponseTrailers,
+	sendEarlyHints,
+	setHibernationState,
+	getHibernationState,
 	upgradeWebSocket
 };
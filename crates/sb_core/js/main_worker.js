@@ -9,8 +9,27 @@ Object.defineProperty(globalThis, 'EdgeRuntime', {
 		return {
 			userWorkers: SUPABASE_USER_WORKERS,
 			getRuntimeMetrics: () => /* async */ ops.op_runtime_metrics(),
+			getAutoscaleSignals: () => ops.op_autoscale_signals(),
+			getEventSchema: () => ops.op_event_schema(),
 			applySupabaseTag: (src, dest) => applySupabaseTag(src, dest),
 			systemMemoryInfo: () => ops.op_system_memory_info(),
+			introspection: {
+				listServices: (root) => ops.op_user_worker_list_services(root),
+				readServiceConfig: (servicePath) => ops.op_user_worker_service_config(servicePath),
+				getModuleCacheStatus: (specifiers) => ops.op_user_worker_module_cache_status(specifiers),
+				listWorkers: () => /* async */ ops.op_user_worker_list_workers(),
+				// Resolves with the path of the next service whose
+				// `function.toml` may have changed, or `null` if services
+				// aren't watched (see `--watch`).
+				nextConfigChange: () => /* async */ ops.op_user_worker_next_config_change(),
+			},
+			deploy: {
+				swapService: (servicePath, releasePath) =>
+					/* async */ ops.op_user_worker_swap_service(servicePath, releasePath),
+				terminateWorker: (key) => /* async */ ops.op_user_worker_terminate_worker(key),
+				terminateService: (servicePath) =>
+					/* async */ ops.op_user_worker_terminate_service(servicePath),
+			},
 		};
 	},
 	configurable: true,
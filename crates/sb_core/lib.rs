@@ -1,7 +1,8 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use base_mem_check::WorkerHeapStatistics;
 use deno_core::error::AnyError;
@@ -12,17 +13,24 @@ use enum_as_inner::EnumAsInner;
 use futures::task::AtomicWaker;
 use futures::FutureExt;
 use log::error;
+use schemars::JsonSchema;
 use serde::Serialize;
 use tokio::sync::oneshot;
 
 mod upgrade;
 
 pub mod auth_tokens;
+pub mod background_tasks;
+pub mod broadcast_channel;
 pub mod cache;
 pub mod cert;
 pub mod conn_sync;
+pub mod cpu_profile;
+pub mod dns;
+pub mod egress;
 pub mod emit;
 pub mod errors_rt;
+pub mod execution_context;
 pub mod external_memory;
 pub mod http;
 pub mod http_start;
@@ -30,6 +38,7 @@ pub mod net;
 pub mod node;
 pub mod npm;
 pub mod permissions;
+pub mod proxy;
 pub mod runtime;
 pub mod transpiler;
 pub mod util;
@@ -48,6 +57,9 @@ pub struct SharedMetricSource {
     retired_user_workers: Arc<AtomicUsize>,
     received_requests: Arc<AtomicUsize>,
     handled_requests: Arc<AtomicUsize>,
+    pending_requests: Arc<AtomicUsize>,
+    shed_requests: Arc<AtomicUsize>,
+    queued_requests: Arc<Mutex<HashMap<String, usize>>>,
     active_io: Arc<AtomicUsize>,
 }
 
@@ -84,6 +96,39 @@ impl SharedMetricSource {
         self.handled_requests.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn incl_pending_requests(&self) {
+        self.pending_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn decl_pending_requests(&self) {
+        self.pending_requests.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn incl_shed_requests(&self) {
+        self.shed_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn incl_queued_requests(&self, service_path: &str) {
+        *self
+            .queued_requests
+            .lock()
+            .unwrap()
+            .entry(service_path.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn decl_queued_requests(&self, service_path: &str) {
+        let mut queued_requests = self.queued_requests.lock().unwrap();
+
+        if let Some(count) = queued_requests.get_mut(service_path) {
+            *count = count.saturating_sub(1);
+
+            if *count == 0 {
+                queued_requests.remove(service_path);
+            }
+        }
+    }
+
     pub fn incl_active_io(&self) {
         self.active_io.fetch_add(1, Ordering::Relaxed);
     }
@@ -97,6 +142,9 @@ impl SharedMetricSource {
         self.retired_user_workers.store(0, Ordering::Relaxed);
         self.received_requests.store(0, Ordering::Relaxed);
         self.handled_requests.store(0, Ordering::Relaxed);
+        self.pending_requests.store(0, Ordering::Relaxed);
+        self.shed_requests.store(0, Ordering::Relaxed);
+        self.queued_requests.lock().unwrap().clear();
         self.active_io.store(0, Ordering::Relaxed);
     }
 }
@@ -217,20 +265,24 @@ impl RuntimeMetricSource {
     }
 }
 
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Serialize, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 struct RuntimeHeapStatistics {
     main_worker_heap_stats: WorkerHeapStatistics,
     event_worker_heap_stats: Option<WorkerHeapStatistics>,
 }
 
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Serialize, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 struct RuntimeSharedStatistics {
     active_user_workers_count: usize,
     retired_user_workers_count: usize,
     received_requests_count: usize,
     handled_requests_count: usize,
+    pending_requests_count: usize,
+    shed_requests_count: usize,
+    /// Requests waiting for a user worker, keyed by service path.
+    queued_requests_count_by_service: HashMap<String, usize>,
 }
 
 impl RuntimeSharedStatistics {
@@ -240,11 +292,57 @@ impl RuntimeSharedStatistics {
             retired_user_workers_count: src.retired_user_workers.load(Ordering::Relaxed),
             received_requests_count: src.received_requests.load(Ordering::Relaxed),
             handled_requests_count: src.handled_requests.load(Ordering::Relaxed),
+            pending_requests_count: src.pending_requests.load(Ordering::Relaxed),
+            shed_requests_count: src.shed_requests.load(Ordering::Relaxed),
+            queued_requests_count_by_service: src.queued_requests.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Load signals intended for external autoscalers (e.g. KEDA or HPA adapters).
+#[derive(Debug, Serialize, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+struct AutoscaleSignals {
+    /// Requests waiting for a user worker to become available.
+    queue_depth: usize,
+    /// Requests accepted by the server that have not been handled yet.
+    in_flight_requests: usize,
+    active_user_workers: usize,
+    /// Ratio of in-flight requests to active user workers, clamped to `1.0`.
+    #[schemars(range(min = 0, max = 1))]
+    busy_worker_ratio: f64,
+    /// Requests rejected because no user worker became available in time.
+    shed_requests: usize,
+}
+
+impl AutoscaleSignals {
+    fn from_shared_metric_src(src: &SharedMetricSource) -> Self {
+        let active_user_workers = src.active_user_workers.load(Ordering::Relaxed);
+        let in_flight_requests = src
+            .received_requests()
+            .saturating_sub(src.handled_requests());
+
+        let busy_worker_ratio = if active_user_workers == 0 {
+            if in_flight_requests > 0 {
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            (in_flight_requests as f64 / active_user_workers as f64).min(1.0)
+        };
+
+        Self {
+            queue_depth: src.pending_requests.load(Ordering::Relaxed),
+            in_flight_requests,
+            active_user_workers,
+            busy_worker_ratio,
+            shed_requests: src.shed_requests.load(Ordering::Relaxed),
         }
     }
 }
 
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Serialize, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 struct RuntimeMetrics {
     #[serde(flatten)]
@@ -285,6 +383,12 @@ async fn op_runtime_metrics(state: Rc<RefCell<OpState>>) -> Result<RuntimeMetric
     Ok(runtime_metrics)
 }
 
+#[op2]
+#[serde]
+fn op_autoscale_signals(state: &mut OpState) -> AutoscaleSignals {
+    AutoscaleSignals::from_shared_metric_src(&state.borrow::<RuntimeMetricSource>().shared)
+}
+
 #[op2(fast)]
 fn op_schedule_mem_check(state: &mut OpState) -> Result<(), AnyError> {
     if let Some(waker) = state.try_borrow::<MemCheckWaker>() {
@@ -358,10 +462,18 @@ deno_core::extension!(
         op_read_line_prompt,
         op_set_exit_code,
         op_runtime_metrics,
+        op_autoscale_signals,
         op_schedule_mem_check,
         op_runtime_memory_usage,
         op_set_raw,
-        op_bootstrap_unstable_args
+        op_bootstrap_unstable_args,
+        background_tasks::op_background_task_begin,
+        background_tasks::op_background_task_end,
+        cpu_profile::op_cpu_profile_start,
+        cpu_profile::op_cpu_profile_stop,
+        execution_context::op_execution_context,
+        execution_context::op_execution_budget,
+        execution_context::op_execution_deadline
     ],
     esm_entry_point = "ext:sb_core_main_js/js/bootstrap.js",
     esm = [
@@ -369,6 +481,8 @@ deno_core::extension!(
         "js/errors.js",
         "js/fieldUtils.js",
         "js/promises.js",
+        "js/context.js",
+        "js/cron.js",
         "js/http.js",
         "js/denoOverrides.js",
         "js/navigator.js",
@@ -378,3 +492,65 @@ deno_core::extension!(
         "js/01_http.js"
     ]
 );
+
+#[cfg(test)]
+mod test {
+    use deno_core::serde_json::{self, Value};
+    use schemars::gen::{SchemaGenerator, SchemaSettings};
+
+    use super::*;
+
+    static OPENAPI_SPEC_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../examples/main/openapi.json"
+    );
+
+    /// Compares the schemas generated from the types with the components of the
+    /// OpenAPI spec of the main worker, or rewrites them with
+    /// `UPDATE_SCHEMAS=1`.
+    fn check_openapi_schemas(add: impl FnOnce(&mut SchemaGenerator)) {
+        let mut gen = SchemaSettings::draft2019_09()
+            .with(|it| it.definitions_path = "#/components/schemas/".to_string())
+            .into_generator();
+
+        add(&mut gen);
+
+        let mut spec: Value =
+            serde_json::from_str(&std::fs::read_to_string(OPENAPI_SPEC_PATH).unwrap()).unwrap();
+        let components = spec["components"]["schemas"].as_object_mut().unwrap();
+        let mut outdated = vec![];
+
+        for (name, schema) in gen.take_definitions() {
+            let schema = serde_json::to_value(schema).unwrap();
+
+            if components.get(&name) != Some(&schema) {
+                outdated.push(name.clone());
+                components.insert(name, schema);
+            }
+        }
+
+        if std::env::var_os("UPDATE_SCHEMAS").is_some() {
+            std::fs::write(
+                OPENAPI_SPEC_PATH,
+                serde_json::to_string_pretty(&spec).unwrap() + "\n",
+            )
+            .unwrap();
+
+            return;
+        }
+
+        assert!(
+            outdated.is_empty(),
+            "{:?} in openapi.json are out of date, run the tests with `UPDATE_SCHEMAS=1`",
+            outdated
+        );
+    }
+
+    #[test]
+    fn test_openapi_spec_matches_the_runtime_metrics() {
+        check_openapi_schemas(|gen| {
+            gen.subschema_for::<RuntimeMetrics>();
+            gen.subschema_for::<AutoscaleSignals>();
+        });
+    }
+}
@@ -1,12 +1,15 @@
 use deno_core::error::bad_resource;
 use deno_core::error::AnyError;
 use deno_core::op2;
+use deno_core::url::Url;
 use deno_core::AsyncRefCell;
 use deno_core::AsyncResult;
+use deno_core::ByteString;
 use deno_core::CancelHandle;
 use deno_core::OpState;
 use deno_core::Resource;
 use deno_core::ResourceId;
+use deno_net::io::TcpStreamResource;
 use deno_net::ops::IpAddr;
 use std::borrow::Cow;
 use std::cell::RefCell;
@@ -15,12 +18,15 @@ use std::rc::Rc;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use tokio::io;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use tracing::span;
 use tracing::Level;
 
-use crate::conn_sync::DenoRuntimeDropToken;
+use crate::conn_sync::{DenoRuntimeDropToken, RequestMarks};
+use crate::dns::resolve_addr;
+use crate::permissions::Permissions;
 
 pub struct TokioDuplexResource {
     id: usize,
@@ -103,9 +109,11 @@ pub async fn op_net_accept(
         let mut op_state = state.borrow_mut();
 
         (
-            op_state
-                .try_take::<mpsc::UnboundedReceiver<(io::DuplexStream, Option<CancellationToken>)>>(
-                ),
+            op_state.try_take::<mpsc::UnboundedReceiver<(
+                io::DuplexStream,
+                Option<CancellationToken>,
+                RequestMarks,
+            )>>(),
             op_state
                 .try_borrow::<DenoRuntimeDropToken>()
                 .cloned()
@@ -122,13 +130,15 @@ pub async fn op_net_accept(
         let state = state.clone();
         move |value| {
             let mut op_state = state.borrow_mut();
-            op_state.put::<mpsc::UnboundedReceiver<(io::DuplexStream, Option<CancellationToken>)>>(
-                value,
-            );
+            op_state.put::<mpsc::UnboundedReceiver<(
+                io::DuplexStream,
+                Option<CancellationToken>,
+                RequestMarks,
+            )>>(value);
         }
     });
 
-    let Some((stream, conn_token)) = rx.recv().await else {
+    let Some((stream, conn_token, marks)) = rx.recv().await else {
         return Err(bad_resource("duplex stream channel is closed"));
     };
 
@@ -165,6 +175,10 @@ pub async fn op_net_accept(
             .insert(id, token);
     }
 
+    if let Some(requests) = op_state.try_borrow_mut::<HashMap<usize, RequestMarks>>() {
+        requests.insert(id, marks);
+    }
+
     Ok((
         rid,
         IpAddr {
@@ -178,6 +192,92 @@ pub async fn op_net_accept(
     ))
 }
 
+/// `Deno.connect()` over TCP, resolving the hostname through the DNS overrides
+/// of the runtime.
+#[op2(async)]
+#[serde]
+pub async fn op_net_connect_tcp(
+    state: Rc<RefCell<OpState>>,
+    #[serde] addr: IpAddr,
+) -> Result<(ResourceId, IpAddr, IpAddr), AnyError> {
+    state
+        .borrow()
+        .borrow::<Permissions>()
+        .check_net_descriptor(&addr.hostname, Some(addr.port))?;
+
+    // NOTE: The addresses are checked after resolving them, as they're the
+    // ones connected to.
+    let addrs = resolve_addr(&addr.hostname, addr.port).await?;
+
+    state
+        .borrow()
+        .borrow::<Permissions>()
+        .egress_policy()
+        .check_addrs(&addr.hostname, &addrs)?;
+
+    let stream = TcpStream::connect(addrs.as_slice()).await?;
+    let local_addr = stream.local_addr()?;
+    let remote_addr = stream.peer_addr()?;
+
+    let rid = state
+        .borrow_mut()
+        .resource_table
+        .add(TcpStreamResource::new(stream.into_split()));
+
+    Ok((rid, IpAddr::from(local_addr), IpAddr::from(remote_addr)))
+}
+
+/// Checks the addresses `hostname` resolves to against the egress policy, for
+/// the connections deno_net resolves the hostname of on its own.
+#[op2(async)]
+pub async fn op_net_check_egress(
+    state: Rc<RefCell<OpState>>,
+    #[string] hostname: String,
+) -> Result<(), AnyError> {
+    let policy = state
+        .borrow()
+        .borrow::<Permissions>()
+        .egress_policy()
+        .clone();
+
+    policy.check_lookup(&hostname).await
+}
+
+/// Stands in for `op_ws_create` of deno_websocket, which resolves the host of
+/// `new WebSocket()` on its own. The addresses of the host are checked against
+/// the egress policy first, since the permission check of the constructor is
+/// synchronous and can't resolve it without blocking the event loop.
+#[op2(async)]
+#[serde]
+pub async fn op_ws_create(
+    state: Rc<RefCell<OpState>>,
+    #[string] api_name: String,
+    #[string] url: String,
+    #[string] protocols: String,
+    #[smi] cancel_handle: Option<ResourceId>,
+    #[serde] headers: Option<Vec<(ByteString, ByteString)>>,
+) -> Result<deno_websocket::CreateResponse, AnyError> {
+    let policy = state
+        .borrow()
+        .borrow::<Permissions>()
+        .egress_policy()
+        .clone();
+
+    policy
+        .check_lookup(Url::parse(&url)?.host_str().unwrap_or_default())
+        .await?;
+
+    deno_websocket::op_ws_create::<Permissions>::call(
+        state,
+        api_name,
+        url,
+        protocols,
+        cancel_handle,
+        headers,
+    )
+    .await
+}
+
 // TODO: This should be a global ext
 #[op2(fast)]
 pub fn op_net_unsupported(_state: &mut OpState) -> Result<(), AnyError> {
@@ -186,9 +286,12 @@ pub fn op_net_unsupported(_state: &mut OpState) -> Result<(), AnyError> {
 
 deno_core::extension!(
     sb_core_net,
+    ops = [op_net_check_egress],
     middleware = |op| match op.name {
         "op_net_listen_tcp" => op.with_implementation_from(&op_net_listen()),
         "op_net_accept_tcp" => op.with_implementation_from(&op_net_accept()),
+        "op_net_connect_tcp" => op.with_implementation_from(&op_net_connect_tcp()),
+        "op_ws_create" => op.with_implementation_from(&op_ws_create()),
 
         // disable listening on TLS, UDP and Unix sockets
         "op_net_listen_tls" => op.with_implementation_from(&op_net_unsupported()),
@@ -2,85 +2,282 @@ use deno_core::error::{custom_error, generic_error, AnyError};
 use deno_core::url::Url;
 use deno_fs::OpenOptions;
 use deno_permissions::NetDescriptor;
+use futures::future::{BoxFuture, FutureExt};
 use std::borrow::Cow;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::egress::EgressPolicy;
+
+/// Whether the operator lets services load native libraries. A service can't
+/// grant `allow_ffi` to itself without it.
+pub static FFI_ALLOWED: AtomicBool = AtomicBool::new(false);
+
+/// What a worker is allowed to do. The default allows everything except high
+/// resolution timers.
+#[derive(Debug, Clone, Default)]
+pub struct PermissionsOptions {
+    pub net_access_disabled: bool,
+    /// Hosts that can be reached. `None` allows all of them.
+    pub allow_net: Option<Vec<NetDescriptor>>,
+    /// Absolute paths that can be read, along with everything below them.
+    /// `None` allows all of them.
+    pub allow_read: Option<Vec<PathBuf>>,
+    /// Absolute paths that can be written, along with everything below them.
+    /// `None` allows all of them.
+    pub allow_write: Option<Vec<PathBuf>>,
+    /// Directory relative paths are resolved against, i.e. that of the
+    /// service. `None` resolves them against the current directory.
+    pub base_dir: Option<PathBuf>,
+    /// Env vars that can be read. `None` allows all of them.
+    pub allow_env: Option<Vec<String>>,
+    pub allow_hrtime: bool,
+    /// Whether native libraries can be loaded with `Deno.dlopen()`, which
+    /// bypasses every other permission.
+    pub allow_ffi: bool,
+    pub egress_policy: EgressPolicy,
+}
 
 pub struct Permissions {
-    net_access_disabled: bool,
-    allow_net: Option<Vec<NetDescriptor>>,
+    options: PermissionsOptions,
 }
 
 impl Default for Permissions {
     fn default() -> Self {
-        Self::new(false, None)
+        Self::new(PermissionsOptions::default())
+    }
+}
+
+fn permission_denied(msg: impl Into<Cow<'static, str>>) -> AnyError {
+    custom_error("PermissionDenied", msg)
+}
+
+/// Resolves `.` and `..` without touching the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+
+            it => normalized.push(it),
+        }
+    }
+
+    normalized
+}
+
+/// Resolves `path` the way the file system will, symlinks included, so that
+/// it can't escape an allowed directory through them. The part of the path
+/// that doesn't exist yet, e.g. a file about to be created, is resolved
+/// without touching the file system. A relative path is resolved against
+/// `base_dir`, or the current directory.
+fn resolve_path(path: &Path, base_dir: Option<&Path>) -> Result<PathBuf, AnyError> {
+    let path = match base_dir {
+        _ if path.is_absolute() => Cow::Borrowed(path),
+        Some(base_dir) => Cow::Owned(base_dir.join(path)),
+        None => Cow::Owned(std::env::current_dir()?.join(path)),
+    };
+
+    let mut existing = path.as_ref();
+    let mut missing = vec![];
+
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return Ok(normalize_path(
+                &missing.iter().rev().fold(resolved, |acc, it| acc.join(it)),
+            ));
+        }
+
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(component)) => {
+                missing.push(component.as_os_str());
+                existing = parent;
+            }
+
+            _ => return Ok(normalize_path(&path)),
+        }
+    }
+}
+
+fn resolve_paths(paths: &mut Option<Vec<PathBuf>>) {
+    for path in paths.iter_mut().flatten() {
+        if let Ok(resolved) = resolve_path(path, None) {
+            *path = resolved;
+        }
     }
 }
 
 impl Permissions {
-    pub fn new(net_access_disabled: bool, allow_net: Option<Vec<NetDescriptor>>) -> Self {
-        Self {
-            net_access_disabled,
-            allow_net,
+    pub fn new(mut options: PermissionsOptions) -> Self {
+        resolve_paths(&mut options.allow_read);
+        resolve_paths(&mut options.allow_write);
+
+        if let Some(base_dir) = options.base_dir.as_mut() {
+            if let Ok(resolved) = resolve_path(base_dir, None) {
+                *base_dir = resolved;
+            }
         }
+
+        Self { options }
     }
 
-    pub fn check_env(&mut self, _var: &str) -> Result<(), AnyError> {
-        Ok(())
+    pub fn check_env(&mut self, var: &str) -> Result<(), AnyError> {
+        match &self.options.allow_env {
+            Some(allow_env) if !allow_env.iter().any(|it| it == var) => Err(permission_denied(
+                format!("Access to env var \"{var}\" is not allowed for user worker"),
+            )),
+
+            _ => Ok(()),
+        }
     }
 
     pub fn check_env_all(&mut self) -> Result<(), AnyError> {
+        if self.options.allow_env.is_some() {
+            return Err(permission_denied(
+                "Access to all env vars is not allowed for user worker",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks a connection to `hostname`, whose addresses are left for the
+    /// caller to check against the egress policy once resolved.
+    pub(crate) fn check_net_descriptor(
+        &self,
+        hostname: &str,
+        port: Option<u16>,
+    ) -> Result<(), AnyError> {
+        if self.options.net_access_disabled {
+            return Err(permission_denied("net access disabled for the user worker"));
+        }
+
+        if let Some(allow_net) = &self.options.allow_net {
+            let descriptor = NetDescriptor(hostname.parse()?, port);
+            if !allow_net.contains(&descriptor) {
+                return Err(permission_denied(format!(
+                    "Access to {descriptor} is not allowed for user worker"
+                )));
+            }
+        }
+
+        self.options.egress_policy.check(hostname)
+    }
+
+    fn check_url(&self, url: &Url) -> Result<(), AnyError> {
+        self.check_net_descriptor(
+            url.host_str().ok_or(generic_error("empty host"))?,
+            url.port(),
+        )
+    }
+
+    pub(crate) fn egress_policy(&self) -> &EgressPolicy {
+        &self.options.egress_policy
+    }
+
+    /// Returns the path `path` is to be accessed at, once it has been checked
+    /// against `allowed`. A restricted path is resolved through symlinks, so
+    /// that what is accessed is what was checked.
+    fn check_path(
+        &self,
+        path: &Path,
+        allowed: Option<&[PathBuf]>,
+        access: &str,
+    ) -> Result<PathBuf, AnyError> {
+        let base_dir = self.options.base_dir.as_deref();
+        let Some(allowed) = allowed else {
+            return Ok(match base_dir {
+                Some(base_dir) => base_dir.join(path),
+                None => path.to_path_buf(),
+            });
+        };
+
+        let path = resolve_path(path, base_dir)?;
+
+        if !allowed.iter().any(|it| path.starts_with(it)) {
+            return Err(permission_denied(format!(
+                "{access} access to {} is not allowed for user worker",
+                path.display()
+            )));
+        }
+
+        Ok(path)
+    }
+
+    fn check_read_path(&self, path: &Path) -> Result<(), AnyError> {
+        self.check_path(path, self.options.allow_read.as_deref(), "Read")
+            .map(drop)
+    }
+
+    fn check_write_path(&self, path: &Path) -> Result<(), AnyError> {
+        self.check_path(path, self.options.allow_write.as_deref(), "Write")
+            .map(drop)
+    }
+
+    fn check_write_all(&self) -> Result<(), AnyError> {
+        if self.options.allow_write.is_some() {
+            return Err(permission_denied(
+                "Write access to the file system is not allowed for user worker",
+            ));
+        }
+
         Ok(())
     }
 
     pub fn check_read_blind(
         &mut self,
-        _path: &Path,
-        _display: &str,
+        path: &Path,
+        display: &str,
         _api_name: &str,
     ) -> Result<(), AnyError> {
-        Ok(())
+        self.check_read_path(path).map_err(|_| {
+            permission_denied(format!(
+                "Read access to {display} is not allowed for user worker"
+            ))
+        })
     }
 }
 
 deno_core::extension!(
     sb_core_permissions,
-    options = { net_access_disabled: bool, allow_net: Option<Vec<NetDescriptor>> },
+    options = { permissions: PermissionsOptions },
     state = |state, options| {
-        state.put::<Permissions>(Permissions::new(options.net_access_disabled, options.allow_net));
+        state.put::<Permissions>(Permissions::new(options.permissions));
     }
 );
 
+impl deno_ffi::FfiPermissions for Permissions {
+    fn check_partial(&mut self, path: Option<&Path>) -> Result<(), AnyError> {
+        if !self.options.allow_ffi {
+            return Err(permission_denied("FFI is not allowed for user worker"));
+        }
+
+        match path {
+            Some(path) => self.check_read_path(path),
+            None => Ok(()),
+        }
+    }
+}
+
 impl deno_web::TimersPermission for Permissions {
     fn allow_hrtime(&mut self) -> bool {
-        false
+        self.options.allow_hrtime
     }
 }
 
 impl deno_fetch::FetchPermissions for Permissions {
+    // NOTE: The client of `fetch()` in user workers resolves hosts through an
+    // `EgressResolver`, which checks the addresses it connects to.
     fn check_net_url(&mut self, url: &Url, _api_name: &str) -> Result<(), AnyError> {
-        if self.net_access_disabled {
-            return Err(custom_error(
-                "PermissionDenied",
-                "net access disabled for the user worker",
-            ));
-        }
-
-        if let Some(allow_net) = &self.allow_net {
-            let hostname = url.host_str().ok_or(generic_error("empty host"))?.parse()?;
-            let descriptor = NetDescriptor(hostname, url.port());
-            if !allow_net.contains(&descriptor) {
-                return Err(custom_error(
-                    "PermissionDenied",
-                    format!("Access to {descriptor} is not allowed for user worker"),
-                ));
-            }
-        }
-
-        Ok(())
+        self.check_url(url)
     }
 
-    fn check_read(&mut self, _p: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_read(&mut self, p: &Path, _api_name: &str) -> Result<(), AnyError> {
+        self.check_read_path(p)
     }
 }
 
@@ -90,54 +287,25 @@ impl deno_net::NetPermissions for Permissions {
         host: &(T, Option<u16>),
         _api_name: &str,
     ) -> Result<(), AnyError> {
-        if self.net_access_disabled {
-            return Err(custom_error(
-                "PermissionDenied",
-                "net access disabled for the user worker",
-            ));
-        }
-
-        if let Some(allow_net) = &self.allow_net {
-            let hostname = host.0.as_ref().parse()?;
-            let descriptor = NetDescriptor(hostname, host.1);
-            if !allow_net.contains(&descriptor) {
-                return Err(custom_error(
-                    "PermissionDenied",
-                    format!("Access to {descriptor} is not allowed for user worker"),
-                ));
-            }
-        }
-        Ok(())
+        // NOTE: The only connection left to deno_net resolves its host after
+        // `op_net_check_egress` has checked its addresses.
+        self.check_net_descriptor(host.0.as_ref(), host.1)
     }
 
-    fn check_read(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_read(&mut self, path: &Path, _api_name: &str) -> Result<(), AnyError> {
+        self.check_read_path(path)
     }
 
-    fn check_write(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_write(&mut self, path: &Path, _api_name: &str) -> Result<(), AnyError> {
+        self.check_write_path(path)
     }
 }
 
 impl deno_websocket::WebSocketPermissions for Permissions {
     fn check_net_url(&mut self, url: &Url, _api_name: &str) -> Result<(), AnyError> {
-        if self.net_access_disabled {
-            return Err(custom_error(
-                "PermissionDenied",
-                "net access disabled for the user worker",
-            ));
-        }
-        if let Some(allow_net) = &self.allow_net {
-            let hostname = url.host_str().ok_or(generic_error("empty host"))?.parse()?;
-            let descriptor = NetDescriptor(hostname, url.port());
-            if !allow_net.contains(&descriptor) {
-                return Err(custom_error(
-                    "PermissionDenied",
-                    format!("Access to {descriptor} is not allowed for user worker"),
-                ));
-            }
-        }
-        Ok(())
+        // NOTE: The addresses of the host are checked by `op_ws_create`, see
+        // `net.rs`.
+        self.check_url(url)
     }
 }
 
@@ -148,50 +316,78 @@ impl deno_fs::FsPermissions for Permissions {
     fn check_open<'a>(
         &mut self,
         _resolved: bool,
-        _read: bool,
-        _write: bool,
+        read: bool,
+        write: bool,
         path: &'a Path,
         _api_name: &str,
     ) -> Result<Cow<'a, Path>, deno_io::fs::FsError> {
-        Ok(Cow::Borrowed(path))
+        let mut checked = Cow::Borrowed(path);
+
+        // NOTE: The file is opened at the path that was checked, rather than
+        // resolved again by the file system.
+        if read {
+            checked = self
+                .check_path(path, self.options.allow_read.as_deref(), "Read")
+                .map(Cow::Owned)
+                .map_err(|_| deno_io::fs::FsError::PermissionDenied("read"))?;
+        }
+
+        if write {
+            checked = self
+                .check_path(path, self.options.allow_write.as_deref(), "Write")
+                .map(Cow::Owned)
+                .map_err(|_| deno_io::fs::FsError::PermissionDenied("write"))?;
+        }
+
+        Ok(checked)
     }
 
-    fn check_read(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_read(&mut self, path: &Path, _api_name: &str) -> Result<(), AnyError> {
+        self.check_read_path(path)
     }
 
     fn check_read_all(&mut self, _api_name: &str) -> Result<(), AnyError> {
+        if self.options.allow_read.is_some() {
+            return Err(permission_denied(
+                "Read access to the file system is not allowed for user worker",
+            ));
+        }
+
         Ok(())
     }
 
     fn check_read_blind(
         &mut self,
-        _path: &Path,
-        _display: &str,
-        _api_name: &str,
+        path: &Path,
+        display: &str,
+        api_name: &str,
     ) -> Result<(), AnyError> {
-        Ok(())
+        Permissions::check_read_blind(self, path, display, api_name)
     }
 
-    fn check_write(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_write(&mut self, path: &Path, _api_name: &str) -> Result<(), AnyError> {
+        self.check_write_path(path)
     }
 
-    fn check_write_partial(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_write_partial(&mut self, path: &Path, _api_name: &str) -> Result<(), AnyError> {
+        self.check_write_path(path)
     }
 
     fn check_write_all(&mut self, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+        Permissions::check_write_all(self)
     }
 
     fn check_write_blind(
         &mut self,
-        _p: &Path,
-        _display: &str,
+        path: &Path,
+        display: &str,
         _api_name: &str,
     ) -> Result<(), AnyError> {
-        Ok(())
+        self.check_write_path(path).map_err(|_| {
+            permission_denied(format!(
+                "Write access to {display} is not allowed for user worker"
+            ))
+        })
     }
 
     fn check<'a>(
@@ -212,20 +408,29 @@ impl deno_fs::FsPermissions for Permissions {
 }
 
 impl sb_node::NodePermissions for Permissions {
-    fn check_net_url(&mut self, _url: &Url, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_net_url(&mut self, url: &Url, _api_name: &str) -> Result<(), AnyError> {
+        self.check_url(url)
     }
 
-    fn check_read(&mut self, _path: &Path) -> Result<(), AnyError> {
-        Ok(())
+    // NOTE: The http module of node can send requests through clients of its
+    // own, which don't resolve hosts through an `EgressResolver`.
+    fn check_net_url_lookup(&self, url: &Url) -> BoxFuture<'static, Result<(), AnyError>> {
+        let policy = self.options.egress_policy.clone();
+        let host = url.host_str().unwrap_or_default().to_string();
+
+        async move { policy.check_lookup(&host).await }.boxed()
+    }
+
+    fn check_read(&mut self, path: &Path) -> Result<(), AnyError> {
+        self.check_read_path(path)
     }
 
     fn check_read_with_api_name(
         &mut self,
-        _path: &Path,
+        path: &Path,
         _api_name: Option<&str>,
     ) -> Result<(), AnyError> {
-        Ok(())
+        self.check_read_path(path)
     }
 
     fn check_sys(&mut self, _kind: &str, _api_name: &str) -> Result<(), AnyError> {
@@ -234,9 +439,152 @@ impl sb_node::NodePermissions for Permissions {
 
     fn check_write_with_api_name(
         &mut self,
-        _path: &Path,
+        path: &Path,
         _api_name: Option<&str>,
     ) -> Result<(), AnyError> {
-        Ok(())
+        self.check_write_path(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_permissions() {
+        let mut permissions = Permissions::new(PermissionsOptions {
+            allow_net: Some(vec![NetDescriptor("example.com".parse().unwrap(), None)]),
+            allow_read: Some(vec![PathBuf::from("/srv/functions/hello/../static")]),
+            allow_env: Some(vec!["API_KEY".to_string()]),
+            ..Default::default()
+        });
+
+        assert!(permissions
+            .check_url(&Url::parse("https://example.com/").unwrap())
+            .is_ok());
+        assert!(permissions
+            .check_url(&Url::parse("https://example.org/").unwrap())
+            .is_err());
+
+        assert!(permissions
+            .check_read_path(Path::new("/srv/functions/static/logo.png"))
+            .is_ok());
+        assert!(permissions
+            .check_read_path(Path::new("/srv/functions/static/../secrets.json"))
+            .is_err());
+
+        assert!(permissions.check_env("API_KEY").is_ok());
+        assert!(permissions.check_env("DATABASE_URL").is_err());
+        assert!(permissions.check_env_all().is_err());
+        assert!(deno_ffi::FfiPermissions::check_partial(&mut permissions, None).is_err());
+
+        let mut permissions = Permissions::default();
+
+        assert!(permissions
+            .check_read_path(Path::new("/etc/passwd"))
+            .is_ok());
+        assert!(permissions.check_env_all().is_ok());
+        assert!(deno_ffi::FfiPermissions::check_partial(&mut permissions, None).is_err());
+
+        let mut permissions = Permissions::new(PermissionsOptions {
+            allow_read: Some(vec![PathBuf::from("/srv/functions/hello")]),
+            allow_ffi: true,
+            ..Default::default()
+        });
+
+        assert!(deno_ffi::FfiPermissions::check_partial(
+            &mut permissions,
+            Some(Path::new("/srv/functions/hello/libsum.so"))
+        )
+        .is_ok());
+        assert!(deno_ffi::FfiPermissions::check_partial(
+            &mut permissions,
+            Some(Path::new("/usr/lib/libc.so.6"))
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_follow_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = dir.path().join("hello");
+        let secrets = dir.path().join("secrets");
+
+        std::fs::create_dir_all(service.join("tmp")).unwrap();
+        std::fs::create_dir_all(&secrets).unwrap();
+        std::fs::write(secrets.join("key.pem"), "").unwrap();
+        std::os::unix::fs::symlink(&secrets, service.join("link")).unwrap();
+
+        let mut permissions = Permissions::new(PermissionsOptions {
+            allow_read: Some(vec![service.clone()]),
+            allow_write: Some(vec![service.join("tmp")]),
+            ..Default::default()
+        });
+
+        assert!(permissions
+            .check_read_path(&service.join("index.ts"))
+            .is_ok());
+        assert!(permissions
+            .check_read_path(&service.join("link/key.pem"))
+            .is_err());
+
+        assert!(permissions
+            .check_write_path(&service.join("tmp/new/out.txt"))
+            .is_ok());
+        assert!(permissions
+            .check_write_path(&service.join("index.ts"))
+            .is_err());
+        assert!(permissions
+            .check_write_path(&service.join("tmp/../link/new.pem"))
+            .is_err());
+        assert!(Permissions::check_write_all(&permissions).is_err());
+        assert!(
+            deno_fs::FsPermissions::check_write(&mut permissions, &service.join("x"), "").is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_resolve_against_the_service() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = dir.path().join("hello");
+
+        std::fs::create_dir_all(service.join("tmp")).unwrap();
+        std::os::unix::fs::symlink(service.join("tmp"), service.join("scratch")).unwrap();
+
+        let mut permissions = Permissions::new(PermissionsOptions {
+            allow_read: Some(vec![service.clone()]),
+            allow_write: Some(vec![service.join("tmp")]),
+            base_dir: Some(service.clone()),
+            ..Default::default()
+        });
+
+        assert!(permissions.check_read_path(Path::new("index.ts")).is_ok());
+        assert!(permissions
+            .check_read_path(Path::new("../other/index.ts"))
+            .is_err());
+
+        // The file is opened at the path that was checked.
+        let opened = deno_fs::FsPermissions::check_open(
+            &mut permissions,
+            false,
+            true,
+            true,
+            Path::new("scratch/out.txt"),
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(opened, service.canonicalize().unwrap().join("tmp/out.txt"));
+        assert!(deno_fs::FsPermissions::check_open(
+            &mut permissions,
+            false,
+            false,
+            true,
+            Path::new("index.ts"),
+            "",
+        )
+        .is_err());
     }
 }
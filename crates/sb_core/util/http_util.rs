@@ -22,6 +22,8 @@ use deno_fetch::reqwest::Response;
 use deno_fetch::reqwest::StatusCode;
 use deno_fetch::CreateHttpClientOptions;
 use deno_tls::RootCertStoreProvider;
+use deno_tls::SocketUse;
+use deno_tls::TlsKeys;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::thread::ThreadId;
@@ -29,6 +31,81 @@ use std::time::Duration;
 use std::time::SystemTime;
 use thiserror::Error;
 
+/// Like [`create_http_client`], but the client resolves hosts through
+/// `resolver` rather than the resolver of the host, and gives up on
+/// connections not established within `connect_timeout`.
+pub fn create_http_client_with_resolver(
+    user_agent: &str,
+    options: CreateHttpClientOptions,
+    resolver: Arc<dyn reqwest::dns::Resolve>,
+    connect_timeout: Option<Duration>,
+) -> Result<reqwest::Client, AnyError> {
+    let mut tls_config = deno_tls::create_client_config(
+        options.root_cert_store,
+        options.ca_certs,
+        options.unsafely_ignore_certificate_errors,
+        options
+            .client_cert_chain_and_key
+            .map_or(TlsKeys::Null, TlsKeys::Static),
+        SocketUse::Http,
+    )?;
+
+    let mut alpn_protocols = vec![];
+
+    if options.http2 {
+        alpn_protocols.push("h2".into());
+    }
+
+    if options.http1 {
+        alpn_protocols.push("http/1.1".into());
+    }
+
+    tls_config.alpn_protocols = alpn_protocols;
+
+    let mut headers = reqwest::header::HeaderMap::new();
+
+    headers.insert(reqwest::header::USER_AGENT, user_agent.parse()?);
+
+    let mut builder = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .default_headers(headers)
+        .use_preconfigured_tls(tls_config)
+        .dns_resolver(resolver);
+
+    if let Some(proxy) = options.proxy {
+        let mut reqwest_proxy = reqwest::Proxy::all(&proxy.url)?;
+
+        if let Some(basic_auth) = &proxy.basic_auth {
+            reqwest_proxy = reqwest_proxy.basic_auth(&basic_auth.username, &basic_auth.password);
+        }
+
+        builder = builder.proxy(reqwest_proxy);
+    }
+
+    if let Some(pool_max_idle_per_host) = options.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(pool_max_idle_per_host);
+    }
+
+    if let Some(pool_idle_timeout) = options.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(pool_idle_timeout.map(Duration::from_millis));
+    }
+
+    if let Some(connect_timeout) = connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+
+    match (options.http1, options.http2) {
+        (true, false) => builder = builder.http1_only(),
+        (false, true) => builder = builder.http2_prior_knowledge(),
+        (true, true) => {}
+        (false, false) => {
+            return Err(generic_error("Either `http1` or `http2` needs to be true"));
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
 /// Construct the next uri based on base uri and location header fragment
 /// See <https://tools.ietf.org/html/rfc3986#section-4.2>
 fn resolve_url_from_location(base_url: &Url, location: &str) -> Url {
@@ -534,3 +611,111 @@ pub async fn get_response_body_with_progress(
     let bytes = response.bytes().await?;
     Ok(bytes.into())
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    struct NoResolver;
+
+    impl reqwest::dns::Resolve for NoResolver {
+        fn resolve(&self, _: reqwest::dns::Name) -> reqwest::dns::Resolving {
+            Box::pin(async { Err("the tests only connect to addresses".into()) })
+        }
+    }
+
+    /// Serves `ok` to every request, counting the connections it accepts.
+    async fn serve_ok() -> (u16, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = Arc::new(AtomicUsize::new(0));
+
+        tokio::spawn({
+            let accepted = accepted.clone();
+            async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(async move {
+                        let mut buf = vec![0; 4096];
+                        let mut head = vec![];
+
+                        while let Ok(n) = stream.read(&mut buf).await {
+                            if n == 0 {
+                                break;
+                            }
+
+                            head.extend_from_slice(&buf[..n]);
+
+                            if head.windows(4).any(|it| it == b"\r\n\r\n") {
+                                head.clear();
+                                let _ = stream
+                                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                                    .await;
+                            }
+                        }
+                    });
+                }
+            }
+        });
+
+        (port, accepted)
+    }
+
+    async fn connections_for_two_requests(options: CreateHttpClientOptions) -> usize {
+        let (port, accepted) = serve_ok().await;
+        let client =
+            create_http_client_with_resolver("test", options, Arc::new(NoResolver), None).unwrap();
+
+        for _ in 0..2 {
+            let res = client
+                .get(format!("http://127.0.0.1:{}/", port))
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(res.text().await.unwrap(), "ok");
+        }
+
+        accepted.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn test_http_client_pooling() {
+        assert_eq!(
+            connections_for_two_requests(CreateHttpClientOptions::default()).await,
+            1
+        );
+        assert_eq!(
+            connections_for_two_requests(CreateHttpClientOptions {
+                pool_max_idle_per_host: Some(0),
+                ..Default::default()
+            })
+            .await,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_client_connect_timeout() {
+        let client = create_http_client_with_resolver(
+            "test",
+            CreateHttpClientOptions::default(),
+            Arc::new(NoResolver),
+            Some(Duration::from_millis(200)),
+        )
+        .unwrap();
+
+        // NOTE: An address of TEST-NET-1 (RFC 5737), which nothing answers on.
+        let started = Instant::now();
+        let res = client.get("http://192.0.2.1:81/").send().await;
+
+        assert!(res.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
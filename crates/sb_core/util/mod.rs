@@ -5,4 +5,5 @@ pub mod http_util;
 pub mod path;
 pub mod sync;
 pub mod text_encoding;
+pub mod token;
 pub mod versions_util;
@@ -0,0 +1,23 @@
+/// Whether `token` is `expected`. Compares in constant time, so a secret token
+/// can't be guessed byte by byte from the response times.
+pub fn token_eq(expected: &[u8], token: &[u8]) -> bool {
+    expected.len() == token.len()
+        && expected
+            .iter()
+            .zip(token)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token_eq() {
+        assert!(token_eq(b"s3cr3t", b"s3cr3t"));
+        assert!(!token_eq(b"s3cr3t", b"s3cr3T"));
+        assert!(!token_eq(b"s3cr3t", b"s3cr3"));
+        assert!(!token_eq(b"s3cr3t", b""));
+    }
+}
@@ -0,0 +1,206 @@
+//! Secrets injected into the environment of user workers. A service gets those
+//! in its own directory under `--secrets-dir`, one file per variable named
+//! after it, the way Docker and Kubernetes mount secrets.
+//!
+//! The directory of a service is found at the path of the service relative to
+//! `--services-root` (the working directory by default), e.g.
+//! `<dir>/a/functions/hello` for `/tenants/a/functions/hello` under
+//! `/tenants`, so services of the same name in different places don't get each
+//! other's secrets.
+
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+use deno_core::anyhow::{bail, Context};
+use deno_core::error::AnyError;
+
+use crate::EnvVars;
+
+pub static SECRETS_CONFIG: OnceLock<SecretsConfig> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct SecretsConfig {
+    pub dir: PathBuf,
+    /// The services are looked up at their path relative to this directory,
+    /// or to the working directory if `None`.
+    pub services_root: Option<PathBuf>,
+}
+
+/// Loads the secrets of the service at `service_path`, if a secrets directory
+/// is configured.
+pub fn load_secrets(service_path: &Path) -> Result<EnvVars, AnyError> {
+    match SECRETS_CONFIG.get() {
+        Some(config) => {
+            let cwd = std::env::current_dir()?;
+            let services_root = config.services_root.as_deref().unwrap_or(&cwd);
+
+            read_secrets_dir(
+                &config
+                    .dir
+                    .join(secrets_key(service_path, services_root, &cwd)?),
+            )
+        }
+        None => Ok(EnvVars::new()),
+    }
+}
+
+/// Returns the path of the directory of the service at `service_path` within
+/// the secrets directory. Refuses paths that would leave it. Relative paths
+/// are resolved against `cwd`.
+fn secrets_key(service_path: &Path, services_root: &Path, cwd: &Path) -> Result<PathBuf, AnyError> {
+    let service_path = cwd.join(service_path);
+    let Ok(relative) = service_path.strip_prefix(cwd.join(services_root)) else {
+        bail!(
+            "can't look up the secrets of a service outside the services root: {}",
+            service_path.display()
+        );
+    };
+
+    let mut key = PathBuf::new();
+
+    for component in relative.components() {
+        match component {
+            Component::Normal(it) => key.push(it),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => bail!(
+                "can't look up the secrets of a service outside the services root: {}",
+                service_path.display()
+            ),
+        }
+    }
+
+    if key.as_os_str().is_empty() {
+        bail!(
+            "can't look up the secrets of the services root as a service: {}",
+            service_path.display()
+        );
+    }
+
+    Ok(key)
+}
+
+fn read_secrets_dir(dir: &Path) -> Result<EnvVars, AnyError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(it) => it,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(EnvVars::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read secrets in {}", dir.display()))
+        }
+    };
+
+    let mut secrets = EnvVars::new();
+
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|it| it.to_str()) else {
+            continue;
+        };
+
+        // NOTE: Kubernetes keeps its bookkeeping in hidden entries next to the
+        // secrets, which are symlinks into them.
+        if name.starts_with('.') || !path.is_file() {
+            continue;
+        }
+
+        let value = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read secret {}", path.display()))?;
+
+        secrets.insert(
+            name.to_string(),
+            value.strip_suffix('\n').unwrap_or(&value).to_string(),
+        );
+    }
+
+    Ok(secrets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_secrets_dir() {
+        let dir = std::env::temp_dir().join(format!("sb-env-secrets-{}", std::process::id()));
+
+        std::fs::create_dir_all(dir.join(".data")).unwrap();
+        std::fs::write(dir.join("API_KEY"), "s3cr3t\n").unwrap();
+        std::fs::write(dir.join(".hidden"), "nope").unwrap();
+
+        let secrets = read_secrets_dir(&dir).unwrap();
+
+        assert_eq!(secrets.len(), 1);
+        assert_eq!(secrets.get("API_KEY").map(String::as_str), Some("s3cr3t"));
+        assert!(read_secrets_dir(&dir.join("missing")).unwrap().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_secrets_key() {
+        let cwd = Path::new("/srv");
+
+        for (path, key) in [
+            ("./functions/hello", "functions/hello"),
+            ("functions/hello/", "functions/hello"),
+            ("/srv/functions/hello", "functions/hello"),
+            ("other/hello", "other/hello"),
+        ] {
+            assert_eq!(
+                secrets_key(Path::new(path), cwd, cwd).unwrap(),
+                Path::new(key),
+                "{}",
+                path
+            );
+        }
+
+        for path in [
+            "../hello",
+            "functions/../../hello",
+            "/etc/hello",
+            ".",
+            "/srv",
+        ] {
+            assert!(secrets_key(Path::new(path), cwd, cwd).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_secrets_key_outside_the_working_directory() {
+        let cwd = Path::new("/srv/app");
+
+        for (path, root, key) in [
+            (
+                "/tenants/a/functions/hello",
+                "/tenants",
+                "a/functions/hello",
+            ),
+            (
+                "/tenants/b/functions/hello",
+                "/tenants",
+                "b/functions/hello",
+            ),
+            ("../functions/hello", "../functions", "hello"),
+        ] {
+            assert_eq!(
+                secrets_key(Path::new(path), Path::new(root), cwd).unwrap(),
+                Path::new(key),
+                "{}",
+                path
+            );
+        }
+
+        for (path, root) in [
+            ("/tenants/a/functions/hello", "/srv"),
+            ("/tenants/../etc/hello", "/tenants"),
+            ("./hello", "/tenants"),
+            ("/tenants", "/tenants"),
+        ] {
+            assert!(
+                secrets_key(Path::new(path), Path::new(root), cwd).is_err(),
+                "{}",
+                path
+            );
+        }
+    }
+}
@@ -6,6 +6,7 @@ pub static SUPABASE_ESZIP_VERSION_KEY: &str = "---SUPABASE-ESZIP-VERSION-ESZIP--
 pub static VFS_ESZIP_KEY: &str = "---SUPABASE-VFS-DATA-ESZIP---";
 pub static SOURCE_CODE_ESZIP_KEY: &str = "---SUPABASE-SOURCE-CODE-ESZIP---";
 pub static STATIC_FILES_ESZIP_KEY: &str = "---SUPABASE-STATIC-FILES-ESZIP---";
+pub static PACKAGE_JSON_ESZIP_KEY: &str = "---SUPABASE-PACKAGE-JSON-ESZIP---";
 
 pub trait AsyncEszipDataRead: std::fmt::Debug + Send + Sync {
     fn ensure_module(&self, specifier: &str) -> Option<Module>;
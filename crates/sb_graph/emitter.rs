@@ -1,10 +1,13 @@
+use crate::graph_limits::ModuleGraphLimits;
 use crate::jsx_util::{get_jsx_emit_opts, get_rt_from_jsx};
 use crate::resolver::{
     CjsResolutionStore, CliGraphResolver, CliGraphResolverOptions, CliNodeResolver,
 };
 use crate::DecoratorType;
+use anyhow::Context;
 use deno_ast::{EmitOptions, SourceMapOption, TranspileOptions};
 use deno_cache_dir::HttpCache;
+use deno_config::package_json::PackageJsonRc;
 use deno_config::workspace::{PackageJsonDepResolution, WorkspaceResolver};
 use deno_config::JsxImportSourceConfig;
 use deno_core::error::AnyError;
@@ -22,19 +25,22 @@ use sb_core::cache::emit::EmitCache;
 use sb_core::cache::fc_permissions::FcPermissions;
 use sb_core::cache::module_info::ModuleInfoCache;
 use sb_core::cache::parsed_source::ParsedSourceCache;
-use sb_core::cache::{CacheSetting, GlobalHttpCache, RealDenoCacheEnv};
+use sb_core::cache::{CacheSetting, GlobalHttpCache, RealDenoCacheEnv, CACHE_PERM};
+use sb_core::cert::SharedRootCertStoreProvider;
 use sb_core::emit::Emitter;
 use sb_core::npm;
+use sb_core::util::fs::atomic_write_file_with_retries;
 use sb_core::util::http_util::HttpClientProvider;
 use sb_node::NodeResolver;
 
+use sb_npm::package_json::PackageJsonInstallDepsProvider;
 use sb_npm::{
     create_managed_npm_resolver, CliNpmResolver, CliNpmResolverManagedCreateOptions,
     CliNpmResolverManagedSnapshotOption,
 };
 use std::collections::HashMap;
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 struct Deferred<T>(once_cell::unsync::OnceCell<T>);
@@ -97,6 +103,8 @@ pub struct EmitterFactory {
     file_fetcher_cache_strategy: Option<CacheSetting>,
     jsx_import_source_config: Option<JsxImportSourceConfig>,
     file_fetcher_allow_remote: bool,
+    module_graph_limits: ModuleGraphLimits,
+    pub maybe_package_json: Option<PackageJsonRc>,
     pub maybe_import_map: Option<ImportMap>,
     module_info_cache: Deferred<Arc<ModuleInfoCache>>,
 }
@@ -128,6 +136,8 @@ impl EmitterFactory {
             file_fetcher: Default::default(),
             file_fetcher_cache_strategy: None,
             file_fetcher_allow_remote: true,
+            module_graph_limits: ModuleGraphLimits::default(),
+            maybe_package_json: None,
             maybe_import_map: None,
             jsx_import_source_config: None,
         }
@@ -141,6 +151,75 @@ impl EmitterFactory {
         self.file_fetcher_allow_remote = allow_remote;
     }
 
+    pub fn set_module_graph_limits(&mut self, limits: ModuleGraphLimits) {
+        self.module_graph_limits = limits;
+    }
+
+    pub fn module_graph_limits(&self) -> ModuleGraphLimits {
+        self.module_graph_limits
+    }
+
+    /// Resolves bare specifiers against the dependencies of the `package.json`
+    /// in `dir`, if there's one, and installs them from npm.
+    pub fn set_package_json_dir(&mut self, dir: &Path) -> Result<(), AnyError> {
+        self.maybe_package_json =
+            sb_node::load_pkg_json(&*self.real_fs(), &dir.join("package.json"))?;
+
+        Ok(())
+    }
+
+    /// Verifies the remote modules of the graph against the `deno.lock` in
+    /// `dir`, if there's one, and records the ones it doesn't know yet.
+    pub fn set_lockfile_dir(&mut self, dir: &Path) -> Result<(), AnyError> {
+        let path = dir.join("deno.lock");
+        let text = match std::fs::read_to_string(&path) {
+            Ok(it) => it,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+
+        // NOTE: An empty lockfile opts the service in before anything was
+        // recorded in it.
+        let lockfile = if text.trim().is_empty() {
+            Lockfile::new_empty(path.clone(), false)
+        } else {
+            Lockfile::with_lockfile_content(path.clone(), &text, false)
+                .with_context(|| format!("failed to parse {}", path.display()))?
+        };
+
+        self.maybe_lockfile = Some(LockfileOpts {
+            path,
+            overwrite: false,
+        });
+        self.lockfile = Deferred(once_cell::unsync::OnceCell::with_value(Some(Arc::new(
+            Mutex::new(lockfile),
+        ))));
+
+        Ok(())
+    }
+
+    /// Writes the lockfile found by `set_lockfile_dir` back if the graph has
+    /// recorded anything in it.
+    pub fn write_lockfile_if_changed(&self) -> Result<(), AnyError> {
+        if self.maybe_lockfile.is_none() {
+            return Ok(());
+        }
+
+        let Some(lockfile) = self.get_lock_file() else {
+            return Ok(());
+        };
+
+        let mut lockfile = lockfile.lock();
+        let Some(bytes) = lockfile.resolve_write_bytes() else {
+            return Ok(());
+        };
+
+        atomic_write_file_with_retries(&lockfile.filename, bytes, CACHE_PERM)
+            .with_context(|| format!("failed to write {}", lockfile.filename.display()))
+    }
+
     pub fn set_import_map(&mut self, import_map: Option<ImportMap>) {
         self.maybe_import_map = import_map;
     }
@@ -238,8 +317,12 @@ impl EmitterFactory {
     }
 
     pub fn http_client_provider(&self) -> &Arc<HttpClientProvider> {
-        self.http_client_provider
-            .get_or_init(|| Arc::new(HttpClientProvider::new(None, None)))
+        self.http_client_provider.get_or_init(|| {
+            Arc::new(HttpClientProvider::new(
+                Some(Arc::new(SharedRootCertStoreProvider)),
+                None,
+            ))
+        })
     }
 
     pub fn real_fs(&self) -> Arc<dyn deno_fs::FileSystem> {
@@ -285,7 +368,12 @@ impl EmitterFactory {
                     cache_setting: CacheSetting::Use,
                     maybe_node_modules_path: None,
                     npm_system_info: Default::default(),
-                    package_json_deps_provider: Default::default(),
+                    package_json_deps_provider: Arc::new(
+                        self.maybe_package_json
+                            .as_deref()
+                            .map(PackageJsonInstallDepsProvider::from_package_json)
+                            .unwrap_or_default(),
+                    ),
                     npmrc: npm::create_default_npmrc(),
                 })
                 .await
@@ -324,8 +412,12 @@ impl EmitterFactory {
         self.workspace_resolver.get_or_try_init(|| {
             Ok(Arc::new(WorkspaceResolver::new_raw(
                 self.maybe_import_map.clone(),
-                vec![],
-                PackageJsonDepResolution::Disabled,
+                self.maybe_package_json.iter().cloned().collect(),
+                if self.maybe_package_json.is_some() {
+                    PackageJsonDepResolution::Enabled
+                } else {
+                    PackageJsonDepResolution::Disabled
+                },
             )))
         })
     }
@@ -342,7 +434,7 @@ impl EmitterFactory {
                     },
 
                     workspace_resolver: self.workspace_resolver()?.clone(),
-                    bare_node_builtins_enabled: false,
+                    bare_node_builtins_enabled: true,
                     maybe_jsx_import_source_config: self.jsx_import_source_config.clone(),
                     maybe_vendor_dir: None,
                 })))
@@ -0,0 +1,163 @@
+//! Limits on the module graph of a service, enforced while it's being loaded so
+//! that the dependencies of a single function can't take unbounded disk and
+//! network.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error};
+use deno_core::futures::{future, FutureExt};
+use deno_core::ModuleSpecifier;
+use deno_graph::source::{CacheInfo, LoadFuture, LoadOptions, LoadResponse, Loader};
+use deno_graph::ModuleInfo;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ModuleGraphLimits {
+    /// Total size of the sources of the modules in the graph.
+    pub max_size_bytes: Option<u64>,
+    /// Modules loaded over http(s), including those of JSR packages.
+    pub max_remote_modules: Option<usize>,
+}
+
+impl ModuleGraphLimits {
+    pub fn is_empty(&self) -> bool {
+        self.max_size_bytes.is_none() && self.max_remote_modules.is_none()
+    }
+}
+
+#[derive(Debug, Default)]
+struct ModuleGraphUsage {
+    size_bytes: u64,
+    remote_modules: usize,
+}
+
+impl ModuleGraphUsage {
+    fn add_remote_module(
+        &mut self,
+        specifier: &ModuleSpecifier,
+        limits: &ModuleGraphLimits,
+    ) -> Result<(), Error> {
+        self.remote_modules += 1;
+
+        match limits.max_remote_modules {
+            Some(max) if self.remote_modules > max => Err(anyhow!(
+                "the module graph exceeds the limit of {} remote modules (loading {})",
+                max,
+                specifier
+            )),
+
+            _ => Ok(()),
+        }
+    }
+
+    fn add_module(
+        &mut self,
+        specifier: &ModuleSpecifier,
+        size_bytes: usize,
+        limits: &ModuleGraphLimits,
+    ) -> Result<(), Error> {
+        self.size_bytes += size_bytes as u64;
+
+        match limits.max_size_bytes {
+            Some(max) if self.size_bytes > max => Err(anyhow!(
+                "the module graph exceeds the limit of {} bytes (loading {} brought it to {} bytes)",
+                max,
+                specifier,
+                self.size_bytes
+            )),
+
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Fails the loads of modules once the graph exceeds its limits.
+///
+/// NOTE: Remote modules are counted before they're fetched, so the fetch that
+/// would exceed the limit never happens. npm packages are resolved outside of
+/// the graph and aren't counted.
+pub struct LimitedLoader {
+    inner: Box<dyn Loader>,
+    limits: ModuleGraphLimits,
+    usage: Rc<RefCell<ModuleGraphUsage>>,
+}
+
+impl LimitedLoader {
+    pub fn new(inner: Box<dyn Loader>, limits: ModuleGraphLimits) -> Self {
+        Self {
+            inner,
+            limits,
+            usage: Rc::default(),
+        }
+    }
+}
+
+impl Loader for LimitedLoader {
+    fn get_cache_info(&self, specifier: &ModuleSpecifier) -> Option<CacheInfo> {
+        self.inner.get_cache_info(specifier)
+    }
+
+    fn load(&self, specifier: &ModuleSpecifier, options: LoadOptions) -> LoadFuture {
+        if matches!(specifier.scheme(), "http" | "https") {
+            if let Err(err) = self
+                .usage
+                .borrow_mut()
+                .add_remote_module(specifier, &self.limits)
+            {
+                return future::ready(Err(err)).boxed_local();
+            }
+        }
+
+        let limits = self.limits;
+        let usage = self.usage.clone();
+
+        self.inner
+            .load(specifier, options)
+            .map(move |result| {
+                if let Ok(Some(LoadResponse::Module {
+                    specifier, content, ..
+                })) = &result
+                {
+                    usage
+                        .borrow_mut()
+                        .add_module(specifier, content.len(), &limits)?;
+                }
+
+                result
+            })
+            .boxed_local()
+    }
+
+    fn cache_module_info(
+        &self,
+        specifier: &ModuleSpecifier,
+        source: &Arc<[u8]>,
+        module_info: &ModuleInfo,
+    ) {
+        self.inner.cache_module_info(specifier, source, module_info)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_module_graph_usage() {
+        let limits = ModuleGraphLimits {
+            max_size_bytes: Some(100),
+            max_remote_modules: Some(1),
+        };
+
+        let mut usage = ModuleGraphUsage::default();
+        let local = ModuleSpecifier::parse("file:///src/index.ts").unwrap();
+        let remote = ModuleSpecifier::parse("https://deno.land/std/http/mod.ts").unwrap();
+
+        assert!(usage.add_module(&local, 60, &limits).is_ok());
+        assert!(usage.add_remote_module(&remote, &limits).is_ok());
+        assert!(usage.add_remote_module(&remote, &limits).is_err());
+        assert!(usage.add_module(&remote, 60, &limits).is_err());
+        assert!(ModuleGraphLimits::default().is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::emitter::EmitterFactory;
 use crate::graph_fs::DenoGraphFsAdapter;
+use crate::graph_limits::LimitedLoader;
 use crate::jsr::CliJsrUrlProvider;
 use crate::resolver::CliGraphResolver;
 use anyhow::Context;
@@ -305,7 +306,10 @@ impl ModuleGraphBuilder {
         &self,
         roots: Vec<ModuleSpecifier>,
     ) -> Result<deno_graph::ModuleGraph, AnyError> {
-        let mut cache = self.emitter_factory.file_fetcher_loader().await?;
+        let mut loader = LimitedLoader::new(
+            self.emitter_factory.file_fetcher_loader().await?,
+            self.emitter_factory.module_graph_limits(),
+        );
         let cli_resolver = self.resolver().await.clone();
         let graph_resolver = cli_resolver.as_graph_resolver();
         let graph_npm_resolver = cli_resolver.create_graph_npm_resolver();
@@ -326,7 +330,7 @@ impl ModuleGraphBuilder {
         self.build_graph_with_npm_resolution(
             &mut graph,
             roots,
-            cache.as_mut(),
+            &mut loader,
             deno_graph::BuildOptions {
                 is_dynamic: false,
                 imports: vec![],
@@ -419,12 +423,15 @@ pub async fn create_graph(
         ModuleSpecifier::parse(&format_specifier).unwrap()
     };
 
-    let builder = ModuleGraphBuilder::new(emitter_factory, false);
+    let builder = ModuleGraphBuilder::new(emitter_factory.clone(), false);
     let create_module_graph_task = builder.create_graph_and_maybe_check(vec![module_specifier]);
-
-    create_module_graph_task
+    let graph = create_module_graph_task
         .await
-        .context("failed to create the graph")
+        .context("failed to create the graph")?;
+
+    emitter_factory.write_lockfile_if_changed()?;
+
+    Ok(graph)
 }
 
 /// Adds more explanatory information to a resolution error.
@@ -497,7 +504,7 @@ fn enhanced_lockfile_error_message(err: &ModuleError) -> Option<String> {
             "This could be caused by:\n",
             "  * the lock file may be corrupt\n",
             "  * the source itself may be corrupt\n\n",
-            "Use the --lock-write flag to regenerate the lockfile or --reload to reload the source code from the server."
+            "Remove the entry from the deno.lock of the service to accept the new source code."
           ),
           package_nv,
           checksum_err.actual,
@@ -518,7 +525,7 @@ fn enhanced_lockfile_error_message(err: &ModuleError) -> Option<String> {
             "This could be caused by:\n",
             "  * the lock file may be corrupt\n",
             "  * the source itself may be corrupt\n\n",
-            "Use the --lock-write flag to regenerate the lockfile or --reload to reload the source code from the server."
+            "Remove the entry from the deno.lock of the service to accept the new source code."
           ),
           specifier,
           checksum_err.actual,
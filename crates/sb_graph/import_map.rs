@@ -1,24 +1,48 @@
 use anyhow::{anyhow, Error};
 use deno_core::url::Url;
+use deno_graph::source::RawDataUrl;
 use import_map::{parse_from_json, ImportMap};
 use std::fs;
 use std::path::Path;
 use urlencoding::decode;
 
+/// Returns whether `path_str` carries the import map itself (as JSON or a
+/// `data:` URL) rather than pointing at a file.
+pub fn is_inline_import_map(path_str: &str) -> bool {
+    path_str.starts_with("data:") || path_str.trim_start().starts_with('{')
+}
+
 pub fn load_import_map(maybe_path: Option<String>) -> Result<Option<ImportMap>, Error> {
     if let Some(path_str) = maybe_path {
         let json_str;
         let base_url;
+        let cwd_url = || {
+            std::env::current_dir().map_err(Error::from).and_then(|it| {
+                Url::from_directory_path(it).map_err(|_| anyhow!("invalid import map base url"))
+            })
+        };
 
         // check if the path is a data URI (prefixed with data:)
         // the data URI takes the following format
         // data:{encodeURIComponent(mport_map.json)?{encodeURIComponent(base_path)}
+        // or else is a regular data URL, whose specifiers are resolved against
+        // the current directory
         if path_str.starts_with("data:") {
             let data_uri = Url::parse(&path_str)?;
-            json_str = decode(data_uri.path())?.into_owned();
-            base_url =
-                Url::from_directory_path(decode(data_uri.query().unwrap_or(""))?.into_owned())
-                    .map_err(|_| anyhow!("invalid import map base url"))?;
+            let path = decode(data_uri.path())?.into_owned();
+
+            if path.trim_start().starts_with('{') {
+                json_str = path;
+                base_url =
+                    Url::from_directory_path(decode(data_uri.query().unwrap_or(""))?.into_owned())
+                        .map_err(|_| anyhow!("invalid import map base url"))?;
+            } else {
+                json_str = RawDataUrl::parse(&data_uri)?.decode()?;
+                base_url = cwd_url()?;
+            }
+        } else if path_str.trim_start().starts_with('{') {
+            json_str = path_str;
+            base_url = cwd_url()?;
         } else {
             let path = Path::new(&path_str);
             let abs_path = std::env::current_dir().map(|p| p.join(path))?;
@@ -33,3 +57,33 @@ pub fn load_import_map(maybe_path: Option<String>) -> Result<Option<ImportMap>,
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_load_inline_import_map() {
+        let json = r#"{ "imports": { "std/": "https://deno.land/std@0.224.0/" } }"#;
+        let data_url = format!("data:application/json,{}", urlencoding::encode(json));
+
+        for path_str in [json.to_string(), data_url] {
+            assert!(is_inline_import_map(&path_str));
+
+            let import_map = load_import_map(Some(path_str)).unwrap().unwrap();
+            let resolved = import_map
+                .resolve(
+                    "std/path/mod.ts",
+                    &Url::parse("file:///src/index.ts").unwrap(),
+                )
+                .unwrap();
+
+            assert_eq!(
+                resolved.as_str(),
+                "https://deno.land/std@0.224.0/path/mod.ts"
+            );
+        }
+
+        assert!(!is_inline_import_map("./import_map.json"));
+    }
+}
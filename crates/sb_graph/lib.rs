@@ -15,8 +15,8 @@ use futures::{AsyncReadExt, AsyncSeekExt};
 use glob::glob;
 use log::error;
 use sb_eszip_shared::{
-    AsyncEszipDataRead, SOURCE_CODE_ESZIP_KEY, STATIC_FILES_ESZIP_KEY, SUPABASE_ESZIP_VERSION,
-    SUPABASE_ESZIP_VERSION_KEY, VFS_ESZIP_KEY,
+    AsyncEszipDataRead, PACKAGE_JSON_ESZIP_KEY, SOURCE_CODE_ESZIP_KEY, STATIC_FILES_ESZIP_KEY,
+    SUPABASE_ESZIP_VERSION, SUPABASE_ESZIP_VERSION_KEY, VFS_ESZIP_KEY,
 };
 use sb_fs::{build_vfs, VfsOpts};
 use sb_npm::InnerCliNpmResolverRef;
@@ -37,6 +37,7 @@ pub mod emitter;
 pub mod errors;
 pub mod eszip_migrate;
 pub mod graph_fs;
+pub mod graph_limits;
 pub mod graph_util;
 pub mod import_map;
 pub mod jsr;
@@ -716,6 +717,15 @@ where
 
     eszip.add_opaque_data(String::from(SOURCE_CODE_ESZIP_KEY), bin_code);
 
+    // NOTE: The module loader needs the `package.json` to resolve the bare
+    // specifiers of the bundle the same way as the graph did.
+    if let Some(pkg_json) = emitter_factory.maybe_package_json.as_ref() {
+        eszip.add_opaque_data(
+            String::from(PACKAGE_JSON_ESZIP_KEY),
+            Arc::from(fs::read(&pkg_json.path)?.into_boxed_slice()),
+        );
+    }
+
     // add import map
     if emitter_factory.maybe_import_map.is_some() {
         eszip.add_import_map(
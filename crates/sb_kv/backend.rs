@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use deno_core::error::AnyError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KvEntry {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub versionstamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KvMutation {
+    Set {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        /// Unix time in milliseconds.
+        expire_at: Option<u64>,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KvAtomicWrite {
+    /// Keys and the versionstamps they must be at, `None` for absent keys.
+    pub checks: Vec<(Vec<u8>, Option<u64>)>,
+    pub mutations: Vec<KvMutation>,
+}
+
+/// Storage of the key-value store of a service. Keys are encoded by
+/// [`crate::codec::encode_key`] and values are opaque to the backend.
+#[async_trait(?Send)]
+pub trait KvBackend {
+    async fn get(&self, keys: Vec<Vec<u8>>) -> Result<Vec<Option<KvEntry>>, AnyError>;
+
+    /// Entries whose keys are in `start..end`, in key order unless `reverse`.
+    async fn list(
+        &self,
+        start: Vec<u8>,
+        end: Vec<u8>,
+        limit: usize,
+        reverse: bool,
+    ) -> Result<Vec<KvEntry>, AnyError>;
+
+    /// Applies the mutations if all checks pass, returning the versionstamp of
+    /// the write, or `None` if a check failed.
+    async fn atomic_write(&self, write: KvAtomicWrite) -> Result<Option<u64>, AnyError>;
+}
+
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|it| it.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,155 @@
+//! Order-preserving encoding of keys, so that SQLite and remote backends can
+//! compare and range over the encoded bytes. Modeled on the tuple layer of
+//! FoundationDB, like the keys of Deno KV.
+
+use anyhow::{anyhow, bail, Error};
+
+const BYTES: u8 = 0x01;
+const STRING: u8 = 0x02;
+const NUMBER: u8 = 0x21;
+const FALSE: u8 = 0x26;
+const TRUE: u8 = 0x27;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyPart {
+    Bytes(Vec<u8>),
+    String(String),
+    Number(f64),
+    False,
+    True,
+}
+
+pub fn encode_key(key: &[KeyPart]) -> Vec<u8> {
+    let mut out = vec![];
+
+    for part in key {
+        match part {
+            KeyPart::Bytes(bytes) => {
+                out.push(BYTES);
+                escape_into(bytes, &mut out);
+            }
+
+            KeyPart::String(s) => {
+                out.push(STRING);
+                escape_into(s.as_bytes(), &mut out);
+            }
+
+            KeyPart::Number(n) => {
+                let bits = n.to_bits();
+                let bits = if bits >> 63 == 1 {
+                    !bits
+                } else {
+                    bits | (1 << 63)
+                };
+
+                out.push(NUMBER);
+                out.extend_from_slice(&bits.to_be_bytes());
+            }
+
+            KeyPart::False => out.push(FALSE),
+            KeyPart::True => out.push(TRUE),
+        }
+    }
+
+    out
+}
+
+pub fn decode_key(mut bytes: &[u8]) -> Result<Vec<KeyPart>, Error> {
+    let mut key = vec![];
+
+    while let Some((&tag, rest)) = bytes.split_first() {
+        bytes = rest;
+        key.push(match tag {
+            BYTES => KeyPart::Bytes(unescape_from(&mut bytes)?),
+            STRING => KeyPart::String(String::from_utf8(unescape_from(&mut bytes)?)?),
+            NUMBER => {
+                let Some((number, rest)) = bytes.split_first_chunk::<8>() else {
+                    bail!("truncated number in key");
+                };
+
+                let bits = u64::from_be_bytes(*number);
+                let bits = if bits >> 63 == 1 {
+                    bits & !(1 << 63)
+                } else {
+                    !bits
+                };
+
+                bytes = rest;
+                KeyPart::Number(f64::from_bits(bits))
+            }
+
+            FALSE => KeyPart::False,
+            TRUE => KeyPart::True,
+            _ => bail!("invalid tag in key: {:#04x}", tag),
+        });
+    }
+
+    Ok(key)
+}
+
+fn escape_into(bytes: &[u8], out: &mut Vec<u8>) {
+    for &byte in bytes {
+        out.push(byte);
+
+        if byte == 0 {
+            out.push(0xff);
+        }
+    }
+
+    out.push(0);
+}
+
+fn unescape_from(bytes: &mut &[u8]) -> Result<Vec<u8>, Error> {
+    let mut out = vec![];
+    let mut iter = bytes.iter().enumerate();
+
+    while let Some((idx, &byte)) = iter.next() {
+        if byte != 0 {
+            out.push(byte);
+            continue;
+        }
+
+        if bytes.get(idx + 1) == Some(&0xff) {
+            out.push(0);
+            iter.next();
+            continue;
+        }
+
+        *bytes = &bytes[idx + 1..];
+        return Ok(out);
+    }
+
+    Err(anyhow!("unterminated part in key"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_codec() {
+        let keys = [
+            vec![KeyPart::Bytes(vec![0, 1])],
+            vec![KeyPart::String("a".into())],
+            vec![KeyPart::String("a".into()), KeyPart::Number(-1.5)],
+            vec![KeyPart::String("a".into()), KeyPart::Number(0.0)],
+            vec![KeyPart::String("a".into()), KeyPart::Number(2.0)],
+            vec![KeyPart::String("a\0b".into())],
+            vec![KeyPart::String("b".into())],
+            vec![KeyPart::Number(1.0)],
+            vec![KeyPart::False],
+            vec![KeyPart::True],
+        ];
+
+        for window in keys.windows(2) {
+            assert!(encode_key(&window[0]) < encode_key(&window[1]));
+        }
+
+        for key in keys {
+            assert_eq!(decode_key(&encode_key(&key)).unwrap(), key);
+        }
+
+        assert!(decode_key(&[STRING, b'a']).is_err());
+        assert!(decode_key(&[0x7f]).is_err());
+    }
+}
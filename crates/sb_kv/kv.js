@@ -0,0 +1,163 @@
+import { primordials, core } from "ext:core/mod.js";
+
+const ops = core.ops;
+
+const {
+	ArrayIsArray,
+	ArrayPrototypeMap,
+	MathMin,
+	ObjectFreeze,
+	TypeError,
+} = primordials;
+
+const {
+	op_kv_open,
+	op_kv_get,
+	op_kv_list,
+	op_kv_atomic_write,
+} = ops;
+
+function encodeKeyPart(part) {
+	switch (typeof part) {
+		case "string":
+			return { kind: "string", value: part };
+		case "number":
+			return { kind: "number", value: part };
+		case "boolean":
+			return { kind: "boolean", value: part };
+	}
+
+	if (part instanceof Uint8Array) {
+		return { kind: "bytes", value: part };
+	}
+
+	throw new TypeError(`unsupported key part: ${typeof part}`);
+}
+
+function encodeKey(key) {
+	if (!ArrayIsArray(key)) {
+		throw new TypeError("key must be an array");
+	}
+
+	return ArrayPrototypeMap(key, encodeKeyPart);
+}
+
+function decodeEntry(entry) {
+	return {
+		key: ObjectFreeze(ArrayPrototypeMap(entry.key, (it) => it.value)),
+		value: core.deserialize(entry.value, { forStorage: true }),
+		versionstamp: entry.versionstamp,
+	};
+}
+
+class AtomicOperation {
+	#rid;
+	#checks = [];
+	#mutations = [];
+
+	constructor(rid) {
+		this.#rid = rid;
+	}
+
+	check(...checks) {
+		for (const { key, versionstamp } of checks) {
+			this.#checks.push({ key: encodeKey(key), versionstamp: versionstamp ?? null });
+		}
+
+		return this;
+	}
+
+	set(key, value, options = {}) {
+		this.#mutations.push({
+			key: encodeKey(key),
+			kind: "set",
+			value: core.serialize(value, { forStorage: true }),
+			expireIn: options.expireIn ?? null,
+		});
+
+		return this;
+	}
+
+	delete(key) {
+		this.#mutations.push({ key: encodeKey(key), kind: "delete", value: null, expireIn: null });
+		return this;
+	}
+
+	async commit() {
+		const versionstamp = await op_kv_atomic_write(this.#rid, this.#checks, this.#mutations);
+		return versionstamp === null ? { ok: false } : { ok: true, versionstamp };
+	}
+}
+
+class Kv {
+	#rid;
+
+	constructor(rid) {
+		this.#rid = rid;
+	}
+
+	async get(key) {
+		const [entry] = await this.getMany([key]);
+		return entry;
+	}
+
+	async getMany(keys) {
+		const entries = await op_kv_get(this.#rid, ArrayPrototypeMap(keys, encodeKey));
+
+		return ArrayPrototypeMap(entries, (entry, idx) =>
+			entry === null
+				? { key: keys[idx], value: null, versionstamp: null }
+				: decodeEntry(entry)
+		);
+	}
+
+	set(key, value, options) {
+		return this.atomic().set(key, value, options).commit();
+	}
+
+	async delete(key) {
+		await this.atomic().delete(key).commit();
+	}
+
+	async *list(selector, options = {}) {
+		const { limit = Infinity, reverse = false, batchSize = 100 } = options;
+		const encodedSelector = {
+			prefix: selector.prefix ? encodeKey(selector.prefix) : null,
+			start: selector.start ? encodeKey(selector.start) : null,
+			end: selector.end ? encodeKey(selector.end) : null,
+		};
+
+		let cursor = null;
+		let remaining = limit;
+
+		while (remaining > 0) {
+			const count = MathMin(batchSize, remaining);
+			const entries = await op_kv_list(this.#rid, encodedSelector, cursor, count, reverse);
+
+			for (const entry of entries) {
+				yield decodeEntry(entry);
+			}
+
+			if (entries.length < count) {
+				break;
+			}
+
+			remaining -= entries.length;
+			cursor = entries[entries.length - 1].key;
+		}
+	}
+
+	atomic() {
+		return new AtomicOperation(this.#rid);
+	}
+
+	close() {
+		core.close(this.#rid);
+	}
+}
+
+async function openKv(path) {
+	return new Kv(await op_kv_open(path ?? null));
+}
+
+export { AtomicOperation, Kv, openKv };
@@ -0,0 +1,368 @@
+//! A key-value store in the style of Deno KV, exposed to workers as
+//! `Deno.openKv()`. Each service gets a store of its own.
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use anyhow::Context;
+use deno_core::error::{type_error, AnyError};
+use deno_core::url::Url;
+use deno_core::{op2, JsBuffer, OpState, Resource, ResourceId, ToJsBuffer};
+use once_cell::sync::OnceCell;
+use sb_core::cache::deno_dir::DenoDir;
+use sb_core::util::checksum;
+use serde::{Deserialize, Serialize};
+
+mod backend;
+mod codec;
+mod remote;
+mod sqlite;
+
+pub use backend::{KvAtomicWrite, KvBackend, KvEntry, KvMutation};
+pub use remote::RemoteKv;
+pub use sqlite::SqliteKv;
+
+use backend::now_ms;
+use codec::{decode_key, encode_key, KeyPart};
+
+const MAX_KEY_SIZE_BYTES: usize = 2048;
+const MAX_VALUE_SIZE_BYTES: usize = 65536;
+const MAX_LIST_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KvBackendConfig {
+    /// A SQLite database per service, in `dir`.
+    Sqlite { dir: PathBuf },
+    /// A store shared by several runtimes, see [`RemoteKv`].
+    Remote {
+        url: Url,
+        access_token: Option<String>,
+    },
+}
+
+impl KvBackendConfig {
+    pub fn remote(url: &str, access_token: Option<String>) -> Result<Self, AnyError> {
+        let mut url = Url::parse(url).with_context(|| format!("invalid KV URL: {}", url))?;
+
+        // The calls are joined to the URL as relative paths.
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+
+        Ok(Self::Remote { url, access_token })
+    }
+}
+
+/// Backend of the stores of services, set once from the CLI. Defaults to
+/// SQLite databases in the `kv` folder of the Deno dir.
+pub static KV_BACKEND: OnceCell<KvBackendConfig> = OnceCell::new();
+
+/// The store of a worker, put into its `OpState`.
+#[derive(Debug, Clone)]
+pub struct KvOptions {
+    /// Identifies the store of the service, e.g. its path.
+    pub namespace: String,
+    /// Caps the size of the keys and values of the store.
+    pub max_size_bytes: Option<u64>,
+}
+
+struct KvResource {
+    backend: Rc<dyn KvBackend>,
+}
+
+impl Resource for KvResource {
+    fn name(&self) -> std::borrow::Cow<str> {
+        "kv".into()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "camelCase")]
+enum KeyPartArg {
+    Bytes(JsBuffer),
+    String(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "camelCase")]
+enum KeyPartValue {
+    Bytes(ToJsBuffer),
+    String(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+type KeyArg = Vec<KeyPartArg>;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KvEntryValue {
+    key: Vec<KeyPartValue>,
+    value: ToJsBuffer,
+    versionstamp: String,
+}
+
+impl TryFrom<KvEntry> for KvEntryValue {
+    type Error = AnyError;
+
+    fn try_from(entry: KvEntry) -> Result<Self, Self::Error> {
+        let key = decode_key(&entry.key)?
+            .into_iter()
+            .map(|it| match it {
+                KeyPart::Bytes(bytes) => KeyPartValue::Bytes(bytes.into()),
+                KeyPart::String(s) => KeyPartValue::String(s),
+                KeyPart::Number(n) => KeyPartValue::Number(n),
+                KeyPart::False => KeyPartValue::Boolean(false),
+                KeyPart::True => KeyPartValue::Boolean(true),
+            })
+            .collect();
+
+        Ok(Self {
+            key,
+            value: entry.value.into(),
+            versionstamp: format_versionstamp(entry.versionstamp),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListSelectorArg {
+    prefix: Option<KeyArg>,
+    start: Option<KeyArg>,
+    end: Option<KeyArg>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CheckArg {
+    key: KeyArg,
+    versionstamp: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MutationArg {
+    key: KeyArg,
+    /// `set` or `delete`.
+    kind: String,
+    value: Option<JsBuffer>,
+    expire_in: Option<u64>,
+}
+
+fn format_versionstamp(versionstamp: u64) -> String {
+    format!("{:020x}", versionstamp)
+}
+
+fn parse_versionstamp(versionstamp: &str) -> Result<u64, AnyError> {
+    u64::from_str_radix(versionstamp, 16)
+        .map_err(|_| type_error(format!("invalid versionstamp: {versionstamp}")))
+}
+
+fn encode_key_arg(key: KeyArg) -> Result<Vec<u8>, AnyError> {
+    let key = key
+        .into_iter()
+        .map(|it| match it {
+            KeyPartArg::Bytes(bytes) => KeyPart::Bytes(bytes.to_vec()),
+            KeyPartArg::String(s) => KeyPart::String(s),
+            KeyPartArg::Number(n) => KeyPart::Number(n),
+            KeyPartArg::Boolean(false) => KeyPart::False,
+            KeyPartArg::Boolean(true) => KeyPart::True,
+        })
+        .collect::<Vec<_>>();
+
+    let encoded = encode_key(&key);
+
+    if encoded.len() > MAX_KEY_SIZE_BYTES {
+        return Err(type_error(format!(
+            "key too large (max {MAX_KEY_SIZE_BYTES} bytes)"
+        )));
+    }
+
+    Ok(encoded)
+}
+
+fn get_backend(state: &RefCell<OpState>, rid: ResourceId) -> Result<Rc<dyn KvBackend>, AnyError> {
+    Ok(state
+        .borrow()
+        .resource_table
+        .get::<KvResource>(rid)?
+        .backend
+        .clone())
+}
+
+#[op2(async)]
+#[smi]
+async fn op_kv_open(
+    state: Rc<RefCell<OpState>>,
+    #[string] path: Option<String>,
+) -> Result<ResourceId, AnyError> {
+    if path.is_some() {
+        return Err(type_error(
+            "Deno.openKv() doesn't take a path: each service has a store of its own",
+        ));
+    }
+
+    let options = state
+        .borrow()
+        .try_borrow::<KvOptions>()
+        .cloned()
+        .ok_or_else(|| type_error("Deno.openKv() is not available in this worker"))?;
+
+    let config = match KV_BACKEND.get() {
+        Some(config) => config.clone(),
+        None => KvBackendConfig::Sqlite {
+            dir: DenoDir::new(None)?.kv_folder_path(),
+        },
+    };
+
+    // NOTE: The namespace is the path of the service on the host, which
+    // doesn't leave it; stores are told apart by its digest.
+    let store_id = checksum::gen(&[options.namespace.as_bytes()]);
+
+    let backend: Rc<dyn KvBackend> = match config {
+        KvBackendConfig::Sqlite { dir } => Rc::new(SqliteKv::open(
+            &dir.join(format!("{}.sqlite3", store_id)),
+            options.max_size_bytes,
+        )?),
+
+        KvBackendConfig::Remote { url, access_token } => {
+            let client = deno_fetch::get_or_create_client_from_state(&mut state.borrow_mut())?;
+
+            Rc::new(RemoteKv::new(client, url, access_token, store_id))
+        }
+    };
+
+    Ok(state
+        .borrow_mut()
+        .resource_table
+        .add(KvResource { backend }))
+}
+
+#[op2(async)]
+#[serde]
+async fn op_kv_get(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+    #[serde] keys: Vec<KeyArg>,
+) -> Result<Vec<Option<KvEntryValue>>, AnyError> {
+    let backend = get_backend(&state, rid)?;
+    let keys = keys
+        .into_iter()
+        .map(encode_key_arg)
+        .collect::<Result<_, _>>()?;
+
+    backend
+        .get(keys)
+        .await?
+        .into_iter()
+        .map(|it| it.map(KvEntryValue::try_from).transpose())
+        .collect()
+}
+
+#[op2(async)]
+#[serde]
+async fn op_kv_list(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+    #[serde] selector: ListSelectorArg,
+    #[serde] cursor: Option<KeyArg>,
+    #[smi] limit: u32,
+    reverse: bool,
+) -> Result<Vec<KvEntryValue>, AnyError> {
+    let backend = get_backend(&state, rid)?;
+    let prefix = encode_key_arg(selector.prefix.unwrap_or_default())?;
+
+    // NOTE: Parts of a key start with a tag greater than 0x00, so the keys
+    // under a prefix sort between these bounds, excluding the prefix itself.
+    let mut start = match selector.start {
+        Some(start) => encode_key_arg(start)?,
+        None => [prefix.as_slice(), &[0x00]].concat(),
+    };
+
+    let mut end = match selector.end {
+        Some(end) => encode_key_arg(end)?,
+        None => [prefix.as_slice(), &[0xff]].concat(),
+    };
+
+    if !start.starts_with(&prefix) || !end.starts_with(&prefix) {
+        return Err(type_error("start and end keys must be within the prefix"));
+    }
+
+    if let Some(cursor) = cursor {
+        let cursor = encode_key_arg(cursor)?;
+
+        if reverse {
+            end = cursor;
+        } else {
+            start = [cursor.as_slice(), &[0x00]].concat();
+        }
+    }
+
+    backend
+        .list(start, end, limit.clamp(1, MAX_LIST_LIMIT) as usize, reverse)
+        .await?
+        .into_iter()
+        .map(KvEntryValue::try_from)
+        .collect()
+}
+
+#[op2(async)]
+#[string]
+async fn op_kv_atomic_write(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+    #[serde] checks: Vec<CheckArg>,
+    #[serde] mutations: Vec<MutationArg>,
+) -> Result<Option<String>, AnyError> {
+    let backend = get_backend(&state, rid)?;
+    let mut write = KvAtomicWrite::default();
+
+    for check in checks {
+        write.checks.push((
+            encode_key_arg(check.key)?,
+            check
+                .versionstamp
+                .as_deref()
+                .map(parse_versionstamp)
+                .transpose()?,
+        ));
+    }
+
+    for mutation in mutations {
+        let key = encode_key_arg(mutation.key)?;
+
+        write
+            .mutations
+            .push(match (mutation.kind.as_str(), mutation.value) {
+                ("set", Some(value)) => {
+                    if value.len() > MAX_VALUE_SIZE_BYTES {
+                        return Err(type_error(format!(
+                            "value too large (max {MAX_VALUE_SIZE_BYTES} bytes)"
+                        )));
+                    }
+
+                    KvMutation::Set {
+                        key,
+                        value: value.to_vec(),
+                        expire_at: mutation.expire_in.map(|it| now_ms() + it),
+                    }
+                }
+
+                ("delete", _) => KvMutation::Delete { key },
+                (kind, _) => return Err(type_error(format!("invalid mutation: {kind}"))),
+            });
+    }
+
+    Ok(backend.atomic_write(write).await?.map(format_versionstamp))
+}
+
+deno_core::extension!(
+    sb_kv,
+    ops = [op_kv_open, op_kv_get, op_kv_list, op_kv_atomic_write],
+    esm_entry_point = "ext:sb_kv/kv.js",
+    esm = ["kv.js"]
+);
@@ -0,0 +1,223 @@
+//! A key-value store shared by several runtimes, kept by a remote service.
+//!
+//! Every call is a `POST` of a JSON body to `<url>/<call>`, carrying the
+//! namespace of the service, a digest of its path, and base64 encoded keys and
+//! values:
+//!
+//! - `get`: `{ namespace, keys }` → `{ entries: [{ key, value, versionstamp } | null] }`
+//! - `list`: `{ namespace, start, end, limit, reverse }` → `{ entries }`
+//! - `atomic_write`: `{ namespace, checks: [{ key, versionstamp }], mutations:
+//!   [{ type: "set", key, value, expireAt } | { type: "delete", key }] }` →
+//!   `{ versionstamp }`, which is `null` if a check failed
+//!
+//! The remote service is expected to enforce the quotas of namespaces.
+
+use async_trait::async_trait;
+use deno_core::error::{custom_error, AnyError};
+use deno_core::serde_json;
+use deno_core::url::Url;
+use deno_fetch::reqwest;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{KvAtomicWrite, KvBackend, KvEntry, KvMutation};
+
+mod b64 {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        bytes: impl AsRef<[u8]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize)]
+struct Key<'a>(#[serde(serialize_with = "b64::serialize")] &'a [u8]);
+
+#[derive(Deserialize)]
+struct Entry {
+    #[serde(with = "b64")]
+    key: Vec<u8>,
+    #[serde(with = "b64")]
+    value: Vec<u8>,
+    versionstamp: u64,
+}
+
+impl From<Entry> for KvEntry {
+    fn from(entry: Entry) -> Self {
+        Self {
+            key: entry.key,
+            value: entry.value,
+            versionstamp: entry.versionstamp,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Entries<T> {
+    entries: Vec<T>,
+}
+
+#[derive(Serialize)]
+struct Check<'a> {
+    key: Key<'a>,
+    versionstamp: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Mutation<'a> {
+    #[serde(rename_all = "camelCase")]
+    Set {
+        key: Key<'a>,
+        value: Key<'a>,
+        expire_at: Option<u64>,
+    },
+    Delete {
+        key: Key<'a>,
+    },
+}
+
+#[derive(Deserialize)]
+struct WriteResult {
+    versionstamp: Option<u64>,
+}
+
+pub struct RemoteKv {
+    client: reqwest::Client,
+    url: Url,
+    access_token: Option<String>,
+    namespace: String,
+}
+
+impl RemoteKv {
+    pub fn new(
+        client: reqwest::Client,
+        url: Url,
+        access_token: Option<String>,
+        namespace: String,
+    ) -> Self {
+        Self {
+            client,
+            url,
+            access_token,
+            namespace,
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        name: &str,
+        mut body: serde_json::Value,
+    ) -> Result<T, AnyError> {
+        body["namespace"] = self.namespace.clone().into();
+
+        let mut req = self
+            .client
+            .post(self.url.join(name)?)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?);
+
+        if let Some(token) = &self.access_token {
+            req = req.bearer_auth(token);
+        }
+
+        let res = req.send().await?;
+        let status = res.status();
+
+        if !status.is_success() {
+            return Err(custom_error(
+                "Http",
+                format!("the remote key-value store responded with {status} to {name}"),
+            ));
+        }
+
+        Ok(serde_json::from_slice(&res.bytes().await?)?)
+    }
+}
+
+#[async_trait(?Send)]
+impl KvBackend for RemoteKv {
+    async fn get(&self, keys: Vec<Vec<u8>>) -> Result<Vec<Option<KvEntry>>, AnyError> {
+        let keys = keys.iter().map(|it| Key(it)).collect::<Vec<_>>();
+        let res: Entries<Option<Entry>> = self
+            .call("get", serde_json::json!({ "keys": keys }))
+            .await?;
+
+        Ok(res
+            .entries
+            .into_iter()
+            .map(|it| it.map(KvEntry::from))
+            .collect())
+    }
+
+    async fn list(
+        &self,
+        start: Vec<u8>,
+        end: Vec<u8>,
+        limit: usize,
+        reverse: bool,
+    ) -> Result<Vec<KvEntry>, AnyError> {
+        let res: Entries<Entry> = self
+            .call(
+                "list",
+                serde_json::json!({
+                    "start": Key(&start),
+                    "end": Key(&end),
+                    "limit": limit,
+                    "reverse": reverse,
+                }),
+            )
+            .await?;
+
+        Ok(res.entries.into_iter().map(KvEntry::from).collect())
+    }
+
+    async fn atomic_write(&self, write: KvAtomicWrite) -> Result<Option<u64>, AnyError> {
+        let checks = write
+            .checks
+            .iter()
+            .map(|(key, versionstamp)| Check {
+                key: Key(key),
+                versionstamp: *versionstamp,
+            })
+            .collect::<Vec<_>>();
+
+        let mutations = write
+            .mutations
+            .iter()
+            .map(|it| match it {
+                KvMutation::Set {
+                    key,
+                    value,
+                    expire_at,
+                } => Mutation::Set {
+                    key: Key(key),
+                    value: Key(value),
+                    expire_at: *expire_at,
+                },
+
+                KvMutation::Delete { key } => Mutation::Delete { key: Key(key) },
+            })
+            .collect::<Vec<_>>();
+
+        let res: WriteResult = self
+            .call(
+                "atomic_write",
+                serde_json::json!({ "checks": checks, "mutations": mutations }),
+            )
+            .await?;
+
+        Ok(res.versionstamp)
+    }
+}
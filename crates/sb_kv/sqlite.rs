@@ -0,0 +1,290 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use deno_core::error::{custom_error, AnyError};
+use deno_webstorage::rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::backend::{now_ms, KvAtomicWrite, KvBackend, KvEntry, KvMutation};
+
+const INIT: &str = "
+PRAGMA journal_mode=WAL;
+PRAGMA busy_timeout=5000;
+CREATE TABLE IF NOT EXISTS kv (
+  key BLOB PRIMARY KEY,
+  value BLOB NOT NULL,
+  versionstamp INTEGER NOT NULL,
+  expire_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS kv_expire_at ON kv (expire_at) WHERE expire_at IS NOT NULL;
+CREATE TABLE IF NOT EXISTS kv_meta (
+  name TEXT PRIMARY KEY,
+  value INTEGER NOT NULL
+);
+INSERT OR IGNORE INTO kv_meta VALUES ('versionstamp', 0), ('size', 0);
+";
+
+/// A key-value store kept in a SQLite database, one per service.
+///
+/// NOTE: The workers of a service share the database file. Writes take an
+/// immediate transaction, so that concurrent atomic writes are serialized by
+/// SQLite rather than by this process. Waiting on the lock of the file, and
+/// the disk, would stall the event loop of the worker, so the queries run on
+/// the blocking pool.
+pub struct SqliteKv {
+    conn: Arc<Mutex<Connection>>,
+    max_size_bytes: Option<u64>,
+}
+
+impl SqliteKv {
+    pub fn open(path: &Path, max_size_bytes: Option<u64>) -> Result<Self, AnyError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        Self::new(Connection::open(path)?, max_size_bytes)
+    }
+
+    pub fn new(conn: Connection, max_size_bytes: Option<u64>) -> Result<Self, AnyError> {
+        conn.execute_batch(INIT)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            max_size_bytes,
+        })
+    }
+
+    /// Runs `f` with the connection on the blocking pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, AnyError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, AnyError> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
+    }
+
+    /// Applies `write` in a single immediate transaction.
+    fn write(
+        conn: &mut Connection,
+        write: &KvAtomicWrite,
+        max_size_bytes: Option<u64>,
+    ) -> Result<Option<u64>, AnyError> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let now = now_ms();
+
+        for (key, versionstamp) in &write.checks {
+            let current = Self::read_entry(&tx, key, now)?.map(|it| it.versionstamp);
+
+            if current != *versionstamp {
+                return Ok(None);
+            }
+        }
+
+        // Expired entries are dropped as writes come in, so they don't count
+        // towards the quota for long.
+        let expired: i64 = tx.query_row(
+            "SELECT COALESCE(SUM(LENGTH(key) + LENGTH(value)), 0) FROM kv WHERE expire_at <= ?1",
+            params![now],
+            |row| row.get(0),
+        )?;
+
+        tx.execute("DELETE FROM kv WHERE expire_at <= ?1", params![now])?;
+
+        let versionstamp: u64 = tx.query_row(
+            "UPDATE kv_meta SET value = value + 1 WHERE name = 'versionstamp' RETURNING value",
+            [],
+            |row| row.get(0),
+        )?;
+
+        let mut delta = -expired;
+
+        for mutation in &write.mutations {
+            let key = match mutation {
+                KvMutation::Set { key, .. } | KvMutation::Delete { key } => key,
+            };
+
+            let previous: Option<i64> = tx
+                .query_row(
+                    "SELECT LENGTH(key) + LENGTH(value) FROM kv WHERE key = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            delta -= previous.unwrap_or_default();
+
+            match mutation {
+                KvMutation::Set {
+                    key,
+                    value,
+                    expire_at,
+                } => {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO kv (key, value, versionstamp, expire_at) VALUES (?1, ?2, ?3, ?4)",
+                        params![key, value, versionstamp, expire_at],
+                    )?;
+
+                    delta += entry_size(key, value);
+                }
+
+                KvMutation::Delete { key } => {
+                    tx.execute("DELETE FROM kv WHERE key = ?1", params![key])?;
+                }
+            }
+        }
+
+        let size: i64 = tx.query_row(
+            "UPDATE kv_meta SET value = MAX(value + ?1, 0) WHERE name = 'size' RETURNING value",
+            params![delta],
+            |row| row.get(0),
+        )?;
+
+        if let Some(max) = max_size_bytes {
+            if delta > 0 && size as u64 > max {
+                return Err(custom_error(
+                    "QuotaExceededError",
+                    format!("the key-value store of the service exceeds its quota of {max} bytes"),
+                ));
+            }
+        }
+
+        tx.commit()?;
+        Ok(Some(versionstamp))
+    }
+
+    fn read_entry(conn: &Connection, key: &[u8], now: u64) -> Result<Option<KvEntry>, AnyError> {
+        Ok(conn
+            .query_row(
+                "SELECT value, versionstamp FROM kv WHERE key = ?1 AND (expire_at IS NULL OR expire_at > ?2)",
+                params![key, now],
+                |row| {
+                    Ok(KvEntry {
+                        key: key.to_vec(),
+                        value: row.get(0)?,
+                        versionstamp: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+}
+
+fn entry_size(key: &[u8], value: &[u8]) -> i64 {
+    (key.len() + value.len()) as i64
+}
+
+#[async_trait(?Send)]
+impl KvBackend for SqliteKv {
+    async fn get(&self, keys: Vec<Vec<u8>>) -> Result<Vec<Option<KvEntry>>, AnyError> {
+        let now = now_ms();
+
+        self.with_conn(move |conn| {
+            keys.iter()
+                .map(|key| Self::read_entry(conn, key, now))
+                .collect()
+        })
+        .await
+    }
+
+    async fn list(
+        &self,
+        start: Vec<u8>,
+        end: Vec<u8>,
+        limit: usize,
+        reverse: bool,
+    ) -> Result<Vec<KvEntry>, AnyError> {
+        let now = now_ms();
+
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare_cached(if reverse {
+                "SELECT key, value, versionstamp FROM kv WHERE key >= ?1 AND key < ?2 AND (expire_at IS NULL OR expire_at > ?3) ORDER BY key DESC LIMIT ?4"
+            } else {
+                "SELECT key, value, versionstamp FROM kv WHERE key >= ?1 AND key < ?2 AND (expire_at IS NULL OR expire_at > ?3) ORDER BY key ASC LIMIT ?4"
+            })?;
+
+            let rows = stmt.query_map(params![start, end, now, limit as i64], |row| {
+                Ok(KvEntry {
+                    key: row.get(0)?,
+                    value: row.get(1)?,
+                    versionstamp: row.get(2)?,
+                })
+            })?;
+
+            Ok(rows.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+
+    async fn atomic_write(&self, write: KvAtomicWrite) -> Result<Option<u64>, AnyError> {
+        let max_size_bytes = self.max_size_bytes;
+
+        self.with_conn(move |conn| Self::write(conn, &write, max_size_bytes))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn set(key: &[u8], value: &[u8], expire_at: Option<u64>) -> KvAtomicWrite {
+        KvAtomicWrite {
+            checks: vec![],
+            mutations: vec![KvMutation::Set {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                expire_at,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_kv() {
+        let kv = SqliteKv::new(Connection::open_in_memory().unwrap(), Some(16)).unwrap();
+
+        let v1 = kv
+            .atomic_write(set(b"a", b"1", None))
+            .await
+            .unwrap()
+            .unwrap();
+        let v2 = kv
+            .atomic_write(set(b"b", b"2", None))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(v2 > v1);
+        kv.atomic_write(set(b"c", b"3", Some(1))).await.unwrap();
+
+        let entries = kv.get(vec![b"a".to_vec(), b"c".to_vec()]).await.unwrap();
+        assert_eq!(entries[0].as_ref().unwrap().value, b"1");
+        assert_eq!(entries[1], None);
+
+        let listed = kv
+            .list(b"a".to_vec(), b"z".to_vec(), 10, true)
+            .await
+            .unwrap();
+        assert_eq!(
+            listed
+                .iter()
+                .map(|it| it.key.as_slice())
+                .collect::<Vec<_>>(),
+            [b"b", b"a"]
+        );
+
+        // A stale check fails the write.
+        let mut write = set(b"a", b"x", None);
+        write.checks.push((b"a".to_vec(), Some(v1 - 1)));
+        assert_eq!(kv.atomic_write(write).await.unwrap(), None);
+
+        let mut write = set(b"a", b"x", None);
+        write.checks.push((b"a".to_vec(), Some(v1)));
+        assert!(kv.atomic_write(write).await.unwrap().is_some());
+
+        // a and b take 4 bytes once c expired; 13 more exceed the quota.
+        assert!(kv.atomic_write(set(b"d", &[0; 12], None)).await.is_err());
+        assert!(kv.atomic_write(set(b"d", &[0; 9], None)).await.is_ok());
+    }
+}
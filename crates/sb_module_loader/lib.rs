@@ -3,11 +3,13 @@ use deno_npm::resolution::ValidSerializedNpmResolutionSnapshot;
 use sb_fs::virtual_fs::FileBackedVfs;
 use sb_fs::EszipStaticFiles;
 use sb_node::{NodeResolver, NpmResolver};
+use source_map::SourceMapStore;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 
 pub mod metadata;
+pub mod source_map;
 pub mod standalone;
 pub mod util;
 
@@ -20,4 +22,5 @@ pub struct RuntimeProviders {
     pub static_files: EszipStaticFiles,
     pub npm_snapshot: Option<ValidSerializedNpmResolutionSnapshot>,
     pub vfs_path: PathBuf,
+    pub source_maps: SourceMapStore,
 }
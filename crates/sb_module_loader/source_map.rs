@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use deno_core::url::Url;
+use deno_core::{ModuleSpecifier, SourceMapGetter};
+use eszip::deno_graph::source::RawDataUrl;
+
+static SOURCE_MAPPING_URL_PREFIX: &str = "//# sourceMappingURL=";
+
+/// Source maps of the modules a worker has loaded, which the stack traces of
+/// errors and `console.trace` are mapped back to the original source with.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMapStore(Arc<Mutex<HashMap<String, Arc<[u8]>>>>);
+
+impl SourceMapStore {
+    /// Records the source map of a module: the one emitted along with it, or
+    /// else the one its code links to, either inline or as a file next to it.
+    pub(crate) fn register(
+        &self,
+        specifier: &ModuleSpecifier,
+        code: &str,
+        maybe_emitted: Option<Arc<[u8]>>,
+    ) {
+        let maybe_source_map = maybe_emitted
+            .filter(|it| !it.is_empty())
+            .or_else(|| linked_source_map(specifier, code).map(Arc::from));
+
+        if let Some(source_map) = maybe_source_map {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(specifier.to_string(), source_map);
+        }
+    }
+}
+
+impl SourceMapGetter for SourceMapStore {
+    fn get_source_map(&self, file_name: &str) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().get(file_name).map(|it| it.to_vec())
+    }
+
+    fn get_source_line(&self, _file_name: &str, _line_number: usize) -> Option<String> {
+        None
+    }
+}
+
+fn linked_source_map(specifier: &ModuleSpecifier, code: &str) -> Option<Vec<u8>> {
+    let url = code
+        .trim_end()
+        .rsplit('\n')
+        .next()?
+        .strip_prefix(SOURCE_MAPPING_URL_PREFIX)?
+        .trim();
+
+    let url = specifier.join(url).ok()?;
+
+    match url.scheme() {
+        "data" => RawDataUrl::parse(&url)
+            .ok()?
+            .decode()
+            .ok()
+            .map(String::into_bytes),
+
+        // NOTE: Only services loaded from the file system have the files next
+        // to their modules around.
+        "file" => std::fs::read(adjacent_source_map_path(specifier, &url)?).ok(),
+        _ => None,
+    }
+}
+
+/// The map file a module links to, as long as it's a `.map` file in the
+/// directory of the module or below it. The link is up to the code, so it
+/// can't be trusted to stay inside the service.
+fn adjacent_source_map_path(specifier: &ModuleSpecifier, url: &Url) -> Option<PathBuf> {
+    let module_dir = specifier
+        .to_file_path()
+        .ok()?
+        .parent()?
+        .canonicalize()
+        .ok()?;
+    let path = url.to_file_path().ok()?.canonicalize().ok()?;
+
+    (path.starts_with(module_dir) && path.extension().is_some_and(|it| it == "map")).then_some(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_linked_source_map() {
+        let specifier = ModuleSpecifier::parse("file:///src/index.js").unwrap();
+        let code = concat!(
+            "console.log(1);\n",
+            "//# sourceMappingURL=data:application/json;base64,eyJ2ZXJzaW9uIjozfQ==\n"
+        );
+
+        assert_eq!(
+            linked_source_map(&specifier, code).as_deref(),
+            Some(br#"{"version":3}"#.as_slice())
+        );
+
+        assert_eq!(linked_source_map(&specifier, "console.log(1);"), None);
+        assert_eq!(
+            linked_source_map(&specifier, "//# sourceMappingURL=missing.js.map"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_linked_source_map_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = dir.path().join("hello");
+
+        std::fs::create_dir_all(&service).unwrap();
+        std::fs::write(service.join("index.js.map"), r#"{"version":3}"#).unwrap();
+        std::fs::write(dir.path().join("secrets.map"), "").unwrap();
+        std::fs::write(service.join("config.json"), "").unwrap();
+
+        let specifier = ModuleSpecifier::from_file_path(service.join("index.js")).unwrap();
+
+        assert_eq!(
+            linked_source_map(&specifier, "//# sourceMappingURL=index.js.map").as_deref(),
+            Some(br#"{"version":3}"#.as_slice())
+        );
+        assert_eq!(
+            linked_source_map(&specifier, "//# sourceMappingURL=../secrets.map"),
+            None
+        );
+        assert_eq!(
+            linked_source_map(&specifier, "//# sourceMappingURL=config.json"),
+            None
+        );
+        assert_eq!(
+            linked_source_map(&specifier, "//# sourceMappingURL=file:///etc/passwd"),
+            None
+        );
+    }
+}
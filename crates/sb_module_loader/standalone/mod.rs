@@ -1,26 +1,32 @@
 use crate::metadata::Metadata;
+use crate::source_map::SourceMapStore;
 use crate::standalone::standalone_module_loader::{EmbeddedModuleLoader, SharedModuleLoaderState};
 use crate::RuntimeProviders;
 use anyhow::{bail, Context};
+use deno_config::package_json::{PackageJson, PackageJsonRc};
 use deno_config::workspace::{PackageJsonDepResolution, WorkspaceResolver};
 use deno_core::error::AnyError;
 use deno_core::url::Url;
-use deno_core::{FastString, ModuleSpecifier};
+use deno_core::{serde_json, FastString, ModuleSpecifier};
 use deno_npm::npm_rc::ResolvedNpmRc;
 use deno_tls::rustls::RootCertStore;
 use deno_tls::RootCertStoreProvider;
 use futures_util::future::OptionFuture;
 use import_map::{parse_from_json, ImportMap};
 use sb_core::cache::caches::Caches;
+use sb_core::cache::code_cache::CodeCache;
 use sb_core::cache::deno_dir::DenoDirProvider;
 use sb_core::cache::node::NodeAnalysisCache;
 use sb_core::cache::CacheSetting;
 use sb_core::cert::{get_root_cert_store, CaData};
 use sb_core::node::CliCjsCodeAnalyzer;
 use sb_core::util::http_util::HttpClientProvider;
-use sb_eszip_shared::{AsyncEszipDataRead, SOURCE_CODE_ESZIP_KEY, VFS_ESZIP_KEY};
+use sb_eszip_shared::{
+    AsyncEszipDataRead, PACKAGE_JSON_ESZIP_KEY, SOURCE_CODE_ESZIP_KEY, VFS_ESZIP_KEY,
+};
 use sb_fs::file_system::DenoCompileFileSystem;
 use sb_fs::{extract_static_files_from_eszip, load_npm_vfs};
+use sb_graph::import_map::{is_inline_import_map, load_import_map};
 use sb_graph::resolver::{CjsResolutionStore, CliNodeResolver, NpmModuleLoader};
 use sb_graph::{eszip_migrate, payload_to_eszip, EszipPayloadKind, LazyLoadableEszip};
 use sb_node::analyze::NodeCodeTranslator;
@@ -105,6 +111,21 @@ where
     .map(|it| String::from_utf8_lossy(it.as_ref()).into_owned())
     .map(FastString::from);
 
+    let maybe_package_json = match OptionFuture::<_>::from(
+        eszip
+            .ensure_module(PACKAGE_JSON_ESZIP_KEY)
+            .map(|it| async move { it.source().await }),
+    )
+    .await
+    .flatten()
+    {
+        Some(source) => Some(PackageJsonRc::new(PackageJson::load_from_value(
+            base_dir_path.as_ref().join("package.json"),
+            serde_json::from_slice(&source).context("Failed to parse package.json.")?,
+        ))),
+        None => None,
+    };
+
     let snapshot = eszip.take_npm_snapshot();
     let static_files = extract_static_files_from_eszip(&eszip, base_dir_path).await;
     let vfs_root_dir_path = npm_cache_dir.root_dir().to_owned();
@@ -184,10 +205,16 @@ where
             },
             workspace_resolver: WorkspaceResolver::new_raw(
                 maybe_import_map,
-                vec![],
-                PackageJsonDepResolution::Disabled,
+                maybe_package_json.iter().cloned().collect(),
+                if maybe_package_json.is_some() {
+                    PackageJsonDepResolution::Enabled
+                } else {
+                    PackageJsonDepResolution::Disabled
+                },
             ),
             node_resolver: cli_node_resolver.clone(),
+            code_cache: Arc::new(CodeCache::new(cache_db.code_cache_db())),
+            source_maps: SourceMapStore::default(),
             npm_module_loader: Arc::new(NpmModuleLoader::new(
                 cjs_resolutions,
                 node_code_translator,
@@ -209,6 +236,7 @@ where
         static_files,
         npm_snapshot: snapshot,
         vfs_path: vfs_root_dir_path,
+        source_maps: module_loader_factory.shared.source_maps.clone(),
     })
 }
 
@@ -237,6 +265,11 @@ where
         if maybe_import_map.is_some() {
             break 'scope maybe_import_map;
         } else if let Some(import_map_path) = maybe_import_map_path {
+            // An inline import map takes the place of the one in the eszip.
+            if is_inline_import_map(&import_map_path) {
+                break 'scope load_import_map(Some(import_map_path))?;
+            }
+
             let import_map_url = Url::parse(import_map_path.as_str())?;
             if let Some(import_map_module) = eszip.ensure_import_map(import_map_url.as_str()) {
                 if let Some(source) = import_map_module.source().await {
@@ -11,19 +11,26 @@ use deno_core::error::AnyError;
 use deno_core::futures::FutureExt;
 use deno_core::ModuleType;
 use deno_core::ResolutionKind;
+use deno_core::SourceCodeCacheInfo;
 use deno_core::{ModuleLoader, ModuleSourceCode};
 use deno_core::{ModuleSpecifier, RequestedModuleType};
 use deno_semver::npm::NpmPackageReqReference;
 use eszip::deno_graph;
 use eszip::EszipRelativeFileBaseUrl;
+use sb_core::cache::code_cache::CodeCache;
+use sb_core::cache::common::FastInsecureHasher;
 use sb_eszip_shared::AsyncEszipDataRead;
 use sb_graph::resolver::CliNodeResolver;
 use sb_graph::resolver::NpmModuleLoader;
 use sb_graph::LazyLoadableEszip;
-use sb_node::NodeResolutionMode;
+use sb_node::{is_builtin_node_module, NodeResolutionMode};
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 use tracing::instrument;
 
+use crate::source_map::SourceMapStore;
 use crate::util::arc_u8_to_arc_str;
 
 pub struct WorkspaceEszipModule {
@@ -62,6 +69,8 @@ pub struct SharedModuleLoaderState {
     pub(crate) workspace_resolver: WorkspaceResolver,
     pub(crate) npm_module_loader: Arc<NpmModuleLoader>,
     pub(crate) node_resolver: Arc<CliNodeResolver>,
+    pub(crate) code_cache: Arc<CodeCache>,
+    pub(crate) source_maps: SourceMapStore,
 }
 
 #[derive(Clone)]
@@ -160,6 +169,12 @@ impl ModuleLoader for EmbeddedModuleLoader {
                     .handle_if_in_node_modules(specifier)
             }
             Err(err) if err.is_unmapped_bare_specifier() && referrer.scheme() == "file" => {
+                // NOTE: The graph resolves bare specifiers of Node built-ins
+                // (e.g. `buffer`) to their `node:` counterparts.
+                if is_builtin_node_module(specifier) {
+                    return Ok(ModuleSpecifier::parse(&format!("node:{}", specifier))?);
+                }
+
                 // todo(dsherret): return a better error from node resolution so that
                 // we can more easily tell whether to surface it or not
                 let node_result = self.shared.node_resolver.resolve(
@@ -237,6 +252,8 @@ impl ModuleLoader for EmbeddedModuleLoader {
         };
 
         let original_specifier = original_specifier.clone();
+        let code_cache = self.shared.code_cache.clone();
+        let source_maps = self.shared.source_maps.clone();
 
         deno_core::ModuleLoadResponse::Async(
             async move {
@@ -246,6 +263,9 @@ impl ModuleLoader for EmbeddedModuleLoader {
                 let code = arc_u8_to_arc_str(code)
                     .map_err(|_| type_error("Module source is not utf-8"))?;
                 let source_map = module.inner.source_map().await;
+
+                source_maps.register(&module.specifier, &code, source_map.clone());
+
                 let maybe_code_with_source_map = 'scope: {
                     if !include_source_map {
                         break 'scope code;
@@ -266,6 +286,21 @@ impl ModuleLoader for EmbeddedModuleLoader {
 
                     Arc::from(src)
                 };
+                let maybe_code_cache = match module.inner.kind {
+                    eszip::ModuleKind::JavaScript => {
+                        let hash = FastInsecureHasher::new_deno_versioned()
+                            .write_str(&maybe_code_with_source_map)
+                            .finish();
+
+                        Some(SourceCodeCacheInfo {
+                            hash,
+                            data: code_cache.get_sync(&module.specifier, hash).map(Cow::Owned),
+                        })
+                    }
+
+                    _ => None,
+                };
+
                 Ok(deno_core::ModuleSource::new_with_redirect(
                     match module.inner.kind {
                         eszip::ModuleKind::JavaScript => ModuleType::JavaScript,
@@ -280,10 +315,22 @@ impl ModuleLoader for EmbeddedModuleLoader {
                     ModuleSourceCode::String(maybe_code_with_source_map.into()),
                     &original_specifier,
                     &module.specifier,
-                    None,
+                    maybe_code_cache,
                 ))
             }
             .boxed_local(),
         )
     }
+
+    fn code_cache_ready(
+        &self,
+        specifier: ModuleSpecifier,
+        hash: u64,
+        code_cache: &[u8],
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.shared
+            .code_cache
+            .set_sync(&specifier, hash, code_cache);
+        std::future::ready(()).boxed_local()
+    }
 }
@@ -2,12 +2,15 @@ use anyhow::{anyhow, Error};
 use deno_config::JsxImportSourceConfig;
 use deno_core::FastString;
 use enum_as_inner::EnumAsInner;
-use event_worker::events::{UncaughtExceptionEvent, WorkerEventWithMetadata};
+use event_worker::events::{
+    MemoryPressure, ShutdownReason, UncaughtExceptionEvent, WorkerEventWithMetadata,
+};
 use hyper_v014::{Body, Request, Response};
 use sb_core::util::sync::AtomicFlag;
 use sb_core::{MetricSource, SharedMetricSource};
 use std::path::PathBuf;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::mpsc::unbounded_channel;
 use tokio::sync::{mpsc, oneshot, Mutex, Notify, OwnedSemaphorePermit};
@@ -15,11 +18,18 @@ use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use sb_graph::{DecoratorType, EszipPayloadKind};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::WorkerError;
+use crate::retirement::{RetirementBudgets, RetirementPolicy};
 
 #[derive(Debug, Clone)]
 pub enum WorkerExitStatus {
     Normal,
     WithUncaughtException(UncaughtExceptionEvent),
+    /// Shut down by its supervisor, e.g. for exceeding its resource limits.
+    Shutdown(ShutdownReason),
 }
 
 impl Default for WorkerExitStatus {
@@ -34,18 +44,98 @@ pub struct WorkerExit(Arc<Mutex<WorkerExitStatus>>);
 impl WorkerExit {
     pub async fn error(&self) -> Option<anyhow::Error> {
         match &*self.0.lock().await {
-            WorkerExitStatus::Normal => None,
+            WorkerExitStatus::Normal | WorkerExitStatus::Shutdown(_) => None,
             WorkerExitStatus::WithUncaughtException(UncaughtExceptionEvent {
                 exception, ..
             }) => Some(anyhow!("{exception}")),
         }
     }
 
+    /// The error a request cancelled by the supervisor of the worker fails
+    /// with.
+    pub async fn cancelled_error(&self) -> anyhow::Error {
+        let status = self.0.lock().await;
+        let reason = match &*status {
+            WorkerExitStatus::WithUncaughtException(UncaughtExceptionEvent {
+                exception, ..
+            }) => return anyhow!("{exception}"),
+            WorkerExitStatus::Shutdown(reason) => Some(*reason),
+            WorkerExitStatus::Normal => None,
+        };
+
+        anyhow!(WorkerError::RequestCancelledBySupervisor(reason))
+    }
+
     pub async fn set(&self, exit_status: WorkerExitStatus) {
         *self.0.lock().await = exit_status;
     }
 }
 
+/// Named heap sizing profiles for user workers.
+///
+/// A profile tunes the initial heap size (V8 derives the semi-space size of the
+/// young generation from it) together with the allowance given to the isolate
+/// once it reaches the near-heap-limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HeapProfile {
+    Small,
+    Medium,
+    Large,
+}
+
+impl HeapProfile {
+    pub fn initial_heap_size_mb(&self) -> u64 {
+        match self {
+            Self::Small => 4,
+            Self::Medium => 32,
+            Self::Large => 128,
+        }
+    }
+
+    pub fn near_heap_limit_multiplier(&self) -> u64 {
+        match self {
+            Self::Small => 5,
+            Self::Medium => 3,
+            Self::Large => 2,
+        }
+    }
+}
+
+impl std::str::FromStr for HeapProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "small" => Ok(Self::Small),
+            "medium" => Ok(Self::Medium),
+            "large" => Ok(Self::Large),
+            _ => Err(anyhow!("invalid heap profile: {}", s)),
+        }
+    }
+}
+
+/// Shared by a worker that can hibernate, its supervisor and the relays of its
+/// upgraded connections.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerHibernation {
+    /// Cancelled by the supervisor when the worker hibernates, which parks its
+    /// upgraded connections.
+    pub token: CancellationToken,
+    /// Upgraded connections of the worker that would be parked if it
+    /// hibernated. A worker without any is left to idle eviction instead.
+    pub resumable: Arc<AtomicUsize>,
+    /// Shared by the workers of the service and the connections they may
+    /// park. The pool forgets how to wake the service up once none is left.
+    pub service: Arc<()>,
+}
+
+impl WorkerHibernation {
+    pub fn has_resumable(&self) -> bool {
+        self.resumable.load(Ordering::Acquire) > 0
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UserWorkerRuntimeOpts {
     pub service_path: Option<String>,
@@ -54,38 +144,198 @@ pub struct UserWorkerRuntimeOpts {
     pub pool_msg_tx: Option<mpsc::UnboundedSender<UserWorkerMsgs>>,
     pub events_msg_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
     pub cancel: Option<CancellationToken>,
+    /// Shared with the pool, which reports it through introspection.
+    pub stats: Option<UserWorkerStats>,
 
     pub memory_limit_mb: u64,
     pub low_memory_multiplier: u64,
+    pub heap_profile: Option<HeapProfile>,
 
     pub worker_timeout_ms: u64, // wall clock limit
 
+    /// Fails the boot of the worker if loading its modules takes longer than
+    /// this. Zero leaves the boot unbounded.
+    pub boot_timeout_ms: u64,
+
+    /// Fail the boot of the worker once the sources of its module graph add
+    /// up to more than this, or once it loads more than this many remote
+    /// modules. Zero leaves them unbounded. Not applied to eszip bundles.
+    pub max_module_graph_size_mb: u64,
+    pub max_remote_modules: u64,
+
+    /// Once the wall clock limit is reached, keep the worker for up to this
+    /// long so in-flight requests can finish, without handing it new ones.
+    /// Zero terminates it right away.
+    pub wall_clock_grace_ms: u64,
+
     pub cpu_time_soft_limit_ms: u64,
     pub cpu_time_hard_limit_ms: u64,
 
+    /// Hibernate the worker once it has been idle for this long with nothing
+    /// but upgraded connections (e.g. WebSockets) open. The worker is
+    /// terminated and its connections are parked, until the next inbound
+    /// frame of each wakes a worker of the service up to pick it back up. Zero
+    /// disables hibernation.
+    ///
+    /// Only upgraded connections can be parked. A streamed response, such as
+    /// server-sent events, counts as a request in flight until it ends, and
+    /// keeps the worker from hibernating meanwhile.
+    pub hibernate_after_ms: u64,
+    /// Set by the pool for the workers it can wake up.
+    pub hibernation: Option<WorkerHibernation>,
+
+    /// Evict the worker after it has served no request for this long. Zero
+    /// keeps it until another limit retires it.
+    pub idle_timeout_ms: u64,
+
+    /// Retire the worker once it has been handed this many requests, so that
+    /// slow leaks in user code can't grow forever. Zero disables recycling.
+    pub max_requests: u64,
+
+    /// Service booted instead when booting the worker fails, e.g. the
+    /// previous version of the service.
+    pub fallback_service_path: Option<String>,
+
+    /// Caps the workers of the service below the pool-wide parallelism.
+    /// Requests beyond it wait for a worker like any other.
+    pub max_workers: Option<usize>,
+    /// Caps the requests the workers of the service handle at once. Requests
+    /// beyond it are rejected.
+    pub max_concurrent_requests: Option<usize>,
+
+    /// CPUs (e.g. `0-3,8`) the worker runs on, and the nice value of its
+    /// thread. Only honored with process isolation, where the worker has
+    /// threads of its own; in-process workers share the threads placed by
+    /// `EDGE_RUNTIME_WORKER_CPU_SET` and `EDGE_RUNTIME_WORKER_NICE`.
+    pub cpu_set: Option<String>,
+    pub nice: Option<i32>,
+    /// Flags V8 is started with in the process of the worker, on top of those
+    /// of the server. Only honored with process isolation, as V8 flags are
+    /// global to a process.
+    pub v8_flags: Vec<String>,
+
+    /// Tenant the service belongs to, for the per-tenant quotas of the pool.
+    /// Defaults to the directory containing the service.
+    pub tenant: Option<String>,
+
+    /// Budgets of the built-in retirement policies, consulted by the
+    /// supervisor after each request along with
+    /// [`Self::retirement_policies`].
+    pub retirement_budgets: RetirementBudgets,
+    /// Consulted by the supervisor after each request. See
+    /// [`crate::retirement`].
+    pub retirement_policies: Vec<Arc<dyn RetirementPolicy>>,
+
     pub force_create: bool,
     pub net_access_disabled: bool,
     pub allow_net: Option<Vec<String>>,
+    /// Paths the worker can read besides its own service. `None` allows all.
+    pub allow_read: Option<Vec<String>>,
+    /// Paths the worker can write. `None` allows all.
+    pub allow_write: Option<Vec<String>>,
+    /// Env vars the worker can read. `None` allows all.
+    pub allow_env: Option<Vec<String>>,
+    /// Env vars the worker is given, out of those it is created with. `None`
+    /// gives it all of them. The secrets of the service are given regardless.
+    pub env_allowlist: Option<Vec<String>>,
+    pub allow_hrtime: bool,
+    /// Whether the worker can load native libraries with `Deno.dlopen()`.
+    pub allow_ffi: bool,
+    /// Exceptions to `egress_deny`, as CIDRs or hostnames.
+    pub egress_allow: Vec<String>,
+    /// CIDRs and hostnames the worker can't reach. `None` denies
+    /// [`sb_core::egress::DEFAULT_EGRESS_DENY`].
+    pub egress_deny: Option<Vec<String>>,
+    /// Proxy `fetch()` goes through, instead of those of the environment.
+    pub http_proxy: Option<String>,
+    /// How long idle connections of `fetch()` are kept around for reuse. `0`
+    /// disables keep-alive. `None` keeps the default of 90 seconds.
+    pub fetch_keep_alive_ms: Option<u64>,
+    /// Caps the idle connections `fetch()` keeps per host. `None` keeps them
+    /// all.
+    pub fetch_max_idle_per_host: Option<usize>,
+    /// How long `fetch()` waits for a connection to be established. `None`
+    /// waits as long as the OS does.
+    pub fetch_connect_timeout_ms: Option<u64>,
+    /// Seeds the random number generator of `crypto`, which makes
+    /// `getRandomValues()` and `randomUUID()` deterministic. For tests and
+    /// replays only.
+    pub crypto_seed: Option<u64>,
+    /// Caps the size of the key-value store of the service. `0` disables it.
+    pub kv_max_size_mb: u64,
+    /// Whether `BroadcastChannel` of the service reaches the main worker, and
+    /// the other services sharing theirs, rather than its own workers only.
+    pub share_broadcast_channel: bool,
     pub custom_module_root: Option<String>,
     pub allow_remote_modules: bool,
 }
 
+impl UserWorkerRuntimeOpts {
+    /// Initial heap size in MiB, never exceeding the memory limit of the worker.
+    pub fn initial_heap_size_mb(&self) -> u64 {
+        self.heap_profile
+            .map(|it| it.initial_heap_size_mb())
+            .unwrap_or_default()
+            .min(self.memory_limit_mb)
+    }
+
+    pub fn near_heap_limit_multiplier(&self) -> u64 {
+        self.heap_profile
+            .map(|it| it.near_heap_limit_multiplier())
+            .unwrap_or(self.low_memory_multiplier)
+    }
+}
+
 impl Default for UserWorkerRuntimeOpts {
     fn default() -> UserWorkerRuntimeOpts {
         UserWorkerRuntimeOpts {
             memory_limit_mb: 512,
             worker_timeout_ms: 5 * 60 * 1000,
+            wall_clock_grace_ms: 0,
+            boot_timeout_ms: 0,
+            max_module_graph_size_mb: 0,
+            max_remote_modules: 0,
             low_memory_multiplier: 5,
+            heap_profile: None,
             cpu_time_soft_limit_ms: 50,
             cpu_time_hard_limit_ms: 100,
+            hibernate_after_ms: 0,
+            hibernation: None,
+            idle_timeout_ms: 0,
+            max_requests: 0,
 
+            fallback_service_path: None,
+            max_workers: None,
+            max_concurrent_requests: None,
+            cpu_set: None,
+            nice: None,
+            v8_flags: vec![],
+            tenant: None,
+            retirement_budgets: RetirementBudgets::default(),
+            retirement_policies: vec![],
             force_create: false,
             key: None,
             pool_msg_tx: None,
             events_msg_tx: None,
             cancel: None,
+            stats: None,
             net_access_disabled: false,
             allow_net: None,
+            allow_read: None,
+            allow_write: None,
+            allow_env: None,
+            env_allowlist: None,
+            allow_hrtime: false,
+            allow_ffi: false,
+            egress_allow: vec![],
+            egress_deny: None,
+            http_proxy: None,
+            fetch_keep_alive_ms: None,
+            fetch_max_idle_per_host: None,
+            fetch_connect_timeout_ms: None,
+            crypto_seed: None,
+            kv_max_size_mb: 0,
+            share_broadcast_channel: false,
             allow_remote_modules: true,
             custom_module_root: None,
             service_path: None,
@@ -101,10 +351,167 @@ pub struct UserWorkerProfile {
         mpsc::UnboundedSender<()>,
     ),
     pub service_path: String,
+    /// Tenant whose quotas the worker counts towards.
+    pub tenant: String,
     pub permit: Option<Arc<OwnedSemaphorePermit>>,
+    /// Pool-wide slot, if the number of user workers is capped.
+    pub slot: Option<Arc<WorkerSlot>>,
     pub cancel: CancellationToken,
+    /// Asks the supervisor to terminate the worker.
+    pub termination: CancellationToken,
     pub status: TimingStatus,
     pub exit: WorkerExit,
+    pub stats: UserWorkerStats,
+    pub limits: UserWorkerLimits,
+    pub max_requests: Option<usize>,
+    /// Time the request the worker was booted for waited for a slot.
+    pub queue_wait: Duration,
+    /// See [`UserWorkerRuntimeOpts::hibernation`].
+    pub hibernation: Option<WorkerHibernation>,
+}
+
+/// A pool-wide worker slot granted to a service. The slot is released once
+/// this is dropped, and no longer counts towards the slots the service holds.
+#[derive(Debug)]
+pub struct WorkerSlot {
+    _permit: OwnedSemaphorePermit,
+    held: Arc<AtomicUsize>,
+}
+
+impl WorkerSlot {
+    pub fn new(permit: OwnedSemaphorePermit, held: Arc<AtomicUsize>) -> Self {
+        held.fetch_add(1, Ordering::AcqRel);
+
+        Self {
+            _permit: permit,
+            held,
+        }
+    }
+}
+
+impl Drop for WorkerSlot {
+    fn drop(&mut self) {
+        self.held.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Lifecycle bookkeeping of a user worker, kept by the worker pool.
+#[derive(Debug, Clone)]
+pub struct UserWorkerStats {
+    pub booted_at: Instant,
+    pub requests_served: Arc<AtomicUsize>,
+    /// Requests whose response hasn't been fully consumed yet.
+    pub in_flight: Arc<AtomicUsize>,
+    /// Notified whenever the last in-flight request completes.
+    pub drained: Arc<Notify>,
+    /// Updated by the supervisor whenever the worker leaves the isolate.
+    pub cpu_time_ms: Arc<AtomicU64>,
+    pub used_heap_size: Arc<AtomicUsize>,
+}
+
+impl Default for UserWorkerStats {
+    fn default() -> Self {
+        Self {
+            booted_at: Instant::now(),
+            requests_served: Arc::default(),
+            in_flight: Arc::default(),
+            drained: Arc::default(),
+            cpu_time_ms: Arc::default(),
+            used_heap_size: Arc::default(),
+        }
+    }
+}
+
+impl UserWorkerStats {
+    pub fn uptime(&self) -> Duration {
+        self.booted_at.elapsed()
+    }
+
+    pub fn requests_served(&self) -> usize {
+        self.requests_served.load(Ordering::Acquire)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Resolves once no request is in flight on the worker.
+    pub async fn wait_drained(&self) {
+        loop {
+            // NOTE: `notify_waiters` wakes every `Notified` created before it
+            // is called, so no completion is missed between the check and the
+            // await.
+            let notified = self.drained.notified();
+
+            if self.in_flight() == 0 {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+
+    pub fn cpu_time_ms(&self) -> u64 {
+        self.cpu_time_ms.load(Ordering::Acquire)
+    }
+
+    pub fn used_heap_size(&self) -> usize {
+        self.used_heap_size.load(Ordering::Acquire)
+    }
+}
+
+/// Limits a user worker was booted with.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "WorkerLimits")]
+pub struct UserWorkerLimits {
+    pub memory_limit_mb: u64,
+    pub worker_timeout_ms: u64,
+    pub cpu_time_soft_limit_ms: u64,
+    pub cpu_time_hard_limit_ms: u64,
+    pub idle_timeout_ms: u64,
+    pub max_requests: u64,
+}
+
+impl From<&UserWorkerRuntimeOpts> for UserWorkerLimits {
+    fn from(value: &UserWorkerRuntimeOpts) -> Self {
+        Self {
+            memory_limit_mb: value.memory_limit_mb,
+            worker_timeout_ms: value.worker_timeout_ms,
+            cpu_time_soft_limit_ms: value.cpu_time_soft_limit_ms,
+            cpu_time_hard_limit_ms: value.cpu_time_hard_limit_ms,
+            idle_timeout_ms: value.idle_timeout_ms,
+            max_requests: value.max_requests,
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "WorkerState")]
+pub enum UserWorkerState {
+    Booting,
+    Idle,
+    Busy,
+    /// Retired from the pool; finishing the requests it's still handling.
+    Retiring,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "WorkerSnapshot")]
+pub struct UserWorkerSnapshot {
+    /// `None` for a worker that is still booting.
+    pub key: Option<Uuid>,
+    pub service_path: String,
+    pub state: UserWorkerState,
+    pub uptime_ms: u64,
+    pub requests_served: usize,
+    pub in_flight: usize,
+    pub cpu_time_ms: u64,
+    pub used_heap_size: usize,
+    /// `None` for a worker that is still booting.
+    pub limits: Option<UserWorkerLimits>,
 }
 
 #[derive(Debug, Clone)]
@@ -163,6 +570,18 @@ pub struct TimingStatus {
     pub is_retired: Arc<AtomicFlag>,
 }
 
+impl TimingStatus {
+    /// Counts a request handed to the worker, retiring the worker if it was
+    /// the last one it may serve.
+    pub fn demand(&self, max_requests: Option<usize>) {
+        let handed = self.demand.fetch_add(1, Ordering::Release) + 1;
+
+        if max_requests.is_some_and(|it| handed >= it) {
+            self.is_retired.raise();
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Timing {
     pub status: TimingStatus,
@@ -216,6 +635,39 @@ pub enum UserWorkerMsgs {
     ),
     Idle(Uuid),
     Shutdown(Uuid),
+    /// A file in a watched service directory has changed.
+    ServiceChanged(PathBuf),
+    /// Sends the path of each service whose `function.toml` may have changed
+    /// to the given channel, which is closed if services aren't watched.
+    WatchConfigs(mpsc::UnboundedSender<String>),
+    /// Retires the warm workers of a service, letting them finish in-flight
+    /// requests. Replies with the number of retired workers.
+    Drain(String, oneshot::Sender<usize>),
+    /// Replies with the state of every worker of the pool.
+    Snapshot(oneshot::Sender<Vec<UserWorkerSnapshot>>),
+    /// Retires the given workers and terminates each once its in-flight
+    /// requests have completed. Replies with the number of workers found.
+    Terminate(TerminateTarget, oneshot::Sender<usize>),
+    /// The memory pressure of the host exceeds the policy of the pool.
+    MemoryPressure(MemoryPressure),
+    /// Other services are waiting for a pool-wide slot while the given one
+    /// holds more than its share of them. Retires one of its workers.
+    Reclaim(String),
+    /// Sends the request picking back up a connection a hibernated worker of
+    /// the service parked to a worker of the service, booting one if needed.
+    Resume(
+        String,
+        Request<Body>,
+        oneshot::Sender<Result<SendRequestResult, Error>>,
+    ),
+}
+
+/// Workers targeted by [`UserWorkerMsgs::Terminate`].
+#[derive(Debug, Clone)]
+pub enum TerminateTarget {
+    Worker(Uuid),
+    /// Every worker of a service, including the retiring ones.
+    Service(String),
 }
 
 pub type SendRequestResult = (Response<Body>, mpsc::UnboundedSender<()>);
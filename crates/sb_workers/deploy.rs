@@ -0,0 +1,190 @@
+//! Blue/green deploys of services.
+//!
+//! A service directory managed this way is a symlink to one of its releases.
+//! Swapping the symlink is atomic, so requests never boot a worker from a
+//! half-written directory, and the workers of the previous release are drained
+//! rather than killed.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use deno_core::error::{custom_error, AnyError};
+use deno_core::{op2, OpState};
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+use crate::context::{TerminateTarget, UserWorkerMsgs};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapServiceResult {
+    previous_release: Option<String>,
+    release: String,
+    drained_workers: usize,
+}
+
+/// Points `service_path` at `release_path` by atomically replacing the symlink,
+/// and returns the release it pointed at before.
+fn swap_symlink(service_path: &Path, release_path: &Path) -> Result<Option<PathBuf>, AnyError> {
+    let previous_release = match std::fs::symlink_metadata(service_path) {
+        Ok(meta) if meta.file_type().is_symlink() => Some(std::fs::read_link(service_path)?),
+        Ok(_) => {
+            return Err(custom_error(
+                "AlreadyExists",
+                format!(
+                    "{} is not a symlink; move it into a release directory first",
+                    service_path.display()
+                ),
+            ))
+        }
+
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    let Some(file_name) = service_path.file_name() else {
+        return Err(custom_error("TypeError", "invalid service path"));
+    };
+
+    // NOTE: The temporary link must live in the same directory for the rename
+    // to be atomic.
+    let tmp_path = service_path.with_file_name(format!(
+        ".{}.swap-{}",
+        file_name.to_string_lossy(),
+        Uuid::new_v4()
+    ));
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(release_path, &tmp_path)?;
+
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_dir(release_path, &tmp_path)?;
+
+    if let Err(err) = std::fs::rename(&tmp_path, service_path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err.into());
+    }
+
+    Ok(previous_release)
+}
+
+#[op2(async)]
+#[serde]
+pub async fn op_user_worker_swap_service(
+    state: Rc<RefCell<OpState>>,
+    #[string] service_path: String,
+    #[string] release_path: String,
+) -> Result<SwapServiceResult, AnyError> {
+    let tx = {
+        let op_state = state.borrow();
+
+        crate::introspection::ensure_main_worker(&op_state)?;
+        op_state
+            .borrow::<mpsc::UnboundedSender<UserWorkerMsgs>>()
+            .clone()
+    };
+
+    let release = std::fs::canonicalize(&release_path)?;
+
+    if !release.is_dir() {
+        return Err(custom_error(
+            "NotFound",
+            format!("{} is not a directory", release.display()),
+        ));
+    }
+
+    let previous_release = swap_symlink(Path::new(&service_path), &release)?;
+    let (drained_tx, drained_rx) = oneshot::channel();
+
+    tx.send(UserWorkerMsgs::Drain(service_path, drained_tx))?;
+
+    Ok(SwapServiceResult {
+        previous_release: previous_release.map(|it| it.to_string_lossy().into_owned()),
+        release: release.to_string_lossy().into_owned(),
+        drained_workers: drained_rx.await.unwrap_or_default(),
+    })
+}
+
+async fn terminate(
+    state: Rc<RefCell<OpState>>,
+    target: TerminateTarget,
+) -> Result<usize, AnyError> {
+    let tx = {
+        let op_state = state.borrow();
+
+        crate::introspection::ensure_main_worker(&op_state)?;
+        op_state
+            .borrow::<mpsc::UnboundedSender<UserWorkerMsgs>>()
+            .clone()
+    };
+
+    let (terminated_tx, terminated_rx) = oneshot::channel();
+
+    tx.send(UserWorkerMsgs::Terminate(target, terminated_tx))?;
+
+    Ok(terminated_rx.await.unwrap_or_default())
+}
+
+/// Drains and terminates the worker with the given key. Returns the number of
+/// workers terminated.
+#[op2(async)]
+#[number]
+pub async fn op_user_worker_terminate_worker(
+    state: Rc<RefCell<OpState>>,
+    #[string] key: String,
+) -> Result<usize, AnyError> {
+    let key = Uuid::parse_str(&key)
+        .map_err(|_| custom_error("TypeError", format!("invalid worker key: {}", key)))?;
+
+    terminate(state, TerminateTarget::Worker(key)).await
+}
+
+/// Drains and terminates every worker of a service. Returns the number of
+/// workers terminated.
+#[op2(async)]
+#[number]
+pub async fn op_user_worker_terminate_service(
+    state: Rc<RefCell<OpState>>,
+    #[string] service_path: String,
+) -> Result<usize, AnyError> {
+    terminate(state, TerminateTarget::Service(service_path)).await
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_swap_symlink() {
+        let root = std::env::temp_dir().join(format!("swap-symlink-{}", Uuid::new_v4()));
+        let service_path = root.join("my-fn");
+        let (first, second) = (root.join("my-fn.releases/1"), root.join("my-fn.releases/2"));
+
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+
+        assert_eq!(swap_symlink(&service_path, &first).unwrap(), None);
+        assert_eq!(std::fs::read_link(&service_path).unwrap(), first);
+
+        assert_eq!(
+            swap_symlink(&service_path, &second).unwrap(),
+            Some(first.clone())
+        );
+        assert_eq!(std::fs::read_link(&service_path).unwrap(), second);
+
+        // No temporary link is left behind.
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 2);
+
+        // A plain directory is not replaced.
+        let plain = root.join("plain");
+
+        std::fs::create_dir(&plain).unwrap();
+        assert!(swap_symlink(&plain, &first).is_err());
+        assert!(plain.is_dir() && !plain.is_symlink());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
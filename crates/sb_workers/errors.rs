@@ -1,7 +1,24 @@
+use event_worker::events::ShutdownReason;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum WorkerError {
+    /// Carries the reason the worker was shut down for, if it is known.
     #[error("request has been cancelled by supervisor")]
-    RequestCancelledBySupervisor,
+    RequestCancelledBySupervisor(Option<ShutdownReason>),
+
+    #[error("service is failing repeatedly, retry after {retry_after_secs}s")]
+    CircuitOpen { retry_after_secs: u64 },
+
+    #[error("too many requests are waiting for the service")]
+    RequestQueueFull,
+
+    #[error("service is already handling {limit} concurrent requests")]
+    ConcurrencyLimitReached { limit: usize },
+
+    #[error("tenant exceeded its {resource} quota, retry after {retry_after_secs}s")]
+    TenantQuotaExceeded {
+        resource: &'static str,
+        retry_after_secs: u64,
+    },
 }
@@ -0,0 +1,313 @@
+//! Ops that let the main worker inspect the services on disk and the workers
+//! running them, so platform logic (routing, deploy verification, ...) can be
+//! written in TypeScript.
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use deno_cache_dir::HttpCache;
+use deno_core::error::{custom_error, AnyError};
+use deno_core::{op2, ModuleSpecifier, OpState};
+use sb_core::cache::deno_dir::DenoDir;
+use sb_core::cache::{GlobalHttpCache, RealDenoCacheEnv};
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::context::{UserWorkerMsgs, UserWorkerSnapshot};
+
+/// File names probed, in order, when looking for the entrypoint of a service.
+static ENTRYPOINT_CANDIDATES: &[&str] = &[
+    "index.ts",
+    "index.tsx",
+    "index.js",
+    "index.jsx",
+    "index.mjs",
+];
+
+pub static SERVICE_CONFIG_FILE: &str = "function.toml";
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceEntry {
+    name: String,
+    path: String,
+    entrypoint: Option<String>,
+    has_config: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleCacheStatus {
+    specifier: String,
+    cached: bool,
+}
+
+/// Only the main worker holds the sender to the worker pool.
+pub(crate) fn ensure_main_worker(state: &OpState) -> Result<(), AnyError> {
+    if state
+        .try_borrow::<mpsc::UnboundedSender<UserWorkerMsgs>>()
+        .is_none()
+    {
+        return Err(custom_error(
+            "PermissionDenied",
+            "only the main worker can introspect services",
+        ));
+    }
+
+    Ok(())
+}
+
+#[op2]
+#[serde]
+pub fn op_user_worker_list_services(
+    state: &mut OpState,
+    #[string] root: String,
+) -> Result<Vec<ServiceEntry>, AnyError> {
+    ensure_main_worker(state)?;
+
+    let mut services = vec![];
+
+    for entry in std::fs::read_dir(&root)? {
+        let path = entry?.path();
+
+        if !path.is_dir() {
+            continue;
+        }
+
+        let Some(name) = path.file_name().and_then(|it| it.to_str()) else {
+            continue;
+        };
+
+        services.push(ServiceEntry {
+            name: name.to_string(),
+            path: path.to_string_lossy().into_owned(),
+            entrypoint: ENTRYPOINT_CANDIDATES
+                .iter()
+                .find(|it| path.join(it).is_file())
+                .map(|it| it.to_string()),
+            has_config: path.join(SERVICE_CONFIG_FILE).is_file(),
+        });
+    }
+
+    services.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(services)
+}
+
+/// Returns the parsed `function.toml` of a service, or `null` if it has none.
+#[op2]
+#[serde]
+pub fn op_user_worker_service_config(
+    state: &mut OpState,
+    #[string] service_path: String,
+) -> Result<Option<toml::Table>, AnyError> {
+    ensure_main_worker(state)?;
+
+    let path = Path::new(&service_path).join(SERVICE_CONFIG_FILE);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    content.parse::<toml::Table>().map(Some).map_err(|err| {
+        custom_error(
+            "TypeError",
+            format!("failed to parse {}: {}", path.display(), err),
+        )
+    })
+}
+
+/// Reports whether each of the given module specifiers can be loaded without
+/// hitting the network.
+#[op2]
+#[serde]
+pub fn op_user_worker_module_cache_status(
+    state: &mut OpState,
+    #[serde] specifiers: Vec<String>,
+) -> Result<Vec<ModuleCacheStatus>, AnyError> {
+    ensure_main_worker(state)?;
+
+    let http_cache = GlobalHttpCache::new(DenoDir::new(None)?.deps_folder_path(), RealDenoCacheEnv);
+
+    Ok(specifiers
+        .into_iter()
+        .map(|specifier| {
+            let cached = match ModuleSpecifier::parse(&specifier) {
+                Ok(url) if url.scheme() == "file" => {
+                    url.to_file_path().is_ok_and(|it| it.is_file())
+                }
+
+                Ok(url) if matches!(url.scheme(), "http" | "https") => http_cache.contains(&url),
+                _ => false,
+            };
+
+            ModuleCacheStatus { specifier, cached }
+        })
+        .collect())
+}
+
+/// Lists the workers of the pool, including the ones that are still booting.
+#[op2(async)]
+#[serde]
+pub async fn op_user_worker_list_workers(
+    state: Rc<RefCell<OpState>>,
+) -> Result<Vec<UserWorkerSnapshot>, AnyError> {
+    let tx = {
+        let op_state = state.borrow();
+
+        ensure_main_worker(&op_state)?;
+        op_state
+            .borrow::<mpsc::UnboundedSender<UserWorkerMsgs>>()
+            .clone()
+    };
+
+    let (snapshot_tx, snapshot_rx) = oneshot::channel();
+
+    tx.send(UserWorkerMsgs::Snapshot(snapshot_tx))?;
+
+    Ok(snapshot_rx.await?)
+}
+
+/// The config changes the main worker waits on, see
+/// [`op_user_worker_next_config_change`].
+struct ConfigChanges(Rc<tokio::sync::Mutex<mpsc::UnboundedReceiver<String>>>);
+
+/// Waits for the `function.toml` of a service the pool watches to change, and
+/// returns the path of the service. Returns `null` if services aren't watched.
+#[op2(async)]
+#[string]
+pub async fn op_user_worker_next_config_change(
+    state: Rc<RefCell<OpState>>,
+) -> Result<Option<String>, AnyError> {
+    let changes = {
+        let mut op_state = state.borrow_mut();
+
+        ensure_main_worker(&op_state)?;
+
+        if !op_state.has::<ConfigChanges>() {
+            let (tx, rx) = mpsc::unbounded_channel();
+
+            op_state
+                .borrow::<mpsc::UnboundedSender<UserWorkerMsgs>>()
+                .send(UserWorkerMsgs::WatchConfigs(tx))?;
+            op_state.put(ConfigChanges(Rc::new(tokio::sync::Mutex::new(rx))));
+        }
+
+        op_state.borrow::<ConfigChanges>().0.clone()
+    };
+
+    let mut changes = changes.lock().await;
+
+    Ok(changes.recv().await)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use deno_core::serde_json::{self, Value};
+    use schemars::gen::{SchemaGenerator, SchemaSettings};
+
+    use super::*;
+
+    static OPENAPI_SPEC_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../examples/main/openapi.json"
+    );
+    static MAIN_WORKER: &str = include_str!("../../examples/main/index.ts");
+
+    fn spec() -> Value {
+        serde_json::from_str(&std::fs::read_to_string(OPENAPI_SPEC_PATH).unwrap()).unwrap()
+    }
+
+    /// The `/_...` paths the main worker routes on, skipping commented lines.
+    fn routed_paths() -> BTreeSet<String> {
+        MAIN_WORKER
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("//"))
+            .flat_map(|line| line.split('\'').skip(1).step_by(2))
+            .filter(|literal| literal.starts_with("/_"))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Compares the schemas generated from the types with the components of the
+    /// OpenAPI spec, or rewrites them with `UPDATE_SCHEMAS=1`.
+    fn check_openapi_schemas(add: impl FnOnce(&mut SchemaGenerator)) {
+        let mut gen = SchemaSettings::draft2019_09()
+            .with(|it| it.definitions_path = "#/components/schemas/".to_string())
+            .into_generator();
+
+        add(&mut gen);
+
+        let mut spec = spec();
+        let components = spec["components"]["schemas"].as_object_mut().unwrap();
+        let mut outdated = vec![];
+
+        for (name, schema) in gen.take_definitions() {
+            let schema = serde_json::to_value(schema).unwrap();
+
+            if components.get(&name) != Some(&schema) {
+                outdated.push(name.clone());
+                components.insert(name, schema);
+            }
+        }
+
+        if std::env::var_os("UPDATE_SCHEMAS").is_some() {
+            std::fs::write(
+                OPENAPI_SPEC_PATH,
+                serde_json::to_string_pretty(&spec).unwrap() + "\n",
+            )
+            .unwrap();
+
+            return;
+        }
+
+        assert!(
+            outdated.is_empty(),
+            "{:?} in openapi.json are out of date, run the tests with `UPDATE_SCHEMAS=1`",
+            outdated
+        );
+    }
+
+    #[test]
+    fn test_openapi_spec_covers_the_routes_of_the_main_worker() {
+        let spec = spec();
+        let documented = spec["paths"].as_object().unwrap();
+        let routed = routed_paths();
+
+        // A route matched by prefix (e.g. `/_internal/admin/workers/`) is
+        // documented with a path parameter after it.
+        let is_documented = |route: &str| {
+            documented
+                .keys()
+                .any(|path| path == route || (route.ends_with('/') && path.starts_with(route)))
+        };
+
+        let is_routed = |path: &str| {
+            routed.iter().any(|route| {
+                route == path || (route.ends_with('/') && path.starts_with(route.as_str()))
+            })
+        };
+
+        for route in &routed {
+            assert!(is_documented(route), "{route} is missing from openapi.json");
+        }
+
+        for path in documented.keys() {
+            assert!(
+                is_routed(path),
+                "{path} is no longer routed by the main worker"
+            );
+        }
+    }
+
+    #[test]
+    fn test_openapi_spec_matches_the_worker_snapshot() {
+        check_openapi_schemas(|gen| {
+            gen.subschema_for::<UserWorkerSnapshot>();
+        });
+    }
+}
@@ -1,9 +1,13 @@
 pub mod context;
+pub mod deploy;
 pub mod errors;
+pub mod introspection;
+pub mod retirement;
+pub mod shared_buffers;
 
 use crate::context::{
-    CreateUserWorkerResult, UserWorkerMsgs, UserWorkerRuntimeOpts, WorkerContextInitOpts,
-    WorkerRuntimeOpts,
+    CreateUserWorkerResult, HeapProfile, UserWorkerMsgs, UserWorkerRuntimeOpts,
+    WorkerContextInitOpts, WorkerRuntimeOpts,
 };
 use anyhow::Error;
 use context::SendRequestResult;
@@ -24,7 +28,7 @@ use hyper_v014::header::{HeaderName, HeaderValue, CONTENT_LENGTH};
 use hyper_v014::upgrade::OnUpgrade;
 use hyper_v014::{Body, Method, Request};
 use log::error;
-use sb_core::conn_sync::ConnWatcher;
+use sb_core::conn_sync::{ConnWatcher, CronRequest, EarlyHintsSender, ResponseTrailers};
 use sb_graph::{DecoratorType, EszipPayloadKind};
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
@@ -43,6 +47,16 @@ deno_core::extension!(
         op_user_worker_create,
         op_user_worker_fetch_build,
         op_user_worker_fetch_send,
+        introspection::op_user_worker_list_services,
+        introspection::op_user_worker_service_config,
+        introspection::op_user_worker_module_cache_status,
+        introspection::op_user_worker_list_workers,
+        introspection::op_user_worker_next_config_change,
+        deploy::op_user_worker_swap_service,
+        deploy::op_user_worker_terminate_worker,
+        deploy::op_user_worker_terminate_service,
+        shared_buffers::op_user_worker_share_buffer,
+        shared_buffers::op_user_worker_shared_buffer,
     ],
     esm_entry_point = "ext:sb_user_workers/user_workers.js",
     esm = ["user_workers.js",]
@@ -64,9 +78,31 @@ pub struct UserWorkerCreateOptions {
     import_map_path: Option<String>,
     env_vars: Vec<(String, String)>,
     force_create: bool,
+    fallback_service_path: Option<String>,
+    max_workers: Option<usize>,
+    max_concurrent_requests: Option<usize>,
+    tenant: Option<String>,
+    cpu_set: Option<String>,
+    nice: Option<i32>,
+    v8_flags: Vec<String>,
     allow_remote_modules: bool,
     net_access_disabled: bool,
     allow_net: Option<Vec<String>>,
+    allow_read: Option<Vec<String>>,
+    allow_write: Option<Vec<String>>,
+    allow_env: Option<Vec<String>>,
+    env_allowlist: Option<Vec<String>>,
+    allow_hrtime: bool,
+    allow_ffi: bool,
+    egress_allow: Vec<String>,
+    egress_deny: Option<Vec<String>>,
+    http_proxy: Option<String>,
+    fetch_keep_alive_ms: Option<u64>,
+    fetch_max_idle_per_host: Option<usize>,
+    fetch_connect_timeout_ms: Option<u64>,
+    crypto_seed: Option<u64>,
+    kv_max_size_mb: u64,
+    share_broadcast_channel: bool,
     custom_module_root: Option<String>,
     maybe_eszip: Option<JsBuffer>,
     maybe_entrypoint: Option<String>,
@@ -74,9 +110,17 @@ pub struct UserWorkerCreateOptions {
 
     memory_limit_mb: u64,
     low_memory_multiplier: u64,
+    heap_profile: Option<HeapProfile>,
     worker_timeout_ms: u64,
+    wall_clock_grace_ms: u64,
+    boot_timeout_ms: u64,
+    max_module_graph_size_mb: u64,
+    max_remote_modules: u64,
     cpu_time_soft_limit_ms: u64,
     cpu_time_hard_limit_ms: u64,
+    hibernate_after_ms: u64,
+    idle_timeout_ms: u64,
+    max_requests: u64,
 
     jsx_import_source_config: Option<JsxImportBaseConfig>,
     decorator_type: Option<DecoratorType>,
@@ -99,8 +143,30 @@ pub async fn op_user_worker_create(
             import_map_path,
             env_vars,
             force_create,
+            fallback_service_path,
+            max_workers,
+            max_concurrent_requests,
+            tenant,
+            cpu_set,
+            nice,
+            v8_flags,
             net_access_disabled,
             allow_net,
+            allow_read,
+            allow_write,
+            allow_env,
+            env_allowlist,
+            allow_hrtime,
+            allow_ffi,
+            egress_allow,
+            egress_deny,
+            http_proxy,
+            fetch_keep_alive_ms,
+            fetch_max_idle_per_host,
+            fetch_connect_timeout_ms,
+            crypto_seed,
+            kv_max_size_mb,
+            share_broadcast_channel,
             allow_remote_modules,
             custom_module_root,
             maybe_eszip,
@@ -109,9 +175,17 @@ pub async fn op_user_worker_create(
 
             memory_limit_mb,
             low_memory_multiplier,
+            heap_profile,
             worker_timeout_ms,
+            wall_clock_grace_ms,
+            boot_timeout_ms,
+            max_module_graph_size_mb,
+            max_remote_modules,
             cpu_time_soft_limit_ms,
             cpu_time_hard_limit_ms,
+            hibernate_after_ms,
+            idle_timeout_ms,
+            max_requests,
             jsx_import_source_config,
             decorator_type: maybe_decorator,
         } = opts;
@@ -151,12 +225,46 @@ pub async fn op_user_worker_create(
             conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
                 memory_limit_mb,
                 low_memory_multiplier,
+                heap_profile,
                 worker_timeout_ms,
+                wall_clock_grace_ms,
+                boot_timeout_ms,
+                max_module_graph_size_mb,
+                max_remote_modules,
                 cpu_time_soft_limit_ms,
                 cpu_time_hard_limit_ms,
+                hibernate_after_ms,
+                hibernation: None,
+                idle_timeout_ms,
+                max_requests,
+                fallback_service_path,
+                max_workers,
+                max_concurrent_requests,
+                tenant,
+                cpu_set,
+                nice,
+                v8_flags,
+                retirement_budgets: Default::default(),
+                retirement_policies: vec![],
+                stats: None,
                 force_create,
                 net_access_disabled,
                 allow_net,
+                allow_read,
+                allow_write,
+                allow_env,
+                env_allowlist,
+                allow_hrtime,
+                allow_ffi,
+                egress_allow,
+                egress_deny,
+                http_proxy,
+                fetch_keep_alive_ms,
+                fetch_max_idle_per_host,
+                fetch_connect_timeout_ms,
+                crypto_seed,
+                kv_max_size_mb,
+                share_broadcast_channel,
                 allow_remote_modules,
                 custom_module_root,
                 key: None,
@@ -184,7 +292,21 @@ pub async fn op_user_worker_create(
     // channel returns a Result<T, E>, we need to unwrap it first;
     let result = result.unwrap();
     match result {
-        Err(e) => Err(custom_error("InvalidWorkerCreation", e.to_string())),
+        Err(e) => match e.downcast_ref() {
+            Some(err @ WorkerError::CircuitOpen { .. }) => {
+                Err(custom_error("WorkerCircuitOpen", err.to_string()))
+            }
+
+            Some(err @ WorkerError::RequestQueueFull) => {
+                Err(custom_error("WorkerQueueFull", err.to_string()))
+            }
+
+            Some(err @ WorkerError::TenantQuotaExceeded { .. }) => {
+                Err(custom_error("WorkerTenantQuotaExceeded", err.to_string()))
+            }
+
+            _ => Err(custom_error("InvalidWorkerCreation", e.to_string())),
+        },
         Ok(res) => Ok(res.key.to_string()),
     }
 }
@@ -196,6 +318,8 @@ pub struct UserWorkerRequest {
     url: String,
     headers: Vec<(String, String)>,
     has_body: bool,
+    #[serde(default)]
+    cron: Option<CronRequest>,
 }
 
 #[derive(Serialize)]
@@ -289,6 +413,9 @@ struct UserWorkerResponseBodyResource {
     req_end_tx: mpsc::UnboundedSender<()>,
     cancel: CancelHandle,
     conn_token: Option<CancellationToken>,
+    /// Body of the request still streaming into the worker, which is of no
+    /// use once the response is done with.
+    request_body: Option<Rc<UserWorkerRequestBodyResource>>,
 }
 
 impl Resource for UserWorkerResponseBodyResource {
@@ -332,6 +459,10 @@ impl Resource for UserWorkerResponseBodyResource {
     fn close(self: Rc<Self>) {
         self.cancel.cancel();
 
+        if let Some(request_body) = self.request_body.as_ref() {
+            request_body.cancel.cancel();
+        }
+
         let _ = self.req_end_tx.send(());
         let Ok(this) = Rc::try_unwrap(self) else {
             return;
@@ -390,6 +521,10 @@ pub fn op_user_worker_fetch_build(
         }
     }
 
+    if let Some(cron) = req.cron {
+        builder = builder.extension(cron);
+    }
+
     let req = builder.body(body)?;
     let request_rid = state.resource_table.add(UserWorkerRequestResource(req));
 
@@ -406,10 +541,10 @@ pub async fn op_user_worker_fetch_send(
     #[string] key: String,
     #[smi] rid: ResourceId,
     #[smi] request_body_rid: Option<ResourceId>,
-    #[smi] stream_rid: ResourceId,
+    #[smi] stream_rid: Option<ResourceId>,
     #[smi] watcher_rid: Option<ResourceId>,
 ) -> Result<UserWorkerResponse, AnyError> {
-    let (tx, req) = {
+    let (tx, mut req) = {
         let (tx, mut req) = {
             let mut op_state = state.borrow_mut();
             let tx = op_state
@@ -427,7 +562,8 @@ pub async fn op_user_worker_fetch_send(
             (tx, req)
         };
 
-        if get_upgrade_type(req.0.headers()).is_some() {
+        if let Some(stream_rid) = stream_rid.filter(|_| get_upgrade_type(req.0.headers()).is_some())
+        {
             let req_stream = state
                 .borrow_mut()
                 .resource_table
@@ -458,16 +594,24 @@ pub async fn op_user_worker_fetch_send(
         })
         .map(Rc::try_unwrap);
 
-    let conn_token = match conn_token {
-        Some(Ok(it)) => it.get(),
+    let (conn_token, trailers, early_hints) = match conn_token {
+        Some(Ok(it)) => (it.get(), Some(it.1.trailers), it.1.early_hints),
         Some(Err(_)) => {
             error!("failed to unwrap connection watcher");
-            None
+            (None, None, None)
         }
 
-        None => None,
+        None => (None, None, None),
     };
 
+    // NOTE: The user worker answers the request of the main worker, so it's
+    // the one that can send its client hints.
+    if let Some(early_hints) = early_hints {
+        req.0
+            .extensions_mut()
+            .insert::<EarlyHintsSender>(early_hints);
+    }
+
     tx.send(UserWorkerMsgs::SendRequest(
         key_parsed,
         req.0,
@@ -475,6 +619,14 @@ pub async fn op_user_worker_fetch_send(
         conn_token.clone(),
     ))?;
 
+    let request_body = request_body_rid.and_then(|rid| {
+        state
+            .borrow()
+            .resource_table
+            .get::<UserWorkerRequestBodyResource>(rid)
+            .ok()
+    });
+
     let request_body_guard = scopeguard::guard(request_body_rid, |rid| {
         if let Some(rid) = rid {
             match state
@@ -497,18 +649,33 @@ pub async fn op_user_worker_fetch_send(
             error!("user worker failed to respond: {}", err);
 
             match err.downcast_ref() {
-                Some(err @ WorkerError::RequestCancelledBySupervisor) => {
-                    return Err(custom_error("WorkerRequestCancelled", err.to_string()));
+                // NOTE: The reason is stripped off the message, and set as the
+                // `shutdownReason` of the error in `UserWorker.fetch`.
+                Some(err @ WorkerError::RequestCancelledBySupervisor(reason)) => {
+                    let msg = match reason {
+                        Some(reason) => format!("{err} ({reason:?})"),
+                        None => err.to_string(),
+                    };
+
+                    return Err(custom_error("WorkerRequestCancelled", msg));
                 }
 
-                None => {
+                Some(err @ WorkerError::ConcurrencyLimitReached { .. }) => {
+                    return Err(custom_error("WorkerConcurrencyLimit", err.to_string()));
+                }
+
+                _ => {
                     return Err(custom_error("InvalidWorkerResponse", err.to_string()));
                 }
             }
         }
     };
 
-    drop(request_body_guard);
+    // NOTE: The body keeps streaming into the worker after it has responded,
+    // so that it can read the rest of it while streaming its response. It is
+    // cancelled if the worker fails to respond, or else once the response
+    // body is closed (see `UserWorkerResponseBodyResource`).
+    scopeguard::ScopeGuard::into_inner(request_body_guard);
 
     let mut headers = vec![];
     for (key, value) in res.headers().iter() {
@@ -527,7 +694,7 @@ pub async fn op_user_worker_fetch_send(
 
     let size = HttpBody::size_hint(res.body()).exact();
     let stream: BytesStream = Box::pin(
-        res.into_body()
+        body_with_trailers(res.into_body(), trailers)
             .map(|r| r.map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))),
     );
 
@@ -539,6 +706,7 @@ pub async fn op_user_worker_fetch_send(
         size,
         req_end_tx,
         conn_token,
+        request_body,
     });
 
     let response = UserWorkerResponse {
@@ -552,6 +720,40 @@ pub async fn op_user_worker_fetch_send(
     Ok(response)
 }
 
+/// Streams the data of `body`. Its trailers are set as those of the response to
+/// the request of the main worker once the data is done, so that they are sent
+/// along if the main worker responds with this body.
+fn body_with_trailers(
+    body: Body,
+    trailers: Option<ResponseTrailers>,
+) -> impl Stream<Item = Result<bytes::Bytes, hyper_v014::Error>> {
+    deno_core::futures::stream::unfold(Some(body), move |body| {
+        let trailers = trailers.clone();
+
+        async move {
+            let mut body = body?;
+
+            match body.data().await {
+                Some(chunk) => Some((chunk, Some(body))),
+                None => {
+                    match body.trailers().await {
+                        Ok(Some(map)) => {
+                            if let Some(trailers) = trailers {
+                                trailers.set(map);
+                            }
+                        }
+
+                        Ok(None) => {}
+                        Err(err) => return Some((Err(err), None)),
+                    }
+
+                    None
+                }
+            }
+        }
+    })
+}
+
 /// Wraps a [`mpsc::Receiver`] in a [`Stream`] that can be used as a Hyper [`Body`].
 pub struct BodyStream(pub mpsc::Receiver<Result<bytes::Bytes, Error>>);
 
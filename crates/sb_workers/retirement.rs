@@ -0,0 +1,200 @@
+//! Policies deciding when a user worker is retired.
+//!
+//! The supervisor consults the policies of a worker whenever it completes a
+//! request. Once a policy asks for retirement, the pool stops handing the
+//! worker new requests, and it's terminated after the last in-flight one with
+//! the [`ShutdownReason`] returned by the policy.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use event_worker::events::ShutdownReason;
+use serde::{Deserialize, Serialize};
+
+/// What the supervisor knows about a worker when it consults the policies.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WorkerUsage {
+    pub uptime: Duration,
+    pub cpu_time_ms: u64,
+    pub requests_served: usize,
+    pub used_heap_size: usize,
+}
+
+pub trait RetirementPolicy: Debug + Send + Sync {
+    /// Returns the reason to retire the worker for, if it should be.
+    fn should_retire(&self, usage: &WorkerUsage) -> Option<ShutdownReason>;
+}
+
+/// Retires workers once they have been up for the given duration.
+#[derive(Debug, Clone, Copy)]
+pub struct WallClockBudget(pub Duration);
+
+impl RetirementPolicy for WallClockBudget {
+    fn should_retire(&self, usage: &WorkerUsage) -> Option<ShutdownReason> {
+        (usage.uptime >= self.0).then_some(ShutdownReason::WallClockTime)
+    }
+}
+
+/// Retires workers once they have used the given CPU time, in milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuBudget(pub u64);
+
+impl RetirementPolicy for CpuBudget {
+    fn should_retire(&self, usage: &WorkerUsage) -> Option<ShutdownReason> {
+        (usage.cpu_time_ms >= self.0).then_some(ShutdownReason::CPUTime)
+    }
+}
+
+/// Retires workers whose heap has grown to the given size, in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryBudget(pub usize);
+
+impl RetirementPolicy for MemoryBudget {
+    fn should_retire(&self, usage: &WorkerUsage) -> Option<ShutdownReason> {
+        (usage.used_heap_size >= self.0).then_some(ShutdownReason::Memory)
+    }
+}
+
+/// Retires workers once they have served the given number of requests.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestBudget(pub usize);
+
+impl RetirementPolicy for RequestBudget {
+    fn should_retire(&self, usage: &WorkerUsage) -> Option<ShutdownReason> {
+        (usage.requests_served >= self.0).then_some(ShutdownReason::MaxRequests)
+    }
+}
+
+/// Budgets of the built-in policies, set by the operator for every worker.
+/// Unlike custom policies, they cross the process isolation boundary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetirementBudgets {
+    pub wall_clock_ms: Option<u64>,
+    pub cpu_time_ms: Option<u64>,
+    pub heap_size_bytes: Option<usize>,
+    pub requests: Option<usize>,
+}
+
+impl RetirementBudgets {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl RetirementPolicy for RetirementBudgets {
+    fn should_retire(&self, usage: &WorkerUsage) -> Option<ShutdownReason> {
+        self.wall_clock_ms
+            .and_then(|it| WallClockBudget(Duration::from_millis(it)).should_retire(usage))
+            .or_else(|| {
+                self.cpu_time_ms
+                    .and_then(|it| CpuBudget(it).should_retire(usage))
+            })
+            .or_else(|| {
+                self.heap_size_bytes
+                    .and_then(|it| MemoryBudget(it).should_retire(usage))
+            })
+            .or_else(|| {
+                self.requests
+                    .and_then(|it| RequestBudget(it).should_retire(usage))
+            })
+    }
+}
+
+/// Returns the reason of the first policy asking to retire the worker, the
+/// built-in ones first.
+pub fn check_retirement(
+    budgets: &RetirementBudgets,
+    policies: &[Arc<dyn RetirementPolicy>],
+    usage: &WorkerUsage,
+) -> Option<ShutdownReason> {
+    budgets
+        .should_retire(usage)
+        .or_else(|| policies.iter().find_map(|it| it.should_retire(usage)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_retirement() {
+        let policies: Vec<Arc<dyn RetirementPolicy>> =
+            vec![Arc::new(RequestBudget(10)), Arc::new(MemoryBudget(1024))];
+
+        let usage = WorkerUsage {
+            requests_served: 3,
+            used_heap_size: 512,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            check_retirement(&RetirementBudgets::default(), &policies, &usage),
+            None
+        );
+        assert_eq!(
+            check_retirement(
+                &RetirementBudgets::default(),
+                &policies,
+                &WorkerUsage {
+                    used_heap_size: 2048,
+                    ..usage
+                }
+            ),
+            Some(ShutdownReason::Memory)
+        );
+        assert_eq!(
+            check_retirement(
+                &RetirementBudgets::default(),
+                &policies,
+                &WorkerUsage {
+                    requests_served: 10,
+                    used_heap_size: 2048,
+                    ..usage
+                }
+            ),
+            Some(ShutdownReason::MaxRequests)
+        );
+    }
+
+    #[test]
+    fn test_retirement_budgets() {
+        let budgets = RetirementBudgets {
+            wall_clock_ms: Some(60_000),
+            requests: Some(2),
+            ..Default::default()
+        };
+
+        let usage = WorkerUsage {
+            uptime: Duration::from_secs(1),
+            requests_served: 1,
+            ..Default::default()
+        };
+
+        assert!(!budgets.is_empty());
+        assert_eq!(check_retirement(&budgets, &[], &usage), None);
+        assert_eq!(
+            check_retirement(
+                &budgets,
+                &[],
+                &WorkerUsage {
+                    requests_served: 2,
+                    ..usage
+                }
+            ),
+            Some(ShutdownReason::MaxRequests)
+        );
+        assert_eq!(
+            check_retirement(
+                &budgets,
+                &[Arc::new(MemoryBudget(0))],
+                &WorkerUsage {
+                    uptime: Duration::from_secs(60),
+                    ..usage
+                }
+            ),
+            Some(ShutdownReason::WallClockTime)
+        );
+    }
+}
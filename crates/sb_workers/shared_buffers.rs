@@ -0,0 +1,174 @@
+//! `SharedArrayBuffer`s the main worker shares with user workers. A user worker
+//! gets a buffer backed by the same memory, so large payloads aren't copied.
+//!
+//! Only the workers running in this process can be handed a buffer, so a
+//! worker run in process isolation can't be, and sharing a buffer with it
+//! fails.
+//!
+//! NOTE: The memory of a shared buffer isn't counted against the memory limit
+//! of the user workers it is shared with.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use deno_core::error::{custom_error, type_error, AnyError};
+use deno_core::{op2, v8, OpState};
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+type SharedBuffers = HashMap<String, v8::SharedRef<v8::BackingStore>>;
+
+/// Buffers shared with each user worker running in this process, dropped
+/// along with the worker.
+static SHARED_BUFFERS: Lazy<Mutex<HashMap<Uuid, SharedBuffers>>> = Lazy::new(Mutex::default);
+
+/// Key of a user worker, put into its `OpState`.
+#[derive(Debug, Clone, Copy)]
+pub struct UserWorkerKey(pub Uuid);
+
+/// Lets buffers be shared with the user worker of `key`, until
+/// [`drop_shared_buffers`] is called for it.
+pub fn register_shared_buffers(key: Uuid) {
+    SHARED_BUFFERS.lock().unwrap().entry(key).or_default();
+}
+
+/// Drops the buffers shared with the user worker of `key`.
+pub fn drop_shared_buffers(key: &Uuid) {
+    SHARED_BUFFERS.lock().unwrap().remove(key);
+}
+
+#[op2]
+pub fn op_user_worker_share_buffer(
+    state: &mut OpState,
+    #[string] key: String,
+    #[string] name: String,
+    buffer: v8::Local<v8::Value>,
+) -> Result<(), AnyError> {
+    crate::introspection::ensure_main_worker(state)?;
+
+    let key = Uuid::parse_str(&key)?;
+    let buffer = v8::Local::<v8::SharedArrayBuffer>::try_from(buffer)
+        .map_err(|_| type_error("expected a SharedArrayBuffer"))?;
+
+    // NOTE: A worker that has exited, or that runs in another process, has
+    // no entry, so nothing is kept for it.
+    SHARED_BUFFERS
+        .lock()
+        .unwrap()
+        .get_mut(&key)
+        .ok_or_else(|| {
+            custom_error(
+                "WorkerNotFound",
+                "the worker is not running in this process",
+            )
+        })?
+        .insert(name, buffer.get_backing_store());
+
+    Ok(())
+}
+
+#[op2]
+pub fn op_user_worker_shared_buffer<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    state: &mut OpState,
+    #[string] name: String,
+) -> Result<v8::Local<'a, v8::Value>, AnyError> {
+    let Some(UserWorkerKey(key)) = state.try_borrow::<UserWorkerKey>().copied() else {
+        return Err(type_error(
+            "shared buffers are only available to user workers",
+        ));
+    };
+
+    let backing_store = SHARED_BUFFERS
+        .lock()
+        .unwrap()
+        .get(&key)
+        .and_then(|it| it.get(&name))
+        .cloned();
+
+    Ok(match backing_store {
+        Some(it) => v8::SharedArrayBuffer::with_backing_store(scope, &it).into(),
+        None => v8::undefined(scope).into(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use deno_core::{JsRuntime, RuntimeOptions};
+    use tokio::sync::mpsc;
+
+    use crate::context::UserWorkerMsgs;
+
+    use super::*;
+
+    deno_core::extension!(
+        shared_buffers_test,
+        ops = [op_user_worker_share_buffer, op_user_worker_shared_buffer]
+    );
+
+    fn runtime() -> JsRuntime {
+        JsRuntime::new(RuntimeOptions {
+            extensions: vec![shared_buffers_test::init_ops()],
+            ..Default::default()
+        })
+    }
+
+    fn eval(runtime: &mut JsRuntime, code: String) -> Result<i64, AnyError> {
+        let value = runtime.execute_script("test.js", code)?;
+        let scope = &mut runtime.handle_scope();
+        let value = v8::Local::new(scope, value);
+
+        Ok(value.integer_value(scope).unwrap_or_default())
+    }
+
+    #[test]
+    fn test_shared_buffers() {
+        let key = Uuid::new_v4();
+        let (pool_msg_tx, _pool_msg_rx) = mpsc::unbounded_channel::<UserWorkerMsgs>();
+        let mut main = runtime();
+        let mut user = runtime();
+
+        main.op_state().borrow_mut().put(pool_msg_tx);
+        user.op_state().borrow_mut().put(UserWorkerKey(key));
+
+        let share = format!(
+            "globalThis.buffer = new SharedArrayBuffer(1); \
+             new Uint8Array(globalThis.buffer)[0] = 7; \
+             Deno.core.ops.op_user_worker_share_buffer('{}', 'payload', globalThis.buffer); 0",
+            key
+        );
+
+        // NOTE: Nothing is kept for a worker that isn't running.
+        assert!(eval(&mut main, share.clone()).is_err());
+        assert!(SHARED_BUFFERS.lock().unwrap().get(&key).is_none());
+
+        register_shared_buffers(key);
+        eval(&mut main, share.clone()).unwrap();
+
+        // NOTE: The user worker sees the same memory, both ways.
+        assert_eq!(
+            eval(
+                &mut user,
+                "const it = new Uint8Array(Deno.core.ops.op_user_worker_shared_buffer('payload')); \
+                 it[0] += 1; it[0]"
+                    .to_string()
+            )
+            .unwrap(),
+            8
+        );
+        assert_eq!(
+            eval(
+                &mut main,
+                "new Uint8Array(globalThis.buffer)[0]".to_string()
+            )
+            .unwrap(),
+            8
+        );
+
+        // NOTE: Only the main worker can share a buffer.
+        assert!(eval(&mut user, share).is_err());
+
+        drop_shared_buffers(&key);
+        assert!(SHARED_BUFFERS.lock().unwrap().get(&key).is_none());
+    }
+}
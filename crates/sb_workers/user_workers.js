@@ -9,11 +9,15 @@ const { TypeError } = primordials;
 const {
 	op_user_worker_fetch_send,
 	op_user_worker_create,
+	op_user_worker_terminate_worker,
+	op_user_worker_share_buffer,
 } = ops;
 
 const NO_SUPABASE_TAG_WARN_MSG = `Unable to find the supabase tag from the request instance.\n\
 Invoke \`EdgeRuntime.applySupabaseTag(origReq, newReq)\` if you have cloned the original request.`
 
+const SHUTDOWN_REASON_SUFFIX = / \((\w+)\)$/;
+
 function nullBodyStatus(status) {
 	return status === 101 || status === 204 || status === 205 || status === 304;
 }
@@ -43,11 +47,14 @@ class UserWorker {
 		const headersArray = Array.from(headers.entries());
 		const hasBody = !bodyUsed && !!body;
 
+		// NOTE: `options.cron` (`"list"` or `{ run: name }`) lists or runs
+		// the `Deno.cron()` jobs of the worker.
 		const userWorkerReq = {
 			method,
 			url,
 			hasBody,
 			headers: headersArray,
+			cron: options.cron ?? null,
 		};
 
 		const { requestRid, requestBodyRid } = await ops.op_user_worker_fetch_build(
@@ -55,35 +62,50 @@ class UserWorker {
 		);
 
 		// stream the request body
-		let requestBodyPromise = null;
-
+		//
+		// NOTE: The response is handed back without waiting for the body to be
+		// sent, as the worker may well respond (or stream its response) while
+		// it is still reading the body. The pipe is only held back by how fast
+		// the worker reads, and is cancelled once the response is done with. A
+		// body that fails to be sent surfaces in the worker as an aborted read.
 		if (hasBody) {
-			let writableStream = writableStreamForRid(requestBodyRid);
-			requestBodyPromise = body.pipeTo(writableStream, { signal });
-		}
+			const writableStream = writableStreamForRid(requestBodyRid);
 
-		const responsePromise = op_user_worker_fetch_send(
-			this.key,
-			requestRid,
-			requestBodyRid,
-			tag.streamRid,
-			tag.watcherRid
-		);
-
-		const [requestBodyPromiseResult, responsePromiseResult] = await Promise.allSettled([
-			requestBodyPromise,
-			responsePromise
-		]);
+			body.pipeTo(writableStream, { signal }).catch((error) => {
+				if (signal?.aborted || error?.name === "Interrupted") {
+					return;
+				}
 
-		if (requestBodyPromiseResult.status === "rejected") {
-			// console.warn(requestBodyPromiseResult.reason);
+				console.error("failed to send the request body to the worker:", error);
+			});
 		}
 
-		if (responsePromiseResult.status === "rejected") {
-			throw responsePromiseResult.reason;
+		let result;
+
+		try {
+			result = await op_user_worker_fetch_send(
+				this.key,
+				requestRid,
+				requestBodyRid,
+				tag?.streamRid,
+				tag?.watcherRid
+			);
+		} catch (e) {
+			// NOTE: The runtime appends the reason the worker was shut down for
+			// (e.g. `CPUTime`) to the message of a cancelled request.
+			if (e?.name === "WorkerRequestCancelled") {
+				const match = SHUTDOWN_REASON_SUFFIX.exec(e.message);
+
+				e.shutdownReason = match?.[1] ?? null;
+
+				if (match) {
+					e.message = e.message.slice(0, match.index);
+				}
+			}
+
+			throw e;
 		}
 
-		const result = responsePromiseResult.value;
 		const response = {
 			headers: result.headers,
 			status: result.status,
@@ -115,19 +137,64 @@ class UserWorker {
 		});
 	}
 
+	// Lets the in-flight requests of the worker complete, then terminates it.
+	// Resolves to `false` if the worker had already exited.
+	async terminate() {
+		const terminated = await op_user_worker_terminate_worker(this.key);
+
+		return terminated > 0;
+	}
+
+	// Shares a `SharedArrayBuffer` with the worker, which gets it without a
+	// copy from `EdgeRuntime.sharedBuffer(name)`. Throws if the worker has
+	// exited or runs in process isolation, since it can't share memory then.
+	shareBuffer(name, buffer) {
+		op_user_worker_share_buffer(this.key, name, buffer);
+	}
+
 	static async create(opts) {
 		const readyOptions = {
 			memoryLimitMb: 512,
 			lowMemoryMultiplier: 5,
+			heapProfile: null,
 			workerTimeoutMs: 5 * 60 * 1000,
+			wallClockGraceMs: 0,
+			bootTimeoutMs: 0,
+			maxModuleGraphSizeMb: 0,
+			maxRemoteModules: 0,
 			cpuTimeSoftLimitMs: 50,
 			cpuTimeHardLimitMs: 100,
+			hibernateAfterMs: 0,
+			idleTimeoutMs: 0,
+			maxRequests: 0,
 			noModuleCache: false,
 			importMapPath: null,
 			envVars: [],
 			forceCreate: false,
+			fallbackServicePath: null,
+			maxWorkers: null,
+			maxConcurrentRequests: null,
+			tenant: null,
+			cpuSet: null,
+			nice: null,
+			v8Flags: [],
 			netAccessDisabled: false,
 			allowNet: null,
+			allowRead: null,
+			allowWrite: null,
+			allowEnv: null,
+			envAllowlist: null,
+			allowHrtime: false,
+			allowFfi: false,
+			egressAllow: [],
+			egressDeny: null,
+			httpProxy: null,
+			fetchKeepAliveMs: null,
+			fetchMaxIdlePerHost: null,
+			fetchConnectTimeoutMs: null,
+			cryptoSeed: null,
+			kvMaxSizeMb: 0,
+			shareBroadcastChannel: false,
 			allowRemoteModules: true,
 			customModuleRoot: '',
 			maybeEszip: null,
@@ -0,0 +1,31 @@
+// Links are sent to the client in a `103 Early Hints` ahead of the response,
+// either with `EdgeRuntime.sendEarlyHints()` while the response is still being
+// worked on, or set in `x-early-hints` on the response itself, in which case
+// they're also kept as `Link` headers of the response.
+
+const encoder = new TextEncoder();
+
+Deno.serve(async (req) => {
+	// The client can start fetching the stylesheet while the page is rendered.
+	EdgeRuntime.sendEarlyHints(req, ['</style.css>; rel=preload; as=style']);
+
+	await new Promise((resolve) => setTimeout(resolve, 500));
+
+	const body = new ReadableStream({
+		async start(controller) {
+			controller.enqueue(encoder.encode('<!doctype html><html><head>'));
+			await new Promise((resolve) => setTimeout(resolve, 500));
+			controller.enqueue(
+				encoder.encode('<link rel="stylesheet" href="/style.css"><script src="/app.js"></script></head></html>'),
+			);
+			controller.close();
+		},
+	});
+
+	return new Response(body, {
+		headers: {
+			'Content-Type': 'text/html',
+			'x-early-hints': '</app.js>; rel=preload; as=script',
+		},
+	});
+});
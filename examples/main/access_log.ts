@@ -0,0 +1,129 @@
+// Structured (JSON) access log, one line per request.
+//
+// `ACCESS_LOG` selects the sink: `stdout`, or `file:<path>` to append to a
+// file. Access logging is disabled when unset.
+
+export interface AccessLogEntry {
+	time: string;
+	requestId: string;
+	method: string;
+	path: string;
+	// Effective client address, resolved by the runtime with respect to its
+	// trusted proxies (`--trusted-proxy`).
+	clientIp: string | null;
+	service: string | null;
+	tenant: string | null;
+	status: number;
+	latencyMs: number;
+	requestBytes: number | null;
+	responseBytes: number;
+	// Set when the worker handling the request was shut down by its
+	// supervisor (e.g. it exceeded its resource limits), to the reason it was
+	// shut down for, e.g. `CPUTime`.
+	shutdownReason: string | null;
+}
+
+type Sink = (line: string) => void;
+
+const encoder = new TextEncoder();
+
+function createSink(): Sink | null {
+	const target = Deno.env.get('ACCESS_LOG');
+
+	if (!target) {
+		return null;
+	}
+
+	if (target === 'stdout') {
+		return (line) => console.log(line);
+	}
+
+	if (target.startsWith('file:')) {
+		const file = Deno.openSync(target.slice('file:'.length), {
+			create: true,
+			append: true,
+		});
+
+		return (line) => file.writeSync(encoder.encode(line + '\n'));
+	}
+
+	console.error(`unknown access log sink: ${target}`);
+	return null;
+}
+
+const sink = createSink();
+
+export type AccessLoggedHandler = (req: Request, entry: AccessLogEntry) => Promise<Response>;
+
+export async function withAccessLog(req: Request, handler: AccessLoggedHandler) {
+	if (!sink) {
+		return await handler(req, {} as AccessLogEntry);
+	}
+
+	const startedAt = performance.now();
+	const contentLength = req.headers.get('content-length');
+	const entry: AccessLogEntry = {
+		time: new Date().toISOString(),
+		requestId: req.headers.get('x-request-id') ?? crypto.randomUUID(),
+		method: req.method,
+		path: new URL(req.url).pathname,
+		clientIp: req.headers.get('x-client-ip'),
+		service: null,
+		tenant: null,
+		status: 0,
+		latencyMs: 0,
+		requestBytes: contentLength === null ? null : parseInt(contentLength, 10),
+		responseBytes: 0,
+		shutdownReason: null,
+	};
+
+	let emitted = false;
+	const emit = () => {
+		if (emitted) {
+			return;
+		}
+
+		emitted = true;
+		entry.latencyMs = Math.round(performance.now() - startedAt);
+		sink(JSON.stringify(entry));
+	};
+
+	let resp: Response;
+
+	try {
+		resp = await handler(req, entry);
+	} catch (e) {
+		// NOTE: The error surfaces as a 500 to the client.
+		entry.status = 500;
+		emit();
+		throw e;
+	}
+
+	entry.status = resp.status;
+
+	// NOTE: Upgraded (e.g. WebSocket) responses must be handed back untouched.
+	if (resp.status === 101 || resp.body === null) {
+		emit();
+		return resp;
+	}
+
+	const counter = new TransformStream<Uint8Array, Uint8Array>({
+		transform(chunk, controller) {
+			entry.responseBytes += chunk.byteLength;
+			controller.enqueue(chunk);
+		},
+		flush: emit,
+		// The body was not sent in full, e.g. the client went away.
+		cancel: emit,
+	});
+
+	const headers = new Headers(resp.headers);
+
+	headers.set('x-request-id', entry.requestId);
+
+	return new Response(resp.body.pipeThrough(counter), {
+		status: resp.status,
+		statusText: resp.statusText,
+		headers,
+	});
+}
@@ -0,0 +1,40 @@
+// Authorization of the operations under `/_internal/admin/`.
+//
+// They are disabled unless `ADMIN_TOKEN` is set, in which case requests must
+// carry it as a bearer token.
+
+const adminToken = Deno.env.get('ADMIN_TOKEN');
+const encoder = new TextEncoder();
+
+function timingSafeEqual(a: string, b: string) {
+	const left = encoder.encode(a);
+	const right = encoder.encode(b);
+	let diff = left.length ^ right.length;
+
+	for (let i = 0; i < left.length; i++) {
+		diff |= left[i] ^ (right[i % right.length] ?? 0);
+	}
+
+	return diff === 0;
+}
+
+// Returns a response rejecting the request, or `null` if it's authorized.
+export function rejectUnauthorizedAdmin(req: Request): Response | null {
+	if (!adminToken) {
+		return new Response(
+			JSON.stringify({ msg: 'admin operations are disabled' }),
+			{ status: 404, headers: { 'Content-Type': 'application/json' } },
+		);
+	}
+
+	const token = req.headers.get('authorization')?.match(/^Bearer\s+(.+)$/i)?.[1];
+
+	if (!token || !timingSafeEqual(token, adminToken)) {
+		return new Response(
+			JSON.stringify({ msg: 'unauthorized' }),
+			{ status: 401, headers: { 'Content-Type': 'application/json' } },
+		);
+	}
+
+	return null;
+}
@@ -0,0 +1,17 @@
+// Encodes a JSON value as base64, as forwarded in the identity and JWT claims
+// headers.
+
+// Bytes passed to `String.fromCharCode` at a time, as spreading a large array
+// into a single call overflows the argument limit.
+const CHUNK_SIZE = 0x8000;
+
+export function encodeJsonBase64(value: unknown): string {
+	const bytes = new TextEncoder().encode(JSON.stringify(value));
+	let binary = '';
+
+	for (let i = 0; i < bytes.length; i += CHUNK_SIZE) {
+		binary += String.fromCharCode(...bytes.subarray(i, i + CHUNK_SIZE));
+	}
+
+	return btoa(binary);
+}
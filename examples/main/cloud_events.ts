@@ -0,0 +1,188 @@
+// Ingestion of CloudEvents posted to `/_events`.
+//
+// Both HTTP bindings are accepted: binary mode (attributes in `ce-*` headers,
+// data in the body) and structured mode (`application/cloudevents+json`).
+// Events are delivered to the service their type is routed to, always in
+// binary mode, so a function only needs to handle one shape.
+//
+// `CLOUD_EVENTS_ROUTES_PATH` points at a JSON file mapping event types to
+// services. A trailing `*` matches by prefix; the longest match wins:
+//
+// ```json
+// {
+//   "com.example.order.created": "orders",
+//   "com.example.*": "events-fallback"
+// }
+// ```
+//
+// See: https://github.com/cloudevents/spec/blob/v1.0.2/cloudevents/bindings/http-protocol-binding.md
+
+export interface CloudEvent {
+	attributes: Record<string, string>;
+	contentType: string | null;
+	data: BodyInit | null;
+}
+
+const REQUIRED_ATTRIBUTES = ['id', 'source', 'specversion', 'type'];
+const STRUCTURED_CONTENT_TYPE = 'application/cloudevents+json';
+const BATCH_CONTENT_TYPE = 'application/cloudevents-batch+json';
+
+let routes: Record<string, string> | null = null;
+
+function loadRoutes(): Record<string, string> {
+	if (!routes) {
+		const path = Deno.env.get('CLOUD_EVENTS_ROUTES_PATH');
+
+		try {
+			routes = path ? JSON.parse(Deno.readTextFileSync(path)) : {};
+		} catch (e) {
+			console.error('failed to load cloud event routes:', e);
+			routes = {};
+		}
+	}
+
+	return routes!;
+}
+
+// Returns the service events of `type` are delivered to, if any.
+export function routeCloudEvent(type: string): string | null {
+	let match: string | null = null;
+	let matchLength = -1;
+
+	for (const [pattern, service] of Object.entries(loadRoutes())) {
+		const isPrefix = pattern.endsWith('*');
+		const prefix = isPrefix ? pattern.slice(0, -1) : pattern;
+		const matches = isPrefix ? type.startsWith(prefix) : type === pattern;
+
+		// NOTE: An exact pattern beats a prefix of the same length.
+		const length = isPrefix ? prefix.length : prefix.length + 1;
+
+		if (matches && length > matchLength) {
+			match = service;
+			matchLength = length;
+		}
+	}
+
+	return match;
+}
+
+// Percent-encodes what the HTTP binding doesn't allow in `ce-*` header values:
+// anything outside printable ASCII, plus space, `"` and `%`.
+function encodeHeaderValue(value: string) {
+	return Array.from(new TextEncoder().encode(value), (b) =>
+		b > 0x20 && b < 0x7f && b !== 0x22 && b !== 0x25
+			? String.fromCharCode(b)
+			: `%${b.toString(16).toUpperCase().padStart(2, '0')}`).join('');
+}
+
+function decodeHeaderValue(value: string) {
+	try {
+		return decodeURIComponent(value);
+	} catch {
+		return value;
+	}
+}
+
+function structuredData(data: unknown, dataBase64: string | undefined, contentType: string | null) {
+	if (dataBase64 !== undefined) {
+		return Uint8Array.from(atob(dataBase64), (c) => c.charCodeAt(0));
+	}
+
+	if (data === undefined) {
+		return null;
+	}
+
+	// Non-JSON data is carried as a string in the structured mode.
+	if (typeof data === 'string' && contentType && !contentType.includes('json')) {
+		return data;
+	}
+
+	return JSON.stringify(data);
+}
+
+function mediaType(req: Request) {
+	return req.headers.get('content-type')?.split(';')[0].trim().toLowerCase() ?? null;
+}
+
+// Parses the event carried by `req`. Throws a `TypeError` if it isn't a valid
+// CloudEvent.
+export async function parseCloudEvent(req: Request): Promise<CloudEvent> {
+	const type = mediaType(req);
+
+	if (type === BATCH_CONTENT_TYPE) {
+		throw new TypeError('batched cloud events are not supported');
+	}
+
+	let event: CloudEvent;
+
+	if (type === STRUCTURED_CONTENT_TYPE) {
+		const { data, data_base64, datacontenttype, ...rest } = await req.json();
+		const attributes: Record<string, string> = {};
+
+		for (const [key, value] of Object.entries(rest)) {
+			attributes[key] = typeof value === 'string' ? value : JSON.stringify(value);
+		}
+
+		const contentType = datacontenttype ?? (data !== undefined ? 'application/json' : null);
+
+		event = {
+			attributes,
+			contentType,
+			data: structuredData(data, data_base64, contentType),
+		};
+	} else {
+		const attributes: Record<string, string> = {};
+
+		for (const [key, value] of req.headers) {
+			if (key.startsWith('ce-')) {
+				attributes[key.slice(3)] = decodeHeaderValue(value);
+			}
+		}
+
+		event = {
+			attributes,
+			contentType: req.headers.get('content-type'),
+			data: req.body,
+		};
+	}
+
+	for (const attribute of REQUIRED_ATTRIBUTES) {
+		if (!event.attributes[attribute]) {
+			throw new TypeError(`missing cloud event attribute: ${attribute}`);
+		}
+	}
+
+	return event;
+}
+
+// Builds the request delivering `event` to `servicePath` (relative to the
+// origin of `req`) in binary mode.
+export function toDeliveryRequest(req: Request, servicePath: string, event: CloudEvent): Request {
+	const headers = new Headers();
+
+	for (const [key, value] of Object.entries(event.attributes)) {
+		headers.set(`ce-${key}`, encodeHeaderValue(value));
+	}
+
+	if (event.contentType) {
+		headers.set('content-type', event.contentType);
+	}
+
+	for (const name of ['host', 'authorization', 'x-client-ip', 'x-forwarded-for']) {
+		const value = req.headers.get(name);
+
+		if (value !== null) {
+			headers.set(name, value);
+		}
+	}
+
+	const delivery = new Request(new URL(servicePath, req.url), {
+		method: 'POST',
+		headers,
+		body: event.data,
+	});
+
+	EdgeRuntime.applySupabaseTag(req, delivery);
+
+	return delivery;
+}
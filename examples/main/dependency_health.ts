@@ -0,0 +1,68 @@
+import { DependencyConfig } from './service_config.ts';
+
+// Upstream dependencies declared by services are probed every
+// `DEPENDENCY_PROBE_INTERVAL_MS` (default 10s). A dependency is considered
+// down when the probe fails or times out, or responds with a 5xx status.
+const probeIntervalMs = parseInt(Deno.env.get('DEPENDENCY_PROBE_INTERVAL_MS') ?? '10000', 10);
+const probeTimeoutMs = parseInt(Deno.env.get('DEPENDENCY_PROBE_TIMEOUT_MS') ?? '3000', 10);
+
+export interface DependencyStatus {
+	url: string;
+	hard: boolean;
+	healthy: boolean;
+	lastCheckedAt: string | null;
+	reason: string | null;
+}
+
+const services = new Map<string, DependencyStatus[]>();
+
+async function probe(status: DependencyStatus) {
+	try {
+		const resp = await fetch(status.url, { signal: AbortSignal.timeout(probeTimeoutMs) });
+
+		await resp.body?.cancel();
+
+		status.healthy = resp.status < 500;
+		status.reason = status.healthy ? null : `upstream responded with ${resp.status}`;
+	} catch (e) {
+		status.healthy = false;
+		status.reason = e.toString();
+	}
+
+	status.lastCheckedAt = new Date().toISOString();
+}
+
+export function watchDependencies(servicePath: string, dependencies: DependencyConfig[]) {
+	if (services.has(servicePath) || dependencies.length === 0) {
+		return;
+	}
+
+	const statuses = dependencies.map(({ url, hard }) => ({
+		url,
+		hard,
+		// NOTE: Optimistic until the first probe completes so that a freshly
+		// seen service is not rejected before its dependencies were checked.
+		healthy: true,
+		lastCheckedAt: null,
+		reason: null,
+	}));
+
+	services.set(servicePath, statuses);
+
+	const probeAll = () => Promise.all(statuses.map(probe));
+
+	probeAll();
+	setInterval(probeAll, probeIntervalMs);
+}
+
+// Returns the reason the service can't be served, if any of its hard
+// dependencies is down.
+export function hardDependencyFailure(servicePath: string): string | null {
+	const down = services.get(servicePath)?.find((it) => it.hard && !it.healthy);
+
+	return down ? `dependency ${down.url} is unavailable: ${down.reason}` : null;
+}
+
+export function dependencyReport(): Record<string, DependencyStatus[]> {
+	return Object.fromEntries(services);
+}
@@ -0,0 +1,116 @@
+// Per-service header rewrite rules, configured in the `[headers]` section of
+// `function.toml`.
+//
+// ```toml
+// [headers]
+// allow = ["content-type", "authorization"] # only these client headers reach the worker
+// strip = ["x-debug"]                        # client headers never forwarded
+// inject = { "x-forwarded-service" = "hello" }
+// strip_response = ["server"]
+// inject_response = { "x-served-by" = "edge-runtime" }
+// ```
+export interface HeaderPolicy {
+	allow: string[] | null;
+	strip: string[];
+	inject: Record<string, string>;
+	stripResponse: string[];
+	injectResponse: Record<string, string>;
+}
+
+// Hop-by-hop headers (RFC 9110, section 7.6.1) are connection specific and
+// never forwarded to or from the worker. `connection` and `upgrade` are kept
+// for upgrade requests (e.g. WebSocket), which depend on them.
+const HOP_BY_HOP_HEADERS = [
+	'keep-alive',
+	'proxy-connection',
+	'proxy-authenticate',
+	'proxy-authorization',
+	'te',
+	'trailer',
+];
+
+const UPGRADE_HEADERS = ['connection', 'upgrade'];
+
+// `te: trailers` is the only `te` allowed over HTTP/2, and gRPC requires it to
+// reach the worker.
+function isTeTrailers(name: string, value: string) {
+	return name === 'te' && value.trim().toLowerCase() === 'trailers';
+}
+
+// Headers the main worker uses to pass information to the worker. A client
+// must not be able to set them.
+const INTERNAL_HEADERS = ['x-edge-runtime-identity', 'x-jwt-claims'];
+
+function toLowerList(value: unknown): string[] {
+	return Array.isArray(value) ? value.map((it) => String(it).toLowerCase()) : [];
+}
+
+function toRecord(value: unknown): Record<string, string> {
+	if (typeof value !== 'object' || value === null) {
+		return {};
+	}
+
+	return Object.fromEntries(Object.entries(value).map(([k, v]) => [k, String(v)]));
+}
+
+export function parseHeaderPolicy(raw: any): HeaderPolicy {
+	return {
+		allow: Array.isArray(raw?.allow) ? toLowerList(raw.allow) : null,
+		strip: toLowerList(raw?.strip),
+		inject: toRecord(raw?.inject),
+		stripResponse: toLowerList(raw?.strip_response),
+		injectResponse: toRecord(raw?.inject_response),
+	};
+}
+
+export function applyRequestHeaderPolicy(req: Request, policy: HeaderPolicy): Request {
+	const headers = new Headers();
+	const isUpgrade = req.headers.has('upgrade');
+
+	for (const [name, value] of req.headers) {
+		if (
+			(HOP_BY_HOP_HEADERS.includes(name) && !isTeTrailers(name, value)) ||
+			(!isUpgrade && UPGRADE_HEADERS.includes(name)) ||
+			INTERNAL_HEADERS.includes(name) ||
+			policy.strip.includes(name) ||
+			(policy.allow && !policy.allow.includes(name) && !UPGRADE_HEADERS.includes(name) &&
+				!isTeTrailers(name, value))
+		) {
+			continue;
+		}
+
+		headers.append(name, value);
+	}
+
+	for (const [name, value] of Object.entries(policy.inject)) {
+		headers.set(name, value);
+	}
+
+	const newReq = new Request(req, { headers });
+
+	EdgeRuntime.applySupabaseTag(req, newReq);
+	return newReq;
+}
+
+export function applyResponseHeaderPolicy(resp: Response, policy: HeaderPolicy): Response {
+	// NOTE: Upgraded (e.g. WebSocket) responses must be handed back untouched.
+	if (resp.status === 101) {
+		return resp;
+	}
+
+	const headers = new Headers(resp.headers);
+
+	for (const name of [...HOP_BY_HOP_HEADERS, ...policy.stripResponse]) {
+		headers.delete(name);
+	}
+
+	for (const [name, value] of Object.entries(policy.injectResponse)) {
+		headers.set(name, value);
+	}
+
+	return new Response(resp.body, {
+		status: resp.status,
+		statusText: resp.statusText,
+		headers,
+	});
+}
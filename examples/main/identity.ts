@@ -0,0 +1,153 @@
+// Derives the caller identity of a request and forwards it to the user worker,
+// where it's available as `EdgeRuntime.context.identity`.
+//
+// `IDENTITY_PROVIDERS` is a comma separated list of providers tried in order
+// (default: `jwt,mtls,apiKey`). The first one that recognizes the caller wins.
+//
+// - `jwt`: claims of a token verified by the service's `[jwt]` config
+// - `mtls`: subject of a verified TLS client certificate
+// - `apiKey`: lookup of the `apikey`/`x-api-key` header in the JSON file at
+//   `IDENTITY_API_KEYS_PATH` (`{ "<key>": { "subject": "...", ... } }`)
+//
+// Operators can plug in their own derivation by pointing
+// `IDENTITY_HOOK_MODULE` at a module whose default export is a `IdentityHook`.
+// It runs before the built-in providers.
+
+import { encodeJsonBase64 } from './base64.ts';
+
+export const IDENTITY_HEADER = 'x-edge-runtime-identity';
+
+export interface Identity {
+	provider: string;
+	subject: string;
+	claims: Record<string, unknown>;
+}
+
+export interface IdentityInput {
+	req: Request;
+	jwtClaims: Record<string, unknown> | null;
+}
+
+export type IdentityHook = (input: IdentityInput) => Promise<Identity | null> | Identity | null;
+
+const builtinProviders: Record<string, IdentityHook> = {
+	jwt: ({ jwtClaims }) => {
+		if (!jwtClaims) {
+			return null;
+		}
+
+		return { provider: 'jwt', subject: String(jwtClaims.sub ?? ''), claims: jwtClaims };
+	},
+
+	mtls: ({ req }) => {
+		const subject = req.headers.get('x-client-cert-subject');
+
+		if (!subject) {
+			return null;
+		}
+
+		return {
+			provider: 'mtls',
+			subject,
+			claims: { sha256: req.headers.get('x-client-cert-sha256') },
+		};
+	},
+
+	apiKey: async ({ req }) => {
+		const key = req.headers.get('apikey') ?? req.headers.get('x-api-key');
+
+		if (!key) {
+			return null;
+		}
+
+		const entry = (await loadApiKeys())[key];
+
+		if (!entry) {
+			return null;
+		}
+
+		const { subject, ...claims } = entry;
+		return { provider: 'apiKey', subject: String(subject ?? ''), claims };
+	},
+};
+
+let apiKeys: Promise<Record<string, Record<string, unknown>>> | null = null;
+
+function loadApiKeys() {
+	if (!apiKeys) {
+		const path = Deno.env.get('IDENTITY_API_KEYS_PATH');
+
+		apiKeys = path
+			? Deno.readTextFile(path).then(JSON.parse).catch((e) => {
+				console.error('failed to load api keys:', e);
+				return {};
+			})
+			: Promise.resolve({});
+	}
+
+	return apiKeys;
+}
+
+let providers: Promise<IdentityHook[]> | null = null;
+
+function loadProviders() {
+	if (!providers) {
+		providers = (async () => {
+			const names = (Deno.env.get('IDENTITY_PROVIDERS') ?? 'jwt,mtls,apiKey')
+				.split(',')
+				.map((it) => it.trim())
+				.filter((it) => it !== '');
+
+			const hooks = names.flatMap((name) => {
+				const hook = builtinProviders[name];
+
+				if (!hook) {
+					console.error(`unknown identity provider: ${name}`);
+					return [];
+				}
+
+				return [hook];
+			});
+
+			const hookModule = Deno.env.get('IDENTITY_HOOK_MODULE');
+
+			if (hookModule) {
+				const { default: hook } = await import(hookModule);
+				hooks.unshift(hook);
+			}
+
+			return hooks;
+		})();
+	}
+
+	return providers;
+}
+
+export async function deriveIdentity(input: IdentityInput): Promise<Identity | null> {
+	for (const hook of await loadProviders()) {
+		const identity = await hook(input);
+
+		if (identity) {
+			return identity;
+		}
+	}
+
+	return null;
+}
+
+// Returns a copy of the request carrying `identity`. A client supplied identity
+// header is never passed through.
+export function withIdentity(req: Request, identity: Identity | null): Request {
+	const headers = new Headers(req.headers);
+
+	headers.delete(IDENTITY_HEADER);
+
+	if (identity) {
+		headers.set(IDENTITY_HEADER, encodeJsonBase64(identity));
+	}
+
+	const newReq = new Request(req, { headers });
+
+	EdgeRuntime.applySupabaseTag(req, newReq);
+	return newReq;
+}
@@ -1,12 +1,137 @@
 // @ts-ignore
 import { STATUS_CODE } from 'https://deno.land/std/http/status.ts';
 
+import { AccessLogEntry, withAccessLog } from './access_log.ts';
+import { rejectUnauthorizedAdmin } from './admin.ts';
+import { parseCloudEvent, routeCloudEvent, toDeliveryRequest } from './cloud_events.ts';
+import { dependencyReport, hardDependencyFailure, watchDependencies } from './dependency_health.ts';
+import { applyRequestHeaderPolicy, applyResponseHeaderPolicy } from './header_policy.ts';
+import { deriveIdentity, withIdentity } from './identity.ts';
+import { verifyJwt, withClaims } from './jwt.ts';
+import { sampleMirror, sendMirror } from './mirror.ts';
+import { prewarmServices } from './prewarm.ts';
+import { startQueueConsumers } from './queue_consumers.ts';
+import {
+	invalidateServiceConfig,
+	loadServiceConfig,
+	ServiceConfig,
+	serviceWorkerOverrides,
+	watchServiceConfigs,
+} from './service_config.ts';
+import { withResponseCache } from './response_cache.ts';
+import { startScheduler } from './scheduler.ts';
+import { isInsideRoot, resolveServicePath } from './service_path.ts';
+import { resolveServicesRoot } from './tenant.ts';
+import { splitTraffic, withSplitCookie } from './traffic_split.ts';
+import { WorkerOverrides, workerOptions } from './worker_options.ts';
+
 console.log('main function started');
 
+// Set `SERVICE_RESOLUTION=host` to pick the service from the first label of
+// the Host header (`<service>.example.com`) instead of the first path segment.
+// In that mode, functions are served at the root path of their own subdomain.
+const serviceResolution = Deno.env.get('SERVICE_RESOLUTION') ?? 'path';
+
+// `SERVICE_RESOLUTION=single` routes every request to the service named by
+// `SINGLE_SERVICE`.
+function resolveServiceName(req: Request, pathname: string): string | undefined {
+	if (serviceResolution === 'single') {
+		return Deno.env.get('SINGLE_SERVICE');
+	}
+
+	if (serviceResolution === 'host') {
+		const host = req.headers.get('host') ?? new URL(req.url).host;
+		const hostname = host.replace(/:\d+$/, '');
+		const labels = hostname.split('.');
+
+		// A bare hostname (e.g. `localhost`) carries no service label.
+		return labels.length > 1 ? labels[0] : undefined;
+	}
+
+	return pathname.split('/')[1];
+}
+
 // log system memory usage every 30s
 // setInterval(() => console.log(EdgeRuntime.systemMemoryInfo()), 30 * 1000);
 
-Deno.serve(async (req: Request) => {
+// Optionally push autoscale signals to an external autoscaler (e.g. a KEDA
+// metrics adapter) every `AUTOSCALE_WEBHOOK_INTERVAL_MS` (default 15s).
+const autoscaleWebhookUrl = Deno.env.get('AUTOSCALE_WEBHOOK_URL');
+
+if (autoscaleWebhookUrl) {
+	const intervalMs = parseInt(Deno.env.get('AUTOSCALE_WEBHOOK_INTERVAL_MS') ?? '15000', 10);
+
+	setInterval(async () => {
+		try {
+			await fetch(autoscaleWebhookUrl, {
+				method: 'POST',
+				headers: { 'Content-Type': 'application/json' },
+				body: JSON.stringify(EdgeRuntime.getAutoscaleSignals()),
+			});
+		} catch (e) {
+			console.error('failed to push autoscale signals:', e);
+		}
+	}, intervalMs);
+}
+
+// Creates (or reuses) the worker of a service. `overrides` are applied on top
+// of the defaults from `worker_options.ts`.
+async function createWorker(servicePath: string, overrides: WorkerOverrides = {}) {
+	// you can provide an import map inline
+	// const inlineImportMap = {
+	//   imports: {
+	//     "std/": "https://deno.land/std@0.131.0/",
+	//     "cors": "./examples/_shared/cors.ts"
+	//   }
+	// }
+
+	// const importMapPath = `data:${encodeURIComponent(JSON.stringify(importMap))}?${encodeURIComponent('/home/deno/functions/test')}`;
+
+	// load source from an eszip
+	//const maybeEszip = await Deno.readFile('./bin.eszip');
+	//const maybeEntrypoint = 'file:///src/index.ts';
+
+	// const maybeEntrypoint = 'file:///src/index.ts';
+	// or load module source from an inline module
+	// const maybeModuleCode = 'Deno.serve((req) => new Response("Hello from Module Code"));';
+
+	return await EdgeRuntime.userWorkers.create({
+		servicePath,
+		...workerOptions(overrides),
+		// maybeEszip,
+		// maybeEntrypoint,
+		// maybeModuleCode,
+	});
+}
+
+// Creates (or reuses) the worker of a service with the options from its
+// `function.toml`. Every path booting a service goes through here, so that
+// they agree on the worker they boot.
+function createServiceWorker(servicePath: string, config: ServiceConfig, tenant?: string | null) {
+	return createWorker(servicePath, {
+		...serviceWorkerOverrides(config),
+		tenant: tenant ?? undefined,
+	});
+}
+
+const warmService = (servicePath: string, config: ServiceConfig) => createServiceWorker(servicePath, config);
+
+prewarmServices(warmService);
+
+watchServiceConfigs().catch((e) => console.error('failed to watch service configs:', e));
+
+startScheduler(async (servicePath, config, req, cron) => {
+	const worker = await createServiceWorker(servicePath, config);
+	return await worker.fetch(req, { cron });
+});
+
+startQueueConsumers(async (servicePath, req) => {
+	const config = await loadServiceConfig(servicePath);
+	const worker = await createWorker(servicePath, serviceWorkerOverrides(config));
+	return await worker.fetch(req);
+});
+
+async function handleRequest(req: Request, accessLog: AccessLogEntry) {
 	const headers = new Headers({
 		'Content-Type': 'application/json',
 	});
@@ -14,10 +139,148 @@ Deno.serve(async (req: Request) => {
 	const url = new URL(req.url);
 	const { pathname } = url;
 
+	// Hints sent by the server when `--speculative-boot` is enabled, so that
+	// the worker the upcoming request most likely targets boots ahead of time.
+	// The server refuses this path to clients, so only its own hints get here.
+	if (pathname === '/_internal/speculative-boot') {
+		const path = req.headers.get('x-edge-runtime-speculative-path') ?? '/';
+		const name = resolveServiceName(req, new URL(path, url).pathname);
+		const servicesRoot = resolveServicesRoot(req);
+
+		const servicePath = name && servicesRoot ? await resolveServicePath(servicesRoot.root, name) : null;
+
+		if (servicePath) {
+			loadServiceConfig(servicePath)
+				.then((config) => createServiceWorker(servicePath, config, servicesRoot!.tenant))
+				.catch(() => {});
+		}
+
+		return new Response(null, { status: STATUS_CODE.NoContent });
+	}
+
+	// Blue/green deploy: atomically points a service at another release
+	// directory (a path relative to the services root, e.g.
+	// `my-fn.releases/2024-06-01`), drains the workers of the previous release
+	// and warms the new one.
+	if (pathname === '/_internal/admin/swap' && req.method === 'POST') {
+		const rejected = rejectUnauthorizedAdmin(req);
+
+		if (rejected) {
+			return rejected;
+		}
+
+		const { service, release } = await req.json();
+		const servicesRoot = resolveServicesRoot(req);
+		const servicePath = servicesRoot && typeof service === 'string'
+			? await resolveServicePath(servicesRoot.root, service)
+			: null;
+		const releasePath = servicesRoot && typeof release === 'string'
+			? `${servicesRoot.root}/${release}`
+			: null;
+
+		if (!servicePath || !releasePath || !(await isInsideRoot(servicesRoot!.root, releasePath))) {
+			return new Response(
+				JSON.stringify({ msg: 'invalid service or release' }),
+				{ status: STATUS_CODE.BadRequest, headers },
+			);
+		}
+
+		try {
+			const result = await EdgeRuntime.deploy.swapService(servicePath, releasePath);
+
+			invalidateServiceConfig(servicePath);
+
+			const config = await loadServiceConfig(servicePath);
+			const warmed = await createServiceWorker(servicePath, config, servicesRoot!.tenant)
+				.then(() => true, (e) => {
+					console.error(`failed to warm ${servicePath}:`, e);
+					return false;
+				});
+
+			return new Response(JSON.stringify({ ...result, warmed }), { headers });
+		} catch (e) {
+			return new Response(
+				JSON.stringify({ msg: e.toString() }),
+				{ status: STATUS_CODE.Conflict, headers },
+			);
+		}
+	}
+
+	// Boots the services listed in `PREWARM_SERVICES` again, reloading their
+	// configuration.
+	if (pathname === '/_internal/admin/prewarm' && req.method === 'POST') {
+		const rejected = rejectUnauthorizedAdmin(req);
+
+		if (rejected) {
+			return rejected;
+		}
+
+		return new Response(JSON.stringify(await prewarmServices(warmService, true)), { headers });
+	}
+
+	// Lists the live workers with their usage and limits.
+	if (pathname === '/_internal/admin/workers' && req.method === 'GET') {
+		const rejected = rejectUnauthorizedAdmin(req);
+
+		if (rejected) {
+			return rejected;
+		}
+
+		return new Response(JSON.stringify(await EdgeRuntime.introspection.listWorkers()), { headers });
+	}
+
+	// Drains and terminates a worker (`/_internal/admin/workers/<key>`) or every
+	// worker of a service (`/_internal/admin/workers?service=<name>`).
+	if (
+		(pathname === '/_internal/admin/workers' || pathname.startsWith('/_internal/admin/workers/')) &&
+		req.method === 'DELETE'
+	) {
+		const rejected = rejectUnauthorizedAdmin(req);
+
+		if (rejected) {
+			return rejected;
+		}
+
+		const key = pathname.slice('/_internal/admin/workers/'.length);
+		const service = url.searchParams.get('service');
+
+		try {
+			let terminated: number;
+
+			if (key) {
+				terminated = await EdgeRuntime.deploy.terminateWorker(key);
+			} else {
+				const servicesRoot = resolveServicesRoot(req);
+				const servicePath = servicesRoot && service
+					? await resolveServicePath(servicesRoot.root, service)
+					: null;
+
+				if (!servicePath) {
+					return new Response(
+						JSON.stringify({ msg: 'invalid worker or service' }),
+						{ status: STATUS_CODE.BadRequest, headers },
+					);
+				}
+
+				terminated = await EdgeRuntime.deploy.terminateService(servicePath);
+			}
+
+			return new Response(
+				JSON.stringify({ terminated }),
+				{ status: terminated > 0 ? STATUS_CODE.OK : STATUS_CODE.NotFound, headers },
+			);
+		} catch (e) {
+			return new Response(
+				JSON.stringify({ msg: e.toString() }),
+				{ status: STATUS_CODE.BadRequest, headers },
+			);
+		}
+	}
+
 	// handle health checks
 	if (pathname === '/_internal/health') {
 		return new Response(
-			JSON.stringify({ 'message': 'ok' }),
+			JSON.stringify({ 'message': 'ok', 'services': dependencyReport() }),
 			{
 				status: STATUS_CODE.OK,
 				headers,
@@ -30,6 +293,51 @@ Deno.serve(async (req: Request) => {
 		return Response.json(metric);
 	}
 
+	if (pathname === '/_internal/autoscale') {
+		return Response.json(EdgeRuntime.getAutoscaleSignals());
+	}
+
+	// Machine-readable schemas of the endpoints above and of worker events, for
+	// generating clients.
+	if (pathname === '/_internal/openapi.json') {
+		return new Response(await Deno.readTextFile(new URL('./openapi.json', import.meta.url)), {
+			headers,
+		});
+	}
+
+	if (pathname === '/_internal/schemas/worker-event.json') {
+		return new Response(EdgeRuntime.getEventSchema(), {
+			headers: { 'Content-Type': 'application/schema+json' },
+		});
+	}
+
+	// CloudEvents are routed to a service by their type, see `cloud_events.ts`.
+	let eventService: string | null = null;
+
+	if (pathname === '/_events' && req.method === 'POST') {
+		let event;
+
+		try {
+			event = await parseCloudEvent(req);
+		} catch (e) {
+			return new Response(
+				JSON.stringify({ msg: `invalid cloud event: ${e.message}` }),
+				{ status: STATUS_CODE.BadRequest, headers },
+			);
+		}
+
+		eventService = routeCloudEvent(event.attributes.type);
+
+		if (!eventService) {
+			return new Response(
+				JSON.stringify({ msg: `no service for event type: ${event.attributes.type}` }),
+				{ status: STATUS_CODE.NotFound, headers },
+			);
+		}
+
+		req = toDeliveryRequest(req, `/${eventService}`, event);
+	}
+
 	// NOTE: You can test WebSocket in the main worker by uncommenting below.
 	// if (pathname === '/_internal/ws') {
 	// 	const upgrade = req.headers.get("upgrade") || "";
@@ -52,8 +360,7 @@ Deno.serve(async (req: Request) => {
 	// 	return response; // 101 (Switching Protocols)
 	// }
 
-	const path_parts = pathname.split('/');
-	const service_name = path_parts[1];
+	const service_name = eventService ?? resolveServiceName(req, pathname);
 
 	if (!service_name || service_name === '') {
 		const error = { msg: 'missing function name in request' };
@@ -63,74 +370,110 @@ Deno.serve(async (req: Request) => {
 		);
 	}
 
-	const servicePath = `./examples/${service_name}`;
+	// Canary rollouts route a share of the traffic to another version of the
+	// service (`<service>@<version>`).
+	const split = splitTraffic(req, service_name);
+
+	accessLog.service = split.serviceName;
+
+	const servicesRoot = resolveServicesRoot(req);
+
+	if (!servicesRoot) {
+		return new Response(
+			JSON.stringify({ msg: 'invalid tenant' }),
+			{ status: STATUS_CODE.BadRequest, headers },
+		);
+	}
+
+	accessLog.tenant = servicesRoot.tenant;
+
+	const servicePath = await resolveServicePath(servicesRoot.root, split.serviceName);
+
+	if (!servicePath) {
+		return new Response(
+			JSON.stringify({ msg: 'invalid function name in request' }),
+			{ status: STATUS_CODE.BadRequest, headers },
+		);
+	}
+
 	// console.error(`serving the request with ${servicePath}`);
 
-	const createWorker = async () => {
-		const memoryLimitMb = 150;
-		const workerTimeoutMs = 5 * 60 * 1000;
-		const noModuleCache = false;
-
-		// you can provide an import map inline
-		// const inlineImportMap = {
-		//   imports: {
-		//     "std/": "https://deno.land/std@0.131.0/",
-		//     "cors": "./examples/_shared/cors.ts"
-		//   }
-		// }
-
-		// const importMapPath = `data:${encodeURIComponent(JSON.stringify(importMap))}?${encodeURIComponent('/home/deno/functions/test')}`;
-		const importMapPath = null;
-		const envVarsObj = Deno.env.toObject();
-		const envVars = Object.keys(envVarsObj).map((k) => [k, envVarsObj[k]]);
-		const forceCreate = false;
-		const netAccessDisabled = false;
-
-		// load source from an eszip
-		//const maybeEszip = await Deno.readFile('./bin.eszip');
-		//const maybeEntrypoint = 'file:///src/index.ts';
-
-		// const maybeEntrypoint = 'file:///src/index.ts';
-		// or load module source from an inline module
-		// const maybeModuleCode = 'Deno.serve((req) => new Response("Hello from Module Code"));';
-		//
-		const cpuTimeSoftLimitMs = 10000;
-		const cpuTimeHardLimitMs = 20000;
-
-		return await EdgeRuntime.userWorkers.create({
-			servicePath,
-			memoryLimitMb,
-			workerTimeoutMs,
-			noModuleCache,
-			importMapPath,
-			envVars,
-			forceCreate,
-			netAccessDisabled,
-			cpuTimeSoftLimitMs,
-			cpuTimeHardLimitMs,
-			// maybeEszip,
-			// maybeEntrypoint,
-			// maybeModuleCode,
+	const serviceConfig = await loadServiceConfig(servicePath);
+
+	// NOTE: Keyed by path, as services of different tenants may share a name.
+	watchDependencies(servicePath, serviceConfig.dependencies);
+
+	const dependencyFailure = hardDependencyFailure(servicePath);
+
+	if (dependencyFailure) {
+		return new Response(
+			JSON.stringify({ msg: dependencyFailure }),
+			{ status: STATUS_CODE.ServiceUnavailable, headers },
+		);
+	}
+
+	let jwtClaims = null;
+
+	if (serviceConfig.jwt) {
+		const result = await verifyJwt(req, servicePath, serviceConfig.jwt);
+
+		if (!result.ok) {
+			return new Response(
+				JSON.stringify({ msg: `unauthorized: ${result.reason}` }),
+				{ status: STATUS_CODE.Unauthorized, headers },
+			);
+		}
+
+		jwtClaims = result.claims;
+	}
+
+	const identity = await deriveIdentity({ req, jwtClaims });
+
+	req = applyRequestHeaderPolicy(req, serviceConfig.headers);
+
+	if (jwtClaims) {
+		req = withClaims(req, jwtClaims);
+	}
+
+	req = withIdentity(req, identity);
+
+	const mirrored = sampleMirror(req, service_name);
+
+	if (mirrored) {
+		sendMirror(servicesRoot.root, service_name, mirrored, async (servicePath, req, signal) => {
+			const config = await loadServiceConfig(servicePath);
+			const worker = await createServiceWorker(servicePath, config, servicesRoot.tenant);
+			return await worker.fetch(req, { signal });
 		});
-	};
+	}
 
 	const callWorker = async () => {
 		try {
 			// If a worker for the given service path already exists,
 			// it will be reused by default.
 			// Update forceCreate option in createWorker to force create a new worker for each request.
-			const worker = await createWorker();
-			const controller = new AbortController();
+			return await withResponseCache(servicePath, req, async (req) => {
+				const worker = await createServiceWorker(servicePath, serviceConfig, servicesRoot.tenant);
+				const controller = new AbortController();
 
-			const signal = controller.signal;
-			// Optional: abort the request after a timeout
-			//setTimeout(() => controller.abort(), 2 * 60 * 1000);
+				const signal = controller.signal;
+				// Optional: abort the request after a timeout
+				//setTimeout(() => controller.abort(), 2 * 60 * 1000);
 
-			return await worker.fetch(req, { signal });
+				const resp = await worker.fetch(req, { signal });
+
+				return applyResponseHeaderPolicy(resp, serviceConfig.headers);
+			});
 		} catch (e) {
 			console.error(e);
 
 			if (e instanceof Deno.errors.WorkerRequestCancelled) {
+				// Set by `UserWorker.fetch` to the reason the supervisor shut
+				// the worker down for, e.g. `CPUTime`.
+				const { shutdownReason } = e as { shutdownReason?: string | null };
+
+				accessLog.shutdownReason = shutdownReason ?? null;
+
 				headers.append('Connection', 'close');
 
 				// XXX(Nyannyacha): I can't think right now how to re-poll
@@ -148,6 +491,58 @@ Deno.serve(async (req: Request) => {
 				// return await callWorker();
 			}
 
+			if (e instanceof Deno.errors.WorkerQueueFull) {
+				return new Response(
+					JSON.stringify({ msg: e.toString() }),
+					{
+						status: STATUS_CODE.ServiceUnavailable,
+						headers,
+					},
+				);
+			}
+
+			if (e instanceof Deno.errors.WorkerConcurrencyLimit) {
+				return new Response(
+					JSON.stringify({ msg: e.toString() }),
+					{
+						status: STATUS_CODE.TooManyRequests,
+						headers,
+					},
+				);
+			}
+
+			if (e instanceof Deno.errors.WorkerTenantQuotaExceeded) {
+				const retryAfter = e.message.match(/retry after (\d+)s/)?.[1];
+
+				if (retryAfter) {
+					headers.set('Retry-After', retryAfter);
+				}
+
+				return new Response(
+					JSON.stringify({ msg: e.toString() }),
+					{
+						status: STATUS_CODE.TooManyRequests,
+						headers,
+					},
+				);
+			}
+
+			if (e instanceof Deno.errors.WorkerCircuitOpen) {
+				const retryAfter = e.message.match(/retry after (\d+)s/)?.[1];
+
+				if (retryAfter) {
+					headers.set('Retry-After', retryAfter);
+				}
+
+				return new Response(
+					JSON.stringify({ msg: e.toString() }),
+					{
+						status: STATUS_CODE.ServiceUnavailable,
+						headers,
+					},
+				);
+			}
+
 			const error = { msg: e.toString() };
 			return new Response(
 				JSON.stringify(error),
@@ -159,5 +554,7 @@ Deno.serve(async (req: Request) => {
 		}
 	};
 
-	return callWorker();
-});
+	return withSplitCookie(await callWorker(), split);
+}
+
+Deno.serve((req: Request) => withAccessLog(req, handleRequest));
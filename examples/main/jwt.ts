@@ -0,0 +1,90 @@
+// @ts-ignore
+import * as jose from 'https://deno.land/x/jose@v4.14.4/index.ts';
+
+import { encodeJsonBase64 } from './base64.ts';
+import { JwtConfig } from './service_config.ts';
+
+// Verified claims are forwarded to the function in this header as
+// base64-encoded JSON.
+export const JWT_CLAIMS_HEADER = 'x-jwt-claims';
+
+type KeyLike = Parameters<typeof jose.jwtVerify>[1];
+
+// Keyed by the config the key was resolved from, so that reloading the config
+// of a service (see `invalidateServiceConfig`) resolves its key again.
+const keyCache = new WeakMap<JwtConfig, KeyLike>();
+
+async function resolveKey(servicePath: string, config: JwtConfig): Promise<KeyLike> {
+	const cached = keyCache.get(config);
+
+	if (cached) {
+		return cached;
+	}
+
+	let key: KeyLike;
+
+	if (config.algorithm === 'HS256') {
+		const secret = config.secretEnv ? Deno.env.get(config.secretEnv) : undefined;
+
+		if (!secret) {
+			throw new Error(`jwt secret is not set (${config.secretEnv ?? 'secret_env'})`);
+		}
+
+		key = new TextEncoder().encode(secret);
+	} else if (config.jwksUrl) {
+		key = jose.createRemoteJWKSet(new URL(config.jwksUrl));
+	} else if (config.publicKeyPath) {
+		const pem = await Deno.readTextFile(`${servicePath}/${config.publicKeyPath}`);
+		key = await jose.importSPKI(pem, 'RS256');
+	} else {
+		throw new Error('RS256 requires either jwks_url or public_key_path');
+	}
+
+	keyCache.set(config, key);
+	return key;
+}
+
+export type JwtResult =
+	| { ok: true; claims: jose.JWTPayload }
+	| { ok: false; reason: string };
+
+export async function verifyJwt(
+	req: Request,
+	servicePath: string,
+	config: JwtConfig,
+): Promise<JwtResult> {
+	const [scheme, token] = (req.headers.get('authorization') ?? '').split(' ');
+
+	if (scheme?.toLowerCase() !== 'bearer' || !token) {
+		return { ok: false, reason: 'missing bearer token' };
+	}
+
+	try {
+		const { payload } = await jose.jwtVerify(token, await resolveKey(servicePath, config), {
+			algorithms: [config.algorithm],
+			issuer: config.issuer,
+			audience: config.audience,
+		});
+
+		return { ok: true, claims: payload };
+	} catch (e) {
+		return { ok: false, reason: e.toString() };
+	}
+}
+
+// Returns a copy of the request carrying the verified claims. A client supplied
+// claims header is never passed through.
+export function withClaims(req: Request, claims: jose.JWTPayload | null): Request {
+	const headers = new Headers(req.headers);
+
+	headers.delete(JWT_CLAIMS_HEADER);
+
+	if (claims) {
+		headers.set(JWT_CLAIMS_HEADER, encodeJsonBase64(claims));
+	}
+
+	const newReq = new Request(req, { headers });
+
+	EdgeRuntime.applySupabaseTag(req, newReq);
+	return newReq;
+}
@@ -0,0 +1,83 @@
+// Shadow traffic, for validating a new implementation of a service against
+// production requests.
+//
+// `MIRROR_CONFIG_PATH` points at a JSON file declaring, per service name, the
+// service a share of its requests is duplicated to:
+//
+// ```json
+// {
+//   "my-fn": { "target": "my-fn-next", "percent": 5 }
+// }
+// ```
+//
+// The mirrored request is sent in the background; its response is discarded
+// and its failures are only logged, so clients never observe the target.
+
+import { resolveServicePath } from './service_path.ts';
+
+interface MirrorConfig {
+	target: string;
+	percent: number;
+}
+
+let mirrors: Record<string, MirrorConfig> | null = null;
+
+function loadMirrors(): Record<string, MirrorConfig> {
+	if (!mirrors) {
+		const path = Deno.env.get('MIRROR_CONFIG_PATH');
+
+		try {
+			mirrors = path ? JSON.parse(Deno.readTextFileSync(path)) : {};
+		} catch (e) {
+			console.error('failed to load mirror config:', e);
+			mirrors = {};
+		}
+	}
+
+	return mirrors!;
+}
+
+// Returns a copy of the request to mirror, or `null` if it isn't sampled.
+//
+// NOTE: Must be called before the request is dispatched, as it tees the body.
+export function sampleMirror(req: Request, serviceName: string): Request | null {
+	const config = loadMirrors()[serviceName];
+
+	// Upgraded connections can't be duplicated.
+	if (!config || req.headers.get('upgrade') || Math.random() * 100 >= config.percent) {
+		return null;
+	}
+
+	// NOTE: The copy must not carry the supabase tag of the request. The tag
+	// hands the client connection over to the worker the request is sent to,
+	// so the mirrored request would race the original one for it, and might
+	// take its cancellation, early hints and trailers away.
+	return req.clone();
+}
+
+export function sendMirror(
+	root: string,
+	serviceName: string,
+	req: Request,
+	fetchWorker: (servicePath: string, req: Request, signal: AbortSignal) => Promise<Response>,
+) {
+	const target = loadMirrors()[serviceName].target;
+	const controller = new AbortController();
+
+	// The mirrored request doesn't outlive the client of the original one.
+	req.signal.addEventListener('abort', () => controller.abort(), { once: true });
+
+	(async () => {
+		const servicePath = await resolveServicePath(root, target);
+
+		if (!servicePath) {
+			throw new Error(`invalid mirror target: ${target}`);
+		}
+
+		const resp = await fetchWorker(servicePath, req, controller.signal);
+
+		await resp.body?.cancel();
+	})().catch((e) => {
+		console.error(`failed to mirror a request of ${serviceName} to ${target}:`, e);
+	});
+}
@@ -0,0 +1,49 @@
+// Boots the workers of critical services ahead of their first request, so that
+// it isn't slowed down by cold module loading.
+//
+// `PREWARM_SERVICES` lists them, comma-separated, by their name under the
+// default services root (see `tenant.ts`). They are booted when the main
+// worker starts, and again on `POST /_internal/admin/prewarm`, which also
+// reloads their `function.toml` (e.g. after a deploy changed it).
+
+import { ServiceConfig, invalidateServiceConfig, loadServiceConfig } from './service_config.ts';
+import { resolveServicePath } from './service_path.ts';
+import { defaultRoot } from './tenant.ts';
+
+export interface PrewarmResult {
+	service: string;
+	warmed: boolean;
+	error?: string;
+}
+
+type WarmFn = (servicePath: string, config: ServiceConfig) => Promise<unknown>;
+
+const services = (Deno.env.get('PREWARM_SERVICES') ?? '')
+	.split(',')
+	.map((it) => it.trim())
+	.filter((it) => it !== '');
+
+async function prewarm(service: string, warm: WarmFn, reload: boolean): Promise<PrewarmResult> {
+	try {
+		const servicePath = await resolveServicePath(defaultRoot, service);
+
+		if (!servicePath) {
+			throw new Error('invalid service name');
+		}
+
+		if (reload) {
+			invalidateServiceConfig(servicePath);
+		}
+
+		await warm(servicePath, await loadServiceConfig(servicePath));
+
+		return { service, warmed: true };
+	} catch (e) {
+		console.error(`failed to prewarm ${service}:`, e);
+		return { service, warmed: false, error: e.toString() };
+	}
+}
+
+export function prewarmServices(warm: WarmFn, reload = false): Promise<PrewarmResult[]> {
+	return Promise.all(services.map((service) => prewarm(service, warm, reload)));
+}
@@ -0,0 +1,498 @@
+// Invokes services for the messages of Redis streams and NATS subjects, so
+// they can be triggered by queues without a bridge in front of the runtime.
+//
+// `QUEUE_CONSUMERS_PATH` points at a JSON file listing the consumers:
+//
+// ```json
+// [
+//   {
+//     "kind": "redis",
+//     "url": "redis://:password@localhost:6379",
+//     "stream": "orders",
+//     "group": "edge-runtime",
+//     "service": "orders",
+//     "concurrency": 4,
+//     "maxDeliveries": 5,
+//     "deadLetter": "orders:dead"
+//   },
+//   {
+//     "kind": "nats",
+//     "url": "nats://localhost:4222",
+//     "subject": "jobs.>",
+//     "queueGroup": "edge-runtime",
+//     "service": "jobs"
+//   }
+// ]
+// ```
+//
+// Each message is posted to `/<service>` with its payload as the body. A 2xx
+// response acks it. Any other outcome nacks it, and it's delivered again until
+// it has been delivered `maxDeliveries` times (default 3), after which it's
+// moved to the `deadLetter` stream or subject, if any, and dropped otherwise.
+//
+// - Redis: messages are read through a consumer group. A nacked message is
+//   added to the stream again with its delivery count in the `deliveries`
+//   field. The payload is the `data` field, or all fields as JSON.
+// - NATS: subscribe to the deliver subject of a JetStream push consumer to get
+//   acks (`+ACK`, `-NAK` and `+TERM`). Core NATS messages have nothing to ack,
+//   so they're delivered once.
+
+import { resolveServicePath } from './service_path.ts';
+import { defaultRoot } from './tenant.ts';
+
+export interface QueueConsumerConfig {
+	kind: 'redis' | 'nats';
+	url: string;
+	service: string;
+	concurrency?: number;
+	maxDeliveries?: number;
+	deadLetter?: string;
+	// redis
+	stream?: string;
+	group?: string;
+	// nats
+	subject?: string;
+	queueGroup?: string;
+}
+
+interface QueueMessage {
+	id: string;
+	payload: Uint8Array;
+	deliveries: number;
+}
+
+type InvokeFn = (servicePath: string, req: Request) => Promise<Response>;
+
+const RECONNECT_DELAY_MS = 1000;
+const REDIS_BLOCK_MS = 5000;
+
+const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+
+function sleep(ms: number) {
+	return new Promise((resolve) => setTimeout(resolve, ms));
+}
+
+function concat(chunks: (string | Uint8Array)[]): Uint8Array {
+	const bytes = chunks.map((it) => typeof it === 'string' ? encoder.encode(it) : it);
+	const buf = new Uint8Array(bytes.reduce((size, it) => size + it.length, 0));
+	let offset = 0;
+
+	for (const it of bytes) {
+		buf.set(it, offset);
+		offset += it.length;
+	}
+
+	return buf;
+}
+
+// Counts the messages being processed by a consumer.
+class Slots {
+	#free: number;
+	#waiters: (() => void)[] = [];
+
+	constructor(size: number) {
+		this.#free = size;
+	}
+
+	get free() {
+		return this.#free;
+	}
+
+	async acquire() {
+		while (this.#free === 0) {
+			await new Promise<void>((resolve) => this.#waiters.push(resolve));
+		}
+
+		this.#free--;
+	}
+
+	release() {
+		this.#free++;
+		this.#waiters.shift()?.();
+	}
+}
+
+// Reads lines and sized chunks off a connection.
+class BufferedConn {
+	#buf = new Uint8Array(0);
+	#writing = Promise.resolve();
+
+	constructor(readonly conn: Deno.Conn) {}
+
+	async #fill() {
+		const chunk = new Uint8Array(64 * 1024);
+		const n = await this.conn.read(chunk);
+
+		if (n === null) {
+			throw new Error('connection closed');
+		}
+
+		const buf = new Uint8Array(this.#buf.length + n);
+
+		buf.set(this.#buf);
+		buf.set(chunk.subarray(0, n), this.#buf.length);
+		this.#buf = buf;
+	}
+
+	async readLine(): Promise<string> {
+		let idx;
+
+		while ((idx = this.#buf.findIndex((it, i) => it === 0x0a && this.#buf[i - 1] === 0x0d)) < 0) {
+			await this.#fill();
+		}
+
+		const line = decoder.decode(this.#buf.subarray(0, idx - 1));
+
+		this.#buf = this.#buf.subarray(idx + 1);
+		return line;
+	}
+
+	// Reads `n` bytes followed by CRLF.
+	async readChunk(n: number): Promise<Uint8Array> {
+		while (this.#buf.length < n + 2) {
+			await this.#fill();
+		}
+
+		const chunk = this.#buf.slice(0, n);
+
+		this.#buf = this.#buf.subarray(n + 2);
+		return chunk;
+	}
+
+	// NOTE: Serialized so that concurrent writes don't interleave.
+	write(data: string | Uint8Array): Promise<void> {
+		const bytes = typeof data === 'string' ? encoder.encode(data) : data;
+		const write = async () => {
+			for (let written = 0; written < bytes.length;) {
+				written += await this.conn.write(bytes.subarray(written));
+			}
+		};
+
+		const result = this.#writing.then(write, write);
+
+		this.#writing = result.catch(() => {});
+		return result;
+	}
+}
+
+// A minimal RESP2 client, enough for the stream commands.
+class RedisConn {
+	#queue = Promise.resolve<unknown>(null);
+
+	private constructor(readonly conn: BufferedConn) {}
+
+	static async connect(url: URL): Promise<RedisConn> {
+		const conn = await Deno.connect({
+			hostname: url.hostname || 'localhost',
+			port: url.port ? Number(url.port) : 6379,
+		});
+		const redis = new RedisConn(new BufferedConn(conn));
+
+		if (url.password) {
+			const password = decodeURIComponent(url.password);
+			const user = url.username ? [decodeURIComponent(url.username)] : [];
+
+			await redis.command('AUTH', ...user, password);
+		}
+
+		return redis;
+	}
+
+	close() {
+		this.conn.conn.close();
+	}
+
+	// NOTE: Serialized so that the replies of overlapping calls don't
+	// interleave.
+	command(...args: (string | Uint8Array)[]): Promise<any> {
+		const run = async () => {
+			const parts = args.map((it) => typeof it === 'string' ? encoder.encode(it) : it);
+
+			await this.conn.write(
+				concat([`*${parts.length}\r\n`, ...parts.flatMap((it) => [`$${it.length}\r\n`, it, '\r\n'])]),
+			);
+
+			return await this.#reply();
+		};
+
+		const result = this.#queue.then(run, run);
+
+		this.#queue = result.catch(() => null);
+		return result;
+	}
+
+	async #reply(): Promise<any> {
+		const line = await this.conn.readLine();
+		const rest = line.slice(1);
+
+		switch (line[0]) {
+			case '+':
+				return rest;
+			case '-':
+				throw new Error(rest);
+			case ':':
+				return Number(rest);
+			case '$':
+				return rest === '-1' ? null : await this.conn.readChunk(Number(rest));
+			case '*': {
+				if (rest === '-1') {
+					return null;
+				}
+
+				const items = [];
+
+				for (let i = 0; i < Number(rest); i++) {
+					items.push(await this.#reply());
+				}
+
+				return items;
+			}
+			default:
+				throw new Error(`unexpected redis reply: ${line}`);
+		}
+	}
+}
+
+function str(value: unknown): string {
+	return value instanceof Uint8Array ? decoder.decode(value) : String(value);
+}
+
+async function consumeRedis(
+	config: QueueConsumerConfig,
+	slots: Slots,
+	handle: (msg: QueueMessage) => Promise<boolean>,
+) {
+	const url = new URL(config.url);
+	const stream = config.stream!;
+	const group = config.group ?? 'edge-runtime';
+	const consumer = `edge-runtime-${crypto.randomUUID()}`;
+	const maxDeliveries = config.maxDeliveries ?? 3;
+
+	// NOTE: Reads block the connection, so acks go through another one.
+	const reader = await RedisConn.connect(url);
+	const writer = await RedisConn.connect(url);
+
+	try {
+		await reader.command('XGROUP', 'CREATE', stream, group, '$', 'MKSTREAM').catch((e) => {
+			if (!e.message.startsWith('BUSYGROUP')) {
+				throw e;
+			}
+		});
+
+		while (true) {
+			await slots.acquire();
+			slots.release();
+
+			const reply = await reader.command(
+				'XREADGROUP',
+				'GROUP',
+				group,
+				consumer,
+				'COUNT',
+				String(slots.free),
+				'BLOCK',
+				String(REDIS_BLOCK_MS),
+				'STREAMS',
+				stream,
+				'>',
+			);
+
+			for (const [id, rawFields] of reply?.[0]?.[1] ?? []) {
+				const fields: Record<string, string> = {};
+
+				for (let i = 0; i + 1 < rawFields.length; i += 2) {
+					fields[str(rawFields[i])] = str(rawFields[i + 1]);
+				}
+
+				const deliveries = (Number(fields.deliveries) || 0) + 1;
+				const payload = 'data' in fields ? encoder.encode(fields.data) : encoder.encode(JSON.stringify(fields));
+
+				await slots.acquire();
+
+				handle({ id: str(id), payload, deliveries }).then(async (ok) => {
+					if (!ok) {
+						const target = deliveries < maxDeliveries ? stream : config.deadLetter;
+						const retry = { ...fields, deliveries: String(deliveries) };
+
+						if (target) {
+							await writer.command('XADD', target, '*', ...Object.entries(retry).flat());
+						}
+					}
+
+					await writer.command('XACK', stream, group, str(id));
+				}).catch((e) => {
+					console.error(`failed to settle message ${str(id)} of ${stream}:`, e);
+				}).finally(() => slots.release());
+			}
+		}
+	} finally {
+		reader.close();
+		writer.close();
+	}
+}
+
+async function consumeNats(
+	config: QueueConsumerConfig,
+	slots: Slots,
+	handle: (msg: QueueMessage) => Promise<boolean>,
+) {
+	const url = new URL(config.url);
+	const subject = config.subject!;
+	const maxDeliveries = config.maxDeliveries ?? 3;
+	const conn = new BufferedConn(
+		await Deno.connect({
+			hostname: url.hostname || 'localhost',
+			port: url.port ? Number(url.port) : 4222,
+		}),
+	);
+
+	const connectOpts: Record<string, unknown> = { verbose: false, pedantic: false, headers: false };
+
+	if (url.username) {
+		connectOpts.user = decodeURIComponent(url.username);
+		connectOpts.pass = decodeURIComponent(url.password);
+	}
+
+	const publish = (subject: string, payload: Uint8Array) =>
+		conn.write(concat([`PUB ${subject} ${payload.length}\r\n`, payload, '\r\n']));
+
+	try {
+		await conn.write(`CONNECT ${JSON.stringify(connectOpts)}\r\n`);
+		await conn.write(`SUB ${[subject, config.queueGroup, '1'].filter(Boolean).join(' ')}\r\n`);
+
+		while (true) {
+			const line = await conn.readLine();
+			const [op, ...args] = line.split(' ');
+
+			if (op === 'PING') {
+				await conn.write('PONG\r\n');
+				continue;
+			}
+
+			if (op === '-ERR') {
+				throw new Error(`nats error: ${args.join(' ')}`);
+			}
+
+			if (op !== 'MSG') {
+				continue;
+			}
+
+			const [msgSubject, , replyTo, size] = args.length === 4 ? args : [args[0], args[1], null, args[2]];
+			const payload = await conn.readChunk(Number(size));
+
+			// `$JS.ACK.<stream>.<consumer>.<delivered>.<stream seq>.<consumer seq>...`
+			const ackTokens = replyTo?.startsWith('$JS.ACK.') ? replyTo.split('.') : null;
+			const deliveries = ackTokens ? Number(ackTokens[4]) || 1 : 1;
+
+			await slots.acquire();
+
+			handle({ id: ackTokens?.[5] ?? msgSubject, payload, deliveries }).then(async (ok) => {
+				if (!ackTokens) {
+					if (!ok && config.deadLetter) {
+						await publish(config.deadLetter, payload);
+					}
+
+					return;
+				}
+
+				if (ok) {
+					await publish(replyTo!, encoder.encode('+ACK'));
+				} else if (deliveries < maxDeliveries) {
+					await publish(replyTo!, encoder.encode('-NAK'));
+				} else {
+					if (config.deadLetter) {
+						await publish(config.deadLetter, payload);
+					}
+
+					await publish(replyTo!, encoder.encode('+TERM'));
+				}
+			}).catch((e) => {
+				console.error(`failed to settle message of ${msgSubject}:`, e);
+			}).finally(() => slots.release());
+		}
+	} finally {
+		conn.conn.close();
+	}
+}
+
+async function run(config: QueueConsumerConfig, invoke: InvokeFn) {
+	const source = config.kind === 'redis' ? `redis:${config.stream}` : `nats:${config.subject}`;
+	const slots = new Slots(Math.max(config.concurrency ?? 1, 1));
+
+	const handle = async (msg: QueueMessage) => {
+		try {
+			const servicePath = await resolveServicePath(defaultRoot, config.service);
+
+			if (!servicePath) {
+				throw new Error(`invalid service: ${config.service}`);
+			}
+
+			const resp = await invoke(
+				servicePath,
+				new Request(`http://localhost/${config.service}`, {
+					method: 'POST',
+					body: msg.payload,
+					headers: {
+						'x-queue-source': source,
+						'x-queue-message-id': msg.id,
+						'x-queue-deliveries': String(msg.deliveries),
+					},
+				}),
+			);
+
+			await resp.body?.cancel();
+			return resp.ok;
+		} catch (e) {
+			console.error(`failed to deliver message ${msg.id} of ${source}:`, e);
+			return false;
+		}
+	};
+
+	while (true) {
+		try {
+			if (config.kind === 'redis') {
+				await consumeRedis(config, slots, handle);
+			} else {
+				await consumeNats(config, slots, handle);
+			}
+		} catch (e) {
+			console.error(`queue consumer of ${source} failed, reconnecting:`, e);
+		}
+
+		await sleep(RECONNECT_DELAY_MS);
+	}
+}
+
+function loadConsumers(): QueueConsumerConfig[] {
+	const path = Deno.env.get('QUEUE_CONSUMERS_PATH');
+
+	if (!path) {
+		return [];
+	}
+
+	try {
+		const consumers = JSON.parse(Deno.readTextFileSync(path));
+
+		return (Array.isArray(consumers) ? consumers : []).filter((it) => {
+			const valid = typeof it?.service === 'string' && typeof it?.url === 'string' &&
+				((it.kind === 'redis' && typeof it.stream === 'string') ||
+					(it.kind === 'nats' && typeof it.subject === 'string'));
+
+			if (!valid) {
+				console.error('invalid queue consumer:', it);
+			}
+
+			return valid;
+		});
+	} catch (e) {
+		console.error('failed to load queue consumers:', e);
+		return [];
+	}
+}
+
+export function startQueueConsumers(invoke: InvokeFn) {
+	for (const config of loadConsumers()) {
+		run(config, invoke);
+	}
+}
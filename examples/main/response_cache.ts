@@ -0,0 +1,262 @@
+// Shared HTTP cache in front of user workers, so that hot idempotent endpoints
+// skip worker invocation entirely.
+//
+// `RESPONSE_CACHE` enables it: `memory`, or `disk:<dir>` to also persist
+// entries across restarts. Responses are stored per service, keyed by method,
+// URL and the request headers named by `Vary`, and only when `Cache-Control`
+// allows a shared cache to store them (`max-age`/`s-maxage`, no `private`,
+// `no-store` or `Set-Cookie`). Stale entries carrying an `ETag` or
+// `Last-Modified` are revalidated with the worker instead of being refetched.
+//
+// `RESPONSE_CACHE_MAX_ENTRIES` (default 1000) bounds the number of in-memory
+// entries and `RESPONSE_CACHE_MAX_BODY_BYTES` (default 1MiB) the size of a
+// stored body.
+
+interface CacheEntry {
+	vary: Record<string, string | null>;
+	status: number;
+	statusText: string;
+	headers: [string, string][];
+	body: Uint8Array;
+	storedAt: number;
+	freshForMs: number;
+}
+
+const mode = Deno.env.get('RESPONSE_CACHE');
+const diskDir = mode?.startsWith('disk:') ? mode.slice('disk:'.length) : null;
+const maxEntries = parseInt(Deno.env.get('RESPONSE_CACHE_MAX_ENTRIES') ?? '1000', 10);
+const maxBodyBytes = parseInt(Deno.env.get('RESPONSE_CACHE_MAX_BODY_BYTES') ?? '1048576', 10);
+
+const CACHEABLE_STATUSES = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+// NOTE: Entries are kept in insertion order, which doubles as LRU order since
+// hits re-insert them.
+const memory = new Map<string, CacheEntry[]>();
+
+function parseCacheControl(value: string | null): Map<string, string | null> {
+	const directives = new Map<string, string | null>();
+
+	for (const part of (value ?? '').split(',')) {
+		const [name, arg] = part.split('=', 2).map((it) => it.trim());
+
+		if (name) {
+			directives.set(name.toLowerCase(), arg?.replace(/^"|"$/g, '') ?? null);
+		}
+	}
+
+	return directives;
+}
+
+function cacheKey(servicePath: string, req: Request) {
+	return `${servicePath} ${req.method === 'HEAD' ? 'GET' : req.method} ${req.url}`;
+}
+
+function varyOf(resp: Headers, req: Request): Record<string, string | null> | null {
+	const names = (resp.get('vary') ?? '').split(',').map((it) => it.trim().toLowerCase()).filter(
+		Boolean,
+	);
+
+	if (names.includes('*')) {
+		return null;
+	}
+
+	return Object.fromEntries(names.map((name) => [name, req.headers.get(name)]));
+}
+
+function matchesVary(entry: CacheEntry, req: Request) {
+	return Object.entries(entry.vary).every(([name, value]) => req.headers.get(name) === value);
+}
+
+function ageMs(entry: CacheEntry) {
+	return Date.now() - entry.storedAt;
+}
+
+function freshnessMs(resp: Response, directives: Map<string, string | null>): number | null {
+	const seconds = directives.get('s-maxage') ?? directives.get('max-age');
+
+	if (seconds === undefined || seconds === null || isNaN(parseInt(seconds, 10))) {
+		return null;
+	}
+
+	const age = parseInt(resp.headers.get('age') ?? '0', 10) || 0;
+
+	return Math.max(0, parseInt(seconds, 10) - age) * 1000;
+}
+
+async function diskPath(key: string) {
+	const digest = await crypto.subtle.digest('SHA-256', new TextEncoder().encode(key));
+	const name = Array.from(new Uint8Array(digest), (it) => it.toString(16).padStart(2, '0')).join('');
+
+	return `${diskDir}/${name}.json`;
+}
+
+async function loadEntries(key: string): Promise<CacheEntry[]> {
+	const cached = memory.get(key);
+
+	if (cached || !diskDir) {
+		return cached ?? [];
+	}
+
+	try {
+		const stored = JSON.parse(await Deno.readTextFile(await diskPath(key)));
+		const entries = stored.map((it: CacheEntry & { body: number[] }) => ({
+			...it,
+			body: new Uint8Array(it.body),
+		}));
+
+		memory.set(key, entries);
+		return entries;
+	} catch (e) {
+		if (!(e instanceof Deno.errors.NotFound)) {
+			console.error('failed to read cached response:', e);
+		}
+
+		return [];
+	}
+}
+
+function touchEntries(key: string, entries: CacheEntry[]) {
+	memory.delete(key);
+	memory.set(key, entries);
+
+	while (memory.size > maxEntries) {
+		memory.delete(memory.keys().next().value);
+	}
+}
+
+async function saveEntries(key: string, entries: CacheEntry[]) {
+	touchEntries(key, entries);
+
+	if (!diskDir) {
+		return;
+	}
+
+	try {
+		await Deno.mkdir(diskDir, { recursive: true });
+		await Deno.writeTextFile(
+			await diskPath(key),
+			JSON.stringify(entries.map((it) => ({ ...it, body: Array.from(it.body) }))),
+		);
+	} catch (e) {
+		console.error('failed to persist cached response:', e);
+	}
+}
+
+function toResponse(entry: CacheEntry, req: Request, status: string) {
+	const headers = new Headers(entry.headers);
+
+	headers.set('age', String(Math.floor(ageMs(entry) / 1000)));
+	headers.set('x-cache', status);
+
+	const etag = headers.get('etag');
+
+	if (etag && req.headers.get('if-none-match') === etag) {
+		return new Response(null, { status: 304, headers });
+	}
+
+	return new Response(req.method === 'HEAD' ? null : entry.body, {
+		status: entry.status,
+		statusText: entry.statusText,
+		headers,
+	});
+}
+
+// Whether a shared cache may store `resp` for `req`. Requests carrying
+// credentials are only shared when the response is explicitly public.
+function storable(req: Request, resp: Response, directives: Map<string, string | null>) {
+	const hasCredentials = req.headers.has('authorization') ||
+		req.headers.has('x-edge-runtime-identity');
+
+	return (
+		CACHEABLE_STATUSES.includes(resp.status) &&
+		!directives.has('no-store') &&
+		!directives.has('private') &&
+		!resp.headers.has('set-cookie') &&
+		(!hasCredentials || directives.has('public') || directives.has('s-maxage'))
+	);
+}
+
+async function store(key: string, req: Request, resp: Response, entries: CacheEntry[]) {
+	const directives = parseCacheControl(resp.headers.get('cache-control'));
+	const freshForMs = freshnessMs(resp, directives);
+	const vary = varyOf(resp.headers, req);
+	const contentLength = parseInt(resp.headers.get('content-length') ?? '', 10);
+
+	if (
+		req.method !== 'GET' || freshForMs === null || vary === null ||
+		!storable(req, resp, directives) || !(contentLength <= maxBodyBytes)
+	) {
+		return resp;
+	}
+
+	const body = new Uint8Array(await resp.arrayBuffer());
+	const entry: CacheEntry = {
+		vary,
+		status: resp.status,
+		statusText: resp.statusText,
+		headers: Array.from(resp.headers.entries()),
+		body,
+		storedAt: Date.now(),
+		freshForMs,
+	};
+
+	await saveEntries(key, [entry, ...entries.filter((it) => !matchesVary(it, req))]);
+
+	return toResponse(entry, req, 'MISS');
+}
+
+export type WorkerFetch = (req: Request) => Promise<Response>;
+
+export async function withResponseCache(servicePath: string, req: Request, fetchWorker: WorkerFetch) {
+	const requestDirectives = parseCacheControl(req.headers.get('cache-control'));
+
+	if (!mode || !['GET', 'HEAD'].includes(req.method) || requestDirectives.has('no-store')) {
+		return await fetchWorker(req);
+	}
+
+	const key = cacheKey(servicePath, req);
+	const entries = await loadEntries(key);
+	const entry = entries.find((it) => matchesVary(it, req));
+
+	if (entry && ageMs(entry) < entry.freshForMs && !requestDirectives.has('no-cache')) {
+		touchEntries(key, entries);
+		return toResponse(entry, req, 'HIT');
+	}
+
+	const etag = entry?.headers.find(([name]) => name === 'etag')?.[1];
+	const lastModified = entry?.headers.find(([name]) => name === 'last-modified')?.[1];
+
+	if (!entry || (!etag && !lastModified)) {
+		return await store(key, req, await fetchWorker(req), entries);
+	}
+
+	const headers = new Headers(req.headers);
+
+	if (etag) {
+		headers.set('if-none-match', etag);
+	}
+	if (lastModified) {
+		headers.set('if-modified-since', lastModified);
+	}
+
+	const revalidateReq = new Request(req, { headers });
+
+	EdgeRuntime.applySupabaseTag(req, revalidateReq);
+
+	const resp = await fetchWorker(revalidateReq);
+
+	if (resp.status !== 304) {
+		return await store(key, req, resp, entries);
+	}
+
+	await resp.body?.cancel();
+
+	const directives = parseCacheControl(resp.headers.get('cache-control'));
+
+	entry.storedAt = Date.now();
+	entry.freshForMs = freshnessMs(resp, directives) ?? entry.freshForMs;
+
+	await saveEntries(key, entries);
+
+	return toResponse(entry, req, 'REVALIDATED');
+}
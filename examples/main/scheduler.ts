@@ -0,0 +1,333 @@
+// Invokes services on the schedules declared in their `function.toml` (see
+// `ScheduleConfig`), so periodic jobs don't need an external trigger.
+//
+// Enabled with `SCHEDULER_ENABLED=true`. The services under the default root
+// (see `tenant.ts`) are scanned when the main worker starts and again every
+// `SCHEDULER_RESCAN_INTERVAL_MS` (default 60s). Rescans pick up new services,
+// and the schedules of services whose config was reloaded (e.g. by a deploy).
+//
+// Each run sends a synthetic request to `/<service><path>`, carrying the cron
+// expression in `x-edge-runtime-schedule` and the time the run was due at in
+// `x-edge-runtime-scheduled-at`.
+//
+// Services can also declare jobs in code with `Deno.cron()`. Those of the
+// services with a config are discovered when the service is scanned, which
+// boots a worker for it, and are run by a request to `/<service>/` sent with
+// `worker.fetch(req, { cron: { run: job } })`. Only the main worker can send
+// those. Their runs don't overlap, and missed runs are skipped.
+
+import { loadServiceConfig, ScheduleConfig, ServiceConfig } from './service_config.ts';
+import { defaultRoot } from './tenant.ts';
+
+const enabled = Deno.env.get('SCHEDULER_ENABLED') === 'true';
+const rescanIntervalMs = parseInt(Deno.env.get('SCHEDULER_RESCAN_INTERVAL_MS') ?? '60000', 10);
+
+// `setTimeout` can't wait longer than this, longer delays are waited in steps.
+const MAX_TIMEOUT_MS = 2 ** 31 - 1;
+const MINUTE_MS = 60 * 1000;
+
+// A schedule of the config, or one of a `Deno.cron()` job.
+type ScheduleSpec = ScheduleConfig & { job?: string };
+
+// Lists (`"list"`) or runs (`{ run: job }`) the `Deno.cron()` jobs of a worker.
+export type CronRequest = 'list' | { run: string };
+
+type InvokeFn = (
+	servicePath: string,
+	config: ServiceConfig,
+	req: Request,
+	cron?: CronRequest,
+) => Promise<Response>;
+
+interface FieldSpec {
+	min: number;
+	max: number;
+}
+
+// minute, hour, day of month, month, day of week (0 or 7 is Sunday)
+const FIELD_SPECS: FieldSpec[] = [
+	{ min: 0, max: 59 },
+	{ min: 0, max: 23 },
+	{ min: 1, max: 31 },
+	{ min: 1, max: 12 },
+	{ min: 0, max: 7 },
+];
+
+function parseField(field: string, { min, max }: FieldSpec): Set<number> {
+	const values = new Set<number>();
+
+	for (const part of field.split(',')) {
+		const [range, rawStep] = part.split('/');
+		const step = rawStep === undefined ? 1 : Number(rawStep);
+		let [lo, hi] = [min, max];
+
+		if (range !== '*') {
+			const [start, end] = range.split('-').map(Number);
+
+			lo = start;
+			// `5/15` runs from 5 to the end of the range.
+			hi = end ?? (rawStep === undefined ? start : max);
+		}
+
+		if (![lo, hi, step].every(Number.isInteger) || lo < min || hi > max || lo > hi || step < 1) {
+			throw new Error(`invalid cron field: ${field}`);
+		}
+
+		for (let value = lo; value <= hi; value += step) {
+			values.add(value);
+		}
+	}
+
+	return values;
+}
+
+export class CronExpression {
+	readonly #fields: Set<number>[];
+	// Per cron convention, a restricted day of month and day of week match
+	// when either does.
+	readonly #anyDay: boolean;
+
+	constructor(expr: string) {
+		const fields = expr.trim().split(/\s+/);
+
+		if (fields.length !== FIELD_SPECS.length) {
+			throw new Error(`invalid cron expression: ${expr}`);
+		}
+
+		this.#fields = fields.map((it, idx) => parseField(it, FIELD_SPECS[idx]));
+		this.#anyDay = fields[2] !== '*' && fields[4] !== '*';
+
+		if (this.#fields[4].delete(7)) {
+			this.#fields[4].add(0);
+		}
+	}
+
+	#matchesDay(date: Date): boolean {
+		const dom = this.#fields[2].has(date.getUTCDate());
+		const dow = this.#fields[4].has(date.getUTCDay());
+
+		return this.#anyDay ? dom || dow : dom && dow;
+	}
+
+	// Returns the first time strictly after `after` the expression matches, or
+	// `null` if it never does (e.g. `0 0 30 2 *`).
+	next(after: Date): Date | null {
+		const date = new Date(Math.floor(after.getTime() / MINUTE_MS) * MINUTE_MS + MINUTE_MS);
+		const until = after.getUTCFullYear() + 5;
+		const [minutes, hours, , months] = this.#fields;
+
+		while (date.getUTCFullYear() <= until) {
+			if (!months.has(date.getUTCMonth() + 1)) {
+				date.setUTCMonth(date.getUTCMonth() + 1, 1);
+				date.setUTCHours(0, 0);
+			} else if (!this.#matchesDay(date)) {
+				date.setUTCDate(date.getUTCDate() + 1);
+				date.setUTCHours(0, 0);
+			} else if (!hours.has(date.getUTCHours())) {
+				date.setUTCHours(date.getUTCHours() + 1, 0);
+			} else if (!minutes.has(date.getUTCMinutes())) {
+				date.setUTCMinutes(date.getUTCMinutes() + 1);
+			} else {
+				return date;
+			}
+		}
+
+		return null;
+	}
+}
+
+class Schedule {
+	readonly #cron: CronExpression;
+	#timer: number | undefined;
+	#running = 0;
+	#stopped = false;
+
+	constructor(
+		readonly name: string,
+		readonly config: ScheduleSpec,
+		readonly invoke: (req: Request, cron?: CronRequest) => Promise<Response>,
+	) {
+		this.#cron = new CronExpression(config.cron);
+	}
+
+	start() {
+		this.#arm(new Date());
+	}
+
+	stop() {
+		this.#stopped = true;
+		clearTimeout(this.#timer);
+	}
+
+	#arm(after: Date) {
+		const due = this.#cron.next(after);
+
+		if (!due || this.#stopped) {
+			return;
+		}
+
+		const jitterMs = Math.floor(Math.random() * this.config.jitterMs);
+
+		const wait = () => {
+			const delayMs = due.getTime() + jitterMs - Date.now();
+
+			this.#timer = setTimeout(
+				() => (delayMs > MAX_TIMEOUT_MS ? wait() : this.#fire(due, jitterMs)),
+				Math.min(Math.max(delayMs, 0), MAX_TIMEOUT_MS),
+			);
+		};
+
+		wait();
+	}
+
+	#fire(due: Date, jitterMs: number) {
+		const now = new Date();
+		let last = due;
+		let missed = 0;
+
+		// NOTE: The timer fires late when the main worker was stalled or the
+		// host suspended, in which case later runs may have been due already.
+		for (let it = this.#cron.next(due); it && it.getTime() + jitterMs <= now.getTime(); it = this.#cron.next(it)) {
+			last = it;
+			missed++;
+		}
+
+		if (missed > 0) {
+			console.warn(`schedule ${this.config.cron} of ${this.name} missed ${missed} run(s)`);
+		}
+
+		if (missed === 0 || this.config.missed === 'run_once') {
+			this.#run(last);
+		}
+
+		this.#arm(last);
+	}
+
+	async #run(due: Date) {
+		if (this.#running > 0 && this.config.overlap === 'skip') {
+			console.warn(`skipped schedule ${this.config.cron} of ${this.name}: previous run is ongoing`);
+			return;
+		}
+
+		const headers = new Headers({
+			'x-edge-runtime-schedule': this.config.cron,
+			'x-edge-runtime-scheduled-at': due.toISOString(),
+		});
+
+		const req = new Request(`http://localhost/${this.name}${this.config.path}`, {
+			method: this.config.method,
+			headers,
+		});
+
+		this.#running++;
+
+		try {
+			const resp = await this.invoke(req, this.config.job ? { run: this.config.job } : undefined);
+
+			await resp.body?.cancel();
+
+			if (!resp.ok) {
+				console.error(`schedule ${this.config.cron} of ${this.name} responded with ${resp.status}`);
+			}
+		} catch (e) {
+			console.error(`schedule ${this.config.cron} of ${this.name} failed:`, e);
+		} finally {
+			this.#running--;
+		}
+	}
+}
+
+// Armed schedules by service path, along with the config they were armed from.
+const services = new Map<string, { config: ServiceConfig; schedules: Schedule[] }>();
+
+// Asks a worker of the service for the jobs it declared with `Deno.cron()`.
+async function discoverJobs(name: string, path: string, config: ServiceConfig, invoke: InvokeFn) {
+	try {
+		const req = new Request(`http://localhost/${name}/`);
+		const resp = await invoke(path, config, req, 'list');
+
+		if (!resp.ok) {
+			await resp.body?.cancel();
+			throw new Error(`responded with ${resp.status}`);
+		}
+
+		const jobs: { name: string; schedule: string }[] = await resp.json();
+
+		return jobs.map((it): ScheduleSpec => ({
+			cron: it.schedule,
+			path: '/',
+			method: 'POST',
+			overlap: 'skip',
+			jitterMs: 0,
+			missed: 'skip',
+			job: it.name,
+		}));
+	} catch (e) {
+		console.error(`failed to discover the cron jobs of ${name}:`, e);
+		return [];
+	}
+}
+
+async function rescan(invoke: InvokeFn) {
+	let entries;
+
+	try {
+		entries = EdgeRuntime.introspection.listServices(defaultRoot);
+	} catch (e) {
+		console.error(`failed to list services of ${defaultRoot}:`, e);
+		return;
+	}
+
+	const seen = new Set<string>();
+
+	for (const { name, path, hasConfig } of entries) {
+		if (!hasConfig) {
+			continue;
+		}
+
+		const config = await loadServiceConfig(path);
+
+		seen.add(path);
+
+		// NOTE: The config is cached until the service is reloaded, at which
+		// point its jobs are discovered again.
+		if (services.get(path)?.config === config) {
+			continue;
+		}
+
+		services.get(path)?.schedules.forEach((it) => it.stop());
+
+		const specs: ScheduleSpec[] = [
+			...config.schedules,
+			...(await discoverJobs(name, path, config, invoke)),
+		];
+
+		const schedules = specs.flatMap((schedule) => {
+			try {
+				return [new Schedule(name, schedule, (req, cron) => invoke(path, config, req, cron))];
+			} catch (e) {
+				console.error(`invalid schedule of ${name}:`, e);
+				return [];
+			}
+		});
+
+		schedules.forEach((it) => it.start());
+		services.set(path, { config, schedules });
+	}
+
+	for (const [path, { schedules }] of services) {
+		if (!seen.has(path)) {
+			schedules.forEach((it) => it.stop());
+			services.delete(path);
+		}
+	}
+}
+
+export function startScheduler(invoke: InvokeFn) {
+	if (!enabled) {
+		return;
+	}
+
+	rescan(invoke);
+	setInterval(() => rescan(invoke), rescanIntervalMs);
+}
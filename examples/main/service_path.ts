@@ -0,0 +1,79 @@
+// Joins a service name taken from the request onto the services root.
+//
+// The name comes straight from the URL (or the Host header), so it is decoded
+// and then rejected if it could address anything but a direct child of the
+// root (`..`, `..%2f..`, `a/b`, ...). The joined path is canonicalized as well,
+// so a symlink inside the root can't point a service outside of it.
+
+const FORBIDDEN_SEGMENTS = new Set(['', '.', '..']);
+
+function decodeServiceName(name: string): string | null {
+	try {
+		return decodeURIComponent(name);
+	} catch {
+		return null;
+	}
+}
+
+async function realPathOrNull(path: string): Promise<string | null> {
+	try {
+		return await Deno.realPath(path);
+	} catch (e) {
+		if (e instanceof Deno.errors.NotFound) {
+			return null;
+		}
+
+		throw e;
+	}
+}
+
+function isWithin(realRoot: string, realPath: string) {
+	const separator = realRoot.includes('\\') ? '\\' : '/';
+	const prefix = realRoot.endsWith(separator) ? realRoot : realRoot + separator;
+
+	return realPath.startsWith(prefix);
+}
+
+// Returns `null` when the name does not resolve to a path inside `root`.
+export async function resolveServicePath(root: string, name: string): Promise<string | null> {
+	const decoded = decodeServiceName(name);
+
+	if (
+		decoded === null ||
+		FORBIDDEN_SEGMENTS.has(decoded) ||
+		/[\/\\\0]/.test(decoded)
+	) {
+		return null;
+	}
+
+	// A service is either a directory or a single `<name>.eszip` bundle.
+	let servicePath = `${root}/${decoded}`;
+	let [realRoot, realServicePath] = await Promise.all([
+		realPathOrNull(root),
+		realPathOrNull(servicePath),
+	]);
+
+	if (realServicePath === null) {
+		const realBundlePath = await realPathOrNull(`${servicePath}.eszip`);
+
+		if (realBundlePath !== null) {
+			servicePath = `${servicePath}.eszip`;
+			realServicePath = realBundlePath;
+		}
+	}
+
+	// A service that doesn't exist fails on worker creation; there is nothing
+	// it could escape to.
+	if (realRoot === null || realServicePath === null) {
+		return servicePath;
+	}
+
+	return isWithin(realRoot, realServicePath) ? servicePath : null;
+}
+
+// Whether `path` exists and resolves to a path inside `root`.
+export async function isInsideRoot(root: string, path: string): Promise<boolean> {
+	const [realRoot, realPath] = await Promise.all([realPathOrNull(root), realPathOrNull(path)]);
+
+	return realRoot !== null && realPath !== null && isWithin(realRoot, realPath);
+}
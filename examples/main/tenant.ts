@@ -0,0 +1,72 @@
+// Selects the directory services are loaded from, so that one runtime can serve
+// the function trees of multiple isolated tenants.
+//
+// `SERVICES_ROOT` is the directory used when no tenant is selected (default:
+// `./examples`). A tenant is selected by, in order:
+//
+// - `TENANT_HEADER`: name of a header carrying the tenant id. Only set this
+//   when a trusted proxy in front of the runtime sets (and overwrites) it.
+// - `TENANT_HOST_MAP_PATH`: JSON file mapping hosts to tenant ids
+//   (`{ "a.example.com": "tenant-a" }`).
+//
+// The root of a tenant is `TENANT_ROOT_TEMPLATE` with `{tenant}` replaced by its
+// id (default: `/tenants/{tenant}/functions`).
+
+export interface ServicesRoot {
+	tenant: string | null;
+	root: string;
+}
+
+export const defaultRoot = Deno.env.get('SERVICES_ROOT') ?? './examples';
+const tenantHeader = Deno.env.get('TENANT_HEADER')?.toLowerCase();
+const tenantRootTemplate = Deno.env.get('TENANT_ROOT_TEMPLATE') ?? '/tenants/{tenant}/functions';
+
+// Tenant ids end up in a path, so anything that could escape the template is
+// rejected.
+const TENANT_ID_PATTERN = /^[A-Za-z0-9_-]+$/;
+
+let hostMap: Record<string, string> | null = null;
+
+function loadHostMap(): Record<string, string> {
+	if (!hostMap) {
+		const path = Deno.env.get('TENANT_HOST_MAP_PATH');
+
+		try {
+			hostMap = path ? JSON.parse(Deno.readTextFileSync(path)) : {};
+		} catch (e) {
+			console.error('failed to load tenant host map:', e);
+			hostMap = {};
+		}
+	}
+
+	return hostMap!;
+}
+
+function resolveTenant(req: Request): string | null {
+	if (tenantHeader) {
+		const tenant = req.headers.get(tenantHeader);
+
+		if (tenant) {
+			return tenant;
+		}
+	}
+
+	const host = (req.headers.get('host') ?? new URL(req.url).host).replace(/:\d+$/, '');
+
+	return loadHostMap()[host] ?? null;
+}
+
+// Returns `null` when the request selects a malformed tenant id.
+export function resolveServicesRoot(req: Request): ServicesRoot | null {
+	const tenant = resolveTenant(req);
+
+	if (tenant === null) {
+		return { tenant: null, root: defaultRoot };
+	}
+
+	if (!TENANT_ID_PATTERN.test(tenant)) {
+		return null;
+	}
+
+	return { tenant, root: tenantRootTemplate.replaceAll('{tenant}', tenant) };
+}
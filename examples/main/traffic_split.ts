@@ -0,0 +1,132 @@
+// Weighted routing between versions of a service, for canary rollouts.
+//
+// Versions are sibling service directories named `<service>@<version>`.
+// `TRAFFIC_SPLIT_PATH` points at a JSON file declaring the weights of the
+// versions a service name is split into:
+//
+// ```json
+// {
+//   "my-fn": {
+//     "versions": { "v1": 90, "v2": 10 },
+//     "stickyHeader": "x-user-id",
+//     "cookie": "my-fn-version"
+//   }
+// }
+// ```
+//
+// Assignment is sticky. A client presenting the cookie of a configured version
+// keeps it. Otherwise, the version is derived from a hash of `stickyHeader`
+// when present, or picked at random; either way the cookie (default:
+// `edge-runtime-version`) is set on the response so the client stays on it.
+
+interface SplitConfig {
+	versions: Record<string, number>;
+	stickyHeader?: string;
+	cookie?: string;
+}
+
+export interface SplitDecision {
+	serviceName: string;
+	setCookie: string | null;
+}
+
+const DEFAULT_COOKIE = 'edge-runtime-version';
+
+let splits: Record<string, SplitConfig> | null = null;
+
+function loadSplits(): Record<string, SplitConfig> {
+	if (!splits) {
+		const path = Deno.env.get('TRAFFIC_SPLIT_PATH');
+
+		try {
+			splits = path ? JSON.parse(Deno.readTextFileSync(path)) : {};
+		} catch (e) {
+			console.error('failed to load traffic split config:', e);
+			splits = {};
+		}
+	}
+
+	return splits!;
+}
+
+// FNV-1a, so that the same sticky key maps to the same bucket across restarts
+// and instances.
+function hash(value: string) {
+	let h = 0x811c9dc5;
+
+	for (let i = 0; i < value.length; i++) {
+		h ^= value.charCodeAt(i);
+		h = Math.imul(h, 0x01000193);
+	}
+
+	return h >>> 0;
+}
+
+function getCookie(req: Request, name: string): string | null {
+	for (const part of (req.headers.get('cookie') ?? '').split(';')) {
+		const [key, ...rest] = part.trim().split('=');
+
+		if (key === name) {
+			return rest.join('=');
+		}
+	}
+
+	return null;
+}
+
+function pickVersion(versions: [string, number][], bucket: number) {
+	let acc = 0;
+
+	for (const [version, weight] of versions) {
+		acc += weight;
+
+		if (bucket < acc) {
+			return version;
+		}
+	}
+
+	return versions[versions.length - 1][0];
+}
+
+export function splitTraffic(req: Request, serviceName: string): SplitDecision {
+	const config = loadSplits()[serviceName];
+	const versions = Object.entries(config?.versions ?? {}).filter(([, weight]) => weight > 0);
+
+	if (versions.length === 0) {
+		return { serviceName, setCookie: null };
+	}
+
+	const cookie = config.cookie ?? DEFAULT_COOKIE;
+	const pinned = getCookie(req, cookie);
+
+	if (pinned && versions.some(([version]) => version === pinned)) {
+		return { serviceName: `${serviceName}@${pinned}`, setCookie: null };
+	}
+
+	const total = versions.reduce((acc, [, weight]) => acc + weight, 0);
+	const stickyKey = config.stickyHeader ? req.headers.get(config.stickyHeader) : null;
+	const bucket = stickyKey === null ? Math.random() * total : hash(stickyKey) % total;
+	const version = pickVersion(versions, bucket);
+
+	return {
+		serviceName: `${serviceName}@${version}`,
+		setCookie: `${cookie}=${version}; Path=/; HttpOnly; SameSite=Lax`,
+	};
+}
+
+export function withSplitCookie(resp: Response, decision: SplitDecision) {
+	// NOTE: Upgraded (e.g. WebSocket) responses must be handed back untouched.
+	if (!decision.setCookie || resp.status === 101) {
+		return resp;
+	}
+
+	const headers = new Headers(resp.headers);
+
+	headers.append('set-cookie', decision.setCookie);
+
+	return new Response(resp.body, {
+		status: resp.status,
+		statusText: resp.statusText,
+		headers,
+	});
+}
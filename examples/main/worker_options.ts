@@ -0,0 +1,137 @@
+// Options user workers are created with.
+//
+// The defaults are taken from the environment of the main worker, so that a
+// deployment can tune them without editing this function:
+//
+// - `WORKER_MEMORY_LIMIT_MB` (default: 150)
+// - `WORKER_TIMEOUT_MS` (default: 5 minutes)
+// - `WORKER_BOOT_TIMEOUT_MS`: fails the boot of workers whose modules take
+//   longer to load, e.g. stuck fetching remote imports (default: 0, disabled)
+// - `WORKER_MAX_MODULE_GRAPH_SIZE_MB` / `WORKER_MAX_REMOTE_MODULES`: fails the
+//   boot of workers whose dependencies grow past this (default: 0, disabled)
+// - `WORKER_WALL_CLOCK_GRACE_MS`: lets in-flight requests finish for this long
+//   once the wall clock limit is reached (default: 0, disabled)
+// - `WORKER_CPU_TIME_SOFT_LIMIT_MS` / `WORKER_CPU_TIME_HARD_LIMIT_MS`
+//   (default: 10s / 20s)
+// - `WORKER_IDLE_TIMEOUT_MS`: evicts workers that served no request for this
+//   long (default: 0, disabled)
+// - `WORKER_MAX_REQUESTS`: replaces workers after they served this many
+//   requests (default: 0, disabled)
+// - `WORKER_KV_MAX_SIZE_MB`: caps the store of `Deno.openKv()` of each service
+//   (default: 0, disabled)
+// - `WORKER_IMPORT_MAP_PATH`
+// - `WORKER_EGRESS_DENY`: comma-separated CIDRs and hostnames workers can't
+//   reach (default: the private and link-local ranges)
+// - `WORKER_EGRESS_ALLOW`: comma-separated exceptions to the above
+// - `WORKER_NO_MODULE_CACHE`, `WORKER_NET_ACCESS_DISABLED` and
+//   `WORKER_FORCE_CREATE` (`true` to enable)
+//
+// Per-service overrides are layered on top of them, see `service_config.ts`.
+
+export interface WorkerLimits {
+	memoryLimitMb: number;
+	workerTimeoutMs: number;
+	wallClockGraceMs: number;
+	bootTimeoutMs: number;
+	maxModuleGraphSizeMb: number;
+	maxRemoteModules: number;
+	cpuTimeSoftLimitMs: number;
+	cpuTimeHardLimitMs: number;
+	idleTimeoutMs: number;
+	maxRequests: number;
+	kvMaxSizeMb: number;
+	// Per-service caps, see `ConcurrencyConfig` in `service_config.ts`.
+	maxWorkers?: number;
+	maxConcurrentRequests?: number;
+	cpuSet?: string;
+	nice?: number;
+}
+
+export interface WorkerOverrides extends Partial<WorkerLimits> {
+	// Only these env vars of the main worker are passed to the worker.
+	envAllowlist?: string[];
+	// Tenant whose quotas the worker counts towards (see `--tenant-max-*`).
+	// Defaults to the directory containing the service.
+	tenant?: string;
+	// Entrypoint of the service, as a URL or a path relative to the service.
+	maybeEntrypoint?: string;
+	// Permissions of the worker, see `PermissionsConfig` in `service_config.ts`.
+	allowNet?: string[];
+	allowRead?: string[];
+	allowWrite?: string[];
+	allowEnv?: string[];
+	allowHrtime?: boolean;
+	allowFfi?: boolean;
+	egressAllow?: string[];
+	egressDeny?: string[] | null;
+	// Proxy fetch() goes through, see `ServiceConfig` in `service_config.ts`.
+	httpProxy?: string;
+	// See `FetchConfig` in `service_config.ts`.
+	fetchKeepAliveMs?: number;
+	fetchMaxIdlePerHost?: number;
+	fetchConnectTimeoutMs?: number;
+	// Makes crypto.getRandomValues() and crypto.randomUUID() deterministic.
+	cryptoSeed?: number;
+	// See `ServiceConfig` in `service_config.ts`.
+	v8Flags?: string[];
+	// See `ServiceConfig` in `service_config.ts`.
+	shareBroadcastChannel?: boolean;
+}
+
+export interface WorkerOptions extends WorkerLimits {
+	noModuleCache: boolean;
+	importMapPath: string | null;
+	envVars: [string, string][];
+	envAllowlist: string[] | null;
+	forceCreate: boolean;
+	netAccessDisabled: boolean;
+	egressAllow: string[];
+	egressDeny: string[] | null;
+}
+
+function envNumber(name: string, fallback: number) {
+	const value = parseInt(Deno.env.get(name) ?? '', 10);
+	return Number.isFinite(value) && value > 0 ? value : fallback;
+}
+
+function envFlag(name: string) {
+	return Deno.env.get(name) === 'true';
+}
+
+function envList(name: string) {
+	const value = Deno.env.get(name);
+	return value === undefined ? null : value.split(',').map((it) => it.trim()).filter(Boolean);
+}
+
+export const workerDefaults = Object.freeze({
+	memoryLimitMb: envNumber('WORKER_MEMORY_LIMIT_MB', 150),
+	workerTimeoutMs: envNumber('WORKER_TIMEOUT_MS', 5 * 60 * 1000),
+	wallClockGraceMs: envNumber('WORKER_WALL_CLOCK_GRACE_MS', 0),
+	bootTimeoutMs: envNumber('WORKER_BOOT_TIMEOUT_MS', 0),
+	maxModuleGraphSizeMb: envNumber('WORKER_MAX_MODULE_GRAPH_SIZE_MB', 0),
+	maxRemoteModules: envNumber('WORKER_MAX_REMOTE_MODULES', 0),
+	cpuTimeSoftLimitMs: envNumber('WORKER_CPU_TIME_SOFT_LIMIT_MS', 10000),
+	cpuTimeHardLimitMs: envNumber('WORKER_CPU_TIME_HARD_LIMIT_MS', 20000),
+	idleTimeoutMs: envNumber('WORKER_IDLE_TIMEOUT_MS', 0),
+	maxRequests: envNumber('WORKER_MAX_REQUESTS', 0),
+	kvMaxSizeMb: envNumber('WORKER_KV_MAX_SIZE_MB', 0),
+	noModuleCache: envFlag('WORKER_NO_MODULE_CACHE'),
+	importMapPath: Deno.env.get('WORKER_IMPORT_MAP_PATH') ?? null,
+	forceCreate: envFlag('WORKER_FORCE_CREATE'),
+	netAccessDisabled: envFlag('WORKER_NET_ACCESS_DISABLED'),
+	egressAllow: envList('WORKER_EGRESS_ALLOW') ?? [],
+	egressDeny: envList('WORKER_EGRESS_DENY'),
+});
+
+export function workerOptions(overrides: WorkerOverrides = {}): WorkerOptions {
+	const { envAllowlist, ...limits } = overrides;
+
+	// NOTE: The runtime drops the env vars outside of the allowlist, and adds
+	// the secrets of the service (see `--secrets-dir`).
+	return {
+		...workerDefaults,
+		...limits,
+		envAllowlist: envAllowlist ?? null,
+		envVars: Object.entries(Deno.env.toObject()),
+	};
+}